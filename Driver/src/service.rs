@@ -12,7 +12,7 @@ use log::{debug, error, info};
 #[cfg(windows)]
 use ptree_cache::DiskCache;
 #[cfg(windows)]
-use ptree_core::{Args, ColorMode, OutputFormat};
+use ptree_core::{Args, BranchStyle, Charset, ColorMode, JsonSchema, OutputFormat, ScanEngine, SortOrder, ThemeName};
 #[cfg(windows)]
 use ptree_incremental::IncrementalChange;
 #[cfg(windows)]
@@ -74,9 +74,7 @@ impl PtreeService {
     pub fn run(&mut self) -> DriverResult<()> {
         #[cfg(not(windows))]
         {
-            return Err(crate::error::DriverError::Windows(
-                "ptree-driver service is only available on Windows".to_string(),
-            ));
+            Err(crate::error::DriverError::Windows("ptree-driver service is only available on Windows".to_string()))
         }
 
         #[cfg(windows)]
@@ -180,10 +178,18 @@ impl PtreeService {
 
         let scan_root = std::path::PathBuf::from(format!("{}:\\", self.config.drive_letter));
         let args = Args {
-            path:                Some(scan_root.clone()),
-            drive:               self.config.drive_letter,
+            command:             None,
+            paths:               vec![scan_root.clone()],
+            drive:               vec![self.config.drive_letter],
             admin:               true,
             force:               false,
+            verify:              false,
+            mft:                 false,
+            ads:                 false,
+            xattrs:              false,
+            owners:              false,
+            extensions:          false,
+            long:                false,
             cache_ttl:           Some(3600),
             cache_dir:           self
                 .config
@@ -191,32 +197,90 @@ impl PtreeService {
                 .parent()
                 .map(|path| path.to_string_lossy().to_string()),
             no_cache:            false,
+            no_save:             false,
+            lock_timeout:        None,
+            cache_max_size:      None,
+            max_memory:          None,
+            checkpoint_interval: None,
+            no_resume:           false,
+            cache_compress:      None,
+            cache_info:          false,
+            watch:               false,
+            serve:               false,
+            emit_events:         false,
             quiet:               true,
+            summary_only:        false,
+            progress:            None,
+            verbose:             false,
+            log_level:           None,
+            log_file:            None,
+            config:              None,
+            no_migrate:          false,
+            interactive:         false,
+            query:               None,
             format:              OutputFormat::Tree,
+            json_schema:         JsonSchema::V2,
+            output:              None,
+            no_pager:            false,
+            no_report:           false,
+            report:              false,
             color:               ColorMode::Never,
+            theme:               ThemeName::Auto,
+            accessible:          false,
             size:                false,
+            no_size:             false,
+            apparent_size:       false,
             file_count:          false,
+            charset:             Charset::Unicode,
+            style:               BranchStyle::Classic,
+            sort:                SortOrder::Name,
+            reverse:             false,
+            newer_than:          None,
+            modified_since:      None,
+            modified_before:     None,
+            min_size:            None,
+            max_size:            None,
+            compact_dirs:        false,
+            full_path:           false,
+            relative_to:         None,
             max_depth:           None,
+            limit:               None,
+            scan_depth:          None,
+            one_file_system:     false,
+            count_links:         false,
+            detect_links:        false,
+            follow_symlinks:     false,
+            max_symlink_depth:   None,
             skip:                None,
+            skip_regex:          Vec::new(),
+            gitignore:           false,
+            no_gitignore:        false,
+            no_markers:          false,
+            include_snapshots:   false,
             hidden:              false,
+            no_hidden:           false,
+            settle:              None,
             threads:             Some(1),
+            engine:              ScanEngine::Dfs,
             stats:               false,
+            metrics_textfile:    None,
             skip_stats:          false,
+            errors:              false,
             scheduler:           false,
             scheduler_uninstall: false,
             scheduler_status:    false,
+            scheduled:           false,
+            include_slow:        false,
         };
 
-        let mut cache =
-            DiskCache::open(&self.config.cache_path).map_err(|e| crate::error::DriverError::Windows(e.to_string()))?;
-        traverse_disk_incremental(
-            &self.config.drive_letter,
-            &mut cache,
-            &args,
-            &self.config.cache_path,
-            &incremental_changes,
-        )
-        .map_err(|e| crate::error::DriverError::Windows(e.to_string()))?;
+        // Same per-root cache file that `ptree C:\` reads
+        let cache_path = ptree_cache::cache_path_for_roots(&self.config.cache_path, std::slice::from_ref(&scan_root));
+        let mut cache = DiskCache::open(&cache_path).map_err(|e| crate::error::DriverError::Windows(e.to_string()))?;
+        cache
+            .load_all_entries_lazy(&cache_path)
+            .map_err(|e| crate::error::DriverError::Windows(e.to_string()))?;
+        traverse_disk_incremental(&self.config.drive_letter, &mut cache, &args, &cache_path, &incremental_changes)
+            .map_err(|e| crate::error::DriverError::Windows(e.to_string()))?;
 
         debug!("Changes: {} created, {} modified, {} deleted", creates, modifies, deletes);
 
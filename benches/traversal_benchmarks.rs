@@ -94,7 +94,7 @@ fn bench_parallel_sorting(c: &mut Criterion) {
     let mut group = c.benchmark_group("parallel_sorting");
 
     for size in [50, 100, 500, 1000, 5000].iter() {
-        let mut names: Vec<String> = (0..*size).map(|i| format!("directory_name_{:04}", i)).collect();
+        let names: Vec<String> = (0..*size).map(|i| format!("directory_name_{:04}", i)).collect();
 
         group.bench_with_input(BenchmarkId::from_parameter(format!("sequential_{}", size)), size, |b, _| {
             b.iter(|| {
@@ -1,16 +1,37 @@
+use std::cmp::Reverse;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::{self, File};
 use std::hash::{Hash, Hasher};
 use std::io::{BufWriter, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
-use colored::Colorize;
+use ptree_core::{JsonSchema, PathDisplay, SortOrder, TreeGlyphs};
 use rayon::slice::ParallelSliceMut;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+
+use crate::cache_lock::{CacheLock, DEFAULT_LOCK_TIMEOUT};
+use crate::cache_provenance::ScanRecord;
+use crate::cache_rkyv::{
+    depth_file_path,
+    encode_index,
+    encode_record,
+    names_file_path,
+    sync_dir,
+    CompactionStats,
+    NameTableWriter,
+    ShardWriter,
+    VerifyReport,
+    RECORD_HEADER_LEN,
+};
+use crate::cache_space;
+use crate::cache_spill::{SpillRecord, SpillStore};
+use crate::names::Name;
+use crate::theme::{paint, Theme};
 
 #[cfg(windows)]
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -23,15 +44,584 @@ pub struct USNJournalState;
 /// Directory metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DirEntry {
-    pub path:         PathBuf,
-    pub name:         String,
-    pub modified:     DateTime<Utc>,
-    pub content_hash: u64,
-    pub file_count:   usize,
-    pub total_size:   u64,
-    pub children:     Vec<String>,
-    pub is_hidden:    bool,
-    pub is_dir:       bool,
+    pub path:           PathBuf,
+    pub name:           Name,
+    pub modified:       DateTime<Utc>,
+    pub content_hash:   u64,
+    pub file_count:     usize,
+    pub total_size:     u64,
+    /// Bytes the files take on disk (allocated blocks; compressed or sparse files take fewer than
+    /// `total_size`), totaled the same way
+    #[serde(default)]
+    pub allocated_size: u64,
+    pub children:       Vec<Name>,
+    pub is_hidden:      bool,
+    pub is_dir:         bool,
+    /// Stable ID, kept across rescans and ancestor renames (0 until assigned)
+    pub id:             u64,
+    /// Filesystem identity (device + inode) used to follow renames; 0 where unavailable
+    pub file_id:        u64,
+    /// Scan that last read this directory from disk (a key of `DiskCache::scans`; 0 if unknown)
+    pub scan:           u64,
+}
+
+/// The size on disk is shown next to the logical size once they differ by this share...
+const ALLOCATION_NOTABLE_RATIO: u64 = 10;
+/// ...and by at least this many bytes (one small file always rounds up to a whole block)
+const ALLOCATION_NOTABLE_BYTES: u64 = 1 << 20;
+
+impl DirEntry {
+    /// Whether the size on disk is far enough from the logical size to be worth showing
+    /// (compressed or sparse files, or a great many small ones).
+    pub fn allocation_differs(&self) -> bool {
+        let difference = self.total_size.abs_diff(self.allocated_size);
+        difference >= ALLOCATION_NOTABLE_BYTES && difference * ALLOCATION_NOTABLE_RATIO >= self.total_size
+    }
+}
+
+/// What was left out of a persisted snapshot to keep it within a size budget
+/// (`--cache-max-size`) or to fit on a full cache volume.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheTrim {
+    /// File names were dropped from `children`; only the directory structure is kept
+    pub files_dropped:   bool,
+    /// Number of directory entries left out of the snapshot: whole subtrees, least recently
+    /// modified first (their sizes and file counts still roll up into the kept ancestors, and
+    /// their tops are `pending_dirs`, read again by the next scan)
+    pub entries_dropped: usize,
+    /// Budget the snapshot was trimmed to fit, if any
+    pub budget_bytes:    Option<u64>,
+}
+
+impl CacheTrim {
+    pub fn is_trimmed(&self) -> bool {
+        self.files_dropped || self.entries_dropped > 0
+    }
+
+    /// One-line description for `--cache-info`.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.files_dropped {
+            parts.push("file names dropped (directory structure kept)".to_string());
+        }
+        if self.entries_dropped > 0 {
+            parts.push(format!("{} least recently modified directories dropped", self.entries_dropped));
+        }
+        if parts.is_empty() {
+            return "none".to_string();
+        }
+
+        let mut description = parts.join("; ");
+        if let Some(budget) = self.budget_bytes {
+            description.push_str(&format!(" to fit {}", DiskCache::format_size(budget)));
+        }
+        description
+    }
+}
+
+/// Package-store layouts whose hard-link reuse makes naive size totals misleading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StoreKind {
+    /// `/nix/store` (with `auto-optimise-store`, identical files are hard-linked via `.links`)
+    Nix,
+    /// pnpm's content-addressed store (`.pnpm-store`) and `node_modules/.pnpm` virtual stores
+    Pnpm,
+}
+
+impl StoreKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StoreKind::Nix => "nix store",
+            StoreKind::Pnpm => "pnpm store",
+        }
+    }
+}
+
+/// Size accounting for one detected package store, with shared content counted once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreUsage {
+    pub kind:          StoreKind,
+    pub root:          PathBuf,
+    pub files:         usize,
+    /// Files with more than one hard link
+    pub linked_files:  usize,
+    /// Sum of every file's size, as a naive walk would report it
+    pub logical_bytes: u64,
+    /// Bytes after counting each hard-linked file once (what rolls up into directory sizes)
+    pub unique_bytes:  u64,
+}
+
+impl StoreUsage {
+    /// Share of the logical size that is reused content, in percent.
+    pub fn reuse_percent(&self) -> f64 {
+        if self.logical_bytes == 0 {
+            return 0.0;
+        }
+        (self.logical_bytes - self.unique_bytes) as f64 * 100.0 / self.logical_bytes as f64
+    }
+}
+
+/// Filesystems whose directories can start a volume of their own inside the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SubvolumeKind {
+    /// A btrfs subvolume (each snapshot is one, sharing its extents with the original)
+    Btrfs,
+    /// A ZFS dataset mounted below its parent dataset
+    Zfs,
+}
+
+impl SubvolumeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubvolumeKind::Btrfs => "btrfs subvolume",
+            SubvolumeKind::Zfs => "ZFS dataset",
+        }
+    }
+}
+
+/// A directory where another btrfs subvolume or ZFS dataset begins (shown as `[subvol]`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subvolume {
+    pub kind:        SubvolumeKind,
+    /// Bytes the filesystem itself accounts to the volume: a ZFS dataset's referenced size, or
+    /// a btrfs subvolume's qgroup when quotas are enabled. Shared extents make it differ from
+    /// the sum of the file sizes below.
+    pub usage_bytes: Option<u64>,
+}
+
+/// What a `--follow-symlinks` scan did with a symbolic link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkStatus {
+    /// Entered (a directory) or counted (a file) below the link
+    Followed,
+    /// Points into a scanned root, which is counted there already
+    InTree,
+    /// Points to a directory it is itself in: following it would never end
+    Loop,
+    /// Would go more links deep than `--max-symlink-depth`
+    TooDeep,
+    /// Its target does not exist or cannot be reached
+    Broken,
+    /// Points into a directory already followed through another link, counted there
+    AlreadyFollowed,
+}
+
+impl SymlinkStatus {
+    /// Marker shown after the link's target when it was not followed
+    pub fn marker(&self) -> Option<&'static str> {
+        match self {
+            SymlinkStatus::Followed => None,
+            SymlinkStatus::InTree => Some("[in tree]"),
+            SymlinkStatus::Loop => Some("[loop]"),
+            SymlinkStatus::TooDeep => Some("[too deep]"),
+            SymlinkStatus::Broken => Some("[broken]"),
+            SymlinkStatus::AlreadyFollowed => Some("[already followed]"),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SymlinkStatus::Followed => "followed",
+            SymlinkStatus::InTree => "not followed, points into the tree",
+            SymlinkStatus::Loop => "not followed, leads back into a directory it is in",
+            SymlinkStatus::TooDeep => "not followed, too many links deep",
+            SymlinkStatus::Broken => "broken",
+            SymlinkStatus::AlreadyFollowed => "not followed, its target was followed through another link",
+        }
+    }
+}
+
+/// Why a directory could not be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanErrorKind {
+    Denied,
+    /// The path is longer than the system allows
+    TooLong,
+    /// Gone between being found and being listed
+    Vanished,
+    /// Any other I/O error
+    Io,
+}
+
+impl ScanErrorKind {
+    pub fn of(err: &std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => ScanErrorKind::Denied,
+            std::io::ErrorKind::InvalidFilename => ScanErrorKind::TooLong,
+            std::io::ErrorKind::NotFound => ScanErrorKind::Vanished,
+            _ => ScanErrorKind::Io,
+        }
+    }
+
+    /// Marker shown after the directory's name in the tree
+    pub fn marker(&self) -> &'static str {
+        match self {
+            ScanErrorKind::Denied => "[denied]",
+            ScanErrorKind::TooLong => "[too long]",
+            ScanErrorKind::Vanished => "[vanished]",
+            ScanErrorKind::Io => "[error]",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScanErrorKind::Denied => "permission denied",
+            ScanErrorKind::TooLong => "path too long",
+            ScanErrorKind::Vanished => "vanished during the scan",
+            ScanErrorKind::Io => "I/O error",
+        }
+    }
+}
+
+/// A directory the last scan that reached it could not read (shown as `[denied]`, ...).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanError {
+    pub kind:    ScanErrorKind,
+    /// The system's message
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn new(err: &std::io::Error) -> Self {
+        ScanError {
+            kind:    ScanErrorKind::of(err),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A symbolic link met by a `--follow-symlinks` scan (shown as `name -> target`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symlink {
+    pub target: PathBuf,
+    pub status: SymlinkStatus,
+}
+
+/// Directories this many levels below a root (or fewer) are checked for `[new]`...
+pub const NEW_DIR_LEVELS: usize = 2;
+/// ...and flagged when they are at least this large.
+pub const NEW_DIR_MIN_BYTES: u64 = 1 << 30;
+/// A flag outlives the scan that set it for this long, so frequent scheduled refreshes
+/// do not clear it before anyone looks.
+pub const NEW_DIR_FLAG_HOURS: i64 = 24;
+
+/// A directory flagged `[new]`: its size at the last scan and when it first appeared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewDir {
+    pub size:       u64,
+    pub first_seen: DateTime<Utc>,
+}
+
+/// Listing a directory takes at least this long counts as a slow scan...
+pub const SLOW_DIR_MILLIS: u64 = 5_000;
+/// ...and this many slow scans in a row put it on the slow list, which scheduled refreshes
+/// skip (`--include-slow` reads it anyway).
+pub const SLOW_DIR_STRIKES: u32 = 3;
+
+/// A directory whose listing was slow on recent scans (typically a flaky network mount).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlowDir {
+    /// Slow scans in a row; a fast listing drops the directory
+    pub strikes:   u32,
+    /// How long the last timed listing took
+    pub last_ms:   u64,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl SlowDir {
+    /// On the slow list: skipped by scheduled refreshes and marked `[slow]`.
+    pub fn is_listed(&self) -> bool {
+        self.strikes >= SLOW_DIR_STRIKES
+    }
+}
+
+/// Named alternate data streams Windows and common tools attach on their own (`Zone.Identifier`
+/// marks downloads); `ptree report ads` leaves them out unless asked for `--all`.
+pub const EXPECTED_STREAMS: &[&str] = &[
+    "Zone.Identifier",
+    "SmartScreen",
+    "encryptable",
+    "favicon",
+    "ms-properties",
+    "OECustomProperty",
+    "AFP_AfpInfo",
+    "AFP_Resource",
+    "com.dropbox.attrs",
+    "com.dropbox.attributes",
+];
+
+/// A named alternate data stream (NTFS) of a file or directory, recorded by `--ads` scans.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AltStream {
+    pub name: String,
+    pub size: u64,
+}
+
+impl AltStream {
+    /// One of the `EXPECTED_STREAMS`.
+    pub fn is_expected(&self) -> bool {
+        EXPECTED_STREAMS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(&self.name))
+    }
+}
+
+/// Drop the records of directories the current `scan` read, and of the files directly in them:
+/// an incremental scan lists those again.
+fn drop_reread<T>(records: &mut HashMap<PathBuf, T>, entries: &HashMap<PathBuf, DirEntry>, scan: u64) {
+    records.retain(|path, _| {
+        let dir = if entries.contains_key(path) {
+            Some(path.as_path())
+        } else {
+            path.parent()
+        };
+        !dir.and_then(|dir| entries.get(dir))
+            .is_some_and(|entry| entry.scan == scan)
+    });
+}
+
+/// Extended attribute values up to this many bytes are kept with their names (SELinux labels,
+/// capability sets, quarantine flags); larger ones only by size.
+pub const XATTR_VALUE_MAX: usize = 256;
+
+/// An extended attribute (Unix, macOS) of a file or directory, recorded by `--xattrs` scans.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct XAttr {
+    pub name:  String,
+    pub size:  u64,
+    /// The value as text, or `0x`-prefixed hex when it is not printable UTF-8; `None` above
+    /// `XATTR_VALUE_MAX` bytes
+    pub value: Option<String>,
+}
+
+/// Files one user owns directly in a directory, recorded by `--owners` scans (Unix); or, summed
+/// over the snapshot, one row of `ptree stats --by-owner`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnerUsage {
+    pub uid:         u32,
+    /// 1 in the record of the directory's own owner
+    #[serde(default)]
+    pub directories: u64,
+    pub files:       u64,
+    pub bytes:       u64,
+}
+
+/// How many of the largest files below the roots a snapshot keeps for `ptree du --files`
+pub const LARGEST_FILES_KEPT: usize = 1000;
+
+/// One of the largest files a scan found, kept for `ptree du --files`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LargeFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Files of one extension directly in a directory, recorded by `--extensions` scans; or, summed
+/// over the snapshot, one row of `ptree stats --by-extension`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionUsage {
+    /// Lowercased, without the dot; empty for names without one
+    pub extension: String,
+    pub files:     u64,
+    pub bytes:     u64,
+}
+
+/// Extension of the file `name` as `ExtensionUsage` keeps it: lowercased, without the dot,
+/// empty for none (`.bashrc` has none).
+pub fn file_extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Permissions, owner, size and modification time of a file or directory, recorded by `--long`
+/// scans for the long listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryMetadata {
+    /// Mode bits, file type included (Unix); file attributes (Windows)
+    pub mode:      u32,
+    /// User ID of the owner (Unix)
+    pub uid:       Option<u32>,
+    pub size:      u64,
+    /// Bytes the file takes on disk (absent from version 1 JSON dumps)
+    #[serde(default)]
+    pub allocated: u64,
+    pub modified:  Option<DateTime<Utc>>,
+}
+
+impl From<&fs::Metadata> for EntryMetadata {
+    fn from(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let (mode, uid, allocated) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.mode(), Some(metadata.uid()), metadata.blocks() * 512)
+        };
+        #[cfg(windows)]
+        let (mode, uid, allocated) = {
+            use std::os::windows::fs::MetadataExt;
+            (metadata.file_attributes(), None, metadata.len())
+        };
+        #[cfg(not(any(unix, windows)))]
+        let (mode, uid, allocated) = (0, None, metadata.len());
+        EntryMetadata {
+            mode,
+            uid,
+            size: metadata.len(),
+            allocated,
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        }
+    }
+}
+
+impl EntryMetadata {
+    /// Whether anyone may run the file, from its mode bits; `None` where the mode holds none
+    /// (Windows attributes).
+    pub fn is_executable(&self) -> Option<bool> {
+        if cfg!(unix) {
+            Some(self.mode & 0o111 != 0)
+        } else {
+            None
+        }
+    }
+}
+
+/// How many of the largest directories below the roots a snapshot keeps for `ptree du`, by
+/// either size
+pub const LARGEST_DIRS_KEPT: usize = 1000;
+
+/// One of the largest directories of a snapshot, kept for `ptree du`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LargeDir {
+    pub path:           PathBuf,
+    pub total_size:     u64,
+    pub allocated_size: u64,
+}
+
+/// Usage per owner of one row of `ptree report matrix`: a directory at the requested level
+/// (or a shallower one, for files directly in it) and everything below it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerRow {
+    pub path:   PathBuf,
+    /// By user ID
+    pub owners: BTreeMap<u32, OwnerUsage>,
+}
+
+/// Headline numbers of a snapshot, compared across runs by `--summary-only`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotTotals {
+    pub directories:     usize,
+    pub files:           usize,
+    pub bytes:           u64,
+    /// Bytes allocated on disk for those files
+    pub allocated_bytes: u64,
+    /// Total size of each directory directly under a scan root
+    pub top_level:       HashMap<PathBuf, u64>,
+}
+
+impl SnapshotTotals {
+    /// Directory directly under a root that grew the most since `previous`, and by how much.
+    pub fn largest_growth(&self, previous: &SnapshotTotals) -> Option<(PathBuf, u64)> {
+        self.top_level
+            .iter()
+            .filter_map(|(path, bytes)| {
+                let before = previous.top_level.get(path).copied().unwrap_or(0);
+                (*bytes > before).then(|| (path.clone(), bytes - before))
+            })
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+    }
+}
+
+/// Filesystem identity of a directory (device and inode), stable across renames within a volume.
+///
+/// Returns 0 where the platform does not expose one through std (Windows), so
+/// correlation falls back to the path.
+pub fn file_id(metadata: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.ino() ^ metadata.dev().rotate_left(40)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        0
+    }
+}
+
+/// Stable IDs of the entries before a scan, so rescanned directories keep theirs.
+#[derive(Debug, Default)]
+pub struct EntryIds {
+    by_path:    HashMap<PathBuf, (u64, u64)>,
+    by_file_id: HashMap<u64, (u64, PathBuf)>,
+    known:      HashSet<u64>,
+}
+
+impl EntryIds {
+    pub fn from_entries<'a>(entries: impl IntoIterator<Item = &'a DirEntry>) -> Self {
+        let mut ids = EntryIds::default();
+        for entry in entries.into_iter().filter(|entry| entry.id != 0) {
+            ids.by_path.insert(entry.path.clone(), (entry.id, entry.file_id));
+            if entry.file_id != 0 {
+                ids.by_file_id.insert(entry.file_id, (entry.id, entry.path.clone()));
+            }
+            ids.known.insert(entry.id);
+        }
+        ids
+    }
+
+    /// Previous ID and path of `entry`: same filesystem object first (follows renames), then
+    /// same path when neither side has a filesystem identity to compare.
+    fn previous_id<'a>(&'a self, entry: &'a DirEntry) -> Option<(u64, &'a Path)> {
+        if entry.file_id != 0 {
+            if let Some((id, path)) = self.by_file_id.get(&entry.file_id) {
+                return Some((*id, path));
+            }
+        }
+
+        match self.by_path.get(&entry.path) {
+            Some((id, previous_file_id)) if *previous_file_id == 0 || entry.file_id == 0 => Some((*id, &entry.path)),
+            _ => None,
+        }
+    }
+
+    pub fn contains_id(&self, id: u64) -> bool {
+        self.known.contains(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.known.is_empty()
+    }
+}
+
+/// Sort `files` largest first and drop all but the `LARGEST_FILES_KEPT` largest.
+pub fn keep_largest_files(files: &mut Vec<LargeFile>) {
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    files.truncate(LARGEST_FILES_KEPT);
+}
+
+/// Sort `dirs` largest on disk first and drop all but the `LARGEST_DIRS_KEPT` largest on disk
+/// and the `LARGEST_DIRS_KEPT` largest in logical size, so `--apparent-size` finds its own.
+pub fn keep_largest_dirs(dirs: &mut Vec<LargeDir>) {
+    let logical: HashSet<PathBuf> = if dirs.len() > LARGEST_DIRS_KEPT {
+        dirs.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.path.cmp(&b.path)));
+        dirs[..LARGEST_DIRS_KEPT].iter().map(|dir| dir.path.clone()).collect()
+    } else {
+        HashSet::new()
+    };
+    dirs.sort_by(|a, b| {
+        b.allocated_size
+            .cmp(&a.allocated_size)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    let mut rank = 0;
+    dirs.retain(|dir| {
+        rank += 1;
+        rank <= LARGEST_DIRS_KEPT || logical.contains(&dir.path)
+    });
 }
 
 /// Compute Merkle tree-style content hash for a directory
@@ -40,6 +630,7 @@ pub struct DirEntry {
 /// - Directory path (normalized)
 /// - Modification timestamp (as i64)
 /// - Number of children (file count)
+/// - Files and total size of the subtree
 /// - Sorted child names (alphabetically)
 /// - Sorted child content hashes (for subdirectories)
 ///
@@ -48,11 +639,16 @@ pub struct DirEntry {
 /// - Deleted files/directories
 /// - Renamed items
 /// - Timestamp changes
+/// - Files that grew or shrank in place
 /// - Recursive child changes (due to Merkle structure)
+///
+/// Entries of `child_hashes` not directly below `path` are ignored but still cost a
+/// comparison each, so pass only the children's (hashing a tree stays linear).
 pub fn compute_content_hash(
     path: &Path,
     modified: DateTime<Utc>,
-    children: &[String],
+    children: &[Name],
+    (file_count, total_size): (usize, u64),
     child_hashes: &HashMap<PathBuf, u64>,
 ) -> u64 {
     let mut hasher = DefaultHasher::new();
@@ -64,14 +660,17 @@ pub fn compute_content_hash(
     // 2. Hash modification timestamp (as i64)
     modified.timestamp().hash(&mut hasher);
 
-    // 3. Hash children count
+    // 3. Hash children count, then the subtree's file count and size
     children.len().hash(&mut hasher);
+    file_count.hash(&mut hasher);
+    total_size.hash(&mut hasher);
 
     // 4. Hash sorted child names
     let mut sorted_children = children.to_vec();
     sorted_children.sort();
     for child_name in &sorted_children {
-        child_name.hash(&mut hasher);
+        // The text, not the ID: IDs differ from one process to the next
+        child_name.as_str().hash(&mut hasher);
     }
 
     // 5. Hash sorted child hashes (Merkle tree propagation)
@@ -114,7 +713,10 @@ pub fn has_directory_changed(old_entry: &DirEntry, new_entry: &DirEntry) -> bool
 /// This is enforced at the type level through bounded path handling and
 /// non-recursive DFS traversal. The 200-byte bound includes:
 /// - PathBuf key in HashMap (varies, but path length is constrained)
-/// - DirEntry value (name String, metadata, Vec<String> children)
+/// - DirEntry value (interned name, metadata, Vec<Name> children)
+///
+/// `memory_estimate` tracks the entries' actual footprint; with `max_memory_bytes` set, a
+/// full scan spills finished subtrees to disk (see `cache_spill`) once it exceeds the budget.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DiskCache {
     /// Map of absolute paths to directory entries
@@ -126,6 +728,10 @@ pub struct DiskCache {
     /// Root path (e.g., C:\)
     pub root: PathBuf,
 
+    /// Every top-level root of a multi-root scan, in command-line order (`root` is the first).
+    /// Empty for the usual single-root scan.
+    pub roots: Vec<PathBuf>,
+
     /// Last scanned directory (for subsequent runs to only scan current dir)
     pub last_scanned_root: PathBuf,
 
@@ -145,10 +751,174 @@ pub struct DiskCache {
     #[serde(skip)]
     pub show_hidden: bool,
 
+    /// Order of each directory's children in output (`--sort`)
+    #[serde(skip)]
+    pub sort: SortOrder,
+
+    /// Print each directory's children in the opposite order (`--reverse`)
+    #[serde(skip)]
+    pub reverse: bool,
+
+    /// Render chains of single-subdirectory directories as one line (`--compact-dirs`)
+    #[serde(skip)]
+    pub compact_dirs: bool,
+
+    /// Children shown per directory before a `… (+K more)` line (`--limit`); `None` shows all
+    #[serde(skip)]
+    pub child_limit: Option<usize>,
+
+    /// Show, total and sort by logical sizes (`--apparent-size`) rather than sizes on disk
+    #[serde(skip)]
+    pub apparent_size: bool,
+
+    /// Branch glyphs of the tree output (`--charset`, `--style`)
+    #[serde(skip)]
+    pub glyphs: TreeGlyphs,
+
+    /// Colors of the colored tree output (`--theme`)
+    #[serde(skip)]
+    pub theme: Theme,
+
+    /// Names or paths in tree output, and paths in JSON (`--full-path`, `--relative-to`)
+    #[serde(skip)]
+    pub path_display: PathDisplay,
+
+    /// Structure of JSON output (`--json-schema`); v1, as before schemas, in a cache opened
+    /// or created without one (`DiskCache::default()` has v2, the command line's default)
+    #[serde(skip)]
+    pub json_schema: JsonSchema,
+
+    /// Modification times output shows (`--newer-than`, `--modified-since`, `--modified-before`),
+    /// and the entries modified then (with their ancestors); set with `set_modified_range`
+    #[serde(skip)]
+    pub(crate) modified_range: Option<(Range<DateTime<Utc>>, HashSet<PathBuf>)>,
+
+    /// Smallest and largest recursive size of the directories output shows; set with
+    /// `set_size_range`
+    #[serde(skip)]
+    pub(crate) size_range: Option<(u64, u64)>,
+
+    /// What the totals line counts when the roots shown are subtrees of the snapshot
+    /// (`--query`, `ptree show PATH`): the directories below them, and their files and sizes
+    #[serde(skip)]
+    pub report_totals: Option<SnapshotTotals>,
+
     /// Skip statistics: count of skipped directories by name
     #[serde(skip)]
     pub skip_stats: std::collections::HashMap<String, usize>,
 
+    /// Package stores found during the last full scan, with hard-link reuse accounted once
+    #[serde(skip)]
+    pub store_usage: Vec<StoreUsage>,
+
+    /// Directories still changing after the `--settle` re-check (their snapshot is fuzzy)
+    #[serde(skip)]
+    pub unsettled_dirs: HashSet<PathBuf>,
+
+    /// Large directories near the roots that an earlier snapshot did not have (shown as `[new]`)
+    #[serde(skip)]
+    pub new_dirs: HashMap<PathBuf, NewDir>,
+
+    /// Directories whose listing was slow on recent scans (shown as `[slow]` once listed)
+    #[serde(skip)]
+    pub slow_dirs: HashMap<PathBuf, SlowDir>,
+
+    /// Files and directories carrying named alternate data streams, as of `ads_scanned`
+    #[serde(skip)]
+    pub alt_streams: HashMap<PathBuf, Vec<AltStream>>,
+
+    /// When a scan last enumerated alternate data streams (`--ads`); `None` if none has
+    #[serde(skip)]
+    pub ads_scanned: Option<DateTime<Utc>>,
+
+    /// Files and directories carrying extended attributes, as of `xattrs_scanned`
+    #[serde(skip)]
+    pub xattrs: HashMap<PathBuf, Vec<XAttr>>,
+
+    /// When a scan last enumerated extended attributes (`--xattrs`); `None` if none has
+    #[serde(skip)]
+    pub xattrs_scanned: Option<DateTime<Utc>>,
+
+    /// Bytes and files each user owns directly in each directory, as of `owners_scanned`
+    #[serde(skip)]
+    pub owner_usage: HashMap<PathBuf, Vec<OwnerUsage>>,
+
+    /// When a scan last recorded file owners (`--owners`); `None` if none has
+    #[serde(skip)]
+    pub owners_scanned: Option<DateTime<Utc>>,
+
+    /// Directories on another filesystem that `--one-file-system` scans did not enter (shown as
+    /// `[mount]`)
+    #[serde(skip)]
+    pub mount_points: HashSet<PathBuf>,
+
+    /// Whether the snapshot was taken with `--one-file-system`; only scans with the same
+    /// setting reuse it
+    #[serde(skip)]
+    pub one_file_system: bool,
+
+    /// Whether the snapshot's sizes count every hard link (`--count-links`) rather than each
+    /// file once; only scans with the same setting reuse it
+    #[serde(skip)]
+    pub count_links: bool,
+
+    /// Fingerprint of the skip rules (`--skip`, `--skip-regex`, `--gitignore`, markers,
+    /// snapshots) the snapshot was taken with; a scan under other rules reads every directory
+    #[serde(skip)]
+    pub skip_rules: u64,
+
+    /// Symbolic links below the directories read by `--follow-symlinks` scans, with their
+    /// targets and whether they were followed
+    #[serde(skip)]
+    pub symlinks: HashMap<PathBuf, Symlink>,
+
+    /// Directories that could not be read when last reached, and why
+    #[serde(skip)]
+    pub scan_errors: HashMap<PathBuf, ScanError>,
+
+    /// Directories an interrupted scan queued but did not read, or `DiskCache::invalidate` forgot.
+    /// While any are left the snapshot is incomplete: it is never served as a cache hit, and
+    /// the next run reads them.
+    #[serde(skip)]
+    pub pending_dirs: Vec<PathBuf>,
+
+    /// Files and bytes of each extension directly in each directory, as of `extensions_scanned`
+    #[serde(skip)]
+    pub extension_usage: HashMap<PathBuf, Vec<ExtensionUsage>>,
+
+    /// When a scan last recorded file extensions (`--extensions`); `None` if none has
+    #[serde(skip)]
+    pub extensions_scanned: Option<DateTime<Utc>>,
+
+    /// Permissions, owner, size and modification time of every file and directory read, as of
+    /// `metadata_scanned`
+    #[serde(skip)]
+    pub entry_metadata: HashMap<PathBuf, EntryMetadata>,
+
+    /// When a scan last recorded entry metadata (`--long`); `None` if none has
+    #[serde(skip)]
+    pub metadata_scanned: Option<DateTime<Utc>>,
+
+    /// Show the recorded metadata in columns before each name (`--long`)
+    #[serde(skip)]
+    pub long_listing: bool,
+
+    /// User names of the owners in `entry_metadata`, looked up once for the long listing
+    #[serde(skip)]
+    pub owner_names: HashMap<u32, String>,
+
+    /// Directories below the roots where another btrfs subvolume or ZFS dataset begins
+    #[serde(skip)]
+    pub subvolumes: HashMap<PathBuf, Subvolume>,
+
+    /// The `LARGEST_FILES_KEPT` largest files below the roots, largest first
+    #[serde(skip)]
+    pub largest_files: Vec<LargeFile>,
+
+    /// The largest directories below the roots as of the last save (see `keep_largest_dirs`)
+    #[serde(skip)]
+    pub largest_dirs: Vec<LargeDir>,
+
     /// True when cache metadata/files were loaded from disk.
     /// Used to distinguish "lazy-loaded cache" from true first run.
     #[serde(skip)]
@@ -161,6 +931,55 @@ pub struct DiskCache {
     /// File-count hint loaded from the cache index for cheap cache-hit stats.
     #[serde(skip)]
     pub persisted_file_count: usize,
+
+    /// True when the persisted snapshot is a reduced, directory-only fallback
+    /// written because the cache volume ran out of space.
+    #[serde(skip)]
+    pub persisted_reduced: bool,
+
+    /// What the persisted snapshot left out to fit a size budget or a full volume.
+    #[serde(skip)]
+    pub persisted_trim: CacheTrim,
+
+    /// On-disk size budget for saved snapshots (`--cache-max-size`); `None` is unlimited.
+    #[serde(skip)]
+    pub max_cache_bytes: Option<u64>,
+
+    /// Write zstd-compressed shards (`--cache-compress`); loaded from the persisted snapshot.
+    #[serde(skip)]
+    pub compress: bool,
+
+    /// Generation of the persisted snapshot; each full save writes the next one.
+    #[serde(skip)]
+    pub generation: u64,
+
+    /// How long `save` waits for another process's save to finish (`--lock-timeout`).
+    #[serde(skip)]
+    pub lock_timeout: Duration,
+
+    /// Next stable entry ID to hand out; IDs are never reused.
+    #[serde(skip)]
+    pub next_entry_id: u64,
+
+    /// Number of the latest scan that read the disk; each one takes the next number.
+    #[serde(skip)]
+    pub scan: u64,
+
+    /// Scans that last verified some of the entries: when each finished and how many it verifies
+    #[serde(skip)]
+    pub scans: BTreeMap<u64, ScanRecord>,
+
+    /// Memory budget for `entries` during a full scan (`--max-memory`); `None` is unlimited.
+    #[serde(skip)]
+    pub max_memory_bytes: Option<u64>,
+
+    /// Running estimate of the memory `entries` takes, kept up to date by `flush_pending_writes`
+    #[serde(skip)]
+    pub(crate) memory_estimate: u64,
+
+    /// Subtrees evicted from `entries` to stay within `max_memory_bytes`, until the next save
+    #[serde(skip)]
+    pub(crate) spill: SpillStore,
 }
 
 impl DiskCache {
@@ -174,6 +993,7 @@ impl DiskCache {
     /// - Load index only (~1ms for millions of entries)
     /// - Defer entry deserialization until output phase
     /// - Use in-memory entries for traversal building
+    #[tracing::instrument(name = "cache_load", skip_all, fields(path = %path.display()))]
     pub fn open(path: &Path) -> Result<Self> {
         fs::create_dir_all(path.parent().unwrap())?;
 
@@ -182,99 +1002,437 @@ impl DiskCache {
         let data_path = path.with_extension("dat");
 
         if index_path.exists() {
+            if let Ok(cache) = Self::load_from_lazy_cache(&index_path, &data_path) {
+                return Ok(cache);
+            }
+
+            // The snapshot may just be mid-save in another process: wait for it, then retry
+            // before treating it as corrupt
+            tracing::debug!("snapshot unreadable, retrying once no save holds the lock");
+            let _lock = CacheLock::acquire(path, DEFAULT_LOCK_TIMEOUT)?;
             match Self::load_from_lazy_cache(&index_path, &data_path) {
                 Ok(cache) => return Ok(cache),
-                Err(_) => Self::recover_snapshot(&index_path, &data_path)?,
+                Err(err) => {
+                    tracing::warn!(error = %err, "snapshot unreadable, recovering");
+                    Self::recover_snapshot(&index_path, &data_path)?
+                }
             }
         }
 
+        tracing::debug!("no snapshot yet");
         Ok(Self::new_empty())
     }
 
     /// Load from lazy cache format - index only (fast cold start)
     /// Entries not loaded until output phase to minimize startup time
-    fn load_from_lazy_cache(index_path: &Path, data_path: &Path) -> Result<Self> {
+    pub(crate) fn load_from_lazy_cache(index_path: &Path, data_path: &Path) -> Result<Self> {
         use crate::cache_rkyv::RkyvMmapCache;
 
-        let rkyv_cache = RkyvMmapCache::open(index_path, data_path)?;
+        let mut rkyv_cache = RkyvMmapCache::open(index_path, data_path)?;
+
+        // Compaction rewrites the snapshot, so it runs only under the cache lock and on the
+        // snapshot as it is once the lock is held: another process may have saved a newer one
+        // since it was opened. Best effort: a failed compaction leaves the snapshot as it was,
+        // and one that would have to wait for another process's save is left for a later run.
+        if rkyv_cache.needs_compaction() {
+            if let Some(_lock) = CacheLock::try_acquire(index_path) {
+                if let Ok(mut locked) = RkyvMmapCache::open(index_path, data_path) {
+                    if locked.needs_compaction() && locked.compact(index_path).is_ok() {
+                        rkyv_cache = locked;
+                    }
+                }
+            }
+        }
 
+        Ok(Self::from_records(&rkyv_cache))
+    }
+
+    /// The cache of an open snapshot: its index, with no entries loaded yet (see
+    /// `load_subtree_from`), for commands that read the records themselves.
+    pub fn from_records(rkyv_cache: &crate::cache_rkyv::RkyvMmapCache) -> Self {
         // DO NOT load all entries - keep HashMap empty for cold-start speed
         // Entries will be loaded on-demand during output formatting
 
-        Ok(DiskCache {
+        DiskCache {
             entries:                   HashMap::new(), // Empty - entries loaded on-demand
             last_scan:                 rkyv_cache.index.last_scan,
             root:                      rkyv_cache.index.root.clone(),
+            roots:                     rkyv_cache.index.roots.clone(),
             last_scanned_root:         rkyv_cache.index.last_scanned_root.clone(),
             #[cfg(windows)]
             usn_state:                 rkyv_cache.index.usn_state.clone(),
             pending_writes:            Vec::new(),
             flush_threshold:           5000,
             show_hidden:               false,
+            sort:                      SortOrder::Name,
+            reverse:                   false,
+            compact_dirs:              false,
+            child_limit:               None,
+            apparent_size:             false,
+            glyphs:                    TreeGlyphs::default(),
+            theme:                     Theme::default(),
+            path_display:              PathDisplay::Name,
+            json_schema:               JsonSchema::V1,
+            modified_range:            None,
+            size_range:                None,
+            report_totals:             None,
             skip_stats:                rkyv_cache.index.skip_stats.clone(),
+            store_usage:               rkyv_cache.index.store_usage.clone(),
+            unsettled_dirs:            rkyv_cache.index.unsettled_dirs.clone(),
+            new_dirs:                  rkyv_cache.index.new_dirs.clone(),
+            slow_dirs:                 rkyv_cache.index.slow_dirs.clone(),
+            alt_streams:               rkyv_cache.index.alt_streams.clone(),
+            ads_scanned:               rkyv_cache.index.ads_scanned,
+            xattrs:                    rkyv_cache.index.xattrs.clone(),
+            xattrs_scanned:            rkyv_cache.index.xattrs_scanned,
+            owner_usage:               rkyv_cache.index.owner_usage.clone(),
+            owners_scanned:            rkyv_cache.index.owners_scanned,
+            extension_usage:           rkyv_cache.index.extension_usage.clone(),
+            extensions_scanned:        rkyv_cache.index.extensions_scanned,
+            entry_metadata:            rkyv_cache.index.entry_metadata.clone(),
+            metadata_scanned:          rkyv_cache.index.metadata_scanned,
+            long_listing:              false,
+            owner_names:               HashMap::new(),
+            largest_files:             rkyv_cache.index.largest_files.clone(),
+            largest_dirs:              rkyv_cache.index.largest_dirs.clone(),
+            subvolumes:                rkyv_cache.index.subvolumes.clone(),
+            mount_points:              rkyv_cache.index.mount_points.clone(),
+            one_file_system:           rkyv_cache.index.one_file_system,
+            count_links:               rkyv_cache.index.count_links,
+            skip_rules:                rkyv_cache.index.skip_rules,
+            symlinks:                  rkyv_cache.index.symlinks.clone(),
+            scan_errors:               rkyv_cache.index.scan_errors.clone(),
+            pending_dirs:              rkyv_cache.index.pending_dirs.clone(),
             has_persisted_snapshot:    true,
             persisted_entry_count:     rkyv_cache.index.offsets.len(),
             persisted_file_count:      rkyv_cache.index.total_files,
-        })
+            persisted_reduced:         rkyv_cache.index.reduced,
+            persisted_trim:            rkyv_cache.index.trim,
+            max_cache_bytes:           None,
+            compress:                  rkyv_cache.index.compressed,
+            generation:                rkyv_cache.index.generation,
+            lock_timeout:              DEFAULT_LOCK_TIMEOUT,
+            next_entry_id:             rkyv_cache.index.next_entry_id,
+            scan:                      rkyv_cache.index.scan,
+            scans:                     rkyv_cache.index.scans.clone(),
+            max_memory_bytes:          None,
+            memory_estimate:           0,
+            spill:                     SpillStore::default(),
+        }
     }
 
     /// Create a new empty cache with default USN state
     #[cfg(windows)]
-    fn new_empty() -> Self {
+    pub(crate) fn new_empty() -> Self {
         DiskCache {
             // Pre-allocate for typical disk with ~100k directories
             // Reduces reallocation overhead during traversal
             entries:                HashMap::with_capacity(100_000),
             last_scan:              Utc::now(),
             root:                   PathBuf::new(),
+            roots:                  Vec::new(),
             last_scanned_root:      PathBuf::new(),
             usn_state:              USNJournalState::default(),
             pending_writes:         Vec::with_capacity(5000),
             flush_threshold:        5000,
             show_hidden:            false,
+            sort:                   SortOrder::Name,
+            reverse:                false,
+            compact_dirs:           false,
+            child_limit:            None,
+            apparent_size:          false,
+            glyphs:                 TreeGlyphs::default(),
+            theme:                  Theme::default(),
+            path_display:           PathDisplay::Name,
+            json_schema:            JsonSchema::V1,
+            modified_range:         None,
+            size_range:             None,
+            report_totals:          None,
             skip_stats:             HashMap::new(),
+            store_usage:            Vec::new(),
+            unsettled_dirs:         HashSet::new(),
+            new_dirs:               HashMap::new(),
+            slow_dirs:              HashMap::new(),
+            alt_streams:            HashMap::new(),
+            ads_scanned:            None,
+            xattrs:                 HashMap::new(),
+            xattrs_scanned:         None,
+            owner_usage:            HashMap::new(),
+            owners_scanned:         None,
+            extension_usage:        HashMap::new(),
+            extensions_scanned:     None,
+            entry_metadata:         HashMap::new(),
+            metadata_scanned:       None,
+            long_listing:           false,
+            owner_names:            HashMap::new(),
+            largest_files:          Vec::new(),
+            largest_dirs:           Vec::new(),
+            subvolumes:             HashMap::new(),
+            mount_points:           HashSet::new(),
+            one_file_system:        false,
+            count_links:            false,
+            skip_rules:             0,
+            symlinks:               HashMap::new(),
+            scan_errors:            HashMap::new(),
+            pending_dirs:           Vec::new(),
             has_persisted_snapshot: false,
             persisted_entry_count:  0,
             persisted_file_count:   0,
+            persisted_reduced:      false,
+            persisted_trim:         CacheTrim::default(),
+            max_cache_bytes:        None,
+            compress:               false,
+            generation:             0,
+            lock_timeout:           DEFAULT_LOCK_TIMEOUT,
+            next_entry_id:          1,
+            scan:                   0,
+            scans:                  BTreeMap::new(),
+            max_memory_bytes:       None,
+            memory_estimate:        0,
+            spill:                  SpillStore::default(),
         }
     }
 
     /// Create a new empty cache with default USN state (non-Windows)
     #[cfg(not(windows))]
-    fn new_empty() -> Self {
+    pub(crate) fn new_empty() -> Self {
         DiskCache {
             // Pre-allocate for typical disk with ~100k directories
             // Reduces reallocation overhead during traversal
             entries:                HashMap::with_capacity(100_000),
             last_scan:              Utc::now(),
             root:                   PathBuf::new(),
+            roots:                  Vec::new(),
             last_scanned_root:      PathBuf::new(),
             pending_writes:         Vec::with_capacity(5000),
             flush_threshold:        5000,
             show_hidden:            false,
+            sort:                   SortOrder::Name,
+            reverse:                false,
+            compact_dirs:           false,
+            child_limit:            None,
+            apparent_size:          false,
+            glyphs:                 TreeGlyphs::default(),
+            theme:                  Theme::default(),
+            path_display:           PathDisplay::Name,
+            json_schema:            JsonSchema::V1,
+            modified_range:         None,
+            size_range:             None,
+            report_totals:          None,
             skip_stats:             HashMap::new(),
+            store_usage:            Vec::new(),
+            unsettled_dirs:         HashSet::new(),
+            new_dirs:               HashMap::new(),
+            slow_dirs:              HashMap::new(),
+            alt_streams:            HashMap::new(),
+            ads_scanned:            None,
+            xattrs:                 HashMap::new(),
+            xattrs_scanned:         None,
+            owner_usage:            HashMap::new(),
+            owners_scanned:         None,
+            extension_usage:        HashMap::new(),
+            extensions_scanned:     None,
+            entry_metadata:         HashMap::new(),
+            metadata_scanned:       None,
+            long_listing:           false,
+            owner_names:            HashMap::new(),
+            largest_files:          Vec::new(),
+            largest_dirs:           Vec::new(),
+            subvolumes:             HashMap::new(),
+            mount_points:           HashSet::new(),
+            one_file_system:        false,
+            count_links:            false,
+            skip_rules:             0,
+            symlinks:               HashMap::new(),
+            scan_errors:            HashMap::new(),
+            pending_dirs:           Vec::new(),
             has_persisted_snapshot: false,
             persisted_entry_count:  0,
             persisted_file_count:   0,
+            persisted_reduced:      false,
+            persisted_trim:         CacheTrim::default(),
+            max_cache_bytes:        None,
+            compress:               false,
+            generation:             0,
+            lock_timeout:           DEFAULT_LOCK_TIMEOUT,
+            next_entry_id:          1,
+            scan:                   0,
+            scans:                  BTreeMap::new(),
+            max_memory_bytes:       None,
+            memory_estimate:        0,
+            spill:                  SpillStore::default(),
         }
     }
 
     /// Save cache using rkyv mmap format (index + data files with O(1) access)
+    #[tracing::instrument(name = "cache_save", skip_all, fields(path = %path.display()))]
     pub fn save(&mut self, path: &Path) -> Result<()> {
+        let _lock = CacheLock::acquire(path, self.lock_timeout)?;
         self.flush_pending_writes();
         self.has_persisted_snapshot = true;
-        self.persisted_entry_count = self.entries.len();
-        self.persisted_file_count = self
-            .entries
-            .get(&self.root)
-            .map(|entry| entry.file_count)
-            .unwrap_or_else(|| self.entries.values().map(|entry| entry.file_count).sum());
+        self.persisted_entry_count = self.entries.len() + self.spill.entries();
+        self.persisted_file_count = self.loaded_file_count();
 
         let index_path = path.with_extension("idx");
         let data_path = path.with_extension("dat");
 
-        self.save_as_rkyv_mmap(&index_path, &data_path)?;
-        Ok(())
+        let (trim, evicted) = self
+            .max_cache_bytes
+            .map(|budget| self.plan_budget_trim(budget))
+            .unwrap_or_default();
+
+        let saved = match self.save_as_rkyv_mmap(&index_path, &data_path, &trim, &evicted, false) {
+            Ok(()) => {
+                self.persisted_reduced = false;
+                self.persisted_trim = trim;
+                Ok(())
+            }
+            Err(err) if cache_space::is_storage_full(&err) => {
+                self.save_reduced_after_disk_full(&index_path, &data_path)
+            }
+            Err(err) => Err(err),
+        };
+
+        // Spilled subtrees are part of the snapshot now
+        if saved.is_ok() {
+            self.spill.clear()?;
+        }
+        saved
+    }
+
+    /// Fallback for a cache volume that filled up mid-save.
+    ///
+    /// The failed attempt already removed its temp files, so the previous snapshot is
+    /// still intact. Retry with a directory-only snapshot (file names dropped from
+    /// `children`); if even that does not fit, keep the previous generation.
+    fn save_reduced_after_disk_full(&mut self, index_path: &Path, data_path: &Path) -> Result<()> {
+        let needed = Self::format_size(self.estimated_snapshot_size());
+        let space = match cache_space::available_space(index_path.parent().unwrap_or_else(|| Path::new("."))) {
+            Some(available) => format!("needed ~{}, available {}", needed, Self::format_size(available)),
+            None => format!("needed ~{}", needed),
+        };
+
+        let trim = CacheTrim {
+            files_dropped: true,
+            ..Default::default()
+        };
+
+        match self.save_as_rkyv_mmap(index_path, data_path, &trim, &HashSet::new(), true) {
+            Ok(()) => {
+                eprintln!(
+                    "warning: cache volume is full ({space}); saved a reduced directory-only cache. \
+                     Free some space to restore file entries on the next scan."
+                );
+                self.persisted_reduced = true;
+                self.persisted_trim = trim;
+                Ok(())
+            }
+            Err(err) if cache_space::is_storage_full(&err) => {
+                eprintln!("warning: cache volume is full ({space}); cache was not updated, previous snapshot kept.");
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Approximate on-disk size of a full snapshot (shards + index).
+    fn estimated_snapshot_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|(path, entry)| Self::estimated_record_size(path, entry, &entry.children))
+            .sum::<u64>()
+            + self.spill.bytes()
+    }
+
+    /// Approximate bytes one entry costs in a snapshot: its shard record plus its index node
+    /// (records and nodes carry only the last path component).
+    fn estimated_record_size(path: &Path, entry: &DirEntry, children: &[Name]) -> u64 {
+        let children_bytes: u64 = children.iter().map(|name| name.len() as u64 + 8).sum();
+        let fixed = bincode::serialized_size(&(&entry.name, entry.modified)).unwrap_or(0) + 64;
+        let index_slot = path.file_name().map_or(0, |name| name.len() as u64) + 32;
+        RECORD_HEADER_LEN + fixed + children_bytes + index_slot
+    }
+
+    fn relative_depth(&self, path: &Path) -> usize {
+        let root = self
+            .roots
+            .iter()
+            .find(|root| path.starts_with(root))
+            .unwrap_or(&self.root);
+        path.components().count().saturating_sub(root.components().count())
+    }
+
+    /// Decide what to leave out of the next snapshot so it fits in `budget` bytes, and which
+    /// directories that leaves out.
+    ///
+    /// File names go first (the directory structure and every aggregate survive); if that is
+    /// still too large, whole subtrees go, least recently modified first. A subtree is as
+    /// recent as the newest directory in it, so a directory only goes after all those below it.
+    pub fn plan_budget_trim(&self, budget: u64) -> (CacheTrim, HashSet<PathBuf>) {
+        let mut trim = CacheTrim {
+            budget_bytes: Some(budget),
+            ..Default::default()
+        };
+        let mut evicted = HashSet::new();
+
+        if self.estimated_snapshot_size() <= budget {
+            return (trim, evicted);
+        }
+        trim.files_dropped = true;
+
+        // Directory-only size of each record, and the newest modification below each directory
+        let mut sizes: HashMap<&Path, u64> = HashMap::with_capacity(self.entries.len());
+        let mut newest: HashMap<&Path, DateTime<Utc>> = HashMap::with_capacity(self.entries.len());
+        for (path, entry) in &self.entries {
+            let dir_children: Vec<Name> = entry
+                .children
+                .iter()
+                .filter(|name| self.entries.contains_key(&path.join(name)))
+                .cloned()
+                .collect();
+            sizes.insert(path, Self::estimated_record_size(path, entry, &dir_children));
+            for ancestor in path
+                .ancestors()
+                .take_while(|ancestor| self.entries.contains_key(*ancestor))
+            {
+                let modified = newest.entry(ancestor).or_insert(entry.modified);
+                *modified = (*modified).max(entry.modified);
+            }
+        }
+
+        let mut total: u64 = sizes.values().sum();
+        // Always keep the roots
+        let mut candidates: Vec<&Path> = sizes
+            .keys()
+            .copied()
+            .filter(|path| self.relative_depth(path) > 0)
+            .collect();
+        candidates.sort_by_key(|path| (newest[path], Reverse(path.components().count())));
+        for path in candidates {
+            if total <= budget {
+                break;
+            }
+            total -= sizes[path];
+            evicted.insert(path.to_path_buf());
+        }
+        trim.entries_dropped = evicted.len();
+
+        (trim, evicted)
+    }
+
+    /// What the persisted snapshot left out (see `--cache-max-size`).
+    pub fn snapshot_trim(&self) -> CacheTrim {
+        self.persisted_trim
+    }
+
+    /// True if the persisted snapshot is a directory-only fallback and should not be served as a cache hit.
+    pub fn is_reduced_snapshot(&self) -> bool {
+        self.persisted_reduced
+    }
+
+    /// True if an interrupted scan left directories of the snapshot unread (see `pending_dirs`).
+    pub fn is_incomplete(&self) -> bool {
+        !self.pending_dirs.is_empty()
     }
 
     /// True if we have an existing on-disk cache snapshot.
@@ -287,7 +1445,7 @@ impl DiskCache {
         if self.entries.is_empty() {
             self.persisted_entry_count
         } else {
-            self.entries.len()
+            self.entries.len() + self.spill.entries()
         }
     }
 
@@ -296,242 +1454,1232 @@ impl DiskCache {
         if self.entries.is_empty() {
             self.persisted_file_count
         } else {
-            self.entries
-                .get(&self.root)
-                .map(|entry| entry.file_count)
-                .unwrap_or_else(|| self.entries.values().map(|entry| entry.file_count).sum())
+            self.loaded_file_count()
         }
     }
 
-    /// Save cache in mmap format (index + data files with bincode serialization)
-    fn save_as_rkyv_mmap(&self, index_path: &Path, data_path: &Path) -> Result<()> {
-        use crate::cache_rkyv::{RkyvCacheIndex, RkyvDirEntry};
+    /// Total files under every top-level root that is loaded.
+    fn loaded_file_count(&self) -> usize {
+        let root_counts: Vec<usize> = self
+            .top_level_roots()
+            .iter()
+            .filter_map(|root| self.entries.get(root).map(|entry| entry.file_count))
+            .collect();
 
-        fs::create_dir_all(index_path.parent().unwrap())?;
+        if root_counts.is_empty() {
+            self.entries.values().map(|entry| entry.file_count).sum()
+        } else {
+            root_counts.into_iter().sum()
+        }
+    }
 
-        // Build index with byte offsets
-        let mut rkyv_index = RkyvCacheIndex::new();
-        rkyv_index.offsets = HashMap::with_capacity(self.entries.len());
-        rkyv_index.total_files = self
-            .entries
-            .get(&self.root)
-            .map(|entry| entry.file_count)
-            .unwrap_or_else(|| self.entries.values().map(|entry| entry.file_count).sum());
-        rkyv_index.root = self.root.clone();
-        rkyv_index.last_scanned_root = self.last_scanned_root.clone();
-        rkyv_index.last_scan = self.last_scan;
-        rkyv_index.skip_stats = self.skip_stats.clone();
-        #[cfg(windows)]
-        {
-            rkyv_index.usn_state = self.usn_state.clone();
+    /// Top-level roots to render: every root of a multi-root scan, or just `root`.
+    pub fn top_level_roots(&self) -> Vec<PathBuf> {
+        if self.roots.is_empty() {
+            vec![self.root.clone()]
+        } else {
+            self.roots.clone()
         }
+    }
 
-        // Group entries by depth for depth-split files
-        let mut entries_by_depth: BTreeMap<u32, Vec<(PathBuf, RkyvDirEntry)>> = BTreeMap::new();
+    /// Stable IDs before a scan: the entries in memory, or else every record of the snapshot on disk.
+    pub fn previous_entry_ids(&self, cache_path: &Path) -> Result<EntryIds> {
+        use crate::cache_rkyv::RkyvMmapCache;
 
-        for (path, entry) in &self.entries {
-            let depth = path.components().count() as u32;
-            let rkyv_entry = RkyvDirEntry {
-                path:         entry.path.clone(),
-                name:         entry.name.clone(),
-                modified:     entry.modified,
-                content_hash: entry.content_hash,
-                file_count:   entry.file_count,
-                total_size:   entry.total_size,
-                children:     entry.children.clone(),
-                is_hidden:    entry.is_hidden,
-                is_dir:       entry.is_dir,
-            };
-            entries_by_depth
-                .entry(depth)
-                .or_insert_with(Vec::new)
-                .push((path.clone(), rkyv_entry));
+        if !self.entries.is_empty() || !self.has_persisted_snapshot {
+            return Ok(EntryIds::from_entries(self.entries.values()));
         }
 
-        let mut final_depth_files = HashSet::new();
-        let mut temp_depth_files = Vec::new();
+        let rkyv_cache = RkyvMmapCache::open(&cache_path.with_extension("idx"), &cache_path.with_extension("dat"))?;
+        Ok(EntryIds::from_entries(rkyv_cache.get_all()?.values()))
+    }
 
-        // Write each depth to its own temp file first so the index never points
-        // at partially rewritten shards.
-        for (depth, entries) in entries_by_depth {
-            if depth >= 31 {
-                continue; // Skip pathological depths
+    /// Stable IDs before an incremental scan that reads the directories `read`: theirs, those
+    /// of the directories right below them, and of everything below the ones gone from disk
+    /// (a rename reported as a deletion and a creation keeps its IDs that way).
+    pub fn entry_ids_around(&self, read: &HashSet<PathBuf>) -> EntryIds {
+        let mut around = Vec::new();
+        for entry in read.iter().filter_map(|path| self.entries.get(path)) {
+            around.push(entry);
+            for child in entry
+                .children
+                .iter()
+                .filter_map(|name| self.entries.get(&entry.path.join(name)))
+            {
+                if read.contains(&child.path) {
+                    continue;
+                }
+                if child.path.is_dir() {
+                    around.push(child);
+                } else {
+                    around.extend(self.subtree_entries(&child.path));
+                }
             }
+        }
+        EntryIds::from_entries(around)
+    }
 
-            // Generate depth-split filename
-            let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
-            let parent = data_path.parent().unwrap_or_else(|| std::path::Path::new("."));
-            let depth_file = parent.join(format!("{}-d{}.dat", stem, depth));
-            let temp_file = parent.join(format!("{}-d{}.dat.tmp", stem, depth));
-            final_depth_files.insert(depth_file.clone());
+    /// The entry at `path` and every one below it, found through `children`.
+    fn subtree_entries<'a>(&'a self, path: &Path) -> Vec<&'a DirEntry> {
+        let mut found = Vec::new();
+        let mut stack = vec![path.to_path_buf()];
+        while let Some(path) = stack.pop() {
+            if let Some(entry) = self.entries.get(&path) {
+                stack.extend(entry.children.iter().map(|name| path.join(name)));
+                found.push(entry);
+            }
+        }
+        found
+    }
 
-            let file = File::create(&temp_file)?;
-            let mut writer = BufWriter::with_capacity(8 * 1024 * 1024, file);
-            let mut offset: u64 = 0;
+    /// Give every entry without an ID (the ones just scanned) its previous ID, or a fresh one.
+    /// A `[new]` flag follows its directory when the ID is found under another path.
+    pub fn assign_entry_ids(&mut self, previous: &EntryIds) {
+        let mut used: HashSet<u64> = self
+            .entries
+            .values()
+            .map(|entry| entry.id)
+            .filter(|id| *id != 0)
+            .chain(self.spill.claimed_ids().iter().copied())
+            .collect();
+        let unassigned: Vec<PathBuf> = self.entries.keys().cloned().collect();
+        self.assign_ids(unassigned, previous, &mut used);
+    }
 
-            for (path, rkyv_entry) in entries {
-                let serialized = bincode::serialize(&rkyv_entry)?;
-                let len = serialized.len() as u32;
+    /// `assign_entry_ids` for the entries at `paths`; IDs in `used` are taken (and the ones
+    /// handed out are added to it).
+    pub(crate) fn assign_ids(&mut self, paths: Vec<PathBuf>, previous: &EntryIds, used: &mut HashSet<u64>) {
+        self.next_entry_id = self.next_entry_id.max(1);
 
-                rkyv_index.offsets.insert(path, (depth, offset));
-                writer.write_all(&len.to_le_bytes())?;
-                writer.write_all(&serialized)?;
-                offset += 4 + len as u64;
-            }
-            writer.flush()?;
-            writer.get_ref().sync_all()?;
-            drop(writer);
-            temp_depth_files.push((temp_file, depth_file));
-        }
+        // Shallow first, so a renamed directory claims its ID before anything below it
+        let mut unassigned: Vec<PathBuf> = paths
+            .into_iter()
+            .filter(|path| self.entries.get(path).is_some_and(|entry| entry.id == 0))
+            .collect();
+        unassigned.sort_by(|a, b| {
+            a.components()
+                .count()
+                .cmp(&b.components().count())
+                .then_with(|| a.cmp(b))
+        });
 
-        for (temp_file, depth_file) in &temp_depth_files {
-            fs::rename(temp_file, depth_file)?;
+        for path in unassigned {
+            let Some(entry) = self.entries.get_mut(&path) else {
+                continue;
+            };
+            entry.id = match previous.previous_id(entry) {
+                Some((id, previous_path)) if used.insert(id) => {
+                    if previous_path != path {
+                        if let Some(flag) = self.new_dirs.remove(previous_path) {
+                            self.new_dirs.insert(path.clone(), flag);
+                        }
+                    }
+                    id
+                }
+                _ => {
+                    let id = self.next_entry_id;
+                    self.next_entry_id += 1;
+                    used.insert(id);
+                    id
+                }
+            };
         }
+    }
 
-        Self::remove_stale_depth_files(data_path, &final_depth_files)?;
+    /// Find the entry with stable ID `id` in the snapshot at `cache_path`.
+    pub fn find_by_id(cache_path: &Path, id: u64) -> Result<Option<DirEntry>> {
+        use crate::cache_rkyv::RkyvMmapCache;
 
-        // Save index
-        let index_serialized = bincode::serialize(&rkyv_index)?;
-        let temp_path = index_path.with_extension("tmp");
-        let index_file = File::create(&temp_path)?;
-        let mut index_file = BufWriter::new(index_file);
-        index_file.write_all(&index_serialized)?;
-        index_file.flush()?;
-        index_file.get_ref().sync_all()?;
-        drop(index_file);
-        fs::rename(&temp_path, index_path)?;
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+        if !index_path.exists() {
+            anyhow::bail!("no cache snapshot at {}", cache_path.display());
+        }
 
-        Ok(())
+        // Only the matching record is copied out; the rest are checked in place
+        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        Ok(rkyv_cache
+            .find_entry(|path, record| (record.id == id).then(|| path.to_path_buf()))?
+            .map(|path| rkyv_cache.get_entry(&path))
+            .transpose()?
+            .flatten()
+            .map(Self::dir_entry_from_rkyv))
     }
 
-    fn remove_stale_depth_files(data_path: &Path, keep: &HashSet<PathBuf>) -> Result<()> {
-        let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
-        let parent = data_path.parent().unwrap_or_else(|| Path::new("."));
+    /// Flag directories within `NEW_DIR_LEVELS` of a root that are at least `min_bytes`
+    /// and whose stable ID `previous` did not have (a moved directory is not new). Only the
+    /// topmost new directory of a subtree is flagged; flags younger than `NEW_DIR_FLAG_HOURS`
+    /// are kept while the directory stays large.
+    pub fn flag_new_dirs(&mut self, previous: &EntryIds, min_bytes: u64, now: DateTime<Utc>) {
+        let mut candidates: Vec<&DirEntry> = self
+            .entries
+            .values()
+            .filter(|entry| entry.is_dir && entry.total_size >= min_bytes)
+            .filter(|entry| (1..=NEW_DIR_LEVELS).contains(&self.relative_depth(&entry.path)))
+            .filter(|entry| !previous.contains_id(entry.id) || self.new_dirs.contains_key(&entry.path))
+            .collect();
+        candidates.sort_by_key(|entry| entry.path.components().count());
 
-        for entry in fs::read_dir(parent)? {
-            let path = entry?.path();
-            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        let mut new_dirs: HashMap<PathBuf, NewDir> = HashMap::new();
+        for entry in candidates {
+            if new_dirs.keys().any(|flagged| entry.path.starts_with(flagged)) {
                 continue;
-            };
+            }
 
-            if name.starts_with(&format!("{stem}-d")) && name.ends_with(".dat") && !keep.contains(&path) {
-                let _ = fs::remove_file(path);
+            let first_seen = match self.new_dirs.get(&entry.path) {
+                Some(flag) if previous.contains_id(entry.id) => flag.first_seen,
+                _ => now,
+            };
+            if now.signed_duration_since(first_seen).num_hours() < NEW_DIR_FLAG_HOURS {
+                new_dirs.insert(
+                    entry.path.clone(),
+                    NewDir {
+                        size: entry.total_size,
+                        first_seen,
+                    },
+                );
             }
         }
+        self.new_dirs = new_dirs;
+    }
 
-        Ok(())
+    /// Record the mount points a `--one-file-system` scan stopped at below the directories it
+    /// read. A full scan replaces every earlier record; an incremental one those below the
+    /// directories it read, and any below directories that are gone.
+    pub fn record_mount_points(&mut self, found: Vec<PathBuf>, full_scan: bool) {
+        if full_scan {
+            self.mount_points.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.mount_points.retain(|path| {
+                path.parent()
+                    .and_then(|parent| entries.get(parent))
+                    .is_some_and(|parent| parent.scan != scan)
+            });
+        }
+        self.mount_points.extend(found);
     }
 
-    fn recover_snapshot(index_path: &Path, data_path: &Path) -> Result<()> {
-        let _ = fs::remove_file(index_path);
+    /// Record the symbolic links a `--follow-symlinks` scan met in the directories it read. A
+    /// full scan replaces every earlier record (and one without `--follow-symlinks` leaves
+    /// none); an incremental one those in the directories it read, and any in directories
+    /// that are gone.
+    pub fn record_symlinks(&mut self, found: Vec<(PathBuf, Symlink)>, full_scan: bool) {
+        if full_scan {
+            self.symlinks.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.symlinks.retain(|path, _| {
+                path.parent()
+                    .and_then(|parent| entries.get(parent))
+                    .is_some_and(|parent| parent.scan != scan)
+            });
+        }
+        self.symlinks.extend(found);
+    }
 
-        let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
-        let parent = data_path.parent().unwrap_or_else(|| Path::new("."));
+    /// Record the directories a scan could not read. A full scan replaces every earlier record;
+    /// an incremental one those below the directories it read, those it could read now, and any
+    /// below directories that are gone.
+    pub fn record_scan_errors(&mut self, found: Vec<(PathBuf, ScanError)>, full_scan: bool) {
+        if full_scan {
+            self.scan_errors.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.scan_errors.retain(|path, _| {
+                !entries.get(path).is_some_and(|entry| entry.scan == scan)
+                    && path
+                        .parent()
+                        .and_then(|parent| entries.get(parent))
+                        .is_some_and(|parent| parent.scan != scan)
+            });
+        }
+        self.scan_errors.extend(found);
+    }
 
-        for entry in fs::read_dir(parent)? {
-            let path = entry?.path();
-            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
-                continue;
-            };
+    /// `name -> target`, with the reason it was not followed (`[loop]`, ...)
+    fn symlink_label(name: &str, symlink: &Symlink) -> String {
+        match symlink.status.marker() {
+            Some(marker) => format!("{} -> {} {}", name, symlink.target.display(), marker),
+            None => format!("{} -> {}", name, symlink.target.display()),
+        }
+    }
 
-            if name.starts_with(&format!("{stem}-d")) && (name.ends_with(".dat") || name.ends_with(".dat.tmp")) {
-                let _ = fs::remove_file(path);
+    /// Flagged new directories, largest first.
+    pub fn new_dirs_by_size(&self) -> Vec<(&Path, u64)> {
+        let mut new_dirs: Vec<(&Path, u64)> = self
+            .new_dirs
+            .iter()
+            .map(|(path, flag)| (path.as_path(), flag.size))
+            .collect();
+        new_dirs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        new_dirs
+    }
+
+    /// Update the slow list from the listings a scan timed (in milliseconds): one taking
+    /// `SLOW_DIR_MILLIS` or more adds a strike, a faster one clears the directory. Entries in
+    /// or below `skipped` keep their strikes; after a full scan, any other entry the scan
+    /// did not time is dropped (the directory is gone).
+    pub fn record_slow_listings(
+        &mut self,
+        listings: &[(PathBuf, u64)],
+        skipped: &HashSet<PathBuf>,
+        full_scan: bool,
+        now: DateTime<Utc>,
+    ) {
+        let timed: HashSet<&Path> = listings.iter().map(|(path, _)| path.as_path()).collect();
+        if full_scan {
+            self.slow_dirs.retain(|path, _| {
+                timed.contains(path.as_path()) || skipped.iter().any(|skipped| path.starts_with(skipped))
+            });
+        }
+
+        for (path, millis) in listings {
+            if *millis < SLOW_DIR_MILLIS {
+                self.slow_dirs.remove(path);
+                continue;
             }
+            let strikes = self.slow_dirs.get(path).map_or(0, |slow| slow.strikes);
+            self.slow_dirs.insert(
+                path.clone(),
+                SlowDir {
+                    strikes:   strikes + 1,
+                    last_ms:   *millis,
+                    last_seen: now,
+                },
+            );
         }
+    }
 
-        Ok(())
+    /// Whether `path` is on the slow list.
+    pub fn is_slow(&self, path: &Path) -> bool {
+        self.slow_dirs.get(path).is_some_and(SlowDir::is_listed)
     }
 
-    // ============================================================================
-    // Entry Management
-    // ============================================================================
+    /// Directories on the slow list, by path.
+    pub fn listed_slow_dirs(&self) -> Vec<(&Path, &SlowDir)> {
+        let mut slow_dirs: Vec<(&Path, &SlowDir)> = self
+            .slow_dirs
+            .iter()
+            .filter(|(_, slow)| slow.is_listed())
+            .map(|(path, slow)| (path.as_path(), slow))
+            .collect();
+        slow_dirs.sort_by(|a, b| a.0.cmp(b.0));
+        slow_dirs
+    }
 
-    /// Buffer a directory entry for batch writing
-    pub fn buffer_entry(&mut self, path: PathBuf, entry: DirEntry) {
-        self.pending_writes.push((path, entry));
+    /// Record the alternate data streams an `--ads` scan found. A full scan replaces every
+    /// earlier record; an incremental one those of the directories it read (and their files).
+    pub fn record_alt_streams(&mut self, found: Vec<(PathBuf, Vec<AltStream>)>, full_scan: bool) {
+        if full_scan {
+            self.alt_streams.clear();
+        } else {
+            drop_reread(&mut self.alt_streams, &self.entries, self.scan);
+        }
+        self.alt_streams.extend(found);
+        self.ads_scanned = Some(self.last_scan);
+    }
 
-        if self.pending_writes.len() >= self.flush_threshold {
-            self.flush_pending_writes();
+    /// Record the extended attributes an `--xattrs` scan found, replacing earlier records
+    /// like `record_alt_streams`.
+    pub fn record_xattrs(&mut self, found: Vec<(PathBuf, Vec<XAttr>)>, full_scan: bool) {
+        if full_scan {
+            self.xattrs.clear();
+        } else {
+            drop_reread(&mut self.xattrs, &self.entries, self.scan);
         }
+        self.xattrs.extend(found);
+        self.xattrs_scanned = Some(self.last_scan);
     }
 
-    /// Flush all buffered writes to main cache HashMap
-    pub fn flush_pending_writes(&mut self) {
-        for (path, entry) in self.pending_writes.drain(..) {
-            self.entries.insert(path, entry);
+    /// Record the usage per owner an `--owners` scan found in the directories it read. A full
+    /// scan replaces every earlier record; an incremental one those of the directories it read.
+    pub fn record_owner_usage(&mut self, found: Vec<(PathBuf, Vec<OwnerUsage>)>, full_scan: bool) {
+        if full_scan {
+            self.owner_usage.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.owner_usage
+                .retain(|path, _| entries.get(path).is_some_and(|entry| entry.scan != scan));
         }
+        self.owner_usage.extend(found);
+        self.owners_scanned = Some(self.last_scan);
     }
 
-    /// Load entries on-demand from lazy cache (for cold-start output)
-    /// Only loads entries needed for tree building, not entire cache
-    pub fn load_entries_lazy(&mut self, paths: &[PathBuf], cache_path: &Path) -> Result<()> {
-        use crate::cache_rkyv::RkyvMmapCache;
+    /// Record the usage per extension an `--extensions` scan found in the directories it read.
+    /// A full scan replaces every earlier record; an incremental one those of the directories it
+    /// read.
+    pub fn record_extension_usage(&mut self, found: Vec<(PathBuf, Vec<ExtensionUsage>)>, full_scan: bool) {
+        if full_scan {
+            self.extension_usage.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.extension_usage
+                .retain(|path, _| entries.get(path).is_some_and(|entry| entry.scan != scan));
+        }
+        self.extension_usage.extend(found);
+        self.extensions_scanned = Some(self.last_scan);
+    }
 
-        let index_path = cache_path.with_extension("idx");
-        let data_path = cache_path.with_extension("dat");
+    /// Record the metadata a `--long` scan found, replacing earlier records like
+    /// `record_alt_streams`.
+    pub fn record_entry_metadata(&mut self, found: Vec<(PathBuf, EntryMetadata)>, full_scan: bool) {
+        if full_scan {
+            self.entry_metadata.clear();
+        } else {
+            drop_reread(&mut self.entry_metadata, &self.entries, self.scan);
+        }
+        self.entry_metadata.extend(found);
+        self.metadata_scanned = Some(self.last_scan);
+    }
 
-        if !index_path.exists() {
-            return Ok(());
+    /// Forget the `--long` records after a scan that read directories without recording them:
+    /// those directories may list other files now, so the next `--long` scan reads everything.
+    pub fn forget_entry_metadata_if_reread(&mut self) {
+        if self.metadata_scanned.is_some() && self.entries.values().any(|entry| entry.scan == self.scan) {
+            self.entry_metadata.clear();
+            self.metadata_scanned = None;
         }
+    }
 
-        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+    /// Files and bytes per extension over the snapshot, from the `--extensions` records; most
+    /// bytes first.
+    pub fn usage_by_extension(&self) -> Vec<ExtensionUsage> {
+        let mut totals: HashMap<&str, ExtensionUsage> = HashMap::new();
+        for usage in self.extension_usage.values().flatten() {
+            let total = totals.entry(&usage.extension).or_insert_with(|| {
+                ExtensionUsage {
+                    extension: usage.extension.clone(),
+                    ..ExtensionUsage::default()
+                }
+            });
+            total.files += usage.files;
+            total.bytes += usage.bytes;
+        }
+        let mut rows: Vec<ExtensionUsage> = totals.into_values().collect();
+        rows.sort_by(|a, b| {
+            (b.bytes, b.files)
+                .cmp(&(a.bytes, a.files))
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+        rows
+    }
 
-        for path in paths {
-            if !self.entries.contains_key(path) {
-                if let Some(rkyv_entry) = rkyv_cache.get_entry(path)? {
-                    self.entries.insert(path.clone(), Self::dir_entry_from_rkyv(rkyv_entry));
+    /// Files per extension over the snapshot, counted from the file names in `children` (no
+    /// sizes: the cache keeps none per file); most files first. Entries must be loaded.
+    /// Directories without an entry of their own (mount points, unreadable ones and those not
+    /// read yet) are left out rather than counted as files.
+    pub fn files_by_extension(&self) -> Vec<ExtensionUsage> {
+        let unread: HashSet<&Path> = self.pending_dirs.iter().map(PathBuf::as_path).collect();
+        let is_unread_dir = |path: &Path| {
+            self.mount_points.contains(path) || self.scan_errors.contains_key(path) || unread.contains(path)
+        };
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        for entry in self.entries.values().filter(|entry| entry.is_dir) {
+            for child in &entry.children {
+                let path = entry.path.join(child.as_str());
+                if !self.entries.contains_key(&path) && !is_unread_dir(&path) {
+                    *totals.entry(file_extension(child.as_str())).or_default() += 1;
                 }
             }
         }
-
-        Ok(())
+        let mut rows: Vec<ExtensionUsage> = totals
+            .into_iter()
+            .map(|(extension, files)| {
+                ExtensionUsage {
+                    extension,
+                    files,
+                    bytes: 0,
+                }
+            })
+            .collect();
+        rows.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.extension.cmp(&b.extension)));
+        rows
     }
 
-    /// Load only the directory entries needed to render output from the root.
-    ///
-    /// This keeps cache hits demand-driven: we load the root entry, then only
-    /// descend into child directories whose contents could actually be printed.
-    pub fn load_visible_entries_lazy(&mut self, cache_path: &Path, max_depth: Option<usize>) -> Result<()> {
-        use crate::cache_rkyv::RkyvMmapCache;
-
-        let index_path = cache_path.with_extension("idx");
-        let data_path = cache_path.with_extension("dat");
-
-        if !index_path.exists() {
-            return Ok(());
+    /// Record the largest files a scan found in the directories it read. A full scan replaces
+    /// the earlier list; an incremental one keeps the files of directories it did not reread.
+    pub fn record_largest_files(&mut self, found: Vec<LargeFile>, full_scan: bool) {
+        if full_scan {
+            self.largest_files.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.largest_files.retain(|file| {
+                file.path
+                    .parent()
+                    .and_then(|parent| entries.get(parent))
+                    .is_some_and(|entry| entry.scan != scan)
+            });
         }
-
-        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
-        let root = self.root.clone();
-        let mut visited = HashSet::new();
-
-        self.expand_visible_entries(&rkyv_cache, &root, 0, max_depth, &mut visited)
+        self.largest_files.extend(found);
+        keep_largest_files(&mut self.largest_files);
     }
 
-    /// Load all entries from lazy cache (fallback for full tree operations)
-    pub fn load_all_entries_lazy(&mut self, cache_path: &Path) -> Result<()> {
-        use crate::cache_rkyv::RkyvMmapCache;
-
-        let index_path = cache_path.with_extension("idx");
-        let data_path = cache_path.with_extension("dat");
+    /// The `limit` largest directories below the roots (the roots themselves left out) by the
+    /// shown size, largest first, from the list the last save kept; no entry is loaded.
+    pub fn top_dirs(&self, limit: usize) -> Vec<&LargeDir> {
+        let shown = |dir: &LargeDir| {
+            if self.apparent_size {
+                dir.total_size
+            } else {
+                dir.allocated_size
+            }
+        };
+        let mut dirs: Vec<&LargeDir> = self.largest_dirs.iter().collect();
+        dirs.sort_by(|a, b| shown(b).cmp(&shown(a)).then_with(|| a.path.cmp(&b.path)));
+        dirs.truncate(limit);
+        dirs
+    }
 
-        if !index_path.exists() {
-            return Ok(());
+    /// Directories, files and bytes per owner over the snapshot, from the `--owners` records;
+    /// most bytes first.
+    pub fn usage_per_owner(&self) -> Vec<OwnerUsage> {
+        let mut totals: BTreeMap<u32, OwnerUsage> = BTreeMap::new();
+        for usage in self.owner_usage.values().flatten() {
+            let total = totals.entry(usage.uid).or_insert(OwnerUsage {
+                uid: usage.uid,
+                ..OwnerUsage::default()
+            });
+            total.directories += usage.directories;
+            total.files += usage.files;
+            total.bytes += usage.bytes;
         }
+        let mut rows: Vec<OwnerUsage> = totals.into_values().collect();
+        rows.sort_by(|a, b| (b.bytes, b.files, a.uid).cmp(&(a.bytes, a.files, b.uid)));
+        rows
+    }
 
-        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
-        let lazy_entries = rkyv_cache.get_all()?;
-
-        for (path, entry) in lazy_entries {
-            if !self.entries.contains_key(&path) {
-                self.entries.insert(path, entry);
+    /// Usage per owner summed up to the directories `level` levels below each root (`/home/*`
+    /// at level 2 under `/`), by path. Files directly in a shallower directory count toward a
+    /// row of that directory's own.
+    pub fn usage_by_owner(&self, level: usize) -> Vec<OwnerRow> {
+        let roots = self.top_level_roots();
+        let mut rows: BTreeMap<PathBuf, BTreeMap<u32, OwnerUsage>> = BTreeMap::new();
+        for (path, usage) in &self.owner_usage {
+            let Some(root) = roots
+                .iter()
+                .filter(|root| path.starts_with(root))
+                .max_by_key(|root| root.as_os_str().len())
+            else {
+                continue;
+            };
+            let row = path
+                .strip_prefix(root)
+                .map(|relative| root.components().chain(relative.components().take(level)).collect())
+                .unwrap_or_else(|_| path.clone());
+            let owners = rows.entry(row).or_default();
+            for owned in usage {
+                let total = owners.entry(owned.uid).or_insert(OwnerUsage {
+                    uid: owned.uid,
+                    ..OwnerUsage::default()
+                });
+                total.directories += owned.directories;
+                total.files += owned.files;
+                total.bytes += owned.bytes;
             }
         }
+        rows.into_iter()
+            .map(|(path, owners)| OwnerRow { path, owners })
+            .collect()
+    }
 
-        Ok(())
+    /// Record the subvolume boundaries a scan found below the directories it read. A full scan
+    /// replaces every earlier record; an incremental one those found in the directories it
+    /// read, and any below directories that are gone.
+    pub fn record_subvolumes(&mut self, found: Vec<(PathBuf, Subvolume)>, full_scan: bool) {
+        if full_scan {
+            self.subvolumes.clear();
+        } else {
+            let (entries, scan) = (&self.entries, self.scan);
+            self.subvolumes.retain(|path, _| {
+                entries.contains_key(path)
+                    && !path
+                        .parent()
+                        .and_then(|parent| entries.get(parent))
+                        .is_some_and(|parent| parent.scan == scan)
+            });
+        }
+        self.subvolumes.extend(found);
     }
 
-    fn expand_visible_entries(
+    /// Subvolume boundaries by path.
+    pub fn listed_subvolumes(&self) -> Vec<(&Path, &Subvolume)> {
+        let mut subvolumes: Vec<(&Path, &Subvolume)> = self
+            .subvolumes
+            .iter()
+            .map(|(path, subvolume)| (path.as_path(), subvolume))
+            .collect();
+        subvolumes.sort_by(|a, b| a.0.cmp(b.0));
+        subvolumes
+    }
+
+    /// Paths with extended attributes whose name starts with one of `prefixes` (any attribute
+    /// if there are none), by path, each with the matching attributes.
+    pub fn reported_xattrs(&self, prefixes: &[String]) -> Vec<(&Path, Vec<&XAttr>)> {
+        let mut reported: Vec<(&Path, Vec<&XAttr>)> = self
+            .xattrs
+            .iter()
+            .map(|(path, attrs)| {
+                (
+                    path.as_path(),
+                    attrs
+                        .iter()
+                        .filter(|attr| {
+                            prefixes.is_empty() || prefixes.iter().any(|prefix| attr.name.starts_with(prefix.as_str()))
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(|(_, attrs)| !attrs.is_empty())
+            .collect();
+        reported.sort_by(|a, b| a.0.cmp(b.0));
+        reported
+    }
+
+    /// Paths with streams outside `EXPECTED_STREAMS` (every path with streams if `all`), by
+    /// path, each with the streams to report.
+    pub fn reported_alt_streams(&self, all: bool) -> Vec<(&Path, Vec<&AltStream>)> {
+        let mut reported: Vec<(&Path, Vec<&AltStream>)> = self
+            .alt_streams
+            .iter()
+            .map(|(path, streams)| {
+                (
+                    path.as_path(),
+                    streams
+                        .iter()
+                        .filter(|stream| all || !stream.is_expected())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(|(_, streams)| !streams.is_empty())
+            .collect();
+        reported.sort_by(|a, b| a.0.cmp(b.0));
+        reported
+    }
+
+    /// Totals of the entries in memory (after a scan).
+    pub fn totals(&self) -> SnapshotTotals {
+        self.totals_with(|path| self.entries.get(path).cloned())
+    }
+
+    /// Totals of the snapshot on disk, reading only the roots and their direct children.
+    pub fn persisted_totals(&self, cache_path: &Path) -> Result<Option<SnapshotTotals>> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        if !self.has_persisted_snapshot {
+            return Ok(None);
+        }
+
+        let rkyv_cache = RkyvMmapCache::open(&cache_path.with_extension("idx"), &cache_path.with_extension("dat"))?;
+        Ok(Some(self.totals_from(&rkyv_cache)))
+    }
+
+    /// Totals of an open snapshot, reading only the roots and their direct children.
+    pub fn totals_from(&self, rkyv_cache: &crate::cache_rkyv::RkyvMmapCache) -> SnapshotTotals {
+        self.totals_with(|path| rkyv_cache.get_entry(path).ok().flatten().map(Self::dir_entry_from_rkyv))
+    }
+
+    fn totals_with(&self, lookup: impl Fn(&Path) -> Option<DirEntry>) -> SnapshotTotals {
+        let mut totals = SnapshotTotals {
+            directories: self.entry_count_hint(),
+            files: self.file_count_hint(),
+            ..Default::default()
+        };
+
+        for root in self.top_level_roots() {
+            let Some(root_entry) = lookup(&root) else {
+                continue;
+            };
+            totals.bytes += root_entry.total_size;
+            totals.allocated_bytes += root_entry.allocated_size;
+            for child in &root_entry.children {
+                let child_path = root.join(child);
+                if let Some(child_entry) = lookup(&child_path) {
+                    totals.top_level.insert(child_path, child_entry.total_size);
+                }
+            }
+        }
+
+        totals
+    }
+
+    /// The line `tree` ends its listing with, `3 directories, 12 files, 4.2 MB total`, counting
+    /// the whole snapshot below the roots (or `report_totals`) rather than what the listing shows.
+    pub fn report_line(&self) -> String {
+        if let Some(totals) = &self.report_totals {
+            return self.format_report_line(totals.directories, totals);
+        }
+        let totals = self.totals();
+        // A cache hit loads only the visible directories; the index knows how many there are
+        let directories = if self.has_persisted_snapshot {
+            totals.directories.max(self.persisted_entry_count)
+        } else {
+            totals.directories
+        };
+        let directories = directories.saturating_sub(self.top_level_roots().len());
+        self.format_report_line(directories, &totals)
+    }
+
+    /// `N directories, M files, S total`, with the size on disk or (`apparent_size`) logical.
+    fn format_report_line(&self, directories: usize, totals: &SnapshotTotals) -> String {
+        let files = totals.files;
+        let bytes = if self.apparent_size {
+            totals.bytes
+        } else {
+            totals.allocated_bytes
+        };
+        format!(
+            "{} {}, {} {}, {} total",
+            directories,
+            if directories == 1 { "directory" } else { "directories" },
+            files,
+            if files == 1 { "file" } else { "files" },
+            Self::format_size(bytes)
+        )
+    }
+
+    fn rkyv_entry(entry: &DirEntry, children: Vec<Name>) -> crate::cache_rkyv::RkyvDirEntry {
+        crate::cache_rkyv::RkyvDirEntry {
+            path: entry.path.clone(),
+            name: entry.name.clone(),
+            modified: entry.modified,
+            content_hash: entry.content_hash,
+            file_count: entry.file_count,
+            total_size: entry.total_size,
+            allocated_size: entry.allocated_size,
+            children,
+            is_hidden: entry.is_hidden,
+            is_dir: entry.is_dir,
+            id: entry.id,
+            file_id: entry.file_id,
+            scan: entry.scan,
+        }
+    }
+
+    /// Save cache in mmap format (bincode index + depth shards of rkyv records)
+    ///
+    /// `trim` leaves file names out of the snapshot, and `evicted` the subtrees it dropped
+    /// (whose tops become `pending_dirs` of the snapshot); `reduced` marks a disk-full
+    /// fallback that must not be served as a cache hit.
+    pub(crate) fn save_as_rkyv_mmap(
+        &mut self,
+        index_path: &Path,
+        data_path: &Path,
+        trim: &CacheTrim,
+        evicted: &HashSet<PathBuf>,
+        reduced: bool,
+    ) -> Result<()> {
+        use crate::cache_rkyv::{RkyvCacheIndex, RkyvDirEntry};
+        use crate::path_index::PathIndex;
+
+        fs::create_dir_all(index_path.parent().unwrap())?;
+
+        // Build index with byte offsets
+        let mut rkyv_index = RkyvCacheIndex::new();
+        rkyv_index.offsets = PathIndex::with_capacity(self.entries.len() + self.spill.entries());
+        rkyv_index.total_files = self.loaded_file_count();
+        rkyv_index.root = self.root.clone();
+        rkyv_index.roots = self.roots.clone();
+        rkyv_index.last_scanned_root = self.last_scanned_root.clone();
+        rkyv_index.last_scan = self.last_scan;
+        rkyv_index.skip_stats = self.skip_stats.clone();
+        rkyv_index.store_usage = self.store_usage.clone();
+        rkyv_index.unsettled_dirs = self.unsettled_dirs.clone();
+        rkyv_index.new_dirs = self.new_dirs.clone();
+        rkyv_index.slow_dirs = self.slow_dirs.clone();
+        rkyv_index.alt_streams = self.alt_streams.clone();
+        rkyv_index.ads_scanned = self.ads_scanned;
+        rkyv_index.xattrs = self.xattrs.clone();
+        rkyv_index.subvolumes = self.subvolumes.clone();
+        rkyv_index.mount_points = self.mount_points.clone();
+        rkyv_index.one_file_system = self.one_file_system;
+        rkyv_index.count_links = self.count_links;
+        rkyv_index.skip_rules = self.skip_rules;
+        rkyv_index.symlinks = self.symlinks.clone();
+        rkyv_index.scan_errors = self.scan_errors.clone();
+        rkyv_index.pending_dirs = self.pending_dirs.clone();
+        let mut evicted_tops: Vec<PathBuf> = evicted
+            .iter()
+            .filter(|path| !path.parent().is_some_and(|parent| evicted.contains(parent)))
+            .cloned()
+            .collect();
+        evicted_tops.sort();
+        rkyv_index.pending_dirs.extend(evicted_tops);
+        rkyv_index.xattrs_scanned = self.xattrs_scanned;
+        rkyv_index.owner_usage = self.owner_usage.clone();
+        rkyv_index.owners_scanned = self.owners_scanned;
+        rkyv_index.extension_usage = self.extension_usage.clone();
+        rkyv_index.extensions_scanned = self.extensions_scanned;
+        rkyv_index.entry_metadata = self.entry_metadata.clone();
+        rkyv_index.metadata_scanned = self.metadata_scanned;
+        rkyv_index.largest_files = self.largest_files.clone();
+        rkyv_index.next_entry_id = self.next_entry_id;
+        rkyv_index.scan = self.scan;
+        rkyv_index.reduced = reduced;
+        rkyv_index.trim = *trim;
+        rkyv_index.compressed = self.compress;
+        // Never reuse a generation, even one whose save failed
+        self.generation += 1;
+        rkyv_index.generation = self.generation;
+        #[cfg(windows)]
+        {
+            rkyv_index.usn_state = self.usn_state.clone();
+        }
+
+        // Group entries by depth for depth-split files
+        let mut entries_by_depth: BTreeMap<u32, Vec<(PathBuf, RkyvDirEntry)>> = BTreeMap::new();
+        let mut kept = Vec::with_capacity(self.entries.len());
+
+        for (path, entry) in &self.entries {
+            if evicted.contains(path) {
+                continue;
+            }
+            kept.push(entry);
+
+            let depth = path.components().count() as u32;
+            let children = if trim.files_dropped {
+                entry
+                    .children
+                    .iter()
+                    .filter(|name| self.entries.contains_key(&path.join(name)))
+                    .cloned()
+                    .collect()
+            } else {
+                entry.children.clone()
+            };
+            entries_by_depth
+                .entry(depth)
+                .or_default()
+                .push((path.clone(), Self::rkyv_entry(entry, children)));
+        }
+
+        rkyv_index.scans = self.count_scans(kept.into_iter());
+
+        // Spilled directories are streamed from their files shard by shard, trimmed the same way
+        let spilled = |record: SpillRecord| {
+            let SpillRecord { entry, dir_children } = record;
+            if entry.path.ancestors().any(|ancestor| evicted.contains(ancestor)) {
+                return None;
+            }
+            let children = if trim.files_dropped {
+                dir_children
+            } else {
+                entry.children.clone()
+            };
+            Some((entry.path.clone(), Self::rkyv_entry(&entry, children)))
+        };
+
+        let mut written = Vec::new();
+        let index_temp_path = index_path.with_extension("tmp");
+
+        // Shards and the name table go to files of the new generation, next to the ones the
+        // current index points at, and the index to a temp file: a failed write (e.g. a full
+        // disk) or a crash leaves the previous generation as it was.
+        let result = Self::write_snapshot_files(
+            entries_by_depth,
+            &self.spill,
+            spilled,
+            &mut rkyv_index,
+            data_path,
+            &index_temp_path,
+            &mut written,
+        );
+        if let Err(err) = result {
+            for file in &written {
+                let _ = fs::remove_file(file);
+            }
+            let _ = fs::remove_file(&index_temp_path);
+            return Err(err);
+        }
+
+        // Renaming the index into place is the commit point: before it the old generation is
+        // the snapshot, after it the new one
+        fs::rename(&index_temp_path, index_path)?;
+        sync_dir(index_path.parent().unwrap_or_else(|| Path::new(".")))?;
+        self.largest_dirs = rkyv_index.largest_dirs;
+
+        Self::remove_other_generations(data_path, rkyv_index.generation);
+        Ok(())
+    }
+
+    fn write_snapshot_files(
+        mut entries_by_depth: BTreeMap<u32, Vec<(PathBuf, crate::cache_rkyv::RkyvDirEntry)>>,
+        spill: &SpillStore,
+        spilled: impl Fn(SpillRecord) -> Option<(PathBuf, crate::cache_rkyv::RkyvDirEntry)>,
+        rkyv_index: &mut crate::cache_rkyv::RkyvCacheIndex,
+        data_path: &Path,
+        index_temp_path: &Path,
+        written: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let mut names = NameTableWriter::new();
+        // `ptree du` reads the largest directories from the index instead of every record
+        let roots = if rkyv_index.roots.is_empty() {
+            vec![rkyv_index.root.clone()]
+        } else {
+            rkyv_index.roots.clone()
+        };
+        let mut largest_dirs = Vec::new();
+        let mut candidate = |path: &Path, entry: &crate::cache_rkyv::RkyvDirEntry| {
+            if entry.is_dir && !roots.iter().any(|root| root == path) {
+                largest_dirs.push(LargeDir {
+                    path:           path.to_path_buf(),
+                    total_size:     entry.total_size,
+                    allocated_size: entry.allocated_size,
+                });
+                if largest_dirs.len() > 4 * LARGEST_DIRS_KEPT {
+                    keep_largest_dirs(&mut largest_dirs);
+                }
+            }
+        };
+        let depths: BTreeSet<u32> = entries_by_depth.keys().copied().chain(spill.depths()).collect();
+        for depth in depths {
+            let entries = entries_by_depth.remove(&depth).unwrap_or_default();
+            if depth >= 31 {
+                continue; // Skip pathological depths
+            }
+
+            let depth_file = depth_file_path(data_path, rkyv_index.generation, depth);
+            written.push(depth_file.clone());
+
+            let file = File::create(&depth_file)?;
+            let mut writer = ShardWriter::create(
+                BufWriter::with_capacity(8 * 1024 * 1024, file),
+                rkyv_index.compressed,
+                rkyv_index.generation,
+            )?;
+
+            // Compressed blocks hold siblings together, so expanding one directory touches few blocks
+            let mut entries = entries;
+            if rkyv_index.compressed {
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+            }
+
+            // A spilled directory is never also in memory, so the order does not matter
+            spill.read(depth, |record| {
+                if let Some((path, rkyv_entry)) = spilled(record) {
+                    candidate(&path, &rkyv_entry);
+                    let offset = writer.push(&encode_record(&rkyv_entry, &mut names)?)?;
+                    rkyv_index.offsets.insert(&path, (depth, offset));
+                }
+                Ok(())
+            })?;
+            for (path, rkyv_entry) in entries {
+                candidate(&path, &rkyv_entry);
+                let offset = writer.push(&encode_record(&rkyv_entry, &mut names)?)?;
+                rkyv_index.offsets.insert(&path, (depth, offset));
+            }
+            let mut writer = writer.finish()?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+
+        // Every name the shards refer to, each once
+        let names_file = names_file_path(data_path, rkyv_index.generation);
+        written.push(names_file.clone());
+        names.write(&names_file, rkyv_index.generation)?;
+        keep_largest_dirs(&mut largest_dirs);
+        rkyv_index.largest_dirs = largest_dirs;
+
+        // Save index
+        let index_serialized = encode_index(rkyv_index)?;
+        let index_file = File::create(index_temp_path)?;
+        let mut index_file = BufWriter::new(index_file);
+        index_file.write_all(&index_serialized)?;
+        index_file.flush()?;
+        index_file.get_ref().sync_all()?;
+
+        Ok(())
+    }
+
+    /// Remove the shards and name tables of every generation but `generation` (and those of
+    /// versions that did not number them). One another process still has mapped stays
+    /// readable to it on Unix; on Windows it cannot be removed yet and goes with a later save.
+    fn remove_other_generations(data_path: &Path, generation: u64) {
+        let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+        let parent = data_path.parent().unwrap_or_else(|| Path::new("."));
+        let Ok(entries) = fs::read_dir(parent) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            if parse_snapshot_file(name, stem, true).is_some_and(|file| file.generation != Some(generation)) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Remove the snapshot at `path`, once no other process is saving it, so the next run
+    /// rescans its roots: for a snapshot whose records turned out corrupt as they were read.
+    pub fn discard_snapshot(path: &Path) -> Result<()> {
+        let _lock = CacheLock::acquire(path, DEFAULT_LOCK_TIMEOUT)?;
+        Self::recover_snapshot(&path.with_extension("idx"), &path.with_extension("dat"))
+    }
+
+    /// Where scans of the snapshot at `path` save their checkpoints, and interrupted scans of a
+    /// complete snapshot what they read: `--force` resumes from there, and the snapshot itself
+    /// is only replaced by a scan that completes.
+    pub fn resume_path(path: &Path) -> PathBuf {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+        path.with_file_name(format!("{stem}.resume.dat"))
+    }
+
+    /// Remove the resume point of the snapshot at `path` (see `resume_path`), if it has one.
+    pub fn remove_resume_point(path: &Path) {
+        let resume = Self::resume_path(path);
+        if resume.with_extension("idx").exists() {
+            let _ = Self::recover_snapshot(&resume.with_extension("idx"), &resume);
+            let _ = fs::remove_file(CacheLock::path_for(&resume));
+        }
+    }
+
+    fn recover_snapshot(index_path: &Path, data_path: &Path) -> Result<()> {
+        let _ = fs::remove_file(index_path);
+
+        let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+        let parent = data_path.parent().unwrap_or_else(|| Path::new("."));
+
+        for entry in fs::read_dir(parent)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            if is_snapshot_file(name, stem, true) {
+                let _ = fs::remove_file(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    // ============================================================================
+    // Entry Management
+    // ============================================================================
+
+    /// Buffer a directory entry for batch writing
+    pub fn buffer_entry(&mut self, path: PathBuf, entry: DirEntry) {
+        self.pending_writes.push((path, entry));
+
+        if self.pending_writes.len() >= self.flush_threshold {
+            self.flush_pending_writes();
+        }
+    }
+
+    /// Flush all buffered writes to main cache HashMap
+    pub fn flush_pending_writes(&mut self) {
+        for (path, entry) in self.pending_writes.drain(..) {
+            self.memory_estimate += Self::entry_memory(&path, &entry);
+            if let Some(replaced) = self.entries.insert(path, entry) {
+                self.memory_estimate = self
+                    .memory_estimate
+                    .saturating_sub(Self::entry_memory(&replaced.path, &replaced));
+            }
+        }
+    }
+
+    /// Load entries on-demand from lazy cache (for cold-start output)
+    /// Only loads entries needed for tree building, not entire cache
+    pub fn load_entries_lazy(&mut self, paths: &[PathBuf], cache_path: &Path) -> Result<()> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+
+        if !index_path.exists() {
+            return Ok(());
+        }
+
+        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+
+        for path in paths {
+            if !self.entries.contains_key(path) {
+                if let Some(rkyv_entry) = rkyv_cache.get_entry(path)? {
+                    self.entries.insert(path.clone(), Self::dir_entry_from_rkyv(rkyv_entry));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load only the directory entries needed to render output from the root.
+    ///
+    /// This keeps cache hits demand-driven: we load the root entry, then only
+    /// descend into child directories whose contents could actually be printed.
+    pub fn load_visible_entries_lazy(&mut self, cache_path: &Path, max_depth: Option<usize>) -> Result<()> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+
+        if !index_path.exists() {
+            return Ok(());
+        }
+
+        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        let mut visited = HashSet::new();
+
+        for root in self.top_level_roots() {
+            self.expand_visible_entries(&rkyv_cache, &root, 0, max_depth, &mut visited)?;
+        }
+        Ok(())
+    }
+
+    /// Load the directories of an open snapshot below `path` down to `max_depth` levels
+    /// (`path` itself being level 0), enough for `write_json_subtree` with the same depth.
+    pub fn load_subtree_from(
+        &mut self,
+        rkyv_cache: &crate::cache_rkyv::RkyvMmapCache,
+        path: &Path,
+        max_depth: Option<usize>,
+    ) -> Result<()> {
+        let mut visited = HashSet::new();
+        self.expand_visible_entries(rkyv_cache, path, 0, max_depth.map(|depth| depth + 1), &mut visited)
+    }
+
+    /// Load the persisted subtree below `root` (its directory records) to keep a directory
+    /// a scan skips as it was. Records hold subtree totals; the loaded ones are turned back
+    /// into per-directory counts, so `refresh_derived_metadata` sums them like scanned entries.
+    pub fn load_subtree_lazy(&mut self, root: &Path, cache_path: &Path) -> Result<()> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+
+        if !index_path.exists() {
+            return Ok(());
+        }
+
+        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        let mut visited = HashSet::new();
+        let present: HashSet<PathBuf> = self
+            .entries
+            .keys()
+            .filter(|path| path.starts_with(root))
+            .cloned()
+            .collect();
+        self.expand_visible_entries(&rkyv_cache, root, 0, None, &mut visited)?;
+
+        let direct: Vec<(PathBuf, (usize, u64, u64))> = visited
+            .into_iter()
+            .filter(|path| !present.contains(path))
+            .filter_map(|path| {
+                let entry = self.entries.get(&path)?;
+                let mut direct = (entry.file_count, entry.total_size, entry.allocated_size);
+                for child in entry
+                    .children
+                    .iter()
+                    .filter_map(|name| self.entries.get(&path.join(name)))
+                {
+                    direct.0 = direct.0.saturating_sub(child.file_count);
+                    direct.1 = direct.1.saturating_sub(child.total_size);
+                    direct.2 = direct.2.saturating_sub(child.allocated_size);
+                }
+                Some((path, direct))
+            })
+            .collect();
+        for (path, (file_count, total_size, allocated_size)) in direct {
+            if let Some(entry) = self.entries.get_mut(&path) {
+                entry.file_count = file_count;
+                entry.total_size = total_size;
+                entry.allocated_size = allocated_size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Load all entries from lazy cache (fallback for full tree operations)
+    pub fn load_all_entries_lazy(&mut self, cache_path: &Path) -> Result<()> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+
+        if !index_path.exists() {
+            return Ok(());
+        }
+
+        let rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        self.load_all_entries_from(&rkyv_cache)
+    }
+
+    /// Load every directory of an open snapshot that is not loaded yet.
+    pub fn load_all_entries_from(&mut self, rkyv_cache: &crate::cache_rkyv::RkyvMmapCache) -> Result<()> {
+        for (path, entry) in rkyv_cache.get_all()? {
+            self.entries.entry(path).or_insert(entry);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the snapshot at `cache_path` without records superseded by in-place updates
+    pub fn compact(cache_path: &Path) -> Result<CompactionStats> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+        if !index_path.exists() {
+            anyhow::bail!("no cache snapshot at {}", cache_path.display());
+        }
+
+        let _lock = CacheLock::acquire(cache_path, DEFAULT_LOCK_TIMEOUT)?;
+        RkyvMmapCache::open(&index_path, &data_path)?.compact(&index_path)
+    }
+
+    /// Rewrite the snapshot at `cache_path` with compressed (or plain) shards in place
+    pub fn set_compression(cache_path: &Path, compressed: bool) -> Result<CompactionStats> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+        if !index_path.exists() {
+            anyhow::bail!("no cache snapshot at {}", cache_path.display());
+        }
+
+        let _lock = CacheLock::acquire(cache_path, DEFAULT_LOCK_TIMEOUT)?;
+        RkyvMmapCache::open(&index_path, &data_path)?.rewrite(&index_path, compressed)
+    }
+
+    /// Forget the cached subtree at `path` in the snapshot at `cache_path` so the next scan
+    /// reads it again, the way it reads what an interrupted scan left. Only the index and the
+    /// records of the directories above are written, under the cache lock. Returns how many
+    /// directories were forgotten (none if `path` is not cached).
+    pub fn invalidate(cache_path: &Path, path: &Path) -> Result<usize> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+        if !index_path.exists() {
+            anyhow::bail!("no cache snapshot at {}", cache_path.display());
+        }
+
+        let _lock = CacheLock::acquire(cache_path, DEFAULT_LOCK_TIMEOUT)?;
+        let mut rkyv_cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        let forgotten = rkyv_cache.invalidate_subtree(path)?;
+        if forgotten > 0 {
+            rkyv_cache.save_index(&index_path)?;
+        }
+        Ok(forgotten)
+    }
+
+    /// Check every record of the snapshot at `cache_path` without trusting any of them
+    pub fn verify(cache_path: &Path) -> Result<VerifyReport> {
+        use crate::cache_rkyv::RkyvMmapCache;
+
+        let index_path = cache_path.with_extension("idx");
+        let data_path = cache_path.with_extension("dat");
+        if !index_path.exists() {
+            anyhow::bail!("no cache snapshot at {}", cache_path.display());
+        }
+
+        Ok(RkyvMmapCache::open_unverified(&index_path, &data_path)?.verify())
+    }
+
+    fn expand_visible_entries(
         &mut self,
         rkyv_cache: &crate::cache_rkyv::RkyvMmapCache,
         path: &Path,
@@ -585,17 +2733,21 @@ impl DiskCache {
         Ok(())
     }
 
-    fn dir_entry_from_rkyv(rkyv_entry: crate::cache_rkyv::RkyvDirEntry) -> DirEntry {
+    pub(crate) fn dir_entry_from_rkyv(rkyv_entry: crate::cache_rkyv::RkyvDirEntry) -> DirEntry {
         DirEntry {
-            path:         rkyv_entry.path,
-            name:         rkyv_entry.name,
-            modified:     rkyv_entry.modified,
-            content_hash: rkyv_entry.content_hash,
-            file_count:   rkyv_entry.file_count,
-            total_size:   rkyv_entry.total_size,
-            children:     rkyv_entry.children,
-            is_hidden:    rkyv_entry.is_hidden,
-            is_dir:       rkyv_entry.is_dir,
+            path:           rkyv_entry.path,
+            name:           rkyv_entry.name,
+            modified:       rkyv_entry.modified,
+            content_hash:   rkyv_entry.content_hash,
+            file_count:     rkyv_entry.file_count,
+            total_size:     rkyv_entry.total_size,
+            allocated_size: rkyv_entry.allocated_size,
+            children:       rkyv_entry.children,
+            is_hidden:      rkyv_entry.is_hidden,
+            is_dir:         rkyv_entry.is_dir,
+            id:             rkyv_entry.id,
+            file_id:        rkyv_entry.file_id,
+            scan:           rkyv_entry.scan,
         }
     }
 
@@ -639,7 +2791,7 @@ impl DiskCache {
 
         let mut report = String::from("Skip Statistics:\n");
         let mut sorted: Vec<_> = self.skip_stats.iter().collect();
-        sorted.sort_by_key(|(_name, count)| std::cmp::Reverse(**count));
+        sorted.sort_by_key(|(_name, count)| Reverse(**count));
 
         for (name, count) in sorted {
             report.push_str(&format!("  {} × {}\n", count, name));
@@ -648,6 +2800,155 @@ impl DiskCache {
         report
     }
 
+    /// The directories that could not be read, grouped by reason (`--errors`).
+    pub fn get_error_report(&self) -> String {
+        if self.scan_errors.is_empty() {
+            return "(every directory could be read)".to_string();
+        }
+
+        let mut by_kind: BTreeMap<ScanErrorKind, Vec<(&PathBuf, &ScanError)>> = BTreeMap::new();
+        for (path, error) in &self.scan_errors {
+            by_kind.entry(error.kind).or_default().push((path, error));
+        }
+        let count = self.scan_errors.len();
+        let mut report =
+            format!("Errors: {} {} could not be read\n", count, if count == 1 { "directory" } else { "directories" });
+        for (kind, mut errors) in by_kind {
+            errors.sort_by(|a, b| a.0.cmp(b.0));
+            report.push_str(&format!("  {} ({}):\n", kind.label(), errors.len()));
+            for (path, error) in errors {
+                report.push_str(&format!("    {}: {}\n", path.display(), error.message));
+            }
+        }
+
+        report
+    }
+
+    /// Logical vs. unique size and reuse percentage for every detected package store.
+    pub fn get_store_report(&self) -> String {
+        if self.store_usage.is_empty() {
+            return "(no package stores detected)".to_string();
+        }
+
+        let mut report = String::from("Package Stores:\n");
+        for usage in &self.store_usage {
+            report.push_str(&format!(
+                "  {} {}: {} logical, {} unique ({:.1}% reused), {} of {} files hard-linked\n",
+                usage.kind.label(),
+                usage.root.display(),
+                Self::format_size(usage.logical_bytes),
+                Self::format_size(usage.unique_bytes),
+                usage.reuse_percent(),
+                usage.linked_files,
+                usage.files
+            ));
+        }
+
+        report
+    }
+
+    /// Every subvolume boundary with the size the tree adds up and, where the filesystem
+    /// reports it, the volume's own usage.
+    pub fn get_subvolume_report(&self) -> String {
+        if self.subvolumes.is_empty() {
+            return "(no subvolumes below the roots)".to_string();
+        }
+
+        let mut report = String::from("Subvolumes:\n");
+        for (path, subvolume) in self.listed_subvolumes() {
+            let scanned = self
+                .entries
+                .get(path)
+                .map(|entry| Self::format_size(entry.total_size))
+                .unwrap_or_else(|| "not loaded".to_string());
+            let usage = match subvolume.usage_bytes {
+                Some(bytes) => format!(", {} used per the filesystem", Self::format_size(bytes)),
+                None => String::new(),
+            };
+            report.push_str(&format!(
+                "  {} {}: {} scanned{}\n",
+                subvolume.kind.label(),
+                path.display(),
+                scanned,
+                usage
+            ));
+        }
+
+        report
+    }
+
+    /// Describe the persisted snapshot at `cache_path` (`--cache-info`).
+    pub fn get_cache_info_report(&self, cache_path: &Path) -> String {
+        let mut report = String::from("Cache Info:\n");
+        report.push_str(&format!("  {:<18} {}\n", "Location:", cache_path.display()));
+
+        if !self.has_cache_snapshot() {
+            report.push_str("  (no cache snapshot)\n");
+            return report;
+        }
+
+        for root in self.top_level_roots() {
+            report.push_str(&format!("  {:<18} {}\n", "Root:", root.display()));
+        }
+        report.push_str(&format!("  {:<18} {}\n", "Last scan:", self.last_scan.to_rfc3339()));
+        report.push_str(&format!("  {:<18} {}\n", "Directories:", self.entry_count_hint()));
+        report.push_str(&format!("  {:<18} {}\n", "Files:", self.file_count_hint()));
+        report.push_str(&format!("  {:<18} {}\n", "Size on disk:", Self::format_size(snapshot_disk_size(cache_path))));
+        let compression = if self.compress { "zstd blocks" } else { "none" };
+        report.push_str(&format!("  {:<18} {}\n", "Compression:", compression));
+        if let Some(budget) = self.max_cache_bytes {
+            report.push_str(&format!("  {:<18} {}\n", "Size budget:", Self::format_size(budget)));
+        }
+        if self.is_reduced_snapshot() {
+            report.push_str(&format!("  {:<18} {}\n", "Reduced:", "yes (cache volume was full)"));
+        }
+        if self.is_incomplete() {
+            report.push_str(&format!(
+                "  {:<18} {} directories left to read (interrupted scan, `ptree cache invalidate` or size budget)\n",
+                "Incomplete:",
+                self.pending_dirs.len()
+            ));
+        }
+        report.push_str(&format!("  {:<18} {}\n", "Trimmed:", self.persisted_trim.describe()));
+        report.push_str(&self.scan_report());
+        if !self.unsettled_dirs.is_empty() {
+            report.push_str(&format!(
+                "  {:<18} {} directories still changing at scan time\n",
+                "Unsettled:",
+                self.unsettled_dirs.len()
+            ));
+        }
+        for (path, size) in self.new_dirs_by_size() {
+            report.push_str(&format!("  {:<18} {} ({})\n", "New:", path.display(), Self::format_size(size)));
+        }
+        for (path, slow) in self.listed_slow_dirs() {
+            report.push_str(&format!(
+                "  {:<18} {} ({} slow scans, last {:.1} s)\n",
+                "Slow:",
+                path.display(),
+                slow.strikes,
+                slow.last_ms as f64 / 1000.0
+            ));
+        }
+        for (path, subvolume) in self.listed_subvolumes() {
+            let usage = subvolume
+                .usage_bytes
+                .map(|bytes| format!(" ({} used)", Self::format_size(bytes)))
+                .unwrap_or_default();
+            report.push_str(&format!("  {:<18} {}{}\n", "Subvolume:", path.display(), usage));
+        }
+        for usage in &self.store_usage {
+            report.push_str(&format!(
+                "  {:<18} {} ({:.1}% reused)\n",
+                format!("{}:", usage.kind.label()),
+                usage.root.display(),
+                usage.reuse_percent()
+            ));
+        }
+
+        report
+    }
+
     /// Remove entry and all child entries
     pub fn remove_entry(&mut self, path: &Path) {
         // Path::starts_with checks path components, so "/foo" does not match "/foobar".
@@ -655,22 +2956,30 @@ impl DiskCache {
     }
 
     /// Remove cached directory subtrees whose names disappeared from a rescanned parent.
-    pub fn remove_missing_child_subtrees(&mut self, parent: &Path, current_children: &[String]) {
+    pub fn remove_missing_child_subtrees(&mut self, parent: &Path, current_children: &[Name]) {
         let Some(previous) = self.entries.get(parent) else {
             return;
         };
 
-        let current_children: std::collections::HashSet<&str> = current_children.iter().map(String::as_str).collect();
+        let current_children: HashSet<&Name> = current_children.iter().collect();
         let stale_paths: Vec<PathBuf> = previous
             .children
             .iter()
-            .filter(|child_name| !current_children.contains(child_name.as_str()))
+            .filter(|child_name| !current_children.contains(child_name))
             .map(|child_name| parent.join(child_name))
             .filter(|child_path| self.entries.contains_key(child_path))
             .collect();
 
+        // Through `children` rather than over every entry: incremental scans call this too
         for child_path in stale_paths {
-            self.remove_entry(&child_path);
+            let subtree: Vec<PathBuf> = self
+                .subtree_entries(&child_path)
+                .into_iter()
+                .map(|entry| entry.path.clone())
+                .collect();
+            for path in subtree {
+                self.entries.remove(&path);
+            }
         }
     }
 
@@ -688,25 +2997,17 @@ impl DiskCache {
         self.build_tree_output_with_options(max_depth, false, false)
     }
 
+    /// The whole tree as one String; for small trees and tests, as a full drive renders to
+    /// hundreds of MB. Output paths stream with `write_tree_output_with_options` instead.
     pub fn build_tree_output_with_options(
         &self,
         max_depth: Option<usize>,
         show_size: bool,
         show_file_count: bool,
     ) -> Result<String> {
-        let mut output = String::new();
-
-        if self.entries.is_empty() {
-            return Ok("(empty)\n".to_string());
-        }
-
-        let root = &self.root;
-        output.push_str(&format!("{}\n", root.display()));
-
-        // No need for visited set - filesystem is acyclic and in_progress set prevents cycles during traversal
-        self.print_tree(&mut output, root, "", true, 0, max_depth, show_size, show_file_count)?;
-
-        Ok(output)
+        let mut output = Vec::new();
+        self.write_tree_output_with_options(&mut output, max_depth, show_size, show_file_count)?;
+        Ok(String::from_utf8(output)?)
     }
 
     /// Stream ASCII tree directly to a writer to avoid building a giant String.
@@ -726,19 +3027,20 @@ impl DiskCache {
             return Ok(());
         }
 
-        let root = &self.root;
-        writeln!(writer, "{}", root.display())?;
-
-        self.write_tree(writer, root, "", true, 0, max_depth, show_size, show_file_count)?;
+        // Each root of a multi-root scan is rendered as its own top-level tree
+        for root in self.top_level_roots() {
+            writeln!(writer, "{}", self.root_label(&root))?;
+            self.write_tree(writer, &root, "", 0, max_depth, show_size, show_file_count)?;
+        }
         Ok(())
     }
 
-    fn print_tree(
+    #[allow(clippy::too_many_arguments)]
+    fn write_tree<W: Write>(
         &self,
-        output: &mut String,
+        writer: &mut W,
         path: &Path,
         prefix: &str,
-        is_last: bool,
         current_depth: usize,
         max_depth: Option<usize>,
         show_size: bool,
@@ -752,109 +3054,167 @@ impl DiskCache {
         }
 
         if let Some(entry) = self.entries.get(path) {
-            // Sort children only at output time (not during traversal)
-            let mut children: Vec<_> = entry.children.iter().collect();
-            children.sort();
+            let (children, more) = self.limited_children(path, entry);
 
             for (i, child_name) in children.iter().enumerate() {
-                let is_last_child = i == children.len() - 1;
-                let child_prefix = if is_last {
-                    "    ".to_string()
+                let is_last_child = more == 0 && i == children.len() - 1;
+                let child_prefix = if is_last_child {
+                    self.glyphs.space
                 } else {
-                    "│   ".to_string()
+                    self.glyphs.pipe
                 };
 
-                let branch = if is_last_child { "└── " } else { "├── " };
-
-                let child_path = path.join(child_name);
-                let display_name = if let Some(child_entry) = self.entries.get(&child_path) {
-                    let name = if self.show_hidden && child_entry.is_hidden {
-                        format!("{} [H]", child_name)
-                    } else {
-                        child_name.to_string()
-                    };
-                    format!("{}{}", name, Self::metadata_suffix(child_entry, show_size, show_file_count))
+                let branch = if is_last_child {
+                    self.glyphs.last
                 } else {
-                    child_name.to_string()
+                    self.glyphs.branch
                 };
 
-                output.push_str(&format!("{}{}{}\n", prefix, branch, display_name));
-                self.print_tree(
-                    output,
+                let (child_path, child_name) = self.output_child(child_name, path);
+                let display_name = self.child_label(&child_name, &child_path, show_size, show_file_count);
+
+                let columns = self.long_columns(&child_path);
+                writeln!(writer, "{}{}{}{}", prefix, branch, columns, display_name)?;
+                self.write_tree(
+                    writer,
                     &child_path,
                     &format!("{}{}", prefix, child_prefix),
-                    is_last_child,
                     current_depth + 1,
                     max_depth,
                     show_size,
                     show_file_count,
                 )?;
             }
+            if more > 0 {
+                writeln!(writer, "{}{}{}", prefix, self.glyphs.last, self.more_label(more))?;
+            }
         }
 
         Ok(())
     }
 
-    fn write_tree<W: Write>(
+    // ============================================================================
+    // Accessible Output
+    // ============================================================================
+
+    /// Screen-reader friendly output: one indented "level N: name, kind, ..." line per entry,
+    /// with no box-drawing glyphs or colors and every state spelled out.
+    pub fn write_accessible_output<W: Write>(
+        &self,
+        writer: &mut W,
+        max_depth: Option<usize>,
+        show_size: bool,
+        show_file_count: bool,
+    ) -> Result<()> {
+        if self.entries.is_empty() {
+            writeln!(writer, "empty: no cached entries")?;
+            return Ok(());
+        }
+
+        for root in self.top_level_roots() {
+            let name = self.root_label(&root);
+            writeln!(writer, "{}", self.accessible_line(&name, &root, 0, show_size, show_file_count))?;
+            self.write_accessible(writer, &root, 0, max_depth, show_size, show_file_count)?;
+        }
+        Ok(())
+    }
+
+    fn write_accessible<W: Write>(
         &self,
         writer: &mut W,
         path: &Path,
-        prefix: &str,
-        is_last: bool,
         current_depth: usize,
         max_depth: Option<usize>,
         show_size: bool,
         show_file_count: bool,
     ) -> Result<()> {
-        // Check depth limit
         if let Some(max) = max_depth {
             if current_depth >= max {
                 return Ok(());
             }
-        }
-
-        if let Some(entry) = self.entries.get(path) {
-            // Sort children only at output time (not during traversal)
-            let mut children: Vec<_> = entry.children.iter().collect();
-            children.sort();
-
-            for (i, child_name) in children.iter().enumerate() {
-                let is_last_child = i == children.len() - 1;
-                let child_prefix = if is_last {
-                    "    ".to_string()
-                } else {
-                    "│   ".to_string()
-                };
+        }
 
-                let branch = if is_last_child { "└── " } else { "├── " };
+        if let Some(entry) = self.entries.get(path) {
+            let (children, more) = self.limited_children(path, entry);
+
+            for child_name in children {
+                let (child_path, child_name) = self.output_child(child_name, path);
+                let line =
+                    self.accessible_line(&child_name, &child_path, current_depth + 1, show_size, show_file_count);
+                writeln!(writer, "{}", line)?;
+                self.write_accessible(writer, &child_path, current_depth + 1, max_depth, show_size, show_file_count)?;
+            }
+            if more > 0 {
+                let indent = "  ".repeat(current_depth + 1);
+                let entries = if more == 1 { "entry" } else { "entries" };
+                writeln!(writer, "{}level {}: {} more {} not shown", indent, current_depth + 1, more, entries)?;
+            }
+        }
 
-                let child_path = path.join(child_name);
-                let display_name = if let Some(child_entry) = self.entries.get(&child_path) {
-                    let name = if self.show_hidden && child_entry.is_hidden {
-                        format!("{} [H]", child_name)
-                    } else {
-                        child_name.to_string()
-                    };
-                    format!("{}{}", name, Self::metadata_suffix(child_entry, show_size, show_file_count))
-                } else {
-                    child_name.to_string()
-                };
+        Ok(())
+    }
 
-                writeln!(writer, "{}{}{}", prefix, branch, display_name)?;
-                self.write_tree(
-                    writer,
-                    &child_path,
-                    &format!("{}{}", prefix, child_prefix),
-                    is_last_child,
-                    current_depth + 1,
-                    max_depth,
-                    show_size,
-                    show_file_count,
-                )?;
+    /// `level 3: src, directory, 14 items, 1.2 MB, 80 files, hidden`
+    fn accessible_line(&self, name: &str, path: &Path, depth: usize, show_size: bool, show_file_count: bool) -> String {
+        let indent = "  ".repeat(depth);
+        let Some(entry) = self.entries.get(path) else {
+            if self.mount_points.contains(path) {
+                return format!("{}level {}: {}, mount point, not read", indent, depth, name);
+            }
+            if let Some(error) = self.scan_errors.get(path) {
+                return format!("{}level {}: {}, directory, not read: {}", indent, depth, name, error.kind.label());
             }
+            if let Some(symlink) = self.symlinks.get(path) {
+                return format!(
+                    "{}level {}: {}, symbolic link to {}, {}",
+                    indent,
+                    depth,
+                    name,
+                    symlink.target.display(),
+                    symlink.status.label()
+                );
+            }
+            let details: String = self
+                .long_details(path)
+                .iter()
+                .map(|detail| format!(", {detail}"))
+                .collect();
+            return format!("{}level {}: {}, file{}", indent, depth, name, details);
+        };
+
+        let items = entry.children.len();
+        let mut parts = vec![
+            format!("level {}: {}", depth, name),
+            "directory".to_string(),
+            format!("{} {}", items, if items == 1 { "item" } else { "items" }),
+        ];
+        if let Some(symlink) = self.symlinks.get(path) {
+            parts.push(format!("symbolic link to {}", symlink.target.display()));
+        }
+        if show_size {
+            parts.extend(self.size_parts(entry));
+        }
+        if show_file_count {
+            parts.push(format!("{} files", entry.file_count));
+        }
+        parts.extend(self.long_details(path));
+        if self.show_hidden && entry.is_hidden {
+            parts.push("hidden".to_string());
+        }
+        if self.unsettled_dirs.contains(path) {
+            parts.push("unsettled, changed during the scan".to_string());
+        }
+        if self.new_dirs.contains_key(path) {
+            parts.push("new since the last scan".to_string());
+        }
+        if let Some(subvolume) = self.subvolumes.get(path) {
+            parts.push(subvolume.kind.label().to_string());
+        }
+        if self.is_slow(path) {
+            parts.push("slow, skipped by scheduled refreshes".to_string());
         }
 
-        Ok(())
+        format!("{}{}", indent, parts.join(", "))
     }
 
     // ============================================================================
@@ -871,25 +3231,16 @@ impl DiskCache {
         self.build_colored_tree_output_with_options(max_depth, false, false)
     }
 
+    /// The whole colored tree as one String (see `build_tree_output_with_options`)
     pub fn build_colored_tree_output_with_options(
         &self,
         max_depth: Option<usize>,
         show_size: bool,
         show_file_count: bool,
     ) -> Result<String> {
-        let mut output = String::new();
-
-        if self.entries.is_empty() {
-            return Ok("(empty)\n".to_string());
-        }
-
-        let root = &self.root;
-        output.push_str(&format!("{}\n", root.display().to_string().blue().bold()));
-
-        // No need for visited set - filesystem is acyclic and in_progress set prevents cycles during traversal
-        self.print_colored_tree(&mut output, root, "", true, 0, max_depth, show_size, show_file_count)?;
-
-        Ok(output)
+        let mut output = Vec::new();
+        self.write_colored_tree_output_with_options(&mut output, max_depth, show_size, show_file_count)?;
+        Ok(String::from_utf8(output)?)
     }
 
     /// Stream colored tree directly to a writer.
@@ -913,89 +3264,19 @@ impl DiskCache {
             return Ok(());
         }
 
-        let root = &self.root;
-        writeln!(writer, "{}", root.display().to_string().blue().bold())?;
-
-        self.write_colored_tree(writer, root, "", true, 0, max_depth, show_size, show_file_count)?;
-        Ok(())
-    }
-
-    fn print_colored_tree(
-        &self,
-        output: &mut String,
-        path: &Path,
-        prefix: &str,
-        is_last: bool,
-        current_depth: usize,
-        max_depth: Option<usize>,
-        show_size: bool,
-        show_file_count: bool,
-    ) -> Result<()> {
-        // Check depth limit
-        if let Some(max) = max_depth {
-            if current_depth >= max {
-                return Ok(());
-            }
-        }
-
-        if let Some(entry) = self.entries.get(path) {
-            // Sort children only at output time (not during traversal)
-            // Use parallel sort for large directories (>500 children)
-            let mut children: Vec<_> = entry.children.iter().collect();
-            if children.len() > 500 {
-                children.par_sort();
-            } else {
-                children.sort();
-            }
-
-            for (i, child_name) in children.iter().enumerate() {
-                let is_last_child = i == children.len() - 1;
-                let child_prefix = if is_last {
-                    "    ".to_string()
-                } else {
-                    "│   ".to_string()
-                };
-
-                let branch = if is_last_child { "└── " } else { "├── " };
-                let branch_colored = branch.cyan().to_string();
-
-                let child_path = path.join(child_name);
-                let display_name = if let Some(child_entry) = self.entries.get(&child_path) {
-                    let name = if self.show_hidden && child_entry.is_hidden {
-                        format!("{} [H]", child_name)
-                    } else {
-                        child_name.to_string()
-                    };
-                    format!("{}{}", name, Self::metadata_suffix(child_entry, show_size, show_file_count))
-                        .bright_blue()
-                        .to_string()
-                } else {
-                    child_name.bright_blue().to_string()
-                };
-
-                output.push_str(&format!("{}{}{}\n", prefix, branch_colored, display_name));
-                self.print_colored_tree(
-                    output,
-                    &child_path,
-                    &format!("{}{}", prefix, child_prefix),
-                    is_last_child,
-                    current_depth + 1,
-                    max_depth,
-                    show_size,
-                    show_file_count,
-                )?;
-            }
+        for root in self.top_level_roots() {
+            writeln!(writer, "{}", paint(self.theme.root.as_deref(), &self.root_label(&root)))?;
+            self.write_colored_tree(writer, &root, "", 0, max_depth, show_size, show_file_count)?;
         }
-
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn write_colored_tree<W: Write>(
         &self,
         writer: &mut W,
         path: &Path,
         prefix: &str,
-        is_last: bool,
         current_depth: usize,
         max_depth: Option<usize>,
         show_size: bool,
@@ -1009,52 +3290,45 @@ impl DiskCache {
         }
 
         if let Some(entry) = self.entries.get(path) {
-            // Sort children only at output time (not during traversal)
-            // Use parallel sort for large directories (>500 children)
-            let mut children: Vec<_> = entry.children.iter().collect();
-            if children.len() > 500 {
-                children.par_sort();
-            } else {
-                children.sort();
-            }
+            let (children, more) = self.limited_children(path, entry);
 
             for (i, child_name) in children.iter().enumerate() {
-                let is_last_child = i == children.len() - 1;
-                let child_prefix = if is_last {
-                    "    ".to_string()
+                let is_last_child = more == 0 && i == children.len() - 1;
+                let child_prefix = if is_last_child {
+                    self.glyphs.space
                 } else {
-                    "│   ".to_string()
+                    self.glyphs.pipe
                 };
 
-                let branch = if is_last_child { "└── " } else { "├── " };
-                let branch_colored = branch.cyan().to_string();
-
-                let child_path = path.join(child_name);
-                let display_name = if let Some(child_entry) = self.entries.get(&child_path) {
-                    let name = if self.show_hidden && child_entry.is_hidden {
-                        format!("{} [H]", child_name)
-                    } else {
-                        child_name.to_string()
-                    };
-                    format!("{}{}", name, Self::metadata_suffix(child_entry, show_size, show_file_count))
-                        .bright_blue()
-                        .to_string()
+                let branch = if is_last_child {
+                    self.glyphs.last
                 } else {
-                    child_name.bright_blue().to_string()
+                    self.glyphs.branch
                 };
+                let branch_colored = paint(self.theme.branch.as_deref(), branch);
+
+                let (child_path, child_name) = self.output_child(child_name, path);
+                let display_name = paint(
+                    self.entry_style(&child_path),
+                    &self.child_label(&child_name, &child_path, show_size, show_file_count),
+                );
 
-                writeln!(writer, "{}{}{}", prefix, branch_colored, display_name)?;
+                let columns = self.long_columns(&child_path);
+                writeln!(writer, "{}{}{}{}", prefix, branch_colored, columns, display_name)?;
                 self.write_colored_tree(
                     writer,
                     &child_path,
                     &format!("{}{}", prefix, child_prefix),
-                    is_last_child,
                     current_depth + 1,
                     max_depth,
                     show_size,
                     show_file_count,
                 )?;
             }
+            if more > 0 {
+                let branch = paint(self.theme.branch.as_deref(), self.glyphs.last);
+                writeln!(writer, "{}{}{}", prefix, branch, self.more_label(more))?;
+            }
         }
 
         Ok(())
@@ -1074,126 +3348,154 @@ impl DiskCache {
         self.build_json_output_with_options(max_depth, false, false)
     }
 
+    /// The whole JSON document as one String (see `build_tree_output_with_options`);
+    /// `write_json_output` streams it.
     pub fn build_json_output_with_options(
         &self,
         max_depth: Option<usize>,
         show_size: bool,
         show_file_count: bool,
     ) -> Result<String> {
-        let mut root_json = json!({
-            "path": self.root.to_string_lossy().to_string(),
-            "children": []
-        });
-
-        if self.entries.is_empty() {
-            return Ok(root_json.to_string());
-        }
-
-        // No need for visited set - filesystem is acyclic and in_progress set prevents cycles during traversal
-        if let Some(root_entry) = self.get_entry(&self.root) {
-            if show_size {
-                root_json["size_bytes"] = json!(root_entry.total_size);
-            }
-            if show_file_count {
-                root_json["file_count"] = json!(root_entry.file_count);
-            }
-        }
-
-        self.populate_json(&mut root_json, &self.root, 0, max_depth, show_size, show_file_count)?;
+        let mut out = Vec::new();
+        self.write_json_output(&mut out, max_depth, show_size, show_file_count)?;
+        Ok(String::from_utf8(out)?)
+    }
 
-        Ok(serde_json::to_string_pretty(&root_json)?)
+    pub fn refresh_derived_metadata(&mut self) {
+        let paths: Vec<PathBuf> = self.entries.keys().cloned().collect();
+        self.derive_metadata(paths);
     }
 
-    fn populate_json(
-        &self,
-        node: &mut serde_json::Value,
-        path: &Path,
-        current_depth: usize,
-        max_depth: Option<usize>,
-        show_size: bool,
-        show_file_count: bool,
-    ) -> Result<()> {
-        // Check depth limit
-        if let Some(max) = max_depth {
-            if current_depth >= max {
-                return Ok(());
+    /// `refresh_derived_metadata` and `assign_entry_ids` after an incremental scan that read
+    /// the directories `read` (every one's ancestors included): only those and the new
+    /// directories found below them are gone over, the rest of the snapshot keeps its totals.
+    pub fn refresh_derived_metadata_of(&mut self, read: &HashSet<PathBuf>, previous: &EntryIds) {
+        let mut paths = Vec::new();
+        let mut stack: Vec<PathBuf> = read.iter().cloned().collect();
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        while let Some(path) = stack.pop() {
+            let Some(entry) = self.entries.get(&path) else {
+                continue;
+            };
+            if !seen.insert(path.clone()) {
+                continue;
             }
+            stack.extend(
+                entry
+                    .children
+                    .iter()
+                    .map(|name| path.join(name))
+                    .filter(|child| self.entries.get(child).is_some_and(|child| child.scan == self.scan)),
+            );
+            paths.push(path);
         }
 
-        if let Some(entry) = self.get_entry(path) {
-            let mut children_array = Vec::new();
-            let mut children_names: Vec<_> = entry.children.iter().collect();
-            // Sort children only at output time (not during traversal)
-            // Use parallel sort for large directories (>500 children)
-            if children_names.len() > 500 {
-                children_names.par_sort();
-            } else {
-                children_names.sort();
-            }
+        // Their IDs and those of the directories right below them are the ones in play
+        let mut used: HashSet<u64> = paths
+            .iter()
+            .filter_map(|path| self.entries.get(path))
+            .flat_map(|entry| {
+                std::iter::once(entry.id).chain(
+                    entry
+                        .children
+                        .iter()
+                        .filter_map(|name| self.entries.get(&entry.path.join(name)))
+                        .map(|child| child.id),
+                )
+            })
+            .filter(|id| *id != 0)
+            .collect();
+        self.derive_metadata(paths.clone());
+        self.assign_ids(paths, previous, &mut used);
+    }
 
-            for child_name in children_names {
-                let child_path = path.join(child_name);
-                let mut child_json = json!({
-                    "name": child_name,
-                    "path": child_path.to_string_lossy().to_string(),
-                    "children": []
-                });
+    /// Undo the totals of `refresh_derived_metadata` for the loaded entries: each directory
+    /// keeps only the files directly inside it, as a scan records them, so a refresh that
+    /// reuses some entries and rereads others can sum them up again.
+    pub fn restore_direct_totals(&mut self) {
+        let paths: Vec<PathBuf> = self.entries.keys().cloned().collect();
+        self.restore_direct_totals_of(&paths);
+    }
 
-                if let Some(child_entry) = self.get_entry(&child_path) {
-                    if show_size {
-                        child_json["size_bytes"] = json!(child_entry.total_size);
-                    }
-                    if show_file_count {
-                        child_json["file_count"] = json!(child_entry.file_count);
-                    }
+    /// `restore_direct_totals` for the directories at `paths` only.
+    pub fn restore_direct_totals_of<'a>(&mut self, paths: impl IntoIterator<Item = &'a PathBuf>) {
+        let direct: Vec<(PathBuf, (usize, u64, u64))> = paths
+            .into_iter()
+            .filter_map(|path| {
+                let entry = self.entries.get(path)?;
+                let mut direct = (entry.file_count, entry.total_size, entry.allocated_size);
+                for child in entry
+                    .children
+                    .iter()
+                    .filter_map(|name| self.entries.get(&path.join(name)))
+                {
+                    direct.0 = direct.0.saturating_sub(child.file_count);
+                    direct.1 = direct.1.saturating_sub(child.total_size);
+                    direct.2 = direct.2.saturating_sub(child.allocated_size);
                 }
+                Some((path.clone(), direct))
+            })
+            .collect();
 
-                self.populate_json(
-                    &mut child_json,
-                    &child_path,
-                    current_depth + 1,
-                    max_depth,
-                    show_size,
-                    show_file_count,
-                )?;
-                children_array.push(child_json);
+        for (path, (file_count, total_size, allocated_size)) in direct {
+            if let Some(entry) = self.entries.get_mut(&path) {
+                entry.file_count = file_count;
+                entry.total_size = total_size;
+                entry.allocated_size = allocated_size;
             }
-
-            node["children"] = serde_json::json!(children_array);
         }
-
-        Ok(())
     }
 
-    pub fn refresh_derived_metadata(&mut self) {
-        let mut paths: Vec<PathBuf> = self.entries.keys().cloned().collect();
-        paths.sort_by_key(|path| std::cmp::Reverse(path.components().count()));
+    /// `refresh_derived_metadata` for a whole subtree (or several) at `paths`: one bottom-up
+    /// pass that sums file counts and sizes and computes each directory's Merkle hash from
+    /// those of its subdirectories.
+    pub(crate) fn derive_metadata(&mut self, mut paths: Vec<PathBuf>) {
+        paths.sort_by_key(|path| Reverse(path.components().count()));
 
         let mut computed_hashes = HashMap::with_capacity(paths.len());
+        let mut child_hashes = HashMap::new();
 
         for path in paths {
             let Some(existing) = self.entries.get(&path) else {
                 continue;
             };
+            // A spilled subtree's root already holds the totals of everything below it
+            if self.spill.is_root(&path) {
+                computed_hashes.insert(path, existing.content_hash);
+                continue;
+            }
 
             let children = existing.children.clone();
             let modified = existing.modified;
             let mut file_count = existing.file_count;
             let mut total_size = existing.total_size;
+            let mut allocated_size = existing.allocated_size;
 
+            child_hashes.clear();
             for child_name in &children {
                 let child_path = path.join(child_name);
                 if let Some(child_entry) = self.entries.get(&child_path) {
                     file_count += child_entry.file_count;
                     total_size += child_entry.total_size;
+                    allocated_size += child_entry.allocated_size;
+                }
+                // Children are deeper, so theirs are done (or kept from before, or not in memory)
+                let hash = computed_hashes
+                    .get(&child_path)
+                    .copied()
+                    .or_else(|| self.entries.get(&child_path).map(|child| child.content_hash));
+                if let Some(hash) = hash {
+                    child_hashes.insert(child_path, hash);
                 }
             }
 
-            let content_hash = compute_content_hash(&path, modified, &children, &computed_hashes);
+            let content_hash =
+                compute_content_hash(&path, modified, &children, (file_count, total_size), &child_hashes);
 
             if let Some(entry) = self.entries.get_mut(&path) {
                 entry.file_count = file_count;
                 entry.total_size = total_size;
+                entry.allocated_size = allocated_size;
                 entry.content_hash = content_hash;
             }
 
@@ -1201,10 +3503,238 @@ impl DiskCache {
         }
     }
 
-    fn metadata_suffix(entry: &DirEntry, show_size: bool, show_file_count: bool) -> String {
+    /// Only show entries modified at or after `since` and before `before` (either may be left
+    /// open; both open shows everything again), with the directories leading to them. Files go
+    /// by the mtime `--long` recorded for them, or else by their directory's. Applies to the
+    /// entries loaded now.
+    pub fn set_modified_range(&mut self, since: Option<DateTime<Utc>>, before: Option<DateTime<Utc>>) {
+        if since.is_none() && before.is_none() {
+            self.modified_range = None;
+            return;
+        }
+        let range = since.unwrap_or(DateTime::<Utc>::MIN_UTC)..before.unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let dirs = self
+            .entries
+            .values()
+            .filter(|entry| range.contains(&entry.modified))
+            .map(|entry| entry.path.as_path());
+        let files = self
+            .entry_metadata
+            .iter()
+            .filter(|(path, metadata)| {
+                !self.entries.contains_key(*path) && metadata.modified.is_some_and(|modified| range.contains(&modified))
+            })
+            .map(|(path, _)| path.as_path());
+
+        let mut matching = HashSet::new();
+        for path in dirs.chain(files) {
+            for ancestor in path.ancestors() {
+                if !matching.insert(ancestor.to_path_buf()) {
+                    break;
+                }
+            }
+        }
+        self.modified_range = Some((range, matching));
+    }
+
+    /// Limit output to directories whose recursive size is at least `min` and at most `max`
+    /// (either bound may be left open; both open shows everything again). Files are cached by
+    /// name only, so they are left out while a range is set.
+    pub fn set_size_range(&mut self, min: Option<u64>, max: Option<u64>) {
+        self.size_range = (min.is_some() || max.is_some()).then(|| (min.unwrap_or(0), max.unwrap_or(u64::MAX)));
+    }
+
+    /// The children of `entry` (at `path`) that output shows, in `--sort` order.
+    pub(crate) fn output_children<'a>(&self, path: &Path, entry: &'a DirEntry) -> Vec<&'a Name> {
+        let mut children: Vec<&Name> = match &self.modified_range {
+            Some((range, matching)) => {
+                let files_match = range.contains(&entry.modified);
+                entry
+                    .children
+                    .iter()
+                    .filter(|name| {
+                        let child_path = path.join(name);
+                        let recorded = self.metadata_of(&child_path).and_then(|metadata| metadata.modified);
+                        if self.entries.contains_key(&child_path) || recorded.is_some() {
+                            matching.contains(&child_path)
+                        } else {
+                            files_match
+                        }
+                    })
+                    .collect()
+            }
+            None => entry.children.iter().collect(),
+        };
+        if let Some((min, max)) = self.size_range {
+            children.retain(|name| {
+                self.entries
+                    .get(&path.join(name))
+                    .is_some_and(|child| child.is_dir && (min..=max).contains(&self.shown_size(child)))
+            });
+        }
+
+        // Sort children only at output time (not during traversal)
+        // Use parallel sort for large directories (>500 children)
+        if children.len() > 500 {
+            children.par_sort();
+        } else {
+            children.sort();
+        }
+        // Stable, so files (no recorded mtime, size or children) and ties stay in name order
+        let child = |name: &Name| self.entries.get(&path.join(name));
+        match self.sort {
+            SortOrder::Name => {}
+            SortOrder::Mtime => children.sort_by_cached_key(|name| Reverse(child(name).map(|child| child.modified))),
+            SortOrder::Size => {
+                children.sort_by_cached_key(|name| Reverse(child(name).map(|child| self.shown_size(child))))
+            }
+            SortOrder::Count => {
+                children.sort_by_cached_key(|name| Reverse(child(name).map(|child| child.children.len())))
+            }
+        }
+        if self.reverse {
+            children.reverse();
+        }
+        children
+    }
+
+    /// `output_children` cut to the first `child_limit`, and how many of them were left out.
+    pub(crate) fn limited_children<'a>(&self, path: &Path, entry: &'a DirEntry) -> (Vec<&'a Name>, usize) {
+        let mut children = self.output_children(path, entry);
+        let more = match self.child_limit {
+            Some(limit) if children.len() > limit => children.len() - limit,
+            _ => 0,
+        };
+        children.truncate(children.len() - more);
+        (children, more)
+    }
+
+    /// The line standing for the `more` children `--limit` leaves out: `… (+12 more)`.
+    fn more_label(&self, more: usize) -> String {
+        format!("{} (+{} more)", self.glyphs.ellipsis, more)
+    }
+
+    /// The path whose children are rendered after the child `child_name` of `parent`, and the
+    /// child's line: its (`compact_dirs` joined) name, or its path with `path_display`.
+    fn output_child(&self, child_name: &str, parent: &Path) -> (PathBuf, String) {
+        let (path, name) = self.compact_chain(child_name, parent.join(child_name));
+        match self.path_display.shown(&path) {
+            Some(shown) => (path, shown.display().to_string()),
+            None => (path, name),
+        }
+    }
+
+    /// A root's line: its path, absolute or relative with `path_display`.
+    fn root_label(&self, root: &Path) -> String {
+        let shown = self.path_display.shown(root);
+        shown.as_deref().unwrap_or(root).display().to_string()
+    }
+
+    /// With `compact_dirs`, follow the directory at `child_path` down while it holds nothing but
+    /// one subdirectory and has no marker of its own. Returns the directory whose children are
+    /// rendered next and the joined name for its line (`src/main/java`).
+    fn compact_chain(&self, child_name: &str, child_path: PathBuf) -> (PathBuf, String) {
+        let mut name = child_name.to_string();
+        let mut path = child_path;
+        if !self.compact_dirs {
+            return (path, name);
+        }
+        while let Some(entry) = self.entries.get(&path) {
+            if self.is_marked(&path, entry) {
+                break;
+            }
+            let children = self.output_children(&path, entry);
+            let [only] = children[..] else {
+                break;
+            };
+            let next = path.join(only);
+            if !self.entries.contains_key(&next) {
+                break;
+            }
+            name.push(std::path::MAIN_SEPARATOR);
+            name.push_str(only.as_str());
+            path = next;
+        }
+        (path, name)
+    }
+
+    /// Whether the directory's line carries a hidden / unsettled / new / subvolume / slow marker
+    /// or a symlink target.
+    fn is_marked(&self, path: &Path, entry: &DirEntry) -> bool {
+        (self.show_hidden && entry.is_hidden)
+            || self.unsettled_dirs.contains(path)
+            || self.new_dirs.contains_key(path)
+            || self.subvolumes.contains_key(path)
+            || self.symlinks.contains_key(path)
+            || self.is_slow(path)
+    }
+
+    /// Tree label for a child: its name (and symlink target) plus hidden / unsettled / new /
+    /// subvolume / slow markers and the metadata suffix.
+    fn child_label(&self, child_name: &str, child_path: &Path, show_size: bool, show_file_count: bool) -> String {
+        let Some(child_entry) = self.entries.get(child_path) else {
+            if self.mount_points.contains(child_path) {
+                return format!("{child_name} [mount]");
+            }
+            let mut label = match self.symlinks.get(child_path) {
+                Some(symlink) => Self::symlink_label(child_name, symlink),
+                None => child_name.to_string(),
+            };
+            if let Some(error) = self.scan_errors.get(child_path) {
+                label.push(' ');
+                label.push_str(error.kind.marker());
+            }
+            return label;
+        };
+
+        let mut name = match self.symlinks.get(child_path) {
+            Some(symlink) => Self::symlink_label(child_name, symlink),
+            None => child_name.to_string(),
+        };
+        if self.show_hidden && child_entry.is_hidden {
+            name.push_str(" [H]");
+        }
+        if self.unsettled_dirs.contains(child_path) {
+            name.push_str(" [unsettled]");
+        }
+        if self.new_dirs.contains_key(child_path) {
+            name.push_str(" [new]");
+        }
+        if self.subvolumes.contains_key(child_path) {
+            name.push_str(" [subvol]");
+        }
+        if self.is_slow(child_path) {
+            name.push_str(" [slow]");
+        }
+        format!("{}{}", name, self.metadata_suffix(child_entry, show_size, show_file_count))
+    }
+
+    /// The size of `entry` that output shows: on disk, or logical with `apparent_size`.
+    pub fn shown_size(&self, entry: &DirEntry) -> u64 {
+        if self.apparent_size {
+            entry.total_size
+        } else {
+            entry.allocated_size
+        }
+    }
+
+    /// `entry`'s shown size, then the other one when they are far apart.
+    fn size_parts(&self, entry: &DirEntry) -> Vec<String> {
+        let mut parts = vec![Self::format_size(self.shown_size(entry))];
+        if entry.allocation_differs() {
+            parts.push(if self.apparent_size {
+                format!("{} on disk", Self::format_size(entry.allocated_size))
+            } else {
+                format!("{} apparent", Self::format_size(entry.total_size))
+            });
+        }
+        parts
+    }
+
+    fn metadata_suffix(&self, entry: &DirEntry, show_size: bool, show_file_count: bool) -> String {
         let mut parts = Vec::new();
         if show_size {
-            parts.push(Self::format_size(entry.total_size));
+            parts.extend(self.size_parts(entry));
         }
         if show_file_count {
             parts.push(format!("{} files", entry.file_count));
@@ -1217,7 +3747,8 @@ impl DiskCache {
         }
     }
 
-    fn format_size(size: u64) -> String {
+    /// Human-readable size (`1.5 GB`), as used in tree output and reports.
+    pub fn format_size(size: u64) -> String {
         const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
 
         let mut value = size as f64;
@@ -1246,7 +3777,7 @@ pub fn get_cache_path() -> Result<PathBuf> {
     #[cfg(not(windows))]
     {
         if let Some(cache_home) = xdg_absolute_dir("XDG_CACHE_HOME") {
-            return Ok(PathBuf::from(cache_home).join("ptree").join("ptree.dat"));
+            return Ok(cache_home.join("ptree").join("ptree.dat"));
         }
 
         if let Ok(home) = std::env::var("HOME") {
@@ -1273,22 +3804,188 @@ fn parse_absolute_dir(raw: &str) -> Option<PathBuf> {
         return None;
     }
 
-    let path = PathBuf::from(trimmed);
-    path.is_absolute().then_some(path)
+    let path = PathBuf::from(trimmed);
+    path.is_absolute().then_some(path)
+}
+
+/// Get cache directory path with custom directory
+pub fn get_cache_path_custom(custom_dir: Option<&str>) -> Result<PathBuf> {
+    if let Some(dir) = custom_dir {
+        Ok(PathBuf::from(dir).join("ptree.dat"))
+    } else {
+        get_cache_path()
+    }
+}
+
+/// A depth shard or name table of a snapshot, as told by its file name.
+struct SnapshotFile {
+    /// `None` for files of versions that replaced shards in place instead of numbering them
+    generation: Option<u64>,
+}
+
+/// Parse `<stem>-g<G>-d<N>.dat` (a depth shard) and `<stem>-g<G>-names.dat` (the name table),
+/// or the unnumbered `<stem>-d<N>.dat` and `<stem>-names.dat` of older versions; their `.tmp`
+/// files too when `include_temp`.
+///
+/// Matching the digits exactly keeps `ptree-d1.dat` from claiming shards of a per-root
+/// cache whose hash happens to start with `d` (`ptree-d3f0...-d1.dat`).
+fn parse_snapshot_file(name: &str, stem: &str, include_temp: bool) -> Option<SnapshotFile> {
+    let rest = name.strip_prefix(stem)?;
+    let rest = match rest.strip_suffix(".dat") {
+        Some(rest) => rest,
+        None if include_temp => rest.strip_suffix(".dat.tmp")?,
+        None => return None,
+    };
+    let digits = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+
+    let (generation, kind) = match rest.strip_prefix("-g") {
+        Some(numbered) => {
+            let (generation, kind) = numbered.split_once('-')?;
+            if !digits(generation) {
+                return None;
+            }
+            (Some(generation.parse().ok()?), kind)
+        }
+        None => (None, rest.strip_prefix('-')?),
+    };
+    if kind != "names" && !digits(kind.strip_prefix('d')?) {
+        return None;
+    }
+    Some(SnapshotFile { generation })
+}
+
+/// True if `name` is a depth shard of the snapshot `stem` (of any generation).
+#[cfg(test)]
+fn is_depth_file(name: &str, stem: &str, include_temp: bool) -> bool {
+    is_snapshot_file(name, stem, include_temp) && !name.contains("-names.dat")
+}
+
+/// A depth shard or the name table of the snapshot `stem`, of any generation.
+pub(crate) fn is_snapshot_file(name: &str, stem: &str, include_temp: bool) -> bool {
+    parse_snapshot_file(name, stem, include_temp).is_some()
+}
+
+/// Cache file for one set of scan roots: `<cache dir>/ptree-<hash>.dat`.
+///
+/// `base` is the default cache path (`get_cache_path_custom`); only its directory is used.
+/// Roots are canonicalized before hashing so `ptree .` and `ptree /abs/path` share a cache.
+pub fn cache_path_for_roots(base: &Path, roots: &[PathBuf]) -> PathBuf {
+    let parent = base.parent().unwrap_or_else(|| Path::new("."));
+    parent.join(format!("ptree-{}.dat", root_cache_key(roots)))
+}
+
+/// Stable 64-bit FNV-1a hash of the scan roots, as 16 hex digits.
+pub fn root_cache_key(roots: &[PathBuf]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for root in roots {
+        let canonical = fs::canonicalize(root).unwrap_or_else(|_| root.clone());
+        let key = canonical.to_string_lossy();
+        // NTFS paths are case-insensitive
+        #[cfg(windows)]
+        let key = key.to_lowercase();
+
+        for byte in key.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// One cache snapshot found in the cache directory (`ptree cache list`).
+#[derive(Debug, Clone)]
+pub struct RootCacheInfo {
+    pub cache_path:  PathBuf,
+    pub roots:       Vec<PathBuf>,
+    pub last_scan:   DateTime<Utc>,
+    pub directories: usize,
+    pub files:       usize,
+    pub disk_size:   u64,
+    /// `ptree.dat` written before caches were keyed by scan root
+    pub legacy:      bool,
+}
+
+/// Enumerate every root cache (`ptree-<hash>.idx`, plus a legacy `ptree.idx`) in `cache_dir`.
+pub fn list_root_caches(cache_dir: &Path) -> Result<Vec<RootCacheInfo>> {
+    let mut caches = Vec::new();
+
+    for cache_path in cache_snapshots(cache_dir)? {
+        // Unreadable snapshots are left for the next scan of that root to recover
+        let Ok(cache) = DiskCache::load_from_lazy_cache(&cache_path.with_extension("idx"), &cache_path) else {
+            continue;
+        };
+
+        caches.push(RootCacheInfo {
+            roots: cache.top_level_roots(),
+            last_scan: cache.last_scan,
+            directories: cache.entry_count_hint(),
+            files: cache.file_count_hint(),
+            disk_size: snapshot_disk_size(&cache_path),
+            legacy: cache_path.file_stem().is_some_and(|stem| stem == "ptree"),
+            cache_path,
+        });
+    }
+
+    caches.sort_by(|a, b| a.roots.cmp(&b.roots));
+    Ok(caches)
+}
+
+/// Cache path of every snapshot in `cache_dir` (per-root `ptree-<hash>` and legacy `ptree`),
+/// whether or not it is readable.
+pub fn cache_snapshots(cache_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut snapshots = Vec::new();
+    if !cache_dir.is_dir() {
+        return Ok(snapshots);
+    }
+
+    for entry in fs::read_dir(cache_dir)? {
+        let index_path = entry?.path();
+        let Some(stem) = index_path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if index_path.extension().and_then(|ext| ext.to_str()) != Some("idx") {
+            continue;
+        }
+
+        let legacy = stem == "ptree";
+        let keyed = stem
+            .strip_prefix("ptree-")
+            .is_some_and(|key| key.len() == 16 && key.bytes().all(|b| b.is_ascii_hexdigit()));
+        if legacy || keyed {
+            snapshots.push(index_path.with_extension("dat"));
+        }
+    }
+
+    snapshots.sort();
+    Ok(snapshots)
 }
 
-/// Get cache directory path with custom directory
-pub fn get_cache_path_custom(custom_dir: Option<&str>) -> Result<PathBuf> {
-    if let Some(dir) = custom_dir {
-        Ok(PathBuf::from(dir).join("ptree.dat"))
-    } else {
-        get_cache_path()
+/// Bytes the snapshot for `cache_path` occupies on disk (index plus every depth shard).
+pub fn snapshot_disk_size(cache_path: &Path) -> u64 {
+    let index_path = cache_path.with_extension("idx");
+    let stem = cache_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+    let parent = cache_path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut total = fs::metadata(&index_path).map(|meta| meta.len()).unwrap_or(0);
+    if let Ok(entries) = fs::read_dir(parent) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if is_snapshot_file(&name, stem, false) {
+                total += entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+            }
+        }
     }
+
+    total
 }
 
 #[cfg(test)]
 mod tests {
+    use ptree_core::{BranchStyle, Charset};
+
     use super::*;
+    use crate::testing;
 
     #[test]
     fn test_cache_creation() -> Result<()> {
@@ -1313,36 +4010,20 @@ mod tests {
         let root = temp_dir.join("root");
         let child = root.join("child");
 
-        let mut cache = DiskCache::default();
-        cache.root = root.clone();
+        let mut cache = DiskCache {
+            root: root.clone(),
+            ..Default::default()
+        };
         cache.entries.insert(
             root.clone(),
             DirEntry {
-                path:         root.clone(),
-                name:         "root".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   0,
-                total_size:   0,
-                children:     vec!["child".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
-            },
-        );
-        cache.entries.insert(
-            child.clone(),
-            DirEntry {
-                path:         child.clone(),
-                name:         "child".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   1,
-                total_size:   128,
-                children:     vec!["leaf.txt".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
+                file_count: 0,
+                ..testing::dir(&root, 0, &["child"])
             },
         );
+        cache
+            .entries
+            .insert(child.clone(), testing::dir(&child, 128, &["leaf.txt"]));
 
         cache.save(&cache_path)?;
 
@@ -1370,51 +4051,28 @@ mod tests {
         let child = root.join("alpha");
         let grandchild = child.join("beta");
 
-        let mut cache = DiskCache::default();
-        cache.root = root.clone();
-        cache.last_scanned_root = root.clone();
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
         cache.entries.insert(
             root.clone(),
             DirEntry {
-                path:         root.clone(),
-                name:         "root".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   1,
-                total_size:   64,
-                children:     vec!["alpha".to_string(), "note.txt".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
+                file_count: 1,
+                ..testing::dir(&root, 64, &["alpha", "note.txt"])
             },
         );
         cache.entries.insert(
             child.clone(),
             DirEntry {
-                path:         child.clone(),
-                name:         "alpha".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   1,
-                total_size:   32,
-                children:     vec!["beta".to_string(), "child.txt".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
-            },
-        );
-        cache.entries.insert(
-            grandchild.clone(),
-            DirEntry {
-                path:         grandchild.clone(),
-                name:         "beta".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   1,
-                total_size:   16,
-                children:     vec!["leaf.txt".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
+                file_count: 1,
+                ..testing::dir(&child, 32, &["beta", "child.txt"])
             },
         );
+        cache
+            .entries
+            .insert(grandchild.clone(), testing::dir(&grandchild, 16, &["leaf.txt"]));
 
         cache.save(&cache_path)?;
 
@@ -1429,117 +4087,838 @@ mod tests {
         assert!(depth_one_tree.contains("note.txt"));
         assert!(!depth_one_tree.contains("beta"));
 
-        let mut depth_two = DiskCache::open(&cache_path)?;
-        depth_two.load_visible_entries_lazy(&cache_path, Some(2))?;
-        assert!(depth_two.entries.contains_key(&root));
-        assert!(depth_two.entries.contains_key(&child));
-        assert!(!depth_two.entries.contains_key(&grandchild));
-        let depth_two_tree = depth_two.build_tree_output_with_depth(Some(2))?;
-        assert!(depth_two_tree.contains("beta"));
-        assert!(!depth_two_tree.contains("leaf.txt"));
+        let mut depth_two = DiskCache::open(&cache_path)?;
+        depth_two.load_visible_entries_lazy(&cache_path, Some(2))?;
+        assert!(depth_two.entries.contains_key(&root));
+        assert!(depth_two.entries.contains_key(&child));
+        assert!(!depth_two.entries.contains_key(&grandchild));
+        let depth_two_tree = depth_two.build_tree_output_with_depth(Some(2))?;
+        assert!(depth_two_tree.contains("beta"));
+        assert!(!depth_two_tree.contains("leaf.txt"));
+
+        let mut full = DiskCache::open(&cache_path)?;
+        full.load_visible_entries_lazy(&cache_path, None)?;
+        assert!(full.entries.contains_key(&root));
+        assert!(full.entries.contains_key(&child));
+        assert!(full.entries.contains_key(&grandchild));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_options_and_persisted_file_counts() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_render_options");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = temp_dir.join("root");
+        let child = root.join("alpha");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.clone(),
+            DirEntry {
+                file_count: 1,
+                ..testing::dir(&root, 64, &["alpha", "note.txt"])
+            },
+        );
+        cache
+            .entries
+            .insert(child.clone(), testing::dir(&child, 256, &["leaf-a.txt", "leaf-b.txt"]));
+
+        cache.refresh_derived_metadata();
+        cache.save(&cache_path)?;
+
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.file_count_hint(), 3);
+
+        let mut hydrated = DiskCache::open(&cache_path)?;
+        hydrated.load_visible_entries_lazy(&cache_path, None)?;
+
+        let tree = hydrated.build_tree_output_with_options(None, true, true)?;
+        assert!(tree.contains("alpha (256 B, 2 files)"));
+
+        let json = hydrated.build_json_output_with_options(None, true, true)?;
+        assert!(json.contains("\"file_count\": 3"));
+        assert!(json.contains("\"size_bytes\": 320"));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_on_disk_is_rolled_up_and_shown_when_notable() -> Result<()> {
+        let root = PathBuf::from("/data");
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
+        for (path, total_size, allocated_size, children) in [
+            ("/data", 4096, 4096, vec!["disk.img", "src"]),
+            ("/data/disk.img", 64 << 20, 4096, vec![]),
+            ("/data/src", 2 << 20, 2 << 20, vec!["main.rs"]),
+        ] {
+            let path = PathBuf::from(path);
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    allocated_size,
+                    ..testing::dir(&path, total_size, &children)
+                },
+            );
+        }
+
+        cache.refresh_derived_metadata();
+
+        let sparse = &cache.entries[Path::new("/data/disk.img")];
+        assert!(sparse.allocation_differs());
+        assert!(!cache.entries[Path::new("/data/src")].allocation_differs());
+        // A notable share of a tiny size is not worth a mention
+        let mut small = sparse.clone();
+        small.total_size = 64 << 10;
+        assert!(!small.allocation_differs());
+
+        let totals = cache.totals();
+        assert_eq!(totals.bytes, 4096 + (64 << 20) + (2 << 20));
+        assert_eq!(totals.allocated_bytes, 4096 + 4096 + (2 << 20));
+        // Sizes on disk by default, as du shows them; logical sizes with --apparent-size
+        assert_eq!(cache.report_line(), "2 directories, 3 files, 2.0 MB total");
+        cache.apparent_size = true;
+        assert_eq!(cache.report_line(), "2 directories, 3 files, 66.0 MB total");
+        // A cache hit has only the visible part loaded; the snapshot's count still holds
+        cache.has_persisted_snapshot = true;
+        cache.persisted_entry_count = 42;
+        assert!(cache.report_line().starts_with("41 directories, "));
+
+        let tree = cache.build_tree_output_with_options(None, true, false)?;
+        let sparse_line = tree.lines().find(|line| line.contains("disk.img")).unwrap();
+        assert!(sparse_line.contains("(64.0 MB, 4.0 KB on disk)"), "{sparse_line}");
+        let src_line = tree.lines().find(|line| line.contains("src")).unwrap();
+        assert!(!src_line.contains("on disk"), "{src_line}");
+        cache.apparent_size = false;
+        let tree = cache.build_tree_output_with_options(None, true, false)?;
+        let sparse_line = tree.lines().find(|line| line.contains("disk.img")).unwrap();
+        assert!(sparse_line.contains("(4.0 KB, 64.0 MB apparent)"), "{sparse_line}");
+
+        let json = cache.build_json_output_with_options(None, true, false)?;
+        assert!(json.contains(&format!("\"size_on_disk_bytes\": {}", 4096 + 4096 + (2 << 20))));
+
+        cache.restore_direct_totals();
+        assert_eq!(cache.entries[&root].allocated_size, 4096);
+        Ok(())
+    }
+
+    #[test]
+    fn test_accessible_output_spells_out_structure_and_state() -> Result<()> {
+        let root = PathBuf::from("/data");
+        let child = root.join(".cache");
+        let dir = |path: &PathBuf, name: &str, children: Vec<Name>, is_hidden: bool| {
+            DirEntry {
+                name: Name::new(name),
+                children,
+                file_count: 1,
+                is_hidden,
+                ..testing::dir(path, 2048, &[])
+            }
+        };
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            show_hidden: true,
+            ..Default::default()
+        };
+        cache
+            .entries
+            .insert(root.clone(), dir(&root, "data", vec![Name::new(".cache")], false));
+        cache
+            .entries
+            .insert(child.clone(), dir(&child, ".cache", vec![Name::new("blob")], true));
+        cache.unsettled_dirs.insert(child.clone());
+
+        let mut output = Vec::new();
+        cache.write_accessible_output(&mut output, None, true, false)?;
+        let output = String::from_utf8(output)?;
+
+        assert_eq!(
+            output,
+            "level 0: /data, directory, 1 item, 2.0 KB\n  level 1: .cache, directory, 1 item, 2.0 KB, hidden, \
+             unsettled, changed during the scan\n    level 2: blob, file\n"
+        );
+        assert!(output.is_ascii());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_recovers_from_corrupt_snapshot() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_corrupt_snapshot");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let index_path = cache_path.with_extension("idx");
+        let depth_path = temp_dir.join("ptree-g3-d1.dat");
+
+        fs::write(&index_path, b"not a valid index")?;
+        fs::write(&depth_path, b"stale depth data")?;
+
+        let cache = DiskCache::open(&cache_path)?;
+        assert!(cache.entries.is_empty());
+        assert!(!index_path.exists());
+        assert!(!depth_path.exists());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_record_is_reported_and_forces_rescan() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_record_checksum");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = temp_dir.join("root");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.clone(),
+            DirEntry {
+                name: Name::new("root"),
+                ..testing::dir(&root, 64, &["note.txt"])
+            },
+        );
+        cache.save(&cache_path)?;
+        assert!(DiskCache::verify(&cache_path)?.is_ok());
+
+        // Flip one payload byte; the length still fits, only the checksum can catch it
+        let shard = fs::read_dir(&temp_dir)?
+            .map(|entry| entry.unwrap().path())
+            .find(|path| is_depth_file(&path.file_name().unwrap().to_string_lossy(), "ptree", false))
+            .unwrap();
+        let mut bytes = fs::read(&shard)?;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&shard, bytes)?;
+
+        let report = DiskCache::verify(&cache_path)?;
+        assert_eq!(report.records, 1);
+        assert_eq!(report.corrupt.len(), 1);
+        assert!(report.corrupt[0].1.contains("checksum mismatch"));
+
+        // Opening does not read the records; reading the corrupt one is a typed error
+        let mut reopened = DiskCache::open(&cache_path)?;
+        assert!(reopened.has_persisted_snapshot);
+        let err = reopened.load_visible_entries_lazy(&cache_path, None).unwrap_err();
+        assert!(crate::is_corrupt_cache(&err), "{err:#}");
+
+        DiskCache::discard_snapshot(&cache_path)?;
+        assert!(!cache_path.with_extension("idx").exists());
+        assert!(!DiskCache::open(&cache_path)?.has_persisted_snapshot);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_of_another_format_version_is_rejected() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_index_version_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let index_path = cache_path.with_extension("idx");
+
+        let mut cache = DiskCache {
+            root: temp_dir.clone(),
+            last_scanned_root: temp_dir.clone(),
+            ..Default::default()
+        };
+        cache.entries.insert(
+            temp_dir.clone(),
+            DirEntry {
+                name: Name::new("root"),
+                ..testing::dir(&temp_dir, 0, &[])
+            },
+        );
+        cache.save(&cache_path)?;
+        assert!(DiskCache::open(&cache_path)?.has_persisted_snapshot);
+
+        // An index without the header (format version 1), of version 2 (file metadata without the
+        // allocated size) or of a later version is not read
+        let data = fs::read(&index_path)?;
+        for index in [
+            data[4..].to_vec(),
+            [b"PTI\x02".as_slice(), &data[4..]].concat(),
+            [b"PTI\x09".as_slice(), &data[4..]].concat(),
+        ] {
+            fs::write(&index_path, index)?;
+            assert!(crate::cache_rkyv::RkyvMmapCache::open(&index_path, &cache_path).is_err());
+        }
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_automatic_compaction_waits_for_the_cache_lock() -> Result<()> {
+        use crate::cache_rkyv::{RkyvDirEntry, RkyvMmapCache};
+
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_auto_compact_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let index_path = cache_path.with_extension("idx");
+
+        // Rewrite one large directory in place until over a megabyte of its records is stale
+        let mut rkyv_cache = RkyvMmapCache::open(&index_path, &cache_path)?;
+        let mut entry = RkyvDirEntry {
+            path:           temp_dir.join("big"),
+            name:           Name::new("big"),
+            modified:       Utc::now(),
+            content_hash:   0,
+            file_count:     0,
+            total_size:     0,
+            allocated_size: 0,
+            children:       (0..50_000).map(|i| Name::new(&format!("file{i}"))).collect(),
+            is_hidden:      false,
+            is_dir:         true,
+            id:             1,
+            file_id:        0,
+            scan:           0,
+        };
+        for file_count in 0..8 {
+            entry.file_count = file_count;
+            rkyv_cache.append_entry(&entry)?;
+        }
+        rkyv_cache.save_index(&index_path)?;
+        assert!(rkyv_cache.needs_compaction());
+
+        // Another process holds the lock: opening leaves the snapshot alone
+        let held = CacheLock::acquire(&cache_path, DEFAULT_LOCK_TIMEOUT)?;
+        assert_eq!(DiskCache::load_from_lazy_cache(&index_path, &cache_path)?.generation, 0);
+        assert!(RkyvMmapCache::open(&index_path, &cache_path)?.needs_compaction());
+
+        drop(held);
+        assert_eq!(DiskCache::load_from_lazy_cache(&index_path, &cache_path)?.generation, 1);
+        let compacted = RkyvMmapCache::open(&index_path, &cache_path)?;
+        assert_eq!(compacted.index.stale_bytes, 0);
+        assert_eq!(compacted.get_entry(&entry.path)?.map(|entry| entry.file_count), Some(7));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_switches_generations_only_by_renaming_the_index() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_generation_switch_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let index_path = cache_path.with_extension("idx");
+        let root = temp_dir.join("root");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.clone(),
+            DirEntry {
+                name: Name::new("root"),
+                ..testing::dir(&root, 0, &[])
+            },
+        );
+        cache.save(&cache_path)?;
+        let first_index = fs::read(&index_path)?;
+        let snapshot_files = || -> Result<Vec<String>> {
+            let mut names: Vec<String> = fs::read_dir(&temp_dir)?
+                .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+                .filter(|name| name.as_ref().map_or(true, |name| is_snapshot_file(name, "ptree", true)))
+                .collect::<std::io::Result<_>>()?;
+            names.sort();
+            Ok(names)
+        };
+        let depth = root.components().count();
+        assert_eq!(snapshot_files()?, [format!("ptree-g1-d{depth}.dat"), "ptree-g1-names.dat".to_string()]);
+
+        // A save that died before its index was renamed into place leaves files of the next
+        // generation behind; the snapshot is still the previous one
+        fs::write(temp_dir.join(format!("ptree-g2-d{depth}.dat")), b"half written")?;
+        let reopened = DiskCache::open(&cache_path)?;
+        assert!(reopened.has_persisted_snapshot);
+        assert_eq!(reopened.generation, 1);
+
+        // The next save writes past the leftovers and removes the superseded generation
+        cache.save(&cache_path)?;
+        assert_eq!(DiskCache::open(&cache_path)?.generation, 2);
+        assert_eq!(snapshot_files()?, [format!("ptree-g2-d{depth}.dat"), "ptree-g2-names.dat".to_string()]);
+
+        // An index whose generation is gone is rejected, not paired with the newer shards
+        fs::write(&index_path, first_index)?;
+        assert!(!DiskCache::verify(&cache_path)?.is_ok());
+
+        let reopened = DiskCache::open(&cache_path)?;
+        assert!(!reopened.has_persisted_snapshot);
+        assert!(!index_path.exists());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_largest_dirs_and_files() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_largest_dirs_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let data = temp_dir.join("data");
+        let mut cache = DiskCache {
+            root: data.clone(),
+            scan: 2,
+            ..Default::default()
+        };
+        for (path, total_size, scan, children) in [
+            ("", 700, 2, &["a", "c"][..]),
+            ("a", 600, 1, &["b"][..]),
+            ("a/b", 500, 2, &[][..]),
+            ("c", 100, 2, &[][..]),
+        ] {
+            let path = data.join(path);
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    scan,
+                    ..testing::dir(&path, total_size, children)
+                },
+            );
+        }
+        cache.save(&cache_path)?;
+
+        // `ptree du` reads the list the save kept, not the records
+        let saved = DiskCache::open(&cache_path)?;
+        assert!(saved.entries.is_empty());
+        let largest: Vec<(&Path, u64)> = saved
+            .top_dirs(2)
+            .iter()
+            .map(|dir| (dir.path.as_path(), dir.allocated_size))
+            .collect();
+        assert_eq!(largest, [(data.join("a").as_path(), 600), (data.join("a/b").as_path(), 500)]);
+
+        // Forgetting a subtree drops it from the list and shrinks the directories above
+        DiskCache::invalidate(&cache_path, &data.join("a/b"))?;
+        let forgotten = DiskCache::open(&cache_path)?;
+        let largest: Vec<(&Path, u64)> = forgotten
+            .top_dirs(3)
+            .iter()
+            .map(|dir| (dir.path.as_path(), dir.total_size))
+            .collect();
+        assert_eq!(largest, [(data.join("a").as_path(), 100), (data.join("c").as_path(), 100)]);
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        // The largest by either size are kept, for `--apparent-size`
+        let mut dirs: Vec<LargeDir> = (0..LARGEST_DIRS_KEPT as u64 + 2)
+            .map(|n| {
+                LargeDir {
+                    path:           PathBuf::from(format!("/d/{n}")),
+                    total_size:     n,
+                    allocated_size: 10_000 - n,
+                }
+            })
+            .collect();
+        keep_largest_dirs(&mut dirs);
+        assert_eq!(dirs.len(), LARGEST_DIRS_KEPT + 2);
+        dirs.push(LargeDir {
+            path:           PathBuf::from("/d/small"),
+            total_size:     0,
+            allocated_size: 0,
+        });
+        keep_largest_dirs(&mut dirs);
+        assert!(dirs.iter().all(|dir| dir.path != Path::new("/d/small")));
+
+        let file = |path: &str, size| {
+            LargeFile {
+                path: data.join(path),
+                size,
+            }
+        };
+        cache.largest_files = vec![file("a/old.iso", 90), file("c/gone.bin", 80)];
+        // An incremental scan reread data/c (and data/a/b), but not data/a
+        cache.record_largest_files(vec![file("a/b/x.bin", 50), file("c/new.bin", 95)], false);
+        let sizes: Vec<u64> = cache.largest_files.iter().map(|file| file.size).collect();
+        assert_eq!(sizes, [95, 90, 50]);
+
+        cache.record_largest_files(vec![file("c/new.bin", 95)], true);
+        assert_eq!(cache.largest_files.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_and_usage_by_extension() {
+        let mut cache = DiskCache {
+            root: PathBuf::from("/src"),
+            ..Default::default()
+        };
+        for (path, children) in [
+            ("/src", vec!["lib", "main.rs", "README"]),
+            ("/src/lib", vec!["a.RS", ".gitignore"]),
+        ] {
+            cache
+                .entries
+                .insert(PathBuf::from(path), testing::dir(path, 0, &children));
+        }
+        // `lib` is a directory; `.gitignore` and `README` have no extension
+        let counted = |cache: &DiskCache| -> Vec<(String, u64)> {
+            cache
+                .files_by_extension()
+                .into_iter()
+                .map(|row| (row.extension, row.files))
+                .collect()
+        };
+        assert_eq!(counted(&cache), [(String::new(), 2), ("rs".to_string(), 2)]);
+
+        // Directories that have no entry are not files either
+        cache
+            .entries
+            .get_mut(Path::new("/src"))
+            .unwrap()
+            .children
+            .extend(["mnt.d", "locked.d", "later.d"].map(Name::new));
+        cache.mount_points.insert(PathBuf::from("/src/mnt.d"));
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        cache
+            .scan_errors
+            .insert(PathBuf::from("/src/locked.d"), ScanError::new(&denied));
+        cache.pending_dirs.push(PathBuf::from("/src/later.d"));
+        assert_eq!(counted(&cache), [(String::new(), 2), ("rs".to_string(), 2)]);
+
+        let usage = |extension: &str, files, bytes| {
+            ExtensionUsage {
+                extension: extension.to_string(),
+                files,
+                bytes,
+            }
+        };
+        cache
+            .extension_usage
+            .insert(PathBuf::from("/src"), vec![usage("rs", 1, 10), usage("", 1, 500)]);
+        cache
+            .extension_usage
+            .insert(PathBuf::from("/src/lib"), vec![usage("rs", 1, 30), usage("", 1, 5)]);
+        assert_eq!(cache.usage_by_extension(), [usage("", 2, 505), usage("rs", 2, 40)]);
+    }
+
+    #[test]
+    fn test_usage_per_owner_sums_every_directory() {
+        let usage = |uid, directories, files, bytes| {
+            OwnerUsage {
+                uid,
+                directories,
+                files,
+                bytes,
+            }
+        };
+        let mut cache = DiskCache::default();
+        cache
+            .owner_usage
+            .insert(PathBuf::from("/srv"), vec![usage(0, 1, 2, 100), usage(1001, 0, 1, 50)]);
+        cache
+            .owner_usage
+            .insert(PathBuf::from("/srv/alice"), vec![usage(1001, 1, 3, 900)]);
+        cache
+            .owner_usage
+            .insert(PathBuf::from("/srv/empty"), vec![usage(1002, 1, 0, 0)]);
+
+        // Most bytes first; an owner of only directories still counts them
+        assert_eq!(cache.usage_per_owner(), [usage(1001, 1, 4, 950), usage(0, 1, 2, 100), usage(1002, 1, 0, 0)]);
+    }
+
+    #[test]
+    fn test_largest_growth_compares_top_level_directories() {
+        let totals = |sizes: &[(&str, u64)]| {
+            SnapshotTotals {
+                top_level: sizes
+                    .iter()
+                    .map(|(path, bytes)| (PathBuf::from(path), *bytes))
+                    .collect(),
+                ..Default::default()
+            }
+        };
+        let previous = totals(&[("/home", 100), ("/var", 500), ("/tmp", 50)]);
+        let current = totals(&[("/home", 150), ("/var", 400), ("/opt", 80)]);
+
+        // New directories count from zero; shrinking ones never win
+        assert_eq!(current.largest_growth(&previous), Some((PathBuf::from("/opt"), 80)));
+        assert_eq!(previous.largest_growth(&previous), None);
+    }
 
-        let mut full = DiskCache::open(&cache_path)?;
-        full.load_visible_entries_lazy(&cache_path, None)?;
-        assert!(full.entries.contains_key(&root));
-        assert!(full.entries.contains_key(&child));
-        assert!(full.entries.contains_key(&grandchild));
+    #[test]
+    fn test_compressed_snapshot_round_trips_and_converts() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_compressed_snapshot");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = temp_dir.join("root");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            compress: true,
+            ..Default::default()
+        };
+        let names: Vec<String> = (0..500).map(|i| format!("directory-{i:04}")).collect();
+        for name in names.iter().map(|name| Some(name.as_str())).chain([None]) {
+            let path = name.map_or_else(|| root.clone(), |name| root.join(name));
+            let children = if name.is_none() {
+                names.iter().map(|name| Name::new(name)).collect()
+            } else {
+                vec![Name::new("file.txt")]
+            };
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    name: Name::new(name.unwrap_or("root")),
+                    children,
+                    file_count: 1,
+                    ..testing::dir(&path, 10, &[])
+                },
+            );
+        }
+        cache.refresh_derived_metadata();
+        cache.save(&cache_path)?;
+        // The index (with its list of the largest directories) is not compressed
+        let data_size =
+            || snapshot_disk_size(&cache_path) - fs::metadata(cache_path.with_extension("idx")).unwrap().len();
+        let compressed_size = data_size();
+
+        let mut reopened = DiskCache::open(&cache_path)?;
+        assert!(reopened.compress);
+        reopened.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(reopened.entries.len(), 501);
+        assert_eq!(reopened.entries[&root.join("directory-0042")].children, vec![Name::new("file.txt")]);
+        assert!(DiskCache::verify(&cache_path)?.is_ok());
+
+        DiskCache::set_compression(&cache_path, false)?;
+        // The name table is the same either way; only the records shrink
+        assert!(data_size() > compressed_size * 3 / 2);
+        let mut plain = DiskCache::open(&cache_path)?;
+        assert!(!plain.compress);
+        plain.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(plain.entries.len(), 501);
 
         let _ = fs::remove_dir_all(&temp_dir);
         Ok(())
     }
 
     #[test]
-    fn test_render_options_and_persisted_file_counts() -> Result<()> {
-        let temp_dir = std::env::temp_dir().join("ptree_test_render_options");
+    fn test_reduced_snapshot_keeps_directory_structure_only() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_reduced_snapshot");
         let _ = fs::remove_dir_all(&temp_dir);
         fs::create_dir_all(&temp_dir)?;
         let cache_path = temp_dir.join("ptree.dat");
         let root = temp_dir.join("root");
         let child = root.join("alpha");
 
-        let mut cache = DiskCache::default();
-        cache.root = root.clone();
-        cache.last_scanned_root = root.clone();
-        cache.entries.insert(
-            root.clone(),
-            DirEntry {
-                path:         root.clone(),
-                name:         "root".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   1,
-                total_size:   64,
-                children:     vec!["alpha".to_string(), "note.txt".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
-            },
-        );
-        cache.entries.insert(
-            child.clone(),
-            DirEntry {
-                path:         child.clone(),
-                name:         "alpha".to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   2,
-                total_size:   256,
-                children:     vec!["leaf-a.txt".to_string(), "leaf-b.txt".to_string()],
-                is_hidden:    false,
-                is_dir:       true,
-            },
-        );
+        let mut cache = DiskCache {
+            root: root.clone(),
+            ..Default::default()
+        };
+        for (path, children) in [(&root, vec!["alpha", "note.txt"]), (&child, vec!["leaf.txt"])] {
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    file_count: 1,
+                    ..testing::dir(path, 16, &children)
+                },
+            );
+        }
+
+        let trim = CacheTrim {
+            files_dropped: true,
+            ..Default::default()
+        };
+        cache.save_as_rkyv_mmap(
+            &cache_path.with_extension("idx"),
+            &cache_path.with_extension("dat"),
+            &trim,
+            &HashSet::new(),
+            true,
+        )?;
+
+        let mut reopened = DiskCache::open(&cache_path)?;
+        assert!(reopened.is_reduced_snapshot());
+        reopened.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(reopened.entries[&root].children, vec!["alpha".to_string()]);
+        assert!(reopened.entries[&child].children.is_empty());
+        assert_eq!(reopened.entries[&child].file_count, 1);
+        assert!(!temp_dir.join("ptree.tmp").exists());
 
-        cache.refresh_derived_metadata();
         cache.save(&cache_path)?;
+        assert!(!DiskCache::open(&cache_path)?.is_reduced_snapshot());
 
-        let reopened = DiskCache::open(&cache_path)?;
-        assert_eq!(reopened.file_count_hint(), 3);
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
 
-        let mut hydrated = DiskCache::open(&cache_path)?;
-        hydrated.load_visible_entries_lazy(&cache_path, None)?;
+    #[test]
+    fn test_cache_budget_drops_files_then_least_recently_modified_subtrees() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_cache_budget_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = temp_dir.join("root");
+        let alpha = root.join("alpha");
+        let beta = alpha.join("beta");
+        let gamma = root.join("gamma");
 
-        let tree = hydrated.build_tree_output_with_options(None, true, true)?;
-        assert!(tree.contains("alpha (256 B, 2 files)"));
+        let mut cache = DiskCache {
+            root: root.clone(),
+            ..Default::default()
+        };
+        let now = Utc::now();
+        for (path, days_ago, children) in [
+            (&root, 0, vec!["alpha", "gamma", "a.txt", "b.txt"]),
+            (&alpha, 2, vec!["beta", "c.txt"]),
+            (&beta, 3, vec!["d.txt", "e.txt"]),
+            (&gamma, 1, vec!["f.txt"]),
+        ] {
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    modified: now - chrono::Duration::days(days_ago),
+                    file_count: 2,
+                    ..testing::dir(path, 64, &children)
+                },
+            );
+        }
+        let directories_only = |path: &PathBuf, dirs: &[&str]| {
+            let dirs: Vec<Name> = dirs.iter().map(|name| Name::new(name)).collect();
+            DiskCache::estimated_record_size(path, &cache.entries[path], &dirs)
+        };
+        let beta_size = directories_only(&beta, &[]);
+        let structure = directories_only(&root, &["alpha", "gamma"])
+            + directories_only(&alpha, &["beta"])
+            + beta_size
+            + directories_only(&gamma, &[]);
+
+        let full_size = cache.estimated_snapshot_size();
+        assert!(!cache.plan_budget_trim(full_size).0.is_trimmed());
+
+        let (files_only, evicted) = cache.plan_budget_trim(structure);
+        assert!(files_only.files_dropped);
+        assert!(evicted.is_empty());
+
+        // The oldest subtree goes first, and a directory only after everything below it
+        let (_, evicted) = cache.plan_budget_trim(structure - 1);
+        assert_eq!(evicted, HashSet::from([beta.clone()]));
+        let (trim, evicted) = cache.plan_budget_trim(structure - beta_size - 1);
+        assert_eq!(evicted, HashSet::from([alpha.clone(), beta.clone()]));
+        assert_eq!(trim.entries_dropped, 2);
+        let (_, evicted) = cache.plan_budget_trim(1);
+        assert_eq!(evicted.len(), 3);
+        assert!(!evicted.contains(&root));
+
+        cache.max_cache_bytes = Some(structure - beta_size - 1);
+        cache.save(&cache_path)?;
 
-        let json = hydrated.build_json_output_with_options(None, true, true)?;
-        assert!(json.contains("\"file_count\": 3"));
-        assert!(json.contains("\"size_bytes\": 320"));
+        // What was dropped is left for the next scan to read, so the snapshot is no full hit
+        let mut reopened = DiskCache::open(&cache_path)?;
+        assert!(!reopened.is_reduced_snapshot());
+        assert_eq!(reopened.snapshot_trim().entries_dropped, 2);
+        assert_eq!(reopened.pending_dirs, vec![alpha.clone()]);
+        assert!(reopened.is_incomplete());
+        reopened.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(reopened.entries.len(), 2);
+        assert!(reopened.entries.contains_key(&gamma));
+        assert_eq!(reopened.entries[&root].children, vec!["alpha".to_string(), "gamma".to_string()]);
+        assert_eq!(reopened.entries[&root].total_size, 64);
+        assert!(reopened
+            .get_cache_info_report(&cache_path)
+            .contains("2 least recently modified directories dropped"));
 
         let _ = fs::remove_dir_all(&temp_dir);
         Ok(())
     }
 
     #[test]
-    fn test_open_recovers_from_corrupt_snapshot() -> Result<()> {
-        let temp_dir = std::env::temp_dir().join("ptree_test_corrupt_snapshot");
+    fn test_root_caches_are_keyed_and_listed_per_root() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_root_caches");
         let _ = fs::remove_dir_all(&temp_dir);
         fs::create_dir_all(&temp_dir)?;
-        let cache_path = temp_dir.join("ptree.dat");
-        let index_path = cache_path.with_extension("idx");
-        let depth_path = temp_dir.join("ptree-d1.dat");
-
-        fs::write(&index_path, b"not a valid index")?;
-        fs::write(&depth_path, b"stale depth data")?;
+        let base = temp_dir.join("ptree.dat");
+
+        let home = PathBuf::from("/home");
+        let var = PathBuf::from("/var");
+        let home_path = cache_path_for_roots(&base, std::slice::from_ref(&home));
+        let var_path = cache_path_for_roots(&base, std::slice::from_ref(&var));
+        assert_ne!(home_path, var_path);
+        assert_eq!(home_path, cache_path_for_roots(&base, std::slice::from_ref(&home)));
+
+        for (path, root) in [(&home_path, &home), (&var_path, &var)] {
+            let mut cache = DiskCache {
+                root: root.clone(),
+                ..Default::default()
+            };
+            cache.entries.insert(
+                root.clone(),
+                DirEntry {
+                    name: Name::default(),
+                    file_count: 3,
+                    ..testing::dir(root, 0, &[])
+                },
+            );
+            cache.save(path)?;
+        }
 
-        let cache = DiskCache::open(&cache_path)?;
-        assert!(cache.entries.is_empty());
-        assert!(!index_path.exists());
-        assert!(!depth_path.exists());
+        let listed = list_root_caches(&temp_dir)?;
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].roots, vec![home]);
+        assert_eq!(listed[1].roots, vec![var]);
+        assert_eq!(listed[0].files, 3);
+        assert!(listed.iter().all(|cache| !cache.legacy && cache.disk_size > 0));
 
         let _ = fs::remove_dir_all(&temp_dir);
         Ok(())
     }
 
+    #[test]
+    fn test_depth_file_matching_is_exact() {
+        assert!(is_depth_file("ptree-g12-d3.dat", "ptree", false));
+        assert!(!is_depth_file("ptree-g12-d3.dat.tmp", "ptree", false));
+        assert!(!is_depth_file("ptree-g-d3.dat", "ptree", false));
+        assert!(is_snapshot_file("ptree-g12-names.dat", "ptree", false));
+        assert!(!is_depth_file("ptree-0123456789abcdef-g2-d1.dat", "ptree", false));
+        assert!(is_depth_file("ptree-0123456789abcdef-g2-d1.dat", "ptree-0123456789abcdef", false));
+        assert_eq!(parse_snapshot_file("ptree-g12-d3.dat", "ptree", false).and_then(|file| file.generation), Some(12));
+        // Unnumbered files of older versions are still recognized, to be cleaned up
+        assert!(is_depth_file("ptree-d3.dat", "ptree", false));
+        assert!(!is_depth_file("ptree-d3.dat.tmp", "ptree", false));
+        assert!(is_depth_file("ptree-d3.dat.tmp", "ptree", true));
+        assert!(!is_depth_file("ptree-d3f0a1b2c3d4e5f6-d2.dat", "ptree", true));
+        assert!(is_depth_file("ptree-d3f0a1b2c3d4e5f6-d2.dat", "ptree-d3f0a1b2c3d4e5f6", false));
+        assert!(is_snapshot_file("ptree-names.dat", "ptree", false));
+        assert!(!is_snapshot_file("ptree-names.dat.tmp", "ptree", false));
+        assert!(!is_depth_file("ptree-names.dat", "ptree", true));
+    }
+
     #[test]
     fn test_content_hash_stability() {
         // Same inputs should produce same hash
         let path = std::path::Path::new("C:\\test");
         let modified = Utc::now();
-        let children = vec!["file1.txt".to_string(), "file2.txt".to_string()];
+        let children = vec![Name::new("file1.txt"), Name::new("file2.txt")];
         let child_hashes = HashMap::new();
 
-        let hash1 = compute_content_hash(path, modified, &children, &child_hashes);
-        let hash2 = compute_content_hash(path, modified, &children, &child_hashes);
+        let hash1 = compute_content_hash(path, modified, &children, (0, 0), &child_hashes);
+        let hash2 = compute_content_hash(path, modified, &children, (0, 0), &child_hashes);
 
         assert_eq!(hash1, hash2, "Identical inputs should produce identical hashes");
     }
@@ -1559,24 +4938,28 @@ mod tests {
         let modified = Utc::now();
 
         // Base hash
-        let children = vec!["file1.txt".to_string()];
+        let children = vec![Name::new("file1.txt")];
         let child_hashes = HashMap::new();
-        let base_hash = compute_content_hash(path, modified, &children, &child_hashes);
+        let base_hash = compute_content_hash(path, modified, &children, (0, 0), &child_hashes);
 
         // Hash with additional file
-        let children_added = vec!["file1.txt".to_string(), "file2.txt".to_string()];
-        let hash_added = compute_content_hash(path, modified, &children_added, &child_hashes);
+        let children_added = vec![Name::new("file1.txt"), Name::new("file2.txt")];
+        let hash_added = compute_content_hash(path, modified, &children_added, (0, 0), &child_hashes);
         assert_ne!(base_hash, hash_added, "Adding a file should change hash");
 
         // Hash with removed file
         let children_removed = vec![];
-        let hash_removed = compute_content_hash(path, modified, &children_removed, &child_hashes);
+        let hash_removed = compute_content_hash(path, modified, &children_removed, (0, 0), &child_hashes);
         assert_ne!(base_hash, hash_removed, "Removing a file should change hash");
 
         // Hash with renamed file
-        let children_renamed = vec!["renamed_file.txt".to_string()];
-        let hash_renamed = compute_content_hash(path, modified, &children_renamed, &child_hashes);
+        let children_renamed = vec![Name::new("renamed_file.txt")];
+        let hash_renamed = compute_content_hash(path, modified, &children_renamed, (0, 0), &child_hashes);
         assert_ne!(base_hash, hash_renamed, "Renaming a file should change hash");
+
+        // Hash with a file rewritten in place (same names and mtime, new size)
+        let hash_grown = compute_content_hash(path, modified, &children, (0, 4096), &child_hashes);
+        assert_ne!(base_hash, hash_grown, "A file changing size should change hash");
     }
 
     #[test]
@@ -1587,15 +4970,15 @@ mod tests {
         let modified = Utc::now();
 
         // Parent with no child hashes
-        let parent_children = vec!["child".to_string()];
+        let parent_children = vec![Name::new("child")];
         let mut child_hashes = HashMap::new();
         child_hashes.insert(child_path.to_path_buf(), 12345u64);
 
-        let parent_hash1 = compute_content_hash(parent_path, modified, &parent_children, &child_hashes);
+        let parent_hash1 = compute_content_hash(parent_path, modified, &parent_children, (0, 0), &child_hashes);
 
         // Change child hash
         child_hashes.insert(child_path.to_path_buf(), 54321u64);
-        let parent_hash2 = compute_content_hash(parent_path, modified, &parent_children, &child_hashes);
+        let parent_hash2 = compute_content_hash(parent_path, modified, &parent_children, (0, 0), &child_hashes);
 
         assert_ne!(parent_hash1, parent_hash2, "Child hash change should affect parent hash");
     }
@@ -1605,39 +4988,21 @@ mod tests {
         let path = std::path::Path::new("C:\\test");
 
         let old_entry = DirEntry {
-            path:         path.to_path_buf(),
-            name:         "test".to_string(),
-            modified:     Utc::now(),
+            name: Name::new("test"),
             content_hash: 12345u64,
-            file_count:   1,
-            total_size:   64,
-            children:     vec!["file.txt".to_string()],
-            is_hidden:    false,
-            is_dir:       true,
+            ..testing::dir(path, 64, &["file.txt"])
         };
 
         let new_entry_unchanged = DirEntry {
-            path:         path.to_path_buf(),
-            name:         "test".to_string(),
-            modified:     Utc::now(),
+            name: Name::new("test"),
             content_hash: 12345u64,
-            file_count:   1,
-            total_size:   64,
-            children:     vec!["file.txt".to_string()],
-            is_hidden:    false,
-            is_dir:       true,
+            ..testing::dir(path, 64, &["file.txt"])
         };
 
         let new_entry_changed = DirEntry {
-            path:         path.to_path_buf(),
-            name:         "test".to_string(),
-            modified:     Utc::now(),
+            name: Name::new("test"),
             content_hash: 54321u64,
-            file_count:   2,
-            total_size:   96,
-            children:     vec!["file.txt".to_string(), "newfile.txt".to_string()],
-            is_hidden:    false,
-            is_dir:       true,
+            ..testing::dir(path, 96, &["file.txt", "newfile.txt"])
         };
 
         assert!(!has_directory_changed(&old_entry, &new_entry_unchanged), "Same hash should not indicate change");
@@ -1651,23 +5016,7 @@ mod tests {
         let child = std::path::PathBuf::from("/foo/bar");
         let sibling_prefix = std::path::PathBuf::from("/foobar");
 
-        let mk_entry = |path: &std::path::Path| {
-            DirEntry {
-                path:         path.to_path_buf(),
-                name:         path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or_default()
-                    .to_string(),
-                modified:     Utc::now(),
-                content_hash: 0,
-                file_count:   0,
-                total_size:   0,
-                children:     Vec::new(),
-                is_hidden:    false,
-                is_dir:       true,
-            }
-        };
+        let mk_entry = |path: &std::path::Path| testing::dir(path, 0, &[]);
 
         cache.entries.insert(base.clone(), mk_entry(&base));
         cache.entries.insert(child.clone(), mk_entry(&child));
@@ -1679,4 +5028,601 @@ mod tests {
         assert!(!cache.entries.contains_key(&child));
         assert!(cache.entries.contains_key(&sibling_prefix));
     }
+
+    #[test]
+    fn test_slow_listings_add_strikes_until_a_fast_one() {
+        let mut cache = DiskCache::new_empty();
+        let share = PathBuf::from("/mnt/share");
+        let inside = PathBuf::from("/mnt/share/deep");
+        let gone = PathBuf::from("/mnt/gone");
+        let now = Utc::now();
+        let none = HashSet::new();
+
+        cache.record_slow_listings(&[(gone.clone(), SLOW_DIR_MILLIS)], &none, true, now);
+        for _ in 0..SLOW_DIR_STRIKES - 1 {
+            cache.record_slow_listings(&[(share.clone(), SLOW_DIR_MILLIS + 1)], &none, false, now);
+        }
+        cache.record_slow_listings(&[(inside.clone(), SLOW_DIR_MILLIS * 2)], &none, false, now);
+        assert!(!cache.is_slow(&share));
+        assert!(cache.slow_dirs.contains_key(&gone));
+
+        // A full scan that did not reach `gone` drops it
+        cache.record_slow_listings(&[(share.clone(), SLOW_DIR_MILLIS * 3)], &none, true, now);
+        assert!(cache.is_slow(&share));
+        assert!(!cache.slow_dirs.contains_key(&gone));
+        assert!(!cache.slow_dirs.contains_key(&inside));
+        assert_eq!(cache.listed_slow_dirs(), vec![(share.as_path(), &cache.slow_dirs[&share])]);
+        assert_eq!(cache.slow_dirs[&share].last_ms, SLOW_DIR_MILLIS * 3);
+
+        // Skipped directories keep their strikes; a fast listing clears them
+        let skipped = HashSet::from([share.clone()]);
+        cache.record_slow_listings(&[], &skipped, true, now);
+        assert_eq!(cache.slow_dirs[&share].strikes, SLOW_DIR_STRIKES);
+        cache.record_slow_listings(&[(share.clone(), 10)], &none, true, now);
+        assert!(cache.slow_dirs.is_empty());
+    }
+
+    #[test]
+    fn test_sort_orders_and_newer_than_filter() -> Result<()> {
+        let now = Utc::now();
+        let root = PathBuf::from("/data");
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
+        let mut dir = |path: &str, age_hours: i64, children: &[&str]| {
+            let path = PathBuf::from(path);
+            let entry = DirEntry {
+                modified: now - chrono::Duration::hours(age_hours),
+                file_count: 0,
+                ..testing::dir(&path, 0, children)
+            };
+            cache.entries.insert(path, entry);
+        };
+        dir("/data", 240, &["a.txt", "b-old", "c-new", "d-stale"]);
+        dir("/data/b-old", 240, &["deep", "x.txt"]);
+        dir("/data/b-old/deep", 1, &["y.txt"]);
+        dir("/data/c-new", 2, &["z.txt"]);
+        dir("/data/d-stale", 720, &["w.txt"]);
+
+        let render = |cache: &DiskCache| -> Result<Vec<String>> {
+            let mut output = Vec::new();
+            cache.write_accessible_output(&mut output, None, false, false)?;
+            Ok(String::from_utf8(output)?
+                .lines()
+                .map(|line| line.trim_start().split(',').next().unwrap_or_default().to_string())
+                .collect())
+        };
+
+        cache.sort = SortOrder::Mtime;
+        assert_eq!(
+            render(&cache)?,
+            [
+                "level 0: /data",
+                "level 1: c-new",
+                "level 2: z.txt",
+                "level 1: b-old",
+                "level 2: deep",
+                "level 3: y.txt",
+                "level 2: x.txt",
+                "level 1: d-stale",
+                "level 2: w.txt",
+                "level 1: a.txt",
+            ]
+        );
+
+        // Most children first, then turned around
+        cache.sort = SortOrder::Count;
+        cache.reverse = true;
+        assert_eq!(
+            render(&cache)?,
+            [
+                "level 0: /data",
+                "level 1: a.txt",
+                "level 1: d-stale",
+                "level 2: w.txt",
+                "level 1: c-new",
+                "level 2: z.txt",
+                "level 1: b-old",
+                "level 2: x.txt",
+                "level 2: deep",
+                "level 3: y.txt",
+            ]
+        );
+
+        // Old directories stay when something below them changed; files only in recent ones
+        cache.sort = SortOrder::Name;
+        cache.reverse = false;
+        cache.set_modified_range(Some(now - chrono::Duration::days(1)), None);
+        assert_eq!(
+            render(&cache)?,
+            [
+                "level 0: /data",
+                "level 1: b-old",
+                "level 2: deep",
+                "level 3: y.txt",
+                "level 1: c-new",
+                "level 2: z.txt",
+            ]
+        );
+
+        // Files whose own mtime --long recorded go by it, wherever their directory stands
+        cache.entry_metadata.insert(
+            root.join("a.txt"),
+            EntryMetadata {
+                mode:      0,
+                uid:       None,
+                size:      0,
+                allocated: 0,
+                modified:  Some(now - chrono::Duration::hours(3)),
+            },
+        );
+        cache.set_modified_range(Some(now - chrono::Duration::days(20)), Some(now - chrono::Duration::days(5)));
+        assert_eq!(render(&cache)?, ["level 0: /data", "level 1: b-old", "level 2: x.txt"]);
+        cache.set_modified_range(None, Some(now - chrono::Duration::hours(2)));
+        assert!(render(&cache)?.contains(&"level 1: a.txt".to_string()));
+        assert!(!render(&cache)?.contains(&"level 2: y.txt".to_string()));
+        cache.set_modified_range(None, None);
+        assert_eq!(render(&cache)?.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_range_shows_directories_within_it() -> Result<()> {
+        let mut cache = DiskCache {
+            root: PathBuf::from("/data"),
+            ..Default::default()
+        };
+        for (path, total_size, children) in [
+            ("/data", 3000, vec!["big", "small", "notes.txt"]),
+            ("/data/big", 2500, vec!["bigger", "tiny"]),
+            ("/data/big/bigger", 2000, vec![]),
+            ("/data/big/tiny", 10, vec![]),
+            ("/data/small", 500, vec![]),
+        ] {
+            let path = PathBuf::from(path);
+            let entry = DirEntry {
+                file_count: 0,
+                ..testing::dir(&path, total_size, &children)
+            };
+            cache.entries.insert(path, entry);
+        }
+        let render = |cache: &DiskCache| -> Result<String> {
+            let mut output = Vec::new();
+            cache.write_tree_output_with_options(&mut output, None, false, false)?;
+            Ok(String::from_utf8(output)?)
+        };
+
+        cache.set_size_range(Some(1000), None);
+        assert_eq!(render(&cache)?, "/data\n└── big\n    └── bigger\n");
+        cache.set_size_range(None, Some(600));
+        assert_eq!(render(&cache)?, "/data\n└── small\n");
+        cache.set_size_range(None, None);
+        assert!(render(&cache)?.contains("notes.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_dirs_joins_single_subdirectory_chains() -> Result<()> {
+        let root = PathBuf::from("/r");
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            compact_dirs: true,
+            ..Default::default()
+        };
+        for (path, children) in [
+            ("/r", &["build", "docs", "src"][..]),
+            ("/r/src", &["main"]),
+            ("/r/src/main", &["java"]),
+            ("/r/src/main/java", &["App.java", "util"]),
+            ("/r/src/main/java/util", &[]),
+            ("/r/docs", &["guide.md"]),
+            ("/r/build", &["out"]),
+            ("/r/build/out", &["classes"]),
+            ("/r/build/out/classes", &[]),
+        ] {
+            let path = PathBuf::from(path);
+            let entry = DirEntry {
+                file_count: 0,
+                ..testing::dir(&path, 0, children)
+            };
+            cache.entries.insert(path, entry);
+        }
+        // A marked directory ends the chain above it, keeping its marker on the joined line
+        cache.unsettled_dirs.insert(PathBuf::from("/r/build/out"));
+
+        let sep = std::path::MAIN_SEPARATOR;
+        let tree = cache.build_tree_output()?;
+        assert_eq!(
+            tree,
+            format!(
+                "/r\n├── build{sep}out [unsettled]\n│   └── classes\n├── docs\n│   └── guide.md\n└── \
+                 src{sep}main{sep}java\n    ├── App.java\n    └── util\n"
+            )
+        );
+
+        // A joined line is one level deep
+        let shallow = cache.build_tree_output_with_depth(Some(1))?;
+        assert!(shallow.ends_with(&format!("└── src{sep}main{sep}java\n")));
+
+        cache.compact_dirs = false;
+        assert!(cache
+            .build_tree_output()?
+            .contains("└── src\n    └── main\n        └── java\n"));
+
+        // --charset ascii and --style draw the same tree with other glyphs
+        cache.glyphs = TreeGlyphs::new(Charset::Ascii, BranchStyle::Double);
+        assert!(cache
+            .build_tree_output()?
+            .contains("|   `-- guide.md\n`-- src\n    `-- main\n"));
+        cache.glyphs = TreeGlyphs::new(Charset::Unicode, BranchStyle::Rounded);
+        assert!(cache.build_colored_tree_output()?.contains("╰── "));
+
+        // --limit keeps the first children of each directory and counts the rest
+        cache.glyphs = TreeGlyphs::default();
+        cache.child_limit = Some(1);
+        assert_eq!(
+            cache.build_tree_output()?,
+            "/r\n├── build\n│   └── out [unsettled]\n│       └── classes\n└── … (+2 more)\n"
+        );
+        assert!(cache.build_json_output()?.contains("\"more_children\": 2"));
+        let mut accessible = Vec::new();
+        cache.write_accessible_output(&mut accessible, None, false, false)?;
+        assert!(String::from_utf8(accessible)?.ends_with("  level 1: 2 more entries not shown\n"));
+        cache.child_limit = None;
+
+        // --full-path and --relative-to print paths where the names were
+        #[cfg(unix)]
+        {
+            cache.glyphs = TreeGlyphs::default();
+            cache.path_display = PathDisplay::Full;
+            assert!(cache.build_tree_output()?.contains("│   └── /r/docs/guide.md\n"));
+            cache.path_display = PathDisplay::RelativeTo(PathBuf::from("/r/docs"));
+            let tree = cache.build_tree_output()?;
+            assert!(tree.starts_with("..\n├── ../build\n"));
+            assert!(tree.contains("├── .\n│   └── guide.md\n"));
+            assert!(cache.build_json_output()?.contains("\"path\": \"../src/main\""));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_alt_streams_are_replaced_per_rescanned_directory_and_saved() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_alt_streams");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = PathBuf::from("/data");
+        let stream = |name: &str, size: u64| {
+            AltStream {
+                name: name.to_string(),
+                size,
+            }
+        };
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            ..Default::default()
+        };
+        for (path, scan) in [("/data", 1), ("/data/docs", 1), ("/data/bin", 1)] {
+            let path = PathBuf::from(path);
+            let entry = DirEntry {
+                scan,
+                ..testing::dir(&path, 0, &[])
+            };
+            cache.entries.insert(path, entry);
+        }
+        cache.scan = 1;
+        cache.record_alt_streams(
+            vec![
+                (root.join("docs/report.txt"), vec![stream("Zone.Identifier", 26), stream("payload.exe", 4096)]),
+                (root.join("docs/setup.msi"), vec![stream("Zone.Identifier", 26)]),
+                (root.join("bin/tool.exe"), vec![stream("hidden", 10)]),
+            ],
+            true,
+        );
+
+        // An incremental rescan of `docs` replaces what was recorded there
+        cache.scan = 2;
+        cache.entries.get_mut(&root.join("docs")).unwrap().scan = 2;
+        cache.record_alt_streams(vec![(root.join("docs/notes.txt"), vec![stream("secret", 7)])], false);
+        let reported: Vec<(&Path, Vec<&str>)> = cache
+            .reported_alt_streams(false)
+            .into_iter()
+            .map(|(path, streams)| (path, streams.iter().map(|stream| stream.name.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (root.join("bin/tool.exe").as_path(), vec!["hidden"]),
+                (root.join("docs/notes.txt").as_path(), vec!["secret"]),
+            ]
+        );
+
+        cache.save(&cache_path)?;
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.alt_streams, cache.alt_streams);
+        assert_eq!(reopened.ads_scanned, cache.ads_scanned);
+        assert!(reopened.ads_scanned.is_some());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subvolumes_are_marked_replaced_per_reread_parent_and_saved() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_subvolumes");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = PathBuf::from("/pool");
+        let subvolume = |kind, usage_bytes| Subvolume { kind, usage_bytes };
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            scan: 1,
+            json_schema: JsonSchema::V1,
+            ..Default::default()
+        };
+        for (path, children) in [
+            ("/pool", vec!["home", "srv"]),
+            ("/pool/home", vec![]),
+            ("/pool/srv", vec!["www"]),
+            ("/pool/srv/www", vec![]),
+        ] {
+            let path = PathBuf::from(path);
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    file_count: 0,
+                    scan: 1,
+                    ..testing::dir(&path, 0, &children)
+                },
+            );
+        }
+        cache.record_subvolumes(
+            vec![
+                (root.join("home"), subvolume(SubvolumeKind::Btrfs, None)),
+                (root.join("srv/www"), subvolume(SubvolumeKind::Zfs, Some(3 << 30))),
+            ],
+            true,
+        );
+
+        let tree = cache.build_tree_output_with_options(None, false, false)?;
+        assert!(tree.contains("home [subvol]"), "{tree}");
+        assert!(tree.contains("www [subvol]"), "{tree}");
+        let json = cache.build_json_output_with_options(None, false, false)?;
+        assert!(json.contains(r#""subvolume": {"kind":"Zfs","usage_bytes":3221225472}"#), "{json}");
+        assert!(cache
+            .get_subvolume_report()
+            .contains("ZFS dataset /pool/srv/www: 0 B scanned, 3.0 GB used"));
+
+        cache.save(&cache_path)?;
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.subvolumes, cache.subvolumes);
+
+        // A refresh that reread /pool/srv alone keeps what it did not look at
+        cache.scan = 2;
+        cache.entries.get_mut(&root.join("srv")).unwrap().scan = 2;
+        cache.record_subvolumes(Vec::new(), false);
+        let listed: Vec<&Path> = cache.listed_subvolumes().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(listed, vec![root.join("home").as_path()]);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mount_points_are_marked_and_saved() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_mount_points");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = PathBuf::from("/srv");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            scan: 1,
+            one_file_system: true,
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.clone(),
+            DirEntry {
+                name: Name::new("srv"),
+                file_count: 0,
+                scan: 1,
+                ..testing::dir(&root, 0, &["nfs"])
+            },
+        );
+        cache.record_mount_points(vec![root.join("nfs")], true);
+
+        let tree = cache.build_tree_output_with_options(None, false, false)?;
+        assert!(tree.contains("nfs [mount]"), "{tree}");
+        let json = cache.build_json_output_with_options(None, false, false)?;
+        assert!(json.contains(r#""mount_point": true"#), "{json}");
+
+        cache.save(&cache_path)?;
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.mount_points, cache.mount_points);
+        assert!(reopened.one_file_system);
+
+        // A refresh that reread /srv and found nothing mounted there drops the mark
+        cache.scan = 2;
+        cache.entries.get_mut(&root).unwrap().scan = 2;
+        cache.record_mount_points(Vec::new(), false);
+        assert!(cache.mount_points.is_empty());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlinks_show_their_target_and_are_saved() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_symlinks");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = PathBuf::from("/home/user");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            scan: 1,
+            json_schema: JsonSchema::V1,
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.clone(),
+            DirEntry {
+                name: Name::new("user"),
+                file_count: 1,
+                scan: 1,
+                ..testing::dir(&root, 0, &["data", "self"])
+            },
+        );
+        cache.entries.insert(
+            root.join("data"),
+            DirEntry {
+                name: Name::new("data"),
+                scan: 1,
+                ..testing::dir(root.join("data"), 0, &[])
+            },
+        );
+        let link = |target: &str, status| {
+            Symlink {
+                target: PathBuf::from(target),
+                status,
+            }
+        };
+        cache.record_symlinks(
+            vec![
+                (root.join("data"), link("/mnt/data", SymlinkStatus::Followed)),
+                (root.join("self"), link(".", SymlinkStatus::InTree)),
+            ],
+            true,
+        );
+
+        let tree = cache.build_tree_output_with_options(None, false, false)?;
+        assert!(tree.contains("data -> /mnt/data\n"), "{tree}");
+        assert!(tree.contains("self -> . [in tree]"), "{tree}");
+        let json = cache.build_json_output_with_options(None, false, false)?;
+        assert!(json.contains(r#""symlink": {"target":".","status":"in_tree"}"#), "{json}");
+
+        cache.save(&cache_path)?;
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.symlinks, cache.symlinks);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_errors_are_marked_reported_and_cleared_once_read() -> Result<()> {
+        let root = PathBuf::from("/data");
+        let mut cache = DiskCache {
+            root: root.clone(),
+            last_scanned_root: root.clone(),
+            scan: 1,
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.clone(),
+            DirEntry {
+                name: Name::new("data"),
+                file_count: 0,
+                scan: 1,
+                ..testing::dir(&root, 0, &["private", "tmp"])
+            },
+        );
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let vanished = std::io::Error::from(std::io::ErrorKind::NotFound);
+        cache.record_scan_errors(
+            vec![
+                (root.join("private"), ScanError::new(&denied)),
+                (root.join("tmp"), ScanError::new(&vanished)),
+            ],
+            true,
+        );
+
+        let tree = cache.build_tree_output_with_options(None, false, false)?;
+        assert!(tree.contains("private [denied]"), "{tree}");
+        assert!(tree.contains("tmp [vanished]"), "{tree}");
+        let json = cache.build_json_output_with_options(None, false, false)?;
+        assert!(json.contains(r#""error": {"kind":"denied","#), "{json}");
+        let report = cache.get_error_report();
+        assert!(report.starts_with("Errors: 2 directories could not be read\n  permission denied (1):\n"));
+        assert!(report.contains("    /data/tmp: "), "{report}");
+
+        // A refresh that kept /data and could read /data/private now drops only that record
+        cache.scan = 2;
+        let mut private = cache.entries[&root].clone();
+        private.path = root.join("private");
+        private.children.clear();
+        private.scan = 2;
+        cache.entries.insert(root.join("private"), private);
+        cache.record_scan_errors(Vec::new(), false);
+        assert_eq!(cache.scan_errors.keys().collect::<Vec<_>>(), vec![&root.join("tmp")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_xattrs_are_reported_by_name_prefix_and_saved() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_xattrs");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let attr = |name: &str, value: &str| {
+            XAttr {
+                name:  name.to_string(),
+                size:  value.len() as u64,
+                value: Some(value.to_string()),
+            }
+        };
+
+        let mut cache = DiskCache::new_empty();
+        cache.record_xattrs(
+            vec![
+                (
+                    PathBuf::from("/data/app.dmg"),
+                    vec![
+                        attr("com.apple.quarantine", "0081;6530e5c1;Safari;"),
+                        attr("com.apple.lastuseddate#PS", "x"),
+                    ],
+                ),
+                (PathBuf::from("/data/ping"), vec![attr("security.capability", "0x0100000200200000")]),
+            ],
+            true,
+        );
+
+        let names = |prefixes: &[&str]| -> Vec<(PathBuf, Vec<String>)> {
+            let prefixes: Vec<String> = prefixes.iter().map(|prefix| prefix.to_string()).collect();
+            cache
+                .reported_xattrs(&prefixes)
+                .into_iter()
+                .map(|(path, attrs)| (path.to_path_buf(), attrs.iter().map(|attr| attr.name.clone()).collect()))
+                .collect()
+        };
+        assert_eq!(names(&[]).len(), 2);
+        assert_eq!(
+            names(&["com.apple.quarantine", "security."]),
+            vec![
+                (PathBuf::from("/data/app.dmg"), vec!["com.apple.quarantine".to_string()]),
+                (PathBuf::from("/data/ping"), vec!["security.capability".to_string()]),
+            ]
+        );
+
+        cache.save(&cache_path)?;
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.xattrs, cache.xattrs);
+        assert_eq!(reopened.xattrs_scanned, cache.xattrs_scanned);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
 }
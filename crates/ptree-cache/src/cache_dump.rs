@@ -0,0 +1,586 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{
+    root_cache_key,
+    AltStream,
+    CacheTrim,
+    DirEntry,
+    DiskCache,
+    EntryMetadata,
+    ExtensionUsage,
+    LargeFile,
+    NewDir,
+    OwnerUsage,
+    ScanError,
+    SlowDir,
+    StoreUsage,
+    Subvolume,
+    Symlink,
+    XAttr,
+};
+use crate::cache_lock::CacheLock;
+use crate::cache_provenance::ScanRecord;
+
+/// `format` tag of every dump, so a stray JSON file is not taken for one.
+pub const DUMP_FORMAT: &str = "ptree-cache-dump";
+
+/// Layout version of `CacheDump`; bumped whenever a field changes meaning or goes away.
+/// Version 2 added `allocated_size` to entries; version 1 dumps still restore.
+pub const DUMP_VERSION: u32 = 2;
+
+/// A whole snapshot as plain JSON (`ptree cache dump --json` / `ptree cache restore --json`).
+///
+/// Collections are ordered so the same snapshot always dumps to the same text. Everything a
+/// restore does not need to rebuild the snapshot has a default, so hand-written fixtures can
+/// stick to `format`, `version`, `root`, `last_scan` and `entries`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheDump {
+    pub format:             String,
+    pub version:            u32,
+    pub root:               PathBuf,
+    /// Every top-level root of a multi-root scan (empty for a single root)
+    #[serde(default)]
+    pub roots:              Vec<PathBuf>,
+    pub last_scan:          DateTime<Utc>,
+    #[serde(default)]
+    pub last_scanned_root:  PathBuf,
+    /// Next stable entry ID; a restore raises it past the largest ID in `entries`
+    #[serde(default)]
+    pub next_entry_id:      u64,
+    #[serde(default)]
+    pub scan:               u64,
+    /// Scans that verify some entries (their counts are recomputed on restore)
+    #[serde(default)]
+    pub scans:              BTreeMap<u64, ScanRecord>,
+    #[serde(default)]
+    pub compressed:         bool,
+    #[serde(default)]
+    pub reduced:            bool,
+    #[serde(default)]
+    pub trim:               CacheTrim,
+    #[serde(default)]
+    pub skip_stats:         BTreeMap<String, usize>,
+    #[serde(default)]
+    pub store_usage:        Vec<StoreUsage>,
+    #[serde(default)]
+    pub unsettled_dirs:     BTreeSet<PathBuf>,
+    #[serde(default)]
+    pub new_dirs:           BTreeMap<PathBuf, NewDir>,
+    #[serde(default)]
+    pub slow_dirs:          BTreeMap<PathBuf, SlowDir>,
+    #[serde(default)]
+    pub alt_streams:        BTreeMap<PathBuf, Vec<AltStream>>,
+    #[serde(default)]
+    pub ads_scanned:        Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub xattrs:             BTreeMap<PathBuf, Vec<XAttr>>,
+    #[serde(default)]
+    pub xattrs_scanned:     Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub owner_usage:        BTreeMap<PathBuf, Vec<OwnerUsage>>,
+    #[serde(default)]
+    pub owners_scanned:     Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub extension_usage:    BTreeMap<PathBuf, Vec<ExtensionUsage>>,
+    #[serde(default)]
+    pub extensions_scanned: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub entry_metadata:     BTreeMap<PathBuf, EntryMetadata>,
+    #[serde(default)]
+    pub metadata_scanned:   Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub largest_files:      Vec<LargeFile>,
+    #[serde(default)]
+    pub subvolumes:         BTreeMap<PathBuf, Subvolume>,
+    #[serde(default)]
+    pub mount_points:       BTreeSet<PathBuf>,
+    #[serde(default)]
+    pub one_file_system:    bool,
+    #[serde(default)]
+    pub count_links:        bool,
+    #[serde(default)]
+    pub skip_rules:         u64,
+    #[serde(default)]
+    pub symlinks:           BTreeMap<PathBuf, Symlink>,
+    #[serde(default)]
+    pub scan_errors:        BTreeMap<PathBuf, ScanError>,
+    #[serde(default)]
+    pub pending_dirs:       Vec<PathBuf>,
+    /// Every directory (and file) record, sorted by path
+    pub entries:            Vec<DirEntry>,
+}
+
+impl CacheDump {
+    /// Roots the snapshot was scanned from, which also key its cache file.
+    pub fn scan_roots(&self) -> Vec<PathBuf> {
+        if self.roots.is_empty() {
+            vec![self.root.clone()]
+        } else {
+            self.roots.clone()
+        }
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("cannot create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_json(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        let dump: CacheDump = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("{} is not a ptree cache dump", path.display()))?;
+        dump.validate()?;
+        Ok(dump)
+    }
+
+    /// Reject dumps a restore could not turn into a consistent snapshot.
+    fn validate(&self) -> Result<()> {
+        if self.format != DUMP_FORMAT {
+            bail!("unknown dump format {:?} (expected {:?})", self.format, DUMP_FORMAT);
+        }
+        if !(1..=DUMP_VERSION).contains(&self.version) {
+            bail!("dump version {} is not supported (this ptree reads version {})", self.version, DUMP_VERSION);
+        }
+
+        let roots = self.scan_roots();
+        let mut paths = HashSet::with_capacity(self.entries.len());
+        let mut ids = HashSet::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            if !roots.iter().any(|root| entry.path.starts_with(root)) {
+                bail!("entry {} is outside the dump's roots", entry.path.display());
+            }
+            if !paths.insert(&entry.path) {
+                bail!("entry {} appears twice", entry.path.display());
+            }
+            if entry.id != 0 && !ids.insert(entry.id) {
+                bail!("entry ID {} is used twice (at {})", entry.id, entry.path.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DiskCache {
+    /// The snapshot at `cache_path` with every entry loaded, as a `CacheDump`.
+    pub fn dump(cache_path: &Path) -> Result<CacheDump> {
+        let mut cache = DiskCache::open(cache_path)?;
+        if !cache.has_persisted_snapshot {
+            bail!("no cached snapshot at {}", cache_path.display());
+        }
+        cache.load_all_entries_lazy(cache_path)?;
+
+        let mut entries: Vec<DirEntry> = cache.entries.into_values().collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(CacheDump {
+            format: DUMP_FORMAT.to_string(),
+            version: DUMP_VERSION,
+            root: cache.root,
+            roots: cache.roots,
+            last_scan: cache.last_scan,
+            last_scanned_root: cache.last_scanned_root,
+            next_entry_id: cache.next_entry_id,
+            scan: cache.scan,
+            scans: cache.scans,
+            compressed: cache.compress,
+            reduced: cache.persisted_reduced,
+            trim: cache.persisted_trim,
+            skip_stats: cache.skip_stats.into_iter().collect(),
+            store_usage: cache.store_usage,
+            unsettled_dirs: cache.unsettled_dirs.into_iter().collect(),
+            new_dirs: cache.new_dirs.into_iter().collect(),
+            slow_dirs: cache.slow_dirs.into_iter().collect(),
+            alt_streams: cache.alt_streams.into_iter().collect(),
+            ads_scanned: cache.ads_scanned,
+            xattrs: cache.xattrs.into_iter().collect(),
+            xattrs_scanned: cache.xattrs_scanned,
+            owner_usage: cache.owner_usage.into_iter().collect(),
+            owners_scanned: cache.owners_scanned,
+            extension_usage: cache.extension_usage.into_iter().collect(),
+            extensions_scanned: cache.extensions_scanned,
+            entry_metadata: cache.entry_metadata.into_iter().collect(),
+            metadata_scanned: cache.metadata_scanned,
+            largest_files: cache.largest_files,
+            subvolumes: cache.subvolumes.into_iter().collect(),
+            mount_points: cache.mount_points.into_iter().collect(),
+            one_file_system: cache.one_file_system,
+            count_links: cache.count_links,
+            skip_rules: cache.skip_rules,
+            symlinks: cache.symlinks.into_iter().collect(),
+            scan_errors: cache.scan_errors.into_iter().collect(),
+            pending_dirs: cache.pending_dirs,
+            entries,
+        })
+    }
+
+    /// Replace the snapshot at `cache_path` with `dump`, written as the next generation so
+    /// nothing of the old snapshot is paired with it. Returns the number of entries written.
+    pub fn restore(cache_path: &Path, dump: CacheDump) -> Result<usize> {
+        dump.validate()?;
+
+        let mut cache = DiskCache::open(cache_path)?;
+        let _lock = CacheLock::acquire(cache_path, cache.lock_timeout)?;
+        dump.apply_to(&mut cache);
+
+        let (trim, reduced) = (cache.persisted_trim, cache.persisted_reduced);
+        cache.save_as_rkyv_mmap(
+            &cache_path.with_extension("idx"),
+            &cache_path.with_extension("dat"),
+            &trim,
+            &HashSet::new(),
+            reduced,
+        )?;
+        Ok(cache.entries.len())
+    }
+}
+
+impl CacheDump {
+    /// The snapshot in memory, every entry loaded (as a render or a restore needs it).
+    pub fn into_cache(self) -> DiskCache {
+        let mut cache = DiskCache::new_empty();
+        self.apply_to(&mut cache);
+        cache
+    }
+
+    fn apply_to(self, cache: &mut DiskCache) {
+        let largest_id = self.entries.iter().map(|entry| entry.id).max().unwrap_or(0);
+        let version = self.version;
+        cache.entries = self
+            .entries
+            .into_iter()
+            .map(|mut entry| {
+                // Version 1 predates sizes on disk; take them to be the logical ones
+                if version < 2 {
+                    entry.allocated_size = entry.total_size;
+                }
+                (entry.path.clone(), entry)
+            })
+            .collect::<HashMap<_, _>>();
+        cache.root = self.root;
+        cache.roots = self.roots;
+        cache.last_scan = self.last_scan;
+        cache.last_scanned_root = self.last_scanned_root;
+        cache.next_entry_id = self.next_entry_id.max(largest_id + 1);
+        cache.scan = self.scan;
+        cache.scans = self.scans;
+        cache.compress = self.compressed;
+        cache.persisted_reduced = self.reduced;
+        cache.persisted_trim = self.trim;
+        cache.skip_stats = self.skip_stats.into_iter().collect();
+        cache.store_usage = self.store_usage;
+        cache.unsettled_dirs = self.unsettled_dirs.into_iter().collect();
+        cache.new_dirs = self.new_dirs.into_iter().collect();
+        cache.slow_dirs = self.slow_dirs.into_iter().collect();
+        cache.alt_streams = self.alt_streams.into_iter().collect();
+        cache.ads_scanned = self.ads_scanned;
+        cache.xattrs = self.xattrs.into_iter().collect();
+        cache.xattrs_scanned = self.xattrs_scanned;
+        cache.owner_usage = self.owner_usage.into_iter().collect();
+        cache.owners_scanned = self.owners_scanned;
+        cache.extension_usage = self.extension_usage.into_iter().collect();
+        cache.extensions_scanned = self.extensions_scanned;
+        cache.entry_metadata = self.entry_metadata.into_iter().collect();
+        cache.metadata_scanned = self.metadata_scanned;
+        cache.largest_files = self.largest_files;
+        cache.subvolumes = self.subvolumes.into_iter().collect();
+        cache.mount_points = self.mount_points.into_iter().collect();
+        cache.one_file_system = self.one_file_system;
+        cache.count_links = self.count_links;
+        cache.skip_rules = self.skip_rules;
+        cache.symlinks = self.symlinks.into_iter().collect();
+        cache.scan_errors = self.scan_errors.into_iter().collect();
+        cache.pending_dirs = self.pending_dirs;
+    }
+}
+
+// ============================================================================
+// Named Snapshots
+// ============================================================================
+
+/// Where named snapshots (`ptree cache dump --snapshot NAME`) live inside the cache directory.
+pub fn snapshots_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("snapshots")
+}
+
+/// A named snapshot: a dump kept in `snapshots_dir`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub name:      String,
+    pub path:      PathBuf,
+    pub last_scan: DateTime<Utc>,
+}
+
+/// Just enough of a dump to tell what it covers (serde skips the entries).
+#[derive(Deserialize)]
+struct DumpHeader {
+    format:    String,
+    root:      PathBuf,
+    #[serde(default)]
+    roots:     Vec<PathBuf>,
+    last_scan: DateTime<Utc>,
+}
+
+/// File of the named snapshot `name` in `dir`; names are plain file stems.
+pub fn snapshot_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("invalid snapshot name {:?} (use letters, digits, '-' and '_')", name);
+    }
+    Ok(dir.join(format!("{name}.json")))
+}
+
+/// Named snapshots in `dir` of the same roots as `roots`, oldest first. Files that are not
+/// dumps are left out.
+pub fn list_snapshots(dir: &Path, roots: &[PathBuf]) -> Result<Vec<SnapshotInfo>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let key = root_cache_key(roots);
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        let Ok(header) = serde_json::from_reader::<_, DumpHeader>(BufReader::new(file)) else {
+            continue;
+        };
+        let header_roots = if header.roots.is_empty() {
+            vec![header.root]
+        } else {
+            header.roots
+        };
+        if header.format != DUMP_FORMAT || root_cache_key(&header_roots) != key {
+            continue;
+        }
+
+        snapshots.push(SnapshotInfo {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path,
+            last_scan: header.last_scan,
+        });
+    }
+    snapshots.sort_by(|a, b| a.last_scan.cmp(&b.last_scan).then_with(|| a.name.cmp(&b.name)));
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::names::Name;
+    use crate::testing;
+
+    fn entry(path: &Path, id: u64, children: &[&str]) -> DirEntry {
+        DirEntry {
+            content_hash: id * 31,
+            id,
+            scan: 1,
+            ..testing::dir(path, id * 100, children)
+        }
+    }
+
+    #[test]
+    fn dump_and_restore_round_trip_through_json() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_dump_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let root = PathBuf::from("/data");
+        let original_path = temp_dir.join("original").join("ptree.dat");
+        let restored_path = temp_dir.join("restored").join("ptree.dat");
+
+        let mut cache = DiskCache::open(&original_path)?;
+        cache.root = root.clone();
+        cache.scan = 1;
+        cache.record_scan(1, Utc::now());
+        cache.skip_stats.insert("node_modules".to_string(), 2);
+        for (path, id, children) in [
+            (root.clone(), 1, vec!["a", "b", "notes.txt"]),
+            (root.join("a"), 2, vec![]),
+            (root.join("b"), 3, vec!["deep"]),
+            (root.join("b").join("deep"), 4, vec![]),
+        ] {
+            cache.entries.insert(path.clone(), entry(&path, id, &children));
+        }
+        cache.next_entry_id = 5;
+        cache.save(&original_path)?;
+
+        let json_path = temp_dir.join("dump.json");
+        let dump = DiskCache::dump(&original_path)?;
+        assert_eq!(dump.entries.len(), 4);
+        dump.write_json(&json_path)?;
+
+        let restored = CacheDump::read_json(&json_path)?;
+        assert_eq!(DiskCache::restore(&restored_path, restored)?, 4);
+        let round_trip = DiskCache::dump(&restored_path)?;
+        assert_eq!(serde_json::to_value(&round_trip)?, serde_json::to_value(&dump)?);
+
+        let mut reopened = DiskCache::open(&restored_path)?;
+        reopened.load_all_entries_lazy(&restored_path)?;
+        assert_eq!(reopened.entries[&root.join("b")].children, vec![Name::new("deep")]);
+        assert_eq!(reopened.skip_stats["node_modules"], 2);
+
+        // Restoring over an existing snapshot writes the next generation
+        let generation = reopened.generation;
+        DiskCache::restore(&restored_path, CacheDump::read_json(&json_path)?)?;
+        assert_eq!(DiskCache::open(&restored_path)?.generation, generation + 1);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn named_snapshots_are_listed_oldest_first_per_root() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_snapshots_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let dir = snapshots_dir(&temp_dir);
+        fs::create_dir_all(&dir)?;
+
+        let now = Utc::now();
+        let data = PathBuf::from("/data");
+        for (name, root, age_days) in [
+            ("recent", &data, 1),
+            ("old", &data, 30),
+            ("other-root", &PathBuf::from("/var"), 5),
+        ] {
+            let dump = CacheDump {
+                format:             DUMP_FORMAT.to_string(),
+                version:            DUMP_VERSION,
+                root:               root.clone(),
+                roots:              Vec::new(),
+                last_scan:          now - chrono::Duration::days(age_days),
+                last_scanned_root:  PathBuf::new(),
+                next_entry_id:      0,
+                scan:               0,
+                scans:              BTreeMap::new(),
+                compressed:         false,
+                reduced:            false,
+                trim:               CacheTrim::default(),
+                skip_stats:         BTreeMap::new(),
+                store_usage:        Vec::new(),
+                unsettled_dirs:     BTreeSet::new(),
+                new_dirs:           BTreeMap::new(),
+                slow_dirs:          BTreeMap::new(),
+                alt_streams:        BTreeMap::new(),
+                ads_scanned:        None,
+                xattrs:             BTreeMap::new(),
+                xattrs_scanned:     None,
+                owner_usage:        BTreeMap::new(),
+                owners_scanned:     None,
+                extension_usage:    BTreeMap::new(),
+                extensions_scanned: None,
+                entry_metadata:     BTreeMap::new(),
+                metadata_scanned:   None,
+                largest_files:      Vec::new(),
+                subvolumes:         BTreeMap::new(),
+                mount_points:       BTreeSet::new(),
+                one_file_system:    false,
+                count_links:        false,
+                skip_rules:         0,
+                symlinks:           BTreeMap::new(),
+                scan_errors:        BTreeMap::new(),
+                pending_dirs:       Vec::new(),
+                entries:            vec![entry(root, 1, &[])],
+            };
+            dump.write_json(&snapshot_path(&dir, name)?)?;
+        }
+        fs::write(dir.join("notes.json"), "{}")?;
+
+        let names: Vec<String> = list_snapshots(&dir, std::slice::from_ref(&data))?
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        assert_eq!(names, vec!["old", "recent"]);
+        assert!(snapshot_path(&dir, "../escape").is_err());
+
+        let cache = CacheDump::read_json(&snapshot_path(&dir, "old")?)?.into_cache();
+        assert_eq!(cache.last_scan, now - chrono::Duration::days(30));
+        assert!(cache.entries.contains_key(&data));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn inconsistent_dumps_are_rejected() {
+        let root = PathBuf::from("/data");
+        let mut dump = CacheDump {
+            format:             DUMP_FORMAT.to_string(),
+            version:            DUMP_VERSION,
+            root:               root.clone(),
+            roots:              Vec::new(),
+            last_scan:          Utc::now(),
+            last_scanned_root:  PathBuf::new(),
+            next_entry_id:      0,
+            scan:               0,
+            scans:              BTreeMap::new(),
+            compressed:         false,
+            reduced:            false,
+            trim:               CacheTrim::default(),
+            skip_stats:         BTreeMap::new(),
+            store_usage:        Vec::new(),
+            unsettled_dirs:     BTreeSet::new(),
+            new_dirs:           BTreeMap::new(),
+            slow_dirs:          BTreeMap::new(),
+            alt_streams:        BTreeMap::new(),
+            ads_scanned:        None,
+            xattrs:             BTreeMap::new(),
+            xattrs_scanned:     None,
+            owner_usage:        BTreeMap::new(),
+            owners_scanned:     None,
+            extension_usage:    BTreeMap::new(),
+            extensions_scanned: None,
+            entry_metadata:     BTreeMap::new(),
+            metadata_scanned:   None,
+            largest_files:      Vec::new(),
+            subvolumes:         BTreeMap::new(),
+            mount_points:       BTreeSet::new(),
+            one_file_system:    false,
+            count_links:        false,
+            skip_rules:         0,
+            symlinks:           BTreeMap::new(),
+            scan_errors:        BTreeMap::new(),
+            pending_dirs:       Vec::new(),
+            entries:            vec![entry(&root, 1, &[]), entry(&root.join("a"), 2, &[])],
+        };
+        assert!(dump.validate().is_ok());
+
+        dump.entries.push(entry(Path::new("/elsewhere"), 3, &[]));
+        assert!(dump.validate().is_err());
+        dump.entries.pop();
+
+        dump.entries.push(entry(&root.join("b"), 2, &[]));
+        assert!(dump.validate().is_err());
+        dump.entries.pop();
+
+        dump.version = DUMP_VERSION + 1;
+        assert!(dump.validate().is_err());
+
+        // Version 1 had no sizes on disk; they restore as the logical sizes
+        dump.version = 1;
+        assert!(dump.validate().is_ok());
+        for entry in &mut dump.entries {
+            entry.allocated_size = 0;
+        }
+        let cache = dump.into_cache();
+        assert_eq!(cache.entries[&root.join("a")].allocated_size, 200);
+    }
+}
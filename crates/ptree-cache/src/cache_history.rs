@@ -0,0 +1,187 @@
+//! The history of a cache's scans (`ptree-<hash>.history` next to its `.idx`): one JSON line
+//! per saved scan, appended after the save and read by `ptree history`. Appends and the
+//! compaction that drops the oldest records both hold the cache lock, so a compaction never
+//! renames the file away under a concurrent append.
+
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cache_lock::{CacheLock, DEFAULT_LOCK_TIMEOUT};
+
+/// Scans kept in a history; older ones are dropped as new ones are recorded.
+pub const HISTORY_MAX_RECORDS: usize = 1000;
+
+/// The totals of one scan. The snapshot only ever holds the latest scan; this is what
+/// `ptree history` reads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// When the scan was saved
+    pub time:            DateTime<Utc>,
+    pub directories:     usize,
+    pub files:           usize,
+    pub bytes:           u64,
+    /// Bytes allocated on disk for those files
+    pub allocated_bytes: u64,
+    /// Time spent reading, indexing and saving
+    pub duration_ms:     u64,
+    /// `first scan`, `rescan`, `refresh` or `incremental refresh`
+    pub mode:            String,
+}
+
+pub fn history_path(cache_path: &Path) -> PathBuf {
+    cache_path.with_extension("history")
+}
+
+/// Append `record` to the history of the cache at `cache_path`, dropping the oldest records
+/// once there are more than `HISTORY_MAX_RECORDS` (in batches, so most appends are one write and
+/// the file is only read back once its size says it may be over). Waits for the cache lock like
+/// a save; the caller must not hold it.
+pub fn append_history(cache_path: &Path, record: &HistoryRecord) -> Result<()> {
+    let path = history_path(cache_path);
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let _lock = CacheLock::acquire(cache_path, DEFAULT_LOCK_TIMEOUT)?;
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+    // A line cut short by a crash is ended first, so only it is lost
+    if file.metadata()?.len() > 0 {
+        let mut last = [0u8];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            line.insert(0, '\n');
+        }
+    }
+    file.write_all(line.as_bytes())?;
+
+    // Records differ in length by a few digits, so the file only needs counting once it holds
+    // about as many bytes as the records allowed over the limit
+    let limit = HISTORY_MAX_RECORDS + HISTORY_MAX_RECORDS / 10;
+    if file.metadata()?.len() < (limit * line.len()) as u64 {
+        return Ok(());
+    }
+    let records = read_history(cache_path)?;
+    if records.len() > limit {
+        let kept = &records[records.len() - HISTORY_MAX_RECORDS..];
+        let temp_path = path.with_extension("history.tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        for record in kept {
+            serde_json::to_writer(&mut file, record)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)?;
+    }
+    Ok(())
+}
+
+/// The recorded scans of the cache at `cache_path`, oldest first; lines that do not parse
+/// (a write cut short by a crash) are skipped.
+pub fn read_history(cache_path: &Path) -> Result<Vec<HistoryRecord>> {
+    let file = match fs::File::open(history_path(cache_path)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(record) = serde_json::from_str(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn record(time: &str, bytes: u64) -> HistoryRecord {
+        HistoryRecord {
+            time: time.parse().unwrap(),
+            directories: 3,
+            files: 7,
+            bytes,
+            allocated_bytes: bytes,
+            duration_ms: 12,
+            mode: "rescan".to_string(),
+        }
+    }
+
+    #[test]
+    fn history_keeps_the_newest_records_and_skips_torn_lines() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_history_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        assert!(read_history(&cache_path)?.is_empty());
+
+        append_history(&cache_path, &record("2026-10-01T10:00:00Z", 100))?;
+        // A crash mid-append leaves half a line behind
+        OpenOptions::new()
+            .append(true)
+            .open(history_path(&cache_path))?
+            .write_all(b"{\"time\":\"2026-10-0")?;
+        append_history(&cache_path, &record("2026-10-02T10:00:00Z", 150))?;
+        let records = read_history(&cache_path)?;
+        assert_eq!(records.iter().map(|record| record.bytes).collect::<Vec<_>>(), vec![100, 150]);
+
+        // Records of one length, so the size of the file tells when it is over the limit
+        let appended = HISTORY_MAX_RECORDS + HISTORY_MAX_RECORDS / 10;
+        for day in 0..appended {
+            append_history(&cache_path, &record("2026-10-03T10:00:00Z", 1000 + day as u64))?;
+        }
+        let records = read_history(&cache_path)?;
+        assert!((HISTORY_MAX_RECORDS..=appended).contains(&records.len()));
+        assert_ne!(records[0].bytes, 100);
+        assert_eq!(records.last().unwrap().bytes, 1000 + appended as u64 - 1);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn appends_racing_a_compaction_are_all_kept() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_history_race_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        // One short of a compaction, so the appends below race one
+        let mut file = fs::File::create(history_path(&cache_path))?;
+        for _ in 0..HISTORY_MAX_RECORDS + HISTORY_MAX_RECORDS / 10 {
+            serde_json::to_writer(&mut file, &record("2026-10-01T10:00:00Z", 999))?;
+            file.write_all(b"\n")?;
+        }
+        drop(file);
+
+        std::thread::scope(|scope| {
+            for thread in 1..=4u64 {
+                let cache_path = &cache_path;
+                scope.spawn(move || {
+                    for n in 0..10 {
+                        append_history(cache_path, &record("2026-10-02T10:00:00Z", thread * 100 + n)).unwrap();
+                    }
+                });
+            }
+        });
+        let mut appended: Vec<u64> = read_history(&cache_path)?
+            .iter()
+            .map(|record| record.bytes)
+            .filter(|&bytes| bytes != 999)
+            .collect();
+        appended.sort();
+        let expected: Vec<u64> = (1..=4)
+            .flat_map(|thread| (0..10).map(move |n| thread * 100 + n))
+            .collect();
+        assert_eq!(appended, expected);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,626 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::SecondsFormat;
+use ptree_core::JsonSchema;
+use rayon::prelude::*;
+
+use crate::cache::{DirEntry, DiskCache};
+use crate::names::Name;
+
+/// Directories this close to a root serialize their children in parallel, one buffer per
+/// child subtree; deeper levels write straight into their ancestor's buffer. Four levels
+/// give rayon enough independent subtrees to balance even a lopsided tree.
+const PARALLEL_DEPTH: usize = 4;
+
+/// At most this many files' worth of subtrees are buffered at once; a bigger child subtree
+/// is streamed to the writer as it is serialized, so memory stays bounded on a full drive.
+const BUFFERED_SUBTREE_FILES: usize = 50_000;
+
+/// The version v2 output states in its header
+const SCHEMA_VERSION: u32 = 2;
+
+/// What `--format json` includes besides names and paths.
+#[derive(Debug, Clone, Copy)]
+struct JsonOptions {
+    max_depth:       Option<usize>,
+    show_size:       bool,
+    show_file_count: bool,
+    /// Every node carries its type, sizes, modification time, hidden flag and counts
+    complete:        bool,
+}
+
+impl DiskCache {
+    /// Write the `--format json` tree to `writer`.
+    ///
+    /// Independent subtrees are serialized in parallel into byte buffers and stitched
+    /// together in order, so the output is exactly what serde_json's pretty printer makes
+    /// of the tree: keys sorted, two-space indentation. Only small subtrees are buffered;
+    /// the rest goes to `writer` as it is made, so a full drive never sits in memory whole.
+    ///
+    /// Schema v2 puts the tree under a header; v1 is the tree alone (an array of trees for a
+    /// multi-root scan).
+    pub fn write_json_output<W: Write>(
+        &self,
+        writer: &mut W,
+        max_depth: Option<usize>,
+        show_size: bool,
+        show_file_count: bool,
+    ) -> Result<()> {
+        let options = JsonOptions {
+            max_depth,
+            show_size,
+            show_file_count,
+            complete: self.json_schema == JsonSchema::V2,
+        };
+        let roots = self.top_level_roots();
+        if self.json_schema == JsonSchema::V2 {
+            return self.write_json_document(writer, &roots, options);
+        }
+
+        // A multi-root scan renders as an array of sibling root objects
+        if roots.len() == 1 {
+            let root = &roots[0];
+            if self.entries.is_empty() {
+                let empty = serde_json::json!({ "path": root.to_string_lossy(), "children": [] });
+                serde_json::to_writer(&mut *writer, &empty)?;
+                return Ok(());
+            }
+            return self.write_json_node(writer, root, None, 0, 0, options);
+        }
+
+        writer.write_all(b"[")?;
+        for (i, root) in roots.iter().enumerate() {
+            writer.write_all(if i == 0 { b"\n" } else { b",\n" })?;
+            indent(writer, 1)?;
+            self.write_json_node(writer, root, None, 0, 1, options)?;
+        }
+        writer.write_all(if roots.is_empty() { b"]" } else { b"\n]" })?;
+        Ok(())
+    }
+
+    /// Write the JSON object of the cached directory at `path` alone, as `--format json` would
+    /// render it if `path` were the root (`ptree client tree`), its nodes complete under v2.
+    pub fn write_json_subtree<W: Write>(
+        &self,
+        writer: &mut W,
+        path: &Path,
+        max_depth: Option<usize>,
+        show_size: bool,
+        show_file_count: bool,
+        schema: JsonSchema,
+    ) -> Result<()> {
+        if !self.get_entry(path).is_some_and(|entry| entry.is_dir) {
+            anyhow::bail!("{} is not a cached directory", path.display());
+        }
+        let options = JsonOptions {
+            max_depth,
+            show_size,
+            show_file_count,
+            complete: schema == JsonSchema::V2,
+        };
+        self.write_json_node(writer, path, None, 0, 0, options)
+    }
+
+    /// The v2 document: `ptree_version`, `root` (the scan's first root, whatever `roots` are
+    /// shown), `roots` (the tree of each root), `scanned_at` and `schema_version`, in key order
+    /// like the nodes.
+    fn write_json_document<W: Write>(
+        &self,
+        out: &mut W,
+        roots: &[std::path::PathBuf],
+        options: JsonOptions,
+    ) -> Result<()> {
+        let shown = |path: &Path| {
+            let shown = self.path_display.shown(path);
+            shown.as_deref().unwrap_or(path).to_string_lossy().into_owned()
+        };
+        write!(out, "{{\n  \"ptree_version\": \"{}\",\n  \"root\": ", env!("CARGO_PKG_VERSION"))?;
+        serde_json::to_writer(&mut *out, &shown(&self.root))?;
+        out.write_all(b",\n  \"roots\": [")?;
+        // An empty cache has no tree to show for its root
+        let roots = if self.entries.is_empty() { &[][..] } else { roots };
+        for (i, root) in roots.iter().enumerate() {
+            separator(out, i, 1)?;
+            self.write_json_node(out, root, None, 0, 2, options)?;
+        }
+        out.write_all(if roots.is_empty() { b"]" } else { b"\n  ]" })?;
+        out.write_all(b",\n  \"scanned_at\": ")?;
+        serde_json::to_writer(&mut *out, &self.last_scan.to_rfc3339())?;
+        write!(out, ",\n  \"schema_version\": {}\n}}", SCHEMA_VERSION)?;
+        Ok(())
+    }
+
+    /// One directory (or file) object at `level` of indentation; `depth` counts from the
+    /// root for `--max-depth`. Roots have no `name`. Keys that do not apply are left out,
+    /// unless the options ask for complete nodes, where they are `null`.
+    fn write_json_node<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        path: &Path,
+        name: Option<&str>,
+        depth: usize,
+        level: usize,
+        options: JsonOptions,
+    ) -> Result<()> {
+        let entry = self.get_entry(path);
+        let complete = options.complete;
+
+        out.write_all(b"{\n")?;
+        if let Some(entry) = entry.filter(|_| complete) {
+            indent(out, level + 1)?;
+            writeln!(out, "\"child_count\": {},", entry.children.len())?;
+        }
+        indent(out, level + 1)?;
+        out.write_all(b"\"children\": ")?;
+        let more = self.write_json_children(out, path, entry, depth, level + 1, options)?;
+
+        let field = |out: &mut W, key: &str| -> Result<()> {
+            out.write_all(b",\n")?;
+            indent(out, level + 1)?;
+            write!(out, "\"{}\": ", key)?;
+            Ok(())
+        };
+        if let Some(error) = self.scan_errors.get(path) {
+            field(out, "error")?;
+            serde_json::to_writer(&mut *out, error)?;
+        }
+        if let Some(entry) = entry {
+            if options.show_file_count || complete {
+                field(out, "file_count")?;
+                write!(out, "{}", entry.file_count)?;
+            }
+        }
+        if complete {
+            field(out, "hidden")?;
+            write!(out, "{}", self.is_hidden(path))?;
+        }
+        if let Some(entry) = entry {
+            if entry.id != 0 {
+                field(out, "id")?;
+                write!(out, "{}", entry.id)?;
+            }
+        }
+        let recorded = self.metadata_of(path);
+        let metadata = recorded.filter(|_| self.long_listing);
+        if let Some(metadata) = metadata {
+            field(out, "mode")?;
+            serde_json::to_writer(&mut *out, &crate::long_listing::mode_string(metadata.mode))?;
+        }
+        let modified = if complete {
+            recorded
+                .and_then(|metadata| metadata.modified)
+                .or(entry.map(|entry| entry.modified))
+        } else {
+            metadata.and_then(|metadata| metadata.modified)
+        };
+        if complete || modified.is_some() {
+            // In v2 to the microsecond the snapshot keeps, so a cache hit prints what the scan did
+            let modified = modified.map(|modified| {
+                if complete {
+                    modified.to_rfc3339_opts(SecondsFormat::Micros, false)
+                } else {
+                    modified.to_rfc3339()
+                }
+            });
+            field(out, "modified")?;
+            serde_json::to_writer(&mut *out, &modified)?;
+        }
+        if more > 0 {
+            field(out, "more_children")?;
+            write!(out, "{}", more)?;
+        }
+        if self.mount_points.contains(path) {
+            field(out, "mount_point")?;
+            out.write_all(b"true")?;
+        }
+        if let Some(name) = name {
+            field(out, "name")?;
+            serde_json::to_writer(&mut *out, name)?;
+            if self.new_dirs.contains_key(path) {
+                field(out, "new")?;
+                out.write_all(b"true")?;
+            }
+        }
+        if let Some(owner) = metadata.and_then(|metadata| self.owner_of(metadata)) {
+            field(out, "owner")?;
+            serde_json::to_writer(&mut *out, &owner)?;
+        }
+        field(out, "path")?;
+        let shown = self.path_display.shown(path);
+        serde_json::to_writer(&mut *out, &shown.as_deref().unwrap_or(path).to_string_lossy())?;
+        if let Some(entry) = entry {
+            if options.show_size || complete {
+                field(out, "size_bytes")?;
+                write!(out, "{}", entry.total_size)?;
+                field(out, "size_on_disk_bytes")?;
+                write!(out, "{}", entry.allocated_size)?;
+            }
+        } else if let Some(metadata) = if complete { recorded } else { metadata } {
+            // Files have a size only in the long listing, or whenever it was recorded in v2
+            field(out, "size_bytes")?;
+            write!(out, "{}", metadata.size)?;
+            field(out, "size_on_disk_bytes")?;
+            write!(out, "{}", metadata.allocated)?;
+        } else if complete {
+            field(out, "size_bytes")?;
+            out.write_all(b"null")?;
+            field(out, "size_on_disk_bytes")?;
+            out.write_all(b"null")?;
+        }
+        if name.is_some() && self.is_slow(path) {
+            field(out, "slow")?;
+            out.write_all(b"true")?;
+        }
+        if let Some(streams) = self.alt_streams.get(path) {
+            field(out, "streams")?;
+            write_value(out, streams, level + 1, complete)?;
+        }
+        if let Some(subvolume) = self.subvolumes.get(path) {
+            field(out, "subvolume")?;
+            write_value(out, subvolume, level + 1, complete)?;
+        }
+        if let Some(symlink) = self.symlinks.get(path) {
+            field(out, "symlink")?;
+            write_value(out, symlink, level + 1, complete)?;
+        }
+        let xattrs = self.xattrs.get(path);
+        if let Some(xattrs) = xattrs.filter(|_| !complete) {
+            // v1 has them here, out of key order
+            field(out, "xattrs")?;
+            serde_json::to_writer(&mut *out, xattrs)?;
+        }
+        if complete {
+            field(out, "type")?;
+            serde_json::to_writer(&mut *out, self.entry_type(path))?;
+        }
+        if name.is_some() && self.unsettled_dirs.contains(path) {
+            field(out, "unsettled")?;
+            out.write_all(b"true")?;
+        }
+        if let Some(verified) = entry.and_then(|entry| self.verified_at(entry)) {
+            field(out, "verified_at")?;
+            serde_json::to_writer(&mut *out, &verified.to_rfc3339())?;
+        }
+        if let Some(xattrs) = xattrs.filter(|_| complete) {
+            field(out, "xattrs")?;
+            write_value(out, xattrs, level + 1, complete)?;
+        }
+
+        out.write_all(b"\n")?;
+        indent(out, level)?;
+        out.write_all(b"}")?;
+        Ok(())
+    }
+
+    /// `directory`, `file` or `symlink`: the `type` of the entry at `path` in v2 nodes.
+    fn entry_type(&self, path: &Path) -> &'static str {
+        if self.symlinks.contains_key(path) {
+            "symlink"
+        } else if self.entries.contains_key(path)
+            || self.mount_points.contains(path)
+            || self.scan_errors.contains_key(path)
+        {
+            "directory"
+        } else {
+            "file"
+        }
+    }
+
+    /// The `children` array of `path`, in `--sort` order; `level` is that of the key. Returns
+    /// how many children `--limit` left out.
+    fn write_json_children<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        path: &Path,
+        entry: Option<&DirEntry>,
+        depth: usize,
+        level: usize,
+        options: JsonOptions,
+    ) -> Result<usize> {
+        let within_depth = options.max_depth.is_none_or(|max| depth < max);
+        let (names, more) = match entry {
+            Some(entry) if within_depth => self.limited_children(path, entry),
+            _ => (Vec::new(), 0),
+        };
+        if names.is_empty() {
+            out.write_all(b"[]")?;
+            return Ok(more);
+        }
+
+        out.write_all(b"[")?;
+        if depth < PARALLEL_DEPTH && names.len() > 1 {
+            // Runs of small subtrees are buffered in parallel; a big one streams on its own
+            let mut written = 0;
+            let mut run = Vec::new();
+            let mut run_files = 0;
+            for name in names {
+                let files = self.get_entry(&path.join(name)).map_or(0, |entry| entry.file_count);
+                if files > BUFFERED_SUBTREE_FILES || run_files + files > BUFFERED_SUBTREE_FILES {
+                    written = self.write_json_run(out, path, &run, written, depth, level, options)?;
+                    run.clear();
+                    run_files = 0;
+                }
+                if files > BUFFERED_SUBTREE_FILES {
+                    separator(out, written, level)?;
+                    self.write_json_child(out, path, name, depth, level, options)?;
+                    written += 1;
+                } else {
+                    run.push(name);
+                    run_files += files;
+                }
+            }
+            self.write_json_run(out, path, &run, written, depth, level, options)?;
+        } else {
+            for (i, name) in names.into_iter().enumerate() {
+                separator(out, i, level)?;
+                self.write_json_child(out, path, name, depth, level, options)?;
+            }
+        }
+        out.write_all(b"\n")?;
+        indent(out, level)?;
+        out.write_all(b"]")?;
+        Ok(more)
+    }
+
+    /// Serialize the children `names` of `path` in parallel and write them in order after
+    /// the `written` ones before them; returns how many are written now.
+    #[allow(clippy::too_many_arguments)]
+    fn write_json_run<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        path: &Path,
+        names: &[&Name],
+        written: usize,
+        depth: usize,
+        level: usize,
+        options: JsonOptions,
+    ) -> Result<usize> {
+        let parts: Vec<Vec<u8>> = names
+            .par_iter()
+            .map(|name| {
+                let mut part = Vec::new();
+                self.write_json_child(&mut part, path, name, depth, level, options)?;
+                Ok(part)
+            })
+            .collect::<Result<_>>()?;
+        for (i, part) in parts.iter().enumerate() {
+            separator(out, written + i, level)?;
+            out.write_all(part)?;
+        }
+        Ok(written + parts.len())
+    }
+
+    /// The object of the child `name` of `path`, inside a `children` array at `level`.
+    fn write_json_child<W: Write + ?Sized>(
+        &self,
+        out: &mut W,
+        path: &Path,
+        name: &Name,
+        depth: usize,
+        level: usize,
+        options: JsonOptions,
+    ) -> Result<()> {
+        self.write_json_node(out, &path.join(name), Some(name.as_str()), depth + 1, level + 1, options)
+    }
+}
+
+/// What goes before the `i`th element of a `children` array whose key is at `level`.
+fn separator<W: Write + ?Sized>(out: &mut W, i: usize, level: usize) -> std::io::Result<()> {
+    out.write_all(if i == 0 { b"\n" } else { b",\n" })?;
+    indent(out, level + 1)
+}
+
+/// A value whose key is at `level`: on one line in v1, pretty-printed with its keys sorted
+/// like the rest in v2 (`complete`).
+fn write_value<W: Write + ?Sized, T: serde::Serialize>(
+    out: &mut W,
+    value: &T,
+    level: usize,
+    complete: bool,
+) -> Result<()> {
+    if !complete {
+        serde_json::to_writer(&mut *out, value)?;
+        return Ok(());
+    }
+    let pretty = serde_json::to_string_pretty(&serde_json::to_value(value)?)?;
+    for (i, line) in pretty.lines().enumerate() {
+        if i > 0 {
+            out.write_all(b"\n")?;
+            indent(out, level)?;
+        }
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn indent<W: Write + ?Sized>(out: &mut W, level: usize) -> std::io::Result<()> {
+    for _ in 0..level {
+        out.write_all(b"  ")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::Utc;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::cache_provenance::ScanRecord;
+    use crate::testing::dir;
+
+    /// `fanout` subdirectories per level, `levels` deep, plus one file per directory.
+    fn wide_cache(root: &Path, fanout: usize, levels: usize) -> DiskCache {
+        let mut cache = DiskCache::new_empty();
+        cache.root = root.to_path_buf();
+        cache.scans.insert(
+            1,
+            ScanRecord {
+                finished: Utc::now(),
+                entries:  0,
+            },
+        );
+        let mut pending = vec![(root.to_path_buf(), 0)];
+        let mut next_id = 1;
+        while let Some((path, level)) = pending.pop() {
+            let mut children = vec!["file \"quoted\".txt".to_string()];
+            if level < levels {
+                for i in 0..fanout {
+                    let name = format!("dir-{i}");
+                    pending.push((path.join(&name), level + 1));
+                    children.push(name);
+                }
+            }
+            let entry = DirEntry {
+                id: next_id,
+                scan: 1,
+                ..dir(&path, next_id * 100, &children.iter().map(String::as_str).collect::<Vec<_>>())
+            };
+            cache.entries.insert(path.clone(), entry);
+            next_id += 1;
+        }
+        cache
+    }
+
+    fn render(cache: &DiskCache, max_depth: Option<usize>) -> String {
+        let mut out = Vec::new();
+        cache.write_json_output(&mut out, max_depth, true, true).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parallel_output_matches_serde_pretty_printing() {
+        let root = PathBuf::from("/data");
+        let mut cache = wide_cache(&root, 3, 6);
+        cache.unsettled_dirs.insert(root.join("dir-1"));
+
+        let json = render(&cache, None);
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json, serde_json::to_string_pretty(&value).unwrap());
+        assert_eq!(value["children"].as_array().unwrap().len(), 4);
+        assert_eq!(value["children"][1]["unsettled"], Value::Bool(true));
+        assert_eq!(value["children"][0]["name"], "dir-0");
+
+        // 1 + 3 + ... + 3^6 directories, each with its file
+        assert_eq!(json.matches("\"file \\\"quoted\\\".txt\"").count(), 1093);
+
+        // Same bytes run after run, whatever order the subtrees finished in
+        assert_eq!(json, render(&cache, None));
+        let shallow: Value = serde_json::from_str(&render(&cache, Some(1))).unwrap();
+        assert_eq!(shallow["children"][0]["children"], Value::Array(Vec::new()));
+    }
+
+    #[test]
+    fn big_subtrees_stream_between_buffered_ones() {
+        let without_counts = |cache: &DiskCache| {
+            let mut out = Vec::new();
+            cache.write_json_output(&mut out, None, true, false).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let root = PathBuf::from("/data");
+        let mut cache = wide_cache(&root, 4, 3);
+        let expected = without_counts(&cache);
+        // dir-1 and dir-2 are too big to buffer; dir-0 and dir-3 are buffered around them
+        for big in ["dir-1", "dir-2", "dir-2/dir-0"] {
+            cache.entries.get_mut(&root.join(big)).unwrap().file_count = BUFFERED_SUBTREE_FILES + 1;
+        }
+
+        assert_eq!(without_counts(&cache), expected);
+    }
+
+    #[test]
+    fn subtree_renders_one_cached_directory_as_its_root() {
+        let root = PathBuf::from("/data");
+        let cache = wide_cache(&root, 2, 3);
+
+        let mut out = Vec::new();
+        cache
+            .write_json_subtree(&mut out, &root.join("dir-1"), Some(1), true, false, JsonSchema::V1)
+            .unwrap();
+        let value: Value = serde_json::from_str(std::str::from_utf8(&out).unwrap()).unwrap();
+        assert_eq!(value["path"], "/data/dir-1");
+        assert!(value.get("name").is_none());
+        assert_eq!(value["children"].as_array().unwrap().len(), 3);
+        assert_eq!(value["children"][0]["children"], Value::Array(Vec::new()));
+
+        let missing = cache.write_json_subtree(&mut Vec::new(), &root.join("nope"), None, false, false, JsonSchema::V1);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn several_roots_render_as_an_array() {
+        let mut cache = wide_cache(Path::new("/a"), 2, 1);
+        let other = wide_cache(Path::new("/b"), 2, 1);
+        cache.entries.extend(other.entries);
+        cache.roots = vec![PathBuf::from("/a"), PathBuf::from("/b")];
+
+        let json = render(&cache, None);
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json, serde_json::to_string_pretty(&value).unwrap());
+        assert_eq!(value[1]["path"], "/b");
+    }
+
+    #[test]
+    fn schema_v2_wraps_complete_nodes_in_a_header() {
+        let root = PathBuf::from("/data");
+        let mut cache = wide_cache(&root, 3, 2);
+        cache.json_schema = JsonSchema::V2;
+        cache.symlinks.insert(
+            root.join("latest"),
+            crate::cache::Symlink {
+                target: root.join("dir-2"),
+                status: crate::cache::SymlinkStatus::InTree,
+            },
+        );
+        cache.entries.get_mut(&root).unwrap().children.push(Name::new("latest"));
+        cache.entries.get_mut(&root.join("dir-1")).unwrap().is_hidden = true;
+
+        let mut out = Vec::new();
+        cache.write_json_output(&mut out, None, false, false).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json, serde_json::to_string_pretty(&value).unwrap());
+        assert_eq!(value["schema_version"], 2);
+        assert_eq!(value["ptree_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(value["root"], "/data");
+        assert_eq!(value["scanned_at"], cache.last_scan.to_rfc3339());
+
+        // Sizes, counts and times are there without --size or --file-count
+        let tree = &value["roots"][0];
+        assert_eq!((tree["type"].as_str(), tree["child_count"].as_u64()), (Some("directory"), Some(5)));
+        assert_eq!(tree["size_bytes"], 100);
+        assert_eq!(tree["file_count"], 4);
+        assert_eq!(tree["hidden"], false);
+        assert!(tree["modified"].is_string());
+        let child = |name: &str| {
+            tree["children"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|child| child["name"] == name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(child("dir-1")["hidden"], true);
+        // Files without recorded metadata say so with nulls
+        let file = child("file \"quoted\".txt");
+        assert_eq!(file["type"], "file");
+        assert_eq!((file["size_bytes"].is_null(), file["modified"].is_null()), (true, true));
+        assert!(file.get("child_count").is_none());
+        let latest = child("latest");
+        assert_eq!(
+            (latest["type"].as_str(), latest["symlink"]["target"].as_str()),
+            (Some("symlink"), Some("/data/dir-2"))
+        );
+
+        // v1 stays the bare tree
+        cache.json_schema = JsonSchema::V1;
+        let v1: Value = serde_json::from_str(&render(&cache, None)).unwrap();
+        assert_eq!(v1["path"], "/data");
+        assert!(v1.get("type").is_none());
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ptree_core::PTreeError;
+
+/// How long a save waits for another ptree process to finish its own (`--lock-timeout`).
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Advisory lock on one cache snapshot (`ptree-<hash>.lock` next to its `.idx`), held while
+/// the index and shards are rewritten so two processes never interleave a save.
+///
+/// The OS drops the lock when the file handle closes, so a crashed process never leaves it held.
+#[derive(Debug)]
+pub struct CacheLock {
+    _file: File,
+}
+
+impl CacheLock {
+    pub fn path_for(cache_path: &Path) -> PathBuf {
+        cache_path.with_extension("lock")
+    }
+
+    /// Wait up to `timeout` for the lock; fails with `PTreeError::LockTimeout` if it stays taken.
+    pub fn acquire(cache_path: &Path, timeout: Duration) -> Result<Self> {
+        let lock_path = Self::path_for(cache_path);
+        if let Some(parent) = lock_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(CacheLock { _file: file }),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+                Err(TryLockError::WouldBlock) => {
+                    return Err(PTreeError::LockTimeout(format!(
+                        "another ptree process is writing {} (waited {:.1} s)",
+                        cache_path.display(),
+                        timeout.as_secs_f64()
+                    ))
+                    .into())
+                }
+                Err(TryLockError::Error(err)) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Take the lock only if it is free right now.
+    pub fn try_acquire(cache_path: &Path) -> Option<Self> {
+        Self::acquire(cache_path, Duration::ZERO).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_holder_times_out_until_first_releases() {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_cache_lock_{}", std::process::id()));
+        let cache_path = temp_dir.join("ptree.dat");
+
+        let held = CacheLock::acquire(&cache_path, Duration::ZERO).unwrap();
+        let err = CacheLock::acquire(&cache_path, Duration::from_millis(120)).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PTreeError>(), Some(PTreeError::LockTimeout(_))));
+        assert!(CacheLock::try_acquire(&cache_path).is_none());
+
+        drop(held);
+        assert!(CacheLock::try_acquire(&cache_path).is_some());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+}
@@ -0,0 +1,422 @@
+//! Moving caches written by earlier versions to where this one looks for them, so an upgrade
+//! starts from the existing snapshot instead of an empty cache and a full rescan.
+//!
+//! The one layout change so far is per-root caches: versions before it kept a single
+//! `ptree.idx` (plus its `ptree-d<N>.dat` shards and `ptree-names.dat`) for whatever was
+//! scanned last, where the cache of a set of roots is now `ptree-<hash>.idx`. A snapshot this
+//! version reads is migrated by renaming its files; the index and shards keep their generation,
+//! so nothing is rewritten. One in the original format (see `baseline`) is read and saved again
+//! as a snapshot of this version.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::cache::{cache_path_for_roots, is_snapshot_file, DiskCache};
+use crate::cache_lock::CacheLock;
+use crate::cache_rkyv::is_other_version;
+
+/// Stem of the cache written before caches were keyed by scan root
+const LEGACY_STEM: &str = "ptree";
+
+/// What happened to one cache written by an earlier version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationOutcome {
+    /// Renamed to the cache path of its roots
+    Moved { to: PathBuf },
+    /// Read in the original format and saved as a cache of this version at the path of its roots
+    Converted { to: PathBuf },
+    /// Removed: this version already has a cache of the same roots
+    Superseded { by: PathBuf },
+    /// Removed: its index is of another format version, which this one cannot read, so the
+    /// next scan rebuilds it
+    Unreadable,
+}
+
+/// One cache of an earlier version and what was done with it.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub from:    PathBuf,
+    pub roots:   Vec<PathBuf>,
+    pub outcome: MigrationOutcome,
+}
+
+impl Migration {
+    /// One line for the migration report
+    pub fn describe(&self) -> String {
+        let roots: Vec<String> = self.roots.iter().map(|root| root.display().to_string()).collect();
+        let name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        match &self.outcome {
+            MigrationOutcome::Moved { to } => {
+                format!("moved the cache of {} from {} to {}", roots.join(", "), name(&self.from), name(to))
+            }
+            MigrationOutcome::Converted { to } => {
+                format!(
+                    "converted the cache of {} from {} (an earlier format) to {}",
+                    roots.join(", "),
+                    name(&self.from),
+                    name(to)
+                )
+            }
+            MigrationOutcome::Superseded { by } => {
+                format!("removed the old cache of {}, which already has its own ({})", roots.join(", "), name(by))
+            }
+            MigrationOutcome::Unreadable => {
+                format!(
+                    "removed {}, written in a format this version cannot read; the next scan rebuilds it",
+                    name(&self.from)
+                )
+            }
+        }
+    }
+}
+
+/// Migrate every cache in `cache_dir` written by an earlier version. A cache another process
+/// is saving is left for a later run, and one that cannot be read (an I/O error, damage, a
+/// format not known to be another version's) is left in place with an error saying why.
+pub fn migrate_caches(cache_dir: &Path) -> Result<Vec<Migration>> {
+    let legacy = cache_dir.join(format!("{LEGACY_STEM}.dat"));
+    if !legacy.with_extension("idx").exists() {
+        return Ok(Vec::new());
+    }
+    let Some(lock) = CacheLock::try_acquire(&legacy) else {
+        return Ok(Vec::new());
+    };
+
+    let migration = migrate_legacy(&legacy);
+    drop(lock);
+    let _ = fs::remove_file(CacheLock::path_for(&legacy));
+    Ok(vec![migration?])
+}
+
+/// Migrate the legacy cache at `legacy`, whose lock is held.
+fn migrate_legacy(legacy: &Path) -> Result<Migration> {
+    let index_path = legacy.with_extension("idx");
+    let migration = match DiskCache::load_from_lazy_cache(&index_path, legacy) {
+        Ok(cache) => {
+            let roots = cache.top_level_roots();
+            let target = cache_path_for_roots(legacy, &roots);
+            let outcome = if target.with_extension("idx").exists() {
+                remove_snapshot(legacy)?;
+                MigrationOutcome::Superseded { by: target }
+            } else {
+                move_snapshot(legacy, &target)?;
+                MigrationOutcome::Moved { to: target }
+            };
+            Migration {
+                from: legacy.to_path_buf(),
+                roots,
+                outcome,
+            }
+        }
+        Err(load_err) => {
+            match baseline::read(legacy) {
+                Ok(mut cache) => {
+                    let roots = cache.top_level_roots();
+                    let target = cache_path_for_roots(legacy, &roots);
+                    let outcome = if target.with_extension("idx").exists() {
+                        MigrationOutcome::Superseded { by: target }
+                    } else {
+                        cache.save(&target)?;
+                        MigrationOutcome::Converted { to: target }
+                    };
+                    remove_snapshot(legacy)?;
+                    Migration {
+                        from: legacy.to_path_buf(),
+                        roots,
+                        outcome,
+                    }
+                }
+                Err(_) if index_of_other_version(&index_path) => {
+                    remove_snapshot(legacy)?;
+                    Migration {
+                        from:    legacy.to_path_buf(),
+                        roots:   Vec::new(),
+                        outcome: MigrationOutcome::Unreadable,
+                    }
+                }
+                // Not known to be foreign: a read error or damage must not cost the snapshot
+                Err(baseline_err) => {
+                    anyhow::bail!(
+                        "left {} in place, as it could not be read: {load_err:#} (as the original format: \
+                         {baseline_err:#})",
+                        index_path.display()
+                    )
+                }
+            }
+        }
+    };
+    Ok(migration)
+}
+
+/// Whether the index at `index_path` is positively one of another format version (see
+/// `is_other_version`); false when it cannot be read.
+fn index_of_other_version(index_path: &Path) -> bool {
+    let mut header = [0; 4];
+    File::open(index_path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| is_other_version(&header))
+}
+
+/// The files of the snapshot at `cache_path`: its index, shards, name table, render hint and
+/// scan history.
+fn snapshot_files(cache_path: &Path) -> Result<Vec<PathBuf>> {
+    let parent = cache_path.parent().unwrap_or_else(|| Path::new("."));
+    let stem = cache_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(LEGACY_STEM);
+    let mut files = Vec::new();
+    for entry in fs::read_dir(parent)? {
+        let entry = entry?;
+        if is_snapshot_file(&entry.file_name().to_string_lossy(), stem, false) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    // The index goes last, so an interrupted move leaves no index pointing at missing shards
+    files.extend(
+        [
+            cache_path.with_extension("hint"),
+            cache_path.with_extension("history"),
+            cache_path.with_extension("idx"),
+        ]
+        .into_iter()
+        .filter(|path| path.exists()),
+    );
+    Ok(files)
+}
+
+fn move_snapshot(from: &Path, to: &Path) -> Result<()> {
+    let from_stem = from.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let to_stem = to.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    for file in snapshot_files(from)? {
+        let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let renamed = file.with_file_name(format!("{to_stem}{}", &name[from_stem.len()..]));
+        fs::rename(&file, &renamed)
+            .with_context(|| format!("could not move {} to {}", file.display(), renamed.display()))?;
+    }
+    Ok(())
+}
+
+fn remove_snapshot(cache_path: &Path) -> Result<()> {
+    for file in snapshot_files(cache_path)? {
+        fs::remove_file(&file).with_context(|| format!("could not remove {}", file.display()))?;
+    }
+    Ok(())
+}
+
+/// The original cache format, before this series of layout changes: a bincode index with
+/// a full path per record, and `ptree-d<N>.dat` shards of `[len: u32 LE][bincode record]`.
+mod baseline {
+    use std::collections::hash_map::Entry;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{Context, Result};
+    use bincode::Options;
+    use chrono::{DateTime, Utc};
+    use serde::Deserialize;
+
+    use crate::cache::{DirEntry, DiskCache, EntryIds};
+    use crate::names::Name;
+
+    #[derive(Deserialize)]
+    struct Index {
+        offsets:           HashMap<PathBuf, (u32, u64)>,
+        _total_files:      usize,
+        last_scan:         DateTime<Utc>,
+        root:              PathBuf,
+        last_scanned_root: PathBuf,
+        // Windows builds had a `usn_state` here too: a unit struct, so no bytes
+        skip_stats:        HashMap<String, usize>,
+    }
+
+    #[derive(Deserialize)]
+    struct Record {
+        path:         PathBuf,
+        name:         String,
+        modified:     DateTime<Utc>,
+        content_hash: u64,
+        file_count:   usize,
+        total_size:   u64,
+        children:     Vec<String>,
+        is_hidden:    bool,
+        is_dir:       bool,
+    }
+
+    /// Every entry of the snapshot at `cache_path` in the original format. The index has to
+    /// account for all of its bytes, so a snapshot of some other format is not mistaken for one.
+    pub(super) fn read(cache_path: &Path) -> Result<DiskCache> {
+        let data = fs::read(cache_path.with_extension("idx"))?;
+        let index: Index = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(data.len() as u64)
+            .reject_trailing_bytes()
+            .deserialize(&data)?;
+
+        let mut cache = DiskCache::new_empty();
+        cache.root = index.root;
+        cache.last_scanned_root = index.last_scanned_root;
+        cache.last_scan = index.last_scan;
+        cache.skip_stats = index.skip_stats;
+
+        let mut shards = HashMap::new();
+        for (path, (depth, offset)) in index.offsets {
+            let shard = match shards.entry(depth) {
+                Entry::Occupied(shard) => shard.into_mut(),
+                Entry::Vacant(slot) => {
+                    let shard = sibling(cache_path, &format!("-d{depth}.dat"));
+                    slot.insert(fs::read(&shard).with_context(|| format!("missing {}", shard.display()))?)
+                }
+            };
+            let start = usize::try_from(offset)?;
+            let len = shard
+                .get(start..start.saturating_add(4))
+                .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+                .with_context(|| format!("record of {} out of bounds", path.display()))?;
+            let payload = shard
+                .get(start + 4..start + 4 + len)
+                .with_context(|| format!("truncated record of {}", path.display()))?;
+            let record: Record = bincode::deserialize(payload)?;
+
+            cache.entries.insert(
+                path,
+                DirEntry {
+                    path:           record.path,
+                    name:           Name::new(&record.name),
+                    modified:       record.modified,
+                    content_hash:   record.content_hash,
+                    file_count:     record.file_count,
+                    total_size:     record.total_size,
+                    // The original format did not track allocation
+                    allocated_size: record.total_size,
+                    children:       record.children.iter().map(|child| Name::new(child)).collect(),
+                    is_hidden:      record.is_hidden,
+                    is_dir:         record.is_dir,
+                    id:             0,
+                    file_id:        0,
+                    scan:           0,
+                },
+            );
+        }
+        cache.assign_entry_ids(&EntryIds::default());
+        Ok(cache)
+    }
+
+    /// `cache_path` with its extension replaced by `suffix` (`ptree.dat` → `ptree-d3.dat`).
+    fn sibling(cache_path: &Path, suffix: &str) -> PathBuf {
+        let stem = cache_path.file_stem().unwrap_or_default().to_string_lossy();
+        cache_path.with_file_name(format!("{stem}{suffix}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::DirEntry;
+    use crate::names::Name;
+    use crate::testing;
+
+    fn save_cache(cache_path: &Path, root: &Path) -> Result<()> {
+        let mut cache = DiskCache {
+            root: root.to_path_buf(),
+            ..Default::default()
+        };
+        cache.entries.insert(
+            root.to_path_buf(),
+            DirEntry {
+                name: Name::new("root"),
+                ..testing::dir(root, 64, &["notes.txt"])
+            },
+        );
+        cache.save(cache_path)
+    }
+
+    #[test]
+    fn legacy_cache_moves_to_the_path_of_its_roots() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_migrate_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let cache_dir = temp_dir.join("cache");
+        let root = temp_dir.join("root");
+        fs::create_dir_all(&root)?;
+
+        let legacy = cache_dir.join("ptree.dat");
+        save_cache(&legacy, &root)?;
+        let migrations = migrate_caches(&cache_dir)?;
+        let target = cache_path_for_roots(&legacy, std::slice::from_ref(&root));
+        assert_eq!(migrations.len(), 1);
+        assert_eq!(migrations[0].outcome, MigrationOutcome::Moved { to: target.clone() });
+        assert!(!legacy.with_extension("idx").exists());
+        assert!(DiskCache::open(&target)?.has_persisted_snapshot);
+        assert!(snapshot_files(&legacy)?.is_empty());
+
+        // Nothing left to do on the next run
+        assert!(migrate_caches(&cache_dir)?.is_empty());
+
+        // A legacy cache of roots that already have their own cache is dropped
+        save_cache(&legacy, &root)?;
+        let migrations = migrate_caches(&cache_dir)?;
+        assert_eq!(migrations[0].outcome, MigrationOutcome::Superseded { by: target.clone() });
+        assert!(!legacy.with_extension("idx").exists());
+        assert!(target.with_extension("idx").exists());
+
+        // So is one of another format version
+        fs::write(legacy.with_extension("idx"), b"PTI\x01 an index of version 1")?;
+        assert_eq!(migrate_caches(&cache_dir)?[0].outcome, MigrationOutcome::Unreadable);
+        assert!(!legacy.with_extension("idx").exists());
+
+        // One that is not known to be another version's is left where it is, with the reason
+        fs::write(legacy.with_extension("idx"), b"not an index")?;
+        let err = migrate_caches(&cache_dir).unwrap_err();
+        assert!(format!("{err:#}").contains("left"));
+        assert!(legacy.with_extension("idx").exists());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+    #[test]
+    fn cache_of_the_original_format_is_converted() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_migrate_baseline_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let cache_dir = temp_dir.join("cache");
+        fs::create_dir_all(&cache_dir)?;
+
+        // Written by the original `DiskCache::save` (tests/fixtures/baseline/README.md)
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/baseline");
+        for name in ["ptree.idx", "ptree-d3.dat", "ptree-d4.dat", "ptree-d5.dat"] {
+            fs::copy(fixtures.join(name), cache_dir.join(name))?;
+        }
+
+        let legacy = cache_dir.join("ptree.dat");
+        let root = PathBuf::from("/srv/projects");
+        let target = cache_path_for_roots(&legacy, std::slice::from_ref(&root));
+
+        // A shard it cannot read keeps the snapshot from being converted, not from being kept
+        let shard = cache_dir.join("ptree-d4.dat");
+        fs::rename(&shard, cache_dir.join("elsewhere.dat"))?;
+        assert!(migrate_caches(&cache_dir).is_err());
+        assert!(legacy.with_extension("idx").exists());
+        fs::rename(cache_dir.join("elsewhere.dat"), &shard)?;
+
+        let migrations = migrate_caches(&cache_dir)?;
+        assert_eq!(migrations[0].outcome, MigrationOutcome::Converted { to: target.clone() });
+        assert_eq!(migrations[0].roots, vec![root.clone()]);
+        assert!(snapshot_files(&legacy)?.is_empty());
+
+        let mut cache = DiskCache::open(&target)?;
+        assert_eq!(cache.root, root);
+        cache.load_all_entries_lazy(&target)?;
+        assert_eq!(cache.entries.len(), 3);
+        let ptree = &cache.entries[&root.join("ptree")];
+        assert_eq!(ptree.children, vec![Name::new("src"), Name::new("Cargo.toml")]);
+        assert_eq!((ptree.file_count, ptree.total_size), (2, 3072));
+        assert_ne!(ptree.id, 0);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
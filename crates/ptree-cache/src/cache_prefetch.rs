@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{DirEntry, DiskCache};
+use crate::cache_rkyv::RkyvMmapCache;
+
+/// Hints larger than this are cut off; such renders are disk-bound anyway.
+pub const PREFETCH_MAX_PATHS: usize = 200_000;
+
+/// Directories the last render of a snapshot showed (`ptree-<hash>.hint` next to its `.idx`).
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct PrefetchHint {
+    paths: Vec<PathBuf>,
+}
+
+fn hint_path(cache_path: &Path) -> PathBuf {
+    cache_path.with_extension("hint")
+}
+
+fn read_hint(cache_path: &Path) -> Option<PrefetchHint> {
+    bincode::deserialize(&fs::read(hint_path(cache_path)).ok()?).ok()
+}
+
+/// Entries of the last render, loaded from the snapshot on a background thread while the
+/// main thread opens the cache and decides whether it is fresh.
+pub struct Prefetch {
+    handle: JoinHandle<Option<(u64, HashMap<PathBuf, DirEntry>)>>,
+}
+
+impl Prefetch {
+    /// Start loading the hinted entries; `None` when there is no hint or no snapshot.
+    pub fn start(cache_path: &Path) -> Option<Self> {
+        let index_path = cache_path.with_extension("idx");
+        if !index_path.exists() || !hint_path(cache_path).exists() {
+            return None;
+        }
+
+        let cache_path = cache_path.to_path_buf();
+        let handle = thread::spawn(move || {
+            let hint = read_hint(&cache_path)?;
+            let rkyv_cache = RkyvMmapCache::open(&index_path, &cache_path.with_extension("dat")).ok()?;
+            let mut entries = HashMap::with_capacity(hint.paths.len());
+            for path in hint.paths {
+                if let Ok(Some(entry)) = rkyv_cache.get_entry(&path) {
+                    entries.insert(path, DiskCache::dir_entry_from_rkyv(entry));
+                }
+            }
+            Some((rkyv_cache.index.generation, entries))
+        });
+        Some(Prefetch { handle })
+    }
+
+    /// Wait for the prefetch and hand its entries to `cache`, if they come from the same
+    /// snapshot generation it loaded. Returns how many entries were used.
+    pub fn apply(self, cache: &mut DiskCache) -> usize {
+        let Ok(Some((generation, entries))) = self.handle.join() else {
+            return 0;
+        };
+        if generation != cache.generation {
+            return 0;
+        }
+
+        let mut used = 0;
+        for (path, entry) in entries {
+            if let std::collections::hash_map::Entry::Vacant(slot) = cache.entries.entry(path) {
+                slot.insert(entry);
+                used += 1;
+            }
+        }
+        used
+    }
+}
+
+impl DiskCache {
+    /// Directories a render from the roots down to `max_depth` shows, in the order the
+    /// lazy loader visits them.
+    pub fn visible_paths(&self, max_depth: Option<usize>) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack: Vec<(PathBuf, usize)> = self.top_level_roots().into_iter().rev().map(|root| (root, 0)).collect();
+
+        while let Some((path, depth)) = stack.pop() {
+            if max_depth.is_some_and(|max| depth >= max) || !visited.insert(path.clone()) {
+                continue;
+            }
+            let Some(entry) = self.entries.get(&path) else {
+                continue;
+            };
+            paths.push(path.clone());
+            if paths.len() >= PREFETCH_MAX_PATHS {
+                break;
+            }
+
+            for name in entry.children.iter().rev() {
+                let child = path.join(name);
+                if self.entries.contains_key(&child) {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        paths
+    }
+
+    /// Remember what this render showed so the next cold start can prefetch it.
+    /// The hint is only rewritten when it changed.
+    pub fn save_prefetch_hint(&self, cache_path: &Path, max_depth: Option<usize>) -> Result<()> {
+        let hint = PrefetchHint {
+            paths: self.visible_paths(max_depth),
+        };
+        if hint.paths.is_empty() || read_hint(cache_path).as_ref() == Some(&hint) {
+            return Ok(());
+        }
+
+        let path = hint_path(cache_path);
+        let temp_path = path.with_extension("hint.tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&bincode::serialize(&hint)?)?;
+        drop(file);
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::names::Name;
+    use crate::testing::dir;
+
+    #[test]
+    fn hinted_entries_are_prefetched_for_the_next_run() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_prefetch_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let scan_root = temp_dir.join("root");
+        fs::create_dir_all(scan_root.join("a").join("deep"))?;
+        fs::create_dir_all(scan_root.join("b"))?;
+        let cache_path = temp_dir.join("cache").join("ptree.dat");
+
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.root = scan_root.clone();
+        for path in [
+            scan_root.clone(),
+            scan_root.join("a"),
+            scan_root.join("a").join("deep"),
+            scan_root.join("b"),
+        ] {
+            let children = fs::read_dir(&path)?
+                .map(|child| child.map(|child| Name::new(&child.file_name().to_string_lossy())))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            let mut entry = DirEntry {
+                children,
+                ..dir(&path, 0, &[])
+            };
+            entry.children.sort();
+            cache.entries.insert(path, entry);
+        }
+        cache.save(&cache_path)?;
+
+        // A depth-2 render shows the root and its children, not a/deep
+        cache.save_prefetch_hint(&cache_path, Some(2))?;
+        assert_eq!(cache.visible_paths(Some(2)), vec![scan_root.clone(), scan_root.join("a"), scan_root.join("b")]);
+
+        let mut reopened = DiskCache::open(&cache_path)?;
+        let prefetch = Prefetch::start(&cache_path).expect("hint was written");
+        assert_eq!(prefetch.apply(&mut reopened), 3);
+        assert!(reopened.entries.contains_key(&scan_root.join("b")));
+        assert!(!reopened.entries.contains_key(&scan_root.join("a").join("deep")));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
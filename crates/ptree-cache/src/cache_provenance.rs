@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{DirEntry, DiskCache};
+
+/// How many scans `--cache-info` lists one by one; older ones are summarized on one line.
+const CACHE_INFO_SCANS: usize = 5;
+
+/// A scan that still verifies some entries of the snapshot (see `DirEntry::scan`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanRecord {
+    pub finished: DateTime<Utc>,
+    /// Directories whose last read from disk was this scan
+    pub entries:  usize,
+}
+
+/// Topmost directory of a subtree no scan has verified since a cutoff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleSubtree {
+    pub path:        PathBuf,
+    /// Oldest verification in the subtree; `None` when part of it predates scan numbering
+    pub verified:    Option<DateTime<Utc>>,
+    /// Stale directories in the subtree, this one included
+    pub directories: usize,
+}
+
+impl DiskCache {
+    /// When `entry` was last read from disk, if its scan is known.
+    pub fn verified_at(&self, entry: &DirEntry) -> Option<DateTime<Utc>> {
+        self.scans.get(&entry.scan).map(|record| record.finished)
+    }
+
+    /// Record that scan number `scan` finished at `finished`, and recount how many entries
+    /// each scan still verifies (scans that no longer verify any are forgotten).
+    pub fn record_scan(&mut self, scan: u64, finished: DateTime<Utc>) {
+        self.scans.insert(scan, ScanRecord { finished, entries: 0 });
+        self.scans = self.count_scans(self.entries.values());
+    }
+
+    /// `scans`, recounted over `entries` (and the spilled directories).
+    pub(crate) fn count_scans<'a>(&self, entries: impl Iterator<Item = &'a DirEntry>) -> BTreeMap<u64, ScanRecord> {
+        let mut counted = BTreeMap::new();
+        for entry in entries {
+            if let Some(record) = self.scans.get(&entry.scan) {
+                counted
+                    .entry(entry.scan)
+                    .or_insert(ScanRecord {
+                        finished: record.finished,
+                        entries:  0,
+                    })
+                    .entries += 1;
+            }
+        }
+
+        // Spilled directories were all read by the current scan
+        if let (Some(record), true) = (self.scans.get(&self.scan), self.spill.entries() > 0) {
+            counted
+                .entry(self.scan)
+                .or_insert(ScanRecord {
+                    finished: record.finished,
+                    entries:  0,
+                })
+                .entries += self.spill.entries();
+        }
+        counted
+    }
+
+    /// Subtrees whose directories were all last verified before `cutoff`, stalest first,
+    /// so a refresh can start where the snapshot is most likely out of date.
+    pub fn stale_subtrees(&self, cutoff: DateTime<Utc>) -> Vec<StaleSubtree> {
+        let mut stale: Vec<(&PathBuf, Option<DateTime<Utc>>)> = self
+            .entries
+            .iter()
+            .map(|(path, entry)| (path, self.verified_at(entry)))
+            .filter(|(_, verified)| verified.is_none_or(|verified| verified < cutoff))
+            .collect();
+        // Component order puts every descendant right after its ancestor
+        stale.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut subtrees: Vec<StaleSubtree> = Vec::new();
+        for (path, verified) in stale {
+            match subtrees.last_mut() {
+                Some(subtree) if path.starts_with(&subtree.path) => {
+                    subtree.directories += 1;
+                    subtree.verified = subtree.verified.zip(verified).map(|(a, b)| a.min(b));
+                }
+                _ => {
+                    subtrees.push(StaleSubtree {
+                        path: path.clone(),
+                        verified,
+                        directories: 1,
+                    })
+                }
+            }
+        }
+
+        // `None` (never verified by a numbered scan) sorts first
+        subtrees.sort_by(|a, b| a.verified.cmp(&b.verified).then_with(|| a.path.cmp(&b.path)));
+        subtrees
+    }
+
+    /// `--cache-info` lines: which scans verified the snapshot's directories, newest first.
+    pub(crate) fn scan_report(&self) -> String {
+        let mut report = String::new();
+        let mut label = "Verified:";
+        for (scan, record) in self.scans.iter().rev().take(CACHE_INFO_SCANS) {
+            report.push_str(&format!(
+                "  {:<18} {}  {} directories (scan {})\n",
+                label,
+                record.finished.format("%Y-%m-%d %H:%M:%S UTC"),
+                record.entries,
+                scan
+            ));
+            label = "";
+        }
+
+        let older: Vec<&ScanRecord> = self.scans.values().rev().skip(CACHE_INFO_SCANS).collect();
+        if let Some(oldest) = older.last() {
+            report.push_str(&format!(
+                "  {:<18} {} directories by {} older scans, oldest {}\n",
+                label,
+                older.iter().map(|record| record.entries).sum::<usize>(),
+                older.len(),
+                oldest.finished.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+
+        let verified: usize = self.scans.values().map(|record| record.entries).sum();
+        let unverified = self.entry_count_hint().saturating_sub(verified);
+        if unverified > 0 {
+            report.push_str(&format!(
+                "  {:<18} {} directories from before scans were numbered\n",
+                "Unverified:", unverified
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::Duration;
+
+    use super::*;
+    use crate::testing::dir;
+
+    #[test]
+    fn stale_subtrees_are_grouped_and_listed_stalest_first() {
+        let mut cache = DiskCache::new_empty();
+        let now = Utc::now();
+        let root = Path::new("/data");
+        for (path, scan) in [
+            (root.to_path_buf(), 3),
+            (root.join("fresh"), 3),
+            (root.join("old"), 1),
+            (root.join("old").join("deep"), 1),
+            (root.join("older"), 2),
+            (root.join("unknown"), 0),
+        ] {
+            cache.entries.insert(
+                path.clone(),
+                DirEntry {
+                    scan,
+                    ..dir(&path, 0, &[])
+                },
+            );
+        }
+        cache.scans.insert(
+            1,
+            ScanRecord {
+                finished: now - Duration::days(30),
+                entries:  0,
+            },
+        );
+        cache.scans.insert(
+            2,
+            ScanRecord {
+                finished: now - Duration::days(10),
+                entries:  0,
+            },
+        );
+        // A scan no entry refers to any more is dropped when the next one is recorded
+        cache.scans.insert(
+            9,
+            ScanRecord {
+                finished: now - Duration::days(60),
+                entries:  0,
+            },
+        );
+        cache.record_scan(3, now);
+
+        assert_eq!(cache.scans.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(cache.scans[&1].entries, 2);
+        assert_eq!(cache.scans[&3].entries, 2);
+
+        let stale = cache.stale_subtrees(now - Duration::days(7));
+        let summary: Vec<(PathBuf, usize)> = stale
+            .iter()
+            .map(|subtree| (subtree.path.clone(), subtree.directories))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (root.join("unknown"), 1),
+                (root.join("old"), 2),
+                (root.join("older"), 1)
+            ]
+        );
+        assert_eq!(stale[1].verified, Some(now - Duration::days(30)));
+
+        assert!(cache
+            .stale_subtrees(now - Duration::days(20))
+            .iter()
+            .all(|s| s.path != root.join("older")));
+    }
+}
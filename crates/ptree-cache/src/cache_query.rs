@@ -0,0 +1,417 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::cache::{DiskCache, SnapshotTotals};
+use crate::cache_rkyv::RkyvMmapCache;
+
+/// A file or directory whose name matched `DiskCache::search`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub path:   PathBuf,
+    pub is_dir: bool,
+    /// Recursive size of a directory; files are stored by name only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size:   Option<u64>,
+}
+
+impl DiskCache {
+    /// Files and directories in memory matching the wildcard `pattern` (see `wildcard_match`),
+    /// sorted by path, at most `limit` of them. A pattern containing a path separator is
+    /// matched against the whole path, any other against the name alone.
+    pub fn search(&self, pattern: &str, limit: usize) -> Vec<SearchHit> {
+        let matcher = Matcher::new(pattern);
+
+        let mut hits: Vec<SearchHit> = self
+            .entries
+            .par_iter()
+            .flat_map_iter(|(path, entry)| {
+                let matcher = &matcher;
+                entry.children.iter().filter_map(move |name| {
+                    let child = path.join(name);
+                    if !matcher.matches(&child, name.as_str()) {
+                        return None;
+                    }
+                    let dir = self.entries.get(&child).filter(|entry| entry.is_dir);
+                    Some(SearchHit {
+                        is_dir: dir.is_some(),
+                        size:   dir.map(|entry| entry.total_size),
+                        path:   child,
+                    })
+                })
+            })
+            .collect();
+
+        hits.par_sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+impl RkyvMmapCache {
+    /// `DiskCache::search` over the persisted snapshot, reading the records in place instead
+    /// of loading them.
+    pub fn search(&self, pattern: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let matcher = Matcher::new(pattern);
+        let paths: Vec<PathBuf> = self.index.offsets.paths().collect();
+
+        let matched: Vec<Vec<PathBuf>> = paths
+            .par_iter()
+            .map(|path| {
+                let found = self.with_entry(path, |record| {
+                    let mut found = Vec::new();
+                    for &child in record.children.iter() {
+                        let name = self.name(child)?;
+                        let child = path.join(name);
+                        if matcher.matches(&child, name) {
+                            found.push(child);
+                        }
+                    }
+                    Ok(found)
+                })?;
+                Ok(found.unwrap_or_default())
+            })
+            .collect::<Result<_>>()?;
+
+        let mut matched: Vec<PathBuf> = matched.into_iter().flatten().collect();
+        matched.par_sort_unstable();
+        matched.truncate(limit);
+        matched
+            .into_iter()
+            .map(|path| {
+                let size = self.with_entry(&path, |record| Ok(record.is_dir.then_some(record.total_size)))?;
+                Ok(SearchHit {
+                    is_dir: size.flatten().is_some(),
+                    size: size.flatten(),
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    /// `DiskCache::subtree_totals` of the directory at `path`: its files and sizes from the
+    /// totals its record keeps, the directories below it from the index; `None` if the
+    /// snapshot has no such directory.
+    pub fn subtree_totals(&self, path: &Path) -> Result<Option<SnapshotTotals>> {
+        self.with_entry(path, |record| {
+            Ok(SnapshotTotals {
+                directories: self.index.offsets.records_below(path),
+                files: record.file_count as usize,
+                bytes: record.total_size,
+                allocated_bytes: record.allocated_size,
+                ..SnapshotTotals::default()
+            })
+        })
+    }
+}
+
+/// A `--query` path expression: `/`-separated name patterns (`*` and `?` wildcards, matched
+/// without regard to case) where `**` stands for any number of levels, none included. One
+/// starting with `/` is anchored at each root; any other may match at any depth, as if it
+/// started with `/**/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathQuery {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// `**`: zero or more levels
+    AnyDepth,
+    /// One level whose name matches the (lowercased) wildcard pattern
+    Name(String),
+}
+
+impl PathQuery {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let anchored = expr.starts_with(['/', '\\']);
+        let mut segments = if anchored { Vec::new() } else { vec![Segment::AnyDepth] };
+        for part in expr.split(['/', '\\']).filter(|part| !part.is_empty() && *part != ".") {
+            match part {
+                "**" => segments.push(Segment::AnyDepth),
+                _ if part.contains("**") => bail!("`**` must be a whole level of the query, as in `src/**/tests`"),
+                _ => segments.push(Segment::Name(part.to_lowercase())),
+            }
+        }
+        if !anchored && segments.len() == 1 {
+            bail!("the query is empty; `/` matches the roots themselves");
+        }
+        Ok(PathQuery { segments })
+    }
+
+    /// The states (positions in `segments`) reachable from `states` without consuming a level.
+    fn closure(&self, states: &mut Vec<usize>) {
+        let mut i = 0;
+        while i < states.len() {
+            let state = states[i];
+            if self.segments.get(state) == Some(&Segment::AnyDepth) && !states.contains(&(state + 1)) {
+                states.push(state + 1);
+            }
+            i += 1;
+        }
+    }
+
+    /// The states after descending into an entry named `name` (lowercased) from `states`.
+    fn step(&self, states: &[usize], name: &str) -> Vec<usize> {
+        let mut next = Vec::new();
+        for &state in states {
+            let reached = match self.segments.get(state) {
+                Some(Segment::AnyDepth) => Some(state),
+                Some(Segment::Name(pattern)) if wildcard_match(pattern, name) => Some(state + 1),
+                _ => None,
+            };
+            if let Some(reached) = reached.filter(|reached| !next.contains(reached)) {
+                next.push(reached);
+            }
+        }
+        self.closure(&mut next);
+        next
+    }
+
+    fn is_match(&self, states: &[usize]) -> bool {
+        states.contains(&self.segments.len())
+    }
+}
+
+impl DiskCache {
+    /// The files and directories in memory that `query` matches, sorted by path. Matches
+    /// below another match are left out, as they are part of its subtree.
+    pub fn query_paths(&self, query: &PathQuery) -> Vec<PathBuf> {
+        let mut matches = Vec::new();
+        let mut pending: Vec<(PathBuf, Vec<usize>)> = self
+            .top_level_roots()
+            .into_iter()
+            .map(|root| {
+                let mut states = vec![0];
+                query.closure(&mut states);
+                (root, states)
+            })
+            .collect();
+        while let Some((path, states)) = pending.pop() {
+            if query.is_match(&states) {
+                matches.push(path);
+                continue;
+            }
+            let Some(entry) = self.entries.get(&path) else {
+                continue;
+            };
+            for name in &entry.children {
+                let next = query.step(&states, &name.as_str().to_lowercase());
+                if !next.is_empty() {
+                    pending.push((path.join(name), next));
+                }
+            }
+        }
+        matches.sort();
+        matches
+    }
+
+    /// Totals of the subtrees `query_paths` matched, from the entries in memory: the
+    /// directories below them, and the files and sizes of them all (`report_totals`).
+    pub fn subtree_totals(&self, matches: &[PathBuf]) -> SnapshotTotals {
+        let mut totals = SnapshotTotals::default();
+        for path in matches {
+            let Some(entry) = self.entries.get(path) else {
+                totals.files += 1;
+                if let Some(metadata) = self.metadata_of(path) {
+                    totals.bytes += metadata.size;
+                    totals.allocated_bytes += metadata.allocated;
+                }
+                continue;
+            };
+            totals.files += entry.file_count;
+            totals.bytes += entry.total_size;
+            totals.allocated_bytes += entry.allocated_size;
+            let mut pending: Vec<PathBuf> = entry.children.iter().map(|name| path.join(name)).collect();
+            while let Some(child) = pending.pop() {
+                if let Some(entry) = self.entries.get(&child) {
+                    totals.directories += 1;
+                    pending.extend(entry.children.iter().map(|name| child.join(name)));
+                }
+            }
+        }
+        totals
+    }
+}
+
+/// A search pattern, lowercased once: matched against the whole path if it contains a path
+/// separator, against the name alone otherwise.
+struct Matcher {
+    pattern:    String,
+    whole_path: bool,
+}
+
+impl Matcher {
+    fn new(pattern: &str) -> Self {
+        let pattern = pattern.to_lowercase();
+        Self {
+            whole_path: pattern.contains('/') || pattern.contains('\\'),
+            pattern,
+        }
+    }
+
+    fn matches(&self, path: &Path, name: &str) -> bool {
+        if self.whole_path {
+            wildcard_match(&self.pattern, &path.to_string_lossy().to_lowercase())
+        } else {
+            wildcard_match(&self.pattern, &name.to_lowercase())
+        }
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of characters (including
+/// none) and `?` for exactly one. Both sides are compared as given, so callers wanting a
+/// case-insensitive match lowercase them first.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried against
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last `*` swallow one more character and retry from there
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::testing::dir;
+
+    #[test]
+    fn wildcards_match_runs_and_single_characters() {
+        assert!(wildcard_match("*.log", "app.log"));
+        assert!(wildcard_match("*.log", ".log"));
+        assert!(!wildcard_match("*.log", "app.log.1"));
+        assert!(wildcard_match("app-??.txt", "app-01.txt"));
+        assert!(!wildcard_match("app-??.txt", "app-1.txt"));
+        assert!(wildcard_match("*a*b*", "xxaxxbxx"));
+        assert!(wildcard_match("readme", "readme"));
+        assert!(!wildcard_match("readme", "readme.md"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn search_finds_files_and_directories_by_name_or_path() {
+        let mut cache = DiskCache::new_empty();
+        for entry in [
+            dir("/data", 300, &["logs", "notes.txt"]),
+            dir("/data/logs", 200, &["app.log", "old", "App.LOG.txt"]),
+            dir("/data/logs/old", 100, &["app.log"]),
+        ] {
+            cache.entries.insert(entry.path.clone(), entry);
+        }
+
+        let hits = cache.search("*.LOG", 10);
+        let paths: Vec<&Path> = hits.iter().map(|hit| hit.path.as_path()).collect();
+        assert_eq!(paths, vec![Path::new("/data/logs/app.log"), Path::new("/data/logs/old/app.log")]);
+        assert!(hits.iter().all(|hit| !hit.is_dir && hit.size.is_none()));
+
+        assert_eq!(
+            cache.search("log?", 10),
+            vec![SearchHit {
+                path:   PathBuf::from("/data/logs"),
+                is_dir: true,
+                size:   Some(200),
+            }]
+        );
+        assert_eq!(cache.search("/data/logs/old/*", 10).len(), 1);
+        assert_eq!(cache.search("*", 2).len(), 2);
+    }
+
+    #[test]
+    fn path_queries_match_levels_and_any_depth() -> Result<()> {
+        let mut cache = DiskCache::new_empty();
+        cache.root = PathBuf::from("/repo");
+        for entry in [
+            dir("/repo", 600, &["src", "tests", "README.md"]),
+            dir("/repo/src", 500, &["core", "ui", "main.rs"]),
+            dir("/repo/src/core", 300, &["Tests", "lib.rs"]),
+            dir("/repo/src/core/Tests", 100, &["tests"]),
+            dir("/repo/src/core/Tests/tests", 50, &[]),
+            dir("/repo/src/ui", 100, &["widgets"]),
+            dir("/repo/src/ui/widgets", 100, &["tests"]),
+            dir("/repo/src/ui/widgets/tests", 100, &[]),
+            dir("/repo/tests", 100, &[]),
+        ] {
+            cache.entries.insert(entry.path.clone(), entry);
+        }
+        let query = |expr: &str| -> Result<Vec<PathBuf>> { Ok(cache.query_paths(&PathQuery::parse(expr)?)) };
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        // Matches inside a match are part of its subtree
+        assert_eq!(query("/src/**/tests")?, paths(&["/repo/src/core/Tests", "/repo/src/ui/widgets/tests"]));
+        assert_eq!(query("tests")?, paths(&["/repo/src/core/Tests", "/repo/src/ui/widgets/tests", "/repo/tests"]));
+        assert_eq!(query("/tests")?, paths(&["/repo/tests"]));
+        assert_eq!(
+            query("/src/*/*")?,
+            paths(&["/repo/src/core/Tests", "/repo/src/core/lib.rs", "/repo/src/ui/widgets"])
+        );
+        assert_eq!(query("/*.md")?, paths(&["/repo/README.md"]));
+        assert_eq!(query("/")?, paths(&["/repo"]));
+        assert!(query("/nothing/**")?.is_empty());
+        cache.report_totals = Some(cache.subtree_totals(&query("/src/*")?));
+        assert_eq!(cache.report_line(), "4 directories, 4 files, 400 B total");
+
+        assert!(PathQuery::parse("src/a**").is_err());
+        assert!(PathQuery::parse("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_is_searched_and_browsed_in_place() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join("ptree_test_snapshot_search");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let cache_path = temp_dir.join("test.dat");
+
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.roots = vec![PathBuf::from("/data")];
+        for entry in [
+            dir("/data", 300, &["logs", "notes.txt"]),
+            dir("/data/logs", 200, &["app.log", "old"]),
+            dir("/data/logs/old", 100, &["app.log"]),
+        ] {
+            cache.entries.insert(entry.path.clone(), entry);
+        }
+        let in_memory = cache.search("*.log", 10);
+        cache.save(&cache_path)?;
+
+        let records = RkyvMmapCache::open(&cache_path.with_extension("idx"), &cache_path.with_extension("dat"))?;
+        assert_eq!(records.search("*.log", 10)?, in_memory);
+        assert_eq!(records.search("o*", 10)?.len(), 1);
+        assert_eq!(records.search("o*", 10)?[0].size, Some(100));
+
+        // Only the levels asked for are loaded
+        let mut browsed = DiskCache::open(&cache_path)?;
+        browsed.load_subtree_from(&records, Path::new("/data/logs"), Some(0))?;
+        assert_eq!(browsed.entries.len(), 1);
+        browsed.load_subtree_from(&records, Path::new("/data"), Some(1))?;
+        assert!(browsed.get_entry(Path::new("/data/logs/old")).is_none());
+        assert_eq!(browsed.totals_from(&records).bytes, 300);
+        let below_logs = records.subtree_totals(Path::new("/data/logs"))?.unwrap();
+        assert_eq!((below_logs.directories, below_logs.bytes), (1, 200));
+        assert!(records.subtree_totals(Path::new("/data/tmp"))?.is_none());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
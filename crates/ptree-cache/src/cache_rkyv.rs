@@ -1,83 +1,725 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use memmap2::Mmap;
+use parking_lot::Mutex;
+use ptree_core::PTreeError;
+use rkyv::with::{ArchiveWith, SerializeWith};
+use rkyv::{AlignedVec, Archive, Archived, Fallible};
 use serde::{Deserialize, Serialize};
 
 #[cfg(windows)]
 use crate::cache::USNJournalState;
+use crate::cache::{
+    keep_largest_dirs,
+    AltStream,
+    CacheTrim,
+    EntryMetadata,
+    ExtensionUsage,
+    LargeDir,
+    LargeFile,
+    NewDir,
+    OwnerUsage,
+    ScanError,
+    SlowDir,
+    StoreUsage,
+    Subvolume,
+    Symlink,
+    XAttr,
+};
+use crate::cache_provenance::ScanRecord;
+use crate::names::Name;
+use crate::path_index::PathIndex;
 
 /// Compute depth of a path (number of separators)
-fn compute_depth(path: &Path) -> u32 {
+pub(crate) fn compute_depth(path: &Path) -> u32 {
     path.components().count() as u32
 }
 
-/// Serializable directory entry (serde-based for compatibility)
+/// Owned directory entry as read from or written to a snapshot (serde is kept for the
+/// SQLite backend).
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RkyvDirEntry {
-    pub path:         PathBuf,
-    pub name:         String,
-    pub modified:     DateTime<Utc>,
-    pub content_hash: u64, // NEW FIELD - Merkle tree hash
-    pub file_count:   usize,
-    pub total_size:   u64,
-    pub children:     Vec<String>,
-    pub is_hidden:    bool,
-    pub is_dir:       bool,
-}
-
-/// Serializable cache index (serde-based for compatibility)
+    pub path:           PathBuf,
+    pub name:           Name,
+    pub modified:       DateTime<Utc>,
+    pub content_hash:   u64, // NEW FIELD - Merkle tree hash
+    pub file_count:     usize,
+    pub total_size:     u64,
+    pub allocated_size: u64,
+    pub children:       Vec<Name>,
+    pub is_hidden:      bool,
+    pub is_dir:         bool,
+    pub id:             u64,
+    pub file_id:        u64,
+    pub scan:           u64,
+}
+
+/// Directory entry as stored in a shard record: an rkyv archive, read in place from the
+/// mmap as an `ArchivedShardRecord`. The path is not archived (the index already spells it
+/// out as (parent ID, name) nodes), and names are offsets into the snapshot's name table.
+#[derive(rkyv::Archive, rkyv::Serialize)]
+#[archive(check_bytes)]
+pub struct ShardRecord {
+    pub name:           u32,
+    #[with(UnixMicros)]
+    pub modified:       DateTime<Utc>,
+    pub content_hash:   u64,
+    pub file_count:     usize,
+    pub total_size:     u64,
+    pub allocated_size: u64,
+    pub children:       Vec<u32>,
+    pub is_hidden:      bool,
+    pub is_dir:         bool,
+    pub id:             u64,
+    pub file_id:        u64,
+    pub scan:           u64,
+}
+
+/// Archived timestamp: microseconds since the Unix epoch.
+pub struct UnixMicros;
+
+impl ArchiveWith<DateTime<Utc>> for UnixMicros {
+    type Archived = Archived<i64>;
+    type Resolver = ();
+
+    unsafe fn resolve_with(field: &DateTime<Utc>, pos: usize, resolver: (), out: *mut Self::Archived) {
+        field.timestamp_micros().resolve(pos, resolver, out);
+    }
+}
+
+impl<S: Fallible + ?Sized> SerializeWith<DateTime<Utc>, S> for UnixMicros {
+    fn serialize_with(_: &DateTime<Utc>, _: &mut S) -> Result<(), S::Error> {
+        Ok(())
+    }
+}
+
+impl ArchivedShardRecord {
+    pub fn modified(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros(self.modified).unwrap_or_default()
+    }
+}
+
+/// A snapshot's names live once in `<stem>-g<generation>-names.dat`: a shard header, then
+/// `[len: u32 LE][UTF-8 bytes]` per distinct name. Records refer to a name by its offset.
+const NAMES_MAGIC: &[u8; 4] = b"PTN\x01";
+
+/// Name table of the snapshot being written (or appended to); hands out each name's offset.
+pub(crate) struct NameTableWriter {
+    offsets: HashMap<Name, u32>,
+    /// Bytes of the table already on disk
+    base:    u64,
+    /// Names not written yet
+    pending: Vec<u8>,
+}
+
+impl NameTableWriter {
+    pub(crate) fn new() -> Self {
+        NameTableWriter {
+            offsets: HashMap::new(),
+            base:    SHARD_HEADER_LEN,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Continue the table in `table` (a names file as mapped), for appends.
+    fn resume(table: &[u8]) -> Result<Self> {
+        let mut writer = NameTableWriter {
+            offsets: HashMap::new(),
+            base:    table.len() as u64,
+            pending: Vec::new(),
+        };
+        let mut offset = SHARD_HEADER_LEN as usize;
+        while offset < table.len() {
+            let name = name_at(table, offset as u32)?;
+            writer.offsets.insert(Name::new(name), offset as u32);
+            offset += 4 + name.len();
+        }
+        Ok(writer)
+    }
+
+    pub(crate) fn offset(&mut self, name: &Name) -> Result<u32> {
+        if let Some(&offset) = self.offsets.get(name) {
+            return Ok(offset);
+        }
+
+        let offset = u32::try_from(self.base + self.pending.len() as u64)
+            .map_err(|_| anyhow::anyhow!("cache name table exceeds 4 GiB"))?;
+        self.pending.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        self.pending.extend_from_slice(name.as_bytes());
+        self.offsets.insert(name.clone(), offset);
+        Ok(offset)
+    }
+
+    /// Write a new table stamped with `generation` to `path`.
+    pub(crate) fn write(&mut self, path: &Path, generation: u64) -> Result<()> {
+        let mut file = std::io::BufWriter::new(File::create(path)?);
+        file.write_all(&shard_header_with(NAMES_MAGIC, generation))?;
+        file.write_all(&self.pending)?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        self.base += self.pending.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Append the names added since the last write to the table at `path`.
+    fn append(&mut self, path: &Path) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+        file.write_all(&self.pending)?;
+        file.sync_all()?;
+        self.base += self.pending.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// The name at `offset` of a mapped name table.
+fn name_at(table: &[u8], offset: u32) -> Result<&str> {
+    let start = offset as usize;
+    let len = table
+        .get(start..start + 4)
+        .ok_or_else(|| anyhow::anyhow!("name offset {} out of bounds", offset))?;
+    let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    let bytes = table
+        .get(start + 4..start + 4 + len)
+        .ok_or_else(|| anyhow::anyhow!("truncated name at offset {}", offset))?;
+    std::str::from_utf8(bytes).map_err(|_| anyhow::anyhow!("name at offset {} is not UTF-8", offset))
+}
+
+/// `<stem>-g<generation>-names.dat` next to the depth shards of `data_path`.
+pub(crate) fn names_file_path(data_path: &Path, generation: u64) -> PathBuf {
+    let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+    let parent = data_path.parent().unwrap_or_else(|| Path::new("."));
+    parent.join(format!("{}-g{}-names.dat", stem, generation))
+}
+
+/// `<stem>-g<generation>-d<depth>.dat`: the depth shard of one generation. A save writes the
+/// shards of its new generation next to the ones the index points at, so renaming the index
+/// into place is the only step that switches snapshots.
+pub(crate) fn depth_file_path(data_path: &Path, generation: u64, depth: u32) -> PathBuf {
+    let stem = data_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+    let parent = data_path.parent().unwrap_or_else(|| Path::new("."));
+    parent.join(format!("{}-g{}-d{}.dat", stem, generation, depth))
+}
+
+/// Serializable cache index (serde-based; read in full once at open, so it stays bincode)
 /// Maps paths → (depth, offset) for depth-split file access
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RkyvCacheIndex {
-    /// Offsets mapping: (path, depth, offset) for lazy depth-aware access
-    pub offsets:           HashMap<PathBuf, (u32, u64)>,
-    pub total_files:       usize,
-    pub last_scan:         DateTime<Utc>,
-    pub root:              PathBuf,
-    pub last_scanned_root: PathBuf,
+    /// Offsets mapping: path → (depth, offset) for lazy depth-aware access, stored as a
+    /// (parent ID, name) node table so shared prefixes are kept once
+    pub offsets:            PathIndex,
+    pub total_files:        usize,
+    pub last_scan:          DateTime<Utc>,
+    pub root:               PathBuf,
+    /// Every top-level root of a multi-root scan (empty for single-root snapshots)
+    pub roots:              Vec<PathBuf>,
+    pub last_scanned_root:  PathBuf,
     #[cfg(windows)]
-    pub usn_state:         USNJournalState,
-    pub skip_stats:        HashMap<String, usize>,
+    pub usn_state:          USNJournalState,
+    pub skip_stats:         HashMap<String, usize>,
+    pub store_usage:        Vec<StoreUsage>,
+    /// Directories flagged by `--settle` as still changing when the snapshot was taken
+    pub unsettled_dirs:     HashSet<PathBuf>,
+    /// Directories flagged `[new]` by recent full scans
+    pub new_dirs:           HashMap<PathBuf, NewDir>,
+    /// Directories whose listing was slow on recent scans
+    pub slow_dirs:          HashMap<PathBuf, SlowDir>,
+    /// Named alternate data streams found by the last `--ads` scans
+    pub alt_streams:        HashMap<PathBuf, Vec<AltStream>>,
+    /// When a scan last enumerated alternate data streams
+    pub ads_scanned:        Option<DateTime<Utc>>,
+    /// Extended attributes found by the last `--xattrs` scans
+    pub xattrs:             HashMap<PathBuf, Vec<XAttr>>,
+    /// When a scan last enumerated extended attributes
+    pub xattrs_scanned:     Option<DateTime<Utc>>,
+    /// Usage per owner found by the last `--owners` scans
+    pub owner_usage:        HashMap<PathBuf, Vec<OwnerUsage>>,
+    /// When a scan last recorded file owners
+    pub owners_scanned:     Option<DateTime<Utc>>,
+    /// Usage per extension found by the last `--extensions` scans
+    pub extension_usage:    HashMap<PathBuf, Vec<ExtensionUsage>>,
+    /// When a scan last recorded file extensions
+    pub extensions_scanned: Option<DateTime<Utc>>,
+    /// Permissions, owner, size and modification time found by the last `--long` scans
+    pub entry_metadata:     HashMap<PathBuf, EntryMetadata>,
+    /// When a scan last recorded entry metadata
+    pub metadata_scanned:   Option<DateTime<Utc>>,
+    /// The largest files below the roots, largest first
+    pub largest_files:      Vec<LargeFile>,
+    /// The largest directories below the roots, largest on disk first
+    pub largest_dirs:       Vec<LargeDir>,
+    /// Btrfs subvolumes and ZFS datasets found below the roots
+    pub subvolumes:         HashMap<PathBuf, Subvolume>,
+    /// Directories on other filesystems that `--one-file-system` scans did not enter
+    pub mount_points:       HashSet<PathBuf>,
+    /// Taken with `--one-file-system`
+    pub one_file_system:    bool,
+    /// Sizes count every hard link (`--count-links`)
+    pub count_links:        bool,
+    /// Fingerprint of the skip rules the scans used
+    pub skip_rules:         u64,
+    /// Symbolic links met by `--follow-symlinks` scans
+    pub symlinks:           HashMap<PathBuf, Symlink>,
+    /// Directories the scans that last reached them could not read
+    pub scan_errors:        HashMap<PathBuf, ScanError>,
+    /// Directories an interrupted scan left unread
+    pub pending_dirs:       Vec<PathBuf>,
+    /// Next stable entry ID to hand out
+    pub next_entry_id:      u64,
+    /// Directory-only snapshot written after the cache volume ran out of space
+    pub reduced:            bool,
+    /// What was left out to fit `--cache-max-size` (or a full volume)
+    pub trim:               CacheTrim,
+    /// Bytes of superseded records left in the shards by `append_entry` (reclaimed by `compact`)
+    pub stale_bytes:        u64,
+    /// Shards hold zstd-compressed blocks of records (`--cache-compress`)
+    pub compressed:         bool,
+    /// Bumped on every full rewrite and stamped into each shard header, so an index
+    /// is never paired with shards from another save
+    pub generation:         u64,
+    /// Number of the latest scan that read the disk
+    pub scan:               u64,
+    /// Scans that last verified some of the records (`RkyvDirEntry::scan`)
+    pub scans:              BTreeMap<u64, ScanRecord>,
+}
+
+impl Default for RkyvCacheIndex {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RkyvCacheIndex {
     pub fn new() -> Self {
         RkyvCacheIndex {
-            offsets:                   HashMap::new(),
+            offsets:                   PathIndex::new(),
             total_files:               0,
             last_scan:                 Utc::now(),
             root:                      PathBuf::new(),
+            roots:                     Vec::new(),
             last_scanned_root:         PathBuf::new(),
             #[cfg(windows)]
             usn_state:                 USNJournalState::default(),
             skip_stats:                HashMap::new(),
+            store_usage:               Vec::new(),
+            unsettled_dirs:            HashSet::new(),
+            new_dirs:                  HashMap::new(),
+            slow_dirs:                 HashMap::new(),
+            alt_streams:               HashMap::new(),
+            ads_scanned:               None,
+            xattrs:                    HashMap::new(),
+            xattrs_scanned:            None,
+            owner_usage:               HashMap::new(),
+            owners_scanned:            None,
+            extension_usage:           HashMap::new(),
+            extensions_scanned:        None,
+            entry_metadata:            HashMap::new(),
+            metadata_scanned:          None,
+            largest_files:             Vec::new(),
+            largest_dirs:              Vec::new(),
+            subvolumes:                HashMap::new(),
+            mount_points:              HashSet::new(),
+            one_file_system:           false,
+            count_links:               false,
+            skip_rules:                0,
+            symlinks:                  HashMap::new(),
+            scan_errors:               HashMap::new(),
+            pending_dirs:              Vec::new(),
+            next_entry_id:             1,
+            reduced:                   false,
+            trim:                      CacheTrim::default(),
+            stale_bytes:               0,
+            compressed:                false,
+            generation:                0,
+            scan:                      0,
+            scans:                     BTreeMap::new(),
+        }
+    }
+}
+
+/// Every shard starts with `[magic: 4 bytes][generation: u64 LE]`; records follow.
+pub const SHARD_HEADER_LEN: u64 = 12;
+/// Version 6: records hold rkyv archives without their path, stamped with the scan that
+/// last verified them, naming names by their offset in the name table, with the allocated
+/// size next to the logical one (version 5 had no allocated size, version 4 stored the
+/// strings, version 3 had no scan, version 2 archived the path, version 1 held bincode);
+/// older shards are rejected at open and the roots rescanned.
+const SHARD_MAGIC: &[u8; 4] = b"PTS\x06";
+
+fn shard_header(generation: u64) -> [u8; SHARD_HEADER_LEN as usize] {
+    shard_header_with(SHARD_MAGIC, generation)
+}
+
+fn shard_header_with(magic: &[u8; 4], generation: u64) -> [u8; SHARD_HEADER_LEN as usize] {
+    let mut header = [0u8; SHARD_HEADER_LEN as usize];
+    header[..4].copy_from_slice(magic);
+    header[4..].copy_from_slice(&generation.to_le_bytes());
+    header
+}
+
+/// Generation stamped in a shard header, or `None` if the header is missing or foreign.
+fn shard_generation(header: &[u8]) -> Option<u64> {
+    header_generation(header, SHARD_MAGIC)
+}
+
+fn header_generation(header: &[u8], magic: &[u8; 4]) -> Option<u64> {
+    let header = header.get(..SHARD_HEADER_LEN as usize)?;
+    if &header[..4] != magic {
+        return None;
+    }
+    Some(u64::from_le_bytes(header[4..].try_into().ok()?))
+}
+
+/// Flush a directory's entry table so renames into it survive a crash (no-op off Unix).
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// The index file is `INDEX_MAGIC` followed by the bincode `RkyvCacheIndex`. The last byte is
+/// the format version of the snapshot as a whole, bumped whenever the index, the shard records
+/// or their framing change. Version 5: the index keeps the largest directories; version 4: the
+/// index keeps the fingerprint of the skip rules;
+/// version 3: file metadata carries the allocated size (version 2 had none, and bincode cannot
+/// default a missing field); version 2 framed records with a CRC32 and their length (version 1,
+/// whose index had no header, framed them without). An index of another version is rejected at
+/// open and the roots rescanned.
+const INDEX_MAGIC: &[u8; 4] = b"PTI\x05";
+
+/// The index file contents for `index`.
+pub(crate) fn encode_index(index: &RkyvCacheIndex) -> Result<Vec<u8>> {
+    let mut data = INDEX_MAGIC.to_vec();
+    bincode::serialize_into(&mut data, index)?;
+    Ok(data)
+}
+
+/// Whether `header` (the first bytes of an index file) is that of an index of another format
+/// version, which this version cannot read however intact it is.
+pub(crate) fn is_other_version(header: &[u8]) -> bool {
+    header
+        .get(..INDEX_MAGIC.len())
+        .is_some_and(|magic| magic[..3] == INDEX_MAGIC[..3] && magic[3] != INDEX_MAGIC[3])
+}
+
+/// The index in an index file, if it is of this version.
+pub(crate) fn decode_index(data: &[u8]) -> Result<RkyvCacheIndex> {
+    match data.get(..INDEX_MAGIC.len()) {
+        Some(magic) if magic == INDEX_MAGIC => {}
+        Some(magic) if magic[..3] == INDEX_MAGIC[..3] => {
+            anyhow::bail!("cache index is format version {}, this version reads {}", magic[3], INDEX_MAGIC[3])
+        }
+        _ => anyhow::bail!("cache index has no ptree header (written by an older version)"),
+    }
+    bincode::deserialize(&data[INDEX_MAGIC.len()..])
+        .map_err(|e| anyhow::anyhow!("failed to deserialize cache index: {e}"))
+}
+
+/// Write `index` to a temp file and rename it over `path`: the commit point of every save.
+pub(crate) fn write_index(index: &RkyvCacheIndex, path: &Path) -> Result<()> {
+    let data = encode_index(index)?;
+    let temp_path = path.with_extension("tmp");
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, path)?;
+    sync_dir(path.parent().unwrap_or_else(|| Path::new(".")))?;
+    Ok(())
+}
+
+/// Every shard record is `[payload len: u32 LE][CRC32 of payload: u32 LE][rkyv archive]`,
+/// starting on a `RECORD_ALIGN` boundary so the archive can be read in place.
+pub const RECORD_HEADER_LEN: u64 = 8;
+const RECORD_ALIGN: usize = 8;
+
+/// Frame `entry` as a checksummed shard record, adding its names to `names`.
+pub(crate) fn encode_record(entry: &RkyvDirEntry, names: &mut NameTableWriter) -> Result<Vec<u8>> {
+    let record = ShardRecord {
+        name:           names.offset(&entry.name)?,
+        modified:       entry.modified,
+        content_hash:   entry.content_hash,
+        file_count:     entry.file_count,
+        total_size:     entry.total_size,
+        allocated_size: entry.allocated_size,
+        children:       entry
+            .children
+            .iter()
+            .map(|child| names.offset(child))
+            .collect::<Result<_>>()?,
+        is_hidden:      entry.is_hidden,
+        is_dir:         entry.is_dir,
+        id:             entry.id,
+        file_id:        entry.file_id,
+        scan:           entry.scan,
+    };
+    let archive = rkyv::to_bytes::<_, 256>(&record).map_err(|e| anyhow::anyhow!("failed to archive entry: {e}"))?;
+    Ok(frame_payload(&archive))
+}
+
+/// Zero bytes that bring `position` up to the next record boundary.
+fn record_padding(position: u64) -> usize {
+    (RECORD_ALIGN - position as usize % RECORD_ALIGN) % RECORD_ALIGN
+}
+
+/// Validated view of a record payload, copied into an aligned buffer only when the
+/// payload does not sit on an archive boundary (records read back through a file fallback).
+fn with_archive<R>(payload: &[u8], f: impl FnOnce(&ArchivedShardRecord) -> Result<R>) -> Result<R> {
+    if !(payload.as_ptr() as usize).is_multiple_of(RECORD_ALIGN) {
+        let mut aligned = AlignedVec::with_capacity(payload.len());
+        aligned.extend_from_slice(payload);
+        return with_archive(&aligned, f);
+    }
+
+    let archived =
+        rkyv::check_archived_root::<ShardRecord>(payload).map_err(|e| corrupt(format!("undecodable record: {e}")))?;
+    f(archived)
+}
+
+fn frame_payload(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN as usize + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+/// Compressed shards are a sequence of blocks: `[compressed len: u32 LE][raw len: u32 LE][zstd frame]`,
+/// each frame holding whole records. Index offsets then pack `block start << 32 | offset in block`.
+const BLOCK_HEADER_LEN: u64 = 8;
+/// Records are gathered until a block holds about this many uncompressed bytes.
+const BLOCK_TARGET_BYTES: usize = 64 * 1024;
+/// Largest block (uncompressed) written or read: a block holds one record past
+/// `BLOCK_TARGET_BYTES` at most, so only a corrupt header claims more.
+const MAX_BLOCK_BYTES: usize = 64 * 1024 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+fn pack_block_offset(block_start: u64, offset_in_block: usize) -> Result<u64> {
+    if block_start > u32::MAX as u64 || offset_in_block > u32::MAX as usize {
+        anyhow::bail!("compressed cache shard exceeds 4 GiB");
+    }
+    Ok(block_start << 32 | offset_in_block as u64)
+}
+
+fn unpack_block_offset(offset: u64) -> (u64, u64) {
+    (offset >> 32, offset & u32::MAX as u64)
+}
+
+/// Writes framed records to one depth shard, either as-is or packed into zstd blocks,
+/// and hands back the index offset of each record.
+pub(crate) struct ShardWriter<W: Write> {
+    out:        W,
+    compressed: bool,
+    /// Bytes of the shard before the next write
+    position:   u64,
+    /// Uncompressed records of the block being filled
+    block:      Vec<u8>,
+}
+
+impl<W: Write> ShardWriter<W> {
+    /// `position` is where `out` starts in the shard (its current length when appending).
+    pub(crate) fn new(out: W, compressed: bool, position: u64) -> Self {
+        ShardWriter {
+            out,
+            compressed,
+            position,
+            block: Vec::new(),
+        }
+    }
+
+    /// Start a new shard stamped with `generation`.
+    pub(crate) fn create(mut out: W, compressed: bool, generation: u64) -> Result<Self> {
+        out.write_all(&shard_header(generation))?;
+        Ok(Self::new(out, compressed, SHARD_HEADER_LEN))
+    }
+
+    pub(crate) fn push(&mut self, record: &[u8]) -> Result<u64> {
+        if !self.compressed {
+            let padding = record_padding(self.position);
+            self.out.write_all(&[0u8; RECORD_ALIGN][..padding])?;
+            let offset = self.position + padding as u64;
+            self.out.write_all(record)?;
+            self.position = offset + record.len() as u64;
+            return Ok(offset);
+        }
+
+        if record.len() > MAX_BLOCK_BYTES - RECORD_ALIGN {
+            anyhow::bail!("cache record of {} bytes exceeds the compressed block limit", record.len());
+        }
+        if !self.block.is_empty() && self.block.len() + record.len() > BLOCK_TARGET_BYTES {
+            self.flush_block()?;
+        }
+        let padding = record_padding(self.block.len() as u64);
+        self.block.resize(self.block.len() + padding, 0);
+        let offset = pack_block_offset(self.position, self.block.len())?;
+        self.block.extend_from_slice(record);
+        Ok(offset)
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        let frame = zstd::bulk::compress(&self.block, ZSTD_LEVEL)?;
+        self.out.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.out.write_all(&(self.block.len() as u32).to_le_bytes())?;
+        self.out.write_all(&frame)?;
+        self.position += BLOCK_HEADER_LEN + frame.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<W> {
+        if !self.block.is_empty() {
+            self.flush_block()?;
         }
+        Ok(self.out)
+    }
+}
+
+fn corrupt(problem: String) -> anyhow::Error {
+    PTreeError::CorruptCache(problem).into()
+}
+
+/// Whether `err` is a snapshot record failing its checks as it was read
+/// (`PTreeError::CorruptCache`): the snapshot has to be discarded and the roots rescanned.
+pub fn is_corrupt_cache(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| matches!(cause.downcast_ref::<PTreeError>(), Some(PTreeError::CorruptCache(_))))
+}
+
+/// Payload of the record at `offset` in `shard`, after checking its bounds and checksum.
+fn record_payload(shard: &[u8], offset: u64) -> Result<&[u8]> {
+    let start = offset as usize;
+    let header = shard
+        .get(start..start + RECORD_HEADER_LEN as usize)
+        .ok_or_else(|| corrupt(format!("offset {} out of bounds", offset)))?;
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+    let payload_start = start + RECORD_HEADER_LEN as usize;
+    let payload = shard
+        .get(payload_start..payload_start + len)
+        .ok_or_else(|| corrupt(format!("truncated record at offset {}", offset)))?;
+    if crc32fast::hash(payload) != checksum {
+        return Err(corrupt(format!("checksum mismatch at offset {}", offset)));
+    }
+    Ok(payload)
+}
+
+/// Outcome of checking every indexed record of a snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub records: usize,
+    /// Indexed path and what is wrong with its record
+    pub corrupt: Vec<(PathBuf, String)>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// Compaction is automatic once at least this many bytes are stale...
+const AUTO_COMPACT_MIN_STALE_BYTES: u64 = 1 << 20;
+/// ...and they make up at least this share of the shards.
+const AUTO_COMPACT_STALE_PERCENT: u64 = 50;
+
+/// Result of rewriting the shards without superseded records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub records:      usize,
+    pub bytes_before: u64,
+    pub bytes_after:  u64,
+}
+
+impl CompactionStats {
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Decompressed blocks kept for lookups near the last ones
+const BLOCK_CACHE_BLOCKS: usize = 8;
+
+/// The `BLOCK_CACHE_BLOCKS` most recently read decompressed blocks, keyed by (depth, block
+/// start) and aligned so their records are read in place; least recently used first.
+#[derive(Default)]
+struct BlockCache {
+    blocks: VecDeque<((u32, u64), Arc<AlignedVec>)>,
+}
+
+impl BlockCache {
+    fn get(&mut self, key: (u32, u64)) -> Option<Arc<AlignedVec>> {
+        let position = self.blocks.iter().position(|(cached, _)| *cached == key)?;
+        let entry = self.blocks.remove(position)?;
+        let block = Arc::clone(&entry.1);
+        self.blocks.push_back(entry);
+        Some(block)
+    }
+
+    fn insert(&mut self, key: (u32, u64), block: Arc<AlignedVec>) {
+        if self.blocks.len() == BLOCK_CACHE_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((key, block));
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
     }
 }
 
-/// Memory-mapped cache using rkyv for zero-copy single-node O(1) access
+/// Memory-mapped cache of rkyv archives for zero-copy single-node O(1) access
 ///
 /// Architecture (depth-split strategy):
 /// - index file (.idx): contains RkyvCacheIndex with (depth, offset) tuples
-/// - data files (ptree-d0.dat, ptree-d1.dat, etc.): split by directory depth
+/// - data files (ptree-g<G>-d0.dat, ptree-g<G>-d1.dat, etc.): split by directory depth
+/// - name table (ptree-g<G>-names.dat): every distinct name once, referenced by offset
 ///
 /// Single-node access is O(1): load (depth, offset) from index, access depth-specific mmap
-/// No allocation or copying for field access during traversal
+/// No allocation or copying for field access (`with_entry` hands out the validated archive)
+/// (compressed shards decompress one block per lookup; the last few are kept in `blocks`)
 pub struct RkyvMmapCache {
-    pub index: RkyvCacheIndex,
-    mmaps:     Vec<Option<Mmap>>,
-    base_path: PathBuf,
+    pub index:   RkyvCacheIndex,
+    mmaps:       Vec<Option<Mmap>>,
+    names:       Option<Mmap>,
+    /// Names already in the table, loaded on the first `append_entry`
+    name_writer: Option<NameTableWriter>,
+    base_path:   PathBuf,
+    blocks:      Mutex<BlockCache>,
 }
 
 impl RkyvMmapCache {
     /// Load cache from index and depth-split data files
     /// Index is fully deserialized (small), data is mmap'd (large, lazy access)
+    ///
+    /// Opening reads only the shard headers and checks the index offsets against the shard
+    /// sizes; each record's checksum is checked when the record is read, so a cold start does
+    /// not fault in every page of the shards.
     pub fn open(index_path: &std::path::Path, data_path: &std::path::Path) -> Result<Self> {
+        let cache = Self::open_unverified(index_path, data_path)?;
+        if let Some((shard, problem)) = cache.mismatched_shards().into_iter().next() {
+            anyhow::bail!("{}: {problem}", shard.display());
+        }
+        cache.check_index_bounds()?;
+        Ok(cache)
+    }
+
+    /// Map the shards without checking records, so `verify` can report on a corrupt snapshot.
+    pub(crate) fn open_unverified(index_path: &Path, data_path: &Path) -> Result<Self> {
         fs::create_dir_all(index_path.parent().unwrap())?;
 
         // Load index (small, safe to fully deserialize using serde)
@@ -86,111 +728,270 @@ impl RkyvMmapCache {
             let mut data = Vec::new();
             file.read_to_end(&mut data)?;
 
-            // Deserialize index using serde bincode
-            bincode::deserialize::<RkyvCacheIndex>(&data)
-                .map_err(|e| anyhow::anyhow!("failed to deserialize cache index: {e}"))?
+            decode_index(&data)?
         } else {
             RkyvCacheIndex::new()
         };
 
-        // Load depth-split data files (ptree-d0.dat, ptree-d1.dat, etc.)
+        // Load depth-split data files of the index's generation (ptree-g<G>-d0.dat, ...)
         // Support up to depth 30 (typical filesystem is 5-10 levels deep)
         let mut mmaps = Vec::with_capacity(31);
         for depth in 0..31 {
-            let depth_file = Self::depth_file_path(data_path, depth);
-            let mmap = if depth_file.exists() {
-                match File::open(&depth_file) {
-                    Ok(file) => {
-                        match unsafe { Mmap::map(&file) } {
-                            Ok(m) => Some(m),
-                            Err(_) => None,
-                        }
-                    }
-                    Err(_) => None,
-                }
-            } else {
-                None
-            };
-            mmaps.push(mmap);
+            mmaps.push(Self::map_file(&depth_file_path(data_path, index.generation, depth)));
         }
 
-        Self::validate_index_offsets(&index, &mmaps, data_path)?;
-
         Ok(RkyvMmapCache {
+            names: Self::map_file(&names_file_path(data_path, index.generation)),
             index,
             mmaps,
+            name_writer: None,
             base_path: data_path.to_path_buf(),
+            blocks: Mutex::new(BlockCache::default()),
         })
     }
 
-    /// Generate depth-split data file path
-    fn depth_file_path(base_path: &Path, depth: u32) -> PathBuf {
-        let stem = base_path.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
-        let parent = base_path.parent().unwrap_or_else(|| Path::new("."));
-        parent.join(format!("{}-d{}.dat", stem, depth))
+    fn map_file(path: &Path) -> Option<Mmap> {
+        if !path.exists() {
+            return None;
+        }
+        File::open(path).ok().and_then(|file| unsafe { Mmap::map(&file) }.ok())
     }
 
-    fn validate_index_offsets(index: &RkyvCacheIndex, mmaps: &[Option<Mmap>], data_path: &Path) -> Result<()> {
-        for (path, (depth, offset)) in &index.offsets {
-            if *depth >= 31 {
-                anyhow::bail!("indexed depth {} for {} exceeds supported maximum", depth, path.display());
-            }
+    /// Depth shard of the index's generation
+    fn depth_file_path(&self, depth: u32) -> PathBuf {
+        depth_file_path(&self.base_path, self.index.generation, depth)
+    }
 
-            let Some(mmap) = mmaps[*depth as usize].as_ref() else {
-                anyhow::bail!(
-                    "missing cache shard {} for indexed path {}",
-                    Self::depth_file_path(data_path, *depth).display(),
-                    path.display()
-                );
+    /// Shards whose header does not carry the index's generation (left over from an
+    /// interrupted save, or written by an older version).
+    fn mismatched_shards(&self) -> Vec<(PathBuf, String)> {
+        let mut depths: Vec<u32> = self.index.offsets.locations().map(|(depth, _)| depth).collect();
+        depths.sort_unstable();
+        depths.dedup();
+
+        let mut mismatched = Vec::new();
+        for depth in depths.into_iter().filter(|depth| *depth < 31) {
+            let problem = match self.shard_range(depth, 0, SHARD_HEADER_LEN) {
+                Ok(header) => {
+                    match shard_generation(&header) {
+                        Some(generation) if generation == self.index.generation => continue,
+                        Some(generation) => {
+                            format!("shard is from generation {generation}, index expects {}", self.index.generation)
+                        }
+                        None => "shard has no ptree header".to_string(),
+                    }
+                }
+                Err(err) => err.to_string(),
             };
+            mismatched.push((self.depth_file_path(depth), problem));
+        }
 
-            let offset = *offset as usize;
-            if offset + 4 > mmap.len() {
-                anyhow::bail!("offset out of bounds for {}", path.display());
+        if !self.index.offsets.is_empty() {
+            let problem = match self.names.as_deref().map(|table| header_generation(table, NAMES_MAGIC)) {
+                Some(Some(generation)) if generation == self.index.generation => None,
+                Some(Some(generation)) => {
+                    Some(format!("name table is from generation {generation}, index expects {}", self.index.generation))
+                }
+                Some(None) => Some("name table has no ptree header".to_string()),
+                None => Some("missing name table".to_string()),
+            };
+            if let Some(problem) = problem {
+                mismatched.push((names_file_path(&self.base_path, self.index.generation), problem));
             }
+        }
+        mismatched
+    }
 
-            let len = u32::from_le_bytes([mmap[offset], mmap[offset + 1], mmap[offset + 2], mmap[offset + 3]]) as usize;
+    /// The name at `offset` of the snapshot's name table.
+    pub fn name(&self, offset: u32) -> Result<&str> {
+        name_at(self.names.as_deref().unwrap_or_default(), offset)
+    }
+
+    /// Owned entry for the record of `path`, its names resolved.
+    pub fn entry_from_record(&self, path: &Path, record: &ArchivedShardRecord) -> Result<RkyvDirEntry> {
+        Ok(RkyvDirEntry {
+            path:           path.to_path_buf(),
+            name:           Name::new(self.name(record.name)?),
+            modified:       record.modified(),
+            content_hash:   record.content_hash,
+            file_count:     record.file_count as usize,
+            total_size:     record.total_size,
+            allocated_size: record.allocated_size,
+            children:       record
+                .children
+                .iter()
+                .map(|child| self.name(*child).map(Name::new))
+                .collect::<Result<_>>()?,
+            is_hidden:      record.is_hidden,
+            is_dir:         record.is_dir,
+            id:             record.id,
+            file_id:        record.file_id,
+            scan:           record.scan,
+        })
+    }
 
-            if offset + 4 + len > mmap.len() {
-                anyhow::bail!("truncated cache record for {}", path.display());
+    /// Check that every indexed record (or, in compressed shards, its block) starts inside its
+    /// shard, from the shard sizes alone.
+    fn check_index_bounds(&self) -> Result<()> {
+        let mut lengths = [None; 31];
+        for (depth, offset) in self.index.offsets.locations() {
+            if depth >= 31 {
+                anyhow::bail!("indexed depth {} exceeds supported maximum", depth);
+            }
+            let len = match lengths[depth as usize] {
+                Some(len) => len,
+                None => {
+                    let len = match &self.mmaps[depth as usize] {
+                        Some(mmap) => mmap.len() as u64,
+                        None => fs::metadata(self.depth_file_path(depth)).map_or(0, |metadata| metadata.len()),
+                    };
+                    *lengths[depth as usize].insert(len)
+                }
+            };
+            let (start, header_len) = if self.index.compressed {
+                (unpack_block_offset(offset).0, BLOCK_HEADER_LEN)
+            } else {
+                (offset, RECORD_HEADER_LEN)
+            };
+            if start < SHARD_HEADER_LEN || start.saturating_add(header_len) > len {
+                anyhow::bail!(
+                    "corrupt cache index: offset {} out of bounds of {}",
+                    offset,
+                    self.depth_file_path(depth).display()
+                );
             }
         }
-
         Ok(())
     }
 
-    /// O(1) lookup: get single directory entry via depth-specific mmap offset
-    /// Deserializes from depth-split mmap'd region
-    pub fn get_entry(&self, path: &std::path::Path) -> Result<Option<RkyvDirEntry>> {
-        let (depth, offset) = match self.index.offsets.get(path) {
-            Some((d, o)) => (*d, *o),
-            None => return Ok(None),
-        };
+    /// `len` bytes of the depth shard at `start`, from the mapping or (for data appended
+    /// after the shard was mapped) from the file.
+    fn shard_range(&self, depth: u32, start: u64, len: u64) -> Result<Cow<'_, [u8]>> {
+        let end = start
+            .checked_add(len)
+            .ok_or_else(|| corrupt(format!("offset {} out of bounds", start)))?;
+        if let Some(mmap) = self.mmaps[depth as usize].as_ref() {
+            if end <= mmap.len() as u64 {
+                return Ok(Cow::Borrowed(&mmap[start as usize..end as usize]));
+            }
+        }
+
+        let mut file = File::open(self.depth_file_path(depth))
+            .map_err(|e| corrupt(format!("missing shard for depth {}: {e}", depth)))?;
+        if end > file.metadata()?.len() {
+            return Err(corrupt(format!("offset {} out of bounds", start)));
+        }
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0u8; len as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+
+    /// Decompressed records of the block starting at `start`.
+    fn block(&self, depth: u32, start: u64) -> Result<Arc<AlignedVec>> {
+        if let Some(block) = self.blocks.lock().get((depth, start)) {
+            return Ok(block);
+        }
 
-        // Get mmap for this depth (0-30)
+        let header = self.shard_range(depth, start, BLOCK_HEADER_LEN)?;
+        let frame_len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let raw_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if raw_len > MAX_BLOCK_BYTES {
+            return Err(corrupt(format!("block at offset {} claims {} bytes", start, raw_len)));
+        }
+        let frame = self.shard_range(depth, start + BLOCK_HEADER_LEN, frame_len)?;
+        // The frame records its own size too; both have to agree before anything is allocated
+        if !matches!(zstd::zstd_safe::get_frame_content_size(&frame), Ok(Some(size)) if size == raw_len as u64) {
+            return Err(corrupt(format!("block header at offset {} does not match its frame", start)));
+        }
+        let mut block = AlignedVec::with_capacity(raw_len);
+        block.resize(raw_len, 0);
+        let decompressed = zstd::bulk::decompress_to_buffer(&frame, &mut block)
+            .map_err(|e| corrupt(format!("undecodable block at offset {}: {e}", start)))?;
+        if decompressed != raw_len {
+            return Err(corrupt(format!("short block at offset {}", start)));
+        }
+
+        let block = Arc::new(block);
+        self.blocks.lock().insert((depth, start), Arc::clone(&block));
+        Ok(block)
+    }
+
+    /// Run `f` on the checksum-verified payload of the record at (`depth`, `offset`).
+    fn with_record<R>(&self, depth: u32, offset: u64, f: impl FnOnce(&[u8]) -> Result<R>) -> Result<R> {
         if depth >= 31 {
-            return Err(anyhow::anyhow!("Path depth {} exceeds maximum of 30", depth));
+            anyhow::bail!("indexed depth {} exceeds supported maximum", depth);
         }
-        let mmap = self.mmaps[depth as usize]
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("No mmap loaded for depth {}", depth))?;
 
-        let data_slice = &mmap[offset as usize..];
+        if self.index.compressed {
+            let (block_start, offset_in_block) = unpack_block_offset(offset);
+            let block = self.block(depth, block_start)?;
+            return f(record_payload(&block, offset_in_block)?);
+        }
 
-        // Read length prefix
-        if data_slice.len() < 4 {
-            return Ok(None);
+        let header = self.shard_range(depth, offset, RECORD_HEADER_LEN)?;
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let record = self.shard_range(depth, offset, RECORD_HEADER_LEN + len)?;
+        f(record_payload(&record, 0)?)
+    }
+
+    /// Check that every shard belongs to this index, then walk the index and check every
+    /// record: bounds, checksum, decoding and its names.
+    pub fn verify(&self) -> VerifyReport {
+        let mut report = VerifyReport {
+            records: self.index.offsets.len(),
+            corrupt: self.mismatched_shards(),
+        };
+
+        for (path, (depth, offset)) in self.index.offsets.iter() {
+            let checked = self.with_record(depth, offset, |payload| {
+                with_archive(payload, |record| {
+                    let stored = self.name(record.name)?;
+                    let name = path.file_name().map(|name| name.to_string_lossy());
+                    if name.is_some_and(|name| name != stored) {
+                        anyhow::bail!("record belongs to {}", stored);
+                    }
+                    for child in record.children.iter() {
+                        self.name(*child)?;
+                    }
+                    Ok(())
+                })
+            });
+            if let Err(problem) = checked {
+                report.corrupt.push((path, problem.to_string()));
+            }
         }
 
-        let len = u32::from_le_bytes([data_slice[0], data_slice[1], data_slice[2], data_slice[3]]) as usize;
+        report.corrupt.sort();
+        report
+    }
 
-        if data_slice.len() < 4 + len {
+    /// O(1) lookup: run `f` on the validated archive of one directory, read in place from
+    /// the depth-split mmap (or the memoized block of a compressed shard) without copying
+    pub fn with_entry<R>(&self, path: &Path, f: impl FnOnce(&ArchivedShardRecord) -> Result<R>) -> Result<Option<R>> {
+        let Some((depth, offset)) = self.index.offsets.get(path) else {
             return Ok(None);
+        };
+
+        // A bad record is an error, never a silently missing entry
+        self.with_record(depth, offset, |payload| with_archive(payload, f))
+            .map(Some)
+            .with_context(|| path.display().to_string())
+    }
+
+    /// Run `f` on every indexed archive (with its path) until it returns `Some`.
+    pub fn find_entry<R>(&self, mut f: impl FnMut(&Path, &ArchivedShardRecord) -> Option<R>) -> Result<Option<R>> {
+        for path in self.index.offsets.paths() {
+            if let Some(Some(found)) = self.with_entry(&path, |entry| Ok(f(&path, entry)))? {
+                return Ok(Some(found));
+            }
         }
+        Ok(None)
+    }
 
-        // Deserialize entry from mmap'd region
-        let entry: RkyvDirEntry = bincode::deserialize(&data_slice[4..4 + len])?;
-        Ok(Some(entry))
+    /// O(1) lookup: owned copy of a single directory entry
+    pub fn get_entry(&self, path: &std::path::Path) -> Result<Option<RkyvDirEntry>> {
+        self.with_entry(path, |record| self.entry_from_record(path, record))
     }
 
     /// Get all entries (full deserialization - only for batch operations or output)
@@ -198,20 +999,24 @@ impl RkyvMmapCache {
     pub fn get_all(&self) -> Result<HashMap<PathBuf, crate::cache::DirEntry>> {
         let mut entries = HashMap::new();
 
-        for path in self.index.offsets.keys() {
-            if let Some(entry) = self.get_entry(path)? {
+        for path in self.index.offsets.paths() {
+            if let Some(entry) = self.get_entry(&path)? {
                 entries.insert(
                     entry.path.clone(),
                     crate::cache::DirEntry {
-                        path:         entry.path,
-                        name:         entry.name,
-                        modified:     entry.modified,
-                        content_hash: entry.content_hash,
-                        file_count:   entry.file_count,
-                        total_size:   entry.total_size,
-                        children:     entry.children,
-                        is_hidden:    entry.is_hidden,
-                        is_dir:       entry.is_dir,
+                        path:           entry.path,
+                        name:           entry.name,
+                        modified:       entry.modified,
+                        content_hash:   entry.content_hash,
+                        file_count:     entry.file_count,
+                        total_size:     entry.total_size,
+                        allocated_size: entry.allocated_size,
+                        children:       entry.children,
+                        is_hidden:      entry.is_hidden,
+                        is_dir:         entry.is_dir,
+                        id:             entry.id,
+                        file_id:        entry.file_id,
+                        scan:           entry.scan,
                     },
                 );
             }
@@ -228,38 +1033,230 @@ impl RkyvMmapCache {
             anyhow::bail!("Path depth {} exceeds maximum of 30", depth);
         }
 
-        let depth_file = Self::depth_file_path(&self.base_path, depth);
+        let depth_file = self.depth_file_path(depth);
         let mut data_file = std::fs::OpenOptions::new()
             .create(true)
             .append(true)
             .open(&depth_file)?;
 
-        let serialized = bincode::serialize(entry)?;
-        let len = serialized.len() as u32;
+        let names_path = names_file_path(&self.base_path, self.index.generation);
+        let mut names = match self.name_writer.take() {
+            Some(names) => names,
+            None if self.names.is_some() => NameTableWriter::resume(self.names.as_deref().unwrap_or_default())?,
+            None => {
+                let mut names = NameTableWriter::new();
+                names.write(&names_path, self.index.generation)?;
+                names
+            }
+        };
+        let record = encode_record(entry, &mut names)?;
+        // Names first: a record is never on disk before the names it refers to
+        names.append(&names_path)?;
+        self.name_writer = Some(names);
+        self.names = Self::map_file(&names_path);
 
-        let offset = data_file.seek(SeekFrom::End(0))?;
+        // The record this one replaces stays in its shard until the next compaction
+        // (for compressed shards its uncompressed size stands in for the wasted bytes)
+        if let Some((old_depth, old_offset)) = self.index.offsets.get(&entry.path) {
+            self.index.stale_bytes += self
+                .with_record(old_depth, old_offset, |payload| Ok(RECORD_HEADER_LEN + payload.len() as u64))
+                .unwrap_or(0);
+        }
 
-        data_file.write_all(&len.to_le_bytes())?;
-        data_file.write_all(&serialized)?;
+        // Compressed shards get a block of their own for the appended record
+        let end = data_file.seek(SeekFrom::End(0))?;
+        let mut writer = if end == 0 {
+            ShardWriter::create(&mut data_file, self.index.compressed, self.index.generation)?
+        } else {
+            ShardWriter::new(&mut data_file, self.index.compressed, end)
+        };
+        let offset = writer.push(&record)?;
+        writer.finish()?;
         data_file.sync_all()?;
 
         // Update index with (depth, offset)
-        self.index.offsets.insert(entry.path.clone(), (depth, offset));
+        self.index.offsets.insert(&entry.path, (depth, offset));
 
         Ok((depth, offset))
     }
 
+    /// Forget the subtree at `path` without rewriting the shards: its records leave the index
+    /// (and count as stale), the directories above get records without its totals, and `path`
+    /// joins `pending_dirs`, so the next scan reads it like a directory an interrupted scan
+    /// left. Returns how many directories were forgotten (none if `path` is not cached). The
+    /// caller holds the cache lock, opened `self` under it, and saves the index afterwards.
+    pub fn invalidate_subtree(&mut self, path: &Path) -> Result<usize> {
+        let Some(entry) = self.get_entry(path)? else {
+            return Ok(0);
+        };
+        let forgotten: Vec<_> = self
+            .index
+            .offsets
+            .iter()
+            .filter(|(below, _)| below.starts_with(path))
+            .collect();
+        let mut above = Vec::new();
+        for ancestor in path.ancestors().skip(1) {
+            if let Some(mut ancestor) = self.get_entry(ancestor)? {
+                ancestor.file_count = ancestor.file_count.saturating_sub(entry.file_count);
+                ancestor.total_size = ancestor.total_size.saturating_sub(entry.total_size);
+                ancestor.allocated_size = ancestor.allocated_size.saturating_sub(entry.allocated_size);
+                above.push(ancestor);
+            }
+        }
+
+        for (below, (depth, offset)) in &forgotten {
+            self.index.stale_bytes += self
+                .with_record(*depth, *offset, |payload| Ok(RECORD_HEADER_LEN + payload.len() as u64))
+                .unwrap_or(0);
+            self.index.offsets.remove(below);
+        }
+        for ancestor in &above {
+            self.append_entry(ancestor)?;
+        }
+
+        let index = &mut self.index;
+        index.total_files = index.total_files.saturating_sub(entry.file_count);
+        index.entry_metadata.retain(|recorded, _| !recorded.starts_with(path));
+        index.unsettled_dirs.retain(|unsettled| !unsettled.starts_with(path));
+        index.pending_dirs.retain(|pending| !pending.starts_with(path));
+        index.pending_dirs.push(path.to_path_buf());
+        // The list loses the subtree and shrinks the directories above; the next save refills it
+        index.largest_dirs.retain(|dir| !dir.path.starts_with(path));
+        for dir in &mut index.largest_dirs {
+            if let Some(ancestor) = above.iter().find(|ancestor| ancestor.path == dir.path) {
+                dir.total_size = ancestor.total_size;
+                dir.allocated_size = ancestor.allocated_size;
+            }
+        }
+        keep_largest_dirs(&mut index.largest_dirs);
+        Ok(forgotten.len())
+    }
+
     /// Save index to disk (bincode serialized)
     pub fn save_index(&self, path: &std::path::Path) -> Result<()> {
-        let data = bincode::serialize(&self.index)?;
-        let temp_path = path.with_extension("tmp");
+        write_index(&self.index, path)
+    }
 
-        let mut file = File::create(&temp_path)?;
-        file.write_all(&data)?;
-        file.sync_all()?;
+    /// Total bytes of every depth shard on disk.
+    pub fn shard_bytes(&self) -> u64 {
+        (0..31)
+            .filter_map(|depth| fs::metadata(self.depth_file_path(depth)).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
 
-        fs::rename(&temp_path, path)?;
-        Ok(())
+    /// True when enough of the shards is superseded records that a rewrite is worth it.
+    pub fn needs_compaction(&self) -> bool {
+        let stale = self.index.stale_bytes;
+        stale >= AUTO_COMPACT_MIN_STALE_BYTES && stale * 100 >= self.shard_bytes() * AUTO_COMPACT_STALE_PERCENT
+    }
+
+    /// Rewrite every shard with only the records the index still points at, then save the index.
+    /// The caller holds the cache lock, and opened `self` under it.
+    pub fn compact(&mut self, index_path: &Path) -> Result<CompactionStats> {
+        let compressed = self.index.compressed;
+        self.rewrite(index_path, compressed)
+    }
+
+    /// Rewrite the live records into plain or compressed shards, then save the index.
+    ///
+    /// The shards are written under the next generation beside the current ones, and saving
+    /// the index is what switches to them; a crash before that leaves the current snapshot as
+    /// it was. The mappings are dropped before the old generation is removed (Windows cannot
+    /// delete a mapped file) and re-established on the new one.
+    pub fn rewrite(&mut self, index_path: &Path, compressed: bool) -> Result<CompactionStats> {
+        let bytes_before = self.shard_bytes();
+        let generation = self.index.generation + 1;
+
+        let mut written = Vec::new();
+        let rewritten = self.write_generation(generation, compressed, &mut written);
+        let committed = rewritten.and_then(|offsets| {
+            let mut index = self.index.clone();
+            index.offsets = offsets;
+            index.stale_bytes = 0;
+            index.compressed = compressed;
+            index.generation = generation;
+            write_index(&index, index_path)?;
+            Ok(index)
+        });
+        let index = match committed {
+            Ok(index) => index,
+            Err(err) => {
+                for path in &written {
+                    let _ = fs::remove_file(path);
+                }
+                return Err(err);
+            }
+        };
+
+        for mmap in &mut self.mmaps {
+            *mmap = None;
+        }
+        self.names = None;
+        self.name_writer = None;
+        self.blocks.lock().clear();
+        // Superseded now; a reader that still maps them keeps its view (or, on Windows, the
+        // files stay until the next save clears old generations)
+        for depth in 0..31u32 {
+            let _ = fs::remove_file(self.depth_file_path(depth));
+        }
+        let _ = fs::remove_file(names_file_path(&self.base_path, self.index.generation));
+
+        self.index = index;
+        for depth in 0..31u32 {
+            self.mmaps[depth as usize] = Self::map_file(&self.depth_file_path(depth));
+        }
+        self.names = Self::map_file(&names_file_path(&self.base_path, generation));
+
+        Ok(CompactionStats {
+            records: self.index.offsets.len(),
+            bytes_before,
+            bytes_after: self.shard_bytes(),
+        })
+    }
+
+    /// Write the live records to the shards and name table of `generation`, adding each file
+    /// to `written`, and return where the records now are.
+    fn write_generation(&self, generation: u64, compressed: bool, written: &mut Vec<PathBuf>) -> Result<PathIndex> {
+        let mut by_depth: HashMap<u32, Vec<(PathBuf, u64)>> = HashMap::new();
+        for (path, (depth, offset)) in self.index.offsets.iter() {
+            by_depth.entry(depth).or_default().push((path, offset));
+        }
+
+        let mut new_offsets = PathIndex::with_capacity(self.index.offsets.len());
+        let mut names = NameTableWriter::new();
+        for depth in 0..31u32 {
+            let Some(mut records) = by_depth.remove(&depth) else {
+                continue;
+            };
+            // Shard order keeps block reads sequential; paths keep siblings together in new blocks
+            if compressed {
+                records.sort();
+            } else {
+                records.sort_by_key(|(_, offset)| *offset);
+            }
+
+            let shard_path = depth_file_path(&self.base_path, generation, depth);
+            written.push(shard_path.clone());
+            let mut writer =
+                ShardWriter::create(std::io::BufWriter::new(File::create(&shard_path)?), compressed, generation)?;
+            // Re-encoded against a fresh name table, which drops names only superseded records used
+            for (path, offset) in records {
+                let entry = self.with_record(depth, offset, |payload| {
+                    with_archive(payload, |record| self.entry_from_record(&path, record))
+                })?;
+                new_offsets.insert(&path, (depth, writer.push(&encode_record(&entry, &mut names)?)?));
+            }
+            let mut out = writer.finish()?;
+            out.flush()?;
+            out.get_ref().sync_all()?;
+        }
+
+        let names_path = names_file_path(&self.base_path, generation);
+        written.push(names_path.clone());
+        names.write(&names_path, generation)?;
+        Ok(new_offsets)
     }
 
     pub fn len(&self) -> usize {
@@ -280,23 +1277,42 @@ mod tests {
     #[test]
     fn test_rkyv_dir_entry_serialization() -> Result<()> {
         let entry = RkyvDirEntry {
-            path:         PathBuf::from("C:\\test"),
-            name:         "test".to_string(),
-            modified:     Utc::now(),
-            content_hash: 12345u64,
-            file_count:   2,
-            total_size:   4096,
-            children:     vec!["child1".to_string(), "child2".to_string()],
-            is_hidden:    false,
-            is_dir:       true,
+            path:           PathBuf::from("C:\\test"),
+            name:           Name::new("test"),
+            modified:       DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            content_hash:   12345u64,
+            file_count:     2,
+            total_size:     4096,
+            allocated_size: 4096,
+            children:       vec![Name::new("child1"), Name::new("child2")],
+            is_hidden:      false,
+            is_dir:         true,
+            id:             7,
+            file_id:        0,
+            scan:           0,
         };
 
-        let serialized = bincode::serialize(&entry)?;
-        let deserialized: RkyvDirEntry = bincode::deserialize(&serialized)?;
+        let mut names = NameTableWriter::new();
+        let record = encode_record(&entry, &mut names)?;
+        let payload = record_payload(&record, 0)?;
+        let archived = rkyv::check_archived_root::<ShardRecord>(payload).unwrap();
+
+        // "test", "child1", "child2": each stored once, right after the header
+        let mut table = shard_header_with(NAMES_MAGIC, 0).to_vec();
+        table.extend_from_slice(&names.pending);
+        assert_eq!(archived.name as u64, SHARD_HEADER_LEN);
+        assert_eq!(name_at(&table, archived.name)?, "test");
+        assert_eq!(name_at(&table, archived.children[1])?, "child2");
+        assert_eq!(names.offset(&Name::new("child1"))?, archived.children[0]);
+        assert_eq!(archived.modified(), entry.modified);
+        assert_eq!(archived.content_hash, entry.content_hash);
+        assert_eq!(archived.id, 7);
 
-        assert_eq!(entry.name, deserialized.name);
-        assert_eq!(entry.content_hash, deserialized.content_hash);
-        assert_eq!(entry.children.len(), deserialized.children.len());
+        // A payload off its alignment boundary is still readable (through a copy)
+        let mut shifted = vec![0u8; 1];
+        shifted.extend_from_slice(payload);
+        let copied = with_archive(&shifted[1..], |archived| Ok((archived.modified(), archived.file_count)))?;
+        assert_eq!(copied, (entry.modified, 2));
 
         Ok(())
     }
@@ -314,4 +1330,107 @@ mod tests {
         let _ = fs::remove_dir_all(&temp_dir);
         Ok(())
     }
+
+    #[test]
+    fn test_compact_drops_superseded_records() -> Result<()> {
+        for compressed in [false, true] {
+            let temp_dir = env::temp_dir().join(format!("ptree_rkyv_compact_{}_{}", compressed, std::process::id()));
+            let _ = fs::remove_dir_all(&temp_dir);
+            fs::create_dir_all(&temp_dir)?;
+            let index_path = temp_dir.join("test.idx");
+            let data_path = temp_dir.join("test.dat");
+
+            let mut cache = RkyvMmapCache::open(&index_path, &data_path)?;
+            cache.index.compressed = compressed;
+            let mut entry = RkyvDirEntry {
+                path:           temp_dir.join("dir"),
+                name:           Name::new("dir"),
+                modified:       Utc::now(),
+                content_hash:   0,
+                file_count:     0,
+                total_size:     0,
+                allocated_size: 0,
+                children:       Vec::new(),
+                is_hidden:      false,
+                is_dir:         true,
+                id:             0,
+                file_id:        0,
+                scan:           0,
+            };
+            for file_count in 0..10 {
+                entry.file_count = file_count;
+                cache.append_entry(&entry)?;
+            }
+            assert!(cache.index.stale_bytes > 0);
+
+            let stats = cache.compact(&index_path)?;
+            assert_eq!(stats.records, 1);
+            // Ten same-sized records (compressed frames may differ by a byte or two), one kept
+            let before = stats.bytes_before - SHARD_HEADER_LEN;
+            let after = stats.bytes_after - SHARD_HEADER_LEN;
+            assert!(after * 9 < before && before <= after * 10);
+            assert_eq!(cache.index.stale_bytes, 0);
+
+            let reopened = RkyvMmapCache::open(&index_path, &data_path)?;
+            assert_eq!(reopened.index.compressed, compressed);
+            assert_eq!(reopened.get_entry(&entry.path)?.map(|e| e.file_count), Some(9));
+
+            let _ = fs::remove_dir_all(&temp_dir);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_blocks_are_bounded_in_memory_and_on_read() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_rkyv_blocks_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let index_path = temp_dir.join("test.idx");
+        let data_path = temp_dir.join("test.dat");
+
+        let mut cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        cache.index.compressed = true;
+        let paths: Vec<PathBuf> = (0..20).map(|i| temp_dir.join(format!("dir{i}"))).collect();
+        for path in &paths {
+            cache.append_entry(&RkyvDirEntry {
+                path:           path.clone(),
+                name:           Name::new(&path.file_name().unwrap().to_string_lossy()),
+                modified:       Utc::now(),
+                content_hash:   0,
+                file_count:     0,
+                total_size:     0,
+                allocated_size: 0,
+                children:       Vec::new(),
+                is_hidden:      false,
+                is_dir:         true,
+                id:             0,
+                file_id:        0,
+                scan:           0,
+            })?;
+        }
+        cache.save_index(&index_path)?;
+
+        // Every record is its own appended block; only the last few read stay decompressed
+        let cache = RkyvMmapCache::open(&index_path, &data_path)?;
+        for path in &paths {
+            assert!(cache.get_entry(path)?.is_some());
+        }
+        assert_eq!(cache.blocks.lock().blocks.len(), BLOCK_CACHE_BLOCKS);
+        drop(cache);
+
+        // A block header claiming more than any block holds is refused before allocating it
+        let depth = compute_depth(&paths[0]);
+        let shard = depth_file_path(&data_path, 0, depth);
+        let mut bytes = fs::read(&shard)?;
+        let raw_len = SHARD_HEADER_LEN as usize + 4;
+        bytes[raw_len..raw_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&shard, bytes)?;
+        let err = RkyvMmapCache::open(&index_path, &data_path)?
+            .get_entry(&paths[0])
+            .unwrap_err();
+        assert!(is_corrupt_cache(&err), "{err:#}");
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
 }
@@ -0,0 +1,101 @@
+use std::io;
+use std::path::Path;
+
+/// True if `err` (or any error in its chain) means the target volume is out of space.
+pub fn is_storage_full(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+            return is_storage_full_io(io_err);
+        }
+        if let Some(bincode_err) = cause.downcast_ref::<bincode::Error>() {
+            if let bincode::ErrorKind::Io(io_err) = bincode_err.as_ref() {
+                return is_storage_full_io(io_err);
+            }
+        }
+        false
+    })
+}
+
+fn is_storage_full_io(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::StorageFull {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        err.raw_os_error() == Some(libc::ENOSPC) || err.raw_os_error() == Some(libc::EDQUOT)
+    }
+
+    #[cfg(windows)]
+    {
+        const ERROR_HANDLE_DISK_FULL: i32 = 39;
+        const ERROR_DISK_FULL: i32 = 112;
+        matches!(err.raw_os_error(), Some(ERROR_HANDLE_DISK_FULL) | Some(ERROR_DISK_FULL))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        false
+    }
+}
+
+/// Bytes available to the current user on the volume holding `path`, if it can be determined.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes available to the current user on the volume holding `path`, if it can be determined.
+#[cfg(windows)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut free_to_caller = 0u64;
+    let ok =
+        unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut free_to_caller, std::ptr::null_mut(), std::ptr::null_mut()) };
+
+    (ok != 0).then_some(free_to_caller)
+}
+
+/// Bytes available to the current user on the volume holding `path`, if it can be determined.
+#[cfg(not(any(unix, windows)))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn detects_enospc_through_anyhow_and_bincode() {
+        let raw = anyhow::Error::from(io::Error::from_raw_os_error(libc::ENOSPC));
+        assert!(is_storage_full(&raw));
+
+        let wrapped: bincode::Error = Box::new(bincode::ErrorKind::Io(io::Error::from_raw_os_error(libc::ENOSPC)));
+        assert!(is_storage_full(&anyhow::Error::from(wrapped)));
+
+        let other = anyhow::Error::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(!is_storage_full(&other));
+    }
+
+    #[test]
+    fn reports_available_space_for_temp_dir() {
+        #[cfg(any(unix, windows))]
+        assert!(available_space(&std::env::temp_dir()).is_some());
+    }
+}
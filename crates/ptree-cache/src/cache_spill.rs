@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{DirEntry, DiskCache, EntryIds};
+use crate::names::Name;
+
+/// A spilled directory, with the children that are directories themselves (a trimmed
+/// snapshot keeps only those, and they are no longer in memory to check).
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SpillRecord {
+    pub(crate) entry:        DirEntry,
+    pub(crate) dir_children: Vec<Name>,
+}
+
+/// Subtrees a `--max-memory` scan evicted from `DiskCache::entries`.
+///
+/// Records go to one append-only temp file per depth (`ptree-<hash>-spill-d<N>.tmp`) as
+/// `[len: u32 LE][bincode SpillRecord]`; the next save streams them into the depth shards
+/// and removes the files. The topmost directory of each spilled subtree stays in memory
+/// with its totals, so the scan can finish and render without reading them back.
+#[derive(Debug, Clone, Default)]
+pub struct SpillStore {
+    /// Cache file the spill files sit next to; empty until `start`
+    base:        PathBuf,
+    /// Bytes spilled per depth
+    depths:      BTreeMap<u32, u64>,
+    entries:     usize,
+    /// Spilled subtree roots still in memory, with their directory children
+    roots:       HashMap<PathBuf, Vec<Name>>,
+    /// Stable IDs the spilled entries claimed, so no in-memory entry claims them again
+    claimed_ids: HashSet<u64>,
+    /// Estimate at which the next spill is attempted (a round that frees little is not
+    /// retried on every flush)
+    next_check:  u64,
+}
+
+impl SpillStore {
+    /// Spill next to `cache_path`, removing files a crashed scan may have left.
+    pub(crate) fn start(&mut self, cache_path: &Path) -> Result<()> {
+        *self = SpillStore {
+            base: cache_path.to_path_buf(),
+            ..Default::default()
+        };
+        self.remove_files()
+    }
+
+    /// Directories spilled so far.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Bytes spilled so far.
+    pub fn bytes(&self) -> u64 {
+        self.depths.values().sum()
+    }
+
+    pub(crate) fn depths(&self) -> impl Iterator<Item = u32> + '_ {
+        self.depths.keys().copied()
+    }
+
+    pub(crate) fn is_root(&self, path: &Path) -> bool {
+        self.roots.contains_key(path)
+    }
+
+    pub(crate) fn claimed_ids(&self) -> &HashSet<u64> {
+        &self.claimed_ids
+    }
+
+    fn file_path(&self, depth: u32) -> PathBuf {
+        let stem = self.base.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+        let parent = self.base.parent().unwrap_or_else(|| Path::new("."));
+        parent.join(format!("{}-spill-d{}.tmp", stem, depth))
+    }
+
+    fn append(&mut self, depth: u32, records: &[SpillRecord]) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file_path(depth))?;
+        let mut writer = BufWriter::new(file);
+        let mut written = 0u64;
+        for record in records {
+            let bytes = bincode::serialize(record)?;
+            writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(&bytes)?;
+            written += 4 + bytes.len() as u64;
+        }
+        writer.flush()?;
+        *self.depths.entry(depth).or_default() += written;
+        Ok(())
+    }
+
+    /// Stream the records spilled at `depth`, in spill order.
+    pub(crate) fn read(&self, depth: u32, mut f: impl FnMut(SpillRecord) -> Result<()>) -> Result<()> {
+        if !self.depths.contains_key(&depth) {
+            return Ok(());
+        }
+
+        let mut reader = BufReader::new(File::open(self.file_path(depth))?);
+        let mut len = [0u8; 4];
+        let mut bytes = Vec::new();
+        loop {
+            match reader.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+            bytes.resize(u32::from_le_bytes(len) as usize, 0);
+            reader.read_exact(&mut bytes)?;
+            f(bincode::deserialize(&bytes)?)?;
+        }
+    }
+
+    /// Forget every spilled record and remove the files (once a save has taken them over).
+    pub(crate) fn clear(&mut self) -> Result<()> {
+        let result = self.remove_files();
+        *self = SpillStore::default();
+        result
+    }
+
+    fn remove_files(&self) -> Result<()> {
+        if self.base.as_os_str().is_empty() {
+            return Ok(());
+        }
+        let stem = self.base.file_stem().and_then(|s| s.to_str()).unwrap_or("ptree");
+        let prefix = format!("{}-spill-d", stem);
+        let parent = self.base.parent().unwrap_or_else(|| Path::new("."));
+        let Ok(dir) = fs::read_dir(parent) else {
+            return Ok(());
+        };
+        for entry in dir {
+            let path = entry?.path();
+            let spill_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|rest| rest.strip_suffix(".tmp"))
+                .is_some_and(|depth| !depth.is_empty() && depth.bytes().all(|b| b.is_ascii_digit()));
+            if spill_file {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DiskCache {
+    /// Approximate heap bytes of one entry in `entries`: the entry, its key, both copies of
+    /// the path, the children IDs and the map slot. Names themselves are interned and shared.
+    pub fn entry_memory(path: &Path, entry: &DirEntry) -> u64 {
+        (size_of::<DirEntry>()
+            + size_of::<PathBuf>()
+            + path.as_os_str().len()
+            + entry.path.as_os_str().len()
+            + entry.children.capacity() * size_of::<Name>()
+            + 16) as u64
+    }
+
+    /// Running estimate of the memory `entries` takes (see `recount_memory`).
+    pub fn memory_estimate(&self) -> u64 {
+        self.memory_estimate
+    }
+
+    /// Recompute the memory estimate from every entry; flushes keep it up to date afterwards.
+    pub fn recount_memory(&mut self) {
+        self.memory_estimate = self
+            .entries
+            .iter()
+            .map(|(path, entry)| Self::entry_memory(path, entry))
+            .sum();
+    }
+
+    /// Whether the entries exceed `--max-memory` and a spill is due.
+    pub fn over_memory_budget(&self) -> bool {
+        self.max_memory_bytes
+            .is_some_and(|budget| self.memory_estimate > budget && self.memory_estimate >= self.spill.next_check)
+    }
+
+    /// Evict subtrees to files next to `cache_path` from now on (see `spill_subtrees`).
+    pub fn start_spill(&mut self, cache_path: &Path) -> Result<()> {
+        self.spill.start(cache_path)
+    }
+
+    /// Directories evicted to disk by the memory budget and not yet saved.
+    pub fn spilled_dirs(&self) -> usize {
+        self.spill.entries
+    }
+
+    /// Spill the fully scanned subtrees under `roots` to disk: every directory below a root
+    /// gets its final totals, content hash and stable ID, is written out and leaves memory;
+    /// the root stays with the subtree's totals. Returns how many directories were spilled.
+    pub fn spill_subtrees(&mut self, roots: &[PathBuf], previous: &EntryIds) -> Result<usize> {
+        self.flush_pending_writes();
+        let mut spilled = 0;
+
+        for root in roots {
+            let mut subtree = Vec::new();
+            let mut stack = vec![root.clone()];
+            while let Some(path) = stack.pop() {
+                let Some(entry) = self.entries.get(&path) else {
+                    continue;
+                };
+                for child in &entry.children {
+                    let child_path = path.join(child);
+                    if self.entries.contains_key(&child_path) {
+                        stack.push(child_path);
+                    }
+                }
+                subtree.push(path);
+            }
+            if subtree.len() < 2 {
+                continue;
+            }
+
+            self.derive_metadata(subtree.clone());
+            let mut claimed = std::mem::take(&mut self.spill.claimed_ids);
+            self.assign_ids(subtree.clone(), previous, &mut claimed);
+            self.spill.claimed_ids = claimed;
+
+            let mut by_depth: BTreeMap<u32, Vec<SpillRecord>> = BTreeMap::new();
+            for path in &subtree {
+                let dir_children = match self.spill.roots.get(path) {
+                    Some(dir_children) => dir_children.clone(),
+                    None => {
+                        self.entries[path]
+                            .children
+                            .iter()
+                            .filter(|child| self.entries.contains_key(&path.join(child)))
+                            .cloned()
+                            .collect()
+                    }
+                };
+                if path == root {
+                    self.spill.roots.insert(path.clone(), dir_children);
+                    continue;
+                }
+
+                self.spill.roots.remove(path);
+                let entry = self.entries.remove(path).expect("subtree paths are in entries");
+                self.memory_estimate = self.memory_estimate.saturating_sub(Self::entry_memory(path, &entry));
+                by_depth
+                    .entry(path.components().count() as u32)
+                    .or_default()
+                    .push(SpillRecord { entry, dir_children });
+            }
+
+            for (depth, records) in by_depth {
+                spilled += records.len();
+                self.spill.append(depth, &records)?;
+            }
+        }
+
+        self.spill.entries += spilled;
+        // Don't try again until another eighth of the budget has been added
+        self.spill.next_check = self.memory_estimate + self.max_memory_bytes.unwrap_or(0) / 8;
+        Ok(spilled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::testing::dir;
+
+    #[test]
+    fn spilled_subtrees_leave_memory_and_come_back_on_save() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_spill_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        let cache_path = temp_dir.join("ptree.dat");
+        let root = PathBuf::from("/data");
+
+        let mut cache = DiskCache {
+            root: root.clone(),
+            scan: 1,
+            max_memory_bytes: Some(1),
+            ..Default::default()
+        };
+        cache.start_spill(&cache_path)?;
+        for (path, children) in [
+            (root.clone(), vec!["a", "b", "top.txt"]),
+            (root.join("a"), vec!["deep", "a.txt"]),
+            (root.join("a").join("deep"), vec!["d.txt"]),
+            (root.join("b"), vec![]),
+        ] {
+            let entry = DirEntry {
+                file_count: 1,
+                scan: 1,
+                ..dir(&path, 100, &children)
+            };
+            cache.add_entry(path.clone(), entry);
+        }
+        cache.flush_pending_writes();
+        assert!(cache.over_memory_budget());
+
+        let before = cache.memory_estimate();
+        assert_eq!(cache.spill_subtrees(&[root.join("a")], &EntryIds::default())?, 1);
+        assert!(cache.memory_estimate() < before);
+        assert!(!cache.entries.contains_key(&root.join("a").join("deep")));
+        // The subtree root keeps the totals of what was spilled
+        assert_eq!(cache.entries[&root.join("a")].file_count, 2);
+        assert_eq!(cache.entry_count_hint(), 4);
+
+        cache.refresh_derived_metadata();
+        cache.assign_entry_ids(&EntryIds::default());
+        assert_eq!(cache.entries[&root].file_count, 4);
+        cache.save(&cache_path)?;
+        assert_eq!(cache.spilled_dirs(), 0);
+        assert!(!temp_dir.join("ptree-spill-d4.tmp").exists());
+
+        let mut reopened = DiskCache::open(&cache_path)?;
+        reopened.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(reopened.entries.len(), 4);
+        let deep = &reopened.entries[&root.join("a").join("deep")];
+        assert_eq!(deep.children, vec![Name::new("d.txt")]);
+        // IDs handed out at spill time are not handed out again
+        let mut ids: Vec<u64> = reopened.entries.values().map(|entry| entry.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::cache_rkyv::{compute_depth, RkyvCacheIndex, RkyvDirEntry};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key   TEXT PRIMARY KEY,
+        value BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS entries (
+        path       TEXT PRIMARY KEY,
+        depth      INTEGER NOT NULL,
+        name       TEXT NOT NULL,
+        modified   TEXT NOT NULL,
+        file_count INTEGER NOT NULL,
+        total_size INTEGER NOT NULL,
+        is_hidden  INTEGER NOT NULL,
+        id         INTEGER NOT NULL,
+        file_id    INTEGER NOT NULL,
+        record     BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS entries_depth ON entries (depth);
+    CREATE INDEX IF NOT EXISTS entries_id ON entries (id);
+";
+
+/// SQLite-backed cache with the same load/save/lookup surface as `RkyvMmapCache`
+/// (`sqlite` feature).
+///
+/// One database file holds everything:
+/// - `meta`: the bincode-encoded `RkyvCacheIndex` (its `offsets` stay empty; rows are found by path)
+/// - `entries`: one row per directory; `record` is the bincode `RkyvDirEntry`, the other
+///   columns mirror its fields so the snapshot can be queried with plain SQL
+///
+/// Every write is a transaction, so an interrupted save or incremental update never
+/// leaves a half-written snapshot and there are no offsets to keep in step with the data.
+pub struct SqliteCache {
+    pub index: RkyvCacheIndex,
+    conn:      Connection,
+}
+
+impl SqliteCache {
+    /// Open (or create) the database and load its index metadata.
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+
+        let index = conn
+            .query_row("SELECT value FROM meta WHERE key = 'index'", [], |row| row.get::<_, Vec<u8>>(0))
+            .optional()?
+            .map(|data| bincode::deserialize::<RkyvCacheIndex>(&data))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("failed to deserialize cache index: {e}"))?
+            .unwrap_or_default();
+
+        Ok(SqliteCache { index, conn })
+    }
+
+    /// O(log n) lookup of a single directory entry by path
+    pub fn get_entry(&self, path: &Path) -> Result<Option<RkyvDirEntry>> {
+        let record = self
+            .conn
+            .query_row("SELECT record FROM entries WHERE path = ?1", [path.to_string_lossy()], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()?;
+
+        // A bad record is an error, never a silently missing entry
+        record
+            .map(|data| bincode::deserialize::<RkyvDirEntry>(&data))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    /// Entry with a stable ID, if the snapshot has one.
+    pub fn get_by_id(&self, id: u64) -> Result<Option<RkyvDirEntry>> {
+        let record = self
+            .conn
+            .query_row("SELECT record FROM entries WHERE id = ?1", [id as i64], |row| row.get::<_, Vec<u8>>(0))
+            .optional()?;
+        Ok(record
+            .map(|data| bincode::deserialize::<RkyvDirEntry>(&data))
+            .transpose()?)
+    }
+
+    /// Get all entries (full deserialization - only for batch operations or output)
+    pub fn get_all(&self) -> Result<HashMap<PathBuf, crate::cache::DirEntry>> {
+        let mut statement = self.conn.prepare("SELECT record FROM entries")?;
+        let mut rows = statement.query([])?;
+        let mut entries = HashMap::new();
+
+        while let Some(row) = rows.next()? {
+            let entry = bincode::deserialize::<RkyvDirEntry>(&row.get::<_, Vec<u8>>(0)?)?;
+            entries.insert(
+                entry.path.clone(),
+                crate::cache::DirEntry {
+                    path:           entry.path,
+                    name:           entry.name,
+                    modified:       entry.modified,
+                    content_hash:   entry.content_hash,
+                    file_count:     entry.file_count,
+                    total_size:     entry.total_size,
+                    allocated_size: entry.allocated_size,
+                    children:       entry.children,
+                    is_hidden:      entry.is_hidden,
+                    is_dir:         entry.is_dir,
+                    id:             entry.id,
+                    file_id:        entry.file_id,
+                    scan:           entry.scan,
+                },
+            );
+        }
+
+        Ok(entries)
+    }
+
+    /// Insert or replace one entry in its own transaction (incremental updates).
+    pub fn append_entry(&mut self, entry: &RkyvDirEntry) -> Result<()> {
+        let transaction = self.conn.transaction()?;
+        upsert_entry(&transaction, entry)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Replace the whole snapshot and its index in one transaction (full saves).
+    pub fn replace_all<'a>(&mut self, entries: impl IntoIterator<Item = &'a RkyvDirEntry>) -> Result<()> {
+        let transaction = self.conn.transaction()?;
+        transaction.execute("DELETE FROM entries", [])?;
+        for entry in entries {
+            upsert_entry(&transaction, entry)?;
+        }
+        transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('index', ?1)",
+            [bincode::serialize(&self.index)?],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Save the index metadata (totals, roots, flags) without touching the entries.
+    pub fn save_index(&self) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('index', ?1)",
+            [bincode::serialize(&self.index)?],
+        )?;
+        Ok(())
+    }
+
+    /// Run `PRAGMA integrity_check`; returns the problems SQLite reports (empty when healthy).
+    pub fn verify(&self) -> Result<Vec<String>> {
+        let mut statement = self.conn.prepare("PRAGMA integrity_check")?;
+        let problems = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(problems.into_iter().filter(|problem| problem != "ok").collect())
+    }
+
+    /// The underlying connection, for ad-hoc queries over the `entries` table.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn len(&self) -> usize {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM entries", [], |row| row.get::<_, i64>(0))
+            .map(|count| count as usize)
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn upsert_entry(conn: &Connection, entry: &RkyvDirEntry) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO entries
+             (path, depth, name, modified, file_count, total_size, is_hidden, id, file_id, record)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            entry.path.to_string_lossy(),
+            compute_depth(&entry.path),
+            entry.name.as_str(),
+            entry.modified.to_rfc3339(),
+            entry.file_count as i64,
+            entry.total_size as i64,
+            entry.is_hidden,
+            entry.id as i64,
+            entry.file_id as i64,
+            bincode::serialize(entry)?,
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::names::Name;
+
+    fn entry(path: PathBuf, id: u64, total_size: u64) -> RkyvDirEntry {
+        RkyvDirEntry {
+            name: Name::new(&path.file_name().unwrap().to_string_lossy()),
+            path,
+            modified: Utc::now(),
+            content_hash: 0,
+            file_count: 1,
+            total_size,
+            allocated_size: total_size,
+            children: vec![Name::new("file.txt")],
+            is_hidden: false,
+            is_dir: true,
+            id,
+            file_id: 0,
+            scan: 0,
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trips_and_updates_in_place() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_sqlite_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let db_path = temp_dir.join("ptree.sqlite");
+
+        let mut cache = SqliteCache::open(&db_path)?;
+        assert!(cache.is_empty());
+        cache.index.total_files = 2;
+        let entries = [
+            entry(temp_dir.join("a"), 1, 10),
+            entry(temp_dir.join("a").join("b"), 2, 20),
+        ];
+        cache.replace_all(&entries)?;
+
+        cache.append_entry(&entry(temp_dir.join("a"), 1, 30))?;
+        drop(cache);
+
+        let reopened = SqliteCache::open(&db_path)?;
+        assert_eq!(reopened.index.total_files, 2);
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.get_entry(&temp_dir.join("a"))?.map(|e| e.total_size), Some(30));
+        assert_eq!(reopened.get_by_id(2)?.map(|e| e.path), Some(temp_dir.join("a").join("b")));
+        assert_eq!(reopened.get_all()?.len(), 2);
+        assert!(reopened.verify()?.is_empty());
+
+        // The columns beside the record are there for plain SQL
+        let largest: String = reopened.connection().query_row(
+            "SELECT name FROM entries ORDER BY total_size DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(largest, "a");
+
+        drop(reopened);
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
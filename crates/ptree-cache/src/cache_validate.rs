@@ -0,0 +1,186 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::cache::DiskCache;
+use crate::cache_rkyv::RkyvMmapCache;
+
+/// How a sample of cached directories compares to the live filesystem (`ptree validate`).
+#[derive(Debug, Clone, Default)]
+pub struct SampleReport {
+    /// Directories in the snapshot
+    pub entries:    usize,
+    pub sampled:    usize,
+    /// Sampled directories that are gone (or no longer directories)
+    pub missing:    Vec<PathBuf>,
+    /// Sampled directories whose mtime moved since the snapshot, by how much
+    pub drifted:    Vec<(PathBuf, Duration)>,
+    /// Sampled directories that could not be stat'ed (left out of the rate)
+    pub unreadable: usize,
+    pub last_scan:  DateTime<Utc>,
+}
+
+impl SampleReport {
+    /// Share of the checked sample that no longer matches the snapshot, 0.0 to 1.0.
+    pub fn staleness_rate(&self) -> f64 {
+        let checked = self.sampled - self.unreadable;
+        if checked == 0 {
+            return 0.0;
+        }
+        (self.missing.len() + self.drifted.len()) as f64 / checked as f64
+    }
+}
+
+/// splitmix64: plenty for picking a sample, and no extra dependency.
+struct SampleRng(u64);
+
+impl SampleRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound`.
+    fn below(&mut self, bound: usize) -> usize {
+        ((self.next() as u128 * bound as u128) >> 64) as usize
+    }
+}
+
+/// `count` of `items` (of known length `len`) picked uniformly in one pass, in input order.
+fn sample<T>(items: impl Iterator<Item = T>, len: usize, count: usize, seed: u64) -> Vec<T> {
+    let mut rng = SampleRng(seed);
+    let mut needed = count.min(len);
+    let mut picked = Vec::with_capacity(needed);
+    for (seen, item) in items.enumerate() {
+        if needed == 0 {
+            break;
+        }
+        if rng.below(len - seen) < needed {
+            picked.push(item);
+            needed -= 1;
+        }
+    }
+    picked
+}
+
+impl DiskCache {
+    /// Stat `count` randomly picked directories of the snapshot at `cache_path` and compare
+    /// them to their records. Only the sampled records are read; the same `seed` picks the
+    /// same sample of an unchanged snapshot.
+    pub fn validate_sample(cache_path: &Path, count: usize, seed: u64) -> Result<SampleReport> {
+        let index_path = cache_path.with_extension("idx");
+        if !index_path.exists() {
+            bail!("no cached snapshot at {}", cache_path.display());
+        }
+        let rkyv_cache = RkyvMmapCache::open(&index_path, &cache_path.with_extension("dat"))?;
+
+        let mut report = SampleReport {
+            entries: rkyv_cache.index.offsets.len(),
+            last_scan: rkyv_cache.index.last_scan,
+            ..Default::default()
+        };
+        for path in sample(rkyv_cache.index.offsets.paths(), report.entries, count, seed) {
+            let Some(entry) = rkyv_cache.get_entry(&path)? else {
+                continue;
+            };
+            report.sampled += 1;
+
+            match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_dir() == entry.is_dir => {
+                    let Ok(modified) = metadata.modified() else {
+                        continue;
+                    };
+                    // Records keep microseconds
+                    let live = DateTime::<Utc>::from(modified);
+                    if live.timestamp_micros() != entry.modified.timestamp_micros() {
+                        report.drifted.push((path, live - entry.modified));
+                    }
+                }
+                Ok(_) => report.missing.push(path),
+                Err(err) if err.kind() == ErrorKind::NotFound => report.missing.push(path),
+                Err(_) => report.unreadable += 1,
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::env;
+
+    use super::*;
+    use crate::cache::DirEntry;
+    use crate::testing::dir;
+
+    #[test]
+    fn samples_are_uniform_sized_and_reproducible() {
+        let picked = sample(0..1000, 1000, 10, 7);
+        assert_eq!(picked.len(), 10);
+        assert_eq!(picked.iter().collect::<HashSet<_>>().len(), 10);
+        assert_eq!(picked, sample(0..1000, 1000, 10, 7));
+        assert_ne!(picked, sample(0..1000, 1000, 10, 8));
+        assert_eq!(sample(0..5, 5, 10, 1), vec![0, 1, 2, 3, 4]);
+
+        // Every item is about equally likely to be picked
+        let mut hits = [0usize; 10];
+        for seed in 0..10_000 {
+            for item in sample(0..10, 10, 1, seed) {
+                hits[item] += 1;
+            }
+        }
+        assert!(hits.iter().all(|&hit| (800..1200).contains(&hit)), "{hits:?}");
+    }
+
+    #[test]
+    fn missing_and_touched_directories_count_as_stale() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_validate_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let root = temp_dir.join("root");
+        for name in ["kept", "removed", "touched"] {
+            fs::create_dir_all(root.join(name))?;
+        }
+        let cache_path = temp_dir.join("cache").join("ptree.dat");
+
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.root = root.clone();
+        for path in [
+            root.clone(),
+            root.join("kept"),
+            root.join("removed"),
+            root.join("touched"),
+        ] {
+            let modified = DateTime::<Utc>::from(fs::metadata(&path)?.modified()?);
+            let entry = DirEntry {
+                modified: if path.ends_with("touched") {
+                    modified - Duration::hours(1)
+                } else {
+                    modified
+                },
+                ..dir(&path, 0, &[])
+            };
+            cache.entries.insert(path, entry);
+        }
+        cache.save(&cache_path)?;
+        fs::remove_dir(root.join("removed"))?;
+
+        let report = DiskCache::validate_sample(&cache_path, 100, 1)?;
+        assert_eq!((report.entries, report.sampled), (4, 4));
+        assert_eq!(report.missing, vec![root.join("removed")]);
+        // The root's mtime moved too when `removed` went away
+        let drifted: HashSet<PathBuf> = report.drifted.iter().map(|(path, _)| path.clone()).collect();
+        assert!(drifted.contains(&root.join("touched")));
+        assert!(!drifted.contains(&root.join("kept")));
+        assert!(report.staleness_rate() >= 0.5);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::cache::{has_directory_changed, DirEntry};
+
+/// Directories that differ between two snapshots of the same roots.
+///
+/// A directory found under a new path with the same stable ID (or, for entries without one,
+/// the same inode) is reported once as moved instead of as a removal plus an addition.
+/// Entries below an added, removed or moved directory are implied by it and left out.
+/// `changed` holds directories in both snapshots whose own files differ (names, count or
+/// total size); only subtrees whose content hash changed are looked at.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added:   Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub moved:   Vec<(PathBuf, PathBuf)>,
+    pub changed: Vec<PathBuf>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub fn diff_snapshots(previous: &HashMap<PathBuf, DirEntry>, current: &HashMap<PathBuf, DirEntry>) -> SnapshotDiff {
+    let mut removed: HashMap<&Path, &DirEntry> = previous
+        .iter()
+        .filter(|(path, _)| !current.contains_key(*path))
+        .map(|(path, entry)| (path.as_path(), entry))
+        .collect();
+    let mut added: Vec<&DirEntry> = current
+        .iter()
+        .filter(|(path, _)| !previous.contains_key(*path))
+        .map(|(_, entry)| entry)
+        .collect();
+    added.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let removed_by_id: HashMap<u64, &Path> = removed
+        .iter()
+        .filter(|(_, entry)| entry.id != 0)
+        .map(|(path, entry)| (entry.id, *path))
+        .collect();
+    let removed_by_file_id: HashMap<u64, &Path> = removed
+        .iter()
+        .filter(|(_, entry)| entry.file_id != 0)
+        .map(|(path, entry)| (entry.file_id, *path))
+        .collect();
+
+    let mut diff = SnapshotDiff::default();
+    let mut moves = Vec::new();
+    for entry in added {
+        let source = removed_by_id
+            .get(&entry.id)
+            .filter(|_| entry.id != 0)
+            .or_else(|| removed_by_file_id.get(&entry.file_id).filter(|_| entry.file_id != 0));
+
+        match source.and_then(|path| removed.remove_entry(path)) {
+            Some((from, _)) => moves.push((from.to_path_buf(), entry.path.clone())),
+            None => diff.added.push(entry.path.clone()),
+        }
+    }
+    moves.sort();
+
+    // A moved parent carries its whole subtree along
+    for (from, to) in moves {
+        let implied = diff.moved.iter().any(|(parent_from, parent_to)| {
+            from.strip_prefix(parent_from)
+                .ok()
+                .is_some_and(|rest| to == parent_to.join(rest))
+        });
+        if !implied {
+            diff.moved.push((from, to));
+        }
+    }
+
+    diff.removed = removed.into_keys().map(Path::to_path_buf).collect();
+    diff.removed.sort();
+    diff.added = keep_topmost(diff.added);
+    diff.removed = keep_topmost(diff.removed);
+    diff.changed = changed_files(previous, current);
+    diff
+}
+
+/// Directories of both snapshots whose direct files differ, walking down from the roots only
+/// into subdirectories whose Merkle hash changed.
+fn changed_files(previous: &HashMap<PathBuf, DirEntry>, current: &HashMap<PathBuf, DirEntry>) -> Vec<PathBuf> {
+    let mut pending: Vec<PathBuf> = current
+        .keys()
+        .filter(|path| path.parent().is_none_or(|parent| !current.contains_key(parent)))
+        .cloned()
+        .collect();
+    let mut changed = Vec::new();
+    while let Some(path) = pending.pop() {
+        let (Some(old), Some(new)) = (previous.get(&path), current.get(&path)) else {
+            continue;
+        };
+        if !has_directory_changed(old, new) {
+            continue;
+        }
+        if direct_files(old, previous) != direct_files(new, current) {
+            changed.push(path.clone());
+        }
+        pending.extend(
+            new.children
+                .iter()
+                .map(|name| path.join(name))
+                .filter(|child| current.contains_key(child)),
+        );
+    }
+    changed.sort();
+    changed
+}
+
+/// Names, count and total size of the files directly inside `entry`, whose counts cover its
+/// whole subtree in `entries`.
+fn direct_files<'a>(entry: &'a DirEntry, entries: &HashMap<PathBuf, DirEntry>) -> (BTreeSet<&'a str>, usize, u64) {
+    let mut names = BTreeSet::new();
+    let mut file_count = entry.file_count;
+    let mut total_size = entry.total_size;
+    for name in &entry.children {
+        match entries.get(&entry.path.join(name)) {
+            Some(child) => {
+                file_count = file_count.saturating_sub(child.file_count);
+                total_size = total_size.saturating_sub(child.total_size);
+            }
+            None => {
+                names.insert(name.as_str());
+            }
+        }
+    }
+    (names, file_count, total_size)
+}
+
+/// Drop paths that sit below another path of the same (sorted) list.
+fn keep_topmost(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut topmost: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if !topmost.last().is_some_and(|parent| path.starts_with(parent)) {
+            topmost.push(path);
+        }
+    }
+    topmost
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::dir;
+
+    fn entry(path: &str, id: u64, file_id: u64) -> (PathBuf, DirEntry) {
+        (
+            PathBuf::from(path),
+            DirEntry {
+                id,
+                file_id,
+                ..dir(path, 0, &[])
+            },
+        )
+    }
+
+    #[test]
+    fn moved_directory_is_reported_once_with_its_subtree() {
+        let previous = HashMap::from([
+            entry("/r", 1, 10),
+            entry("/r/a", 2, 20),
+            entry("/r/a/x", 3, 30),
+            entry("/r/gone", 4, 40),
+            entry("/r/gone/deep", 5, 50),
+        ]);
+        let current = HashMap::from([
+            entry("/r", 1, 10),
+            entry("/r/b", 2, 20),
+            entry("/r/b/x", 3, 30),
+            entry("/r/fresh", 6, 60),
+            entry("/r/fresh/deep", 7, 70),
+        ]);
+
+        let diff = diff_snapshots(&previous, &current);
+        assert_eq!(diff.moved, vec![(PathBuf::from("/r/a"), PathBuf::from("/r/b"))]);
+        assert_eq!(diff.added, vec![PathBuf::from("/r/fresh")]);
+        assert_eq!(diff.removed, vec![PathBuf::from("/r/gone")]);
+    }
+
+    #[test]
+    fn changed_files_are_found_below_changed_hashes_only() {
+        let dir = |path: &str, hash: u64, files: usize, size: u64, children: &[&str]| {
+            let entry = DirEntry {
+                content_hash: hash,
+                file_count: files,
+                allocated_size: 0,
+                ..dir(path, size, children)
+            };
+            (PathBuf::from(path), entry)
+        };
+        let previous = HashMap::from([
+            dir("/r", 1, 3, 30, &["a", "b", "top.txt"]),
+            dir("/r/a", 2, 1, 10, &["grown.log"]),
+            dir("/r/b", 3, 1, 10, &["same.txt"]),
+        ]);
+        // `grown.log` grew in place; `/r/b` only differs in a count its unchanged hash hides
+        let current = HashMap::from([
+            dir("/r", 4, 4, 60, &["a", "b", "top.txt"]),
+            dir("/r/a", 5, 1, 30, &["grown.log"]),
+            dir("/r/b", 3, 2, 20, &["same.txt"]),
+        ]);
+
+        let diff = diff_snapshots(&previous, &current);
+        assert_eq!(diff.changed, vec![PathBuf::from("/r/a")]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.moved.is_empty());
+    }
+
+    #[test]
+    fn entries_without_ids_fall_back_to_file_ids() {
+        let previous = HashMap::from([entry("/r/old", 0, 20), entry("/r/unknown", 0, 0)]);
+        let current = HashMap::from([entry("/r/new", 0, 20), entry("/r/other", 0, 0)]);
+
+        let diff = diff_snapshots(&previous, &current);
+        assert_eq!(diff.moved, vec![(PathBuf::from("/r/old"), PathBuf::from("/r/new"))]);
+        assert_eq!(diff.added, vec![PathBuf::from("/r/other")]);
+        assert_eq!(diff.removed, vec![PathBuf::from("/r/unknown")]);
+    }
+}
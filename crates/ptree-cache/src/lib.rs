@@ -1,16 +1,99 @@
 pub mod cache;
+pub mod cache_dump;
+pub mod cache_history;
+pub mod cache_json;
+pub mod cache_lock;
+pub mod cache_migrate;
+pub mod cache_prefetch;
+pub mod cache_provenance;
+pub mod cache_query;
 // pub mod cache_lazy;
 // pub mod cache_limcode;
 // pub mod cache_mmap;
 // pub mod cache_opt;
 pub mod cache_rkyv;
+pub mod cache_space;
+pub mod cache_spill;
+#[cfg(feature = "sqlite")]
+pub mod cache_sqlite;
+pub mod cache_validate;
+pub mod diff;
+pub mod long_listing;
+pub mod names;
+pub mod path_index;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod theme;
 
 pub use cache::{
+    cache_path_for_roots,
+    cache_snapshots,
     compute_content_hash,
+    file_extension,
+    file_id,
     get_cache_path,
     get_cache_path_custom,
     has_directory_changed,
+    keep_largest_files,
+    list_root_caches,
+    root_cache_key,
+    snapshot_disk_size,
+    AltStream,
+    CacheTrim,
     DirEntry,
     DiskCache,
+    EntryIds,
+    EntryMetadata,
+    ExtensionUsage,
+    LargeDir,
+    LargeFile,
+    NewDir,
+    OwnerRow,
+    OwnerUsage,
+    RootCacheInfo,
+    ScanError,
+    ScanErrorKind,
+    SlowDir,
+    SnapshotTotals,
+    StoreKind,
+    StoreUsage,
+    Subvolume,
+    SubvolumeKind,
+    Symlink,
+    SymlinkStatus,
     USNJournalState,
+    XAttr,
+    EXPECTED_STREAMS,
+    LARGEST_DIRS_KEPT,
+    LARGEST_FILES_KEPT,
+    NEW_DIR_FLAG_HOURS,
+    NEW_DIR_LEVELS,
+    NEW_DIR_MIN_BYTES,
+    SLOW_DIR_MILLIS,
+    SLOW_DIR_STRIKES,
+    XATTR_VALUE_MAX,
 };
+pub use cache_dump::{
+    list_snapshots,
+    snapshot_path,
+    snapshots_dir,
+    CacheDump,
+    SnapshotInfo,
+    DUMP_FORMAT,
+    DUMP_VERSION,
+};
+pub use cache_history::{append_history, history_path, read_history, HistoryRecord, HISTORY_MAX_RECORDS};
+pub use cache_lock::{CacheLock, DEFAULT_LOCK_TIMEOUT};
+pub use cache_migrate::{migrate_caches, Migration, MigrationOutcome};
+pub use cache_prefetch::{Prefetch, PREFETCH_MAX_PATHS};
+pub use cache_provenance::{ScanRecord, StaleSubtree};
+pub use cache_query::{wildcard_match, PathQuery, SearchHit};
+pub use cache_rkyv::{is_corrupt_cache, CompactionStats, VerifyReport};
+#[cfg(feature = "sqlite")]
+pub use cache_sqlite::SqliteCache;
+pub use cache_validate::SampleReport;
+pub use diff::{diff_snapshots, SnapshotDiff};
+pub use long_listing::{mode_string, owner_name};
+pub use names::Name;
+pub use path_index::PathIndex;
+pub use theme::Theme;
@@ -0,0 +1,220 @@
+//! The long listing (`--long`): permissions, owner, size and modification time in columns
+//! before each name, like `tree -pugsD` (or `ls -l`) prints them, from what `--long` scans
+//! recorded.
+
+use std::path::Path;
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::cache::{DiskCache, EntryMetadata};
+
+/// Columns standing in for metadata that was not recorded (a scan without `--long`)
+const UNKNOWN: &str = "?";
+
+/// `drwxr-xr-x` for Unix mode bits, setuid, setgid and sticky bits included.
+#[cfg(not(windows))]
+pub fn mode_string(mode: u32) -> String {
+    let kind = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ => '-',
+    };
+    let mut text = String::with_capacity(10);
+    text.push(kind);
+    // User, group and others, each with its setuid / setgid / sticky bit and that bit's letter
+    for (shift, special, letter) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
+}
+
+/// `d-a-h-` for Windows file attributes, in the order PowerShell's `Mode` column uses:
+/// directory, archive, read-only, hidden, system, reparse point.
+#[cfg(windows)]
+pub fn mode_string(mode: u32) -> String {
+    [
+        (0x10, 'd'),
+        (0x20, 'a'),
+        (0x1, 'r'),
+        (0x2, 'h'),
+        (0x4, 's'),
+        (0x400, 'l'),
+    ]
+    .iter()
+    .map(|&(bit, letter)| if mode & bit != 0 { letter } else { '-' })
+    .collect()
+}
+
+/// The user name of `uid`, or the number if the system does not know it.
+#[cfg(unix)]
+pub fn owner_name(uid: u32) -> String {
+    let mut passwd = std::mem::MaybeUninit::<libc::passwd>::uninit();
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut found = std::ptr::null_mut();
+    // SAFETY: every pointer is valid for the call, and `buffer.len()` is the buffer's size
+    let result = unsafe { libc::getpwuid_r(uid, passwd.as_mut_ptr(), buffer.as_mut_ptr(), buffer.len(), &mut found) };
+    if result != 0 || found.is_null() {
+        return uid.to_string();
+    }
+    // SAFETY: getpwuid_r filled `passwd`, whose name points into `buffer`
+    unsafe { std::ffi::CStr::from_ptr((*found).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn owner_name(uid: u32) -> String {
+    uid.to_string()
+}
+
+/// A modification time as the long listing prints it, in local time.
+pub fn format_modified(modified: DateTime<Utc>) -> String {
+    modified.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+impl DiskCache {
+    /// Show the recorded metadata before each name, looking up the owners' names once.
+    pub fn enable_long_listing(&mut self) {
+        self.long_listing = true;
+        for uid in self.entry_metadata.values().filter_map(|metadata| metadata.uid) {
+            self.owner_names.entry(uid).or_insert_with(|| owner_name(uid));
+        }
+    }
+
+    /// The metadata recorded for the entry at `path`.
+    pub(crate) fn metadata_of(&self, path: &Path) -> Option<&EntryMetadata> {
+        self.entry_metadata.get(path)
+    }
+
+    /// The owner column of `metadata`: its user name, or nothing where there are no owners.
+    pub(crate) fn owner_of(&self, metadata: &EntryMetadata) -> Option<String> {
+        metadata
+            .uid
+            .map(|uid| self.owner_names.get(&uid).cloned().unwrap_or_else(|| uid.to_string()))
+    }
+
+    /// The size of the file `metadata` describes that output shows: on disk, or logical with
+    /// `apparent_size`.
+    pub(crate) fn shown_file_size(&self, metadata: &EntryMetadata) -> u64 {
+        if self.apparent_size {
+            metadata.size
+        } else {
+            metadata.allocated
+        }
+    }
+
+    /// `[drwxr-xr-x alice    1.2 MB 2026-10-16 19:59]  ` before the name of the entry at `path`;
+    /// empty without `long_listing`. Directories show the total size below them.
+    pub(crate) fn long_columns(&self, path: &Path) -> String {
+        if !self.long_listing {
+            return String::new();
+        }
+        let owner_width = self.owner_names.values().map(String::len).max().unwrap_or(0);
+        let metadata = self.metadata_of(path);
+        let entry = self.entries.get(path);
+
+        let mode = metadata.map_or_else(|| UNKNOWN.to_string(), |metadata| mode_string(metadata.mode));
+        let owner = metadata.and_then(|metadata| self.owner_of(metadata));
+        let size = entry
+            .map(|entry| self.shown_size(entry))
+            .or(metadata.map(|metadata| self.shown_file_size(metadata)));
+        let modified = metadata
+            .and_then(|metadata| metadata.modified)
+            .or(entry.map(|entry| entry.modified));
+
+        let mut columns = format!("[{:<10}", mode);
+        if owner_width > 0 {
+            columns.push_str(&format!(" {:<owner_width$}", owner.as_deref().unwrap_or(UNKNOWN)));
+        }
+        let size = size.map_or_else(|| UNKNOWN.to_string(), Self::format_size);
+        let modified = modified.map_or_else(|| UNKNOWN.to_string(), format_modified);
+        columns.push_str(&format!(" {:>10} {:<16}]  ", size, modified));
+        columns
+    }
+
+    /// `permissions -rw-r--r--, owner alice, 12 KB, modified 2026-10-16 19:59` for the
+    /// accessible output of the entry at `path`; the size only for files, whose lines have none.
+    pub(crate) fn long_details(&self, path: &Path) -> Vec<String> {
+        let Some(metadata) = self.metadata_of(path).filter(|_| self.long_listing) else {
+            return Vec::new();
+        };
+        let mut details = vec![format!("permissions {}", mode_string(metadata.mode))];
+        if let Some(owner) = self.owner_of(metadata) {
+            details.push(format!("owner {}", owner));
+        }
+        if !self.entries.contains_key(path) {
+            details.push(Self::format_size(self.shown_file_size(metadata)));
+        }
+        if let Some(modified) = metadata.modified {
+            details.push(format!("modified {}", format_modified(modified)));
+        }
+        details
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn long_columns_show_the_recorded_metadata() {
+        assert_eq!(mode_string(0o040755), "drwxr-xr-x");
+        assert_eq!(mode_string(0o100644), "-rw-r--r--");
+        assert_eq!(mode_string(0o104755), "-rwsr-xr-x");
+        assert_eq!(mode_string(0o041777), "drwxrwxrwt");
+        assert_eq!(mode_string(0o120777), "lrwxrwxrwx");
+        assert_eq!(mode_string(0o102644), "-rw-r-Sr--");
+
+        let file = PathBuf::from("/r/notes.txt");
+        let modified = Utc::now();
+        let mut cache = DiskCache::default();
+        cache.entry_metadata.insert(
+            file.clone(),
+            EntryMetadata {
+                mode:      0o100640,
+                uid:       Some(0),
+                size:      2048,
+                allocated: 4096,
+                modified:  Some(modified),
+            },
+        );
+        assert_eq!(cache.long_columns(&file), "");
+
+        cache.enable_long_listing();
+        let owner = owner_name(0);
+        let columns = cache.long_columns(&file);
+        assert_eq!(columns, format!("[-rw-r----- {}     4.0 KB {}]  ", owner, format_modified(modified)));
+        // Entries recorded without --long keep the columns aligned
+        let unknown = cache.long_columns(Path::new("/r/other"));
+        assert_eq!(unknown.len(), columns.len());
+        assert!(unknown.starts_with("[?          ? "));
+
+        let details = cache.long_details(&file);
+        assert_eq!(
+            details[..3],
+            [
+                "permissions -rw-r-----".to_string(),
+                format!("owner {}", owner),
+                "4.0 KB".to_string()
+            ]
+        );
+        // --apparent-size shows the logical size instead of the blocks taken
+        cache.apparent_size = true;
+        assert!(cache.long_columns(&file).contains("     2.0 KB "));
+    }
+}
@@ -0,0 +1,230 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// File or directory name, interned: every copy of a name shares one allocation however many
+/// directories list it (`node_modules`, `index.js`, `.git` ...).
+///
+/// A `Name` is one pointer, reference counted: reading it takes no lock, and the bytes go
+/// once nothing refers to them any more, at the next `Name::release_unused` (long-running
+/// watchers call it as they save, after changes dropped directories and their names).
+/// Names compare equal exactly when they point to the same bytes.
+#[derive(Clone)]
+pub struct Name(Arc<String>);
+
+/// A table key that looks up by the text
+struct Interned(Arc<String>);
+
+impl Borrow<str> for Interned {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Interned {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for Interned {}
+
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_str().hash(state)
+    }
+}
+
+/// Every name in use, sharded so scan workers interning names rarely wait on each other
+static NAMES: LazyLock<DashMap<Interned, ()>> = LazyLock::new(DashMap::new);
+
+impl Name {
+    /// Intern `name`, returning a copy of the `Name` every other copy of it already is.
+    pub fn new(name: &str) -> Self {
+        if let Some(interned) = NAMES.get(name) {
+            return Name(Arc::clone(&interned.key().0));
+        }
+        let interned = NAMES.entry(Interned(Arc::new(name.to_owned()))).or_insert(());
+        Name(Arc::clone(&interned.key().0))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Number of distinct names interned (and not yet released).
+    pub fn interned() -> usize {
+        NAMES.len()
+    }
+
+    /// Drop every interned name nothing refers to any more, returning how many went.
+    pub fn release_unused() -> usize {
+        let before = NAMES.len();
+        // Only the table holds such a name, and `new` hands out copies under the same shard
+        // lock `retain` holds, so none can be taken meanwhile
+        NAMES.retain(|interned, _| Arc::strong_count(&interned.0) > 1);
+        before.saturating_sub(NAMES.len())
+    }
+}
+
+impl Default for Name {
+    fn default() -> Self {
+        Name::new("")
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Name {}
+
+/// By address, like equality
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<OsStr> for Name {
+    fn as_ref(&self) -> &OsStr {
+        OsStr::new(self.as_str())
+    }
+}
+
+impl AsRef<Path> for Name {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_str())
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Name::new(name)
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Self {
+        Name::new(&name)
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Name {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+/// Names sort by their text, like the strings they replace.
+impl Ord for Name {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Serialized as the plain string: IDs only mean something inside one process.
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Name::new(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_shared_and_behave_like_strings() {
+        let a = Name::new("index.js");
+        let b = Name::from("index.js".to_string());
+        assert_eq!(a, b);
+        assert_eq!(a, "index.js");
+        assert_eq!(Name::default(), "");
+
+        let long = "x".repeat(100_000);
+        assert_eq!(Name::new(&long).as_str(), long);
+
+        let mut names = vec![Name::new("zeta"), Name::new("alpha"), Name::new("mid")];
+        names.sort();
+        assert_eq!(names, ["alpha", "mid", "zeta"].map(Name::new));
+        assert_eq!(Path::new("/data").join(Name::new("mid")), Path::new("/data/mid"));
+
+        let encoded = bincode::serialize(&names).unwrap();
+        assert_eq!(bincode::deserialize::<Vec<Name>>(&encoded).unwrap(), names);
+    }
+    #[test]
+    fn unused_names_are_released() {
+        let kept = Name::new("ptree-test-release-kept");
+        drop(Name::new("ptree-test-release-dropped"));
+
+        Name::release_unused();
+        assert!(NAMES.get("ptree-test-release-dropped").is_none());
+        assert_eq!(Name::new("ptree-test-release-kept"), kept);
+        assert_eq!(kept.as_str(), "ptree-test-release-kept");
+    }
+}
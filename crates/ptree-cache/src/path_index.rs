@@ -0,0 +1,308 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+const NO_NODE: u32 = u32::MAX;
+
+/// One path component: its parent node, its name and, for indexed directories,
+/// the (depth, offset) of its shard record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PathNode {
+    parent:   u32,
+    name:     OsString,
+    location: Option<(u32, u64)>,
+}
+
+/// Snapshot index keyed by path but stored as a tree of (parent ID, name) nodes, so a
+/// long prefix such as `/home/user/projects` is kept once instead of in every key.
+/// Full paths are rebuilt only when iterating.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PathNode>", into = "Vec<PathNode>")]
+pub struct PathIndex {
+    nodes:      Vec<PathNode>,
+    /// (parent, name hash) → first node with that key; more share it through `collisions`
+    children:   HashMap<(u32, u64), u32>,
+    /// Next node with the same (parent, name hash), for the rare hash collision
+    collisions: HashMap<u32, u32>,
+    records:    usize,
+}
+
+fn name_hash(name: &OsStr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl PathIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        PathIndex {
+            nodes:      Vec::with_capacity(capacity),
+            children:   HashMap::with_capacity(capacity),
+            collisions: HashMap::new(),
+            records:    0,
+        }
+    }
+
+    /// Number of paths with a record.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    pub fn get(&self, path: &Path) -> Option<(u32, u64)> {
+        self.node(path).and_then(|node| self.nodes[node as usize].location)
+    }
+
+    pub fn contains_key(&self, path: &Path) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Set the record location of `path`, returning the one it replaces.
+    pub fn insert(&mut self, path: &Path, location: (u32, u64)) -> Option<(u32, u64)> {
+        let mut parent = NO_NODE;
+        for component in path.components() {
+            parent = match self.child(parent, component.as_os_str()) {
+                Some(node) => node,
+                None => self.push_node(parent, component.as_os_str()),
+            };
+        }
+        if parent == NO_NODE {
+            return None;
+        }
+
+        let previous = self.nodes[parent as usize].location.replace(location);
+        if previous.is_none() {
+            self.records += 1;
+        }
+        previous
+    }
+
+    /// Drop the record location of `path`, returning it; its node stays for the paths below.
+    pub fn remove(&mut self, path: &Path) -> Option<(u32, u64)> {
+        let node = self.node(path)?;
+        let previous = self.nodes[node as usize].location.take();
+        if previous.is_some() {
+            self.records -= 1;
+        }
+        previous
+    }
+
+    /// Indexed paths with their record locations.
+    pub fn iter(&self) -> impl Iterator<Item = (PathBuf, (u32, u64))> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(node, entry)| entry.location.map(|location| (self.path_of(node as u32), location)))
+    }
+
+    /// Number of paths strictly below `path` with a record, counted from the nodes alone.
+    pub fn records_below(&self, path: &Path) -> usize {
+        let Some(top) = self.node(path) else {
+            return 0;
+        };
+        // Nodes come after their parents, so one pass in order reaches every descendant
+        let first = top as usize + 1;
+        let mut below = vec![false; self.nodes.len() - first];
+        let mut records = 0;
+        for (offset, node) in self.nodes[first..].iter().enumerate() {
+            below[offset] = node.parent == top
+                || (node.parent != NO_NODE && node.parent as usize >= first && below[node.parent as usize - first]);
+            if below[offset] && node.location.is_some() {
+                records += 1;
+            }
+        }
+        records
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.iter().map(|(path, _)| path)
+    }
+
+    pub fn locations(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.nodes.iter().filter_map(|node| node.location)
+    }
+
+    /// Same paths with new record locations (after the shards were rewritten); paths
+    /// `relocate` returns `None` for are dropped.
+    pub fn relocated(&self, mut relocate: impl FnMut(&Path, (u32, u64)) -> Option<(u32, u64)>) -> Self {
+        let mut relocated = PathIndex::with_capacity(self.records);
+        for (path, location) in self.iter() {
+            if let Some(location) = relocate(&path, location) {
+                relocated.insert(&path, location);
+            }
+        }
+        relocated
+    }
+
+    fn path_of(&self, mut node: u32) -> PathBuf {
+        let mut names = Vec::new();
+        while node != NO_NODE {
+            let entry = &self.nodes[node as usize];
+            names.push(entry.name.as_os_str());
+            node = entry.parent;
+        }
+        names.into_iter().rev().collect()
+    }
+
+    fn node(&self, path: &Path) -> Option<u32> {
+        let mut node = NO_NODE;
+        for component in path.components() {
+            node = self.child(node, component.as_os_str())?;
+        }
+        (node != NO_NODE).then_some(node)
+    }
+
+    fn child(&self, parent: u32, name: &OsStr) -> Option<u32> {
+        let mut candidate = *self.children.get(&(parent, name_hash(name)))?;
+        loop {
+            if self.nodes[candidate as usize].name == name {
+                return Some(candidate);
+            }
+            candidate = *self.collisions.get(&candidate)?;
+        }
+    }
+
+    fn push_node(&mut self, parent: u32, name: &OsStr) -> u32 {
+        let node = self.nodes.len() as u32;
+        self.nodes.push(PathNode {
+            parent,
+            name: name.to_os_string(),
+            location: None,
+        });
+        self.link(node);
+        node
+    }
+
+    fn link(&mut self, node: u32) {
+        let entry = &self.nodes[node as usize];
+        let key = (entry.parent, name_hash(&entry.name));
+        if let Some(first) = self.children.insert(key, node) {
+            self.collisions.insert(node, first);
+        }
+    }
+}
+
+/// Nodes are stored parents first, so a node whose parent is not an earlier node (a corrupt
+/// index) is refused: `path_of` would index past the table or never reach the root.
+impl TryFrom<Vec<PathNode>> for PathIndex {
+    type Error = String;
+
+    fn try_from(nodes: Vec<PathNode>) -> Result<Self, String> {
+        if let Some(node) =
+            (0..nodes.len()).find(|&node| nodes[node].parent != NO_NODE && nodes[node].parent as usize >= node)
+        {
+            return Err(format!("path index node {} has parent {}, not an earlier node", node, nodes[node].parent));
+        }
+
+        let mut index = PathIndex {
+            records: nodes.iter().filter(|node| node.location.is_some()).count(),
+            children: HashMap::with_capacity(nodes.len()),
+            collisions: HashMap::new(),
+            nodes,
+        };
+        for node in 0..index.nodes.len() as u32 {
+            index.link(node);
+        }
+        Ok(index)
+    }
+}
+
+impl From<PathIndex> for Vec<PathNode> {
+    fn from(index: PathIndex) -> Self {
+        index.nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_share_prefixes_and_round_trip() {
+        let mut index = PathIndex::new();
+        let root = Path::new("/home/user/projects");
+        assert_eq!(index.insert(root, (3, 12)), None);
+        assert_eq!(index.insert(&root.join("ptree"), (4, 40)), None);
+        assert_eq!(index.insert(&root.join("other"), (4, 80)), None);
+        assert_eq!(index.insert(&root.join("ptree"), (4, 120)), Some((4, 40)));
+
+        // "/", "home", "user", "projects", "ptree", "other"
+        assert_eq!(index.nodes.len(), 6);
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get(&root.join("ptree")), Some((4, 120)));
+        assert_eq!(index.get(Path::new("/home/user")), None);
+        assert_eq!(index.get(Path::new("/home/user/missing")), None);
+
+        let encoded = bincode::serialize(&index).unwrap();
+        let decoded: PathIndex = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded.get(&root.join("other")), Some((4, 80)));
+
+        let mut paths: Vec<PathBuf> = decoded.paths().collect();
+        paths.sort();
+        assert_eq!(paths, vec![root.to_path_buf(), root.join("other"), root.join("ptree")]);
+
+        assert_eq!(decoded.records_below(root), 2);
+        assert_eq!(decoded.records_below(Path::new("/home")), 3);
+        assert_eq!(decoded.records_below(&root.join("ptree")), 0);
+        assert_eq!(decoded.records_below(Path::new("/srv")), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_paths_survive_archiving() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        let mut index = PathIndex::new();
+        index.insert(&path, (2, 16));
+
+        let decoded: PathIndex = bincode::deserialize(&bincode::serialize(&index).unwrap()).unwrap();
+        assert_eq!(decoded.get(&path), Some((2, 16)));
+        assert_eq!(decoded.paths().collect::<Vec<_>>(), vec![path]);
+    }
+
+    #[test]
+    fn nodes_must_come_after_their_parents() {
+        let mut index = PathIndex::new();
+        index.insert(Path::new("/home/user"), (2, 16));
+        let mut nodes: Vec<PathNode> = index.into();
+
+        // A parent later in the table, the node itself, or past the end is refused
+        for parent in [2, 1, 7] {
+            nodes[1].parent = parent;
+            let encoded = bincode::serialize(&nodes).unwrap();
+            assert!(bincode::deserialize::<PathIndex>(&encoded).is_err());
+        }
+        nodes[1].parent = 0;
+        let encoded = bincode::serialize(&nodes).unwrap();
+        assert_eq!(bincode::deserialize::<PathIndex>(&encoded).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn colliding_name_hashes_are_told_apart() {
+        let mut index = PathIndex::new();
+        index.insert(Path::new("a"), (1, 1));
+        index.insert(Path::new("b"), (1, 2));
+
+        // Force "b" into the collision chain of "a"
+        let b = index.node(Path::new("b")).unwrap();
+        let a = index.node(Path::new("a")).unwrap();
+        index.children.insert((NO_NODE, name_hash(OsStr::new("a"))), b);
+        index.collisions.insert(b, a);
+
+        assert_eq!(index.get(Path::new("a")), Some((1, 1)));
+        assert_eq!(index.get(Path::new("b")), Some((1, 2)));
+    }
+}
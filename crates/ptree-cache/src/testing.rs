@@ -0,0 +1,30 @@
+//! Fixtures shared by the unit tests of this crate and, with the `testing` feature, of the
+//! crates built on it.
+
+use std::path::Path;
+
+use chrono::Utc;
+
+use crate::cache::DirEntry;
+use crate::names::Name;
+
+/// A directory modified now, named after the last component of `path`, with `size` bytes (on
+/// disk too) and one file counted per child; tests set any other field with `..dir(...)`.
+pub fn dir(path: impl AsRef<Path>, size: u64, children: &[&str]) -> DirEntry {
+    let path = path.as_ref();
+    DirEntry {
+        path:           path.to_path_buf(),
+        name:           Name::new(&path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default()),
+        modified:       Utc::now(),
+        content_hash:   0,
+        file_count:     children.len(),
+        total_size:     size,
+        allocated_size: size,
+        children:       children.iter().map(|name| Name::new(name)).collect(),
+        is_hidden:      false,
+        is_dir:         true,
+        id:             0,
+        file_id:        0,
+        scan:           0,
+    }
+}
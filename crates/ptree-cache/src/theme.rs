@@ -0,0 +1,209 @@
+//! Colors of the tree output: a palette of ANSI styles per kind of entry (directory, symlink,
+//! hidden, executable, file extension), either ptree's own or one read from `LS_COLORS`.
+
+use std::path::Path;
+
+use ptree_core::ThemeName;
+
+use crate::cache::{DiskCache, SymlinkStatus};
+
+/// `LS_COLORS` as GNU `dircolors` sets it by default, for `--theme ls-colors` without one
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:or=40;31;01:ex=01;32:*.tar=01;31:*.tgz=01;31:*.zip=01;31:\
+                                 *.gz=01;31:*.xz=01;31:*.zst=01;31:*.7z=01;31:*.rar=01;31:*.jpg=01;35:\
+                                 *.jpeg=01;35:*.png=01;35:*.gif=01;35:*.svg=01;35:*.mp4=01;35:*.mkv=01;35:\
+                                 *.mp3=00;36:*.flac=00;36:*.wav=00;36";
+
+/// Files run as programs on their own, told apart by their extension where the cache has no
+/// mode bits for them (no `--long` scan, or Windows)
+const EXECUTABLE_EXTENSIONS: &[&str] = &["exe", "com", "bat", "cmd", "ps1", "msi", "sh", "appimage"];
+
+/// ANSI styles (SGR parameters such as `01;34`) of each kind of entry; `None` leaves it in
+/// the terminal's color.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub root:           Option<String>,
+    pub branch:         Option<String>,
+    pub directory:      Option<String>,
+    pub symlink:        Option<String>,
+    pub broken_symlink: Option<String>,
+    pub executable:     Option<String>,
+    /// Overrides the kind's style for hidden entries
+    pub hidden:         Option<String>,
+    pub file:           Option<String>,
+    /// `*.tar`, `*README` ...: name endings (lowercase) and their style; later ones win
+    pub suffixes:       Vec<(String, String)>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::classic()
+    }
+}
+
+impl Theme {
+    /// ptree's palette: bright blue directories and cyan branches as before, with symlinks,
+    /// executables, archives and media set apart and hidden entries dimmed.
+    pub fn classic() -> Self {
+        let style = |sgr: &str| Some(sgr.to_string());
+        let suffixes = [
+            (&["tar", "tgz", "zip", "gz", "xz", "zst", "7z", "rar"][..], "31"),
+            (&["jpg", "jpeg", "png", "gif", "svg", "webp", "mp4", "mkv", "mp3", "flac"], "35"),
+        ];
+        Theme {
+            root:           style("1;34"),
+            branch:         style("36"),
+            directory:      style("94"),
+            symlink:        style("96"),
+            broken_symlink: style("31"),
+            executable:     style("92"),
+            hidden:         style("90"),
+            file:           None,
+            suffixes:       suffixes
+                .iter()
+                .flat_map(|(extensions, sgr)| {
+                    extensions
+                        .iter()
+                        .map(|extension| (format!(".{extension}"), sgr.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    /// The theme `dircolors` output describes (`di=01;34:ln=01;36:*.tar=01;31:...`); keys it
+    /// does not cover stay uncolored, like `ls` leaves them.
+    pub fn from_ls_colors(ls_colors: &str) -> Self {
+        let mut theme = Theme {
+            root:           None,
+            branch:         None,
+            directory:      None,
+            symlink:        None,
+            broken_symlink: None,
+            executable:     None,
+            hidden:         None,
+            file:           None,
+            suffixes:       Vec::new(),
+        };
+        for (key, value) in ls_colors.split(':').filter_map(|pair| pair.split_once('=')) {
+            // `0` and `00` reset to the terminal's color; `ln=target` colors links as what they point to
+            let style = Some(value.to_string()).filter(|value| !value.trim_start_matches('0').is_empty());
+            match key {
+                "di" => theme.directory = style,
+                "ln" if value == "target" => theme.symlink = None,
+                "ln" => theme.symlink = style,
+                "or" => theme.broken_symlink = style,
+                "ex" => theme.executable = style,
+                "fi" => theme.file = style,
+                _ => {
+                    if let (Some(suffix), Some(style)) = (key.strip_prefix('*'), style) {
+                        theme.suffixes.push((suffix.to_lowercase(), style));
+                    }
+                }
+            }
+        }
+        theme.root = theme.directory.clone();
+        theme
+    }
+
+    /// The palette `--theme` names; `auto` follows `LS_COLORS` when it is set.
+    pub fn named(name: ThemeName) -> Self {
+        let ls_colors = std::env::var("LS_COLORS").ok().filter(|value| !value.is_empty());
+        match (name, ls_colors) {
+            (ThemeName::Classic, _) | (ThemeName::Auto, None) => Theme::classic(),
+            (ThemeName::Auto | ThemeName::LsColors, Some(ls_colors)) => Theme::from_ls_colors(&ls_colors),
+            (ThemeName::LsColors, None) => Theme::from_ls_colors(DEFAULT_LS_COLORS),
+        }
+    }
+
+    /// The style of a file named `name`: executable, its suffix's, or plain. `executable` is
+    /// what its mode bits say, when they were recorded; like `ls`, an execute bit wins over the
+    /// suffix. Without them the extension decides, after the suffix.
+    fn file_style(&self, name: &str, executable: Option<bool>) -> Option<&str> {
+        if executable == Some(true) && self.executable.is_some() {
+            return self.executable.as_deref();
+        }
+        let lower = name.to_lowercase();
+        if let Some((_, style)) = self
+            .suffixes
+            .iter()
+            .rev()
+            .find(|(suffix, _)| lower.ends_with(suffix.as_str()))
+        {
+            return Some(style);
+        }
+        let executable = executable.unwrap_or_else(|| {
+            Path::new(&lower)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| EXECUTABLE_EXTENSIONS.contains(&extension))
+        });
+        if executable {
+            self.executable.as_deref()
+        } else {
+            self.file.as_deref()
+        }
+    }
+}
+
+/// `text` in the ANSI `style`, or as is without one.
+pub fn paint(style: Option<&str>, text: &str) -> String {
+    match style {
+        Some(style) => format!("\x1b[{style}m{text}\x1b[0m"),
+        None => text.to_string(),
+    }
+}
+
+impl DiskCache {
+    /// Whether the entry at `path` is hidden: a dot name, or a directory with the hidden attribute.
+    pub(crate) fn is_hidden(&self, path: &Path) -> bool {
+        let dot_name = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        dot_name || self.entries.get(path).is_some_and(|entry| entry.is_hidden)
+    }
+
+    /// The style of the entry at `path` in the colored tree.
+    pub(crate) fn entry_style(&self, path: &Path) -> Option<&str> {
+        let theme = &self.theme;
+        let entry = self.entries.get(path);
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        if theme.hidden.is_some() && self.is_hidden(path) {
+            return theme.hidden.as_deref();
+        }
+        let is_dir = entry.is_some() || self.mount_points.contains(path) || self.scan_errors.contains_key(path);
+        match self.symlinks.get(path) {
+            Some(symlink) if symlink.status == SymlinkStatus::Broken => theme.broken_symlink.as_deref(),
+            Some(_) if theme.symlink.is_some() => theme.symlink.as_deref(),
+            _ if is_dir => theme.directory.as_deref(),
+            _ => {
+                let executable = self
+                    .entry_metadata
+                    .get(path)
+                    .and_then(|metadata| metadata.is_executable());
+                theme.file_style(&name, executable)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ls_colors_set_the_style_of_each_kind_and_suffix() {
+        let theme = Theme::from_ls_colors("rs=0:di=01;34:ln=target:ex=01;32:fi=00:*.tar=01;31:*.TAR=01;33:*README=04");
+        assert_eq!(theme.directory.as_deref(), Some("01;34"));
+        assert_eq!(theme.root, theme.directory);
+        assert_eq!((theme.symlink.as_deref(), theme.file.as_deref()), (None, None));
+        assert_eq!(theme.file_style("backup.tar", None), Some("01;33"));
+        assert_eq!(theme.file_style("README", None), Some("04"));
+        assert_eq!(theme.file_style("setup.EXE", None), Some("01;32"));
+        assert_eq!(theme.file_style("main.rs", None), None);
+        // Recorded mode bits decide over the extension, and an execute bit over the suffix
+        assert_eq!(theme.file_style("configure", Some(true)), Some("01;32"));
+        assert_eq!(theme.file_style("install.sh", Some(false)), None);
+        assert_eq!(theme.file_style("release.tar", Some(true)), Some("01;32"));
+        assert_eq!(paint(Some("01;34"), "src"), "\x1b[01;34msrc\x1b[0m");
+        assert_eq!(paint(None, "src"), "src");
+    }
+}
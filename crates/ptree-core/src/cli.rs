@@ -1,7 +1,14 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 
-use clap::Parser;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::parser::ValueSource;
+use clap::{FromArgMatches, Parser, Subcommand, ValueHint};
+
+use crate::config::Config;
 
 // ============================================================================
 // Output Format Options
@@ -25,6 +32,40 @@ impl std::str::FromStr for OutputFormat {
     }
 }
 
+/// `--json-schema`: the structure of `--format json` output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonSchema {
+    /// The bare tree of nodes, with optional keys only where they apply; what a cache renders
+    /// unless told otherwise
+    V1,
+    /// A header (schema version, ptree version, root, scan time) around the tree, whose nodes
+    /// always carry their type, sizes, modification time, hidden flag and counts; the default
+    /// of the command line and of the servers' `tree` queries
+    #[default]
+    V2,
+}
+
+impl std::fmt::Display for JsonSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JsonSchema::V1 => "v1",
+            JsonSchema::V2 => "v2",
+        })
+    }
+}
+
+impl std::str::FromStr for JsonSchema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "v1" | "1" => Ok(JsonSchema::V1),
+            "v2" | "2" => Ok(JsonSchema::V2),
+            other => Err(format!("Unknown JSON schema: {}", other)),
+        }
+    }
+}
+
 // ============================================================================
 // Color Mode Options
 // ============================================================================
@@ -49,24 +90,815 @@ impl std::str::FromStr for ColorMode {
     }
 }
 
+/// `--theme`: the palette of the colored tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThemeName {
+    /// `LS_COLORS` when it is set, classic otherwise
+    #[default]
+    Auto,
+    /// ptree's own palette
+    Classic,
+    /// `LS_COLORS`, or the `dircolors` defaults when it is not set
+    LsColors,
+}
+
+impl std::str::FromStr for ThemeName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ThemeName::Auto),
+            "classic" => Ok(ThemeName::Classic),
+            "ls-colors" | "ls_colors" | "ls" => Ok(ThemeName::LsColors),
+            other => Err(format!("Unknown theme: {}", other)),
+        }
+    }
+}
+
+// ============================================================================
+// Branch Glyph Options
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Charset {
+    /// Box-drawing characters
+    #[default]
+    Unicode,
+    /// `|--` and `` `-- ``, for consoles and log files without box-drawing characters
+    Ascii,
+}
+
+impl std::str::FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "unicode" | "utf8" | "utf-8" => Ok(Charset::Unicode),
+            "ascii" => Ok(Charset::Ascii),
+            other => Err(format!("Unknown charset: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BranchStyle {
+    /// `├──` and `└──`
+    #[default]
+    Classic,
+    /// `├──` and `╰──`
+    Rounded,
+    /// `┣━━` and `┗━━`
+    Bold,
+    /// `╠══` and `╚══`
+    Double,
+}
+
+impl std::str::FromStr for BranchStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "classic" => Ok(BranchStyle::Classic),
+            "rounded" => Ok(BranchStyle::Rounded),
+            "bold" | "heavy" => Ok(BranchStyle::Bold),
+            "double" => Ok(BranchStyle::Double),
+            other => Err(format!("Unknown style: {}", other)),
+        }
+    }
+}
+
+/// What the tree is drawn with: the branch to a child, to the last child, and the prefixes
+/// of lines below a child that has siblings after it (`pipe`) or not (`space`); `ellipsis`
+/// starts the line of children left out by `--limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeGlyphs {
+    pub branch:   &'static str,
+    pub last:     &'static str,
+    pub pipe:     &'static str,
+    pub space:    &'static str,
+    pub ellipsis: &'static str,
+}
+
+impl TreeGlyphs {
+    /// The glyphs of `style`; the ASCII charset has a single style.
+    pub fn new(charset: Charset, style: BranchStyle) -> Self {
+        let (branch, last, pipe) = match (charset, style) {
+            (Charset::Ascii, _) => ("|-- ", "`-- ", "|   "),
+            (Charset::Unicode, BranchStyle::Classic) => ("├── ", "└── ", "│   "),
+            (Charset::Unicode, BranchStyle::Rounded) => ("├── ", "╰── ", "│   "),
+            (Charset::Unicode, BranchStyle::Bold) => ("┣━━ ", "┗━━ ", "┃   "),
+            (Charset::Unicode, BranchStyle::Double) => ("╠══ ", "╚══ ", "║   "),
+        };
+        TreeGlyphs {
+            branch,
+            last,
+            pipe,
+            space: "    ",
+            ellipsis: match charset {
+                Charset::Unicode => "…",
+                Charset::Ascii => "...",
+            },
+        }
+    }
+}
+
+impl Default for TreeGlyphs {
+    fn default() -> Self {
+        TreeGlyphs::new(Charset::Unicode, BranchStyle::Classic)
+    }
+}
+
+// ============================================================================
+// Path Display Options
+// ============================================================================
+
+/// What tree output prints for each entry (`--full-path`, `--relative-to`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PathDisplay {
+    /// Its name
+    #[default]
+    Name,
+    /// Its absolute path
+    Full,
+    /// Its path relative to this absolute directory
+    RelativeTo(PathBuf),
+}
+
+impl PathDisplay {
+    /// What is printed for `path` where its name would be; `None` keeps the name. A root
+    /// given as a relative path is taken from the current directory.
+    pub fn shown(&self, path: &Path) -> Option<PathBuf> {
+        let absolute = || std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        match self {
+            PathDisplay::Name => None,
+            PathDisplay::Full => Some(absolute()),
+            PathDisplay::RelativeTo(base) => Some(relative_path(&absolute(), base)),
+        }
+    }
+}
+
+/// `path` as reached from `base` (both absolute): `..` up to their common ancestor, then
+/// down; `.` for `base` itself. Paths on different Windows drives stay absolute.
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return path.to_path_buf();
+    }
+    while path_components.peek().is_some() && path_components.peek() == base_components.peek() {
+        path_components.next();
+        base_components.next();
+    }
+    let relative: PathBuf = base_components
+        .map(|_| Component::ParentDir)
+        .chain(path_components)
+        .collect();
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+// ============================================================================
+// Sort Order Options
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By name
+    #[default]
+    Name,
+    /// Most recently modified directories first (files, which have no recorded mtime, follow by name)
+    Mtime,
+    /// Largest directories first, by total size (files follow by name)
+    Size,
+    /// Directories with the most direct children first (files follow by name)
+    Count,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(SortOrder::Name),
+            "mtime" | "modified" => Ok(SortOrder::Mtime),
+            "size" => Ok(SortOrder::Size),
+            "count" | "child-count" | "children" => Ok(SortOrder::Count),
+            other => Err(format!("Unknown sort order: {}", other)),
+        }
+    }
+}
+
+/// A span like `90s`, `15m`, `2h`, `7d`, `2w` or `1h30m` (`--newer-than`).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(format!("Unknown duration unit {:?} in {} (use s, m, h, d or w)", c, s)),
+        };
+        let count: u64 = digits
+            .parse()
+            .map_err(|_| format!("Duration needs a number before each unit, like 2h or 1h30m: {}", s))?;
+        total = count
+            .checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(|| format!("Duration is too long: {}", s))?;
+        digits.clear();
+    }
+    if !digits.is_empty() || total == 0 {
+        return Err(format!("Duration needs a unit and must not be zero, like 30m or 7d: {}", s));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// A point in time (`--modified-since`, `--modified-before`): a span back from now, or a date
+/// or time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    Ago(Duration),
+    At(DateTime<Utc>),
+}
+
+impl TimeSpec {
+    /// The time this stands for, with spans counted back from `now`.
+    pub fn resolve(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            TimeSpec::Ago(span) => {
+                chrono::Duration::from_std(span)
+                    .ok()
+                    .and_then(|span| now.checked_sub_signed(span))
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC)
+            }
+            TimeSpec::At(time) => time,
+        }
+    }
+}
+
+/// A span back from now like `48h` or `7d`, a local date (`2026-10-01`, its midnight) or time
+/// (`2026-10-01 14:30`), or an RFC 3339 time (`2026-10-01T14:30:00Z`).
+pub fn parse_time_spec(s: &str) -> Result<TimeSpec, String> {
+    let s = s.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(TimeSpec::At(time.with_timezone(&Utc)));
+    }
+    let local = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0));
+    if let Some(local) = local {
+        return local
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|time| TimeSpec::At(time.with_timezone(&Utc)))
+            .ok_or_else(|| format!("{} does not exist in the local time zone", s));
+    }
+    parse_duration(s).map(TimeSpec::Ago).map_err(|_| {
+        format!("Expected a span back from now (48h, 7d), a date (2026-10-01) or a time (2026-10-01 14:30): {}", s)
+    })
+}
+
+/// A size like `4096`, `500K`, `1.5GB` or `2GiB`; units are powers of 1024, as in `--size`
+/// output.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Size needs a number, like 500M or 1.5GB: {}", s))?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        other => return Err(format!("Unknown size unit {:?} in {} (use B, K, M, G or T)", other, s)),
+    };
+    let bytes = number * (1u64 << shift) as f64;
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("Size is too large: {}", s));
+    }
+    Ok(bytes as u64)
+}
+
+// ============================================================================
+// Traversal Engine Options
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanEngine {
+    /// Worker threads reading one directory at a time
+    Dfs,
+    /// io_uring batches of directory opens and file stats per thread (Linux, `io-uring` build feature)
+    Uring,
+}
+
+impl std::str::FromStr for ScanEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dfs" => Ok(ScanEngine::Dfs),
+            "uring" | "io-uring" => Ok(ScanEngine::Uring),
+            other => Err(format!("Unknown engine: {}", other)),
+        }
+    }
+}
+
+// ============================================================================
+// Scheduler Backend Options
+// ============================================================================
+
+/// What `ptree scheduler install` schedules the refresh with on Unix (Windows always uses
+/// Task Scheduler).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulerBackend {
+    /// launchd on macOS; elsewhere the one already installed, else cron where `crontab` exists,
+    /// else a systemd user timer
+    Auto,
+    /// A crontab entry
+    Cron,
+    /// `ptree-refresh.service` and `ptree-refresh.timer` user units, enabled with `systemctl --user`
+    Systemd,
+    /// A user agent in `~/Library/LaunchAgents`, loaded with `launchctl` (macOS)
+    Launchd,
+}
+
+impl std::str::FromStr for SchedulerBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(SchedulerBackend::Auto),
+            "cron" => Ok(SchedulerBackend::Cron),
+            "systemd" => Ok(SchedulerBackend::Systemd),
+            "launchd" => Ok(SchedulerBackend::Launchd),
+            other => Err(format!("Unknown scheduler backend: {}", other)),
+        }
+    }
+}
+
+// ============================================================================
+// Log Level Options
+// ============================================================================
+
+/// How much `--log-level` logs, most severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    /// Cache hits and misses with their reason, and how long each phase took
+    Info,
+    /// Also why the live check rejected a snapshot, and checkpoints
+    Debug,
+    Trace,
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            other => Err(format!("Unknown log level: {}", other)),
+        }
+    }
+}
+
+// ============================================================================
+// Validation Sample Options
+// ============================================================================
+
+/// How many cached directories `ptree validate` checks: a share (`1%`) or a count (`500`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    Percent(f64),
+    Count(usize),
+}
+
+impl SampleSize {
+    /// Directories to sample out of `entries` (at least one of a non-empty snapshot).
+    pub fn of(&self, entries: usize) -> usize {
+        match *self {
+            SampleSize::Percent(percent) => {
+                ((entries as f64 * percent / 100.0).ceil() as usize).clamp(1, entries.max(1))
+            }
+            SampleSize::Count(count) => count.min(entries),
+        }
+    }
+}
+
+impl std::str::FromStr for SampleSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(percent) = s.strip_suffix('%') {
+            match percent.trim().parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(SampleSize::Percent(percent)),
+                _ => Err(format!("Sample share must be above 0% and at most 100%: {}", s)),
+            }
+        } else {
+            match s.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(SampleSize::Count(count)),
+                _ => Err(format!("Sample must be a share like 1% or a directory count: {}", s)),
+            }
+        }
+    }
+}
+
+/// Shell a completion script is written for (`ptree completions`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl Shell {
+    pub const NAMES: [&'static str; 4] = ["bash", "zsh", "fish", "powershell"];
+}
+
+impl std::str::FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "powershell" | "pwsh" => Ok(Shell::PowerShell),
+            other => Err(format!("Unknown shell: {}", other)),
+        }
+    }
+}
+
+/// Columns of `ptree report matrix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixGroup {
+    /// One column per file owner
+    Owner,
+}
+
+impl std::str::FromStr for MatrixGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "owner" | "user" => Ok(MatrixGroup::Owner),
+            other => Err(format!("Unknown grouping: {} (expected owner)", other)),
+        }
+    }
+}
+
+// ============================================================================
+// Subcommands
+// ============================================================================
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Scan and render the tree, as `ptree` without a subcommand does; every scan option may follow
+    Scan {
+        /// Paths to scan, and the scan options
+        #[arg(value_name = "ARGS", trailing_var_arg = true, allow_hyphen_values = true, value_hint = ValueHint::DirPath)]
+        paths: Vec<OsString>,
+    },
+    /// Find files and directories of the cached snapshot by name, without scanning
+    ///
+    /// PATTERN takes `*` and `?` wildcards and is case-insensitive; a pattern with a path
+    /// separator is matched against the whole path.
+    Search {
+        pattern: String,
+        /// Roots whose cache to search (default: the current directory, like a scan)
+        paths:   Vec<PathBuf>,
+        /// Most matches to print
+        #[arg(long, default_value_t = 1000)]
+        limit:   usize,
+    },
+    /// List the largest directories (or files) of the cached snapshot with their share of the
+    /// total, without scanning
+    Du {
+        /// Roots whose cache to read (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+        /// How many to list
+        #[arg(long, default_value_t = 20)]
+        top:   usize,
+        /// List the largest files instead of directories
+        #[arg(long)]
+        files: bool,
+    },
+    /// Summarize the cached snapshot: totals, or files and sizes per extension
+    Stats {
+        /// Roots whose cache to read (default: the current directory, like a scan)
+        paths:        Vec<PathBuf>,
+        /// Files (and sizes, after an `--extensions` scan) per file extension
+        #[arg(long)]
+        by_extension: bool,
+        /// Directories, files and sizes per owner, from an `--owners` scan (Unix, macOS: Windows
+        /// scans record no owners)
+        #[arg(long, conflicts_with = "by_extension")]
+        by_owner:     bool,
+        /// How many rows to list (0 for all)
+        #[arg(long, default_value_t = 25)]
+        top:          usize,
+    },
+    /// Install, remove or check the scheduled cache refresh (Windows Task Scheduler / cron / systemd / launchd)
+    Scheduler {
+        #[command(subcommand)]
+        action: SchedulerCommand,
+    },
+    /// Run `--watch` as a Windows service, where Task Scheduler is restricted by policy
+    Service {
+        #[command(subcommand)]
+        action: ServiceCommand,
+    },
+    /// Show or change the defaults kept in the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Inspect and manage cache files
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Rescan and list directories added, removed, moved or with changed files since the cached snapshot
+    /// (nothing is saved)
+    Diff {
+        /// Roots to compare (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+    },
+    /// Render the tree below any cached directory, loading only its records from the cache that
+    /// covers it; with --as-of, the tree as it was in a named snapshot, or in the latest one taken by a time
+    Show {
+        /// Directory to show (default: the current directory); with --as-of, roots whose
+        /// snapshots to look in
+        paths: Vec<PathBuf>,
+        /// Snapshot name, dump file, or time (`2026-09-30`, `2026-09-30T18:00:00Z`)
+        #[arg(long, value_name = "WHEN")]
+        as_of: Option<String>,
+    },
+    /// List the scans of a cache with their totals and how much the size changed since the scan
+    /// before (every scan that reads the disk is recorded)
+    History {
+        /// Roots whose history to show (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+        /// How many of the latest scans to list (0 for all)
+        #[arg(long, default_value_t = 30)]
+        last:  usize,
+    },
+    /// Stat a random sample of cached directories and report how many changed since the snapshot
+    Validate {
+        /// Roots whose cache to check (default: the current directory, like a scan)
+        paths:  Vec<PathBuf>,
+        /// Share of the cached directories (`1%`) or a number of them (`500`) to check
+        #[arg(long, value_name = "SIZE", default_value = "1%")]
+        sample: SampleSize,
+        /// Seed for picking the sample (same seed, same sample of an unchanged cache)
+        #[arg(long)]
+        seed:   Option<u64>,
+    },
+    /// Reports drawn from the cached snapshot
+    Report {
+        #[command(subcommand)]
+        kind: ReportCommand,
+    },
+    /// Query a running `ptree --watch --serve` instead of loading the cache
+    Client {
+        /// Roots the server watches (default: the nearest ancestor of the queried path, or of
+        /// the current directory, that a server is watching)
+        #[arg(long = "root", value_name = "PATH", global = true)]
+        roots: Vec<PathBuf>,
+        #[command(subcommand)]
+        call:  ClientCall,
+    },
+    /// Print a completion script for bash, zsh, fish or powershell; path arguments also offer the
+    /// roots of the caches in the cache directory
+    Completions {
+        #[arg(value_parser = PossibleValuesParser::new(Shell::NAMES)
+            .map(|name| name.parse::<Shell>().unwrap_or(Shell::Bash)))]
+        shell: Shell,
+    },
+    /// Print the root of every cache in the cache directory, one per line (for completion scripts)
+    #[command(hide = true)]
+    CachedRoots,
+    /// Answer tree, search and stats queries over HTTP from the saved snapshot
+    Serve {
+        /// Roots whose snapshot to serve (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+        /// TCP port to listen on
+        #[arg(long, default_value_t = 7878)]
+        port:  u16,
+        /// Address to listen on; anything but localhost lets other machines list the tree
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+        bind:  String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Print every configurable key with its value and where it comes from (config file,
+    /// environment or default)
+    Show,
+    /// Save KEY = VALUE in the config file, written as on the command line (`8`, `never`,
+    /// `node_modules,target`, `true`)
+    Set { key: String, value: String },
+    /// Remove KEY from the config file
+    Unset { key: String },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SchedulerCommand {
+    /// Refresh the cache every 30 minutes; run again to upgrade entries installed by older versions
+    Install {
+        /// On Unix: cron, systemd (user timer), launchd (macOS agent) or auto (launchd on macOS,
+        /// else the installed one, cron if `crontab` exists, or systemd); installing one removes the others
+        #[arg(long, default_value = "auto", value_name = "BACKEND")]
+        backend: SchedulerBackend,
+    },
+    /// Remove the scheduled refresh
+    Uninstall,
+    /// Show the scheduled refresh, the last refresh and any refresh in progress,
+    /// then how the latest scheduled runs ended and the last failure
+    Status {
+        /// How many of the latest scheduled runs to list (0 for all)
+        #[arg(long, default_value_t = 10)]
+        last: usize,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ServiceCommand {
+    /// Register and start a service that watches PATHs at boot, keeping this user's cache
+    /// directory current (run as administrator; run again to change the roots)
+    Install {
+        /// Roots to watch (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+    },
+    /// Stop and remove the service
+    Uninstall,
+    /// Show whether the service is installed and running
+    Status,
+    /// The service itself, started by the Service Control Manager: watch PATHs until it stops
+    /// the service, then save
+    Run {
+        /// Roots to watch
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ClientCall {
+    /// The cached tree below a directory, as `--format json` renders it
+    Tree {
+        /// Directory to show (default: the current directory)
+        path:       Option<PathBuf>,
+        /// Levels of children to include
+        #[arg(long, value_name = "N")]
+        depth:      Option<usize>,
+        /// Include `size_bytes`
+        #[arg(long)]
+        size:       bool,
+        /// Include `file_count`
+        #[arg(long)]
+        file_count: bool,
+    },
+    /// Files and directories whose name matches PATTERN (`*` and `?` wildcards, case-insensitive;
+    /// a pattern with a path separator is matched against the whole path)
+    Search {
+        pattern: String,
+        /// Most matches to return
+        #[arg(long, default_value_t = 1000)]
+        limit:   usize,
+    },
+    /// Roots, directory and file counts, total size and last scan of the served cache
+    Stats,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ReportCommand {
+    /// Files and directories carrying unexpected alternate data streams (recorded by `--ads` scans)
+    Ads {
+        /// Roots whose cache to read (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+        /// Also list streams Windows attaches on its own (Zone.Identifier, ...)
+        #[arg(long)]
+        all:   bool,
+    },
+    /// Files and directories carrying extended attributes (recorded by `--xattrs` scans)
+    Xattrs {
+        /// Roots whose cache to read (default: the current directory, like a scan)
+        paths: Vec<PathBuf>,
+        /// Only attributes whose name starts with PREFIX (repeatable), e.g. com.apple.quarantine
+        /// or security.
+        #[arg(long = "name", value_name = "PREFIX")]
+        names: Vec<String>,
+    },
+    /// Usage per owner of every directory LEVEL levels below the roots (recorded by `--owners` scans)
+    Matrix {
+        /// Roots whose cache to read (default: the current directory, like a scan)
+        paths:    Vec<PathBuf>,
+        /// What the columns are
+        #[arg(long, default_value = "owner")]
+        group_by: MatrixGroup,
+        /// Depth of the rows below each root (`/home/*` is level 2 under `/`)
+        #[arg(long, default_value_t = 1)]
+        level:    usize,
+        /// Print comma-separated values with sizes in bytes, for spreadsheets and billing scripts
+        #[arg(long)]
+        csv:      bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheCommand {
+    /// List the cache file of every scanned root in the cache directory
+    List,
+    /// Rewrite every cache in the cache directory without superseded records
+    Compact,
+    /// Check the checksum of every record in every cache; exits non-zero on corruption
+    Verify,
+    /// Show the directory with a stable entry ID (the `id` of `--format json`) in every cache
+    Lookup {
+        /// Stable entry ID
+        id: u64,
+    },
+    /// List the subtrees of every cache that no scan has read from disk in the last N days, stalest first
+    Stale {
+        /// Age in days after which a directory counts as stale
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+    },
+    /// Forget the cached subtree at a path, so the next scan reads just that subtree again and
+    /// keeps the rest of the snapshot
+    Invalidate {
+        /// Cached directory to read again (the cache covering it is found like `ptree show` finds it)
+        path: PathBuf,
+    },
+    /// Write the cache of the given roots as plain JSON (see README: Cache dump format)
+    Dump {
+        /// JSON file to write
+        #[arg(
+            long,
+            value_name = "FILE",
+            required_unless_present = "snapshot",
+            conflicts_with = "snapshot"
+        )]
+        json:     Option<PathBuf>,
+        /// Keep the dump as a named snapshot in the cache directory (for `ptree show --as-of`)
+        #[arg(long, value_name = "NAME")]
+        snapshot: Option<String>,
+        /// Roots whose cache to dump (default: the current directory, like a scan)
+        paths:    Vec<PathBuf>,
+    },
+    /// Replace the cache of a dump's roots with the snapshot in a JSON dump
+    Restore {
+        /// JSON file written by `ptree cache dump` (or by hand)
+        #[arg(long, value_name = "FILE")]
+        json: PathBuf,
+    },
+}
+
 /// ptree - A cache-first disk tree traversal tool for Windows and Unix
 ///
 /// Scans disk directories with multi-threaded parallelism and caches results
 /// for near-instant subsequent runs.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "ptree")]
 #[command(about = "Fast disk tree visualization with persistent caching")]
+// Configured values are parsed ahead of the command line, which then overrides them
+#[command(args_override_self = true)]
 pub struct Args {
+    /// Cache maintenance commands (a directory named like a command needs `./` in front)
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     // ========================================================================
     // Drive & Scanning Options
     // ========================================================================
-    /// Optional path to scan (overrides drive); supports ~ expansion
-    #[arg(value_name = "PATH")]
-    pub path: Option<PathBuf>,
+    /// Optional paths to scan (override drive); supports ~ expansion. Several paths are
+    /// scanned in one run and rendered as sibling top-level trees
+    #[arg(value_name = "PATH", value_hint = ValueHint::DirPath)]
+    pub paths: Vec<PathBuf>,
 
-    /// Drive letter (e.g., C, D)
-    #[arg(short, long, default_value = "C")]
-    pub drive: char,
+    /// Drive letters (e.g., C or C,D,E); several drives are scanned in one run
+    #[arg(short, long, default_value = "C", value_delimiter = ',')]
+    pub drive: Vec<char>,
 
     /// Enable admin mode to scan system directories
     #[arg(short, long)]
@@ -76,6 +908,41 @@ pub struct Args {
     #[arg(short, long)]
     pub force: bool,
 
+    /// Re-read every directory on a refresh. Without it, a directory whose mtime matches the
+    /// snapshot keeps its cached listing and only its subdirectories are checked
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Read NTFS volumes' Master File Table instead of listing each directory (Windows,
+    /// elevated, `mft` build feature); falls back to the directory walk when unavailable
+    #[arg(long, conflicts_with = "max_memory")]
+    pub mft: bool,
+
+    /// Record the named alternate data streams of every file and directory read (NTFS on
+    /// Windows); list unexpected ones with `ptree report ads`
+    #[arg(long)]
+    pub ads: bool,
+
+    /// Record the extended attributes of every file and directory read, with values up to 256
+    /// bytes (Unix, macOS); list them with `ptree report xattrs`
+    #[arg(long)]
+    pub xattrs: bool,
+
+    /// Sum the files of every directory read by owner (Unix, macOS); report the usage per
+    /// owner and directory with `ptree report matrix`
+    #[arg(long)]
+    pub owners: bool,
+
+    /// Sum the files of every directory read by extension, with their sizes; report them with
+    /// `ptree stats --by-extension`
+    #[arg(long)]
+    pub extensions: bool,
+
+    /// Record the permissions, owner, size and modification time of every entry read and show
+    /// them in columns before each name, like `ls -l`
+    #[arg(short = 'l', long)]
+    pub long: bool,
+
     // ========================================================================
     // Cache Options
     // ========================================================================
@@ -84,13 +951,72 @@ pub struct Args {
     pub cache_ttl: Option<u64>,
 
     /// Override cache directory location
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub cache_dir: Option<String>,
 
+    /// Config file to read defaults from [default: ~/.config/ptree/config.toml, or PTREE_CONFIG]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
     /// Disable cache entirely (scan fresh every time)
     #[arg(long)]
     pub no_cache: bool,
 
+    /// Use the cache but never write it (read-only runs next to a scheduled refresh)
+    #[arg(long, conflicts_with = "cache_compress")]
+    pub no_save: bool,
+
+    /// Leave caches written by earlier versions where they are instead of moving them to
+    /// this version's layout on startup
+    #[arg(long, global = true)]
+    pub no_migrate: bool,
+
+    /// Seconds to wait for another ptree process that is saving the same cache [default: 30]
+    #[arg(long, value_name = "SECS")]
+    pub lock_timeout: Option<u64>,
+
+    /// On-disk cache budget in GB; larger snapshots drop file entries, then the oldest subtrees
+    #[arg(long, value_name = "GB")]
+    pub cache_max_size: Option<f64>,
+
+    /// Memory budget in MB for directory entries during a full scan; fully scanned subtrees
+    /// beyond it are spilled to the cache directory and written into the snapshot on save
+    #[arg(long, value_name = "MB", conflicts_with_all = ["no_cache", "no_save", "settle"])]
+    pub max_memory: Option<u64>,
+
+    /// How often a long full scan saves what it has read, with the directories still queued, so
+    /// a crash or reboot loses at most this much work [default: 5m]
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub checkpoint_interval: Option<Duration>,
+
+    /// With --force, start over instead of resuming a scan that was interrupted or crashed
+    #[arg(long, requires = "force")]
+    pub no_resume: bool,
+
+    /// Store cache shards as zstd-compressed blocks (`--cache-compress=false` converts back);
+    /// without the flag the existing cache keeps its format
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub cache_compress: Option<bool>,
+
+    /// Print cache location, size and what was trimmed to fit the budget, then exit
+    #[arg(long)]
+    pub cache_info: bool,
+
+    /// Stay running after the scan and keep the cache current from filesystem events
+    /// (ReadDirectoryChangesW on Windows, inotify on Linux), saving it every 30 seconds
+    #[arg(long, conflicts_with_all = ["no_cache", "max_memory"])]
+    pub watch: bool,
+
+    /// While watching, answer `ptree client` queries over a local socket next to the cache
+    /// (a named pipe on Windows); needs a build with the `server` feature
+    #[arg(long, requires = "watch")]
+    pub serve: bool,
+
+    /// While watching, print each filesystem change as it is detected, one JSON object per
+    /// line (`{"event","path","time","type"}`) on stdout; status lines move to stderr
+    #[arg(long, requires = "watch")]
+    pub emit_events: bool,
+
     // ========================================================================
     // Output & Display Options
     // ========================================================================
@@ -98,37 +1024,224 @@ pub struct Args {
     #[arg(short, long)]
     pub quiet: bool,
 
+    /// Suppress tree output but print a short digest: entries scanned, change since the
+    /// previous snapshot, largest growth, duration and errors (used by scheduled refreshes)
+    #[arg(long, conflicts_with = "quiet")]
+    pub summary_only: bool,
+
+    /// While scanning, keep a status line on stderr with the directories read, the current
+    /// one, the rate and the elapsed time. On by default when stderr is a terminal, except
+    /// with --quiet or --summary-only; `--progress=false` turns it off
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub progress: Option<bool>,
+
+    /// After the scan or cache hit, read filter and query commands from stdin (`depth 3`,
+    /// `find *.log`, `size > 1GB`, `render /home`) and answer each from the loaded tree
+    #[arg(long, conflicts_with_all = ["quiet", "summary_only", "watch"])]
+    pub interactive: bool,
+
+    /// Print only the subtrees whose path below a root matches EXPR: `/`-separated name
+    /// patterns with `*` and `?`, `**` for any number of levels (`/src/**/tests`); without a
+    /// leading `/` they may start at any depth
+    #[arg(long, value_name = "EXPR", conflicts_with_all = ["quiet", "summary_only", "interactive", "watch"])]
+    pub query: Option<String>,
+
     /// Output format: tree or json
-    #[arg(long, default_value = "tree")]
+    #[arg(long, global = true, default_value = "tree")]
     pub format: OutputFormat,
 
-    /// Color output: auto, always, never
-    #[arg(long, default_value = "auto")]
+    /// Structure of JSON output: v2 (a header with the schema and ptree versions, root and
+    /// scan time, and nodes with every field) or v1 (the bare tree of earlier releases)
+    #[arg(long, global = true, default_value_t = JsonSchema::default())]
+    pub json_schema: JsonSchema,
+
+    /// Write the tree to FILE instead of stdout, through a temp file renamed over it once
+    /// complete; without --format, a `.json` extension picks JSON
+    #[arg(short = 'o', long, value_name = "FILE", conflicts_with_all = ["quiet", "summary_only", "interactive", "watch"])]
+    pub output: Option<PathBuf>,
+
+    /// Print a tree taller than the terminal straight to it instead of through `$PAGER`
+    /// (`less -R` by default)
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
+    /// Leave out the `N directories, M files, S total` line under the tree
+    #[arg(long, global = true, overrides_with = "report")]
+    pub no_report: bool,
+
+    /// Print the report line even if the config file sets `no_report`
+    #[arg(long, global = true, overrides_with = "no_report")]
+    pub report: bool,
+
+    /// Color output: auto, always, never; `auto` colors a terminal unless NO_COLOR is set
+    #[arg(long, global = true, default_value = "auto")]
     pub color: ColorMode,
 
+    /// Palette of the colored tree: auto (LS_COLORS when set, classic otherwise), classic
+    /// or ls-colors (LS_COLORS, or the dircolors defaults)
+    #[arg(long, global = true, default_value = "auto")]
+    pub theme: ThemeName,
+
+    /// Screen-reader friendly tree output: one "level N: name, directory, K items" line per entry,
+    /// no box-drawing glyphs or colors, states spelled out as words
+    #[arg(long, global = true)]
+    pub accessible: bool,
+
     /// Include directory sizes in output
-    #[arg(long)]
+    #[arg(long, global = true, overrides_with = "no_size")]
     pub size: bool,
 
+    /// Leave out directory sizes even if the config file sets `size`
+    #[arg(long, global = true, overrides_with = "size")]
+    pub no_size: bool,
+
+    /// Show, total and sort by logical file sizes instead of the space files take on disk
+    /// (like `du --apparent-size`): compressed and sparse files count in full, and small files
+    /// their bytes rather than whole blocks
+    #[arg(long, global = true)]
+    pub apparent_size: bool,
+
     /// Include file count per directory
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub file_count: bool,
 
+    /// Characters the tree is drawn with: unicode (box drawing) or ascii (`|--`, `` `-- ``)
+    #[arg(long, global = true, default_value = "unicode")]
+    pub charset: Charset,
+
+    /// Branch glyphs of the unicode charset: classic (├──), rounded (╰──), bold (┣━━) or
+    /// double (╠══)
+    #[arg(long, global = true, default_value = "classic")]
+    pub style: BranchStyle,
+
+    /// Order of each directory's children: name, mtime (most recently modified first), size
+    /// (largest first) or count (most children first); the same order in every output format
+    #[arg(long, global = true, default_value = "name")]
+    pub sort: SortOrder,
+
+    /// Print each directory's children in the opposite of the `--sort` order
+    #[arg(long, global = true)]
+    pub reverse: bool,
+
+    /// Show only directories modified within DURATION (e.g. 30m, 2h, 7d), with the directories
+    /// leading to them; files are shown inside directories that match
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    pub newer_than: Option<Duration>,
+
+    /// Show only entries modified at or after WHEN: a span back from now (48h, 7d), a date
+    /// (2026-10-01) or a time (2026-10-01 14:30); with the directories leading to them
+    #[arg(long, global = true, value_name = "WHEN", value_parser = parse_time_spec, conflicts_with = "newer_than")]
+    pub modified_since: Option<TimeSpec>,
+
+    /// Show only entries modified before WHEN (a span back from now, a date or a time); with the
+    /// directories leading to them
+    #[arg(long, global = true, value_name = "WHEN", value_parser = parse_time_spec)]
+    pub modified_before: Option<TimeSpec>,
+
+    /// Show only directories of at least SIZE (e.g. 500M, 1G), sized as --size shows them;
+    /// files are left out while a size filter is set
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_size)]
+    pub min_size: Option<u64>,
+
+    /// Show only directories of at most SIZE (e.g. 10M); files are left out while a size filter
+    /// is set
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_size)]
+    pub max_size: Option<u64>,
+
+    /// Show chains of directories that only hold one subdirectory on one line (src/main/java);
+    /// JSON output keeps every level
+    #[arg(long, global = true)]
+    pub compact_dirs: bool,
+
+    /// Print each entry's absolute path instead of its name
+    #[arg(long, global = true, conflicts_with = "relative_to")]
+    pub full_path: bool,
+
+    /// Print each entry's path relative to DIR instead of its name (and relative paths in JSON)
+    #[arg(long, global = true, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    pub relative_to: Option<PathBuf>,
+
     // ========================================================================
     // Filtering & Traversal Options
     // ========================================================================
     /// Maximum depth to display
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub max_depth: Option<usize>,
 
-    /// Directories to skip (comma-separated)
+    /// Show at most N children of each directory (after sorting), then a `… (+K more)` line
+    #[arg(long, global = true, value_name = "N")]
+    pub limit: Option<usize>,
+
+    /// Stay on the filesystem of each root (like `du -x`): directories where another device is
+    /// mounted are listed as `[mount]` but not entered (Unix, macOS)
+    #[arg(short = 'x', long)]
+    pub one_file_system: bool,
+
+    /// Count a hard-linked file's size at every link, as `du -l` does; by default each file
+    /// counts once, in the directory of its link that sorts first by path
+    #[arg(long)]
+    pub count_links: bool,
+
+    /// Tell hard links apart on Windows too, which takes opening every file for its link
+    /// count; without it every link counts there, as with --count-links. Unix listings carry
+    /// link counts, so there hard links are always told apart
+    #[arg(long, conflicts_with = "count_links")]
+    pub detect_links: bool,
+
+    /// Follow symbolic links to directories and files outside the scanned roots. A target is
+    /// entered once; links into the roots, back into an already followed directory, broken
+    /// links and chains deeper than --max-symlink-depth are shown but not followed
+    #[arg(short = 'L', long)]
+    pub follow_symlinks: bool,
+
+    /// How many followed symlinks deep --follow-symlinks goes [default: 8]
+    #[arg(long, value_name = "N", requires = "follow_symlinks")]
+    pub max_symlink_depth: Option<usize>,
+
+    /// Read directories at most N levels below each root and stop there: a quick shallow
+    /// overview, whose sizes leave out everything deeper. Shows at most N levels and neither
+    /// uses nor saves the cache
+    #[arg(long, value_name = "N", conflicts_with_all = ["watch", "max_memory"])]
+    pub scan_depth: Option<usize>,
+
+    /// Directories to skip (comma-separated); entries containing a path separator skip that exact path only
     #[arg(short, long)]
     pub skip: Option<String>,
 
-    /// Show hidden files
+    /// Skip entries whose name or full path matches this regex (repeatable)
+    #[arg(long = "skip-regex", value_name = "RE")]
+    pub skip_regex: Vec<String>,
+
+    /// Skip paths matched by .gitignore and .ptreeignore files found during traversal
+    #[arg(long, overrides_with = "no_gitignore")]
+    pub gitignore: bool,
+
+    /// Do not skip what .gitignore files match, even if the config file sets `gitignore`
+    #[arg(long, overrides_with = "gitignore")]
+    pub no_gitignore: bool,
+
+    /// Also scan directories marked with a CACHEDIR.TAG or an empty .ptreeignore file
+    #[arg(long)]
+    pub no_markers: bool,
+
+    /// Also scan read-only btrfs snapshots and ZFS .zfs snapshot directories, which share
+    /// their data with the volumes they were taken of
     #[arg(long)]
+    pub include_snapshots: bool,
+
+    /// Show hidden files
+    #[arg(long, global = true, overrides_with = "no_hidden")]
     pub hidden: bool,
 
+    /// Leave out hidden files even if the config file sets `hidden`
+    #[arg(long, global = true, overrides_with = "hidden")]
+    pub no_hidden: bool,
+
+    /// Re-check directories modified within this many milliseconds of being scanned, and
+    /// flag those still changing as [unsettled]
+    #[arg(long, value_name = "MS")]
+    pub settle: Option<u64>,
+
     // ========================================================================
     // Performance Options
     // ========================================================================
@@ -136,35 +1249,281 @@ pub struct Args {
     #[arg(short = 'j', long)]
     pub threads: Option<usize>,
 
-    /// Display summary statistics (total dirs, files, timing, cache location)
+    /// Traversal engine: dfs, or uring (experimental: keeps many directory opens and file
+    /// stats in flight per thread; Linux, `io-uring` build feature)
+    #[arg(long, default_value = "dfs")]
+    pub engine: ScanEngine,
+
+    /// Display summary statistics (total dirs, files, timing, cache location); with
+    /// `--format json`, as one JSON object (on stdout when the tree is not printed)
     #[arg(long)]
     pub stats: bool,
 
+    /// After each run, write the last scan's totals, duration, unreadable directories and
+    /// cache size to this file in Prometheus text format (for node_exporter's textfile collector)
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub metrics_textfile: Option<PathBuf>,
+
     /// Show skip statistics (directories skipped during traversal)
     #[arg(long)]
     pub skip_stats: bool,
 
+    /// List the directories that could not be read (permission denied, path too long, I/O
+    /// errors), grouped by reason; the tree marks them `[denied]`, `[too long]`, ...
+    #[arg(long)]
+    pub errors: bool,
+
+    /// Print every scan warning (unreadable directories, names that are not valid UTF-8)
+    /// instead of the first few of each kind and a count of the rest
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Log cache decisions and how long loading, scanning, saving and rendering took: error,
+    /// warn, info, debug or trace [default: none, or info with --log-file]
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+
+    /// Append the log to this file as JSON lines instead of writing it to stderr
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub log_file: Option<PathBuf>,
+
     // ========================================================================
     // Scheduler Options
     // ========================================================================
-    /// Setup automatic cache refresh every 30 minutes (Windows Task Scheduler / cron)
-    #[arg(long)]
+    /// Same as `ptree scheduler install` (kept for existing scripts)
+    #[arg(long, hide = true)]
     pub scheduler: bool,
 
-    /// Remove scheduled cache updates
-    #[arg(long)]
+    /// Same as `ptree scheduler uninstall`
+    #[arg(long, hide = true)]
     pub scheduler_uninstall: bool,
 
-    /// Show scheduler status
-    #[arg(long)]
+    /// Same as `ptree scheduler status`
+    #[arg(long, hide = true)]
     pub scheduler_status: bool,
+
+    /// Run as a scheduled refresh: skip if another scheduled refresh holds the lock
+    /// and record this one as the last refresh (set by --scheduler)
+    #[arg(long)]
+    pub scheduled: bool,
+
+    /// With --scheduled, also read directories on the slow list (listings that took 5 s or
+    /// more on 3 scans in a row), which scheduled refreshes otherwise keep as last seen
+    #[arg(long, requires = "scheduled")]
+    pub include_slow: bool,
 }
 
 pub fn parse_args() -> Args {
-    Args::parse()
+    parse_args_from(std::env::args_os())
+}
+
+/// Parse a command line over the defaults of the config file, the project file and the
+/// environment (see `crate::config`); `ptree scan ARGS...` parses as `ptree ARGS...`, so the
+/// scan options stay where they are for both spellings. What cannot be applied of those is
+/// warned about and left out, and `ptree config ...` applies none of them, so a broken file
+/// can always be fixed with it.
+pub fn parse_args_from<I, T>(argv: I) -> Args
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let argv: Vec<OsString> = argv.into_iter().map(Into::into).collect();
+    let configures = command()
+        .try_get_matches_from(&argv)
+        .is_ok_and(|matches| matches.subcommand_name() == Some("config"));
+    if configures {
+        return parse_layered(argv, Vec::new());
+    }
+
+    let config = Config::load(config_flag(&argv).as_deref()).unwrap_or_else(|err| {
+        eprintln!("warning: {err:#}; the config file is not applied");
+        Config::default()
+    });
+    let args = parse_layered(argv.clone(), config.to_args());
+
+    // The project file is found from the scan root, which only the parsed paths tell
+    let config = match args.project_root() {
+        Some(root) => {
+            config.clone().with_project(&root).unwrap_or_else(|err| {
+                eprintln!("warning: {err:#}; the project file is not applied");
+                config
+            })
+        }
+        None => config,
+    };
+    for problem in config.problems() {
+        eprintln!("warning: {problem} (ignored)");
+    }
+    if config.project_path().is_none() {
+        return args;
+    }
+    parse_layered(argv, config.to_args())
+}
+
+/// The `--config` of a command line not parsed yet
+fn config_flag(argv: &[OsString]) -> Option<PathBuf> {
+    let mut args = argv.iter().skip(1).take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Parse `argv` with the `configured` options in front of its own.
+fn parse_layered(argv: Vec<OsString>, configured: Vec<OsString>) -> Args {
+    let mut argv: Vec<OsString> = argv
+        .iter()
+        .take(1)
+        .cloned()
+        .chain(configured)
+        .chain(argv.iter().skip(1).cloned())
+        .collect();
+    let mut matches = command().get_matches_from(&argv);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Some(Command::Scan { paths }) = &args.command {
+        // The arguments after `scan` are collected from its first path on; global options
+        // between it and them are not, so it is the last `scan` before them
+        let at = argv[..argv.len() - paths.len()]
+            .iter()
+            .rposition(|arg| arg == "scan")
+            .expect("`scan` precedes its arguments");
+        argv.remove(at);
+        // It comes out once: a path named like a subcommand (`ptree scan scan`) stays a path
+        let parsed = command().try_get_matches_from(&argv);
+        if !parsed.is_ok_and(|matches| matches.subcommand_name().is_none()) {
+            let names: Vec<String> = command()
+                .get_subcommands()
+                .flat_map(|sub| std::iter::once(sub.get_name()).chain(sub.get_all_aliases()))
+                .map(str::to_string)
+                .collect();
+            if let Some(path) = argv[at..]
+                .iter_mut()
+                .find(|arg| arg.to_str().is_some_and(|arg| names.iter().any(|name| name == arg)))
+            {
+                *path = Path::new(".").join(&*path).into_os_string();
+            }
+        }
+        matches = command().get_matches_from(&argv);
+        args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    }
+    let format_given = matches.value_source("format") == Some(ValueSource::CommandLine);
+    args.limited_to_scan_depth().with_output_format(format_given)
+}
+
+/// The command-line definition, for generating completion scripts.
+pub fn command() -> clap::Command {
+    <Args as clap::CommandFactory>::command()
 }
 
 impl Args {
+    /// `--scan-depth`: a partial scan would stand in for the whole tree in the cache, so it
+    /// neither uses nor saves one, and it shows no deeper than it read.
+    fn limited_to_scan_depth(mut self) -> Self {
+        if let Some(depth) = self.scan_depth {
+            self.no_cache = true;
+            self.max_depth = Some(self.max_depth.map_or(depth, |max| max.min(depth)));
+        }
+        self
+    }
+
+    /// `--output` without a `--format` (given or configured): `.json` files get JSON.
+    pub fn with_output_format(mut self, format_given: bool) -> Self {
+        let json_file = self
+            .output
+            .as_ref()
+            .and_then(|path| path.extension())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if !format_given && json_file {
+            self.format = OutputFormat::Json;
+        }
+        self
+    }
+
+    /// What `--full-path` or `--relative-to` print for each entry; a relative DIR is taken
+    /// from the current directory, like relative roots are.
+    pub fn path_display(&self) -> std::io::Result<PathDisplay> {
+        Ok(match &self.relative_to {
+            Some(dir) => PathDisplay::RelativeTo(std::path::absolute(dir)?),
+            None if self.full_path => PathDisplay::Full,
+            None => PathDisplay::Name,
+        })
+    }
+
+    /// The modification times output shows (`--newer-than`, `--modified-since`,
+    /// `--modified-before`), spans counted back from `now`; `None` leaves that end open.
+    pub fn modified_range(&self, now: DateTime<Utc>) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let since = self.newer_than.map(TimeSpec::Ago).or(self.modified_since);
+        (since.map(|since| since.resolve(now)), self.modified_before.map(|before| before.resolve(now)))
+    }
+
+    /// The glyphs of `--charset` and `--style`
+    pub fn tree_glyphs(&self) -> TreeGlyphs {
+        TreeGlyphs::new(self.charset, self.style)
+    }
+
+    /// The subcommand to run: the one given, or the one an older flag (`--scheduler`,
+    /// `--scheduler-uninstall`, `--scheduler-status`) stands for. `scan` is none: it is the
+    /// scan a bare invocation runs.
+    pub fn subcommand(&self) -> Option<Command> {
+        let scheduler = |action| Some(Command::Scheduler { action });
+        if self.scheduler {
+            scheduler(SchedulerCommand::Install {
+                backend: SchedulerBackend::Auto,
+            })
+        } else if self.scheduler_uninstall {
+            scheduler(SchedulerCommand::Uninstall)
+        } else if self.scheduler_status {
+            scheduler(SchedulerCommand::Status { last: 10 })
+        } else {
+            self.command
+                .clone()
+                .filter(|command| !matches!(command, Command::Scan { .. }))
+        }
+    }
+
+    /// The directory a `.ptree.toml` is looked for from: the first path scanned, or the
+    /// current directory when none is given (none for `--force` whole-drive scans).
+    pub fn project_root(&self) -> Option<PathBuf> {
+        match self.paths.first() {
+            Some(path) => Some(path.clone()),
+            None if self.force => None,
+            None => std::env::current_dir().ok(),
+        }
+    }
+
+    /// Whether a hard-linked file's size counts at every link: with `--count-links`, and on
+    /// Windows without `--detect-links`, as telling links apart there means opening every file.
+    pub fn counts_every_link(&self) -> bool {
+        self.count_links || (cfg!(windows) && !self.detect_links)
+    }
+
+    /// First `--drive` letter (the single-drive scan target)
+    pub fn primary_drive(&self) -> char {
+        self.drive.first().copied().unwrap_or('C')
+    }
+
+    /// `--cache-max-size` converted to bytes
+    pub fn cache_max_bytes(&self) -> Option<u64> {
+        self.cache_max_size
+            .filter(|gb| *gb > 0.0)
+            .map(|gb| (gb * 1024.0 * 1024.0 * 1024.0) as u64)
+    }
+
+    /// `--max-memory` converted to bytes
+    pub fn max_memory_bytes(&self) -> Option<u64> {
+        self.max_memory.filter(|mb| *mb > 0).map(|mb| mb * 1024 * 1024)
+    }
+
+    /// `--lock-timeout` as a duration, if given
+    pub fn lock_timeout(&self) -> Option<std::time::Duration> {
+        self.lock_timeout.map(std::time::Duration::from_secs)
+    }
+
     /// Build skip directory set based on arguments
     pub fn skip_dirs(&self) -> HashSet<String> {
         let mut skip = Self::default_skip_dirs();
@@ -198,3 +1557,175 @@ impl Args {
         .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_add_up_their_units() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2W"), Ok(Duration::from_secs(14 * 24 * 3600)));
+        for invalid in ["", "30", "h", "0d", "5y", "1h30"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn scan_subcommand_parses_like_a_bare_invocation() {
+        let parse = |argv: &[&str]| parse_layered(argv.iter().map(OsString::from).collect(), Vec::new());
+        let args = parse(&["ptree", "--cache-dir", "/tmp/c", "scan", "/data", "--force", "-m", "2"]);
+        assert!(args.command.is_none());
+        assert_eq!(args.paths, vec![PathBuf::from("/data")]);
+        assert!(args.force);
+        assert_eq!(args.max_depth, Some(2));
+        assert_eq!(args.cache_dir.as_deref(), Some("/tmp/c"));
+
+        assert!(parse(&["ptree", "scan"]).command.is_none());
+        // A shallow scan shows no deeper than it reads, and leaves the cache alone
+        let shallow = parse(&["ptree", "/data", "--scan-depth", "1", "-m", "3"]);
+        assert_eq!(shallow.max_depth, Some(1));
+        assert!(shallow.no_cache);
+        // What follows `scan` is a path even when named like a subcommand
+        assert_eq!(parse(&["ptree", "scan", "./scan"]).paths, vec![PathBuf::from("./scan")]);
+        let named_scan = parse(&["ptree", "scan", "scan", "--force"]);
+        assert!(named_scan.command.is_none());
+        assert_eq!(named_scan.paths, vec![PathBuf::from("./scan")]);
+        assert!(named_scan.force);
+        let named_cache = parse(&["ptree", "scan", "-m", "2", "cache", "other"]);
+        assert_eq!(named_cache.max_depth, Some(2));
+        assert!(named_cache.command.is_none());
+        assert_eq!(named_cache.paths, vec![PathBuf::from("./cache"), PathBuf::from("other")]);
+
+        let old = parse(&["ptree", "--scheduler-status"]);
+        assert!(matches!(
+            old.subcommand(),
+            Some(Command::Scheduler {
+                action: SchedulerCommand::Status { last: 10 },
+            })
+        ));
+    }
+
+    #[test]
+    fn command_line_overrides_configured_options() {
+        let configured = ["--threads", "8", "--color", "never", "--skip-regex", "^tmp"].map(OsString::from);
+        let argv = [
+            "ptree",
+            "--config=/etc/ptree.toml",
+            "-j",
+            "2",
+            "--skip-regex",
+            "old$",
+            "cache",
+            "list",
+        ];
+        let argv: Vec<OsString> = argv.iter().map(OsString::from).collect();
+        assert_eq!(config_flag(&argv), Some(PathBuf::from("/etc/ptree.toml")));
+
+        let args = parse_layered(argv, configured.to_vec());
+        assert_eq!(args.threads, Some(2));
+        assert!(matches!(args.color, ColorMode::Never));
+        assert_eq!(args.skip_regex, vec!["^tmp", "old$"]);
+        assert!(matches!(args.command, Some(Command::Cache { .. })));
+
+        // Configured flags are turned off by their `--no-` forms
+        let configured = ["--hidden", "--size", "--gitignore", "--no-report"].map(OsString::from);
+        let argv = ["ptree", "--no-hidden", "--no-size", "--no-gitignore", "--report"];
+        let args = parse_layered(argv.iter().map(OsString::from).collect(), configured.to_vec());
+        assert!(!args.hidden && !args.size && !args.gitignore && !args.no_report);
+    }
+
+    #[test]
+    fn config_commands_run_whatever_the_config_file_holds() {
+        let dir = std::env::temp_dir().join("ptree_test_broken_config");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "colour = \"never\"\nthreads = \"eight\"\n").unwrap();
+        let config = format!("--config={}", path.display());
+
+        let args = parse_args_from(["ptree", config.as_str(), "config", "unset", "colour"]);
+        assert!(matches!(args.command, Some(Command::Config { .. })));
+        // Other commands leave out what they cannot use instead of stopping
+        let args = parse_args_from(["ptree", config.as_str(), "--no-report"]);
+        assert_eq!(args.threads, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn project_file_keys_apply_around_the_ones_it_cannot_set() {
+        let dir = std::env::temp_dir().join("ptree_test_project_unknown_key");
+        let project = dir.join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(dir.join("config.toml"), "").unwrap();
+        std::fs::write(project.join(".ptree.toml"), "max_depth = 2\nfancy_new_key = true\nthreads = 4\n").unwrap();
+        let config = format!("--config={}", dir.join("config.toml").display());
+
+        let args = parse_args_from(["ptree", config.as_str(), project.to_str().unwrap()]);
+        assert_eq!(args.max_depth, Some(2));
+        assert_eq!(args.threads, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn json_output_files_get_json_unless_a_format_is_given() {
+        let parse = |argv: &[&str], configured: &[&str]| {
+            let argv = argv.iter().map(OsString::from).collect();
+            parse_layered(argv, configured.iter().map(OsString::from).collect())
+        };
+        let json = |args: Args| matches!(args.format, OutputFormat::Json);
+        assert!(json(parse(&["ptree", "/data", "-o", "/srv/report/tree.JSON"], &[])));
+        assert!(json(parse(&["ptree", "scan", "/data", "--output", "tree.json"], &[])));
+        assert!(!json(parse(&["ptree", "/data", "-o", "tree.txt"], &[])));
+        assert!(!json(parse(&["ptree", "/data", "-o", "tree.json", "--format", "tree"], &[])));
+        assert!(!json(parse(&["ptree", "/data", "-o", "tree.json"], &["--format", "tree"])));
+    }
+
+    #[test]
+    fn relative_paths_climb_to_the_common_ancestor() {
+        let relative = |path: &str, base: &str| relative_path(Path::new(path), Path::new(base));
+        assert_eq!(relative("/srv/data/logs", "/srv/data"), PathBuf::from("logs"));
+        assert_eq!(relative("/srv/data", "/srv/data"), PathBuf::from("."));
+        assert_eq!(relative("/srv/data", "/srv/www/html"), PathBuf::from("../../data"));
+        assert_eq!(relative("/srv", "/srv/data"), PathBuf::from(".."));
+
+        let shown = PathDisplay::RelativeTo(PathBuf::from("/srv")).shown(Path::new("/srv/data/a.log"));
+        assert_eq!(shown, Some(PathBuf::from("data/a.log")));
+        assert_eq!(PathDisplay::Name.shown(Path::new("/srv")), None);
+    }
+
+    #[test]
+    fn sizes_scale_by_their_units() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("500K"), Ok(500 << 10));
+        assert_eq!(parse_size("1.5GB"), Ok(3 << 29));
+        assert_eq!(parse_size("2 GiB"), Ok(2 << 30));
+        for invalid in ["", "GB", "1.2.3M", "5Y", "1e30"] {
+            assert!(parse_size(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn time_specs_are_spans_dates_or_times() {
+        assert_eq!(parse_time_spec("48h"), Ok(TimeSpec::Ago(Duration::from_secs(48 * 3600))));
+        let at = "2026-10-01T14:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(parse_time_spec("2026-10-01T16:30:00+02:00"), Ok(TimeSpec::At(at)));
+        let midnight = NaiveDate::from_ymd_opt(2026, 10, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let local = midnight
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_time_spec("2026-10-01"), Ok(TimeSpec::At(local)));
+        assert_eq!(parse_time_spec("2026-10-01 00:00"), Ok(TimeSpec::At(local)));
+        for invalid in ["", "yesterday", "2026-13-01", "2026-10-01 25:00"] {
+            assert!(parse_time_spec(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(TimeSpec::Ago(Duration::from_secs(3600)).resolve(at), at - chrono::Duration::hours(1));
+        assert_eq!(TimeSpec::At(local).resolve(at), local);
+    }
+}
@@ -0,0 +1,594 @@
+//! Persistent defaults for command-line options, layered as defaults < config file <
+//! project file < environment (`PTREE_<KEY>`) < command line. The file is
+//! `~/.config/ptree/config.toml` (`%APPDATA%\ptree\config.toml` on Windows) unless `--config`
+//! or `PTREE_CONFIG` names another. The project file is a `.ptree.toml` at the scan root (or,
+//! inside a repository, at one of its ancestors up to the repository's top) and may only set
+//! the keys that describe the tree rather than the machine: skips, depth and output.
+//!
+//! The file and environment do not set `Args` fields directly: they are turned back into the
+//! options they stand for and parsed ahead of the command line, so clap validates them and a
+//! flag given on the command line replaces the configured value (`--no-hidden` and the like
+//! turn off a configured flag). A key that is unknown or has a value its option does not take
+//! is left out with a warning (see `Config::problems`) rather than stopping every command.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use toml::{Table, Value};
+
+/// How a key's value is written in the file and passed on as an option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    /// A whole number
+    Number,
+    /// A string
+    Text,
+    /// A list of strings, passed on joined by commas
+    Names,
+    /// A list of strings, passed on as one option each
+    Repeated,
+    /// `true` adds the option, `false` leaves it out
+    Flag,
+}
+
+/// An option the config file and environment can set.
+#[derive(Debug)]
+pub struct Key {
+    pub name:    &'static str,
+    pub flag:    &'static str,
+    pub kind:    KeyKind,
+    /// Whether a project's `.ptree.toml` may set it
+    pub project: bool,
+}
+
+impl Key {
+    /// The environment variable setting this key
+    pub fn env_var(&self) -> String {
+        format!("PTREE_{}", self.name.to_uppercase())
+    }
+}
+
+pub const KEYS: &[Key] = &[
+    Key {
+        name:    "cache_dir",
+        flag:    "--cache-dir",
+        kind:    KeyKind::Text,
+        project: false,
+    },
+    Key {
+        name:    "cache_ttl",
+        flag:    "--cache-ttl",
+        kind:    KeyKind::Number,
+        project: false,
+    },
+    Key {
+        name:    "threads",
+        flag:    "--threads",
+        kind:    KeyKind::Number,
+        project: false,
+    },
+    Key {
+        name:    "max_depth",
+        flag:    "--max-depth",
+        kind:    KeyKind::Number,
+        project: true,
+    },
+    Key {
+        name:    "limit",
+        flag:    "--limit",
+        kind:    KeyKind::Number,
+        project: true,
+    },
+    Key {
+        name:    "skip",
+        flag:    "--skip",
+        kind:    KeyKind::Names,
+        project: true,
+    },
+    Key {
+        name:    "skip_regex",
+        flag:    "--skip-regex",
+        kind:    KeyKind::Repeated,
+        project: true,
+    },
+    Key {
+        name:    "gitignore",
+        flag:    "--gitignore",
+        kind:    KeyKind::Flag,
+        project: true,
+    },
+    Key {
+        name:    "hidden",
+        flag:    "--hidden",
+        kind:    KeyKind::Flag,
+        project: true,
+    },
+    Key {
+        name:    "format",
+        flag:    "--format",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "json_schema",
+        flag:    "--json-schema",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "color",
+        flag:    "--color",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "theme",
+        flag:    "--theme",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "charset",
+        flag:    "--charset",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "style",
+        flag:    "--style",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "no_report",
+        flag:    "--no-report",
+        kind:    KeyKind::Flag,
+        project: true,
+    },
+    Key {
+        name:    "sort",
+        flag:    "--sort",
+        kind:    KeyKind::Text,
+        project: true,
+    },
+    Key {
+        name:    "size",
+        flag:    "--size",
+        kind:    KeyKind::Flag,
+        project: true,
+    },
+];
+
+/// Where a configured value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    File,
+    Project,
+    Env,
+}
+
+/// The config file, as read from disk (empty if there is none yet), and the project file
+/// layered over it.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub path: PathBuf,
+    table:    Table,
+    project:  Option<(PathBuf, Table)>,
+}
+
+impl Config {
+    /// The file `--config` or `PTREE_CONFIG` names, or the default one.
+    pub fn path(given: Option<&Path>) -> Result<PathBuf> {
+        if let Some(path) = given {
+            return Ok(path.to_path_buf());
+        }
+        if let Some(path) = std::env::var_os("PTREE_CONFIG").filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        default_dir()
+            .map(|dir| dir.join("ptree").join("config.toml"))
+            .ok_or_else(|| anyhow!("Could not determine the config directory; pass --config"))
+    }
+
+    /// Read the config file at `path` (see `Config::path`); a missing file is an empty one. Only
+    /// a file that cannot be read or is not TOML is an error; its keys are checked when layered.
+    pub fn load(given: Option<&Path>) -> Result<Self> {
+        let path = Self::path(given)?;
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            // Only a file named on purpose has to exist
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && given.is_none() => String::new(),
+            Err(err) => return Err(err).with_context(|| format!("could not read {}", path.display())),
+        };
+        let table = read_table(&path, &text)?;
+        Ok(Self {
+            path,
+            table,
+            project: None,
+        })
+    }
+
+    /// Layer the `.ptree.toml` that applies to a scan of `root` (see `project_file`) over the
+    /// config file, if there is one.
+    pub fn with_project(mut self, root: &Path) -> Result<Self> {
+        self.project = None;
+        if let Some(path) = project_file(root) {
+            let text = std::fs::read_to_string(&path).with_context(|| format!("could not read {}", path.display()))?;
+            let table = read_table(&path, &text)?;
+            self.project = Some((path, table));
+        }
+        Ok(self)
+    }
+
+    /// The project file layered over the config file, if any
+    pub fn project_path(&self) -> Option<&Path> {
+        self.project.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Set `name` to `value` as written on the command line (`8`, `never`, `node_modules,target`,
+    /// `true`), validating it as the option would be.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let key = key(name)?;
+        let value = parse(key, value)?;
+        accepted(key, &value)?;
+        self.table.insert(key.name.to_string(), value);
+        Ok(())
+    }
+
+    /// Remove `name` from the file, so its default applies again; a key this version does not
+    /// know can be removed too, as long as the file has it.
+    pub fn unset(&mut self, name: &str) -> Result<()> {
+        if self.table.remove(name).is_none() {
+            let key = key(name)?;
+            self.table.remove(key.name);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| format!("could not create {}", dir.display()))?;
+        }
+        std::fs::write(&self.path, toml::to_string(&self.table)?)
+            .with_context(|| format!("could not write {}", self.path.display()))
+    }
+
+    /// Every key the files or the environment set, with its value and where it came from. The
+    /// environment wins over both files, and the project file over the config file, except
+    /// that lists (`skip`, `skip_regex`) from the two files are joined. Values `problems`
+    /// reports are left out.
+    pub fn layered(&self) -> Vec<(&'static Key, Value, Source)> {
+        self.resolve().0
+    }
+
+    /// What the files and the environment set that `layered` leaves out, and why: unknown keys,
+    /// keys a project file may not set, and values their option does not take.
+    pub fn problems(&self) -> Vec<String> {
+        self.resolve().1
+    }
+
+    fn resolve(&self) -> (Vec<(&'static Key, Value, Source)>, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut files = vec![(self.path.as_path(), &self.table, false)];
+        if let Some((path, table)) = &self.project {
+            files.push((path.as_path(), table, true));
+        }
+        for (path, table, project) in files {
+            for name in table.keys() {
+                match key(name) {
+                    Err(err) => problems.push(format!("{}: {err}", path.display())),
+                    Ok(key) if project && !key.project => {
+                        problems.push(format!(
+                            "{}: `{}` cannot be set per project, only in the config file",
+                            path.display(),
+                            name
+                        ))
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        let mut layered = Vec::new();
+        for key in KEYS {
+            let file = usable(key, self.table.get(key.name), &self.path, &mut problems);
+            let project = self
+                .project
+                .as_ref()
+                .filter(|_| key.project)
+                .and_then(|(path, table)| usable(key, table.get(key.name), path, &mut problems));
+            let env = std::env::var_os(key.env_var()).and_then(|env| {
+                let value = env
+                    .into_string()
+                    .map_err(|_| anyhow!("not valid UTF-8"))
+                    .and_then(|env| parse(key, &env))
+                    .and_then(|value| accepted(key, &value).map(|()| value));
+                value
+                    .map_err(|err| problems.push(format!("{}: {err:#}", key.env_var())))
+                    .ok()
+            });
+            if let Some(value) = env {
+                layered.push((key, value, Source::Env));
+            } else if let Some(project) = project {
+                let value = match (&file, project) {
+                    (Some(Value::Array(file)), Value::Array(project)) => {
+                        let mut joined = file.clone();
+                        joined.extend(project.into_iter().filter(|item| !file.contains(item)));
+                        Value::Array(joined)
+                    }
+                    (_, project) => project,
+                };
+                layered.push((key, value, Source::Project));
+            } else if let Some(value) = file {
+                layered.push((key, value, Source::File));
+            }
+        }
+        (layered, problems)
+    }
+
+    /// The options standing for the configured values, to parse ahead of the command line.
+    pub fn to_args(&self) -> Vec<OsString> {
+        self.layered()
+            .iter()
+            .flat_map(|(key, value, _)| to_args(key, value))
+            .collect()
+    }
+}
+
+/// The keys of a config or project file; `Config::problems` checks their names and values.
+fn read_table(path: &Path, text: &str) -> Result<Table> {
+    text.parse()
+        .with_context(|| format!("{} is not valid TOML", path.display()))
+}
+
+/// `value` of `key` as a file at `path` sets it, if its option takes it; otherwise the reason
+/// goes to `problems`.
+fn usable(key: &Key, value: Option<&Value>, path: &Path, problems: &mut Vec<String>) -> Option<Value> {
+    let value = value?;
+    match accepted(key, value) {
+        Ok(()) => Some(value.clone()),
+        Err(err) => {
+            problems.push(format!("{}: `{}` {err:#}", path.display(), key.name));
+            None
+        }
+    }
+}
+
+/// Whether `value` has the type `key` takes and its option accepts it, as clap would check the
+/// option on the command line.
+fn accepted(key: &Key, value: &Value) -> Result<()> {
+    check(key, value)?;
+    let argv = std::iter::once(OsString::from("ptree")).chain(to_args(key, value));
+    <crate::Args as clap::Parser>::try_parse_from(argv).map_err(|err| {
+        // Only the message itself, not the usage hint that follows it
+        let message = err.to_string();
+        let first_line = message.lines().next().unwrap_or_default();
+        anyhow!("{}", first_line.trim_start_matches("error: "))
+    })?;
+    Ok(())
+}
+
+/// The `.ptree.toml` for a scan of `root`: the one in `root` itself or, when `root` is inside
+/// a repository (an ancestor holds `.git`), the nearest one up to the repository's top.
+fn project_file(root: &Path) -> Option<PathBuf> {
+    let root = std::env::current_dir().ok()?.join(root);
+    let mut candidates = Vec::new();
+    for dir in root.ancestors() {
+        candidates.push(dir);
+        if dir.join(".git").exists() {
+            return candidates
+                .into_iter()
+                .map(|dir| dir.join(".ptree.toml"))
+                .find(|file| file.is_file());
+        }
+    }
+    // Outside a repository only the root's own file counts
+    Some(root.join(".ptree.toml")).filter(|file| file.is_file())
+}
+
+/// `~/.config` (or `$XDG_CONFIG_HOME`), `%APPDATA%` on Windows
+fn default_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    }
+
+    #[cfg(not(windows))]
+    {
+        let absolute = |var: &str| {
+            std::env::var_os(var)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+        };
+        absolute("XDG_CONFIG_HOME").or_else(|| absolute("HOME").map(|home| home.join(".config")))
+    }
+}
+
+fn key(name: &str) -> Result<&'static Key> {
+    let name = name.replace('-', "_");
+    KEYS.iter().find(|key| key.name == name).ok_or_else(|| {
+        let known: Vec<_> = KEYS.iter().map(|key| key.name).collect();
+        anyhow!("unknown key `{}` (known: {})", name, known.join(", "))
+    })
+}
+
+/// Whether `value` has the type `key` takes.
+fn check(key: &Key, value: &Value) -> Result<()> {
+    let ok = match key.kind {
+        KeyKind::Number => value.as_integer().is_some_and(|n| n >= 0),
+        KeyKind::Text => value.is_str(),
+        KeyKind::Names | KeyKind::Repeated => value.as_array().is_some_and(|items| items.iter().all(Value::is_str)),
+        KeyKind::Flag => value.is_bool(),
+    };
+    if !ok {
+        bail!("must be {}", describe(key.kind));
+    }
+    Ok(())
+}
+
+fn describe(kind: KeyKind) -> &'static str {
+    match kind {
+        KeyKind::Number => "a non-negative whole number",
+        KeyKind::Text => "a string",
+        KeyKind::Names | KeyKind::Repeated => "a list of strings",
+        KeyKind::Flag => "true or false",
+    }
+}
+
+/// The value of `key` written as on the command line or in the environment.
+fn parse(key: &Key, text: &str) -> Result<Value> {
+    let text = text.trim();
+    let value = match key.kind {
+        KeyKind::Number => text.parse::<u32>().ok().map(|n| Value::Integer(n.into())),
+        KeyKind::Text => Some(Value::String(text.to_string())),
+        KeyKind::Names | KeyKind::Repeated => {
+            Some(Value::Array(
+                text.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            ))
+        }
+        KeyKind::Flag => {
+            match text.to_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Some(Value::Boolean(true)),
+                "false" | "0" | "no" | "off" => Some(Value::Boolean(false)),
+                _ => None,
+            }
+        }
+    };
+    value.ok_or_else(|| anyhow!("`{}` must be {}: {:?}", key.name, describe(key.kind), text))
+}
+
+fn to_args(key: &Key, value: &Value) -> Vec<OsString> {
+    let option = |value: String| vec![OsString::from(key.flag), OsString::from(value)];
+    let strings = || {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+    };
+    match key.kind {
+        KeyKind::Number => value.as_integer().map(|n| option(n.to_string())).unwrap_or_default(),
+        KeyKind::Text => value.as_str().map(|s| option(s.to_string())).unwrap_or_default(),
+        KeyKind::Names => {
+            let names: Vec<String> = strings().collect();
+            if names.is_empty() {
+                Vec::new()
+            } else {
+                option(names.join(","))
+            }
+        }
+        KeyKind::Repeated => strings().flat_map(option).collect(),
+        KeyKind::Flag if value.as_bool() == Some(true) => vec![OsString::from(key.flag)],
+        KeyKind::Flag => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_values_become_options() {
+        let path = std::env::temp_dir().join("ptree_test_config").join("config.toml");
+        let _ = std::fs::remove_file(&path);
+
+        // A file named on purpose must exist
+        assert!(Config::load(Some(&path)).is_err());
+
+        let mut config = Config {
+            path: path.clone(),
+            ..Config::default()
+        };
+        config.set("threads", "8").unwrap();
+        config.set("skip", "node_modules, target").unwrap();
+        config.set("skip-regex", "^build-,\\.cache$").unwrap();
+        config.set("hidden", "yes").unwrap();
+        config.set("size", "false").unwrap();
+        assert!(config.set("color", "purple").is_err());
+        assert!(config.set("threads", "many").is_err());
+        assert!(config.set("depth", "3").is_err());
+        config.save().unwrap();
+
+        let loaded = Config::load(Some(&path)).unwrap();
+        let args: Vec<String> = KEYS
+            .iter()
+            .filter_map(|key| loaded.table.get(key.name).map(|value| (key, value)))
+            .flat_map(|(key, value)| to_args(key, value))
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "--threads",
+                "8",
+                "--skip",
+                "node_modules,target",
+                "--skip-regex",
+                "^build-",
+                "--skip-regex",
+                "\\.cache$",
+                "--hidden",
+            ]
+        );
+
+        // Keys it cannot use are left out with a warning, and can still be removed
+        std::fs::write(&path, "threads = \"eight\"\ncolour = \"never\"\ncolor = \"purple\"\nsize = true\n").unwrap();
+        let mut loaded = Config::load(Some(&path)).unwrap();
+        assert_eq!(loaded.to_args(), ["--size"]);
+        let problems = loaded.problems();
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(problems.iter().any(|problem| problem.contains("unknown key `colour`")));
+        loaded.unset("colour").unwrap();
+        assert_eq!(loaded.problems().len(), 2);
+        std::fs::write(&path, "threads = [").unwrap();
+        assert!(Config::load(Some(&path)).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn project_files_apply_within_their_repository() {
+        let repo = std::env::temp_dir().join("ptree_test_project_config");
+        let _ = std::fs::remove_dir_all(&repo);
+        let app = repo.join("services").join("app");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(&app).unwrap();
+        std::fs::write(repo.join(".ptree.toml"), "skip = [\"bazel-out\", \"target\"]\nmax_depth = 3\n").unwrap();
+
+        let mut table = Table::new();
+        table.insert("skip".to_string(), Value::Array(vec![Value::String("target".to_string())]));
+        table.insert("color".to_string(), Value::String("never".to_string()));
+        let config = Config {
+            table,
+            ..Config::default()
+        };
+
+        // Found from a directory deep inside the repository, and joined with the config file's lists
+        let layered = config.clone().with_project(&app).unwrap();
+        assert_eq!(layered.project_path(), Some(repo.join(".ptree.toml").as_path()));
+        let args: Vec<String> = layered
+            .layered()
+            .iter()
+            .flat_map(|(key, value, _)| to_args(key, value))
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(args, ["--max-depth", "3", "--skip", "target,bazel-out", "--color", "never"]);
+
+        // Outside the repository there is no project file
+        let outside = config.clone().with_project(&std::env::temp_dir()).unwrap();
+        assert!(outside.project_path().is_none());
+
+        // Machine settings stay in the config file, and keys a project file cannot set are left out
+        std::fs::write(repo.join(".ptree.toml"), "cache_dir = \"/tmp/elsewhere\"\nmax_dpeth = 2\nlimit = 5\n").unwrap();
+        let layered = config.with_project(&app).unwrap();
+        assert_eq!(layered.problems().len(), 2);
+        assert!(layered.to_args().iter().any(|arg| arg == "--limit"));
+        assert!(!layered.to_args().iter().any(|arg| arg == "--cache-dir"));
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+}
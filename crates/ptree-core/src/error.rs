@@ -10,6 +10,10 @@ pub enum PTreeError {
     #[error("Cache error: {0}")]
     Cache(String),
 
+    /// A snapshot record failed its checks when read; the snapshot has to be discarded and rescanned
+    #[error("Corrupt cache: {0}")]
+    CorruptCache(String),
+
     #[error("Serialization error: {0}")]
     Serialization(#[from] bincode::Error),
 
@@ -21,6 +25,12 @@ pub enum PTreeError {
 
     #[error("Traversal error: {0}")]
     Traversal(String),
+
+    #[error("Scan cancelled")]
+    Cancelled,
+
+    #[error("Scan interrupted: saved the {saved} directories read so far; `--force` reads the {pending} left")]
+    Interrupted { saved: usize, pending: usize },
 }
 
 pub type PTreeResult<T> = Result<T, PTreeError>;
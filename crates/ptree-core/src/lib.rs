@@ -1,10 +1,43 @@
 pub mod cli;
+pub mod config;
 pub mod error;
 
-pub const SCHEDULED_REFRESH_ARGS: &str = "--quiet --cache-ttl 30";
+pub const SCHEDULED_REFRESH_ARGS: &str = "--scheduled --summary-only --cache-ttl 30";
 pub const SCHEDULED_REFRESH_CACHE_TTL_SECS: u64 = 30;
 
-pub use cli::{parse_args, Args, ColorMode, OutputFormat};
+pub use cli::{
+    command,
+    parse_args,
+    parse_args_from,
+    parse_duration,
+    parse_size,
+    parse_time_spec,
+    relative_path,
+    Args,
+    BranchStyle,
+    CacheCommand,
+    Charset,
+    ClientCall,
+    ColorMode,
+    Command,
+    ConfigCommand,
+    JsonSchema,
+    LogLevel,
+    MatrixGroup,
+    OutputFormat,
+    PathDisplay,
+    ReportCommand,
+    SampleSize,
+    ScanEngine,
+    SchedulerBackend,
+    SchedulerCommand,
+    ServiceCommand,
+    Shell,
+    SortOrder,
+    ThemeName,
+    TimeSpec,
+    TreeGlyphs,
+};
 pub use error::{PTreeError, PTreeResult};
 
 #[cfg(test)]
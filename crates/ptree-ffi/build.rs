@@ -0,0 +1,24 @@
+//! Generate the C header from the `extern "C"` items of `src/lib.rs` into `OUT_DIR`. The copy in
+//! `include/ptree.h` is checked in, so C builds do not need cargo to have run first; it is only
+//! rewritten when asked for with `PTREE_WRITE_HEADER=1 cargo build -p ptree-ffi`, and a test fails
+//! when it falls behind the generated one.
+
+use std::path::Path;
+
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("set by cargo");
+    let out_dir = std::env::var("OUT_DIR").expect("set by cargo");
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=PTREE_WRITE_HEADER");
+
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml")).expect("cbindgen.toml");
+    let generated = Path::new(&out_dir).join("ptree.h");
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("could not generate the C header")
+        .write_to_file(&generated);
+
+    if std::env::var_os("PTREE_WRITE_HEADER").is_some_and(|value| value == "1") {
+        std::fs::copy(&generated, format!("{crate_dir}/include/ptree.h")).expect("could not write include/ptree.h");
+    }
+}
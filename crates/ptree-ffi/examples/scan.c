@@ -0,0 +1,37 @@
+/* Scan a directory through the C API and print its totals and the largest matches of a pattern.
+ *
+ *   cargo build --release -p ptree-ffi
+ *   cc crates/ptree-ffi/examples/scan.c -Icrates/ptree-ffi/include -Ltarget/release -lptree_ffi -o scan
+ *   LD_LIBRARY_PATH=target/release ./scan /home '*.iso'
+ */
+#include <stdio.h>
+
+#include "ptree.h"
+
+int main(int argc, char **argv) {
+    if (argc < 3) {
+        fprintf(stderr, "usage: %s ROOT PATTERN\n", argv[0]);
+        return 2;
+    }
+
+    PTreeScanOptions options = ptree_scan_options_default();
+    options.skip = "node_modules,.git";
+    PTreeTree *tree = ptree_scan(argv[1], &options);
+    if (!tree) {
+        fprintf(stderr, "scan failed: %s\n", ptree_last_error());
+        return 1;
+    }
+
+    char *stats = ptree_query(tree, PTREE_QUERY_STATS, NULL, 0);
+    char *hits = ptree_query(tree, PTREE_QUERY_SEARCH, argv[2], 20);
+    if (!stats || !hits) {
+        fprintf(stderr, "query failed: %s\n", ptree_last_error());
+    } else {
+        printf("%s\n%s\n", stats, hits);
+    }
+
+    ptree_free_string(stats);
+    ptree_free_string(hits);
+    ptree_free(tree);
+    return stats && hits ? 0 : 1;
+}
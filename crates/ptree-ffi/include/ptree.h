@@ -0,0 +1,104 @@
+/* ptree C API. Generated by cbindgen from crates/ptree-ffi/src/lib.rs; do not edit. */
+
+#ifndef PTREE_H
+#define PTREE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define PTREE_UNLIMITED SIZE_MAX
+
+// The directory `argument` (NULL: the root) as a `ptree --format json` object, `limit` levels
+// deep
+#define PTREE_QUERY_TREE 0
+
+// The files and directories matching the wildcard pattern `argument`, as a JSON array of
+// `{"path", "is_dir", "size"}` objects sorted by path, at most `limit` of them
+#define PTREE_QUERY_SEARCH 1
+
+// `{"roots", "directories", "files", "bytes", "allocated_bytes", "from_cache"}`; `argument`
+// and `limit` are ignored
+#define PTREE_QUERY_STATS 2
+
+// A scanned tree, from `ptree_scan`.
+typedef struct PTreeTree PTreeTree;
+
+// How `ptree_scan` scans; start from `ptree_scan_options_default()` and change what differs.
+typedef struct PTreeScanOptions {
+  // Worker threads (0: one per CPU)
+  uint32_t threads;
+  // Directory names to skip, comma-separated; entries with a path separator skip that exact
+  // path (NULL: none)
+  const char *skip;
+  // Cache directory (NULL: ptree's default, shared with the `ptree` command)
+  const char *cache_dir;
+  // Answer from a cached snapshot younger than this many seconds (negative: one hour)
+  int64_t cache_ttl_secs;
+  // Rescan even when the snapshot is younger than the TTL
+  bool refresh;
+  // Read and write the cache at all
+  bool use_cache;
+  // Write the scanned tree to the cache
+  bool save;
+  // Include hidden files and directories in answers
+  bool hidden;
+  // Include directory sizes in `PTREE_QUERY_TREE` answers
+  bool sizes;
+  // Include file counts in `PTREE_QUERY_TREE` answers
+  bool file_counts;
+} PTreeScanOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The options `ptree` uses by default: every CPU, no skips, the default cache directory,
+// an hour's TTL, sizes and file counts in tree answers.
+struct PTreeScanOptions ptree_scan_options_default(void);
+
+// Scan `root` (or load it from the cache), returning a tree to query and to release with
+// `ptree_free`, or NULL on failure.
+//
+// # Safety
+//
+// `root` must be a NUL-terminated string. `options` must be NULL (the defaults) or point to
+// options whose strings are NULL or NUL-terminated.
+struct PTreeTree *ptree_scan(const char *root, const struct PTreeScanOptions *options);
+
+// Answer `query` (a `PTREE_QUERY_*`) about `tree` as a JSON string to release with
+// `ptree_free_string`, or NULL on failure (including a `PTREE_QUERY_TREE` directory that was
+// not scanned).
+//
+// # Safety
+//
+// `tree` must come from `ptree_scan` and not have been freed; `argument` must be NULL or a
+// NUL-terminated string.
+char *ptree_query(const struct PTreeTree *tree,
+                  uint32_t query,
+                  const char *argument,
+                  uintptr_t limit);
+
+// Release a tree from `ptree_scan`; NULL is ignored.
+//
+// # Safety
+//
+// `tree` must be NULL or come from `ptree_scan`, and not be used (or freed) again.
+void ptree_free(struct PTreeTree *tree);
+
+// Release a string from `ptree_query`; NULL is ignored.
+//
+// # Safety
+//
+// `string` must be NULL or come from `ptree_query`, and not be used (or freed) again.
+void ptree_free_string(char *string);
+
+// Why the last call on this thread that returned NULL failed, or NULL if none did. The
+// string belongs to ptree and stays valid until the next failure on this thread.
+const char *ptree_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PTREE_H */
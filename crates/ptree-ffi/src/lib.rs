@@ -0,0 +1,332 @@
+//! C ABI over `ptree-lib`, for C and C++ programs that embed the scanner: `ptree_scan` a root
+//! into a tree handle, ask it `ptree_query`s that answer in JSON, and release both with
+//! `ptree_free` and `ptree_free_string`. The header, `include/ptree.h`, is generated from this
+//! file by the build script and rewritten with `PTREE_WRITE_HEADER=1 cargo build -p ptree-ffi`.
+//!
+//! Functions that fail return NULL and leave a message for `ptree_last_error` on the calling
+//! thread. A tree handle may be queried from several threads at once.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use ptree_lib::{Scanner, Tree};
+use serde_json::json;
+
+/// `limit` of `ptree_query` for no limit (`PTREE_UNLIMITED` in C, `SIZE_MAX`)
+pub const PTREE_UNLIMITED: usize = usize::MAX;
+
+// What `ptree_query` answers. Plain integers rather than an enum, so an unknown value from C is
+// an error instead of undefined behavior.
+
+/// The directory `argument` (NULL: the root) as a `ptree --format json` object, `limit` levels
+/// deep
+pub const PTREE_QUERY_TREE: u32 = 0;
+/// The files and directories matching the wildcard pattern `argument`, as a JSON array of
+/// `{"path", "is_dir", "size"}` objects sorted by path, at most `limit` of them
+pub const PTREE_QUERY_SEARCH: u32 = 1;
+/// `{"roots", "directories", "files", "bytes", "allocated_bytes", "from_cache"}`; `argument`
+/// and `limit` are ignored
+pub const PTREE_QUERY_STATS: u32 = 2;
+
+/// How `ptree_scan` scans; start from `ptree_scan_options_default()` and change what differs.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PTreeScanOptions {
+    /// Worker threads (0: one per CPU)
+    pub threads:        u32,
+    /// Directory names to skip, comma-separated; entries with a path separator skip that exact
+    /// path (NULL: none)
+    pub skip:           *const c_char,
+    /// Cache directory (NULL: ptree's default, shared with the `ptree` command)
+    pub cache_dir:      *const c_char,
+    /// Answer from a cached snapshot younger than this many seconds (negative: one hour)
+    pub cache_ttl_secs: i64,
+    /// Rescan even when the snapshot is younger than the TTL
+    pub refresh:        bool,
+    /// Read and write the cache at all
+    pub use_cache:      bool,
+    /// Write the scanned tree to the cache
+    pub save:           bool,
+    /// Include hidden files and directories in answers
+    pub hidden:         bool,
+    /// Include directory sizes in `PTREE_QUERY_TREE` answers
+    pub sizes:          bool,
+    /// Include file counts in `PTREE_QUERY_TREE` answers
+    pub file_counts:    bool,
+}
+
+/// A scanned tree, from `ptree_scan`.
+pub struct PTreeTree {
+    tree: Tree,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // A message with a NUL in it is cut there rather than lost
+    let message = CString::new(message).unwrap_or_else(|err| {
+        let end = err.nul_position();
+        CString::new(&err.into_vec()[..end]).unwrap_or_default()
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run `body`, turning an error or a panic into NULL and a `ptree_last_error` message.
+fn guarded<T>(body: impl FnOnce() -> Result<*mut T>) -> *mut T {
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            set_last_error(format!("{:#}", err));
+            ptr::null_mut()
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("ptree panicked: {}", message));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// `text` as a string, or `None` for NULL.
+unsafe fn optional_str<'a>(text: *const c_char) -> Result<Option<&'a str>> {
+    if text.is_null() {
+        return Ok(None);
+    }
+    let text = unsafe { CStr::from_ptr(text) };
+    text.to_str()
+        .map(Some)
+        .map_err(|_| anyhow!("{:?} is not valid UTF-8", text))
+}
+
+fn json_string(value: &str) -> Result<*mut c_char> {
+    Ok(CString::new(value)?.into_raw())
+}
+
+/// The options `ptree` uses by default: every CPU, no skips, the default cache directory,
+/// an hour's TTL, sizes and file counts in tree answers.
+#[no_mangle]
+pub extern "C" fn ptree_scan_options_default() -> PTreeScanOptions {
+    PTreeScanOptions {
+        threads:        0,
+        skip:           ptr::null(),
+        cache_dir:      ptr::null(),
+        cache_ttl_secs: -1,
+        refresh:        false,
+        use_cache:      true,
+        save:           true,
+        hidden:         false,
+        sizes:          true,
+        file_counts:    true,
+    }
+}
+
+/// Scan `root` (or load it from the cache), returning a tree to query and to release with
+/// `ptree_free`, or NULL on failure.
+///
+/// # Safety
+///
+/// `root` must be a NUL-terminated string. `options` must be NULL (the defaults) or point to
+/// options whose strings are NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn ptree_scan(root: *const c_char, options: *const PTreeScanOptions) -> *mut PTreeTree {
+    guarded(|| {
+        let root = unsafe { optional_str(root) }?.ok_or_else(|| anyhow!("root is NULL"))?;
+        let options = if options.is_null() {
+            ptree_scan_options_default()
+        } else {
+            unsafe { *options }
+        };
+
+        let mut scanner = Scanner::new(PathBuf::from(root))
+            .refresh(options.refresh)
+            .use_cache(options.use_cache)
+            .save(options.save)
+            .hidden(options.hidden)
+            .sizes(options.sizes)
+            .file_counts(options.file_counts);
+        if options.threads > 0 {
+            scanner = scanner.threads(options.threads as usize);
+        }
+        if let Some(skip) = unsafe { optional_str(options.skip) }? {
+            scanner = scanner.skip(skip);
+        }
+        if let Some(cache_dir) = unsafe { optional_str(options.cache_dir) }? {
+            scanner = scanner.cache_dir(cache_dir);
+        }
+        if options.cache_ttl_secs >= 0 {
+            scanner = scanner.cache_ttl(Duration::from_secs(options.cache_ttl_secs as u64));
+        }
+        let tree = scanner.scan()?;
+        Ok(Box::into_raw(Box::new(PTreeTree { tree })))
+    })
+}
+
+/// Answer `query` (a `PTREE_QUERY_*`) about `tree` as a JSON string to release with
+/// `ptree_free_string`, or NULL on failure (including a `PTREE_QUERY_TREE` directory that was
+/// not scanned).
+///
+/// # Safety
+///
+/// `tree` must come from `ptree_scan` and not have been freed; `argument` must be NULL or a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ptree_query(
+    tree: *const PTreeTree,
+    query: u32,
+    argument: *const c_char,
+    limit: usize,
+) -> *mut c_char {
+    guarded(|| {
+        let tree = &unsafe { tree.as_ref() }.ok_or_else(|| anyhow!("tree is NULL"))?.tree;
+        let argument = unsafe { optional_str(argument) }?;
+        match query {
+            PTREE_QUERY_TREE => {
+                let path = match argument {
+                    Some(path) => PathBuf::from(path),
+                    None => {
+                        tree.roots()
+                            .into_iter()
+                            .next()
+                            .ok_or_else(|| anyhow!("the tree is empty"))?
+                    }
+                };
+                let depth = (limit != PTREE_UNLIMITED).then_some(limit);
+                let object = tree
+                    .subtree_json(&path, depth)?
+                    .ok_or_else(|| anyhow!("{} is not a scanned directory", path.display()))?;
+                json_string(&object)
+            }
+            PTREE_QUERY_SEARCH => {
+                let pattern = argument.ok_or_else(|| anyhow!("a search needs a pattern"))?;
+                json_string(&serde_json::to_string(&tree.search(pattern, limit))?)
+            }
+            PTREE_QUERY_STATS => {
+                let totals = tree.totals();
+                let stats = json!({
+                    "roots": tree.roots(),
+                    "directories": totals.directories,
+                    "files": totals.files,
+                    "bytes": totals.bytes,
+                    "allocated_bytes": totals.allocated_bytes,
+                    "from_cache": tree.from_cache(),
+                });
+                json_string(&stats.to_string())
+            }
+            _ => Err(anyhow!("unknown query {}", query)),
+        }
+    })
+}
+
+/// Release a tree from `ptree_scan`; NULL is ignored.
+///
+/// # Safety
+///
+/// `tree` must be NULL or come from `ptree_scan`, and not be used (or freed) again.
+#[no_mangle]
+pub unsafe extern "C" fn ptree_free(tree: *mut PTreeTree) {
+    if !tree.is_null() {
+        drop(unsafe { Box::from_raw(tree) });
+    }
+}
+
+/// Release a string from `ptree_query`; NULL is ignored.
+///
+/// # Safety
+///
+/// `string` must be NULL or come from `ptree_query`, and not be used (or freed) again.
+#[no_mangle]
+pub unsafe extern "C" fn ptree_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// Why the last call on this thread that returned NULL failed, or NULL if none did. The
+/// string belongs to ptree and stays valid until the next failure on this thread.
+#[no_mangle]
+pub extern "C" fn ptree_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const _: () = {
+        // What lets C query one tree from several threads
+        const fn sync<T: Sync>() {}
+        sync::<PTreeTree>()
+    };
+
+    fn answer(tree: *const PTreeTree, query: u32, argument: Option<&CStr>, limit: usize) -> Option<String> {
+        let argument = argument.map_or(ptr::null(), CStr::as_ptr);
+        let answer = unsafe { ptree_query(tree, query, argument, limit) };
+        if answer.is_null() {
+            return None;
+        }
+        let text = unsafe { CStr::from_ptr(answer) }.to_str().unwrap().to_string();
+        unsafe { ptree_free_string(answer) };
+        Some(text)
+    }
+
+    #[test]
+    fn scans_and_answers_queries_through_the_c_abi() {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_ffi_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let root = temp_dir.join("root");
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("lib/core.c"), "int main;").unwrap();
+
+        let root_c = CString::new(root.display().to_string()).unwrap();
+        let cache_dir = CString::new(temp_dir.join("cache").display().to_string()).unwrap();
+        let options = PTreeScanOptions {
+            cache_dir: cache_dir.as_ptr(),
+            ..ptree_scan_options_default()
+        };
+        let tree = unsafe { ptree_scan(root_c.as_ptr(), &options) };
+        assert!(!tree.is_null());
+
+        let stats: serde_json::Value =
+            serde_json::from_str(&answer(tree, PTREE_QUERY_STATS, None, 0).unwrap()).unwrap();
+        assert_eq!((stats["directories"].as_u64(), stats["files"].as_u64()), (Some(2), Some(1)));
+        let hits = answer(tree, PTREE_QUERY_SEARCH, Some(c"*.c"), PTREE_UNLIMITED).unwrap();
+        assert!(hits.contains("core.c"));
+        let lib = CString::new(root.join("lib").display().to_string()).unwrap();
+        let subtree = answer(tree, PTREE_QUERY_TREE, Some(&lib), PTREE_UNLIMITED).unwrap();
+        assert!(subtree.contains("\"core.c\""));
+        assert!(answer(tree, PTREE_QUERY_TREE, None, 0).unwrap().starts_with('{'));
+
+        // Failures leave a message behind
+        assert!(answer(tree, PTREE_QUERY_TREE, Some(c"/not/scanned"), 0).is_none());
+        let error = unsafe { CStr::from_ptr(ptree_last_error()) }.to_str().unwrap();
+        assert!(error.contains("/not/scanned is not a scanned directory"));
+        assert!(answer(ptr::null(), PTREE_QUERY_STATS, None, 0).is_none());
+        assert!(answer(tree, 7, None, 0).is_none());
+
+        unsafe { ptree_free(tree) };
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn checked_in_header_matches_the_generated_one() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/ptree.h"));
+        let checked_in = include_str!("../include/ptree.h");
+        assert!(
+            generated == checked_in,
+            "include/ptree.h is out of date; rewrite it with PTREE_WRITE_HEADER=1 cargo build -p ptree-ffi"
+        );
+    }
+}
@@ -1,3 +1,5 @@
 pub mod incremental;
+pub mod watch;
 
 pub use incremental::{build_changed_directory_set, try_incremental_update, IncrementalChange, IncrementalChangeKind};
+pub use watch::{ChangeBatch, ChangeWatcher};
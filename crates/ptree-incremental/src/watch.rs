@@ -0,0 +1,251 @@
+// Filesystem event subscription for `ptree --watch`.
+// The OS watcher (ReadDirectoryChangesW on Windows, inotify on Linux, FSEvents on macOS)
+// reports paths; they become `IncrementalChange`s that traversal turns into a refresh plan.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::incremental::IncrementalChange;
+
+/// Changes gathered by one `ChangeWatcher::next_batch`
+#[derive(Debug, Default)]
+pub struct ChangeBatch {
+    pub changes: Vec<IncrementalChange>,
+    /// The OS dropped events (queue overflow): only a rescan brings the cache back in step
+    pub rescan:  bool,
+    /// Errors the watcher reported since the last batch
+    pub errors:  Vec<String>,
+}
+
+impl ChangeBatch {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && !self.rescan && self.errors.is_empty()
+    }
+}
+
+/// Called with each change as its event arrives
+type ChangeHook = Box<dyn Fn(&IncrementalChange)>;
+
+/// Recursive watch on a set of roots.
+pub struct ChangeWatcher {
+    // Dropping the watcher ends the subscription
+    _watcher:  RecommendedWatcher,
+    events:    Receiver<notify::Result<Event>>,
+    ignored:   Vec<PathBuf>,
+    on_change: Option<ChangeHook>,
+}
+
+impl ChangeWatcher {
+    /// Watch every root recursively. Events below `ignored` (the cache directory, whose saves
+    /// would otherwise report themselves) are dropped.
+    pub fn new(roots: &[PathBuf], ignored: Vec<PathBuf>) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+        for root in roots {
+            watcher.watch(root, RecursiveMode::Recursive).with_context(|| {
+                format!("could not watch {} (on Linux, raising fs.inotify.max_user_watches may help)", root.display())
+            })?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            ignored,
+            on_change: None,
+        })
+    }
+
+    /// Call `hook` with each change as its event arrives, before batching holds it back.
+    pub fn on_change(mut self, hook: impl Fn(&IncrementalChange) + 'static) -> Self {
+        self.on_change = Some(Box::new(hook));
+        self
+    }
+
+    /// Wait up to `timeout` for the first event, then keep collecting until `quiet` passes
+    /// without one (bursts such as an unpacked archive become one batch). `is_cached_dir`
+    /// tells deleted directories from deleted files, which no longer exist to be asked.
+    pub fn next_batch(
+        &self,
+        timeout: Duration,
+        quiet: Duration,
+        is_cached_dir: impl Fn(&Path) -> bool,
+    ) -> Result<ChangeBatch> {
+        let mut batch = ChangeBatch::default();
+        let mut wait = timeout;
+        let started = Instant::now();
+        loop {
+            match self.events.recv_timeout(wait) {
+                Ok(Ok(event)) => {
+                    if event.need_rescan() {
+                        batch.rescan = true;
+                    }
+                    let changes = changes_from_event(&event, &is_cached_dir)
+                        .into_iter()
+                        .filter(|change| !self.ignored.iter().any(|ignored| change.path.starts_with(ignored)));
+                    for change in changes {
+                        if let Some(hook) = &self.on_change {
+                            hook(&change);
+                        }
+                        batch.changes.push(change);
+                    }
+                }
+                Ok(Err(err)) => batch.errors.push(err.to_string()),
+                Err(RecvTimeoutError::Timeout) => return Ok(batch),
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("filesystem watcher stopped"),
+            }
+            // A steady trickle of events still ends a batch once `timeout` is used up
+            wait = if started.elapsed() >= timeout {
+                Duration::ZERO
+            } else {
+                quiet
+            };
+        }
+    }
+}
+
+/// The changes one watcher event stands for. Access events change nothing and are dropped.
+fn changes_from_event(event: &Event, is_cached_dir: &impl Fn(&Path) -> bool) -> Vec<IncrementalChange> {
+    let existing = |path: &PathBuf| {
+        if path.exists() {
+            IncrementalChange::created(path.clone(), path.is_dir())
+        } else {
+            IncrementalChange::deleted(path.clone(), is_cached_dir(path))
+        }
+    };
+
+    match &event.kind {
+        EventKind::Create(CreateKind::Folder) => {
+            event
+                .paths
+                .iter()
+                .map(|path| IncrementalChange::created(path.clone(), true))
+                .collect()
+        }
+        EventKind::Create(CreateKind::File) => {
+            event
+                .paths
+                .iter()
+                .map(|path| IncrementalChange::created(path.clone(), false))
+                .collect()
+        }
+        EventKind::Remove(RemoveKind::Folder) => {
+            event
+                .paths
+                .iter()
+                .map(|path| IncrementalChange::deleted(path.clone(), true))
+                .collect()
+        }
+        EventKind::Remove(RemoveKind::File) => {
+            event
+                .paths
+                .iter()
+                .map(|path| IncrementalChange::deleted(path.clone(), false))
+                .collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            event
+                .paths
+                .iter()
+                .map(|path| IncrementalChange::renamed(path.clone(), path.is_dir() || is_cached_dir(path)))
+                .collect()
+        }
+        // One side of a rename, or a kind the platform could not tell: ask the filesystem
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+            event.paths.iter().map(existing).collect()
+        }
+        EventKind::Modify(_) | EventKind::Any | EventKind::Other => {
+            event
+                .paths
+                .iter()
+                .map(|path| IncrementalChange::modified(path.clone(), path.is_dir()))
+                .collect()
+        }
+        EventKind::Access(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use notify::event::{AccessKind, DataChange};
+
+    use super::*;
+    use crate::incremental::IncrementalChangeKind;
+
+    fn test_root(name: &str) -> PathBuf {
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        std::env::temp_dir().join(format!("ptree_watch_{name}_{unique}"))
+    }
+
+    #[test]
+    fn events_map_to_changes_and_deleted_directories_come_from_the_cache() {
+        let root = test_root("events");
+        fs::create_dir_all(root.join("kept")).unwrap();
+        let gone_dir = root.join("old_dir");
+        let gone_file = root.join("old.txt");
+        let is_cached_dir = |path: &Path| path == gone_dir;
+
+        let event = |kind, path: &PathBuf| Event::new(kind).add_path(path.clone());
+        let kinds = |event: Event| -> Vec<(IncrementalChangeKind, bool)> {
+            changes_from_event(&event, &is_cached_dir)
+                .into_iter()
+                .map(|change| (change.kind, change.is_directory))
+                .collect()
+        };
+
+        assert_eq!(
+            kinds(event(EventKind::Create(CreateKind::Any), &root.join("kept"))),
+            vec![(IncrementalChangeKind::Created, true)]
+        );
+        assert_eq!(
+            kinds(event(EventKind::Remove(RemoveKind::Any), &gone_dir)),
+            vec![(IncrementalChangeKind::Deleted, true)]
+        );
+        assert_eq!(
+            kinds(event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &gone_file)),
+            vec![(IncrementalChangeKind::Deleted, false)]
+        );
+        assert_eq!(
+            kinds(event(EventKind::Modify(ModifyKind::Data(DataChange::Size)), &root.join("kept"))),
+            vec![(IncrementalChangeKind::Modified, true)]
+        );
+        assert!(kinds(event(EventKind::Access(AccessKind::Any), &root)).is_empty());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn watcher_batches_changes_below_roots_but_not_ignored_paths() {
+        let root = test_root("batch");
+        let cache_dir = root.join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let watcher = ChangeWatcher::new(std::slice::from_ref(&root), vec![cache_dir.clone()])
+            .unwrap()
+            .on_change({
+                let seen = std::rc::Rc::clone(&seen);
+                move |change| seen.borrow_mut().push(change.clone())
+            });
+        fs::write(root.join("new.txt"), b"hello").unwrap();
+        fs::write(cache_dir.join("ptree.dat"), b"snapshot").unwrap();
+
+        let batch = watcher
+            .next_batch(Duration::from_secs(5), Duration::from_millis(200), |_| false)
+            .unwrap();
+        assert!(batch.changes.iter().any(|change| change.path == root.join("new.txt")));
+        assert!(batch.changes.iter().all(|change| !change.path.starts_with(&cache_dir)));
+        assert_eq!(*seen.borrow(), batch.changes);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,469 @@
+//! ptree as a library, for Rust programs that want a scanned (and cached) directory tree
+//! without running the `ptree` binary. Configure a scan with the `Scanner` builder, run it
+//! with `scan()`, and read or render the resulting `Tree`:
+//!
+//! ```no_run
+//! use ptree_lib::{Format, Scanner};
+//!
+//! let tree = Scanner::new("/home").threads(8).skip("node_modules").skip(".git").scan()?;
+//! println!("{} directories", tree.totals().directories);
+//! print!("{}", tree.render(Format::Json)?);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Scans share the caches of the `ptree` binary: a second scan of the same roots within the
+//! cache TTL is answered from the snapshot the first one saved, and `ptree` run from a shell
+//! sees what a library scan saved (and the other way round).
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use ptree_cache::cache_rkyv::RkyvMmapCache;
+use ptree_cache::{DirEntry, DiskCache};
+use ptree_core::Args;
+pub use ptree_core::{JsonSchema, SortOrder};
+use ptree_traversal::{resolve_scan_roots, traverse_disk};
+use serde::Serialize;
+
+/// How `Tree::render` prints the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The box-drawn tree `ptree` prints, without colors
+    Tree,
+    /// The nested JSON of `ptree --format json`
+    Json,
+}
+
+/// One scanned directory, copied out of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directory {
+    pub path:           PathBuf,
+    pub name:           String,
+    pub modified:       DateTime<Utc>,
+    /// Files anywhere below the directory
+    pub file_count:     usize,
+    /// Bytes of those files
+    pub total_size:     u64,
+    /// Bytes they take on disk (fewer for compressed or sparse files)
+    pub allocated_size: u64,
+    /// Names of the files and directories directly inside
+    pub children:       Vec<String>,
+    pub is_hidden:      bool,
+    /// Stable ID, kept across rescans and renames (the `id` of `ptree --format json`)
+    pub id:             u64,
+}
+
+impl From<&DirEntry> for Directory {
+    fn from(entry: &DirEntry) -> Self {
+        Directory {
+            path:           entry.path.clone(),
+            name:           entry.name.as_str().to_string(),
+            modified:       entry.modified,
+            file_count:     entry.file_count,
+            total_size:     entry.total_size,
+            allocated_size: entry.allocated_size,
+            children:       entry.children.iter().map(|child| child.as_str().to_string()).collect(),
+            is_hidden:      entry.is_hidden,
+            id:             entry.id,
+        }
+    }
+}
+
+/// Directories, files and bytes below the roots of a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    pub directories:     usize,
+    pub files:           usize,
+    pub bytes:           u64,
+    /// Bytes allocated on disk for those files
+    pub allocated_bytes: u64,
+}
+
+/// A file or directory matching a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    pub path:   PathBuf,
+    pub is_dir: bool,
+    /// Recursive size of a directory; `None` for files, which the cache keeps by name only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size:   Option<u64>,
+}
+
+impl From<ptree_cache::SearchHit> for SearchHit {
+    fn from(hit: ptree_cache::SearchHit) -> Self {
+        SearchHit {
+            path:   hit.path,
+            is_dir: hit.is_dir,
+            size:   hit.size,
+        }
+    }
+}
+
+/// A scan of one or more roots, configured step by step; the options are those of the
+/// `ptree` command line of the same names.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    args: Args,
+}
+
+impl Scanner {
+    /// A scan of `root` with `ptree`'s defaults.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        // The defaults of the command line, without the config file or environment
+        let mut args = Args::parse_from(["ptree"]);
+        args.paths = vec![root.into()];
+        args.quiet = true;
+        args.progress = Some(false);
+        Scanner { args }
+    }
+
+    /// Scan another root as well; all roots share one cache file.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.args.paths.push(root.into());
+        self
+    }
+
+    /// Worker threads to read directories with (default: one per CPU, `--threads`).
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.args.threads = Some(threads);
+        self
+    }
+
+    /// Skip directories with this name, or the exact path if it contains a path separator
+    /// (`--skip`); call again to skip more.
+    pub fn skip(mut self, name: impl AsRef<str>) -> Self {
+        let skip = match self.args.skip.take() {
+            Some(skip) => format!("{},{}", skip, name.as_ref()),
+            None => name.as_ref().to_string(),
+        };
+        self.args.skip = Some(skip);
+        self
+    }
+
+    /// Skip entries whose name or full path matches `regex` (`--skip-regex`).
+    pub fn skip_regex(mut self, regex: impl Into<String>) -> Self {
+        self.args.skip_regex.push(regex.into());
+        self
+    }
+
+    /// Leave out what `.gitignore` files ignore (`--gitignore`).
+    pub fn gitignore(mut self, gitignore: bool) -> Self {
+        self.args.gitignore = gitignore;
+        self
+    }
+
+    /// Include hidden files and directories in renders (`--hidden`).
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.args.hidden = hidden;
+        self
+    }
+
+    /// Stay on the filesystem of each root (`--one-file-system`).
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.args.one_file_system = one_file_system;
+        self
+    }
+
+    /// Stop reading `depth` levels below the roots (`--scan-depth`). Like on the command
+    /// line, such a partial scan neither uses nor saves the cache, and renders no deeper than
+    /// it read.
+    pub fn scan_depth(mut self, depth: usize) -> Self {
+        self.args.scan_depth = Some(depth);
+        self.args.max_depth = Some(self.args.max_depth.map_or(depth, |max| max.min(depth)));
+        self
+    }
+
+    /// Render at most `depth` levels below the roots (`--max-depth`); the scan still reads
+    /// the whole tree.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.args.max_depth = Some(self.args.scan_depth.map_or(depth, |scanned| scanned.min(depth)));
+        self
+    }
+
+    /// Show directory sizes in renders (`--size`).
+    pub fn sizes(mut self, sizes: bool) -> Self {
+        self.args.size = sizes;
+        self
+    }
+
+    /// Show the file count of each directory in renders (`--file-count`).
+    pub fn file_counts(mut self, file_counts: bool) -> Self {
+        self.args.file_count = file_counts;
+        self
+    }
+
+    /// Order of each directory's children in renders (`--sort`).
+    pub fn sort(mut self, sort: SortOrder) -> Self {
+        self.args.sort = sort;
+        self
+    }
+
+    /// Structure of JSON renders (`--json-schema`); v2 unless set.
+    pub fn json_schema(mut self, schema: JsonSchema) -> Self {
+        self.args.json_schema = schema;
+        self
+    }
+
+    // ========================================================================
+    // Cache Control
+    // ========================================================================
+
+    /// Keep the cache in `dir` instead of ptree's default cache directory (`--cache-dir`).
+    pub fn cache_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.args.cache_dir = Some(dir.as_ref().display().to_string());
+        self
+    }
+
+    /// Answer from a snapshot younger than `ttl` instead of scanning (default: an hour,
+    /// `--cache-ttl`).
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.args.cache_ttl = Some(ttl.as_secs());
+        self
+    }
+
+    /// Rescan even when the snapshot is within its TTL (`--force`).
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.args.force = refresh;
+        self
+    }
+
+    /// Neither read nor write the cache, scanning every time (`use_cache(false)` is
+    /// `--no-cache`).
+    pub fn use_cache(mut self, use_cache: bool) -> Self {
+        self.args.no_cache = !use_cache;
+        self
+    }
+
+    /// Read the cache but never write it (`save(false)` is `--no-save`).
+    pub fn save(mut self, save: bool) -> Self {
+        self.args.no_save = !save;
+        self
+    }
+
+    /// The cache file a scan of these roots reads and writes.
+    pub fn cache_path(&self) -> Result<PathBuf> {
+        let roots = resolve_scan_roots(&self.args.primary_drive(), &self.args)?;
+        Ok(ptree_cache::cache_path_for_roots(
+            &ptree_cache::get_cache_path_custom(self.args.cache_dir.as_deref())?,
+            &roots,
+        ))
+    }
+
+    /// Scan the roots (or load them from the cache) into a `Tree`.
+    pub fn scan(&self) -> Result<Tree> {
+        let mut args = self.args.clone();
+        // A partial scan would stand in for the whole tree in the cache
+        args.no_cache |= args.scan_depth.is_some();
+        let cache_path = self.cache_path()?;
+        let mut cache = DiskCache::open(&cache_path)?;
+        let debug_info = traverse_disk(&args.primary_drive(), &mut cache, &args, &cache_path)?;
+
+        // Cache hits and spilled scans start with only the index in memory
+        if debug_info.cache_used || debug_info.spilled_dirs > 0 {
+            cache.load_all_entries_lazy(&cache_path)?;
+        }
+        Ok(Tree::new(cache, args, cache_path, debug_info.cache_used))
+    }
+
+    /// The tree the last scan of these roots saved, however old, without reading the disk;
+    /// `None` if they were never scanned with this cache directory.
+    pub fn load_cached(&self) -> Result<Option<Tree>> {
+        let cache_path = self.cache_path()?;
+        let mut cache = DiskCache::open(&cache_path)?;
+        if !cache.has_persisted_snapshot {
+            return Ok(None);
+        }
+        cache.load_all_entries_lazy(&cache_path)?;
+        Ok(Some(Tree::new(cache, self.args.clone(), cache_path, true)))
+    }
+
+    /// Files and directories of the saved snapshot matching the wildcard `pattern`, sorted by
+    /// path, at most `limit` of them (`ptree search`). The snapshot is searched in place, neither
+    /// loaded nor refreshed.
+    pub fn search(&self, pattern: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let cache_path = self.cache_path()?;
+        if !DiskCache::open(&cache_path)?.has_persisted_snapshot {
+            bail!("no cached snapshot in {}; scan first", cache_path.display());
+        }
+        let records = RkyvMmapCache::open(&cache_path.with_extension("idx"), &cache_path.with_extension("dat"))?;
+        Ok(records
+            .search(pattern, limit)?
+            .into_iter()
+            .map(SearchHit::from)
+            .collect())
+    }
+}
+
+/// The directories of a finished scan, with their sizes and file counts.
+pub struct Tree {
+    cache:      DiskCache,
+    args:       Args,
+    cache_path: PathBuf,
+    from_cache: bool,
+}
+
+impl Tree {
+    fn new(mut cache: DiskCache, args: Args, cache_path: PathBuf, from_cache: bool) -> Self {
+        cache.show_hidden = args.hidden;
+        cache.sort = args.sort;
+        cache.reverse = args.reverse;
+        cache.json_schema = args.json_schema;
+        Tree {
+            cache,
+            args,
+            cache_path,
+            from_cache,
+        }
+    }
+
+    /// The roots scanned, in the order they were given.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.cache.top_level_roots()
+    }
+
+    /// The directory at `path`, or `None` if it was not scanned (or is a file).
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<Directory> {
+        self.entry(path.as_ref()).map(Directory::from)
+    }
+
+    /// Every directory scanned, in no particular order.
+    pub fn directories(&self) -> impl Iterator<Item = Directory> + '_ {
+        self.cache
+            .entries
+            .values()
+            .filter(|entry| entry.is_dir)
+            .map(Directory::from)
+    }
+
+    /// Directories, files and bytes below the roots.
+    pub fn totals(&self) -> Totals {
+        let totals = self.cache.totals();
+        Totals {
+            directories:     totals.directories,
+            files:           totals.files,
+            bytes:           totals.bytes,
+            allocated_bytes: totals.allocated_bytes,
+        }
+    }
+
+    fn entry(&self, path: &Path) -> Option<&DirEntry> {
+        self.cache.get_entry(path).filter(|entry| entry.is_dir)
+    }
+
+    /// Whether the tree came from the cache rather than from reading the disk.
+    pub fn from_cache(&self) -> bool {
+        self.from_cache
+    }
+
+    /// The cache file the tree was read from or saved to.
+    pub fn cache_path(&self) -> &Path {
+        &self.cache_path
+    }
+
+    /// Files and directories of the tree matching the wildcard `pattern` (`*`, `?`; matched
+    /// against the whole path if it contains a separator, else the name), sorted by path, at
+    /// most `limit` of them.
+    pub fn search(&self, pattern: &str, limit: usize) -> Vec<SearchHit> {
+        self.cache
+            .search(pattern, limit)
+            .into_iter()
+            .map(SearchHit::from)
+            .collect()
+    }
+
+    /// The JSON object of the directory at `path` alone, `max_depth` levels deep, as
+    /// `ptree client tree` returns it; `None` if `path` is not a scanned directory.
+    pub fn subtree_json(&self, path: impl AsRef<Path>, max_depth: Option<usize>) -> Result<Option<String>> {
+        let path = path.as_ref();
+        if self.entry(path).is_none() {
+            return Ok(None);
+        }
+        let mut out = Vec::new();
+        self.cache.write_json_subtree(
+            &mut out,
+            path,
+            max_depth,
+            self.args.size,
+            self.args.file_count,
+            self.args.json_schema,
+        )?;
+        Ok(Some(String::from_utf8(out)?))
+    }
+
+    /// The tree as `ptree` prints it in `format`.
+    pub fn render(&self, format: Format) -> Result<String> {
+        let mut out = Vec::new();
+        self.write(&mut out, format)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// Stream the tree to `writer` in `format`, without building it as one string first.
+    pub fn write<W: Write>(&self, writer: &mut W, format: Format) -> Result<()> {
+        let args = &self.args;
+        match format {
+            Format::Tree => {
+                self.cache
+                    .write_tree_output_with_options(writer, args.max_depth, args.size, args.file_count)
+            }
+            Format::Json => {
+                self.cache
+                    .write_json_output(writer, args.max_depth, args.size, args.file_count)?;
+                writer.write_all(b"\n")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn scans_renders_and_answers_the_second_scan_from_the_cache() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_lib_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let root = temp_dir.join("root");
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(root.join("target/debug"))?;
+        fs::write(root.join("src/main.rs"), "fn main() {}")?;
+
+        let scanner = Scanner::new(&root)
+            .threads(2)
+            .skip("target")
+            .cache_dir(temp_dir.join("cache"));
+        let tree = scanner.scan()?;
+        assert!(!tree.from_cache());
+        assert!(tree.get(root.join("src")).is_some());
+        assert!(tree.get(root.join("target")).is_none());
+        assert_eq!(tree.totals().files, 1);
+        let json = tree.render(Format::Json)?;
+        assert!(json.contains("\"main.rs\""));
+        assert!(tree.render(Format::Tree)?.contains("src"));
+
+        let cached = scanner.scan()?;
+        assert!(cached.from_cache());
+        assert_eq!(cached.render(Format::Json)?, json);
+        assert_eq!(scanner.load_cached()?.unwrap().render(Format::Json)?, json);
+        let hits = scanner.search("*.rs", 10)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, root.join("src/main.rs"));
+        assert_eq!(cached.search("*.rs", 10), hits);
+        let src = cached.subtree_json(root.join("src"), None)?.unwrap();
+        assert!(src.starts_with('{') && src.contains("\"main.rs\""));
+        assert!(cached.subtree_json(root.join("src/main.rs"), None)?.is_none());
+        assert!(Scanner::new(temp_dir.join("elsewhere"))
+            .cache_dir(temp_dir.join("cache"))
+            .load_cached()?
+            .is_none());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
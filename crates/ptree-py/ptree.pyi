@@ -0,0 +1,38 @@
+from os import PathLike
+from typing import Optional, TypedDict, Union
+
+_Path = Union[str, PathLike[str]]
+
+class Directory(TypedDict):
+    path: str
+    parent: Optional[str]
+    name: str
+    modified: str
+    file_count: int
+    total_size: int
+    allocated_size: int
+    children: int
+    is_hidden: bool
+    id: int
+
+class SearchHit(TypedDict):
+    path: str
+    is_dir: bool
+    size: Optional[int]
+
+def scan(
+    path: _Path,
+    *,
+    threads: Optional[int] = None,
+    skip: list[str] = ...,
+    hidden: bool = False,
+    cache_dir: Optional[_Path] = None,
+    cache_ttl: Optional[int] = None,
+    refresh: bool = False,
+    use_cache: bool = True,
+    save: bool = True,
+) -> list[Directory]: ...
+def load_cache(path: _Path, *, cache_dir: Optional[_Path] = None) -> Optional[list[Directory]]: ...
+def search(
+    pattern: str, path: _Path, *, cache_dir: Optional[_Path] = None, limit: int = 1000
+) -> list[SearchHit]: ...
@@ -0,0 +1,249 @@
+//! Python bindings (`import ptree`), built into a wheel with maturin from this directory.
+//! `scan()`, `load_cache()` and `search()` return lists of dicts, one per directory or match,
+//! which `pandas.DataFrame` takes as they are. The rows are built in plain Rust below; the
+//! module itself is behind the `python` feature, so the workspace builds without Python.
+
+use ptree_lib::{SearchHit, Tree};
+
+/// One directory of a scanned tree, as `scan()` and `load_cache()` return it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryRow {
+    pub path:           String,
+    /// `None` for the roots
+    pub parent:         Option<String>,
+    pub name:           String,
+    /// Last modification, RFC 3339 in UTC
+    pub modified:       String,
+    /// Files anywhere below the directory
+    pub file_count:     usize,
+    /// Bytes of those files
+    pub total_size:     u64,
+    /// Bytes they take on disk
+    pub allocated_size: u64,
+    /// Files and directories directly inside
+    pub children:       usize,
+    pub is_hidden:      bool,
+    /// Stable ID, kept across rescans and renames (the `id` of `ptree --format json`)
+    pub id:             u64,
+}
+
+/// The directories of `tree`, sorted by path so parents come before their children.
+pub fn directory_rows(tree: &Tree) -> Vec<DirectoryRow> {
+    let roots = tree.roots();
+    let mut rows: Vec<DirectoryRow> = tree
+        .directories()
+        .map(|entry| {
+            DirectoryRow {
+                path:           entry.path.display().to_string(),
+                parent:         entry
+                    .path
+                    .parent()
+                    .filter(|_| !roots.contains(&entry.path))
+                    .map(|parent| parent.display().to_string()),
+                name:           entry.name,
+                modified:       entry.modified.to_rfc3339(),
+                file_count:     entry.file_count,
+                total_size:     entry.total_size,
+                allocated_size: entry.allocated_size,
+                children:       entry.children.len(),
+                is_hidden:      entry.is_hidden,
+                id:             entry.id,
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.path.cmp(&b.path));
+    rows
+}
+
+/// One `search()` match: `size` is the recursive size of a directory, `None` for files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchRow {
+    pub path:   String,
+    pub is_dir: bool,
+    pub size:   Option<u64>,
+}
+
+pub fn search_rows(hits: Vec<SearchHit>) -> Vec<SearchRow> {
+    hits.into_iter()
+        .map(|hit| {
+            SearchRow {
+                path:   hit.path.display().to_string(),
+                is_dir: hit.is_dir,
+                size:   hit.size,
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "python")]
+mod python {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use ptree_lib::Scanner;
+    use pyo3::exceptions::PyRuntimeError;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use super::{directory_rows, search_rows, DirectoryRow, SearchRow};
+
+    fn to_py_err(err: anyhow::Error) -> PyErr {
+        PyRuntimeError::new_err(format!("{:#}", err))
+    }
+
+    fn scanner(path: PathBuf, cache_dir: Option<PathBuf>) -> Scanner {
+        let scanner = Scanner::new(path);
+        match cache_dir {
+            Some(dir) => scanner.cache_dir(dir),
+            None => scanner,
+        }
+    }
+
+    fn directory_dicts(py: Python<'_>, rows: Vec<DirectoryRow>) -> PyResult<Vec<Bound<'_, PyDict>>> {
+        rows.into_iter()
+            .map(|row| {
+                let dict = PyDict::new(py);
+                dict.set_item("path", row.path)?;
+                dict.set_item("parent", row.parent)?;
+                dict.set_item("name", row.name)?;
+                dict.set_item("modified", row.modified)?;
+                dict.set_item("file_count", row.file_count)?;
+                dict.set_item("total_size", row.total_size)?;
+                dict.set_item("allocated_size", row.allocated_size)?;
+                dict.set_item("children", row.children)?;
+                dict.set_item("is_hidden", row.is_hidden)?;
+                dict.set_item("id", row.id)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    fn search_dicts(py: Python<'_>, rows: Vec<SearchRow>) -> PyResult<Vec<Bound<'_, PyDict>>> {
+        rows.into_iter()
+            .map(|row| {
+                let dict = PyDict::new(py);
+                dict.set_item("path", row.path)?;
+                dict.set_item("is_dir", row.is_dir)?;
+                dict.set_item("size", row.size)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Scan `path` (or answer from a snapshot younger than `cache_ttl` seconds) and return its
+    /// directories.
+    #[pyfunction]
+    #[pyo3(signature = (
+        path, *, threads=None, skip=Vec::new(), hidden=false, cache_dir=None, cache_ttl=None,
+        refresh=false, use_cache=true, save=true
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn scan(
+        py: Python<'_>,
+        path: PathBuf,
+        threads: Option<usize>,
+        skip: Vec<String>,
+        hidden: bool,
+        cache_dir: Option<PathBuf>,
+        cache_ttl: Option<u64>,
+        refresh: bool,
+        use_cache: bool,
+        save: bool,
+    ) -> PyResult<Vec<Bound<'_, PyDict>>> {
+        let mut scanner = scanner(path, cache_dir)
+            .hidden(hidden)
+            .refresh(refresh)
+            .use_cache(use_cache)
+            .save(save);
+        if let Some(threads) = threads {
+            scanner = scanner.threads(threads);
+        }
+        for name in &skip {
+            scanner = scanner.skip(name);
+        }
+        if let Some(ttl) = cache_ttl {
+            scanner = scanner.cache_ttl(Duration::from_secs(ttl));
+        }
+        // Other Python threads keep running while the disk is read
+        let rows = py
+            .allow_threads(|| scanner.scan().map(|tree| directory_rows(&tree)))
+            .map_err(to_py_err)?;
+        directory_dicts(py, rows)
+    }
+
+    /// The directories the last scan of `path` saved, without reading the disk; `None` if it
+    /// was never scanned.
+    #[pyfunction]
+    #[pyo3(signature = (path, *, cache_dir=None))]
+    fn load_cache(
+        py: Python<'_>,
+        path: PathBuf,
+        cache_dir: Option<PathBuf>,
+    ) -> PyResult<Option<Vec<Bound<'_, PyDict>>>> {
+        let scanner = scanner(path, cache_dir);
+        let rows = py
+            .allow_threads(|| scanner.load_cached().map(|tree| tree.map(|tree| directory_rows(&tree))))
+            .map_err(to_py_err)?;
+        rows.map(|rows| directory_dicts(py, rows)).transpose()
+    }
+
+    /// Files and directories of the snapshot of `path` matching the wildcard `pattern`, like
+    /// `ptree search`.
+    #[pyfunction]
+    #[pyo3(signature = (pattern, path, *, cache_dir=None, limit=1000))]
+    fn search(
+        py: Python<'_>,
+        pattern: String,
+        path: PathBuf,
+        cache_dir: Option<PathBuf>,
+        limit: usize,
+    ) -> PyResult<Vec<Bound<'_, PyDict>>> {
+        let scanner = scanner(path, cache_dir);
+        let rows = py
+            .allow_threads(|| scanner.search(&pattern, limit).map(search_rows))
+            .map_err(to_py_err)?;
+        search_dicts(py, rows)
+    }
+
+    #[pymodule]
+    fn ptree(module: &Bound<'_, PyModule>) -> PyResult<()> {
+        module.add_function(wrap_pyfunction!(scan, module)?)?;
+        module.add_function(wrap_pyfunction!(load_cache, module)?)?;
+        module.add_function(wrap_pyfunction!(search, module)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use ptree_lib::Scanner;
+
+    use super::*;
+
+    #[test]
+    fn rows_list_every_directory_with_its_parent() -> anyhow::Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_py_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let root = temp_dir.join("root");
+        fs::create_dir_all(root.join("data"))?;
+        fs::write(root.join("data/a.csv"), "1,2,3\n")?;
+
+        let scanner = Scanner::new(&root).cache_dir(temp_dir.join("cache"));
+        let rows = directory_rows(&scanner.scan()?);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].path, root.display().to_string());
+        assert_eq!(rows[0].parent, None);
+        assert_eq!(rows[1].name, "data");
+        assert_eq!(rows[1].parent.as_deref(), Some(rows[0].path.as_str()));
+        assert_eq!((rows[1].file_count, rows[1].total_size, rows[1].children), (1, 6, 1));
+
+        let hits = search_rows(scanner.search("*.csv", 10)?);
+        assert_eq!(hits.len(), 1);
+        assert!(!hits[0].is_dir);
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+//! The launchd backend on macOS: a user agent (`~/Library/LaunchAgents/<LABEL>.plist`)
+//! that starts one scheduled refresh every 30 minutes, loaded with `launchctl bootstrap`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+
+pub const LABEL: &str = "com.winux.ptree-refresh";
+
+/// Seconds between refreshes
+const START_INTERVAL: u32 = 30 * 60;
+
+fn plist(exe_path: &str, args: &str, log_path: &str) -> String {
+    let arguments: String = std::iter::once(exe_path)
+        .chain(args.split_whitespace())
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>StartInterval</key>
+    <integer>{}</integer>
+    <key>ProcessType</key>
+    <string>Background</string>
+    <key>LowPriorityIO</key>
+    <true/>
+    <key>Nice</key>
+    <integer>10</integer>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        LABEL,
+        arguments,
+        START_INTERVAL,
+        xml_escape(log_path),
+        xml_escape(log_path)
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn home() -> Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .filter(|home| home.is_absolute())
+        .ok_or_else(|| anyhow!("Could not find the LaunchAgents directory. Set HOME to an absolute path."))
+}
+
+fn plist_path() -> Result<PathBuf> {
+    Ok(home()?
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", LABEL)))
+}
+
+/// `gui/<uid>`: the launchd domain of the logged-in user's agents.
+fn domain() -> Result<String> {
+    let output = Command::new("id").arg("-u").output()?;
+    let uid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || uid.is_empty() {
+        return Err(anyhow!("Could not find the current user id"));
+    }
+    Ok(format!("gui/{}", uid))
+}
+
+fn launchctl(args: &[&str]) -> Result<String> {
+    let output = Command::new("launchctl")
+        .args(args)
+        .output()
+        .map_err(|err| anyhow!("Could not run launchctl: {}", err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("launchctl {} failed: {}", args.join(" "), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn is_available() -> bool {
+    cfg!(target_os = "macos")
+}
+
+/// Whether the ptree agent is installed for this user.
+pub fn is_installed() -> bool {
+    plist_path().is_ok_and(|path| path.exists())
+}
+
+/// Write the agent for `exe_path` and load it; returns false if it was already installed and
+/// loaded as it is.
+pub fn install(exe_path: &str, args: &str) -> Result<bool> {
+    let path = plist_path()?;
+    let log_path = home()?.join("Library").join("Logs").join("ptree-refresh.log");
+    let contents = plist(exe_path, args, &log_path.display().to_string());
+    let domain = domain()?;
+    let service = format!("{}/{}", domain, LABEL);
+    let unchanged = fs::read_to_string(&path).is_ok_and(|current| current == contents);
+    if unchanged && launchctl(&["print", &service]).is_ok() {
+        return Ok(false);
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if let Some(dir) = log_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, contents)?;
+    // A loaded agent keeps its old definition until it is booted out
+    let _ = launchctl(&["bootout", &service]);
+    launchctl(&["bootstrap", &domain, &path.display().to_string()])?;
+    Ok(true)
+}
+
+/// Unload the agent and remove its plist; returns false if it was not installed.
+pub fn uninstall() -> Result<bool> {
+    let path = plist_path()?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    if let Ok(domain) = domain() {
+        let _ = launchctl(&["bootout", &format!("{}/{}", domain, LABEL)]);
+    }
+    fs::remove_file(&path)?;
+    Ok(true)
+}
+
+/// Print where the agent is, whether launchd has it loaded, and how its last run ended.
+pub fn print_status() {
+    if let Ok(path) = plist_path() {
+        println!("launchd agent: {}", path.display());
+    }
+    let printed = domain().and_then(|domain| launchctl(&["print", &format!("{}/{}", domain, LABEL)]));
+    let Ok(printed) = printed else {
+        println!("  State:    not loaded (log in again, or run 'ptree scheduler install')");
+        return;
+    };
+    let field = |key: &str| {
+        printed
+            .lines()
+            .filter_map(|line| line.trim().split_once(" = "))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.to_string())
+    };
+    println!("  State:    loaded, {}", field("state").unwrap_or_else(|| "unknown".to_string()));
+    if let Some(runs) = field("runs") {
+        println!("  Runs:     {}", runs);
+    }
+    if let Some(code) = field("last exit code") {
+        println!("  Last exit code: {}", code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plist_runs_the_scheduled_refresh_every_30_minutes() {
+        let plist = plist("/Users/a&b/bin/ptree", "--scheduled --summary-only", "/Users/a&b/Library/Logs/p.log");
+
+        assert!(plist.contains(&format!("<string>{}</string>", LABEL)));
+        assert!(plist.contains(
+            "        <string>/Users/a&amp;b/bin/ptree</string>\n        <string>--scheduled</string>\n        \
+             <string>--summary-only</string>\n    </array>"
+        ));
+        assert!(plist.contains("<key>StartInterval</key>\n    <integer>1800</integer>"));
+        assert!(plist.contains("<string>/Users/a&amp;b/Library/Logs/p.log</string>"));
+    }
+}
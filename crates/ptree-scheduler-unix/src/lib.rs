@@ -1,7 +1,17 @@
+#[cfg(unix)]
+mod launchd;
+#[cfg(unix)]
+mod systemd;
+
 use anyhow::{anyhow, Result};
-use ptree_core::SCHEDULED_REFRESH_ARGS;
+use ptree_core::{SchedulerBackend, SCHEDULED_REFRESH_ARGS};
 
-const LEGACY_SCHEDULED_REFRESH_ARGS: &str = "--force --quiet";
+/// Args installed by earlier versions; their entries are migrated on install and removed on uninstall.
+const LEGACY_SCHEDULED_REFRESH_ARGS: &[&str] = &[
+    "--force --quiet",
+    "--quiet --cache-ttl 30",
+    "--summary-only --cache-ttl 30",
+];
 
 fn cron_entry(exe_path: &str, args: &str) -> String {
     format!("*/30 * * * * {} {}", exe_path, args)
@@ -9,7 +19,10 @@ fn cron_entry(exe_path: &str, args: &str) -> String {
 
 fn replace_or_append_scheduler_entry(crontab_content: &str, exe_path: &str) -> (String, bool) {
     let desired_entry = cron_entry(exe_path, SCHEDULED_REFRESH_ARGS);
-    let legacy_entry = cron_entry(exe_path, LEGACY_SCHEDULED_REFRESH_ARGS);
+    let legacy_entries: Vec<String> = LEGACY_SCHEDULED_REFRESH_ARGS
+        .iter()
+        .map(|args| cron_entry(exe_path, args))
+        .collect();
 
     let mut changed = false;
     let mut found_desired = false;
@@ -26,7 +39,7 @@ fn replace_or_append_scheduler_entry(crontab_content: &str, exe_path: &str) -> (
             continue;
         }
 
-        if line == legacy_entry {
+        if legacy_entries.iter().any(|legacy| line == legacy) {
             if !found_desired {
                 new_lines.push(desired_entry.clone());
                 found_desired = true;
@@ -48,13 +61,16 @@ fn replace_or_append_scheduler_entry(crontab_content: &str, exe_path: &str) -> (
 
 fn remove_scheduler_entries(crontab_content: &str, exe_path: &str) -> (String, bool) {
     let desired_entry = cron_entry(exe_path, SCHEDULED_REFRESH_ARGS);
-    let legacy_entry = cron_entry(exe_path, LEGACY_SCHEDULED_REFRESH_ARGS);
+    let legacy_entries: Vec<String> = LEGACY_SCHEDULED_REFRESH_ARGS
+        .iter()
+        .map(|args| cron_entry(exe_path, args))
+        .collect();
 
     let mut removed = false;
     let mut new_lines = Vec::new();
 
     for line in crontab_content.lines() {
-        if line == desired_entry || line == legacy_entry {
+        if line == desired_entry || legacy_entries.iter().any(|legacy| line == legacy) {
             removed = true;
             continue;
         }
@@ -68,41 +84,48 @@ fn remove_scheduler_entries(crontab_content: &str, exe_path: &str) -> (String, b
     }
 }
 
-/// Install a cron entry that refreshes the cache every 30 minutes.
-#[cfg(unix)]
-pub fn install_scheduler() -> Result<()> {
-    use std::io::Write;
-    use std::os::unix::process::ExitStatusExt;
-    use std::path::PathBuf;
-    use std::process::{Command, Stdio};
-
-    let exe_path: PathBuf = std::env::current_exe()?;
-    let exe_path_str = exe_path.display().to_string();
-
-    let crontab_check = Command::new("which").arg("crontab").output();
-    if crontab_check.is_err() || !crontab_check?.status.success() {
-        return Err(anyhow!("crontab not found. Please install cron: sudo apt-get install cron (Ubuntu/Debian)"));
+/// The backend `install` uses for `requested`: the one asked for; else launchd on macOS;
+/// else the one already installed, cron where `crontab` exists, or a systemd user timer.
+fn resolve_backend(
+    requested: SchedulerBackend,
+    cron_installed: bool,
+    systemd_installed: bool,
+    has_crontab: bool,
+    macos: bool,
+) -> SchedulerBackend {
+    match requested {
+        // cron entries may never run on macOS, so older installs move to launchd
+        SchedulerBackend::Auto if macos => SchedulerBackend::Launchd,
+        SchedulerBackend::Auto if systemd_installed && !cron_installed => SchedulerBackend::Systemd,
+        SchedulerBackend::Auto if cron_installed || has_crontab => SchedulerBackend::Cron,
+        SchedulerBackend::Auto => SchedulerBackend::Systemd,
+        backend => backend,
     }
+}
 
-    let current_crontab = Command::new("crontab").arg("-l").output().unwrap_or_else(|_| {
-        std::process::Output {
-            status: std::process::ExitStatus::from_raw(1),
-            stdout: Vec::new(),
-            stderr: Vec::new(),
-        }
-    });
+fn backend_name(backend: SchedulerBackend) -> &'static str {
+    match backend {
+        SchedulerBackend::Systemd => "systemd timer",
+        SchedulerBackend::Launchd => "launchd agent",
+        _ => "cron entry",
+    }
+}
 
-    let crontab_content = if current_crontab.status.success() {
-        String::from_utf8_lossy(&current_crontab.stdout).to_string()
-    } else {
-        String::new()
-    };
+/// The user's crontab, or an empty one if there is none.
+#[cfg(unix)]
+fn read_crontab() -> String {
+    use std::process::Command;
 
-    let (new_crontab, changed) = replace_or_append_scheduler_entry(&crontab_content, &exe_path_str);
-    if !changed {
-        println!("✓ Scheduler already installed");
-        return Ok(());
+    match Command::new("crontab").arg("-l").output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).to_string(),
+        _ => String::new(),
     }
+}
+
+#[cfg(unix)]
+fn write_crontab(content: &str) -> Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
 
     let mut child = Command::new("crontab").arg("-").stdin(Stdio::piped()).spawn()?;
 
@@ -111,76 +134,148 @@ pub fn install_scheduler() -> Result<()> {
             .stdin
             .as_mut()
             .ok_or_else(|| anyhow!("Failed to open crontab stdin"))?;
-        stdin.write_all(new_crontab.as_bytes())?;
+        stdin.write_all(content.as_bytes())?;
     }
 
     let output = child.wait_with_output()?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to install cron job: {}", stderr));
+        return Err(anyhow!("Failed to update the crontab: {}", stderr));
     }
-
-    println!("✓ Cache refresh scheduled for every 30 minutes");
-    println!("  Scheduled args: {}", SCHEDULED_REFRESH_ARGS);
-    println!("  Run 'ptree --scheduler-status' to verify installation");
     Ok(())
 }
 
-#[cfg(not(unix))]
-pub fn install_scheduler() -> Result<()> {
-    Err(anyhow!("Unix scheduler is only available on Unix targets"))
+#[cfg(unix)]
+fn has_crontab() -> bool {
+    std::process::Command::new("which")
+        .arg("crontab")
+        .output()
+        .is_ok_and(|output| output.status.success())
 }
 
-/// Remove the ptree cron entry.
+/// Install a cron entry, systemd user timer or launchd agent that refreshes the cache every
+/// 30 minutes, removing the other backends' so the refresh never runs twice.
 #[cfg(unix)]
-pub fn uninstall_scheduler() -> Result<()> {
-    use std::io::Write;
-    use std::os::unix::process::ExitStatusExt;
-    use std::path::PathBuf;
-    use std::process::{Command, Stdio};
-
-    let exe_path: PathBuf = std::env::current_exe()?;
-    let exe_path_str = exe_path.display().to_string();
-
-    let current_crontab = Command::new("crontab").arg("-l").output().unwrap_or_else(|_| {
-        std::process::Output {
-            status: std::process::ExitStatus::from_raw(1),
-            stdout: Vec::new(),
-            stderr: Vec::new(),
+pub fn install_scheduler(backend: SchedulerBackend) -> Result<()> {
+    let exe_path_str = std::env::current_exe()?.display().to_string();
+    let has_crontab = has_crontab();
+    let crontab_content = if has_crontab { read_crontab() } else { String::new() };
+    let cron_installed = remove_scheduler_entries(&crontab_content, &exe_path_str).1;
+
+    let macos = cfg!(target_os = "macos");
+    let backend = resolve_backend(backend, cron_installed, systemd::is_installed(), has_crontab, macos);
+    let changed = match backend {
+        SchedulerBackend::Systemd => {
+            if !systemd::is_available() {
+                let no_cron = if has_crontab {
+                    ""
+                } else {
+                    ", and no crontab. Please install cron: sudo apt-get install cron (Ubuntu/Debian)"
+                };
+                return Err(anyhow!(
+                    "No systemd user session to install the timer in (systemctl --user failed){}",
+                    no_cron
+                ));
+            }
+            systemd::install(&exe_path_str, SCHEDULED_REFRESH_ARGS)?
         }
-    });
+        SchedulerBackend::Launchd => {
+            if !launchd::is_available() {
+                return Err(anyhow!("launchd is only available on macOS"));
+            }
+            launchd::install(&exe_path_str, SCHEDULED_REFRESH_ARGS)?
+        }
+        _ => {
+            if !has_crontab {
+                return Err(anyhow!(
+                    "crontab not found. Please install cron: sudo apt-get install cron (Ubuntu/Debian)"
+                ));
+            }
+            let (new_crontab, changed) = replace_or_append_scheduler_entry(&crontab_content, &exe_path_str);
+            if changed {
+                write_crontab(&new_crontab).map_err(|err| anyhow!("Failed to install cron job: {:#}", err))?;
+            }
+            changed
+        }
+    };
 
-    if !current_crontab.status.success() {
-        println!("✗ No crontab found");
+    // Only one backend runs the refresh
+    let mut removed = Vec::new();
+    if backend != SchedulerBackend::Cron && cron_installed {
+        write_crontab(&remove_scheduler_entries(&crontab_content, &exe_path_str).0)?;
+        removed.push(SchedulerBackend::Cron);
+    }
+    if backend != SchedulerBackend::Systemd && systemd::uninstall()? {
+        removed.push(SchedulerBackend::Systemd);
+    }
+    if backend != SchedulerBackend::Launchd && launchd::uninstall()? {
+        removed.push(SchedulerBackend::Launchd);
+    }
+    for other in &removed {
+        println!("✓ Removed the {} in favor of the {}", backend_name(*other), backend_name(backend));
+    }
+    if !changed && removed.is_empty() {
+        println!("✓ Scheduler already installed ({})", backend_name(backend));
         return Ok(());
     }
 
-    let crontab_content = String::from_utf8_lossy(&current_crontab.stdout);
-    let (new_crontab, removed) = remove_scheduler_entries(&crontab_content, &exe_path_str);
+    println!("✓ Cache refresh scheduled for every 30 minutes ({})", backend_name(backend));
+    println!("  Scheduled args: {}", SCHEDULED_REFRESH_ARGS);
+    println!("  Run 'ptree scheduler status' to verify installation");
+    Ok(())
+}
 
+#[cfg(not(unix))]
+pub fn install_scheduler(_backend: SchedulerBackend) -> Result<()> {
+    Err(anyhow!("Unix scheduler is only available on Unix targets"))
+}
+
+/// Remove the ptree cron entry, systemd timer and launchd agent. Each is removed on its own, so
+/// one that cannot be reached (no HOME for the systemd units) does not keep the others in place.
+#[cfg(unix)]
+pub fn uninstall_scheduler() -> Result<()> {
+    let removed = report_removals([
+        ("systemd timer", systemd::uninstall()),
+        ("launchd agent", launchd::uninstall()),
+        ("cron entry", uninstall_cron()),
+    ])?;
     if !removed {
-        println!("✗ ptree scheduler not found in crontab");
-        return Ok(());
+        println!("✗ ptree scheduler not found in crontab, systemd user units or launchd agents");
     }
+    Ok(())
+}
 
-    let mut child = Command::new("crontab").arg("-").stdin(Stdio::piped()).spawn()?;
-
-    {
-        let stdin = child
-            .stdin
-            .as_mut()
-            .ok_or_else(|| anyhow!("Failed to open crontab stdin"))?;
-        stdin.write_all(new_crontab.as_bytes())?;
+/// Remove this executable's entries from the crontab; false if it had none.
+#[cfg(unix)]
+fn uninstall_cron() -> Result<bool> {
+    let exe_path_str = std::env::current_exe()?.display().to_string();
+    let (new_crontab, removed) = remove_scheduler_entries(&read_crontab(), &exe_path_str);
+    if removed {
+        write_crontab(&new_crontab).map_err(|err| anyhow!("Failed to remove cron job: {:#}", err))?;
     }
+    Ok(removed)
+}
 
-    let output = child.wait_with_output()?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to remove cron job: {}", stderr));
+/// Print each backend that was removed and fail with every one that could not be; true if
+/// anything was removed.
+#[cfg(unix)]
+fn report_removals<const N: usize>(results: [(&str, Result<bool>); N]) -> Result<bool> {
+    let mut removed = false;
+    let mut failed = Vec::new();
+    for (backend, result) in results {
+        match result {
+            Ok(true) => {
+                println!("✓ {} removed", backend);
+                removed = true;
+            }
+            Ok(false) => {}
+            Err(err) => failed.push(format!("{}: {:#}", backend, err)),
+        }
     }
-
-    println!("✓ Cache refresh scheduler removed");
-    Ok(())
+    if !failed.is_empty() {
+        return Err(anyhow!("Could not remove every scheduler: {}", failed.join("; ")));
+    }
+    Ok(removed)
 }
 
 #[cfg(not(unix))]
@@ -188,36 +283,36 @@ pub fn uninstall_scheduler() -> Result<()> {
     Err(anyhow!("Unix scheduler is only available on Unix targets"))
 }
 
-/// Check cron entry status.
+/// Check cron entry, systemd timer and launchd agent status.
 #[cfg(unix)]
 pub fn check_scheduler_status() -> Result<()> {
-    use std::os::unix::process::ExitStatusExt;
-    use std::path::PathBuf;
-    use std::process::Command;
-
-    let exe_path: PathBuf = std::env::current_exe()?;
-    let exe_path_str = exe_path.display().to_string();
-
-    let output = Command::new("crontab").arg("-l").output().unwrap_or_else(|_| {
-        std::process::Output {
-            status: std::process::ExitStatus::from_raw(1),
-            stdout: Vec::new(),
-            stderr: Vec::new(),
-        }
-    });
+    let exe_path_str = std::env::current_exe()?.display().to_string();
+
+    let crontab_content = read_crontab();
+    let cron_lines: Vec<&str> = crontab_content
+        .lines()
+        .filter(|line| line.contains(&exe_path_str))
+        .collect();
+    let systemd_installed = systemd::is_installed();
+    let launchd_installed = launchd::is_installed();
+    if cron_lines.is_empty() && !systemd_installed && !launchd_installed {
+        println!("✗ Scheduler not installed\n");
+        println!("Install with: ptree scheduler install");
+        return Ok(());
+    }
 
-    let crontab_content = String::from_utf8_lossy(&output.stdout);
-    if crontab_content.contains(&exe_path_str) {
-        println!("✓ Scheduler installed and active\n");
+    println!("✓ Scheduler installed and active\n");
+    if !cron_lines.is_empty() {
         println!("Cron entry:");
-        for line in crontab_content.lines() {
-            if line.contains(&exe_path_str) {
-                println!("  {}", line);
-            }
+        for line in cron_lines {
+            println!("  {}", line);
         }
-    } else {
-        println!("✗ Scheduler not installed\n");
-        println!("Install with: ptree --scheduler");
+    }
+    if systemd_installed {
+        systemd::print_status();
+    }
+    if launchd_installed {
+        launchd::print_status();
     }
 
     Ok(())
@@ -230,9 +325,11 @@ pub fn check_scheduler_status() -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use ptree_core::SCHEDULED_REFRESH_ARGS;
+    use ptree_core::{SchedulerBackend, SCHEDULED_REFRESH_ARGS};
 
-    use super::{cron_entry, remove_scheduler_entries, replace_or_append_scheduler_entry};
+    #[cfg(unix)]
+    use super::report_removals;
+    use super::{cron_entry, remove_scheduler_entries, replace_or_append_scheduler_entry, resolve_backend};
 
     #[test]
     fn install_migrates_legacy_force_entry() {
@@ -246,6 +343,29 @@ mod tests {
         assert!(!updated.contains("--force"));
     }
 
+    #[test]
+    fn install_migrates_previous_quiet_entry() {
+        let exe = "/usr/local/bin/ptree";
+        let previous = format!("{}\n", cron_entry(exe, "--quiet --cache-ttl 30"));
+
+        let (updated, changed) = replace_or_append_scheduler_entry(&previous, exe);
+
+        assert!(changed);
+        assert_eq!(updated, format!("{}\n", cron_entry(exe, SCHEDULED_REFRESH_ARGS)));
+    }
+
+    #[test]
+    fn install_migrates_entry_without_machine_coordination() {
+        let exe = "/usr/local/bin/ptree";
+        let previous = format!("{}\n", cron_entry(exe, "--summary-only --cache-ttl 30"));
+
+        let (updated, changed) = replace_or_append_scheduler_entry(&previous, exe);
+
+        assert!(changed);
+        assert_eq!(updated, format!("{}\n", cron_entry(exe, SCHEDULED_REFRESH_ARGS)));
+        assert!(updated.contains("--scheduled"));
+    }
+
     #[test]
     fn install_is_noop_when_desired_entry_exists() {
         let exe = "/usr/local/bin/ptree";
@@ -269,4 +389,33 @@ mod tests {
         assert!(removed);
         assert_eq!(updated, "MAILTO=root\n");
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_backend_that_fails_to_uninstall_does_not_stop_the_others() {
+        use anyhow::anyhow;
+
+        let result = report_removals([
+            ("systemd timer", Err(anyhow!("Could not find the systemd user unit directory"))),
+            ("launchd agent", Ok(false)),
+            ("cron entry", Ok(true)),
+        ]);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("systemd timer: Could not find"));
+        assert!(!err.contains("cron"));
+        assert!(report_removals([("cron entry", Ok(false))]).is_ok_and(|removed| !removed));
+    }
+
+    #[test]
+    fn auto_keeps_the_installed_backend_and_prefers_cron_when_available() {
+        let auto = SchedulerBackend::Auto;
+        assert_eq!(resolve_backend(auto, false, false, true, false), SchedulerBackend::Cron);
+        assert_eq!(resolve_backend(auto, false, false, false, false), SchedulerBackend::Systemd);
+        assert_eq!(resolve_backend(auto, false, true, true, false), SchedulerBackend::Systemd);
+        assert_eq!(resolve_backend(auto, true, false, true, false), SchedulerBackend::Cron);
+        assert_eq!(resolve_backend(SchedulerBackend::Systemd, true, false, true, false), SchedulerBackend::Systemd);
+        // Entries older versions left in the crontab move to launchd on macOS
+        assert_eq!(resolve_backend(auto, true, false, true, true), SchedulerBackend::Launchd);
+        assert_eq!(resolve_backend(SchedulerBackend::Cron, true, false, true, true), SchedulerBackend::Cron);
+    }
 }
@@ -0,0 +1,212 @@
+//! The systemd backend: `ptree-refresh.service` runs one scheduled refresh and
+//! `ptree-refresh.timer` starts it every 30 minutes, both user units under
+//! `~/.config/systemd/user` managed with `systemctl --user`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+
+pub const SERVICE_UNIT: &str = "ptree-refresh.service";
+pub const TIMER_UNIT: &str = "ptree-refresh.timer";
+
+const TIMER: &str = "\
+[Unit]
+Description=Refresh the ptree cache every 30 minutes
+
+[Timer]
+OnCalendar=*:0/30
+# Catch up on a refresh missed while the machine was off or asleep
+Persistent=true
+RandomizedDelaySec=60
+
+[Install]
+WantedBy=timers.target
+";
+
+fn service_unit(exe_path: &str, args: &str) -> String {
+    format!(
+        "\
+[Unit]
+Description=Refresh the ptree cache
+
+[Service]
+Type=oneshot
+ExecStart={} {}
+Nice=10
+IOSchedulingClass=idle
+",
+        exec_quote(exe_path),
+        args
+    )
+}
+
+/// `arg` as one word of an `ExecStart=` line: quoted, with the characters systemd would
+/// expand (`%` specifiers, `$` variables) doubled.
+fn exec_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+/// `$XDG_CONFIG_HOME/systemd/user`, or `~/.config/systemd/user`.
+fn unit_dir() -> Result<PathBuf> {
+    let absolute = |var: &str| {
+        std::env::var_os(var)
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+    };
+    absolute("XDG_CONFIG_HOME")
+        .or_else(|| absolute("HOME").map(|home| home.join(".config")))
+        .map(|config| config.join("systemd").join("user"))
+        .ok_or_else(|| anyhow!("Could not find the systemd user unit directory. Set HOME to an absolute path."))
+}
+
+fn systemctl(args: &[&str]) -> Result<String> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|err| anyhow!("Could not run systemctl: {}", err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("systemctl --user {} failed: {}", args.join(" "), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether this user has a systemd manager to enable the units with.
+pub fn is_available() -> bool {
+    systemctl(&["show-environment"]).is_ok()
+}
+
+/// Whether the ptree timer is installed for this user.
+pub fn is_installed() -> bool {
+    unit_dir().is_ok_and(|dir| installed_in(&dir))
+}
+
+/// Whether either unit is in `dir`: a service left without its timer still needs removing.
+fn installed_in(dir: &Path) -> bool {
+    [TIMER_UNIT, SERVICE_UNIT].iter().any(|unit| dir.join(unit).exists())
+}
+
+/// Write the units for `exe_path` and enable the timer; returns false if they were already
+/// installed as they are.
+pub fn install(exe_path: &str, args: &str) -> Result<bool> {
+    let dir = unit_dir()?;
+    fs::create_dir_all(&dir)?;
+    let mut changed = write_if_changed(&dir.join(SERVICE_UNIT), &service_unit(exe_path, args))?;
+    changed |= write_if_changed(&dir.join(TIMER_UNIT), TIMER)?;
+    let enabled = systemctl(&["is-enabled", TIMER_UNIT]).is_ok_and(|state| state.trim() == "enabled");
+    if !changed && enabled {
+        return Ok(false);
+    }
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", TIMER_UNIT])?;
+    Ok(true)
+}
+
+/// Disable the timer and remove both units; returns false if they were not installed.
+pub fn uninstall() -> Result<bool> {
+    let dir = unit_dir()?;
+    let units = [dir.join(TIMER_UNIT), dir.join(SERVICE_UNIT)];
+    if !units.iter().any(|unit| unit.exists()) {
+        return Ok(false);
+    }
+    // The units are removed even when the user manager is gone (a removed session, a container)
+    let _ = systemctl(&["disable", "--now", TIMER_UNIT]);
+    for unit in &units {
+        if unit.exists() {
+            fs::remove_file(unit)?;
+        }
+    }
+    let _ = systemctl(&["daemon-reload"]);
+    Ok(true)
+}
+
+/// Print where the units are, whether the timer is enabled, and its next and last runs.
+pub fn print_status() {
+    if let Ok(dir) = unit_dir() {
+        println!("systemd timer: {}", dir.join(TIMER_UNIT).display());
+    }
+    let timer = show(TIMER_UNIT, "UnitFileState,ActiveState,NextElapseUSecRealtime,LastTriggerUSec");
+    let service = show(SERVICE_UNIT, "Result");
+    let get = |properties: &[(String, String)], key: &str| {
+        properties
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+            .filter(|value| !value.is_empty() && value != "n/a")
+    };
+    match (get(&timer, "UnitFileState"), get(&timer, "ActiveState")) {
+        (Some(file_state), Some(active)) => println!("  State:    {}, {}", file_state, active),
+        _ => println!("  State:    unknown (systemctl --user is not reachable)"),
+    }
+    if let Some(next) = get(&timer, "NextElapseUSecRealtime") {
+        println!("  Next run: {}", next);
+    }
+    if let Some(last) = get(&timer, "LastTriggerUSec") {
+        let result = get(&service, "Result").unwrap_or_else(|| "unknown".to_string());
+        println!("  Last run: {} ({})", last, result);
+    }
+    if !lingers() {
+        println!("  Runs only while you are logged in; `loginctl enable-linger` keeps it running after logout");
+    }
+}
+
+/// `key=value` properties of `unit`, or none if systemctl cannot be asked.
+fn show(unit: &str, properties: &str) -> Vec<(String, String)> {
+    systemctl(&["show", unit, "--property", properties])
+        .map(|output| {
+            output
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether the user's manager keeps running without a session (`loginctl enable-linger`).
+fn lingers() -> bool {
+    std::env::var("USER").is_ok_and(|user| Path::new("/var/lib/systemd/linger").join(user).exists())
+}
+
+fn write_if_changed(path: &Path, contents: &str) -> Result<bool> {
+    if fs::read_to_string(path).is_ok_and(|current| current == contents) {
+        return Ok(false);
+    }
+    fs::write(path, contents)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_runs_the_scheduled_refresh_with_a_quoted_executable() {
+        let unit = service_unit("/home/a b/.cargo/bin/ptree%1", "--scheduled --summary-only");
+
+        assert!(unit.contains("Type=oneshot\n"));
+        assert!(unit.contains("ExecStart=\"/home/a b/.cargo/bin/ptree%%1\" --scheduled --summary-only\n"));
+        assert!(TIMER.contains("OnCalendar=*:0/30\n"));
+        assert!(TIMER.contains("WantedBy=timers.target\n"));
+    }
+
+    #[test]
+    fn a_service_unit_without_its_timer_counts_as_installed() {
+        let dir = std::env::temp_dir().join(format!("ptree_systemd_units_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        assert!(!installed_in(&dir));
+        fs::write(dir.join(SERVICE_UNIT), service_unit("/usr/bin/ptree", "--scheduled")).unwrap();
+        assert!(installed_in(&dir));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod service;
+
 #[cfg(windows)]
 use std::process::Command;
 
@@ -45,7 +47,7 @@ pub fn install_scheduler() -> Result<()> {
 
     println!("✓ Cache refresh scheduled for every 30 minutes");
     println!("  Scheduled args: {}", SCHEDULED_REFRESH_ARGS);
-    println!("  Run 'ptree --scheduler-status' to verify installation");
+    println!("  Run 'ptree scheduler status' to verify installation");
     Ok(())
 }
 
@@ -114,7 +116,7 @@ if ($task) {{
 }} else {{
     Write-Host "✗ Scheduler not installed"
     Write-Host ""
-    Write-Host "Install with: ptree --scheduler"
+    Write-Host "Install with: ptree scheduler install"
 }}
 "#,
         task_name, task_name
@@ -126,13 +128,12 @@ if ($task) {{
         .arg(&ps_script)
         .output()?;
 
-    println!("{}", String::from_utf8_lossy(&output.stdout));
-
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("{}", stderr);
+        return Err(anyhow!("Failed to read the scheduled task: {}", stderr));
     }
 
+    println!("{}", String::from_utf8_lossy(&output.stdout));
     Ok(())
 }
 
@@ -0,0 +1,448 @@
+//! ptree as a Windows service (`ptree service ...`), for machines where policy restricts Task
+//! Scheduler. The Service Control Manager starts the registered command line at boot and
+//! sends stop and shutdown requests, which the running service answers by saving its cache.
+//! Registering, starting, stopping and removing take a [`ServiceSpec`], so `ptree-driver`
+//! registers its own service with the same code.
+
+#[cfg(windows)]
+use std::sync::atomic::AtomicBool;
+
+#[cfg(windows)]
+use anyhow::Result;
+
+pub const SERVICE_NAME: &str = "PTreeWatch";
+pub const SERVICE_DISPLAY_NAME: &str = "ptree cache watcher";
+
+/// The names a service is registered under and the description services.msc shows.
+#[derive(Debug, Clone, Copy)]
+pub struct ServiceSpec {
+    pub name:         &'static str,
+    pub display_name: &'static str,
+    pub description:  &'static str,
+}
+
+/// `ptree service`: `--watch` run by the Service Control Manager.
+pub const WATCH_SERVICE: ServiceSpec = ServiceSpec {
+    name:         SERVICE_NAME,
+    display_name: SERVICE_DISPLAY_NAME,
+    description:  "Keeps the ptree cache current from file system changes (ptree --watch)",
+};
+
+/// `args` as one Windows command line, quoted so that `CommandLineToArgvW` splits it back
+/// into the same arguments.
+pub fn command_line(args: &[String]) -> String {
+    args.iter().map(|arg| quote_arg(arg)).collect::<Vec<_>>().join(" ")
+}
+
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        // Backslashes are literal unless a quote follows them; then they and the quote are escaped
+        let escaped = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+        quoted.extend(std::iter::repeat_n('\\', escaped));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(windows)]
+mod scm {
+    use std::ffi::c_void;
+    use std::io;
+    use std::ptr::{null, null_mut};
+    use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+    use std::sync::{Mutex, PoisonError};
+    use std::time::{Duration, Instant};
+
+    use anyhow::{anyhow, bail, Result};
+    use windows_sys::core::PWSTR;
+    use windows_sys::Win32::Foundation::{
+        ERROR_ACCESS_DENIED,
+        ERROR_CALL_NOT_IMPLEMENTED,
+        ERROR_FAILED_SERVICE_CONTROLLER_CONNECT,
+        ERROR_SERVICE_ALREADY_RUNNING,
+        ERROR_SERVICE_DOES_NOT_EXIST,
+        ERROR_SERVICE_EXISTS,
+        ERROR_SERVICE_NOT_ACTIVE,
+        ERROR_SERVICE_SPECIFIC_ERROR,
+        NO_ERROR,
+    };
+    use windows_sys::Win32::System::Services::*;
+
+    use super::{ServiceSpec, SERVICE_NAME};
+
+    /// How long a stop may take to save the cache before the service is reported stuck
+    const STOP_WAIT_HINT_MS: u32 = 60_000;
+
+    type Body = Box<dyn FnOnce(&AtomicBool) -> Result<()> + Send>;
+
+    /// What `service_main` runs, handed over from `run_as_service`: the dispatcher calls it
+    /// without any context of ours
+    static BODY: Mutex<Option<Body>> = Mutex::new(None);
+    static RESULT: Mutex<Option<Result<()>>> = Mutex::new(None);
+    static STOP: AtomicBool = AtomicBool::new(false);
+    static STATUS_HANDLE: AtomicPtr<c_void> = AtomicPtr::new(null_mut());
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn scm_error(action: &str, err: io::Error) -> anyhow::Error {
+        if err.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+            anyhow!("Could not {}: access denied (run from an elevated prompt)", action)
+        } else {
+            anyhow!("Could not {}: {}", action, err)
+        }
+    }
+
+    /// A service manager or service handle, closed on drop
+    struct ScHandle(SC_HANDLE);
+
+    impl Drop for ScHandle {
+        fn drop(&mut self) {
+            unsafe { CloseServiceHandle(self.0) };
+        }
+    }
+
+    impl ScHandle {
+        fn manager(access: u32) -> Result<Self> {
+            let handle = unsafe { OpenSCManagerW(null(), null(), access) };
+            if handle.is_null() {
+                return Err(scm_error("open the Service Control Manager", io::Error::last_os_error()));
+            }
+            Ok(ScHandle(handle))
+        }
+
+        /// The service `spec` names, or `None` if it is not installed
+        fn service(&self, spec: &ServiceSpec, access: u32) -> Result<Option<Self>> {
+            let name = wide(spec.name);
+            let handle = unsafe { OpenServiceW(self.0, name.as_ptr(), access) };
+            if handle.is_null() {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32) {
+                    return Ok(None);
+                }
+                return Err(scm_error("open the service", err));
+            }
+            Ok(Some(ScHandle(handle)))
+        }
+
+        fn state(&self) -> Result<SERVICE_STATUS_CURRENT_STATE> {
+            let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+            if unsafe { QueryServiceStatus(self.0, &mut status) } == 0 {
+                return Err(scm_error("query the service", io::Error::last_os_error()));
+            }
+            Ok(status.dwCurrentState)
+        }
+
+        /// Ask the service to stop and wait until it has saved and exited.
+        fn stop(&self) -> Result<()> {
+            let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+            if unsafe { ControlService(self.0, SERVICE_CONTROL_STOP, &mut status) } == 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(ERROR_SERVICE_NOT_ACTIVE as i32) {
+                    return Ok(());
+                }
+                return Err(scm_error("stop the service", err));
+            }
+            let deadline = Instant::now() + Duration::from_millis(STOP_WAIT_HINT_MS as u64);
+            while self.state()? != SERVICE_STOPPED {
+                if Instant::now() > deadline {
+                    bail!("The service did not stop within {} s", STOP_WAIT_HINT_MS / 1000);
+                }
+                std::thread::sleep(Duration::from_millis(250));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn register_service(spec: &ServiceSpec, command_line: &str) -> Result<()> {
+        let manager = ScHandle::manager(SC_MANAGER_CONNECT | SC_MANAGER_CREATE_SERVICE)?;
+        let name = wide(spec.name);
+        let display_name = wide(spec.display_name);
+        let binary_path = wide(command_line);
+
+        let service = match manager.service(spec, SERVICE_ALL_ACCESS)? {
+            Some(service) => {
+                // Registering again replaces the command line; a running service is stopped, so
+                // that it starts again with the new one
+                let changed = unsafe {
+                    ChangeServiceConfigW(
+                        service.0,
+                        SERVICE_NO_CHANGE,
+                        SERVICE_AUTO_START,
+                        SERVICE_NO_CHANGE,
+                        binary_path.as_ptr(),
+                        null(),
+                        null_mut(),
+                        null(),
+                        null(),
+                        null(),
+                        display_name.as_ptr(),
+                    )
+                };
+                if changed == 0 {
+                    return Err(scm_error("update the service", io::Error::last_os_error()));
+                }
+                service.stop()?;
+                service
+            }
+            None => {
+                let handle = unsafe {
+                    CreateServiceW(
+                        manager.0,
+                        name.as_ptr(),
+                        display_name.as_ptr(),
+                        SERVICE_ALL_ACCESS,
+                        SERVICE_WIN32_OWN_PROCESS,
+                        SERVICE_AUTO_START,
+                        SERVICE_ERROR_NORMAL,
+                        binary_path.as_ptr(),
+                        null(),
+                        null_mut(),
+                        null(),
+                        null(),
+                        null(),
+                    )
+                };
+                if handle.is_null() {
+                    let err = io::Error::last_os_error();
+                    if err.raw_os_error() == Some(ERROR_SERVICE_EXISTS as i32) {
+                        bail!("A service named {} is being removed; try again in a moment", spec.name);
+                    }
+                    return Err(scm_error("create the service", err));
+                }
+                ScHandle(handle)
+            }
+        };
+
+        let mut description = wide(spec.description);
+        let info = SERVICE_DESCRIPTIONW {
+            lpDescription: description.as_mut_ptr(),
+        };
+        // Only the description shown in services.msc is lost if this fails
+        unsafe { ChangeServiceConfig2W(service.0, SERVICE_CONFIG_DESCRIPTION, &info as *const _ as *const c_void) };
+        Ok(())
+    }
+
+    pub fn start_service(spec: &ServiceSpec) -> Result<bool> {
+        let manager = ScHandle::manager(SC_MANAGER_CONNECT)?;
+        let Some(service) = manager.service(spec, SERVICE_START)? else {
+            return Ok(false);
+        };
+        if unsafe { StartServiceW(service.0, 0, null()) } == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(ERROR_SERVICE_ALREADY_RUNNING as i32) {
+                return Err(scm_error("start the service", err));
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn stop_service(spec: &ServiceSpec) -> Result<bool> {
+        let manager = ScHandle::manager(SC_MANAGER_CONNECT)?;
+        let Some(service) = manager.service(spec, SERVICE_STOP | SERVICE_QUERY_STATUS)? else {
+            return Ok(false);
+        };
+        service.stop()?;
+        Ok(true)
+    }
+
+    pub fn uninstall_service(spec: &ServiceSpec) -> Result<bool> {
+        let manager = ScHandle::manager(SC_MANAGER_CONNECT)?;
+        let Some(service) = manager.service(spec, SERVICE_ALL_ACCESS)? else {
+            return Ok(false);
+        };
+        service.stop()?;
+        if unsafe { DeleteService(service.0) } == 0 {
+            return Err(scm_error("remove the service", io::Error::last_os_error()));
+        }
+        Ok(true)
+    }
+
+    pub fn service_state(spec: &ServiceSpec) -> Result<Option<&'static str>> {
+        let manager = ScHandle::manager(SC_MANAGER_CONNECT)?;
+        let Some(service) = manager.service(spec, SERVICE_QUERY_STATUS)? else {
+            return Ok(None);
+        };
+        let state = match service.state()? {
+            SERVICE_RUNNING => "running",
+            SERVICE_START_PENDING => "starting",
+            SERVICE_STOP_PENDING => "stopping",
+            SERVICE_STOPPED => "stopped",
+            SERVICE_PAUSED => "paused",
+            _ => "changing state",
+        };
+        Ok(Some(state))
+    }
+
+    pub fn run_as_service(body: Body) -> Result<()> {
+        *BODY.lock().unwrap_or_else(PoisonError::into_inner) = Some(body);
+        let mut name = wide(SERVICE_NAME);
+        let table = [
+            SERVICE_TABLE_ENTRYW {
+                lpServiceName: name.as_mut_ptr(),
+                lpServiceProc: Some(service_main),
+            },
+            SERVICE_TABLE_ENTRYW {
+                lpServiceName: null_mut(),
+                lpServiceProc: None,
+            },
+        ];
+        // Returns once the service has stopped
+        if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) {
+                bail!(
+                    "`ptree service run` is started by the Service Control Manager; use `ptree service install`, \
+                     or `ptree --watch` to watch from a console"
+                );
+            }
+            return Err(err.into());
+        }
+        RESULT
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .unwrap_or(Ok(()))
+    }
+
+    unsafe extern "system" fn service_main(_argc: u32, _argv: *mut PWSTR) {
+        let name = wide(SERVICE_NAME);
+        let handle = RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), null());
+        if handle.is_null() {
+            return;
+        }
+        STATUS_HANDLE.store(handle, Ordering::SeqCst);
+        // The first scan runs inside the body; the service counts as running from the start
+        set_status(SERVICE_RUNNING, NO_ERROR, 0);
+
+        let body = BODY.lock().unwrap_or_else(PoisonError::into_inner).take();
+        let result = body.map_or(Ok(()), |body| body(&STOP));
+        let exit_code = if result.is_ok() {
+            NO_ERROR
+        } else {
+            ERROR_SERVICE_SPECIFIC_ERROR
+        };
+        *RESULT.lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+        set_status(SERVICE_STOPPED, exit_code, 0);
+    }
+
+    unsafe extern "system" fn control_handler(
+        control: u32,
+        _event_type: u32,
+        _event_data: *mut c_void,
+        _context: *mut c_void,
+    ) -> u32 {
+        match control {
+            SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                set_status(SERVICE_STOP_PENDING, NO_ERROR, STOP_WAIT_HINT_MS);
+                STOP.store(true, Ordering::SeqCst);
+                NO_ERROR
+            }
+            SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+            _ => ERROR_CALL_NOT_IMPLEMENTED,
+        }
+    }
+
+    fn set_status(state: SERVICE_STATUS_CURRENT_STATE, exit_code: u32, wait_hint: u32) {
+        let status = SERVICE_STATUS {
+            dwServiceType:             SERVICE_WIN32_OWN_PROCESS,
+            dwCurrentState:            state,
+            dwControlsAccepted:        if state == SERVICE_RUNNING {
+                SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+            } else {
+                0
+            },
+            dwWin32ExitCode:           exit_code,
+            dwServiceSpecificExitCode: (exit_code == ERROR_SERVICE_SPECIFIC_ERROR) as u32,
+            dwCheckPoint:              0,
+            dwWaitHint:                wait_hint,
+        };
+        unsafe { SetServiceStatus(STATUS_HANDLE.load(Ordering::SeqCst), &status) };
+    }
+}
+
+/// Register (or update) `ptree service` to run `ptree.exe <args>` at boot, and start it.
+#[cfg(windows)]
+pub fn install_service(args: &[String]) -> Result<()> {
+    let mut line = vec![std::env::current_exe()?.display().to_string()];
+    line.extend(args.iter().cloned());
+    scm::register_service(&WATCH_SERVICE, &command_line(&line))?;
+    scm::start_service(&WATCH_SERVICE)?;
+    Ok(())
+}
+
+/// Register the service `spec` names to run `command_line` at boot, or replace the command line
+/// of one already registered (stopping it if it runs). The service is not started.
+#[cfg(windows)]
+pub fn register_service(spec: &ServiceSpec, command_line: &str) -> Result<()> {
+    scm::register_service(spec, command_line)
+}
+
+/// Start the service; returns false if it is not installed. One already running is left as is.
+#[cfg(windows)]
+pub fn start_service(spec: &ServiceSpec) -> Result<bool> {
+    scm::start_service(spec)
+}
+
+/// Stop the service and wait until it has exited; returns false if it is not installed.
+#[cfg(windows)]
+pub fn stop_service(spec: &ServiceSpec) -> Result<bool> {
+    scm::stop_service(spec)
+}
+
+/// Stop and remove the service; returns false if it was not installed.
+#[cfg(windows)]
+pub fn uninstall_service(spec: &ServiceSpec) -> Result<bool> {
+    scm::uninstall_service(spec)
+}
+
+/// `running`, `stopped`, ... or `None` if the service is not installed.
+#[cfg(windows)]
+pub fn service_state(spec: &ServiceSpec) -> Result<Option<&'static str>> {
+    scm::service_state(spec)
+}
+
+/// Hand the process to the Service Control Manager and run `body` as `ptree service` until it
+/// returns; `body` should return soon after its flag is set by a stop or shutdown request.
+#[cfg(windows)]
+pub fn run_as_service(body: impl FnOnce(&AtomicBool) -> Result<()> + Send + 'static) -> Result<()> {
+    scm::run_as_service(Box::new(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_quotes_arguments_the_way_windows_splits_them() {
+        let args = [
+            r"C:\Program Files\ptree\ptree.exe",
+            "--cache-dir",
+            r"C:\Users\a b\AppData\Roaming\ptree\cache\",
+            "service",
+            "run",
+            r"C:\",
+            r#"D:\say "hi""#,
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            command_line(&args),
+            r#""C:\Program Files\ptree\ptree.exe" --cache-dir "C:\Users\a b\AppData\Roaming\ptree\cache\\" service run C:\ "D:\say \"hi\"""#
+        );
+        assert_eq!(command_line(&[String::new()]), r#""""#);
+    }
+}
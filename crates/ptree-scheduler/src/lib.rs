@@ -1,7 +1,116 @@
 // Platform-specific scheduler implementations are split into OS-targeted crates
 // to keep dependencies and code paths minimal per platform.
 
+mod machine_lock;
+mod run_log;
+
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::Local;
+pub use machine_lock::{machine_refresh_state, MachineRefreshLock, MachineRefreshState};
+use ptree_core::SchedulerBackend;
+#[cfg(unix)]
+use ptree_scheduler_unix as platform;
+#[cfg(unix)]
+pub use ptree_scheduler_unix::uninstall_scheduler;
+#[cfg(windows)]
+use ptree_scheduler_windows as platform;
+#[cfg(windows)]
+pub use ptree_scheduler_windows::service as windows_service;
+#[cfg(windows)]
+pub use ptree_scheduler_windows::uninstall_scheduler;
+pub use run_log::{append_run, read_runs, run_log_path, RunRecord, RunStatus, RUN_LOG_MAX_RECORDS};
+
+/// Install the refresh every 30 minutes: a scheduled task on Windows, a cron entry or systemd
+/// user timer (`backend`) on Unix.
 #[cfg(unix)]
-pub use ptree_scheduler_unix::{check_scheduler_status, install_scheduler, uninstall_scheduler};
+pub fn install_scheduler(backend: SchedulerBackend) -> Result<()> {
+    platform::install_scheduler(backend)
+}
+
 #[cfg(windows)]
-pub use ptree_scheduler_windows::{check_scheduler_status, install_scheduler, uninstall_scheduler};
+pub fn install_scheduler(backend: SchedulerBackend) -> Result<()> {
+    if backend != SchedulerBackend::Auto {
+        anyhow::bail!("--backend only applies on Unix; Windows always uses Task Scheduler");
+    }
+    platform::install_scheduler()
+}
+
+/// Show the installed cron entry, systemd timer or scheduled task, then the last and any running
+/// refresh under the same lock, then the `last` scheduled runs logged next to `cache_path`.
+pub fn check_scheduler_status(cache_path: &Path, last: usize) -> Result<()> {
+    platform::check_scheduler_status()?;
+
+    let state = machine_refresh_state();
+    println!();
+    match &state.last {
+        Some((user, finished, elapsed)) => {
+            println!(
+                "Last refresh: {} by {} ({:.1} s)",
+                finished.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+                user,
+                elapsed.as_secs_f64()
+            )
+        }
+        None => println!("Last refresh: never"),
+    }
+    if let Some((user, started)) = &state.running {
+        println!(
+            "Refresh in progress: started {} by {}",
+            started.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+            user
+        );
+    }
+
+    print_recent_runs(&read_runs(cache_path)?, last);
+    Ok(())
+}
+
+fn print_recent_runs(runs: &[RunRecord], last: usize) {
+    println!();
+    if runs.is_empty() {
+        println!("Scheduled runs: none recorded");
+        return;
+    }
+    println!("Scheduled runs (newest last):");
+    let first = if last == 0 { 0 } else { runs.len().saturating_sub(last) };
+    for run in &runs[first..] {
+        let status = match run.status {
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+            RunStatus::Skipped => "skipped",
+        };
+        let first_line = run.error.as_deref().and_then(|error| error.lines().next());
+        println!(
+            "  {}  {:<9}  {:>7.1} s{}",
+            run.started.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+            status,
+            run.duration_ms as f64 / 1000.0,
+            first_line.map(|error| format!("  {}", error)).unwrap_or_default()
+        );
+    }
+    // The last failure may be older than the runs shown
+    if let Some(failure) = runs.iter().rev().find(|run| run.status == RunStatus::Failed) {
+        println!(
+            "Last failure: {} (exit code {}): {}",
+            failure.started.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+            failure.exit_code,
+            failure.error.as_deref().unwrap_or("no message")
+        );
+    }
+}
+
+/// "another refresh in progress (started ... by ...)", logged by a scheduled run that skips.
+pub fn refresh_in_progress_message() -> String {
+    match machine_refresh_state().running {
+        Some((user, started)) => {
+            format!(
+                "another refresh in progress (started {} by {})",
+                started.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+                user
+            )
+        }
+        None => "another refresh in progress".to_string(),
+    }
+}
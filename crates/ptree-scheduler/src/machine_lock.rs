@@ -0,0 +1,307 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// Lock taken by scheduled refreshes (`--scheduled`), so only one full scan runs at a time.
+///
+/// Root's refreshes share one machine-wide lock in `/run/ptree`; a user's refreshes share one in
+/// their own runtime directory. The file is never placed where another user can create it, so
+/// nobody can plant a symlink in its place or hold the lock to block someone else's refresh.
+///
+/// The lock file also records the refresh in progress and the last one that finished:
+/// ```text
+/// running alice 2026-10-16T09:30:00Z
+/// last alice 2026-10-16T09:00:00Z 41.2
+/// ```
+/// The OS drops the lock when the process exits, so a crashed refresh never blocks the next one.
+pub struct MachineRefreshLock {
+    file:    File,
+    started: DateTime<Utc>,
+}
+
+/// State read back from the lock file.
+#[derive(Debug, Default, PartialEq)]
+pub struct MachineRefreshState {
+    /// User and start time of the refresh holding the lock right now
+    pub running: Option<(String, DateTime<Utc>)>,
+    /// User, finish time and duration of the last refresh that completed
+    pub last:    Option<(String, DateTime<Utc>, Duration)>,
+}
+
+impl MachineRefreshLock {
+    /// `/run/ptree/scheduled-refresh.lock` for root; for other users `$XDG_RUNTIME_DIR/ptree/`, or
+    /// `~/.cache/ptree/` when cron starts the refresh without a runtime directory.
+    /// `%ProgramData%\ptree\scheduled-refresh.lock` on Windows.
+    pub fn path() -> PathBuf {
+        #[cfg(windows)]
+        {
+            PathBuf::from(std::env::var("ProgramData").unwrap_or_else(|_| "C:\\ProgramData".to_string()))
+                .join("ptree")
+                .join("scheduled-refresh.lock")
+        }
+
+        #[cfg(not(windows))]
+        {
+            // SAFETY: geteuid has no preconditions and cannot fail
+            let dir = if unsafe { libc::geteuid() } == 0 {
+                PathBuf::from("/run/ptree")
+            } else if let Some(runtime) = absolute_env_dir("XDG_RUNTIME_DIR") {
+                runtime.join("ptree")
+            } else if let Some(home) = absolute_env_dir("HOME") {
+                home.join(".cache").join("ptree")
+            } else {
+                std::env::temp_dir().join(format!("ptree-{}", unsafe { libc::geteuid() }))
+            };
+            dir.join("scheduled-refresh.lock")
+        }
+    }
+
+    /// Take the lock if no other scheduled refresh holds it; `None` means one is in progress.
+    pub fn try_acquire() -> Result<Option<Self>> {
+        Self::try_acquire_at(&Self::path())
+    }
+
+    pub fn try_acquire_at(path: &Path) -> Result<Option<Self>> {
+        let mut file = open_lock_file(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        let started = Utc::now();
+        let mut state = read_state(&mut file);
+        state.running = Some((current_user(), started));
+        write_state(&mut file, &state)?;
+
+        Ok(Some(MachineRefreshLock { file, started }))
+    }
+
+    /// Record this refresh as the last machine-wide one; the lock is released on drop.
+    pub fn finish(mut self) -> Result<()> {
+        let finished = Utc::now();
+        let elapsed = (finished - self.started).to_std().unwrap_or_default();
+        let state = MachineRefreshState {
+            running: None,
+            last:    Some((current_user(), finished, elapsed)),
+        };
+        write_state(&mut self.file, &state)
+    }
+}
+
+/// Read the machine-wide refresh state without taking the lock.
+pub fn machine_refresh_state() -> MachineRefreshState {
+    machine_refresh_state_at(&MachineRefreshLock::path())
+}
+
+pub fn machine_refresh_state_at(path: &Path) -> MachineRefreshState {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    let Ok(mut file) = options.open(path) else {
+        return MachineRefreshState::default();
+    };
+
+    let mut state = read_state(&mut file);
+    // A `running` line whose lock is free was left by a refresh that died
+    if file.try_lock().is_ok() {
+        state.running = None;
+    }
+    state
+}
+
+/// Open (creating if needed) the lock file, refusing one that is a symlink, is not ours, or that
+/// other users could write to.
+fn open_lock_file(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+        options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+        let file = options
+            .open(path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+        // fstat the handle itself, so the checks hold for the file the state is written to
+        let metadata = file.metadata()?;
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let euid = unsafe { libc::geteuid() };
+        if !metadata.is_file() || metadata.uid() != euid || metadata.mode() & 0o022 != 0 || metadata.nlink() != 1 {
+            anyhow::bail!(
+                "refusing to use {}: it must be a regular file owned by the current user and writable only by them",
+                path.display()
+            );
+        }
+        Ok(file)
+    }
+
+    #[cfg(not(unix))]
+    {
+        Ok(options
+            .open(path)
+            .with_context(|| format!("could not open {}", path.display()))?)
+    }
+}
+
+/// Create the directory of the lock file, and check that only its owner (us or root) can add files to it.
+fn create_private_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        let metadata = fs::symlink_metadata(dir)?;
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let euid = unsafe { libc::geteuid() };
+        if !metadata.is_dir() || (metadata.uid() != euid && metadata.uid() != 0) || metadata.mode() & 0o022 != 0 {
+            anyhow::bail!("refusing to lock in {}: other users can create files in it", dir.display());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        Ok(fs::create_dir_all(dir)?)
+    }
+}
+
+#[cfg(not(windows))]
+fn absolute_env_dir(var_name: &str) -> Option<PathBuf> {
+    let dir = PathBuf::from(std::env::var_os(var_name)?);
+    dir.is_absolute().then_some(dir)
+}
+
+fn read_state(file: &mut File) -> MachineRefreshState {
+    let mut content = String::new();
+    if file.seek(SeekFrom::Start(0)).is_err() || file.read_to_string(&mut content).is_err() {
+        return MachineRefreshState::default();
+    }
+
+    let mut state = MachineRefreshState::default();
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["running", user, started] => {
+                if let Ok(started) = DateTime::parse_from_rfc3339(started) {
+                    state.running = Some((user.to_string(), started.with_timezone(&Utc)));
+                }
+            }
+            ["last", user, finished, seconds] => {
+                if let (Ok(finished), Ok(seconds)) = (DateTime::parse_from_rfc3339(finished), seconds.parse::<f64>()) {
+                    state.last = Some((
+                        user.to_string(),
+                        finished.with_timezone(&Utc),
+                        Duration::from_secs_f64(seconds.max(0.0)),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    state
+}
+
+/// Rewrite the state through the checked handle, keeping the previous `last` line while a refresh is running.
+fn write_state(file: &mut File, state: &MachineRefreshState) -> Result<()> {
+    let previous = read_state(file);
+    let mut content = String::new();
+    if let Some((user, started)) = &state.running {
+        content.push_str(&format!("running {} {}\n", user, started.to_rfc3339_opts(SecondsFormat::Secs, true)));
+    }
+    if let Some((user, finished, elapsed)) = state.last.as_ref().or(previous.last.as_ref()) {
+        content.push_str(&format!(
+            "last {} {} {:.1}\n",
+            user,
+            finished.to_rfc3339_opts(SecondsFormat::Secs, true),
+            elapsed.as_secs_f64()
+        ));
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .map(|user| user.split_whitespace().collect::<Vec<_>>().join("_"))
+        .filter(|user| !user.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_refresh_skips_and_last_refresh_is_recorded() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_machine_lock_{}", std::process::id()));
+        let lock_path = temp_dir.join("scheduled-refresh.lock");
+
+        let held = MachineRefreshLock::try_acquire_at(&lock_path)?.expect("lock should be free");
+        assert!(MachineRefreshLock::try_acquire_at(&lock_path)?.is_none());
+        let state = machine_refresh_state_at(&lock_path);
+        assert!(state.running.is_some());
+        assert!(state.last.is_none());
+
+        held.finish()?;
+        let state = machine_refresh_state_at(&lock_path);
+        assert!(state.running.is_none());
+        let (user, _, _) = state.last.expect("finished refresh is recorded");
+        assert_eq!(user, current_user());
+
+        // A new refresh keeps the previous one on record while it runs
+        let again = MachineRefreshLock::try_acquire_at(&lock_path)?.expect("lock is released on finish");
+        let state = machine_refresh_state_at(&lock_path);
+        assert!(state.running.is_some() && state.last.is_some());
+
+        // A refresh that dies without finishing is not reported as running
+        drop(again);
+        assert!(machine_refresh_state_at(&lock_path).running.is_none());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn planted_or_shared_lock_files_are_refused() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_machine_lock_planted_{}", std::process::id()));
+        fs::create_dir_all(&temp_dir)?;
+        let victim = temp_dir.join("victim");
+        fs::write(&victim, "keep me")?;
+
+        // A symlink in place of the lock file is not followed, so the file it points to is left alone
+        let planted = temp_dir.join("planted.lock");
+        std::os::unix::fs::symlink(&victim, &planted)?;
+        assert!(MachineRefreshLock::try_acquire_at(&planted).is_err());
+        assert_eq!(fs::read_to_string(&victim)?, "keep me");
+
+        // A lock file other users can write to is not trusted either
+        let shared = temp_dir.join("shared.lock");
+        fs::write(&shared, "")?;
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o666))?;
+        assert!(MachineRefreshLock::try_acquire_at(&shared).is_err());
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Runs kept in the log; older ones are dropped as new ones are recorded.
+pub const RUN_LOG_MAX_RECORDS: usize = 500;
+
+/// How a scheduled refresh ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+    /// Another scheduled refresh held the lock
+    Skipped,
+}
+
+/// One scheduled refresh (`scheduled-runs.log` in the cache directory, one JSON object per
+/// line), so a refresh that fails unattended still leaves a trace for `ptree scheduler status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// When the run started
+    pub started:     DateTime<Utc>,
+    pub status:      RunStatus,
+    /// Exit code of the process: 0, or 1 after an error
+    pub exit_code:   i32,
+    pub duration_ms: u64,
+    /// The error a failed run exited with, with its causes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error:       Option<String>,
+}
+
+/// `scheduled-runs.log` next to the cache file `cache_path`.
+pub fn run_log_path(cache_path: &Path) -> PathBuf {
+    cache_path.with_file_name("scheduled-runs.log")
+}
+
+/// Append `record` to the run log, dropping the oldest records once there are more than
+/// `RUN_LOG_MAX_RECORDS` (in batches, so most appends are one write).
+pub fn append_run(cache_path: &Path, record: &RunRecord) -> Result<()> {
+    let path = run_log_path(cache_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?
+        .write_all(line.as_bytes())?;
+
+    let lines = fs::read(&path)?.iter().filter(|&&byte| byte == b'\n').count();
+    if lines > RUN_LOG_MAX_RECORDS + RUN_LOG_MAX_RECORDS / 10 {
+        let records = read_runs(cache_path)?;
+        let kept = &records[records.len().saturating_sub(RUN_LOG_MAX_RECORDS)..];
+        let temp_path = path.with_extension("log.tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        for record in kept {
+            serde_json::to_writer(&mut file, record)?;
+            file.write_all(b"\n")?;
+        }
+        fs::rename(&temp_path, &path)?;
+    }
+    Ok(())
+}
+
+/// The recorded runs, oldest first; lines that do not parse are skipped.
+pub fn read_runs(cache_path: &Path) -> Result<Vec<RunRecord>> {
+    let file = match fs::File::open(run_log_path(cache_path)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(record) = serde_json::from_str(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn runs_are_read_back_oldest_first_and_trimmed() -> Result<()> {
+        let temp_dir = env::temp_dir().join(format!("ptree_test_run_log_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        let cache_path = temp_dir.join("ptree.dat");
+        assert!(read_runs(&cache_path)?.is_empty());
+
+        let failed = RunRecord {
+            started:     Utc::now(),
+            status:      RunStatus::Failed,
+            exit_code:   1,
+            duration_ms: 1200,
+            error:       Some("could not save the cache: No space left on device".to_string()),
+        };
+        append_run(&cache_path, &failed)?;
+        fs::OpenOptions::new()
+            .append(true)
+            .open(run_log_path(&cache_path))?
+            .write_all(b"not json\n")?;
+        let succeeded = RunRecord {
+            status: RunStatus::Succeeded,
+            exit_code: 0,
+            error: None,
+            ..failed.clone()
+        };
+        append_run(&cache_path, &succeeded)?;
+        assert_eq!(read_runs(&cache_path)?, vec![failed, succeeded.clone()]);
+
+        for _ in 0..RUN_LOG_MAX_RECORDS + RUN_LOG_MAX_RECORDS / 10 {
+            append_run(&cache_path, &succeeded)?;
+        }
+        let records = read_runs(&cache_path)?;
+        assert!(records.len() <= RUN_LOG_MAX_RECORDS + RUN_LOG_MAX_RECORDS / 10);
+        assert!(records.iter().all(|record| record.status == RunStatus::Succeeded));
+
+        let _ = fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,459 @@
+//! Directory enumeration for `dfs_worker`.
+//!
+//! By default this is `fs::read_dir`. With the `fast-readdir` feature on Linux, directories
+//! are read with raw `getdents64` calls into a large per-thread buffer: one syscall returns
+//! thousands of entries, entry kinds come from `d_type` without a stat, and file sizes are
+//! stat'ed relative to the open directory instead of resolving each full path.
+
+use std::ffi::OsString;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+
+/// Kind of a directory entry, as far as the listing can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Dir,
+    Symlink,
+    /// Regular files and anything else that is neither a directory nor a symlink
+    File,
+    /// The kind could not be determined
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedEntry {
+    pub name: OsString,
+    pub kind: EntryKind,
+}
+
+/// Size of a file, its owner and, when it has several hard links, its (device, inode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSize {
+    pub len:       u64,
+    /// Bytes the file takes on disk: its allocated blocks on Unix; on Windows the compressed
+    /// size of compressed and sparse files, the logical size of any other rounded up to whole
+    /// clusters
+    pub allocated: u64,
+    pub hard_link: Option<(u64, u64)>,
+    /// User ID of the owner (Unix)
+    pub owner:     Option<u32>,
+}
+
+impl From<&Metadata> for FileSize {
+    fn from(metadata: &Metadata) -> Self {
+        FileSize {
+            len:       metadata.len(),
+            allocated: allocated_size(metadata),
+            hard_link: hard_link_identity(metadata),
+            owner:     owner(metadata),
+        }
+    }
+}
+
+/// `st_blocks` counts 512-byte units whatever the filesystem's block size
+#[cfg(unix)]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.blocks() * 512
+}
+
+/// Compressed and sparse files need their path asked (`compressed_size`)
+#[cfg(not(unix))]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Bytes the file at `path` takes on disk, as `FileSize::allocated` counts them.
+pub(crate) fn size_on_disk(path: &Path, metadata: &Metadata) -> u64 {
+    #[cfg(windows)]
+    return compressed_size(path, metadata);
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        allocated_size(metadata)
+    }
+}
+
+/// Bytes a compressed or sparse file at `path` takes on disk (`GetCompressedFileSizeW`); other
+/// files take their logical size in whole clusters, and paths that cannot be asked keep
+/// `metadata`'s logical size.
+#[cfg(windows)]
+pub(crate) fn compressed_size(path: &Path, metadata: &Metadata) -> u64 {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::fs::MetadataExt;
+
+    use windows_sys::Win32::Foundation::{GetLastError, NO_ERROR};
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetCompressedFileSizeW,
+        FILE_ATTRIBUTE_COMPRESSED,
+        FILE_ATTRIBUTE_SPARSE_FILE,
+        INVALID_FILE_SIZE,
+    };
+
+    if metadata.file_attributes() & (FILE_ATTRIBUTE_COMPRESSED | FILE_ATTRIBUTE_SPARSE_FILE) == 0 {
+        return match cluster_size(path) {
+            0 => metadata.len(),
+            cluster => metadata.len().div_ceil(cluster) * cluster,
+        };
+    }
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut high = 0u32;
+    // SAFETY: `wide` is NUL-terminated and `high` outlives the call
+    let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+    // INVALID_FILE_SIZE is also a valid low half; only the last error tells them apart
+    // SAFETY: no other call in between could have replaced the thread's last error
+    if low == INVALID_FILE_SIZE && unsafe { GetLastError() } != NO_ERROR {
+        return metadata.len();
+    }
+    (high as u64) << 32 | low as u64
+}
+
+/// Bytes per cluster of the volume holding `path`, asked once per volume and thread; 0 if the
+/// volume cannot tell.
+#[cfg(windows)]
+fn cluster_size(path: &Path) -> u64 {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceW;
+
+    thread_local! {
+        static CLUSTERS: RefCell<HashMap<PathBuf, u64>> = RefCell::new(HashMap::new());
+    }
+
+    // `C:\` or `\\server\share\`: mounted folders are reparse points, which the scan never
+    // enters, so a path is on the volume it starts with
+    let volume: PathBuf = path.components().take(2).collect();
+    CLUSTERS.with(|clusters| {
+        *clusters.borrow_mut().entry(volume).or_insert_with_key(|volume| {
+            let wide: Vec<u16> = volume.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+            let (mut sectors, mut bytes, mut free, mut total) = (0u32, 0u32, 0u32, 0u32);
+            // SAFETY: `wide` is NUL-terminated and the counts outlive the call
+            let ok = unsafe { GetDiskFreeSpaceW(wide.as_ptr(), &mut sectors, &mut bytes, &mut free, &mut total) } != 0;
+            if ok {
+                sectors as u64 * bytes as u64
+            } else {
+                0
+            }
+        })
+    })
+}
+
+/// (device, inode) for files with more than one hard link.
+#[cfg(unix)]
+fn hard_link_identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+/// Link counts need an open handle on Windows; they are not tracked there.
+#[cfg(not(unix))]
+fn hard_link_identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// User ID of the owner of a file or directory (Unix).
+#[cfg(unix)]
+pub(crate) fn owner(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(metadata.uid())
+}
+
+/// Owners are security descriptors on Windows; they are not tracked there.
+#[cfg(not(unix))]
+pub(crate) fn owner(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(all(target_os = "linux", feature = "fast-readdir"))]
+pub use self::getdents::DirListing;
+#[cfg(not(all(target_os = "linux", feature = "fast-readdir")))]
+pub use self::read_dir::DirListing;
+
+#[cfg(not(all(target_os = "linux", feature = "fast-readdir")))]
+mod read_dir {
+    use std::fs;
+
+    use super::*;
+
+    /// Every entry of one directory, read with `fs::read_dir`.
+    pub struct DirListing {
+        entries: Vec<ListedEntry>,
+        sources: Vec<fs::DirEntry>,
+    }
+
+    impl DirListing {
+        pub fn read(path: &Path) -> io::Result<Self> {
+            let mut entries = Vec::new();
+            let mut sources = Vec::new();
+            for entry in fs::read_dir(path)?.flatten() {
+                let kind = match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => EntryKind::Dir,
+                    Ok(file_type) if file_type.is_symlink() => EntryKind::Symlink,
+                    Ok(_) => EntryKind::File,
+                    Err(_) => EntryKind::Unknown,
+                };
+                entries.push(ListedEntry {
+                    name: entry.file_name(),
+                    kind,
+                });
+                sources.push(entry);
+            }
+            Ok(DirListing { entries, sources })
+        }
+
+        pub fn entries(&self) -> &[ListedEntry] {
+            &self.entries
+        }
+
+        /// Size of the `index`th entry (not following symlinks).
+        pub fn file_size(&self, index: usize) -> Option<FileSize> {
+            let source = &self.sources[index];
+            let metadata = source.metadata().ok()?;
+            #[cfg(windows)]
+            return Some(FileSize {
+                allocated: compressed_size(&source.path(), &metadata),
+                ..FileSize::from(&metadata)
+            });
+            #[cfg(not(windows))]
+            Some(FileSize::from(&metadata))
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "fast-readdir"))]
+pub(crate) mod getdents {
+    use std::cell::RefCell;
+    use std::ffi::{CStr, CString};
+    use std::mem::MaybeUninit;
+    #[cfg(feature = "io-uring")]
+    use std::os::fd::{AsFd, BorrowedFd};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    use super::*;
+
+    /// Bytes of `linux_dirent64` records fetched per syscall.
+    const BUFFER_SIZE: usize = 256 * 1024;
+
+    thread_local! {
+        static BUFFER: RefCell<Vec<u8>> = RefCell::new(vec![0; BUFFER_SIZE]);
+    }
+
+    /// Flags every directory is opened with.
+    pub(crate) const OPEN_FLAGS: libc::c_int = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+
+    /// Every entry of one directory, read with `getdents64`; the directory stays open for
+    /// `file_size`.
+    pub struct DirListing {
+        entries: Vec<ListedEntry>,
+        dir:     OwnedFd,
+        /// File sizes fetched ahead of time (`--engine uring`), by entry index
+        sizes:   Option<Vec<Option<FileSize>>>,
+    }
+
+    impl DirListing {
+        pub fn read(path: &Path) -> io::Result<Self> {
+            let c_path = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: `c_path` is NUL-terminated; the returned descriptor is owned below
+            let fd = unsafe { libc::open(c_path.as_ptr(), OPEN_FLAGS) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` was just opened and nothing else owns it
+            Self::from_dir(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+
+        /// List a directory that is already open.
+        pub(crate) fn from_dir(dir: OwnedFd) -> io::Result<Self> {
+            let mut entries = Vec::new();
+            BUFFER.with(|buffer| -> io::Result<()> {
+                let mut buffer = buffer.borrow_mut();
+                loop {
+                    // SAFETY: the kernel writes at most `buffer.len()` bytes into `buffer`
+                    let read = unsafe {
+                        libc::syscall(libc::SYS_getdents64, dir.as_raw_fd(), buffer.as_mut_ptr(), buffer.len())
+                    };
+                    if read < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    if read == 0 {
+                        return Ok(());
+                    }
+                    parse_records(&buffer[..read as usize], |name, d_type| {
+                        let kind = match d_type {
+                            libc::DT_DIR => EntryKind::Dir,
+                            libc::DT_LNK => EntryKind::Symlink,
+                            // Some filesystems leave the type out; ask for it
+                            libc::DT_UNKNOWN => stat_at(&dir, name).map_or(EntryKind::Unknown, |stat| kind_of(&stat)),
+                            _ => EntryKind::File,
+                        };
+                        entries.push(ListedEntry {
+                            name: OsString::from_vec(name.to_bytes().to_vec()),
+                            kind,
+                        });
+                    });
+                }
+            })?;
+            Ok(DirListing {
+                entries,
+                dir,
+                sizes: None,
+            })
+        }
+
+        #[cfg(feature = "io-uring")]
+        pub(crate) fn dir(&self) -> BorrowedFd<'_> {
+            self.dir.as_fd()
+        }
+
+        /// Answer `file_size` from `sizes` (one per entry) instead of stat'ing.
+        #[cfg(feature = "io-uring")]
+        pub(crate) fn set_sizes(&mut self, sizes: Vec<Option<FileSize>>) {
+            self.sizes = Some(sizes);
+        }
+
+        pub fn entries(&self) -> &[ListedEntry] {
+            &self.entries
+        }
+
+        /// Size of the `index`th entry (not following symlinks).
+        pub fn file_size(&self, index: usize) -> Option<FileSize> {
+            if let Some(sizes) = &self.sizes {
+                return sizes[index];
+            }
+            let name = CString::new(self.entries[index].name.as_bytes()).ok()?;
+            let stat = stat_at(&self.dir, &name).ok()?;
+            Some(FileSize {
+                len:       stat.st_size as u64,
+                allocated: stat.st_blocks as u64 * 512,
+                hard_link: (stat.st_nlink > 1).then_some((stat.st_dev, stat.st_ino)),
+                owner:     Some(stat.st_uid),
+            })
+        }
+    }
+
+    /// Call `visit` with the name and `d_type` of every `linux_dirent64` record in `bytes`,
+    /// except `.` and `..`.
+    fn parse_records(bytes: &[u8], mut visit: impl FnMut(&CStr, u8)) {
+        // d_ino (8), d_off (8), d_reclen (2), d_type (1), then the NUL-terminated name
+        const NAME_OFFSET: usize = 19;
+        let mut offset = 0;
+        while offset + NAME_OFFSET <= bytes.len() {
+            let record_length = u16::from_ne_bytes([bytes[offset + 16], bytes[offset + 17]]) as usize;
+            if record_length == 0 || offset + record_length > bytes.len() {
+                break;
+            }
+            let d_type = bytes[offset + 18];
+            if let Ok(name) = CStr::from_bytes_until_nul(&bytes[offset + NAME_OFFSET..offset + record_length]) {
+                if !matches!(name.to_bytes(), b"." | b"..") {
+                    visit(name, d_type);
+                }
+            }
+            offset += record_length;
+        }
+    }
+
+    fn stat_at(dir: &OwnedFd, name: &CStr) -> io::Result<libc::stat> {
+        let mut stat = MaybeUninit::<libc::stat>::uninit();
+        // SAFETY: `name` is NUL-terminated and `stat` is written in full on success
+        let result =
+            unsafe { libc::fstatat(dir.as_raw_fd(), name.as_ptr(), stat.as_mut_ptr(), libc::AT_SYMLINK_NOFOLLOW) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fstatat succeeded
+        Ok(unsafe { stat.assume_init() })
+    }
+
+    fn kind_of(stat: &libc::stat) -> EntryKind {
+        match stat.st_mode & libc::S_IFMT {
+            libc::S_IFDIR => EntryKind::Dir,
+            libc::S_IFLNK => EntryKind::Symlink,
+            _ => EntryKind::File,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    /// Whichever backend is built must list what `fs::read_dir` lists.
+    #[test]
+    fn listing_matches_read_dir() {
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_dir_listing_{unique}"));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("file.txt"), vec![0u8; 1234]).unwrap();
+        // Enough entries to need several getdents64 calls with a smaller buffer
+        for i in 0..300 {
+            fs::write(root.join(format!("entry-with-a-long-name-{i:04}")), b"x").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("sub"), root.join("link")).unwrap();
+
+        let listing = DirListing::read(&root).unwrap();
+        let mut listed: Vec<(OsString, EntryKind)> = listing
+            .entries()
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.kind))
+            .collect();
+        listed.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut expected: Vec<(OsString, EntryKind)> = fs::read_dir(&root)
+            .unwrap()
+            .flatten()
+            .map(|entry| {
+                let file_type = entry.file_type().unwrap();
+                let kind = if file_type.is_dir() {
+                    EntryKind::Dir
+                } else if file_type.is_symlink() {
+                    EntryKind::Symlink
+                } else {
+                    EntryKind::File
+                };
+                (entry.file_name(), kind)
+            })
+            .collect();
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(listed, expected);
+
+        let index = listing
+            .entries()
+            .iter()
+            .position(|entry| entry.name == "file.txt")
+            .unwrap();
+        assert_eq!(listing.file_size(index).map(|size| size.len), Some(1234));
+        assert!(DirListing::read(&root.join("file.txt")).is_err());
+
+        // A file extended without writing takes (next to) no blocks
+        #[cfg(unix)]
+        {
+            fs::File::create(root.join("sparse.img"))
+                .unwrap()
+                .set_len(64 << 20)
+                .unwrap();
+            let listing = DirListing::read(&root).unwrap();
+            let index = listing
+                .entries()
+                .iter()
+                .position(|entry| entry.name == "sparse.img")
+                .unwrap();
+            let size = listing.file_size(index).unwrap();
+            assert_eq!(size.len, 64 << 20);
+            assert!(size.allocated < 1 << 20, "{} bytes allocated", size.allocated);
+        }
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
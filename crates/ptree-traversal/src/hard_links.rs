@@ -0,0 +1,262 @@
+//! Hard-link awareness of directory sizes: a file with several links adds its size to the
+//! totals once, in the directory of its link that sorts first by path, unless `--count-links`
+//! asks for every link to count (as `du -l` does).
+//!
+//! Workers meet the links in no fixed order, so a scan counts each file at the first link it
+//! meets and `reattribute` moves the size to the owning link's directory once every link was
+//! seen. The live check of a snapshot walks twice instead when it met hard links: the first
+//! walk finds the owning links (`settle`), the second counts only those.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use ptree_cache::DirEntry;
+
+use crate::dir_listing::FileSize;
+
+/// The links met so far of one multiply-linked file
+#[derive(Debug)]
+struct Link {
+    /// The link whose directory counted the file as the scan went
+    counted:   PathBuf,
+    /// The link that sorts first by path: the one whose directory the file counts in
+    owner:     PathBuf,
+    len:       u64,
+    allocated: u64,
+}
+
+/// The (device, inode) of every multiply-linked file met so far, shared by the workers (and by
+/// the package store accounting, which counts each file once for its store's unique bytes).
+#[derive(Debug, Default)]
+pub struct HardLinks {
+    /// Every link counts toward directory totals
+    count_links: bool,
+    links:       DashMap<(u64, u64), Link>,
+    /// Set by `settle`: every link was met, and only the owning ones count
+    settled:     AtomicBool,
+}
+
+impl HardLinks {
+    pub fn new(count_links: bool) -> Self {
+        HardLinks {
+            count_links,
+            ..Self::default()
+        }
+    }
+
+    /// What the file at `path` adds to directory totals (nothing for a link that does not count
+    /// the file), with `hard_link` set to its identity, and whether it is the first link to
+    /// the file met.
+    pub fn account(&self, path: &Path, file: FileSize) -> (FileSize, bool) {
+        // Windows listings carry no link count; opening every file for it is what
+        // `--detect-links` asks for, and that is what `count_links` is off for there
+        let identity = file
+            .hard_link
+            .or_else(|| (!self.count_links).then(|| file_index(path)).flatten());
+        let Some(identity) = identity else {
+            return (file, true);
+        };
+
+        let (first, owned) = match self.links.entry(identity) {
+            Entry::Vacant(slot) => {
+                slot.insert(Link {
+                    counted:   path.to_path_buf(),
+                    owner:     path.to_path_buf(),
+                    len:       file.len,
+                    allocated: file.allocated,
+                });
+                (true, true)
+            }
+            Entry::Occupied(mut link) => {
+                let link = link.get_mut();
+                if !self.settled.load(Ordering::Relaxed) && path < link.owner.as_path() {
+                    link.owner = path.to_path_buf();
+                }
+                (false, link.owner == path)
+            }
+        };
+        let counts = if self.settled.load(Ordering::Relaxed) {
+            owned
+        } else {
+            first
+        };
+
+        let file = FileSize {
+            hard_link: Some(identity),
+            ..file
+        };
+        if counts || self.count_links {
+            (file, first)
+        } else {
+            (
+                FileSize {
+                    len: 0,
+                    allocated: 0,
+                    ..file
+                },
+                first,
+            )
+        }
+    }
+
+    /// Count each file only at its owning link from now on, every link having been met. True
+    /// when that changes anything: some file had several links, and they count once.
+    pub fn settle(&self) -> bool {
+        self.settled.store(true, Ordering::Relaxed);
+        !self.count_links && !self.links.is_empty()
+    }
+
+    /// Move the size of every file counted at a link other than its owner to the owner's
+    /// directory, in the direct totals of `entries` (before they are summed up). A file with a
+    /// link outside `entries` (one spilled to disk) stays where it was counted.
+    pub fn reattribute(&self, entries: &mut HashMap<PathBuf, DirEntry>) {
+        if self.count_links {
+            return;
+        }
+        for link in self.links.iter() {
+            let (Some(from), Some(to)) = (link.counted.parent(), link.owner.parent()) else {
+                continue;
+            };
+            if from == to || !entries.contains_key(from) || !entries.contains_key(to) {
+                continue;
+            }
+            if let Some(entry) = entries.get_mut(from) {
+                entry.total_size = entry.total_size.saturating_sub(link.len);
+                entry.allocated_size = entry.allocated_size.saturating_sub(link.allocated);
+            }
+            if let Some(entry) = entries.get_mut(to) {
+                entry.total_size += link.len;
+                entry.allocated_size += link.allocated;
+            }
+        }
+    }
+}
+
+/// Windows listings carry no link count; a handle on the file has it, with the volume serial
+/// number and file index that identify the file.
+#[cfg(windows)]
+fn file_index(path: &Path) -> Option<(u64, u64)> {
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW,
+        GetFileInformationByHandle,
+        BY_HANDLE_FILE_INFORMATION,
+        FILE_READ_ATTRIBUTES,
+        FILE_SHARE_DELETE,
+        FILE_SHARE_READ,
+        FILE_SHARE_WRITE,
+        OPEN_EXISTING,
+    };
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    // SAFETY: `wide` is NUL-terminated; no security attributes or template are passed
+    let handle = unsafe {
+        CreateFileW(
+            wide.as_ptr(),
+            FILE_READ_ATTRIBUTES,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    // SAFETY: the struct is plain integers, for which zero is valid
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    // SAFETY: `handle` is open and `info` outlives the call
+    let ok = unsafe { GetFileInformationByHandle(handle, &mut info) } != 0;
+    // SAFETY: `handle` came from CreateFileW and is closed once
+    unsafe { CloseHandle(handle) };
+    (ok && info.nNumberOfLinks > 1)
+        .then(|| (info.dwVolumeSerialNumber as u64, (info.nFileIndexHigh as u64) << 32 | info.nFileIndexLow as u64))
+}
+
+/// Unix listings already carry the (device, inode) of multiply-linked files.
+#[cfg(not(windows))]
+fn file_index(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn hard_linked_files_count_once_unless_links_are_counted() {
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_hard_links_{unique}"));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a"), vec![0u8; 100]).unwrap();
+        fs::hard_link(root.join("a"), root.join("b")).unwrap();
+        fs::write(root.join("c"), vec![0u8; 10]).unwrap();
+
+        let sizes = |links: &HardLinks| -> u64 {
+            ["a", "b", "c"]
+                .iter()
+                .map(|name| {
+                    let path = root.join(name);
+                    links
+                        .account(&path, FileSize::from(&fs::metadata(&path).unwrap()))
+                        .0
+                        .len
+                })
+                .sum()
+        };
+        assert_eq!(sizes(&HardLinks::new(false)), 110);
+        assert_eq!(sizes(&HardLinks::new(true)), 210);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hard_linked_files_count_at_the_link_that_sorts_first() {
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_hard_link_owner_{unique}"));
+        for dir in ["a", "b"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("a/file"), vec![0u8; 100]).unwrap();
+        fs::hard_link(root.join("a/file"), root.join("b/file")).unwrap();
+        let file = FileSize::from(&fs::metadata(root.join("a/file")).unwrap());
+
+        // Met at b/ first: counted there during the scan, moved to a/ afterwards
+        let links = HardLinks::new(false);
+        assert_eq!(links.account(&root.join("b/file"), file).0.len, 100);
+        assert_eq!(links.account(&root.join("a/file"), file).0.len, 0);
+        let mut entries: HashMap<PathBuf, DirEntry> = ["a", "b"]
+            .into_iter()
+            .map(|dir| {
+                let path = root.join(dir);
+                let total_size = if dir == "b" { 100 } else { 0 };
+                let entry = DirEntry {
+                    allocated_size: 0,
+                    ..ptree_cache::testing::dir(&path, total_size, &["file"])
+                };
+                (path, entry)
+            })
+            .collect();
+        links.reattribute(&mut entries);
+        assert_eq!(entries[&root.join("a")].total_size, 100);
+        assert_eq!(entries[&root.join("b")].total_size, 0);
+
+        // Once settled, only the owning link counts, whichever is met first
+        assert!(links.settle());
+        assert_eq!(links.account(&root.join("b/file"), file).0.len, 0);
+        assert_eq!(links.account(&root.join("a/file"), file).0.len, 100);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use parking_lot::RwLock;
+
+/// Ignore files honored in `--gitignore` mode, in the order they are layered.
+///
+/// `.ptreeignore` is loaded after `.gitignore` so project-level ptree rules can
+/// re-include (`!pattern`) paths that git ignores.
+pub const IGNORE_FILE_NAMES: [&str; 2] = [".gitignore", ".ptreeignore"];
+
+/// Hierarchical `.gitignore` / `.ptreeignore` matcher shared across worker threads.
+///
+/// Each directory's ignore files are loaded once, when that directory is enumerated.
+/// Because the DFS only queues a child after its parent has been enumerated, every
+/// ancestor's rules are already loaded by the time a path is checked.
+#[derive(Debug)]
+pub struct IgnoreRules {
+    scan_roots: Vec<PathBuf>,
+    matchers:   RwLock<HashMap<PathBuf, Option<Arc<Gitignore>>>>,
+}
+
+impl IgnoreRules {
+    pub fn new(scan_roots: &[PathBuf]) -> Self {
+        IgnoreRules {
+            scan_roots: scan_roots.to_vec(),
+            matchers:   RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Load ignore files located directly in `dir` (no-op if already loaded).
+    pub fn load_dir(&self, dir: &Path) {
+        if self.matchers.read().contains_key(dir) {
+            return;
+        }
+
+        let matcher = Self::build_matcher(dir).map(Arc::new);
+        self.matchers.write().insert(dir.to_path_buf(), matcher);
+    }
+
+    fn build_matcher(dir: &Path) -> Option<Gitignore> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+
+        for file_name in IGNORE_FILE_NAMES {
+            let ignore_file = dir.join(file_name);
+            if ignore_file.is_file() {
+                // Malformed lines are skipped by the builder; a partial rule set is still useful.
+                let _ = builder.add(ignore_file);
+                found = true;
+            }
+        }
+
+        if !found {
+            return None;
+        }
+
+        builder.build().ok().filter(|matcher| !matcher.is_empty())
+    }
+
+    /// True if `path` is ignored by the nearest ignore file that has an opinion on it.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        // Rules never leak across roots: stop at the root this path was found under
+        let Some(scan_root) = self.scan_roots.iter().find(|root| path.starts_with(root)) else {
+            return false;
+        };
+
+        let matchers = self.matchers.read();
+        let mut current = path.parent();
+
+        while let Some(dir) = current {
+            if !dir.starts_with(scan_root) {
+                break;
+            }
+
+            if let Some(Some(matcher)) = matchers.get(dir) {
+                match matcher.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+
+            if dir == scan_root {
+                break;
+            }
+            current = dir.parent();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn nested_ignore_files_layer_over_parents() {
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_ignore_rules_{unique}"));
+        let project = root.join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(project.join(".ptreeignore"), "!keep.log\nnode_modules\n").unwrap();
+
+        let rules = IgnoreRules::new(std::slice::from_ref(&root));
+        rules.load_dir(&root);
+        rules.load_dir(&project);
+
+        assert!(rules.is_ignored(&root.join("target"), true));
+        assert!(!rules.is_ignored(&root.join("target"), false));
+        assert!(rules.is_ignored(&project.join("debug.log"), false));
+        assert!(!rules.is_ignored(&project.join("keep.log"), false));
+        assert!(rules.is_ignored(&project.join("node_modules"), true));
+        assert!(!rules.is_ignored(&root.join("node_modules"), true));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
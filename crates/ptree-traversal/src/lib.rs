@@ -1,3 +1,33 @@
+pub mod ads;
+pub mod dir_listing;
+pub mod hard_links;
+pub mod ignore_rules;
+#[cfg(feature = "mft")]
+pub mod mft;
+pub mod root_handles;
+pub mod scan_handle;
+pub mod skip_rules;
+pub mod store_accounting;
+pub mod subvolumes;
 pub mod traversal;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
+pub mod warnings;
+pub mod work_queue;
+pub mod xattrs;
 
-pub use traversal::{traverse_disk, traverse_disk_incremental, DebugInfo, TraversalState};
+pub use hard_links::HardLinks;
+pub use ignore_rules::IgnoreRules;
+pub use scan_handle::{ScanControl, ScanHandle, ScanOutcome, ScanProgress};
+pub use skip_rules::SkipRules;
+pub use store_accounting::StoreAccounting;
+pub use traversal::{
+    resolve_scan_roots,
+    traverse_disk,
+    traverse_disk_incremental,
+    traverse_disk_with_control,
+    DebugInfo,
+    TraversalState,
+};
+pub use warnings::Warnings;
+pub use work_queue::{WorkQueue, WorkerQueue};
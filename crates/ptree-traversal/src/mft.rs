@@ -0,0 +1,765 @@
+//! `--mft`: build the snapshot from the NTFS Master File Table instead of listing every
+//! directory.
+//!
+//! The whole MFT is read sequentially from the raw volume (which needs an elevated
+//! process), each file record is parsed for its names, parent directories, size and
+//! timestamps, and the directory tree is rebuilt from the parent references. Parsing is
+//! plain byte work and builds everywhere; only opening and reading the volume is
+//! Windows-specific.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use ptree_cache::{DirEntry, Name};
+
+use crate::skip_rules::SkipRules;
+use crate::traversal::TraversalState;
+
+/// Record number of the volume's root directory.
+const ROOT_RECORD: u64 = 5;
+/// Records below this are NTFS metadata files (`$MFT`, `$Bitmap`, ...), never listed.
+const FIRST_USER_RECORD: u64 = 24;
+
+const ATTR_STANDARD_INFORMATION: u32 = 0x10;
+const ATTR_FILE_NAME: u32 = 0x30;
+const ATTR_DATA: u32 = 0x80;
+const ATTR_END: u32 = 0xFFFF_FFFF;
+
+/// Attribute header flags of a compressed or sparse stream
+const ATTR_FLAG_COMPRESSED: u16 = 0x0001;
+const ATTR_FLAG_SPARSE: u16 = 0x8000;
+
+const RECORD_IN_USE: u16 = 0x01;
+const RECORD_IS_DIRECTORY: u16 = 0x02;
+
+const FILE_ATTRIBUTE_HIDDEN: u32 = 0x02;
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+/// `$FILE_NAME` namespace of 8.3 short names, which duplicate a long name.
+const NAMESPACE_DOS: u8 = 2;
+
+/// Layout of an NTFS volume, from its boot sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeLayout {
+    pub bytes_per_sector:  u64,
+    pub bytes_per_cluster: u64,
+    pub record_size:       u64,
+    /// First cluster of the MFT
+    pub mft_cluster:       u64,
+}
+
+impl VolumeLayout {
+    /// Read the layout from the first sector of the volume; fails for non-NTFS volumes.
+    pub fn from_boot_sector(boot: &[u8]) -> Result<Self> {
+        if boot.len() < 512 || &boot[3..11] != b"NTFS    " {
+            bail!("not an NTFS volume");
+        }
+        let bytes_per_sector = u16_at(boot, 0x0B) as u64;
+        let bytes_per_cluster = bytes_per_sector * boot[0x0D] as u64;
+        // Positive: clusters per record; negative: the record is 2^-n bytes
+        let clusters_per_record = boot[0x40] as i8;
+        let record_size = if clusters_per_record > 0 {
+            clusters_per_record as u64 * bytes_per_cluster
+        } else {
+            1 << (-clusters_per_record as u32)
+        };
+        if bytes_per_sector == 0 || bytes_per_cluster == 0 || record_size < bytes_per_sector {
+            bail!("unexpected NTFS boot sector");
+        }
+
+        Ok(VolumeLayout {
+            bytes_per_sector,
+            bytes_per_cluster,
+            record_size,
+            mft_cluster: u64_at(boot, 0x30),
+        })
+    }
+}
+
+/// What one file record (or an extension record of it) says about its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MftRecord {
+    /// Number of the base record; extension records carry the one they extend
+    pub number:     u64,
+    pub is_dir:     bool,
+    /// (parent record number, name) for every hard link, short 8.3 names left out
+    pub links:      Vec<(u64, String)>,
+    /// Size of the unnamed data stream
+    pub size:       u64,
+    /// Clusters allocated to it, in bytes (compressed size for compressed and sparse streams;
+    /// 0 for data resident in the record)
+    pub allocated:  u64,
+    pub modified:   Option<DateTime<Utc>>,
+    pub attributes: u32,
+}
+
+/// Parse the file record `number`, in place (the update sequence fixups are applied to
+/// `record`). `None` for free, torn, or non-file records.
+pub fn parse_record(record: &mut [u8], number: u64, bytes_per_sector: usize) -> Option<MftRecord> {
+    if record.len() < 0x30 || &record[0..4] != b"FILE" || !apply_fixups(record, bytes_per_sector) {
+        return None;
+    }
+    let flags = u16_at(record, 0x16);
+    if flags & RECORD_IN_USE == 0 {
+        return None;
+    }
+    let base = u64_at(record, 0x20) & 0xFFFF_FFFF_FFFF;
+
+    let mut parsed = MftRecord {
+        number:     if base != 0 { base } else { number },
+        is_dir:     flags & RECORD_IS_DIRECTORY != 0,
+        links:      Vec::new(),
+        size:       0,
+        allocated:  0,
+        modified:   None,
+        attributes: 0,
+    };
+    let mut dos_links = Vec::new();
+
+    let mut offset = u16_at(record, 0x14) as usize;
+    while offset + 0x18 <= record.len() {
+        let kind = u32_at(record, offset);
+        let length = u32_at(record, offset + 4) as usize;
+        if kind == ATTR_END || length == 0 || offset + length > record.len() {
+            break;
+        }
+        let attribute = &record[offset..offset + length];
+        let non_resident = attribute[8] != 0;
+        let named = attribute[9] != 0;
+
+        match (kind, non_resident) {
+            (ATTR_STANDARD_INFORMATION, false) => {
+                if let Some(value) = resident_value(attribute).filter(|value| value.len() >= 0x24) {
+                    parsed.modified = filetime_to_utc(u64_at(value, 0x08));
+                    parsed.attributes = u32_at(value, 0x20);
+                }
+            }
+            (ATTR_FILE_NAME, false) => {
+                if let Some(value) = resident_value(attribute).filter(|value| value.len() >= 0x42) {
+                    let parent = u64_at(value, 0) & 0xFFFF_FFFF_FFFF;
+                    let name_length = value[0x40] as usize;
+                    let namespace = value[0x41];
+                    if let Some(name) = value.get(0x42..0x42 + name_length * 2) {
+                        let units: Vec<u16> = name.as_chunks::<2>().0.iter().map(|c| u16::from_le_bytes(*c)).collect();
+                        let link = (parent, String::from_utf16_lossy(&units));
+                        if namespace == NAMESPACE_DOS {
+                            dos_links.push(link);
+                        } else {
+                            parsed.links.push(link);
+                        }
+                    }
+                }
+            }
+            (ATTR_DATA, false) if !named => {
+                parsed.size = u32_at(attribute, 0x10) as u64;
+            }
+            // Only the first extent of a non-resident stream carries its size
+            (ATTR_DATA, true) if !named && attribute.len() >= 0x38 && u64_at(attribute, 0x10) == 0 => {
+                parsed.size = u64_at(attribute, 0x30);
+                // Compressed and sparse streams have a longer header ending in the clusters in use
+                let packed = u16_at(attribute, 0x0C) & (ATTR_FLAG_COMPRESSED | ATTR_FLAG_SPARSE) != 0;
+                parsed.allocated = if packed && attribute.len() >= 0x48 {
+                    u64_at(attribute, 0x40)
+                } else {
+                    u64_at(attribute, 0x28)
+                };
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+
+    // A name that fits 8.3 is stored once, in the DOS namespace
+    if parsed.links.is_empty() {
+        parsed.links = dos_links;
+    }
+    Some(parsed)
+}
+
+/// Undo the update sequence protection: the last two bytes of every sector hold a check
+/// value and the real bytes are kept in the update sequence array. `false` if a sector
+/// does not match (a torn write).
+fn apply_fixups(record: &mut [u8], bytes_per_sector: usize) -> bool {
+    let usa_offset = u16_at(record, 0x04) as usize;
+    let usa_count = u16_at(record, 0x06) as usize;
+    if usa_count == 0 || usa_offset + usa_count * 2 > record.len() || bytes_per_sector < 2 {
+        return false;
+    }
+    let check = [record[usa_offset], record[usa_offset + 1]];
+
+    for sector in 1..usa_count {
+        let end = sector * bytes_per_sector;
+        if end > record.len() {
+            break;
+        }
+        if record[end - 2..end] != check {
+            return false;
+        }
+        let original = usa_offset + sector * 2;
+        record[end - 2] = record[original];
+        record[end - 1] = record[original + 1];
+    }
+    true
+}
+
+/// Value of a resident attribute.
+fn resident_value(attribute: &[u8]) -> Option<&[u8]> {
+    let length = u32_at(attribute, 0x10) as usize;
+    let offset = u16_at(attribute, 0x14) as usize;
+    attribute.get(offset..offset + length)
+}
+
+/// Clusters of a non-resident attribute as (first cluster, cluster count); sparse runs
+/// have no clusters and are left out.
+pub fn data_runs(runs: &[u8]) -> Vec<(u64, u64)> {
+    let mut result = Vec::new();
+    let mut cluster: i64 = 0;
+    let mut at = 0;
+    while let Some(&header) = runs.get(at) {
+        if header == 0 {
+            break;
+        }
+        let length_size = (header & 0x0F) as usize;
+        let offset_size = (header >> 4) as usize;
+        if length_size > 8 || offset_size > 8 {
+            break;
+        }
+        let Some(fields) = runs.get(at + 1..at + 1 + length_size + offset_size) else {
+            break;
+        };
+        let length = le_unsigned(&fields[..length_size]);
+        if offset_size > 0 {
+            cluster += le_signed(&fields[length_size..]);
+            result.push((cluster as u64, length));
+        }
+        at += 1 + length_size + offset_size;
+    }
+    result
+}
+
+/// Runs of the `$MFT` file's own data stream, from its first record.
+pub fn mft_runs(record: &mut [u8], bytes_per_sector: usize) -> Result<Vec<(u64, u64)>> {
+    if &record[0..4] != b"FILE" || !apply_fixups(record, bytes_per_sector) {
+        bail!("the first MFT record is damaged");
+    }
+    let mut offset = u16_at(record, 0x14) as usize;
+    while offset + 0x18 <= record.len() {
+        let kind = u32_at(record, offset);
+        let length = u32_at(record, offset + 4) as usize;
+        if kind == ATTR_END || length == 0 || offset + length > record.len() {
+            break;
+        }
+        let attribute = &record[offset..offset + length];
+        if kind == ATTR_DATA && attribute[8] != 0 && attribute[9] == 0 {
+            let runs_offset = u16_at(attribute, 0x20) as usize;
+            return Ok(data_runs(&attribute[runs_offset.min(length)..]));
+        }
+        offset += length;
+    }
+    bail!("the first MFT record has no data runs")
+}
+
+/// The directory tree the parsed records describe.
+#[derive(Debug, Default)]
+pub struct MftIndex {
+    records:  HashMap<u64, MftRecord>,
+    children: HashMap<u64, Vec<(u64, String)>>,
+}
+
+impl MftIndex {
+    /// Merge extension records into their base records and link every name to its parent.
+    pub fn new(records: impl IntoIterator<Item = MftRecord>) -> Self {
+        let mut merged: HashMap<u64, MftRecord> = HashMap::new();
+        for record in records {
+            match merged.get_mut(&record.number) {
+                Some(base) => {
+                    base.links.extend(record.links);
+                    base.size = base.size.max(record.size);
+                    base.allocated = base.allocated.max(record.allocated);
+                    base.is_dir |= record.is_dir;
+                    if record.modified.is_some() {
+                        base.modified = record.modified;
+                        base.attributes = record.attributes;
+                    }
+                }
+                None => {
+                    merged.insert(record.number, record);
+                }
+            }
+        }
+
+        let mut children: HashMap<u64, Vec<(u64, String)>> = HashMap::new();
+        for record in merged.values() {
+            if record.number < FIRST_USER_RECORD {
+                continue;
+            }
+            for (parent, name) in &record.links {
+                children.entry(*parent).or_default().push((record.number, name.clone()));
+            }
+        }
+
+        MftIndex {
+            records: merged,
+            children,
+        }
+    }
+
+    /// Record of the directory `path` on this volume (names compare case-insensitively,
+    /// like NTFS does).
+    pub fn find(&self, path: &Path) -> Option<u64> {
+        let mut current = ROOT_RECORD;
+        for component in path.components() {
+            let Component::Normal(name) = component else {
+                continue;
+            };
+            let name = name.to_string_lossy();
+            current = self
+                .children
+                .get(&current)?
+                .iter()
+                .find(|(_, child)| child.to_lowercase() == name.to_lowercase())
+                .map(|(number, _)| *number)?;
+        }
+        Some(current)
+    }
+
+    /// Entries for `root` (record `number`) and every directory below it, as the directory
+    /// walk would produce them with the scan's skip rules.
+    pub fn entries(
+        &self,
+        skip_rules: &SkipRules,
+        scan: u64,
+        root: &Path,
+        number: u64,
+        skip_stats: &mut HashMap<String, usize>,
+    ) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        let mut pending = vec![(root.to_path_buf(), number)];
+
+        while let Some((path, number)) = pending.pop() {
+            let Some(record) = self.records.get(&number) else {
+                continue;
+            };
+            skip_rules.enter_dir(&path);
+
+            let mut children = Vec::new();
+            let mut file_count = 0usize;
+            let mut total_size = 0u64;
+            let mut allocated_size = 0u64;
+            for (child, name) in self.children.get(&number).into_iter().flatten() {
+                let Some(child_record) = self.records.get(child) else {
+                    continue;
+                };
+                let child_path = path.join(name);
+                // Junctions and directory symlinks are not followed, like the walk does
+                let is_dir = child_record.is_dir && child_record.attributes & FILE_ATTRIBUTE_REPARSE_POINT == 0;
+
+                if skip_rules.should_skip(name, &child_path, is_dir) {
+                    *skip_stats.entry(name.clone()).or_insert(0) += 1;
+                    continue;
+                }
+                if is_dir {
+                    if let Some(marker) = skip_rules.marker(&child_path) {
+                        *skip_stats.entry(marker.to_string()).or_insert(0) += 1;
+                        continue;
+                    }
+                }
+
+                children.push(Name::new(name));
+                if is_dir {
+                    pending.push((child_path, *child));
+                } else {
+                    file_count += 1;
+                    if !child_record.is_dir {
+                        total_size += child_record.size;
+                        allocated_size += child_record.allocated;
+                    }
+                }
+            }
+
+            entries.push(DirEntry {
+                name: path
+                    .file_name()
+                    .and_then(|n| n.to_str().map(Name::new))
+                    .unwrap_or_default(),
+                modified: record.modified.unwrap_or(DateTime::UNIX_EPOCH),
+                content_hash: 0,
+                file_count,
+                total_size,
+                allocated_size,
+                children,
+                is_hidden: record.attributes & FILE_ATTRIBUTE_HIDDEN != 0,
+                is_dir: true,
+                id: 0,
+                file_id: 0,
+                scan,
+                path,
+            });
+        }
+        entries
+    }
+}
+
+/// Read every scan root from its volume's MFT into the shared cache. Nothing is added
+/// unless all roots could be read, so a failure leaves the directory walk a clean start.
+pub(crate) fn scan(state: &TraversalState, scan_roots: &[PathBuf]) -> Result<()> {
+    let mut volumes: HashMap<PathBuf, MftIndex> = HashMap::new();
+    let mut entries = Vec::new();
+    let mut skip_stats = HashMap::new();
+
+    for root in scan_roots {
+        let (volume, relative) = split_volume(root)?;
+        if !volumes.contains_key(&volume) {
+            volumes.insert(volume.clone(), MftIndex::new(read_volume(&volume)?));
+        }
+        let index = &volumes[&volume];
+        let Some(number) = index.find(&relative) else {
+            bail!("{} is not in the MFT of {}", root.display(), volume.display());
+        };
+        entries.extend(index.entries(&state.skip_rules, state.scan, root, number, &mut skip_stats));
+    }
+
+    if let Some(window) = state.settle {
+        let now = Utc::now();
+        let mut recently_modified = state.recently_modified.lock().unwrap();
+        for entry in entries
+            .iter()
+            .filter(|entry| now.signed_duration_since(entry.modified) < window)
+        {
+            recently_modified.push((entry.path.clone(), entry.modified));
+        }
+    }
+
+    let mut cache = state.cache.write();
+    for entry in entries {
+        cache.add_entry(entry.path.clone(), entry);
+    }
+    drop(cache);
+
+    let mut stats = state.skip_stats.lock().unwrap();
+    for (name, count) in skip_stats {
+        *stats.entry(name).or_insert(0) += count;
+    }
+    Ok(())
+}
+
+/// `C:\Users\me` -> (`C:\`, `Users\me`).
+fn split_volume(root: &Path) -> Result<(PathBuf, PathBuf)> {
+    let mut components = root.components();
+    match components.next() {
+        Some(Component::Prefix(prefix)) => {
+            let volume = PathBuf::from(format!("{}\\", prefix.as_os_str().to_string_lossy()));
+            Ok((volume, components.as_path().to_path_buf()))
+        }
+        _ => bail!("{} is not on a drive letter", root.display()),
+    }
+}
+
+/// All file records of the volume mounted at `volume` (`C:\`).
+#[cfg(windows)]
+fn read_volume(volume: &Path) -> Result<Vec<MftRecord>> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    const FILE_SHARE_WRITE: u32 = 0x2;
+    // Raw volume reads need whole sectors; 4 MiB keeps the read count low on big MFTs
+    const CHUNK: u64 = 4 << 20;
+
+    let device = format!("\\\\.\\{}", volume.to_string_lossy().trim_end_matches('\\'));
+    let mut handle = OpenOptions::new()
+        .read(true)
+        .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+        .open(&device)
+        .map_err(|err| anyhow::anyhow!("cannot open {} ({}); --mft needs an elevated prompt", device, err))?;
+
+    let mut boot = vec![0u8; 512];
+    handle.read_exact(&mut boot)?;
+    let layout = VolumeLayout::from_boot_sector(&boot)?;
+    let sector = layout.bytes_per_sector as usize;
+
+    let mut first = vec![0u8; layout.record_size as usize];
+    handle.seek(SeekFrom::Start(layout.mft_cluster * layout.bytes_per_cluster))?;
+    handle.read_exact(&mut first)?;
+    let runs = mft_runs(&mut first, sector)?;
+
+    let mut records = Vec::new();
+    let mut number = 0u64;
+    let mut buffer = Vec::new();
+    for (cluster, clusters) in runs {
+        let mut offset = cluster * layout.bytes_per_cluster;
+        let mut remaining = clusters * layout.bytes_per_cluster;
+        while remaining > 0 {
+            let length = remaining.min(CHUNK);
+            buffer.resize(length as usize, 0);
+            handle.seek(SeekFrom::Start(offset))?;
+            handle.read_exact(&mut buffer)?;
+
+            for record in buffer.chunks_exact_mut(layout.record_size as usize) {
+                if let Some(parsed) = parse_record(record, number, sector) {
+                    records.push(parsed);
+                }
+                number += 1;
+            }
+            offset += length;
+            remaining -= length;
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(not(windows))]
+fn read_volume(volume: &Path) -> Result<Vec<MftRecord>> {
+    bail!("cannot read the MFT of {}: only supported on Windows", volume.display())
+}
+
+/// Windows FILETIME (100 ns ticks since 1601) as UTC.
+fn filetime_to_utc(ticks: u64) -> Option<DateTime<Utc>> {
+    const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+    let since_epoch = (ticks as i64).checked_sub(UNIX_EPOCH_TICKS)?;
+    DateTime::from_timestamp(since_epoch.div_euclid(10_000_000), (since_epoch.rem_euclid(10_000_000) * 100) as u32)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn le_unsigned(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as u64)
+}
+
+/// Little-endian two's complement of any width up to 8 bytes.
+fn le_signed(bytes: &[u8]) -> i64 {
+    if bytes.is_empty() {
+        return 0;
+    }
+    let shift = 64 - 8 * bytes.len() as u32;
+    ((le_unsigned(bytes) << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const SECTOR: usize = 512;
+    const RECORD: usize = 1024;
+    /// 2026-01-01T00:00:00Z as a FILETIME
+    const JAN_2026: u64 = 134_116_992_000_000_000;
+
+    fn resident(kind: u32, value: &[u8]) -> Vec<u8> {
+        let length = (0x18 + value.len()).next_multiple_of(8);
+        let mut attribute = vec![0u8; length];
+        attribute[0..4].copy_from_slice(&kind.to_le_bytes());
+        attribute[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attribute[0x10..0x14].copy_from_slice(&(value.len() as u32).to_le_bytes());
+        attribute[0x14..0x16].copy_from_slice(&0x18u16.to_le_bytes());
+        attribute[0x18..0x18 + value.len()].copy_from_slice(value);
+        attribute
+    }
+
+    fn standard_information(modified: u64, attributes: u32) -> Vec<u8> {
+        let mut value = vec![0u8; 0x48];
+        value[0x08..0x10].copy_from_slice(&modified.to_le_bytes());
+        value[0x20..0x24].copy_from_slice(&attributes.to_le_bytes());
+        resident(ATTR_STANDARD_INFORMATION, &value)
+    }
+
+    fn file_name(parent: u64, name: &str, namespace: u8) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let mut value = vec![0u8; 0x42 + units.len() * 2];
+        // The sequence number in the top 16 bits is not part of the record number
+        value[0..8].copy_from_slice(&(parent | 7 << 48).to_le_bytes());
+        value[0x40] = units.len() as u8;
+        value[0x41] = namespace;
+        for (i, unit) in units.iter().enumerate() {
+            value[0x42 + i * 2..0x44 + i * 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        resident(ATTR_FILE_NAME, &value)
+    }
+
+    fn non_resident_data(size: u64, runs: &[u8]) -> Vec<u8> {
+        let length = (0x40 + runs.len()).next_multiple_of(8);
+        let mut attribute = vec![0u8; length];
+        attribute[0..4].copy_from_slice(&ATTR_DATA.to_le_bytes());
+        attribute[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attribute[8] = 1;
+        attribute[0x20..0x22].copy_from_slice(&0x40u16.to_le_bytes());
+        attribute[0x30..0x38].copy_from_slice(&size.to_le_bytes());
+        attribute[0x40..0x40 + runs.len()].copy_from_slice(runs);
+        attribute
+    }
+
+    /// A file record as NTFS writes it, update sequence protection included.
+    fn record(flags: u16, base: u64, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD];
+        record[0..4].copy_from_slice(b"FILE");
+        record[0x04..0x06].copy_from_slice(&0x30u16.to_le_bytes());
+        record[0x06..0x08].copy_from_slice(&3u16.to_le_bytes());
+        record[0x14..0x16].copy_from_slice(&0x38u16.to_le_bytes());
+        record[0x16..0x18].copy_from_slice(&flags.to_le_bytes());
+        record[0x20..0x28].copy_from_slice(&base.to_le_bytes());
+
+        let mut offset = 0x38;
+        for attribute in attributes {
+            record[offset..offset + attribute.len()].copy_from_slice(attribute);
+            offset += attribute.len();
+        }
+        record[offset..offset + 4].copy_from_slice(&ATTR_END.to_le_bytes());
+        // Bytes under the check values, to see them restored
+        record[SECTOR - 2..SECTOR].copy_from_slice(&[0xAB, 0xCD]);
+
+        record[0x30..0x32].copy_from_slice(&0x0042u16.to_le_bytes());
+        for sector in 1..3 {
+            let end = sector * SECTOR;
+            let saved = 0x30 + sector * 2;
+            record.copy_within(end - 2..end, saved);
+            record[end - 2..end].copy_from_slice(&0x0042u16.to_le_bytes());
+        }
+        record
+    }
+
+    fn parsed(number: u64, flags: u16, attributes: &[Vec<u8>]) -> MftRecord {
+        parse_record(&mut record(flags, 0, attributes), number, SECTOR).unwrap()
+    }
+
+    #[test]
+    fn records_yield_long_names_sizes_and_times() {
+        let mut raw = record(
+            RECORD_IN_USE,
+            0,
+            &[
+                standard_information(JAN_2026, FILE_ATTRIBUTE_HIDDEN),
+                file_name(40, "REPORT~1.PDF", NAMESPACE_DOS),
+                file_name(40, "Quarterly report.pdf", 1),
+                non_resident_data(5_000_000, &[0x11, 0x10, 0x20, 0x00]),
+            ],
+        );
+        let report = parse_record(&mut raw, 41, SECTOR).unwrap();
+
+        assert_eq!(report.number, 41);
+        assert!(!report.is_dir);
+        assert_eq!(report.links, vec![(40, "Quarterly report.pdf".to_string())]);
+        assert_eq!(report.size, 5_000_000);
+        assert_eq!(report.modified, DateTime::from_timestamp(1_767_225_600, 0));
+        assert_eq!(report.attributes, FILE_ATTRIBUTE_HIDDEN);
+        assert_eq!(raw[SECTOR - 2..SECTOR], [0xAB, 0xCD]);
+
+        // 8.3-only names are kept; free and torn records are not records
+        let short = parsed(42, RECORD_IN_USE, &[file_name(40, "A.TXT", NAMESPACE_DOS)]);
+        assert_eq!(short.links, vec![(40, "A.TXT".to_string())]);
+        assert!(parse_record(&mut record(0, 0, &[file_name(40, "gone", 1)]), 43, SECTOR).is_none());
+        let mut torn = record(RECORD_IN_USE, 0, &[file_name(40, "torn", 1)]);
+        torn[2 * SECTOR - 1] ^= 0xFF;
+        assert!(parse_record(&mut torn, 44, SECTOR).is_none());
+
+        // Extension records speak for their base record
+        let mut extension = record(RECORD_IN_USE, 41 | 3 << 48, &[resident(ATTR_DATA, &[0; 12])]);
+        assert_eq!(parse_record(&mut extension, 90, SECTOR).map(|r| (r.number, r.size)), Some((41, 12)));
+    }
+
+    #[test]
+    fn data_runs_are_relative_and_skip_sparse_ranges() {
+        // 16 clusters at 0x100, 8 at 0x100 - 0x10, 4 sparse, 2 at 0xF0 + 0x1000
+        let runs = [
+            0x21, 0x10, 0x00, 0x01, 0x11, 0x08, 0xF0, 0x01, 0x04, 0x21, 0x02, 0x00, 0x10, 0x00,
+        ];
+        assert_eq!(data_runs(&runs), vec![(0x100, 16), (0xF0, 8), (0x10F0, 2)]);
+
+        let mut first = record(RECORD_IN_USE, 0, &[file_name(5, "$MFT", 3), non_resident_data(1 << 20, &runs)]);
+        assert_eq!(mft_runs(&mut first, SECTOR).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn boot_sector_gives_the_volume_layout() {
+        let mut boot = vec![0u8; 512];
+        boot[3..11].copy_from_slice(b"NTFS    ");
+        boot[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+        boot[0x0D] = 8;
+        boot[0x30..0x38].copy_from_slice(&0xC0000u64.to_le_bytes());
+        boot[0x40] = (-10i8) as u8;
+
+        assert_eq!(
+            VolumeLayout::from_boot_sector(&boot).unwrap(),
+            VolumeLayout {
+                bytes_per_sector:  512,
+                bytes_per_cluster: 4096,
+                record_size:       1024,
+                mft_cluster:       0xC0000,
+            }
+        );
+        boot[3..11].copy_from_slice(b"EXFAT   ");
+        assert!(VolumeLayout::from_boot_sector(&boot).is_err());
+    }
+
+    #[test]
+    fn the_tree_is_rebuilt_from_parent_references() {
+        let dir = RECORD_IN_USE | RECORD_IS_DIRECTORY;
+        let index = MftIndex::new([
+            parsed(ROOT_RECORD, dir, &[file_name(ROOT_RECORD, ".", 3)]),
+            parsed(9, RECORD_IN_USE, &[file_name(ROOT_RECORD, "$Secure", 3)]),
+            parsed(30, dir, &[file_name(ROOT_RECORD, "Users", 3)]),
+            parsed(31, dir, &[standard_information(JAN_2026, 0), file_name(30, "Me", 3)]),
+            // Two hard links; the size comes from an extension record
+            parsed(32, RECORD_IN_USE, &[file_name(31, "a.txt", 3), file_name(30, "b.txt", 3)]),
+            MftRecord {
+                number:     32,
+                is_dir:     false,
+                links:      Vec::new(),
+                size:       10,
+                allocated:  16,
+                modified:   None,
+                attributes: 0,
+            },
+            parsed(33, dir, &[file_name(31, "node_modules", 3)]),
+            parsed(
+                34,
+                dir,
+                &[
+                    standard_information(JAN_2026, FILE_ATTRIBUTE_REPARSE_POINT),
+                    file_name(31, "Link", 3),
+                ],
+            ),
+            parsed(35, dir, &[file_name(33, "left-pad", 3)]),
+        ]);
+
+        assert_eq!(index.find(Path::new("users/ME")), Some(31));
+        assert_eq!(index.find(Path::new("Users/nobody")), None);
+
+        let skip_rules = SkipRules {
+            names:     HashSet::from(["node_modules".to_string()]),
+            paths:     Vec::new(),
+            patterns:  None,
+            ignore:    None,
+            markers:   false,
+            snapshots: true,
+            volumes:   crate::subvolumes::Volumes::new(),
+        };
+        let mut skip_stats = HashMap::new();
+        let root = PathBuf::from("C:\\").join("Users");
+        let mut entries = index.entries(&skip_rules, 7, &root, 30, &mut skip_stats);
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let summary: Vec<(PathBuf, usize, u64)> = entries
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.file_count, entry.total_size))
+            .collect();
+        assert_eq!(summary, vec![(root.clone(), 1, 10), (root.join("Me"), 2, 10)]);
+        assert_eq!(entries[1].children.len(), 2);
+        assert_eq!(entries[1].modified, DateTime::from_timestamp(1_767_225_600, 0).unwrap());
+        assert!(entries.iter().all(|entry| entry.scan == 7));
+        assert_eq!(skip_stats.get("node_modules"), Some(&1));
+    }
+}
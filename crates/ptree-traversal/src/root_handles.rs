@@ -0,0 +1,196 @@
+//! Scan roots held open for the whole scan, with every directory below them opened relative
+//! to its root's handle (Linux, `fast-readdir` backend).
+//!
+//! A root is opened once, by path, before any worker starts; from then on renaming or
+//! replacing the root path does not redirect the scan. Directories below it are opened with
+//! `openat2(RESOLVE_BENEATH | RESOLVE_NO_SYMLINKS)` on the root's descriptor, so a directory
+//! swapped for a symlink mid-scan (or a `..` planted in a name) cannot lead the scan out of
+//! the root: the open fails and the directory counts as unreadable. Kernels before 5.6 have
+//! no `openat2`; there the open is a plain `openat` with `O_NOFOLLOW`, which still refuses a
+//! symlink in place of the directory itself. Each open also resolves only the path below the
+//! root, not the root's own components again.
+//!
+//! Every other build (without `fast-readdir`, or not on Linux) lists directories by their full
+//! path, and none of the above holds there: a path that changes mid-scan is read wherever it
+//! then leads.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(all(target_os = "linux", feature = "fast-readdir"))]
+pub use self::handles::RootHandles;
+#[cfg(not(all(target_os = "linux", feature = "fast-readdir")))]
+pub use self::paths::RootHandles;
+use crate::dir_listing::DirListing;
+
+#[cfg(not(all(target_os = "linux", feature = "fast-readdir")))]
+mod paths {
+    use super::*;
+
+    /// Nothing is held open: directories are listed by path.
+    pub struct RootHandles;
+
+    impl RootHandles {
+        pub fn open(_roots: &[PathBuf]) -> Self {
+            RootHandles
+        }
+
+        pub fn read(&self, path: &Path) -> io::Result<DirListing> {
+            DirListing::read(path)
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "fast-readdir"))]
+pub(crate) mod handles {
+    use std::ffi::CString;
+    use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::dir_listing::getdents::OPEN_FLAGS;
+
+    /// `openat2` resolution: stay below the root and follow no symlinks on the way
+    pub(crate) const RESOLVE: u64 = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS | libc::RESOLVE_NO_MAGICLINKS;
+
+    /// Flags a directory below a root is opened with
+    pub(crate) const BENEATH_FLAGS: libc::c_int = OPEN_FLAGS | libc::O_NOFOLLOW;
+
+    /// The scan roots that could be opened, each with its descriptor.
+    pub struct RootHandles {
+        roots:   Vec<(PathBuf, OwnedFd)>,
+        /// Cleared the first time the kernel turns out to have no `openat2`
+        openat2: AtomicBool,
+    }
+
+    impl RootHandles {
+        /// Open every root; a root that cannot be opened is listed by path (and fails there
+        /// with the same error, counted as usual).
+        pub fn open(roots: &[PathBuf]) -> Self {
+            let roots = roots
+                .iter()
+                .filter_map(|root| {
+                    let c_root = CString::new(root.as_os_str().as_bytes()).ok()?;
+                    // SAFETY: `c_root` is NUL-terminated; the returned descriptor is owned below
+                    let fd = unsafe { libc::open(c_root.as_ptr(), OPEN_FLAGS) };
+                    // SAFETY: `fd` was just opened and nothing else owns it
+                    (fd >= 0).then(|| (root.clone(), unsafe { OwnedFd::from_raw_fd(fd) }))
+                })
+                .collect();
+            RootHandles {
+                roots,
+                openat2: AtomicBool::new(true),
+            }
+        }
+
+        /// The descriptor of the root holding `path` (the deepest, if roots nest) and the
+        /// rest of `path` below it (`.` for the root itself).
+        pub(crate) fn locate(&self, path: &Path) -> Option<(BorrowedFd<'_>, CString)> {
+            let (fd, relative) = self
+                .roots
+                .iter()
+                .filter_map(|(root, fd)| Some((fd, path.strip_prefix(root).ok()?)))
+                .min_by_key(|(_, relative)| relative.components().count())?;
+            let relative = if relative.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                relative
+            };
+            Some((fd.as_fd(), CString::new(relative.as_os_str().as_bytes()).ok()?))
+        }
+
+        /// Whether `openat2` is worth trying
+        pub(crate) fn has_openat2(&self) -> bool {
+            self.openat2.load(Ordering::Relaxed)
+        }
+
+        /// List the directory at `path`, opened below its root when it has one.
+        pub fn read(&self, path: &Path) -> io::Result<DirListing> {
+            let Some((root, relative)) = self.locate(path) else {
+                return DirListing::read(path);
+            };
+            DirListing::from_dir(self.open_beneath(root, &relative)?)
+        }
+
+        fn open_beneath(&self, root: BorrowedFd<'_>, relative: &CString) -> io::Result<OwnedFd> {
+            if self.has_openat2() {
+                // SAFETY: `open_how` is plain integers, for which all zeroes is valid
+                let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+                how.flags = BENEATH_FLAGS as u64;
+                how.resolve = RESOLVE;
+                // SAFETY: `relative` is NUL-terminated and `how` outlives the call
+                let fd = unsafe {
+                    libc::syscall(
+                        libc::SYS_openat2,
+                        root.as_raw_fd(),
+                        relative.as_ptr(),
+                        &how as *const libc::open_how,
+                        std::mem::size_of::<libc::open_how>(),
+                    )
+                };
+                if fd >= 0 {
+                    // SAFETY: the kernel opened `fd` for us and nothing else owns it
+                    return Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) });
+                }
+                let err = io::Error::last_os_error();
+                // Seccomp profiles that predate openat2 answer EPERM rather than ENOSYS
+                if !matches!(err.raw_os_error(), Some(libc::ENOSYS | libc::EPERM)) {
+                    return Err(err);
+                }
+                self.openat2.store(false, Ordering::Relaxed);
+            }
+            // SAFETY: `relative` is NUL-terminated; the returned descriptor is owned below
+            let fd = unsafe { libc::openat(root.as_raw_fd(), relative.as_ptr(), BENEATH_FLAGS) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` was just opened and nothing else owns it
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn directories_are_read_below_their_root() {
+        let root = std::env::temp_dir().join("ptree_test_root_handles");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/file.txt"), b"x").unwrap();
+
+        let handles = RootHandles::open(std::slice::from_ref(&root));
+        let names = |path: &Path| -> io::Result<Vec<String>> {
+            let listing = handles.read(path)?;
+            Ok(listing
+                .entries()
+                .iter()
+                .map(|entry| entry.name.to_string_lossy().into_owned())
+                .collect())
+        };
+        assert_eq!(names(&root).unwrap(), vec!["a"]);
+        assert_eq!(names(&root.join("a/b")).unwrap(), vec!["file.txt"]);
+        assert!(names(&root.join("a/missing")).is_err());
+
+        // The root stays the one opened even after its path points elsewhere
+        #[cfg(all(target_os = "linux", feature = "fast-readdir"))]
+        {
+            let moved = root.with_extension("moved");
+            let _ = fs::remove_dir_all(&moved);
+            fs::rename(&root, &moved).unwrap();
+            fs::create_dir_all(root.join("a/b")).unwrap();
+            assert_eq!(names(&root.join("a/b")).unwrap(), vec!["file.txt"]);
+            // A symlink where a directory was is not followed
+            std::os::unix::fs::symlink(&moved, moved.join("a/escape")).unwrap();
+            assert!(names(&root.join("a/escape")).is_err());
+            let _ = fs::remove_dir_all(&moved);
+        }
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
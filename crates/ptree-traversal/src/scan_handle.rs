@@ -0,0 +1,132 @@
+//! Scans running on a background thread, for hosts that cannot block on `traverse_disk`
+//! (GUIs, the TUI): start one with `ScanHandle::spawn`, poll `progress()` while it runs,
+//! `cancel()` it, and `join()` it for the updated cache.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Result};
+use ptree_cache::DiskCache;
+use ptree_core::Args;
+
+use crate::traversal::{traverse_disk_with_control, DebugInfo};
+
+/// Progress counters and the cancellation flag shared between a scan and its host.
+#[derive(Debug, Default)]
+pub struct ScanControl {
+    dirs_scanned: AtomicUsize,
+    files_seen:   AtomicUsize,
+    bytes_seen:   AtomicU64,
+    /// The directory read last, for status lines; workers skip the update when it is locked
+    current_dir:  Mutex<Option<PathBuf>>,
+    cancelled:    AtomicBool,
+    /// Set by `interrupt`: what was read is saved
+    keep_partial: AtomicBool,
+}
+
+impl ScanControl {
+    /// Count one directory read from disk with its direct files.
+    pub(crate) fn record_directory(&self, path: &Path, files: usize, bytes: u64) {
+        self.dirs_scanned.fetch_add(1, Ordering::Relaxed);
+        self.files_seen.fetch_add(files, Ordering::Relaxed);
+        self.bytes_seen.fetch_add(bytes, Ordering::Relaxed);
+        if let Ok(mut current) = self.current_dir.try_lock() {
+            *current = Some(path.to_path_buf());
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop the scan like `cancel`, but save what was read as an incomplete snapshot, which
+    /// the next run completes by reading the directories left; the scan then fails with
+    /// `PTreeError::Interrupted`.
+    pub fn interrupt(&self) {
+        self.keep_partial.store(true, Ordering::Relaxed);
+        self.cancel();
+    }
+
+    /// Whether the scan was stopped by `interrupt`.
+    pub fn is_interrupted(&self) -> bool {
+        self.keep_partial.load(Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// A directory read lately (not necessarily the very last one), if any was read yet.
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        self.current_dir.lock().ok()?.clone()
+    }
+
+    pub fn progress(&self) -> ScanProgress {
+        ScanProgress {
+            dirs_scanned: self.dirs_scanned.load(Ordering::Relaxed),
+            files_seen:   self.files_seen.load(Ordering::Relaxed),
+            bytes_seen:   self.bytes_seen.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// What a scan has read so far. Cache hits and `--mft` reads do not walk directories and
+/// report nothing until they finish.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanProgress {
+    /// Directories read from disk
+    pub dirs_scanned: usize,
+    /// Files and symlinks directly inside those directories
+    pub files_seen:   usize,
+    /// Bytes of those files
+    pub bytes_seen:   u64,
+}
+
+/// The cache a finished scan updated, and its statistics.
+pub struct ScanOutcome {
+    pub cache:      DiskCache,
+    pub debug_info: DebugInfo,
+}
+
+/// A scan started with `ScanHandle::spawn`.
+pub struct ScanHandle {
+    control: Arc<ScanControl>,
+    thread:  JoinHandle<Result<ScanOutcome>>,
+}
+
+impl ScanHandle {
+    /// Run `traverse_disk` on a new thread. The scan owns `cache` until `join` hands it back.
+    pub fn spawn(drive: char, mut cache: DiskCache, args: Args, cache_path: PathBuf) -> Result<Self> {
+        let control = Arc::new(ScanControl::default());
+        let thread = thread::Builder::new().name("ptree-scan".to_string()).spawn({
+            let control = Arc::clone(&control);
+            move || {
+                let debug_info = traverse_disk_with_control(&drive, &mut cache, &args, &cache_path, &control)?;
+                Ok(ScanOutcome { cache, debug_info })
+            }
+        })?;
+        Ok(ScanHandle { control, thread })
+    }
+
+    pub fn progress(&self) -> ScanProgress {
+        self.control.progress()
+    }
+
+    /// Ask the scan to stop and return at once. Workers finish the directories in hand and
+    /// read no more; nothing is saved, and `join` fails with `PTreeError::Cancelled`.
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    /// Whether `join` would return without waiting.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the scan to end.
+    pub fn join(self) -> Result<ScanOutcome> {
+        self.thread.join().map_err(|_| anyhow!("scan thread panicked"))?
+    }
+}
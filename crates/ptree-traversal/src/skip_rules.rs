@@ -0,0 +1,290 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ptree_core::Args;
+use regex::RegexSet;
+
+use crate::ignore_rules::IgnoreRules;
+use crate::subvolumes::{Boundary, Volume, Volumes};
+use crate::traversal::expand_tilde;
+
+/// Standard marker for cache directories (<https://bford.info/cachedir/>).
+pub const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// A `CACHEDIR.TAG` only counts if it starts with this exact signature.
+const CACHEDIR_TAG_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// An empty `.ptreeignore` opts its whole directory out; one with patterns is an ignore file (`--gitignore`).
+pub const PTREEIGNORE: &str = ".ptreeignore";
+
+/// Every rule that can exclude an entry from traversal, shared read-only across worker threads.
+///
+/// Used both by `dfs_worker` and by the live-state check that decides whether a cached
+/// snapshot is still valid, so both sides agree on what the tree contains.
+#[derive(Debug)]
+pub struct SkipRules {
+    /// Case-insensitive exact names (`--skip`, defaults, system directories)
+    pub names:     HashSet<String>,
+    /// `--skip` entries containing a path separator, matched against the full path only
+    pub paths:     Vec<PathBuf>,
+    /// `--skip-regex` patterns, compiled once and matched against the name and the full path
+    pub patterns:  Option<RegexSet>,
+    /// `.gitignore` / `.ptreeignore` rules (only set in `--gitignore` mode)
+    pub ignore:    Option<IgnoreRules>,
+    /// Leave out directories holding a `CACHEDIR.TAG` or an empty `.ptreeignore` (off with `--no-markers`)
+    pub markers:   bool,
+    /// Leave out btrfs snapshots and ZFS `.zfs` directories (off with `--include-snapshots`)
+    pub snapshots: bool,
+    /// Filesystem of each device seen, to find subvolume boundaries
+    pub volumes:   Volumes,
+}
+
+impl SkipRules {
+    /// Relative path-anchored `--skip` entries resolve against the first scan root.
+    pub fn from_args(args: &Args, scan_roots: &[PathBuf]) -> Result<Self> {
+        let scan_root = scan_roots
+            .first()
+            .map(PathBuf::as_path)
+            .unwrap_or_else(|| Path::new("."));
+
+        let patterns = if args.skip_regex.is_empty() {
+            None
+        } else {
+            Some(RegexSet::new(&args.skip_regex).context("invalid --skip-regex pattern")?)
+        };
+
+        let (anchored, names): (HashSet<String>, HashSet<String>) =
+            args.skip_dirs().into_iter().partition(|entry| is_path_entry(entry));
+        let paths = anchored
+            .iter()
+            .map(|entry| anchor_path(entry, scan_root))
+            .collect::<Result<_>>()?;
+
+        Ok(SkipRules {
+            names,
+            paths,
+            patterns,
+            ignore: args.gitignore.then(|| IgnoreRules::new(scan_roots)),
+            markers: !args.no_markers,
+            snapshots: !args.include_snapshots,
+            volumes: Volumes::new(),
+        })
+    }
+
+    /// A hash of what the rules leave out, kept with the snapshot: directories listed under
+    /// other rules cannot be kept as they are, so a scan whose rules differ reads them all.
+    pub fn fingerprint(&self) -> u64 {
+        let mut names: Vec<String> = self.names.iter().map(|name| name.to_ascii_lowercase()).collect();
+        names.sort();
+        let mut paths = self.paths.clone();
+        paths.sort();
+
+        let mut hasher = DefaultHasher::new();
+        names.hash(&mut hasher);
+        paths.hash(&mut hasher);
+        self.patterns.as_ref().map(RegexSet::patterns).hash(&mut hasher);
+        (self.ignore.is_some(), self.markers, self.snapshots).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Prepare per-directory rules (ignore files) before enumerating `dir`.
+    pub fn enter_dir(&self, dir: &Path) {
+        if let Some(rules) = &self.ignore {
+            rules.load_dir(dir);
+        }
+    }
+
+    /// True if the entry `name` at `path` should be left out of the tree.
+    pub fn should_skip(&self, name: &str, path: &Path, is_dir: bool) -> bool {
+        if should_skip(name, &self.names) {
+            return true;
+        }
+
+        if self.paths.iter().any(|anchored| paths_equal(path, anchored)) {
+            return true;
+        }
+
+        if let Some(patterns) = &self.patterns {
+            if patterns.is_match(name) || patterns.is_match(&path.to_string_lossy()) {
+                return true;
+            }
+        }
+
+        self.ignore
+            .as_ref()
+            .map(|rules| rules.is_ignored(path, is_dir))
+            .unwrap_or(false)
+    }
+
+    /// Subvolume boundary at the subdirectory `name` at `path` of a directory on `volume`, and
+    /// whether it is a snapshot to leave out.
+    pub fn subvolume(&self, volume: Option<Volume>, path: &Path, name: &str) -> Option<(Boundary, bool)> {
+        let boundary = self.volumes.boundary(volume?, path, name)?;
+        Some((boundary, self.snapshots && boundary.is_snapshot()))
+    }
+
+    /// Marker file that opts the directory at `path` out of the scan, if any.
+    ///
+    /// Costs up to two failed lookups per directory, so it is only called for
+    /// directories that survived `should_skip`.
+    pub fn marker(&self, path: &Path) -> Option<&'static str> {
+        if !self.markers {
+            return None;
+        }
+
+        if has_cachedir_tag(path) {
+            return Some(CACHEDIR_TAG);
+        }
+
+        fs::metadata(path.join(PTREEIGNORE))
+            .is_ok_and(|metadata| metadata.is_file() && metadata.len() == 0)
+            .then_some(PTREEIGNORE)
+    }
+}
+
+fn has_cachedir_tag(dir: &Path) -> bool {
+    let Ok(file) = File::open(dir.join(CACHEDIR_TAG)) else {
+        return false;
+    };
+
+    let mut header = Vec::with_capacity(CACHEDIR_TAG_SIGNATURE.len());
+    file.take(CACHEDIR_TAG_SIGNATURE.len() as u64)
+        .read_to_end(&mut header)
+        .is_ok_and(|_| header == CACHEDIR_TAG_SIGNATURE)
+}
+
+pub(crate) fn should_skip(name: &str, skip_dirs: &HashSet<String>) -> bool {
+    skip_dirs.iter().any(|skip| name.eq_ignore_ascii_case(skip))
+}
+
+/// `--skip` entries with a separator (`C:\Windows\Temp`, `~/src/target`) name one location, not every same-named dir.
+fn is_path_entry(entry: &str) -> bool {
+    entry.contains('/') || entry.contains('\\')
+}
+
+/// Resolve a path-anchored skip entry; relative entries are taken relative to the scan root.
+fn anchor_path(entry: &str, scan_root: &Path) -> Result<PathBuf> {
+    let path = expand_tilde(Path::new(entry))?;
+    Ok(if path.is_absolute() { path } else { scan_root.join(path) })
+}
+
+#[cfg(windows)]
+fn paths_equal(path: &Path, anchored: &Path) -> bool {
+    // NTFS paths are case-insensitive, like name-based skips
+    path.components().count() == anchored.components().count()
+        && path
+            .components()
+            .zip(anchored.components())
+            .all(|(a, b)| a.as_os_str().eq_ignore_ascii_case(b.as_os_str()))
+}
+
+#[cfg(not(windows))]
+fn paths_equal(path: &Path, anchored: &Path) -> bool {
+    path == anchored
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_should_skip() {
+        let mut skip = HashSet::new();
+        skip.insert("System32".to_string());
+        skip.insert(".git".to_string());
+
+        assert!(should_skip("System32", &skip));
+        assert!(should_skip(".git", &skip));
+        assert!(!should_skip("Documents", &skip));
+    }
+
+    #[test]
+    fn regex_patterns_match_names_and_full_paths() {
+        let rules = SkipRules {
+            names:     HashSet::new(),
+            paths:     Vec::new(),
+            patterns:  Some(RegexSet::new([r"^build-.*", r".*/vendor/cache$"]).unwrap()),
+            ignore:    None,
+            markers:   false,
+            snapshots: true,
+            volumes:   Volumes::new(),
+        };
+
+        assert!(rules.should_skip("build-x86", &PathBuf::from("/src/build-x86"), true));
+        assert!(!rules.should_skip("rebuild-x86", &PathBuf::from("/src/rebuild-x86"), true));
+        assert!(rules.should_skip("cache", &PathBuf::from("/src/vendor/cache"), true));
+        assert!(!rules.should_skip("cache", &PathBuf::from("/src/cache"), true));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn path_entries_skip_one_location_only() {
+        let scan_root = PathBuf::from("/data");
+        let entries = ["Temp", "/data/app/cache", "logs/archive"];
+
+        let (anchored, names): (Vec<&str>, Vec<&str>) = entries.into_iter().partition(|entry| is_path_entry(entry));
+        let rules = SkipRules {
+            names:     names.into_iter().map(String::from).collect(),
+            paths:     anchored
+                .iter()
+                .map(|entry| anchor_path(entry, &scan_root).unwrap())
+                .collect(),
+            patterns:  None,
+            ignore:    None,
+            markers:   false,
+            snapshots: true,
+            volumes:   Volumes::new(),
+        };
+
+        assert!(rules.should_skip("Temp", &PathBuf::from("/data/x/Temp"), true));
+        assert!(rules.should_skip("cache", &PathBuf::from("/data/app/cache"), true));
+        assert!(!rules.should_skip("cache", &PathBuf::from("/data/web/cache"), true));
+        assert!(rules.should_skip("archive", &PathBuf::from("/data/logs/archive"), true));
+        assert!(!rules.should_skip("archive", &PathBuf::from("/data/old/logs/archive"), true));
+    }
+
+    #[test]
+    fn marker_files_opt_directories_out() {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_markers_{unique}"));
+        for dir in ["tagged", "bad_tag", "opted_out", "patterns", "plain"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("tagged").join(CACHEDIR_TAG), b"Signature: 8a477f597d28d172789f06886806bc55\n# cache\n")
+            .unwrap();
+        fs::write(root.join("bad_tag").join(CACHEDIR_TAG), b"not a cache dir\n").unwrap();
+        fs::write(root.join("opted_out").join(PTREEIGNORE), b"").unwrap();
+        fs::write(root.join("patterns").join(PTREEIGNORE), b"*.log\n").unwrap();
+
+        let mut rules = SkipRules {
+            names:     HashSet::new(),
+            paths:     Vec::new(),
+            patterns:  None,
+            ignore:    None,
+            markers:   true,
+            snapshots: true,
+            volumes:   Volumes::new(),
+        };
+
+        assert_eq!(rules.marker(&root.join("tagged")), Some(CACHEDIR_TAG));
+        assert_eq!(rules.marker(&root.join("bad_tag")), None);
+        assert_eq!(rules.marker(&root.join("opted_out")), Some(PTREEIGNORE));
+        assert_eq!(rules.marker(&root.join("patterns")), None);
+        assert_eq!(rules.marker(&root.join("plain")), None);
+
+        rules.markers = false;
+        assert_eq!(rules.marker(&root.join("tagged")), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use ptree_cache::{StoreKind, StoreUsage};
+
+use crate::dir_listing::FileSize;
+
+/// Hard-link aware size accounting for package stores (nix, pnpm), shared across worker threads.
+///
+/// Inside a detected store each hard-linked file is counted once for the whole scan, so the
+/// store's unique bytes reflect the space it really uses next to what its files add up to.
+/// Which links were met before is up to `HardLinks`, which tracks them for directory totals.
+#[derive(Debug, Default)]
+pub struct StoreAccounting {
+    usage: Mutex<HashMap<PathBuf, StoreUsage>>,
+}
+
+impl StoreAccounting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The package store containing `dir`, if any, and that store's root.
+    pub fn detect(dir: &Path) -> Option<(StoreKind, PathBuf)> {
+        let mut prefix = PathBuf::new();
+        let mut previous: Option<&OsStr> = None;
+
+        for component in dir.components() {
+            prefix.push(component);
+            let Component::Normal(name) = component else {
+                previous = None;
+                continue;
+            };
+
+            let kind = match (previous.and_then(OsStr::to_str), name.to_str()) {
+                (Some("nix"), Some("store")) => Some(StoreKind::Nix),
+                (_, Some(".pnpm-store")) | (Some("node_modules"), Some(".pnpm")) => Some(StoreKind::Pnpm),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                return Some((kind, prefix));
+            }
+            previous = Some(name);
+        }
+
+        None
+    }
+
+    /// Record a regular file found in `store`; one that is not the `first` link to its file the
+    /// scan met (`HardLinks::account`) adds nothing to the store's unique bytes.
+    pub fn account_file(&self, store: &(StoreKind, PathBuf), file: FileSize, first: bool) {
+        let identity = file.hard_link;
+
+        let (kind, root) = store;
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(root.clone()).or_insert_with(|| {
+            StoreUsage {
+                kind:          *kind,
+                root:          root.clone(),
+                files:         0,
+                linked_files:  0,
+                logical_bytes: 0,
+                unique_bytes:  0,
+            }
+        });
+        entry.files += 1;
+        entry.linked_files += usize::from(identity.is_some());
+        entry.logical_bytes += file.len;
+        if first {
+            entry.unique_bytes += file.len;
+        }
+    }
+
+    /// Per-store usage, ordered by store path.
+    pub fn into_usage(self) -> Vec<StoreUsage> {
+        let mut usage: Vec<StoreUsage> = self.usage.into_inner().unwrap_or_default().into_values().collect();
+        usage.sort_by(|a, b| a.root.cmp(&b.root));
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+    use crate::hard_links::HardLinks;
+
+    #[test]
+    fn detects_nix_and_pnpm_store_roots() {
+        assert_eq!(
+            StoreAccounting::detect(Path::new("/nix/store/abc-hello/bin")),
+            Some((StoreKind::Nix, PathBuf::from("/nix/store")))
+        );
+        assert_eq!(
+            StoreAccounting::detect(Path::new("/app/node_modules/.pnpm/react@18/node_modules")),
+            Some((StoreKind::Pnpm, PathBuf::from("/app/node_modules/.pnpm")))
+        );
+        assert_eq!(
+            StoreAccounting::detect(Path::new("/home/u/.local/share/pnpm/.pnpm-store/v3")),
+            Some((StoreKind::Pnpm, PathBuf::from("/home/u/.local/share/pnpm/.pnpm-store")))
+        );
+        assert_eq!(StoreAccounting::detect(Path::new("/srv/store/nix")), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hard_linked_store_files_count_once() {
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_store_accounting_{unique}"));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a"), vec![0u8; 100]).unwrap();
+        fs::hard_link(root.join("a"), root.join("b")).unwrap();
+
+        let accounting = StoreAccounting::new();
+        let links = HardLinks::new(true);
+        let store = (StoreKind::Nix, root.clone());
+        for name in ["a", "b"] {
+            let file = FileSize::from(&fs::metadata(root.join(name)).unwrap());
+            let (_, first) = links.account(&root.join(name), file);
+            accounting.account_file(&store, file, first);
+        }
+
+        let usage = accounting.into_usage();
+        assert_eq!(usage[0].logical_bytes, 200);
+        assert_eq!(usage[0].unique_bytes, 100);
+        assert_eq!(usage[0].reuse_percent(), 50.0);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,303 @@
+//! Btrfs subvolumes and ZFS datasets (Linux). Their snapshots look like ordinary directories
+//! sharing every extent with the original, so a walk that reads them counts the same data once
+//! per snapshot. Read-only btrfs snapshots and the `.zfs` directory through which ZFS snapshots
+//! are reached are left out unless `--include-snapshots`; every other subvolume boundary is
+//! recorded, with the usage the filesystem accounts to it where it reports one.
+
+use std::fs::Metadata;
+use std::path::Path;
+
+use dashmap::DashMap;
+use ptree_cache::{Subvolume, SubvolumeKind};
+
+/// Name read-only btrfs snapshots are counted under in skip statistics
+pub const BTRFS_SNAPSHOT: &str = "btrfs snapshot";
+
+/// ZFS control directory at the root of every dataset, holding `snapshot/`
+pub const ZFS_CONTROL_DIR: &str = ".zfs";
+
+/// A directory on btrfs or ZFS, whose subdirectories may start other volumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume {
+    pub kind: SubvolumeKind,
+    dev:      u64,
+}
+
+/// What a subdirectory of a `Volume` turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// Another subvolume or dataset begins here
+    Subvolume(Subvolume),
+    /// A read-only btrfs snapshot (or one received from elsewhere)
+    Snapshot(Subvolume),
+    /// `.zfs` of a dataset, through which its snapshots are reached
+    ZfsSnapshots,
+}
+
+impl Boundary {
+    pub fn is_snapshot(&self) -> bool {
+        !matches!(self, Boundary::Subvolume(_))
+    }
+
+    /// Name a skipped snapshot is counted under in skip statistics.
+    pub fn skip_name(&self) -> &'static str {
+        match self {
+            Boundary::ZfsSnapshots => ZFS_CONTROL_DIR,
+            _ => BTRFS_SNAPSHOT,
+        }
+    }
+
+    /// The volume to record (none for `.zfs`, which is not one).
+    pub fn subvolume(&self) -> Option<Subvolume> {
+        match self {
+            Boundary::Subvolume(subvolume) | Boundary::Snapshot(subvolume) => Some(*subvolume),
+            Boundary::ZfsSnapshots => None,
+        }
+    }
+}
+
+/// Filesystem kinds by device, shared across worker threads so each device is asked once
+/// (every btrfs subvolume has a device number of its own).
+#[derive(Debug, Default)]
+pub struct Volumes {
+    kinds: DashMap<u64, Option<SubvolumeKind>>,
+}
+
+impl Volumes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The volume the directory at `path` (with `metadata`) is on, if it is btrfs or ZFS.
+    #[cfg(target_os = "linux")]
+    pub fn volume_of(&self, path: &Path, metadata: &Metadata) -> Option<Volume> {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = metadata.dev();
+        let kind = *self.kinds.entry(dev).or_insert_with(|| linux::filesystem_kind(path));
+        kind.map(|kind| Volume { kind, dev })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn volume_of(&self, _path: &Path, _metadata: &Metadata) -> Option<Volume> {
+        None
+    }
+
+    /// Whether the subdirectory `name` at `path` of a directory on `parent` starts another
+    /// volume. Costs a `stat` per subdirectory, which is why only btrfs and ZFS directories
+    /// are asked.
+    #[cfg(target_os = "linux")]
+    pub fn boundary(&self, parent: Volume, path: &Path, name: &str) -> Option<Boundary> {
+        if parent.kind == SubvolumeKind::Zfs && name == ZFS_CONTROL_DIR {
+            return Some(Boundary::ZfsSnapshots);
+        }
+
+        let metadata = std::fs::symlink_metadata(path).ok()?;
+        let child = self.volume_of(path, &metadata)?;
+        // Same device: an ordinary directory. Another filesystem altogether is a mount point.
+        if child.dev == parent.dev || child.kind != parent.kind {
+            return None;
+        }
+        match child.kind {
+            SubvolumeKind::Btrfs => linux::btrfs_boundary(path, &metadata),
+            SubvolumeKind::Zfs => {
+                Some(Boundary::Subvolume(Subvolume {
+                    kind:        SubvolumeKind::Zfs,
+                    usage_bytes: linux::used_bytes(path),
+                }))
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn boundary(&self, _parent: Volume, _path: &Path, _name: &str) -> Option<Boundary> {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::fd::{AsRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    use ptree_cache::{Subvolume, SubvolumeKind};
+
+    use super::Boundary;
+
+    const BTRFS_SUPER_MAGIC: u32 = 0x9123_683E;
+    const ZFS_SUPER_MAGIC: u32 = 0x2FC1_2FC2;
+
+    /// Inode number of every btrfs subvolume's root directory
+    const BTRFS_FIRST_FREE_OBJECTID: u64 = 256;
+
+    /// `_IOR(0x94, 60, struct btrfs_ioctl_get_subvol_info_args)` (504 bytes; unprivileged)
+    const BTRFS_IOC_GET_SUBVOL_INFO: u64 = 0x81F8_943C;
+    /// `_IOR(0x94, 31, struct btrfs_ioctl_fs_info_args)` (1024 bytes)
+    const BTRFS_IOC_FS_INFO: u64 = 0x8400_941F;
+    const BTRFS_SUBVOL_RDONLY: u64 = 1 << 1;
+
+    /// Words of `btrfs_ioctl_get_subvol_info_args`: `treeid`, then after the 256-byte name
+    /// `parent_id`, `dirid`, `generation`, `flags` and three 16-byte UUIDs
+    const INFO_TREE_ID: usize = 0;
+    const INFO_FLAGS: usize = 36;
+    const INFO_PARENT_UUID: usize = 39;
+    const INFO_RECEIVED_UUID: usize = 41;
+
+    pub(super) fn filesystem_kind(path: &Path) -> Option<SubvolumeKind> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat = MaybeUninit::<libc::statfs>::uninit();
+        // SAFETY: `path` is NUL-terminated and `stat` is written on success
+        if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: statfs succeeded
+        let stat = unsafe { stat.assume_init() };
+        // `f_type` is signed on some targets; the magic numbers fit 32 bits
+        match stat.f_type as u32 {
+            BTRFS_SUPER_MAGIC => Some(SubvolumeKind::Btrfs),
+            ZFS_SUPER_MAGIC => Some(SubvolumeKind::Zfs),
+            _ => None,
+        }
+    }
+
+    /// Bytes in use on the filesystem at `path`; ZFS reports them per dataset.
+    pub(super) fn used_bytes(path: &Path) -> Option<u64> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: `path` is NUL-terminated and `stat` is written on success
+        if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: statvfs succeeded
+        let stat = unsafe { stat.assume_init() };
+        // The counts are 32 bits wide on some targets
+        #[allow(clippy::unnecessary_cast)]
+        let (blocks, free, block_size) = (stat.f_blocks as u64, stat.f_bfree as u64, stat.f_frsize as u64);
+        Some(blocks.saturating_sub(free) * block_size)
+    }
+
+    /// A btrfs directory on a device of its own: a subvolume if it is the root of one.
+    pub(super) fn btrfs_boundary(path: &Path, metadata: &std::fs::Metadata) -> Option<Boundary> {
+        if metadata.ino() != BTRFS_FIRST_FREE_OBJECTID {
+            return None;
+        }
+
+        let dir = open_dir(path)?;
+        let mut info = [0u64; 63];
+        // SAFETY: `info` is as large as the structure the ioctl fills
+        let info = (unsafe { libc::ioctl(dir.as_raw_fd(), BTRFS_IOC_GET_SUBVOL_INFO as _, info.as_mut_ptr()) } == 0)
+            .then_some(info);
+        let subvolume = Subvolume {
+            kind:        SubvolumeKind::Btrfs,
+            usage_bytes: info.and_then(|info| qgroup_referenced(&dir, info[INFO_TREE_ID])),
+        };
+        Some(match info {
+            Some(info) if is_snapshot(&info) => Boundary::Snapshot(subvolume),
+            _ => Boundary::Subvolume(subvolume),
+        })
+    }
+
+    /// Read-only and made from another subvolume (`btrfs subvolume snapshot -r`, snapper,
+    /// timeshift) or received from one (`btrfs receive`). A writable snapshot is left alone,
+    /// as it is often the live root after a rollback.
+    pub(super) fn is_snapshot(info: &[u64; 63]) -> bool {
+        let derived = info[INFO_PARENT_UUID..INFO_PARENT_UUID + 2]
+            .iter()
+            .chain(&info[INFO_RECEIVED_UUID..INFO_RECEIVED_UUID + 2])
+            .any(|&word| word != 0);
+        info[INFO_FLAGS] & BTRFS_SUBVOL_RDONLY != 0 && derived
+    }
+
+    /// The subvolume's referenced bytes from its qgroup, which the kernel publishes under
+    /// `/sys/fs/btrfs/<fsid>/qgroups` while quotas are enabled.
+    fn qgroup_referenced(dir: &OwnedFd, tree_id: u64) -> Option<u64> {
+        let mut fs_info = [0u64; 128];
+        // SAFETY: `fs_info` is as large as the structure the ioctl fills
+        if unsafe { libc::ioctl(dir.as_raw_fd(), BTRFS_IOC_FS_INFO as _, fs_info.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let mut fsid = [0u8; 16];
+        fsid[..8].copy_from_slice(&fs_info[2].to_ne_bytes());
+        fsid[8..].copy_from_slice(&fs_info[3].to_ne_bytes());
+
+        let referenced = Path::new("/sys/fs/btrfs")
+            .join(format_uuid(&fsid))
+            .join("qgroups")
+            .join(format!("0_{tree_id}"))
+            .join("referenced");
+        std::fs::read_to_string(referenced).ok()?.trim().parse().ok()
+    }
+
+    pub(super) fn format_uuid(bytes: &[u8; 16]) -> String {
+        let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+
+    fn open_dir(path: &Path) -> Option<OwnedFd> {
+        use std::os::fd::FromRawFd;
+
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `path` is NUL-terminated
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+        // SAFETY: a non-negative `fd` was just opened and is owned by nothing else
+        (fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_are_skipped_under_their_own_names() {
+        let subvolume = Subvolume {
+            kind:        SubvolumeKind::Btrfs,
+            usage_bytes: Some(4096),
+        };
+        assert!(!Boundary::Subvolume(subvolume).is_snapshot());
+        assert_eq!(Boundary::Snapshot(subvolume).skip_name(), BTRFS_SNAPSHOT);
+        assert_eq!(Boundary::Snapshot(subvolume).subvolume(), Some(subvolume));
+        assert_eq!(Boundary::ZfsSnapshots.skip_name(), ZFS_CONTROL_DIR);
+        assert_eq!(Boundary::ZfsSnapshots.subvolume(), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn read_only_derived_subvolumes_are_snapshots() {
+        let mut info = [0u64; 63];
+        assert!(!linux::is_snapshot(&info));
+
+        // Read-only alone (`btrfs property set ro true`) is not a snapshot
+        info[36] = 1 << 1;
+        assert!(!linux::is_snapshot(&info));
+        info[40] = 0xABCD;
+        assert!(linux::is_snapshot(&info));
+
+        // Writable snapshots (a rolled-back root) are kept
+        info[36] = 0;
+        assert!(!linux::is_snapshot(&info));
+
+        let fsid = [
+            0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+        ];
+        assert_eq!(linux::format_uuid(&fsid), "12345678-9abc-def0-0123-456789abcdef");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn directories_elsewhere_are_not_volumes() {
+        let volumes = Volumes::new();
+        let temp = std::env::temp_dir();
+        let metadata = std::fs::metadata(&temp).unwrap();
+        // Unless the test machine keeps its temporary files on btrfs or ZFS
+        if let Some(volume) = volumes.volume_of(&temp, &metadata) {
+            assert_eq!(volumes.volume_of(&temp, &metadata), Some(volume));
+            return;
+        }
+        assert_eq!(volumes.kinds.len(), 1);
+    }
+}
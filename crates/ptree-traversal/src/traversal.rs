@@ -1,16 +1,51 @@
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, DashSet};
 use parking_lot::RwLock;
-use ptree_cache::{compute_content_hash, DirEntry, DiskCache};
-use ptree_core::Args;
+use ptree_cache::{
+    compute_content_hash,
+    file_extension,
+    file_id,
+    keep_largest_files,
+    AltStream,
+    DirEntry,
+    DiskCache,
+    EntryIds,
+    EntryMetadata,
+    ExtensionUsage,
+    LargeFile,
+    Name,
+    OwnerUsage,
+    ScanError,
+    Subvolume,
+    Symlink,
+    SymlinkStatus,
+    XAttr,
+    LARGEST_FILES_KEPT,
+    NEW_DIR_MIN_BYTES,
+    SLOW_DIR_MILLIS,
+};
+use ptree_core::{Args, PTreeError, ScanEngine};
 use ptree_incremental::{build_changed_directory_set, IncrementalChange};
 
+use crate::dir_listing::{size_on_disk, DirListing, EntryKind, FileSize};
+use crate::hard_links::HardLinks;
+use crate::root_handles::RootHandles;
+use crate::scan_handle::ScanControl;
+use crate::skip_rules::SkipRules;
+use crate::store_accounting::StoreAccounting;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::uring::UringReader;
+use crate::warnings::Warnings;
+use crate::work_queue::{WorkQueue, WorkerQueue};
+
 fn system_time_to_utc(time: std::time::SystemTime) -> chrono::DateTime<Utc> {
     chrono::DateTime::<Utc>::from(time)
 }
@@ -29,21 +64,41 @@ pub struct DebugInfo {
     pub total_dirs:          usize,
     pub total_files:         usize,
     pub threads_used:        usize,
+    /// Directories that could not be listed (permissions, vanished mid-scan)
+    pub unreadable_dirs:     usize,
+    /// Entries of a cache hit handed over by the cold-start prefetch (set by the caller)
+    pub prefetched:          usize,
+    /// Directories spilled to disk during the scan to stay within `--max-memory`
+    pub spilled_dirs:        usize,
+    /// Most directories queued or being read at once during the scan
+    pub peak_queue_depth:    usize,
+    /// Directories a worker took from another worker's queue
+    pub stolen_dirs:         usize,
+    /// The scan read the NTFS MFT (`--mft`) instead of walking the directories
+    pub read_from_mft:       bool,
+    /// Slow-listed directories a scheduled refresh kept as last seen instead of reading
+    pub skipped_slow_dirs:   usize,
+    /// The directories were read by the io_uring engine (`--engine uring`)
+    pub used_uring:          bool,
+    /// Directories whose mtime matched the snapshot, kept as cached instead of read (see `--verify`)
+    pub unchanged_dirs:      usize,
 }
 
 /// Shared state for parallel DFS traversal across worker threads
 pub struct TraversalState {
-    /// Work queue: directories to be processed
-    pub work_queue: Arc<Mutex<VecDeque<PathBuf>>>,
+    /// Work-stealing queues of directories to be processed
+    pub work_queue: WorkQueue,
 
     /// Shared cache across all worker threads
     pub cache: Arc<RwLock<DiskCache>>,
 
-    /// Track directories currently being processed (prevents duplicates)
-    pub in_progress: Arc<Mutex<std::collections::HashSet<PathBuf>>>,
+    /// Directories queued or being processed (prevents duplicates); a directory stays here
+    /// from being queued until its entry is in the shared cache. Sharded, so workers
+    /// claiming and releasing directories rarely wait on each other.
+    pub in_progress: Arc<DashSet<PathBuf>>,
 
-    /// Directories to skip during traversal
-    pub skip_dirs: std::collections::HashSet<String>,
+    /// Names, patterns, and ignore files that exclude entries during traversal
+    pub skip_rules: SkipRules,
 
     /// Directories that changed since last scan (for incremental updates)
     /// If set, only these directories will be rescanned; unset means full scan
@@ -51,6 +106,138 @@ pub struct TraversalState {
 
     /// Skip statistics: count of skipped directories (shared across threads)
     pub skip_stats: Arc<Mutex<std::collections::HashMap<String, usize>>>,
+
+    /// Hard-link aware sizes for files inside nix / pnpm package stores
+    pub store_accounting: StoreAccounting,
+
+    /// Multiply-linked files met so far and the link each counts at (unless `--count-links`)
+    pub hard_links: HardLinks,
+
+    /// `--settle` window: directories modified this recently are re-checked after the scan
+    pub settle: Option<chrono::Duration>,
+
+    /// Directories (and the mtime seen while scanning) that fell inside the settle window
+    pub recently_modified: Mutex<Vec<(PathBuf, chrono::DateTime<Utc>)>>,
+
+    /// Directories whose listing failed (counted, then left out of the snapshot)
+    pub unreadable_dirs: AtomicUsize,
+
+    /// Unreadable directories and bad names met so far, reported a few of each kind at a time
+    pub warnings: Warnings,
+
+    /// The scan roots, opened before the workers start; directories are read relative to them
+    pub root_handles: RootHandles,
+
+    /// Number of this scan, stamped on every entry it reads from disk
+    pub scan: u64,
+
+    /// `--max-memory` full scan: go depth-first and spill fully scanned subtrees to disk
+    /// whenever the entries outgrow the budget
+    pub spill_cold_subtrees: bool,
+
+    /// Stable IDs before the scan (spilled directories get theirs before leaving memory)
+    pub previous_ids: Arc<EntryIds>,
+
+    /// Directories whose entries went into the cache since the last checkpoint, when the scan
+    /// saves checkpoints
+    pub read_since_checkpoint: Option<Mutex<Vec<PathBuf>>>,
+
+    /// Directories with slow listings on earlier scans; their listings are always timed
+    pub known_slow: std::collections::HashSet<PathBuf>,
+
+    /// Slow-listed directories a scheduled refresh keeps as last seen (their subtrees are
+    /// loaded from the snapshot up front) instead of reading
+    pub skip_slow: std::collections::HashSet<PathBuf>,
+
+    /// Listings that were slow or of a `known_slow` directory, in milliseconds
+    pub slow_listings: Mutex<Vec<(PathBuf, u64)>>,
+
+    /// Directories of `skip_slow` the scan reached and left alone
+    pub skipped_slow: Mutex<Vec<PathBuf>>,
+
+    /// Engine the workers read directories with (`--engine`, after falling back)
+    pub engine: ScanEngine,
+
+    /// Progress counters and cancellation flag shared with a `ScanHandle`
+    pub control: Arc<ScanControl>,
+
+    /// `--ads` (Windows): enumerate the alternate data streams of every file and directory read
+    pub ads: bool,
+
+    /// Files and directories found with named alternate data streams
+    pub alt_streams: Mutex<Vec<(PathBuf, Vec<AltStream>)>>,
+
+    /// `--xattrs` (Unix): enumerate the extended attributes of every file and directory read
+    pub xattrs: bool,
+
+    /// Files and directories found with extended attributes
+    pub found_xattrs: Mutex<Vec<(PathBuf, Vec<XAttr>)>>,
+
+    /// `--owners` (Unix): sum the files of every directory read by owner
+    pub owners: bool,
+
+    /// Usage per owner of the directories read
+    pub owner_usage: Mutex<Vec<(PathBuf, Vec<OwnerUsage>)>>,
+
+    /// `--extensions`: sum the files of every directory read by extension
+    pub extensions: bool,
+
+    /// Usage per extension of the directories read
+    pub extension_usage: Mutex<Vec<(PathBuf, Vec<ExtensionUsage>)>>,
+
+    /// `--long`: record the permissions, owner, size and modification time of every entry read
+    pub long: bool,
+
+    /// Metadata of the files and directories read
+    pub entry_metadata: Mutex<Vec<(PathBuf, EntryMetadata)>>,
+
+    /// `--one-file-system`: subdirectories on another device than their parent are not entered
+    pub one_file_system: bool,
+
+    /// Mount points found (and not entered) under `one_file_system`
+    pub mount_points: Mutex<Vec<PathBuf>>,
+
+    /// `--follow-symlinks`: symlinks are followed out of the roots, at most this many deep
+    pub follow_symlinks: Option<usize>,
+
+    /// Directories reached through followed symlinks, with how many links deep they are; they
+    /// are read by path, as `root_handles` resolves no symlinks
+    pub link_depths: DashMap<PathBuf, usize>,
+
+    /// Canonical paths of the roots: links into them are not followed
+    pub canonical_roots: Vec<PathBuf>,
+
+    /// Canonical paths of the directories entered through a symlink: links back into them are
+    /// not followed again
+    pub followed_targets: Mutex<Vec<PathBuf>>,
+
+    /// Symlinks met under `follow_symlinks`
+    pub symlinks: Mutex<Vec<(PathBuf, Symlink)>>,
+
+    /// Directories that could not be read, and why
+    pub scan_errors: Mutex<Vec<(PathBuf, ScanError)>>,
+
+    /// Directories left unread by an interrupted scan (`ScanControl::interrupt`)
+    pub pending_dirs: Mutex<Vec<PathBuf>>,
+
+    /// `--scan-depth`: directories more than this many levels below their root are not queued
+    pub scan_depth: Option<usize>,
+
+    /// The roots `scan_depth` counts from
+    pub scan_roots: Vec<PathBuf>,
+
+    /// Candidates for the largest files below the roots (trimmed as it grows)
+    pub largest_files: Mutex<Vec<LargeFile>>,
+
+    /// Btrfs subvolumes and ZFS datasets found below the directories read
+    pub subvolumes: Mutex<Vec<(PathBuf, Subvolume)>>,
+
+    /// Refresh without `--verify`: cached directories whose mtime is unchanged and older than
+    /// this are kept as they are instead of read
+    pub reuse_before: Option<chrono::DateTime<Utc>>,
+
+    /// Directories kept as cached under `reuse_before`
+    pub unchanged_dirs: AtomicUsize,
 }
 
 struct LiveDirectorySummary {
@@ -85,9 +272,24 @@ struct LiveDirectorySummary {
 /// 7. Spawn worker threads that process queue in parallel (iterative DFS)
 /// 8. Flush all pending writes and save cache atomically
 pub fn traverse_disk(drive: &char, cache: &mut DiskCache, args: &Args, cache_path: &Path) -> Result<DebugInfo> {
-    traverse_disk_with_filter(drive, cache, args, cache_path, None)
+    traverse_disk_with_filter(drive, cache, args, cache_path, None, &Arc::default())
+}
+
+/// `traverse_disk` reporting progress to, and stopping when cancelled through, `control`
+/// (see `ScanHandle`).
+pub fn traverse_disk_with_control(
+    drive: &char,
+    cache: &mut DiskCache,
+    args: &Args,
+    cache_path: &Path,
+    control: &Arc<ScanControl>,
+) -> Result<DebugInfo> {
+    traverse_disk_with_filter(drive, cache, args, cache_path, None, control)
 }
 
+/// Apply `changes` to a cache whose entries are all loaded (`load_all_entries_lazy`): the
+/// directories they touched are read again, with their ancestors, and everything else is
+/// kept as it is in memory, so a batch costs what it touched rather than the whole tree.
 pub fn traverse_disk_incremental(
     drive: &char,
     cache: &mut DiskCache,
@@ -95,9 +297,13 @@ pub fn traverse_disk_incremental(
     cache_path: &Path,
     changes: &[IncrementalChange],
 ) -> Result<DebugInfo> {
-    let scan_root = resolve_scan_root(drive, args)?;
-    let changed_dirs = build_changed_directory_set(&scan_root, changes);
-    traverse_disk_with_filter(drive, cache, args, cache_path, Some(changed_dirs))
+    let mut changed_dirs = std::collections::HashSet::new();
+    for scan_root in resolve_scan_roots(drive, args)? {
+        changed_dirs.extend(build_changed_directory_set(&scan_root, changes));
+    }
+    // A directory removed with everything in it goes when its nearest remaining ancestor is read
+    changed_dirs.retain(|path| path.is_dir());
+    traverse_disk_with_filter(drive, cache, args, cache_path, Some(changed_dirs), &Arc::default())
 }
 
 fn traverse_disk_with_filter(
@@ -106,45 +312,79 @@ fn traverse_disk_with_filter(
     args: &Args,
     cache_path: &Path,
     changed_dirs_filter: Option<std::collections::HashSet<PathBuf>>,
+    control: &Arc<ScanControl>,
 ) -> Result<DebugInfo> {
     #[cfg(not(windows))]
     let _ = drive;
 
     let incremental_refresh = changed_dirs_filter.is_some();
-    let scan_root = resolve_scan_root(drive, args)?;
-    let skip_dirs = args.skip_dirs();
-
-    // Verify scan root exists and is a directory
-    if !scan_root.exists() {
-        anyhow::bail!("Scan root does not exist: {}", scan_root.display());
+    let scan_roots = resolve_scan_roots(drive, args)?;
+    let _span = tracing::info_span!("scan", roots = ?scan_roots, force = args.force, incremental = incremental_refresh)
+        .entered();
+    let scan_root = scan_roots[0].clone();
+    let skip_rules = SkipRules::from_args(args, &scan_roots)?;
+
+    // Verify every scan root exists and is a directory
+    for root in &scan_roots {
+        if !root.exists() {
+            anyhow::bail!("Scan root does not exist: {}", root.display());
+        }
+        if !root.is_dir() {
+            anyhow::bail!("Scan root is not a directory: {}", root.display());
+        }
     }
-    if !scan_root.is_dir() {
-        anyhow::bail!("Scan root is not a directory: {}", scan_root.display());
+
+    // A complete snapshot is only replaced by a scan that completes: checkpoints, and what an
+    // interrupted scan read, go to its resume point, which `--force` picks up from
+    let keeps_complete_snapshot = cache.has_cache_snapshot() && !cache.is_incomplete();
+    let resume_path = DiskCache::resume_path(cache_path);
+    let mut snapshot_path = cache_path.to_path_buf();
+    if args.force && !args.no_resume && !args.no_cache && !args.mft && !incremental_refresh {
+        if let Some(mut partial) = open_resume_point(&resume_path) {
+            partial.max_cache_bytes = cache.max_cache_bytes;
+            partial.max_memory_bytes = cache.max_memory_bytes;
+            partial.lock_timeout = cache.lock_timeout;
+            // The snapshot's shards keep their generation until the next save replaces them
+            partial.generation = partial.generation.max(cache.generation);
+            *cache = partial;
+            snapshot_path = resume_path.clone();
+        }
     }
 
     let is_first_run = !cache.has_cache_snapshot();
     cache.root = scan_root.clone();
+    cache.roots = if scan_roots.len() > 1 {
+        scan_roots.clone()
+    } else {
+        Vec::new()
+    };
 
-    // Ensure root directory is added to cache (important for --no-cache mode)
-    if is_first_run && !cache.entries.contains_key(&scan_root) {
-        let root_entry = DirEntry {
-            path:         scan_root.clone(),
-            name:         scan_root
-                .file_name()
-                .and_then(|n| n.to_str().map(|s| s.to_string()))
-                .unwrap_or_default(),
-            modified:     fs::metadata(&scan_root)
-                .and_then(|metadata| metadata.modified())
-                .map(system_time_to_utc)
-                .unwrap_or_else(|_| Utc::now()),
-            content_hash: 0,
-            file_count:   0,
-            total_size:   0,
-            children:     Vec::new(),
-            is_hidden:    false,
-            is_dir:       true,
-        };
-        cache.entries.insert(scan_root.clone(), root_entry);
+    // Ensure root directories are added to cache (important for --no-cache mode)
+    for root in &scan_roots {
+        if is_first_run && !cache.entries.contains_key(root) {
+            let root_entry = DirEntry {
+                path:           root.clone(),
+                name:           root
+                    .file_name()
+                    .and_then(|n| n.to_str().map(Name::new))
+                    .unwrap_or_default(),
+                modified:       fs::metadata(root)
+                    .and_then(|metadata| metadata.modified())
+                    .map(system_time_to_utc)
+                    .unwrap_or(DateTime::UNIX_EPOCH),
+                content_hash:   0,
+                file_count:     0,
+                total_size:     0,
+                allocated_size: 0,
+                children:       Vec::new(),
+                is_hidden:      false,
+                is_dir:         true,
+                id:             0,
+                file_id:        0,
+                scan:           0,
+            };
+            cache.entries.insert(root.clone(), root_entry);
+        }
     }
 
     // ============================================================================
@@ -153,26 +393,60 @@ fn traverse_disk_with_filter(
 
     let cache_ttl_seconds = args.cache_ttl.unwrap_or(3600);
 
-    let should_use_cache = if args.no_cache {
-        false // --no-cache always triggers rescan
-    } else if args.force {
-        false // --force always triggers rescan
-    } else if incremental_refresh {
-        false // Incremental refresh must rescan affected directories immediately
-    } else if is_first_run {
-        false // First run always scans
+    let one_file_system = resolve_one_file_system(args.one_file_system)?;
+    let follow_symlinks = args
+        .follow_symlinks
+        .then(|| args.max_symlink_depth.unwrap_or(DEFAULT_MAX_SYMLINK_DEPTH));
+    let skip_fingerprint = skip_rules.fingerprint();
+    let rescan_reason = rescan_reason(
+        args,
+        cache,
+        incremental_refresh,
+        is_first_run,
+        one_file_system,
+        follow_symlinks.is_some(),
+        skip_fingerprint,
+    );
+    let should_use_cache = if let Some(reason) = rescan_reason {
+        tracing::info!(reason, "not using the snapshot");
+        false
     } else {
         // Check cache freshness rule (time-based only)
         let now = Utc::now();
         let age = now.signed_duration_since(cache.last_scan);
         if age.num_seconds() >= cache_ttl_seconds as i64 {
+            tracing::info!(
+                age_secs = age.num_seconds(),
+                ttl_secs = cache_ttl_seconds,
+                "not using the snapshot: older than --cache-ttl"
+            );
             false
         } else {
-            cache_matches_live_state(cache, cache_path, &scan_root, &skip_dirs)?
+            let links = HardLinks::new(args.counts_every_link());
+            let mut live = scan_roots
+                .iter()
+                .map(|root| summarize_live_directory(root, &skip_rules, &links))
+                .collect::<Result<Vec<_>>>()?;
+            // A hard-linked file counts at the link that sorts first, known once every link was met
+            if links.settle() {
+                live = scan_roots
+                    .iter()
+                    .map(|root| summarize_live_directory(root, &skip_rules, &links))
+                    .collect::<Result<Vec<_>>>()?;
+            }
+            let mut all_match = true;
+            for (root, live) in scan_roots.iter().zip(&live) {
+                if !cache_matches_live_state(cache, cache_path, root, live)? {
+                    all_match = false;
+                    break;
+                }
+            }
+            all_match
         }
     };
 
     if should_use_cache {
+        tracing::info!("snapshot matches the disk, using it");
         return Ok(DebugInfo {
             is_first_run:        false,
             incremental_refresh: false,
@@ -185,23 +459,183 @@ fn traverse_disk_with_filter(
             total_dirs:          cache.entry_count_hint(),
             total_files:         cache.file_count_hint(),
             threads_used:        0,
+            unreadable_dirs:     0,
+            prefetched:          0,
+            spilled_dirs:        0,
+            peak_queue_depth:    0,
+            stolen_dirs:         0,
+            read_from_mft:       false,
+            skipped_slow_dirs:   0,
+            used_uring:          false,
+            unchanged_dirs:      0,
         });
     }
 
+    // A refresh of a complete snapshot keeps directories whose mtime has not moved and only
+    // reads the rest. Entries cached within the last moment before the snapshot was saved are
+    // read anyway: a change in the same mtime tick would not show.
+    let reuse_before = (!is_first_run
+        && !args.force
+        && !args.verify
+        && !args.no_cache
+        && !args.mft
+        && !incremental_refresh
+        && cache.max_memory_bytes.is_none()
+        && !cache.is_reduced_snapshot()
+        && !cache.snapshot_trim().is_trimmed()
+        && (!args.ads || cache.ads_scanned.is_some())
+        && (!args.xattrs || cache.xattrs_scanned.is_some())
+        && (!args.owners || cache.owners_scanned.is_some())
+        && (!args.extensions || cache.extensions_scanned.is_some())
+        && (!args.long || cache.metadata_scanned.is_some())
+        && cache.count_links == args.counts_every_link()
+        && cache.one_file_system == one_file_system
+        && cache.skip_rules == skip_fingerprint
+        && follow_symlinks.is_none()
+        && cache.symlinks.is_empty())
+    .then(|| cache.last_scan - chrono::Duration::seconds(2));
+    // --force on a snapshot that an interrupted or crashed scan left incomplete picks up where
+    // it stopped: what it read is kept and only the directories it left are read. A trimmed
+    // snapshot's kept totals already include what it dropped, so it is read whole.
+    let resume = args.force
+        && !args.no_resume
+        && !args.no_cache
+        && !args.mft
+        && !incremental_refresh
+        && cache.is_incomplete()
+        && !cache.snapshot_trim().is_trimmed()
+        && cache.max_memory_bytes.is_none()
+        && cache.count_links == args.counts_every_link()
+        && cache.one_file_system == one_file_system
+        && cache.skip_rules == skip_fingerprint
+        && follow_symlinks.is_none()
+        && cache.symlinks.is_empty();
+    if reuse_before.is_some() {
+        tracing::info!("refreshing: directories whose mtime has not moved are kept");
+    }
+    if resume {
+        tracing::info!(pending = cache.pending_dirs.len(), "resuming an interrupted scan");
+        eprintln!(
+            "Resuming an interrupted scan: {} directories left to read (--no-resume starts over)",
+            cache.pending_dirs.len()
+        );
+    }
+    // They all start from the whole snapshot and sum totals from what they read and what they keep
+    if reuse_before.is_some() || resume {
+        cache.load_all_entries_lazy(&snapshot_path)?;
+        cache.restore_direct_totals();
+    }
+    // An incremental refresh has it loaded already and only reads the changed directories
+    // again; reloading it from disk would bring back what earlier batches removed
+    if let Some(changed) = &changed_dirs_filter {
+        cache.restore_direct_totals_of(changed);
+    }
+
+    // Rescanned directories get their stable IDs back (an unreadable snapshot just means fresh IDs)
+    let previous_ids = Arc::new(match &changed_dirs_filter {
+        Some(changed) => cache.entry_ids_around(changed),
+        None => cache.previous_entry_ids(cache_path).unwrap_or_default(),
+    });
+    let read_dirs = changed_dirs_filter.clone();
+    cache.scan += 1;
+
+    // Incremental refreshes only hold the changed directories; the budget is for full scans
+    let spill_cold_subtrees = cache.max_memory_bytes.is_some() && !incremental_refresh;
+    if spill_cold_subtrees {
+        cache.start_spill(cache_path)?;
+        cache.recount_memory();
+    }
+
+    // Scheduled refreshes keep slow-listed directories (typically hung network mounts) as the
+    // last snapshot had them, so one bad share cannot stall every background scan
+    let skip_slow: std::collections::HashSet<PathBuf> = if args.scheduled && !args.include_slow {
+        cache
+            .listed_slow_dirs()
+            .into_iter()
+            .map(|(path, _)| path.to_path_buf())
+            .filter(|path| !scan_roots.contains(path))
+            .collect()
+    } else {
+        std::collections::HashSet::new()
+    };
+    for path in &skip_slow {
+        cache.load_subtree_lazy(path, &snapshot_path)?;
+    }
+
     // ============================================================================
     // Initialize Traversal State
     // ============================================================================
 
-    let mut work_queue = VecDeque::new();
-    work_queue.push_back(scan_root.clone());
-
+    // A full scan works on a copy, so a cancelled one leaves the cache as it was; an
+    // incremental one cannot be cancelled and takes the cache over rather than copy all of it
+    let known_slow = cache.slow_dirs.keys().cloned().collect();
+    let scan = cache.scan;
+    // Long full scans save checkpoints, so a crash or reboot does not lose all they read
+    let checkpoint_every = (!args.no_cache && !args.no_save && !incremental_refresh && !spill_cold_subtrees)
+        .then(|| args.checkpoint_interval.unwrap_or(DEFAULT_CHECKPOINT_INTERVAL));
+    let scan_cache = if incremental_refresh {
+        std::mem::take(cache)
+    } else {
+        cache.clone()
+    };
     let state = TraversalState {
-        work_queue: Arc::new(Mutex::new(work_queue)),
-        cache: Arc::new(RwLock::new(cache.clone())),
-        in_progress: Arc::new(Mutex::new(std::collections::HashSet::new())),
-        skip_dirs: skip_dirs.clone(),
+        // Depth-first under a memory budget, so subtrees finish (and can be spilled) early
+        work_queue: WorkQueue::new(spill_cold_subtrees),
+        cache: Arc::new(RwLock::new(scan_cache)),
+        in_progress: Arc::new(DashSet::new()),
+        skip_rules,
         changed_dirs_filter,
         skip_stats: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        store_accounting: StoreAccounting::new(),
+        hard_links: HardLinks::new(args.counts_every_link()),
+        settle: args.settle.map(|ms| chrono::Duration::milliseconds(ms as i64)),
+        recently_modified: Mutex::new(Vec::new()),
+        unreadable_dirs: AtomicUsize::new(0),
+        warnings: Warnings::new(args.verbose),
+        root_handles: RootHandles::open(&scan_roots),
+        scan,
+        spill_cold_subtrees,
+        previous_ids: Arc::clone(&previous_ids),
+        read_since_checkpoint: checkpoint_every.map(|_| Mutex::new(Vec::new())),
+        known_slow,
+        skip_slow,
+        slow_listings: Mutex::new(Vec::new()),
+        skipped_slow: Mutex::new(Vec::new()),
+        engine: resolve_engine(args.engine),
+        control: Arc::clone(control),
+        ads: resolve_ads(args.ads),
+        alt_streams: Mutex::new(Vec::new()),
+        xattrs: resolve_xattrs(args.xattrs),
+        found_xattrs: Mutex::new(Vec::new()),
+        owners: resolve_owners(args.owners),
+        owner_usage: Mutex::new(Vec::new()),
+        extensions: args.extensions,
+        extension_usage: Mutex::new(Vec::new()),
+        long: args.long,
+        entry_metadata: Mutex::new(Vec::new()),
+        one_file_system,
+        mount_points: Mutex::new(Vec::new()),
+        follow_symlinks,
+        link_depths: DashMap::new(),
+        canonical_roots: match follow_symlinks {
+            Some(_) => {
+                scan_roots
+                    .iter()
+                    .filter_map(|root| fs::canonicalize(root).ok())
+                    .collect()
+            }
+            None => Vec::new(),
+        },
+        followed_targets: Mutex::new(Vec::new()),
+        symlinks: Mutex::new(Vec::new()),
+        scan_errors: Mutex::new(Vec::new()),
+        pending_dirs: Mutex::new(Vec::new()),
+        scan_depth: args.scan_depth,
+        scan_roots: scan_roots.clone(),
+        largest_files: Mutex::new(Vec::new()),
+        subvolumes: Mutex::new(Vec::new()),
+        reuse_before,
+        unchanged_dirs: AtomicUsize::new(0),
     };
 
     // ============================================================================
@@ -226,30 +660,61 @@ fn traverse_disk_with_filter(
     // ============================================================================
 
     let traversal_start = Instant::now();
-    let filter = state.changed_dirs_filter.clone();
-    let root = scan_root.clone();
-    let skip_stats_ref = Arc::clone(&state.skip_stats);
-    pool.in_place_scope(|s| {
-        for _ in 0..num_threads {
-            let work = Arc::clone(&state.work_queue);
-            let cache_ref = Arc::clone(&state.cache);
-            let skip = state.skip_dirs.clone();
-            let in_progress = Arc::clone(&state.in_progress);
-            let filter_ref = filter.clone();
-            let root_ref = root.clone();
-            let stats_ref = Arc::clone(&skip_stats_ref);
-
-            s.spawn(move |_| {
-                dfs_worker(&work, &cache_ref, &skip, &in_progress, &filter_ref, &root_ref, &stats_ref);
-            });
+    // Full scans with --mft read the volume's MFT; when it cannot be read, walk as usual
+    let read_from_mft = args.mft && !incremental_refresh && read_mft(&state, &scan_roots);
+    if !read_from_mft {
+        // A refresh of an interrupted snapshot keeps what it has and reads what it left; a
+        // resumed one only reads what it left
+        let left = || cache.pending_dirs.iter().filter(|path| path.is_dir()).cloned();
+        match (resume, reuse_before) {
+            (true, _) => queue_shared(&state, left()),
+            (false, Some(_)) => queue_shared(&state, scan_roots.iter().cloned().chain(left())),
+            (false, None) => queue_shared(&state, scan_roots.iter().cloned()),
         }
-    });
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let checkpoints = checkpoint_every.map(|interval| {
+                let (state, base, resume_path, done) = (&state, &*cache, &resume_path, &done);
+                scope.spawn(move || checkpoint_while_scanning(state, base, resume_path, interval, done))
+            });
+            run_workers(&state, &pool, num_threads, &scan_roots);
+            done.store(true, Ordering::Release);
+            if let Some(checkpoints) = checkpoints {
+                checkpoints.thread().unpark();
+            }
+        });
+    }
+
+    // A cancelled scan read only part of the tree; keep the cache as it was. An interrupted
+    // one saves what it read for the next run to complete.
+    let discard = |state: &TraversalState| {
+        state.control.is_cancelled() && (!state.control.is_interrupted() || args.no_cache || args.no_save)
+    };
+    if discard(&state) {
+        return Err(PTreeError::Cancelled.into());
+    }
+
+    // Directories that were changing while we scanned get one more look before caching
+    let unsettled_dirs = if state.settle.is_some() && !state.control.is_cancelled() {
+        settle_recently_modified(&state, &pool, num_threads, &scan_roots)
+    } else {
+        std::collections::HashSet::new()
+    };
+    if discard(&state) {
+        return Err(PTreeError::Cancelled.into());
+    }
+    let interrupted = state.control.is_interrupted();
     let traversal_elapsed = traversal_start.elapsed();
+    state.warnings.print_summary();
 
     // ============================================================================
     // Extract & Save Final Cache
     // ============================================================================
 
+    // Every directory was read: what the snapshot had and the scan did not see is gone
+    let unchanged_dirs = state.unchanged_dirs.load(Ordering::Relaxed);
+    let read_everything = !incremental_refresh && unchanged_dirs == 0 && !interrupted && !resume;
+
     let mut final_cache = match Arc::try_unwrap(state.cache) {
         Ok(lock) => lock.into_inner(),
         Err(arc) => {
@@ -258,14 +723,23 @@ fn traverse_disk_with_filter(
         }
     };
 
+    // Totals and Merkle hashes are derived bottom-up in one pass, counted as index time
+    let cache_index_start = Instant::now();
+
     // Flush any remaining pending writes before saving
     final_cache.flush_pending_writes();
-    final_cache.refresh_derived_metadata();
-
-    let cache_index_start = Instant::now();
+    state.hard_links.reattribute(&mut final_cache.entries);
+    match &read_dirs {
+        Some(read) => final_cache.refresh_derived_metadata_of(read, &previous_ids),
+        None => {
+            final_cache.refresh_derived_metadata();
+            final_cache.assign_entry_ids(&previous_ids);
+        }
+    }
 
     *cache = final_cache;
     cache.last_scan = Utc::now();
+    cache.record_scan(state.scan, cache.last_scan);
 
     // Transfer skip statistics from traversal state to cache
     let skip_stats = match Arc::try_unwrap(state.skip_stats) {
@@ -276,24 +750,132 @@ fn traverse_disk_with_filter(
         }
     };
     cache.skip_stats = skip_stats;
+    if !incremental_refresh {
+        cache.unsettled_dirs = unsettled_dirs;
+    } else {
+        cache.unsettled_dirs.extend(unsettled_dirs);
+    }
+    // A full rescan flags large directories the previous snapshot did not have
+    if !incremental_refresh && !previous_ids.is_empty() {
+        cache.flag_new_dirs(&previous_ids, NEW_DIR_MIN_BYTES, cache.last_scan);
+    }
+
+    let skipped_slow: std::collections::HashSet<PathBuf> = state
+        .skipped_slow
+        .into_inner()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let slow_listings = state.slow_listings.into_inner().unwrap_or_default();
+    cache.record_slow_listings(&slow_listings, &skipped_slow, read_everything && !read_from_mft, cache.last_scan);
+
+    if state.ads && read_from_mft {
+        eprintln!("warning: --ads needs the directory walk; alternate data streams were not recorded with --mft");
+    } else if state.ads {
+        let alt_streams = state.alt_streams.into_inner().unwrap_or_default();
+        cache.record_alt_streams(alt_streams, read_everything);
+    }
+
+    if state.xattrs && read_from_mft {
+        eprintln!("warning: --xattrs needs the directory walk; extended attributes were not recorded with --mft");
+    } else if state.xattrs {
+        let found_xattrs = state.found_xattrs.into_inner().unwrap_or_default();
+        cache.record_xattrs(found_xattrs, read_everything);
+    }
+
+    if state.owners && read_from_mft {
+        eprintln!("warning: --owners needs the directory walk; file owners were not recorded with --mft");
+    } else if state.owners {
+        let owner_usage = state.owner_usage.into_inner().unwrap_or_default();
+        cache.record_owner_usage(owner_usage, read_everything);
+    }
+
+    if state.extensions && read_from_mft {
+        eprintln!("warning: --extensions needs the directory walk; file extensions were not recorded with --mft");
+    } else if state.extensions {
+        let extension_usage = state.extension_usage.into_inner().unwrap_or_default();
+        cache.record_extension_usage(extension_usage, read_everything);
+    }
+
+    if state.long && read_from_mft {
+        eprintln!("warning: --long needs the directory walk; entry metadata was not recorded with --mft");
+        cache.forget_entry_metadata_if_reread();
+    } else if state.long {
+        let entry_metadata = state.entry_metadata.into_inner().unwrap_or_default();
+        cache.record_entry_metadata(entry_metadata, read_everything);
+    } else {
+        cache.forget_entry_metadata_if_reread();
+    }
+
+    // The MFT reader records no file sizes here, so a full --mft scan leaves the list empty
+    let largest_files = state.largest_files.into_inner().unwrap_or_default();
+    cache.record_largest_files(largest_files, read_everything);
+
+    let subvolumes = state.subvolumes.into_inner().unwrap_or_default();
+    cache.record_subvolumes(subvolumes, read_everything);
+
+    let mount_points = state.mount_points.into_inner().unwrap_or_default();
+    cache.record_mount_points(mount_points, read_everything);
+    cache.one_file_system = one_file_system;
+    cache.count_links = args.counts_every_link();
+    cache.skip_rules = skip_fingerprint;
+
+    if follow_symlinks.is_some() && read_from_mft {
+        eprintln!("warning: --follow-symlinks needs the directory walk; symlinks were not followed with --mft");
+    }
+    let symlinks = state.symlinks.into_inner().unwrap_or_default();
+    cache.record_symlinks(symlinks, read_everything);
+
+    let scan_errors = state.scan_errors.into_inner().unwrap_or_default();
+    cache.record_scan_errors(scan_errors, read_everything);
+
+    // Refreshes read what an interrupted scan left, except incremental ones
+    let pending_dirs = state.pending_dirs.into_inner().unwrap_or_default();
+    if interrupted || !incremental_refresh {
+        cache.pending_dirs = pending_dirs;
+    }
+
+    // Refreshes that read only some directories keep the numbers of the last scan that read all
+    if read_everything {
+        cache.store_usage = state.store_accounting.into_usage();
+    }
 
     let cache_index_elapsed = cache_index_start.elapsed();
 
+    let spilled_dirs = cache.spilled_dirs();
     let save_start = Instant::now();
-    if !args.no_cache {
-        cache.save(&cache_path)?;
+    if !args.no_cache && !args.no_save {
+        if interrupted && keeps_complete_snapshot {
+            cache.save(&resume_path)?;
+        } else {
+            cache.save(cache_path)?;
+            DiskCache::remove_resume_point(cache_path);
+        }
     }
     let save_elapsed = save_start.elapsed();
+    if interrupted {
+        return Err(PTreeError::Interrupted {
+            saved:   state.control.progress().dirs_scanned,
+            pending: cache.pending_dirs.len(),
+        }
+        .into());
+    }
 
     // ============================================================================
     // Return Debug Info
     // ============================================================================
 
-    let total_files = cache
-        .entries
-        .get(&cache.root)
-        .map(|entry| entry.file_count)
-        .unwrap_or_else(|| cache.file_count_hint());
+    let total_files = cache.file_count_hint();
+    tracing::info!(
+        dirs_read = state.control.progress().dirs_scanned,
+        unchanged_dirs,
+        unreadable_dirs = state.unreadable_dirs.load(Ordering::Relaxed),
+        threads = num_threads,
+        traversal_ms = traversal_elapsed.as_millis() as u64,
+        index_ms = cache_index_elapsed.as_millis() as u64,
+        save_ms = save_elapsed.as_millis() as u64,
+        "scan finished"
+    );
 
     Ok(DebugInfo {
         is_first_run,
@@ -304,340 +886,1186 @@ fn traverse_disk_with_filter(
         traversal_time: traversal_elapsed,
         save_time: save_elapsed,
         cache_index_time: cache_index_elapsed,
-        total_dirs: cache.entries.len(),
+        total_dirs: cache.entries.len() + spilled_dirs,
         total_files,
         threads_used: num_threads,
+        unreadable_dirs: state.unreadable_dirs.load(Ordering::Relaxed),
+        prefetched: 0,
+        spilled_dirs,
+        peak_queue_depth: state.work_queue.peak_depth(),
+        stolen_dirs: state.work_queue.stolen(),
+        read_from_mft,
+        skipped_slow_dirs: skipped_slow.len(),
+        used_uring: !read_from_mft && state.engine == ScanEngine::Uring,
+        unchanged_dirs,
     })
 }
 
-/// Worker thread for DFS traversal
-///
-/// Each worker thread:
-/// 1. Pulls directories from shared work queue
-/// 2. Acquires per-directory lock to prevent duplicate processing
-/// 3. Enumerates directory, filters skipped entries
-/// 4. For incremental updates: only process directories in changed_dirs_filter
-/// 5. Buffers children in cache and queues directories for processing
-fn dfs_worker(
-    work_queue: &Arc<Mutex<VecDeque<PathBuf>>>,
-    cache: &Arc<RwLock<DiskCache>>,
-    skip_dirs: &std::collections::HashSet<String>,
-    in_progress: &Arc<Mutex<std::collections::HashSet<PathBuf>>>,
-    changed_dirs_filter: &Option<std::collections::HashSet<PathBuf>>,
-    scan_root: &PathBuf,
-    skip_stats: &Arc<Mutex<std::collections::HashMap<String, usize>>>,
-) {
-    // Thread-local buffers to batch cache writes and reduce lock contention
-    let mut entry_buffer: Vec<(PathBuf, DirEntry)> = Vec::with_capacity(500);
-    let mut skip_buffer: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
-    let flush_threshold = 500;
-
-    loop {
-        // ====================================================================
-        // Batch Work Stealing: Grab multiple directories at once (not just 1)
-        // Reduces lock contention on work_queue significantly
-        // ====================================================================
-
-        let batch = {
-            let mut queue = work_queue.lock().unwrap();
-            let mut batch = Vec::new();
-            for _ in 0..10 {
-                // Grab up to 10 items in single lock
-                if let Some(path) = queue.pop_front() {
-                    batch.push(path);
-                } else {
-                    break;
-                }
-            }
-            batch
-        };
-
-        if batch.is_empty() {
-            // Flush remaining buffers before exiting
-            if !entry_buffer.is_empty() {
-                let mut cache_guard = cache.write();
-                for (p, e) in entry_buffer.drain(..) {
-                    cache_guard.add_entry(p, e);
-                }
-            }
-            if !skip_buffer.is_empty() {
-                let mut stats = skip_stats.lock().unwrap();
-                for (name, count) in skip_buffer.drain() {
-                    *stats.entry(name).or_insert(0) += count;
-                }
-            }
-            break;
+/// `--mft`: fill the cache from the MFT of each root's volume; `false` (after a warning)
+/// when that is not possible, so the caller walks the directories instead.
+#[cfg(feature = "mft")]
+fn read_mft(state: &TraversalState, scan_roots: &[PathBuf]) -> bool {
+    match crate::mft::scan(state, scan_roots) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("warning: {err}; falling back to the directory walk");
+            false
         }
+    }
+}
 
-        // Process batch of directories
-        for path in batch {
-            // ================================================================
-            // Acquire Per-Directory Lock (prevents duplicate processing)
-            // ================================================================
+#[cfg(not(feature = "mft"))]
+fn read_mft(_state: &TraversalState, _scan_roots: &[PathBuf]) -> bool {
+    eprintln!("warning: this build has no MFT support (build with --features mft); falling back to the directory walk");
+    false
+}
 
-            let acquired = {
-                let mut progress = in_progress.lock().unwrap();
-                if !progress.contains(&path) {
-                    progress.insert(path.clone());
-                    true
-                } else {
-                    false
-                }
-            };
+/// `--ads`: whether alternate data streams can be enumerated here (after a warning if not).
+#[cfg(windows)]
+fn resolve_ads(requested: bool) -> bool {
+    requested
+}
 
-            if acquired {
-                // ============================================================
-                // Check Incremental Filter (if applicable)
-                // ============================================================
+#[cfg(not(windows))]
+fn resolve_ads(requested: bool) -> bool {
+    if requested {
+        eprintln!("warning: --ads needs NTFS on Windows; alternate data streams are not recorded");
+    }
+    false
+}
 
-                let should_process = if let Some(filter) = changed_dirs_filter {
-                    // Incremental mode: only process directories in the exact affected path set
-                    filter.contains(&path) || path == *scan_root
-                } else {
-                    // Full scan mode: process all directories
-                    true
-                };
+/// `--xattrs`: whether extended attributes can be enumerated here (after a warning if not).
+#[cfg(unix)]
+fn resolve_xattrs(requested: bool) -> bool {
+    requested
+}
 
-                if should_process {
-                    // ============================================================
-                    // Enumerate Directory & Process Entries
-                    // ============================================================
-
-                    if let Ok(entries) = fs::read_dir(&path) {
-                        let mut children = Vec::new();
-                        let mut child_dirs_to_queue = Vec::new();
-                        let mut skipped = Vec::new(); // Batch skipped directories
-                        let mut direct_file_count = 0usize;
-                        let mut direct_file_size = 0u64;
-
-                        for entry_result in entries {
-                            if let Ok(entry) = entry_result {
-                                let file_name = entry.file_name();
-                                let file_name_str = file_name.to_string_lossy();
-
-                                // Skip filtered directories
-                                if should_skip(&file_name_str, skip_dirs) {
-                                    // Batch skip statistics (don't lock on every skip)
-                                    skipped.push(file_name_str.to_string());
-                                    continue;
-                                }
-
-                                let child_path = entry.path();
-                                children.push(file_name_str.to_string());
-
-                                // Check if this is a directory (avoid unnecessary metadata calls for files)
-                                match entry.file_type() {
-                                    Ok(ft) if ft.is_dir() => {
-                                        // Queue directories for processing
-                                        let should_queue = changed_dirs_filter
-                                            .as_ref()
-                                            .map(|filter| filter.contains(&child_path))
-                                            .unwrap_or(true);
-                                        if should_queue {
-                                            child_dirs_to_queue.push(child_path.clone());
-                                        }
-                                    }
-                                    Ok(ft) if ft.is_symlink() => {
-                                        // Symlinks are recorded as names only; we don't traverse them.
-                                        direct_file_count += 1;
-                                    }
-                                    Ok(_) => {
-                                        // Regular file: recorded in `children`; no cache insert needed.
-                                        direct_file_count += 1;
-                                        if let Ok(metadata) = entry.metadata() {
-                                            direct_file_size += metadata.len();
-                                        }
-                                    }
-                                    _ => {} // Couldn't get file type, skip
-                                }
-                            }
-                        }
+#[cfg(not(unix))]
+fn resolve_xattrs(requested: bool) -> bool {
+    if requested {
+        eprintln!("warning: --xattrs needs Unix or macOS; extended attributes are not recorded");
+    }
+    false
+}
 
-                        // ========================================================
-                        // Batch queue directories (reduce lock contention)
-                        // ========================================================
-                        if !child_dirs_to_queue.is_empty() {
-                            let mut queue = work_queue.lock().unwrap();
-                            for dir_path in child_dirs_to_queue {
-                                queue.push_back(dir_path);
-                            }
-                        }
+/// `--owners`: whether file owners are known here (after a warning if not).
+#[cfg(unix)]
+fn resolve_owners(requested: bool) -> bool {
+    requested
+}
 
-                        // ========================================================
-                        // Buffer file entries (thread-local, flush periodically)
-                        // (directory entries only; file names live inside `children`)
-                        // ========================================================
+#[cfg(not(unix))]
+fn resolve_owners(requested: bool) -> bool {
+    if requested {
+        eprintln!("warning: --owners needs Unix or macOS; file owners are not recorded");
+    }
+    false
+}
 
-                        // Buffer skip statistics (thread-local, flush on exit)
-                        // ========================================================
-                        for skip_name in skipped {
-                            *skip_buffer.entry(skip_name).or_insert(0) += 1;
-                        }
+/// `--one-file-system`, where devices can be told apart; elsewhere it is refused rather than
+/// ignored.
+#[cfg(unix)]
+fn resolve_one_file_system(requested: bool) -> Result<bool> {
+    Ok(requested)
+}
 
-                        // ========================================================
-                        // Skip sorting during traversal (defer to output phase)
-                        // Children list stored unsorted for now
-                        // ========================================================
-
-                        // Check if directory has hidden attribute (Windows only)
-                        let is_hidden = {
-                            #[cfg(windows)]
-                            {
-                                use std::os::windows::fs::MetadataExt;
-                                fs::metadata(&path)
-                                    .map(|m| {
-                                        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x02;
-                                        (m.file_attributes() & FILE_ATTRIBUTE_HIDDEN) != 0
-                                    })
-                                    .unwrap_or(false)
-                            }
-                            #[cfg(not(windows))]
-                            {
-                                // Unix-like: check if name starts with dot
-                                path.file_name()
-                                    .and_then(|n| n.to_str())
-                                    .map(|s| s.starts_with('.'))
-                                    .unwrap_or(false)
-                            }
-                        };
-
-                        let mut cache_guard = cache.write();
-                        cache_guard.remove_missing_child_subtrees(&path, &children);
-                        drop(cache_guard);
-
-                        let dir_entry = DirEntry {
-                            path: path.clone(),
-                            name: path
-                                .file_name()
-                                .and_then(|n| n.to_str().map(|s| s.to_string()))
-                                .unwrap_or_default(),
-                            modified: fs::metadata(&path)
-                                .and_then(|metadata| metadata.modified())
-                                .map(system_time_to_utc)
-                                .unwrap_or_else(|_| Utc::now()),
-                            content_hash: 0,
-                            file_count: direct_file_count,
-                            total_size: direct_file_size,
-                            children,
-                            is_hidden,
-                            is_dir: true,
-                        };
-
-                        // ========================================================
-                        // Buffer directory entry (thread-local, flush periodically)
-                        // Minimizes cache.write() lock acquisitions
-                        // ========================================================
-                        entry_buffer.push((path.clone(), dir_entry));
-
-                        if entry_buffer.len() >= flush_threshold {
-                            let mut cache_guard = cache.write();
-                            for (p, e) in entry_buffer.drain(..) {
-                                cache_guard.add_entry(p, e);
-                            }
-                        }
-                    }
+#[cfg(not(unix))]
+fn resolve_one_file_system(requested: bool) -> Result<bool> {
+    if requested {
+        anyhow::bail!(
+            "--one-file-system needs Unix or macOS; on Windows, junctions and mounted folders are never entered anyway"
+        );
+    }
+    Ok(false)
+}
 
-                    // ============================================================
-                    // Release Per-Directory Lock
-                    // ============================================================
+/// Whether the directory at `path` is on another device than its parent (with `parent`
+/// metadata): a mount point. Costs a `stat`, so it is only asked under `--one-file-system`.
+#[cfg(unix)]
+fn on_other_device(parent: Option<&fs::Metadata>, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
 
-                    {
-                        let mut progress = in_progress.lock().unwrap();
-                        progress.remove(&path);
-                    }
-                } else {
-                    // Directory filtered out (incremental mode): skip it
-                    {
-                        let mut progress = in_progress.lock().unwrap();
-                        progress.remove(&path);
-                    }
+    parent.is_some_and(|parent| fs::symlink_metadata(path).is_ok_and(|metadata| metadata.dev() != parent.dev()))
+}
+
+#[cfg(not(unix))]
+fn on_other_device(_parent: Option<&fs::Metadata>, _path: &Path) -> bool {
+    false
+}
+
+/// `--engine`: the engine to read with, falling back to `dfs` (after a warning) when this
+/// build or kernel cannot run the one asked for.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn resolve_engine(requested: ScanEngine) -> ScanEngine {
+    match requested {
+        ScanEngine::Uring => {
+            match UringReader::new() {
+                Ok(_) => ScanEngine::Uring,
+                Err(err) => {
+                    eprintln!("warning: io_uring is unavailable ({err}); using the dfs engine");
+                    ScanEngine::Dfs
                 }
             }
         }
+        ScanEngine::Dfs => ScanEngine::Dfs,
     }
 }
 
-fn should_skip(name: &str, skip_dirs: &std::collections::HashSet<String>) -> bool {
-    skip_dirs.iter().any(|skip| name.eq_ignore_ascii_case(skip))
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+fn resolve_engine(requested: ScanEngine) -> ScanEngine {
+    if requested == ScanEngine::Uring {
+        eprintln!(
+            "warning: this build has no io_uring engine (build with --features io-uring on Linux); using the dfs engine"
+        );
+    }
+    ScanEngine::Dfs
 }
 
-fn cache_matches_live_state(
-    cache: &mut DiskCache,
-    cache_path: &Path,
-    scan_root: &Path,
-    skip_dirs: &std::collections::HashSet<String>,
-) -> Result<bool> {
-    if !cache.entries.contains_key(scan_root) {
-        cache.load_entries_lazy(&[scan_root.to_path_buf()], cache_path)?;
+/// Run `num_threads` workers until every queued directory is processed.
+fn run_workers(state: &TraversalState, pool: &rayon::ThreadPool, num_threads: usize, scan_roots: &[PathBuf]) {
+    pool.in_place_scope(|s| {
+        for queue in state.work_queue.workers(num_threads) {
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            if state.engine == ScanEngine::Uring {
+                if let Ok(reader) = UringReader::new() {
+                    s.spawn(move |_| {
+                        uring_worker(state, queue, scan_roots, reader);
+                    });
+                    continue;
+                }
+            }
+            s.spawn(move |_| {
+                dfs_worker(state, queue, scan_roots);
+            });
+        }
+    });
+}
+
+/// Full scans save a checkpoint this often unless `--checkpoint-interval` says otherwise
+const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Save a checkpoint of the scan that started from `base` every `interval` until `done`, which
+/// the scan sets (and unparks this thread) once its workers are finished.
+fn checkpoint_while_scanning(
+    state: &TraversalState,
+    base: &DiskCache,
+    resume_path: &Path,
+    interval: Duration,
+    done: &AtomicBool,
+) {
+    let mut checkpoint = None;
+    let mut next = Instant::now() + interval;
+    while !done.load(Ordering::Acquire) {
+        let now = Instant::now();
+        if now < next {
+            std::thread::park_timeout(next - now);
+            continue;
+        }
+        if state.control.is_cancelled() {
+            return;
+        }
+        if let Err(err) = save_checkpoint(state, base, &mut checkpoint, resume_path) {
+            tracing::warn!(path = %resume_path.display(), "could not save a scan checkpoint: {err:#}");
+        }
+        next = Instant::now() + interval;
     }
+}
 
-    let Some(root_entry) = cache.get_entry(scan_root) else {
-        return Ok(false);
+/// Save what the scan has read so far at the resume point, as an incomplete snapshot whose
+/// `pending_dirs` are the directories still queued or being read; `--force` resumes from there.
+/// `checkpoint` starts as a copy of `base` and takes in only what was read since the last
+/// checkpoint, so the workers wait on the cache for no more than that.
+fn save_checkpoint(
+    state: &TraversalState,
+    base: &DiskCache,
+    checkpoint: &mut Option<DiskCache>,
+    resume_path: &Path,
+) -> Result<()> {
+    // Directories leave `in_progress` once their entry is in the cache, under its lock
+    let (read, pending_dirs) = {
+        let mut cache = state.cache.write();
+        cache.flush_pending_writes();
+        let read: Vec<(PathBuf, Option<DirEntry>)> = state
+            .read_since_checkpoint
+            .as_ref()
+            .map(|read| std::mem::take(&mut *read.lock().unwrap()))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|path| {
+                let entry = cache.entries.get(&path).cloned();
+                (path, entry)
+            })
+            .collect();
+        let pending_dirs: Vec<PathBuf> = state.in_progress.iter().map(|path| path.clone()).collect();
+        (read, pending_dirs)
     };
 
-    let live = summarize_live_directory(scan_root, skip_dirs)?;
-    Ok(root_entry.content_hash == live.content_hash
-        && root_entry.file_count == live.file_count
-        && root_entry.total_size == live.total_size)
+    let checkpoint = match checkpoint {
+        // Each checkpoint sums up the totals of the last one again
+        Some(checkpoint) => {
+            checkpoint.restore_direct_totals();
+            checkpoint
+        }
+        None => checkpoint.insert(base.clone()),
+    };
+    for (path, entry) in read {
+        match entry {
+            Some(entry) => {
+                checkpoint.remove_missing_child_subtrees(&path, &entry.children);
+                checkpoint.entries.insert(path, entry);
+            }
+            None => {
+                checkpoint.entries.remove(&path);
+            }
+        }
+    }
+    checkpoint.pending_dirs = pending_dirs;
+    checkpoint.refresh_derived_metadata();
+    checkpoint.assign_entry_ids(&state.previous_ids);
+    checkpoint.last_scan = Utc::now();
+    checkpoint.record_scan(state.scan, checkpoint.last_scan);
+    let saved = checkpoint.save(resume_path);
+    tracing::debug!(pending = checkpoint.pending_dirs.len(), ok = saved.is_ok(), "checkpoint");
+    saved
 }
 
-fn summarize_live_directory(
-    path: &Path,
-    skip_dirs: &std::collections::HashSet<String>,
-) -> Result<LiveDirectorySummary> {
-    let modified = fs::metadata(path)
-        .and_then(|metadata| metadata.modified())
-        .map(system_time_to_utc)
-        .unwrap_or_else(|_| Utc::now());
+/// The snapshot an interrupted scan left at `resume_path`, if there is one to resume.
+fn open_resume_point(resume_path: &Path) -> Option<DiskCache> {
+    if !resume_path.with_extension("idx").exists() {
+        return None;
+    }
+    match DiskCache::open(resume_path) {
+        Ok(partial) if partial.is_incomplete() => Some(partial),
+        Ok(_) => None,
+        Err(err) => {
+            tracing::warn!(path = %resume_path.display(), "could not read the resume point: {err:#}");
+            None
+        }
+    }
+}
 
-    let mut children = Vec::new();
-    let mut child_hashes = std::collections::HashMap::new();
-    let mut file_count = 0usize;
-    let mut total_size = 0u64;
+/// Queue directories for any worker, skipping those already queued or being processed.
+fn queue_shared(state: &TraversalState, paths: impl IntoIterator<Item = PathBuf>) {
+    let claimed: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| state.in_progress.insert(path.clone()))
+        .collect();
+    state.work_queue.push_shared(claimed);
+}
 
-    for entry_result in fs::read_dir(path)? {
-        let entry = entry_result?;
-        let name = entry.file_name().to_string_lossy().to_string();
-        if should_skip(&name, skip_dirs) {
-            continue;
+/// Worker thread for DFS traversal
+///
+/// Each worker thread:
+/// 1. Pops directories from its own deque, stealing from the others when it runs dry
+/// 2. Enumerates directory, filters skipped entries (names, marker files and, with --gitignore, ignore files)
+/// 3. For incremental updates: only process directories in changed_dirs_filter
+/// 4. Claims subdirectories in the per-directory lock set (prevents duplicate processing) and
+///    queues them on its own deque
+/// 5. Buffers entries for the cache and flushes them periodically
+fn dfs_worker(state: &TraversalState, queue: WorkerQueue<'_>, scan_roots: &[PathBuf]) {
+    // Thread-local buffers to batch cache writes and reduce lock contention
+    let mut buffers = WorkerBuffers::default();
+
+    while let Some(path) = queue.next() {
+        if should_read(state, &path, scan_roots) && !keep_unchanged(state, &queue, &path) {
+            let listing_start = Instant::now();
+            let listing = read_listing(state, &path);
+            scan_directory(state, &queue, path, listing, listing_start, &mut buffers);
         }
+        queue.done();
+    }
 
-        children.push(name.clone());
-        let child_path = entry.path();
-        match entry.file_type() {
-            Ok(ft) if ft.is_dir() => {
-                let child = summarize_live_directory(&child_path, skip_dirs)?;
-                file_count += child.file_count;
-                total_size += child.total_size;
-                child_hashes.insert(child_path, child.content_hash);
-            }
-            Ok(ft) if ft.is_symlink() => {
-                file_count += 1;
+    // Flush remaining buffers before exiting
+    buffers.flush(state);
+}
+
+/// Worker thread for `--engine uring`: takes up to `uring::BATCH` directories at a time and
+/// reads them together, then handles each one like `dfs_worker`.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn uring_worker(state: &TraversalState, queue: WorkerQueue<'_>, scan_roots: &[PathBuf], mut reader: UringReader) {
+    let mut buffers = WorkerBuffers::default();
+
+    while let Some(first) = queue.next() {
+        let mut batch = Vec::with_capacity(crate::uring::BATCH);
+        for path in std::iter::once(first).chain(std::iter::from_fn(|| queue.try_next())) {
+            if !should_read(state, &path, scan_roots) || keep_unchanged(state, &queue, &path) {
+                queue.done();
+            } else if state.link_depths.contains_key(&path) {
+                // Reached through a symlink, which `read_batch` does not resolve below a root
+                let listing_start = Instant::now();
+                let listing = DirListing::read(&path);
+                scan_directory(state, &queue, path, listing, listing_start, &mut buffers);
+                queue.done();
+            } else {
+                batch.push(path);
             }
-            Ok(_) => {
-                file_count += 1;
-                if let Ok(metadata) = entry.metadata() {
-                    total_size += metadata.len();
-                }
+            if batch.len() == crate::uring::BATCH {
+                break;
             }
-            Err(_) => {}
+        }
+
+        // Each directory is timed from what the batch spent on it, then on its own handling
+        let listings = reader.read_batch(&batch, &state.root_handles);
+        for (path, (listing, took)) in batch.into_iter().zip(listings) {
+            let now = Instant::now();
+            let listing_start = now.checked_sub(took).unwrap_or(now);
+            scan_directory(state, &queue, path, listing, listing_start, &mut buffers);
+            queue.done();
         }
     }
 
-    let content_hash = compute_content_hash(path, modified, &children, &child_hashes);
-    Ok(LiveDirectorySummary {
-        content_hash,
-        file_count,
-        total_size,
-    })
+    buffers.flush(state);
 }
 
-/// Expand leading '~' into the user's home directory. If expansion fails,
-/// returns the original path.
-fn expand_tilde(path: &PathBuf) -> Result<PathBuf> {
-    use std::env;
+/// Entries written to the shared cache in batches of this many
+const FLUSH_THRESHOLD: usize = 500;
 
-    if let Some(raw) = path.to_str() {
+/// A worker's directory entries and skip statistics, handed to the shared state in batches.
+#[derive(Default)]
+struct WorkerBuffers {
+    entries: Vec<(PathBuf, DirEntry)>,
+    skips:   std::collections::HashMap<String, usize>,
+}
+
+impl WorkerBuffers {
+    fn flush(&mut self, state: &TraversalState) {
+        if !self.entries.is_empty() {
+            flush_entry_buffer(state, &mut self.entries);
+        }
+        if !self.skips.is_empty() {
+            let mut stats = state.skip_stats.lock().unwrap();
+            for (name, count) in self.skips.drain() {
+                *stats.entry(name).or_insert(0) += count;
+            }
+        }
+    }
+}
+
+/// List the directory `path`, by path if a followed symlink led to it (`root_handles`
+/// resolves none below a root).
+fn read_listing(state: &TraversalState, path: &Path) -> std::io::Result<DirListing> {
+    if state.link_depths.contains_key(path) {
+        DirListing::read(path)
+    } else {
+        state.root_handles.read(path)
+    }
+}
+
+/// `--follow-symlinks` goes this many links deep unless `--max-symlink-depth` says otherwise
+const DEFAULT_MAX_SYMLINK_DEPTH: usize = 8;
+
+/// What the scan does with a symlink under `--follow-symlinks`.
+enum LinkTarget {
+    /// Read the directory it points to, found this many links deep
+    Dir(usize),
+    /// Count the file it points to
+    File(FileSize),
+    /// Keep the link as a name, as without `--follow-symlinks`
+    Kept,
+}
+
+/// `--follow-symlinks`: decide whether the symlink at `link` (in `parent`) is followed, and
+/// record it with its target. Links into a root, into a directory already entered through a
+/// link (a loop when the link is below that directory), more than `max_depth` links deep or to
+/// nothing are not.
+fn follow_symlink(state: &TraversalState, max_depth: usize, parent: &Path, link: &Path) -> LinkTarget {
+    let depth = state.link_depths.get(parent).map_or(0, |depth| *depth) + 1;
+    let (status, target) = match (fs::metadata(link), fs::canonicalize(link)) {
+        (Ok(_), Ok(canonical)) if state.canonical_roots.iter().any(|root| canonical.starts_with(root)) => {
+            (SymlinkStatus::InTree, LinkTarget::Kept)
+        }
+        (Ok(_), Ok(_)) if depth > max_depth => (SymlinkStatus::TooDeep, LinkTarget::Kept),
+        (Ok(metadata), Ok(canonical)) if metadata.is_dir() => {
+            let mut followed = state.followed_targets.lock().unwrap();
+            if followed.iter().any(|target| canonical.starts_with(target)) {
+                // Only a link inside its own target leads round in a circle
+                let inside = fs::canonicalize(parent).is_ok_and(|here| here.starts_with(&canonical));
+                let status = if inside {
+                    SymlinkStatus::Loop
+                } else {
+                    SymlinkStatus::AlreadyFollowed
+                };
+                (status, LinkTarget::Kept)
+            } else {
+                followed.push(canonical);
+                (SymlinkStatus::Followed, LinkTarget::Dir(depth))
+            }
+        }
+        (Ok(metadata), Ok(_)) => (SymlinkStatus::Followed, LinkTarget::File(FileSize::from(&metadata))),
+        _ => (SymlinkStatus::Broken, LinkTarget::Kept),
+    };
+    let symlink = Symlink {
+        target: fs::read_link(link).unwrap_or_default(),
+        status,
+    };
+    state.symlinks.lock().unwrap().push((link.to_path_buf(), symlink));
+    target
+}
+
+/// `--scan-depth`: whether `path` lies deeper below its root than the scan reads.
+fn beyond_scan_depth(state: &TraversalState, path: &Path) -> bool {
+    let Some(max_depth) = state.scan_depth else {
+        return false;
+    };
+    state
+        .scan_roots
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .map(|root| path.components().count() - root.components().count())
+        .min()
+        .is_some_and(|depth| depth > max_depth)
+}
+
+/// Whether the subdirectory `path` of a directory being read is queued: not below
+/// `--scan-depth` and, on incremental refreshes, affected or new.
+fn should_queue(state: &TraversalState, path: &Path) -> bool {
+    !beyond_scan_depth(state, path)
+        && state
+            .changed_dirs_filter
+            .as_ref()
+            .map(|filter| filter.contains(path) || is_new_directory(state, path))
+            .unwrap_or(true)
+}
+
+/// Whether a worker should read the claimed directory `path`; if not, the claim is released.
+fn should_read(state: &TraversalState, path: &Path, scan_roots: &[PathBuf]) -> bool {
+    if state.control.is_cancelled() {
+        // Cancelled: drain the queue without reading anything more
+        if state.control.is_interrupted() {
+            state.pending_dirs.lock().unwrap().push(path.to_path_buf());
+        }
+        state.in_progress.remove(path);
+        return false;
+    }
+
+    if state.skip_slow.contains(path) {
+        // Slow-listed on a scheduled refresh: its entries from the snapshot stay as they are
+        state.skipped_slow.lock().unwrap().push(path.to_path_buf());
+        state.in_progress.remove(path);
+        return false;
+    }
+
+    let should_process = if let Some(filter) = &state.changed_dirs_filter {
+        // Incremental mode: only process directories in the exact affected path set
+        filter.contains(path) || scan_roots.iter().any(|root| root == path) || is_new_directory(state, path)
+    } else {
+        // Full scan mode: process all directories
+        true
+    };
+    if !should_process {
+        // Directory filtered out (incremental mode): skip it
+        state.in_progress.remove(path);
+    }
+    should_process
+}
+
+/// Incremental mode: a directory the snapshot does not have (one moved in whole reports a
+/// single event) is read with everything below it.
+fn is_new_directory(state: &TraversalState, path: &Path) -> bool {
+    !state.cache.read().entries.contains_key(path)
+}
+
+/// Refresh without `--verify`: keep the cached entry of `path` if its mtime is the one the
+/// snapshot has, and queue its subdirectories to be checked the same way. Adding, removing or
+/// renaming an entry moves a directory's mtime; rewriting a file in place does not, so those
+/// size changes wait for the next `--verify` or `--force` scan.
+fn keep_unchanged(state: &TraversalState, queue: &WorkerQueue<'_>, path: &Path) -> bool {
+    let Some(reuse_before) = state.reuse_before else {
+        return false;
+    };
+    let modified = directory_mtime(path);
+
+    let subdirs: Vec<PathBuf> = {
+        let cache = state.cache.read();
+        let cached = cache.entries.get(path);
+        if let Some(reason) = reread_reason(cached, modified, reuse_before, cache.unsettled_dirs.contains(path)) {
+            tracing::debug!(path = %path.display(), reason, "reading the directory again");
+            return false;
+        }
+        let Some(entry) = cached else {
+            return false;
+        };
+        entry
+            .children
+            .iter()
+            .map(|name| path.join(name))
+            .filter(|child| cache.entries.get(child).is_some_and(|child| child.is_dir))
+            .collect()
+    };
+
+    // Ignore files of a kept directory still apply below it
+    state.skip_rules.enter_dir(path);
+    for subdir in subdirs {
+        if state.in_progress.insert(subdir.clone()) {
+            queue.push(subdir);
+        }
+    }
+    state.unchanged_dirs.fetch_add(1, Ordering::Relaxed);
+    state.in_progress.remove(path);
+    true
+}
+
+/// Why a refresh reads a directory again instead of keeping the entry the snapshot has for it
+/// (`cached`), given its mtime on disk; `None` if the entry is kept.
+fn reread_reason(
+    cached: Option<&DirEntry>,
+    modified: Option<DateTime<Utc>>,
+    reuse_before: DateTime<Utc>,
+    unsettled: bool,
+) -> Option<&'static str> {
+    let Some(entry) = cached else {
+        return Some("not in the snapshot");
+    };
+    let Some(modified) = modified else {
+        return Some("mtime unreadable");
+    };
+    if unsettled {
+        Some("still changing at the last scan")
+    } else if entry.modified >= reuse_before {
+        Some("cached too close to the save to trust its mtime")
+    } else if entry.modified.timestamp_micros() != modified.timestamp_micros() {
+        // The snapshot keeps microseconds
+        Some("mtime changed")
+    } else {
+        None
+    }
+}
+
+/// Process one listed directory: filter its entries, claim and queue its subdirectories,
+/// and buffer its entry for the cache.
+fn scan_directory(
+    state: &TraversalState,
+    queue: &WorkerQueue<'_>,
+    path: PathBuf,
+    listing: std::io::Result<DirListing>,
+    listing_start: Instant,
+    buffers: &mut WorkerBuffers,
+) {
+    let cache = &state.cache;
+    let skip_rules = &state.skip_rules;
+    let in_progress = &state.in_progress;
+
+    let listing = match listing {
+        Ok(listing) => listing,
+        Err(err) => {
+            note_listing_time(state, &path, listing_start);
+            state.unreadable_dirs.fetch_add(1, Ordering::Relaxed);
+            state.warnings.unreadable(&path, &err);
+            state
+                .scan_errors
+                .lock()
+                .unwrap()
+                .push((path.clone(), ScanError::new(&err)));
+
+            // ========================================================
+            // Release Per-Directory Lock
+            // ========================================================
+
+            in_progress.remove(&path);
+            return;
+        }
+    };
+
+    skip_rules.enter_dir(&path);
+    let store = StoreAccounting::detect(&path);
+    let metadata = fs::metadata(&path).ok();
+    // Btrfs and ZFS directories can hold other subvolumes, and snapshots of them
+    let volume = metadata
+        .as_ref()
+        .and_then(|metadata| skip_rules.volumes.volume_of(&path, metadata));
+
+    let mut children = Vec::new();
+    let mut child_dirs_to_queue = Vec::new();
+    let mut skipped = Vec::new(); // Batch skipped directories
+    let mut direct_file_count = 0usize;
+    let mut direct_file_size = 0u64;
+    let mut direct_allocated_size = 0u64;
+    let mut owners: BTreeMap<u32, OwnerUsage> = BTreeMap::new();
+    let mut extensions: BTreeMap<String, ExtensionUsage> = BTreeMap::new();
+    let mut found_metadata = Vec::new();
+    let mut large_files = Vec::new();
+    let link_depth = state.link_depths.get(&path).map(|depth| *depth);
+    let mut unentered_links = Vec::new();
+
+    for (index, entry) in listing.entries().iter().enumerate() {
+        let file_name_str = entry.name.to_string_lossy();
+
+        let child_path = path.join(&entry.name);
+        let is_dir = entry.kind == EntryKind::Dir;
+
+        // Skip filtered entries (names, --skip-regex, ignore files)
+        if skip_rules.should_skip(&file_name_str, &child_path, is_dir) {
+            // Batch skip statistics (don't lock on every skip)
+            skipped.push(file_name_str.to_string());
+            continue;
+        }
+        if entry.name.to_str().is_none() {
+            state.warnings.bad_name(&child_path);
+        }
+
+        // Directories opted out by a marker file are attributed to the marker
+        if is_dir {
+            if let Some(marker) = skip_rules.marker(&child_path) {
+                skipped.push(marker.to_string());
+                continue;
+            }
+            if let Some((boundary, skip)) = skip_rules.subvolume(volume, &child_path, &file_name_str) {
+                if skip {
+                    skipped.push(boundary.skip_name().to_string());
+                    continue;
+                }
+                if let Some(subvolume) = boundary.subvolume() {
+                    state.subvolumes.lock().unwrap().push((child_path.clone(), subvolume));
+                }
+            }
+        }
+
+        children.push(Name::new(&file_name_str));
+        // Directories record their own when they are read
+        if state.long && !is_dir {
+            if let Ok(metadata) = fs::symlink_metadata(&child_path) {
+                let allocated = size_on_disk(&child_path, &metadata);
+                found_metadata.push((
+                    child_path.clone(),
+                    EntryMetadata {
+                        allocated,
+                        ..EntryMetadata::from(&metadata)
+                    },
+                ));
+            }
+        }
+
+        // Check if this is a directory (avoid unnecessary metadata calls for files)
+        let file_size = match entry.kind {
+            EntryKind::Dir => {
+                // Another filesystem is mounted here: keep the name, but do not enter it
+                if state.one_file_system && on_other_device(metadata.as_ref(), &child_path) {
+                    state.mount_points.lock().unwrap().push(child_path.clone());
+                    continue;
+                }
+                // Below a followed symlink, as deep as the link
+                if let Some(depth) = link_depth {
+                    state.link_depths.insert(child_path.clone(), depth);
+                }
+                // Queue directories for processing
+                if should_queue(state, &child_path) {
+                    child_dirs_to_queue.push(child_path.clone());
+                }
+                None
+            }
+            EntryKind::Symlink => {
+                // Without --follow-symlinks, symlinks are recorded as names only
+                let target = state
+                    .follow_symlinks
+                    .map(|max_depth| follow_symlink(state, max_depth, &path, &child_path));
+                match target {
+                    Some(LinkTarget::Dir(depth)) => {
+                        state.link_depths.insert(child_path.clone(), depth);
+                        if should_queue(state, &child_path) {
+                            child_dirs_to_queue.push(child_path.clone());
+                        }
+                        None
+                    }
+                    Some(LinkTarget::File(file)) => {
+                        direct_file_count += 1;
+                        unentered_links.push(child_path.clone());
+                        Some(file)
+                    }
+                    Some(LinkTarget::Kept) | None => {
+                        direct_file_count += 1;
+                        unentered_links.push(child_path.clone());
+                        None
+                    }
+                }
+            }
+            EntryKind::File => {
+                // Regular file: recorded in `children`; no cache insert needed.
+                direct_file_count += 1;
+                if state.ads {
+                    note_alt_streams(state, &child_path);
+                }
+                if state.xattrs {
+                    note_xattrs(state, &child_path);
+                }
+                listing.file_size(index)
+            }
+            EntryKind::Unknown => None, // Couldn't get file type, skip
+        };
+        if let Some(file) = file_size {
+            let (counted, first) = state.hard_links.account(&child_path, file);
+            if let Some(store) = &store {
+                let file = FileSize {
+                    hard_link: counted.hard_link,
+                    ..file
+                };
+                state.store_accounting.account_file(store, file, first);
+            }
+            direct_file_size += counted.len;
+            direct_allocated_size += counted.allocated;
+            if state.extensions {
+                let extension = file_extension(&file_name_str);
+                let usage = extensions.entry(extension).or_insert_with_key(|extension| {
+                    ExtensionUsage {
+                        extension: extension.clone(),
+                        ..ExtensionUsage::default()
+                    }
+                });
+                usage.files += 1;
+                usage.bytes += counted.len;
+            }
+            if file.len > 0 {
+                large_files.push(LargeFile {
+                    path: child_path.clone(),
+                    size: file.len,
+                });
+            }
+            if let (true, Some(uid)) = (state.owners, file.owner) {
+                let owned = owners.entry(uid).or_insert(OwnerUsage {
+                    uid,
+                    ..OwnerUsage::default()
+                });
+                owned.files += 1;
+                owned.bytes += counted.len;
+            }
+        }
+    }
+    note_listing_time(state, &path, listing_start);
+    state
+        .control
+        .record_directory(&path, direct_file_count, direct_file_size);
+    if state.ads {
+        note_alt_streams(state, &path);
+    }
+    if state.xattrs {
+        note_xattrs(state, &path);
+    }
+    if state.long {
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            found_metadata.push((path.clone(), EntryMetadata::from(&metadata)));
+        }
+        state.entry_metadata.lock().unwrap().append(&mut found_metadata);
+    }
+    // The directory counts toward its own owner's directories
+    if let (true, Some(uid)) = (state.owners, metadata.as_ref().and_then(crate::dir_listing::owner)) {
+        owners
+            .entry(uid)
+            .or_insert(OwnerUsage {
+                uid,
+                ..OwnerUsage::default()
+            })
+            .directories += 1;
+    }
+    if !owners.is_empty() {
+        state
+            .owner_usage
+            .lock()
+            .unwrap()
+            .push((path.clone(), owners.into_values().collect()));
+    }
+    if !extensions.is_empty() {
+        state
+            .extension_usage
+            .lock()
+            .unwrap()
+            .push((path.clone(), extensions.into_values().collect()));
+    }
+    if !large_files.is_empty() {
+        let mut largest_files = state.largest_files.lock().unwrap();
+        largest_files.append(&mut large_files);
+        if largest_files.len() > 2 * LARGEST_FILES_KEPT {
+            keep_largest_files(&mut largest_files);
+        }
+    }
+
+    // ========================================================
+    // Claim and queue directories (only their shards are locked)
+    // ========================================================
+    for dir_path in child_dirs_to_queue {
+        if in_progress.insert(dir_path.clone()) {
+            queue.push(dir_path);
+        }
+    }
+
+    // ========================================================
+    // Buffer file entries (thread-local, flush periodically)
+    // (directory entries only; file names live inside `children`)
+    // ========================================================
+
+    // Buffer skip statistics (thread-local, flush on exit)
+    // ========================================================
+    for skip_name in skipped {
+        *buffers.skips.entry(skip_name).or_insert(0) += 1;
+    }
+
+    // ========================================================
+    // Skip sorting during traversal (defer to output phase)
+    // Children list stored unsorted for now
+    // ========================================================
+
+    // Check if directory has hidden attribute (Windows only)
+    let is_hidden = {
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            fs::metadata(&path)
+                .map(|m| {
+                    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x02;
+                    (m.file_attributes() & FILE_ATTRIBUTE_HIDDEN) != 0
+                })
+                .unwrap_or(false)
+        }
+        #[cfg(not(windows))]
+        {
+            // Unix-like: check if name starts with dot
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|s| s.starts_with('.'))
+                .unwrap_or(false)
+        }
+    };
+
+    let mut cache_guard = cache.write();
+    cache_guard.remove_missing_child_subtrees(&path, &children);
+    // Symlinks an earlier scan followed and this one did not
+    for link in &unentered_links {
+        if cache_guard.entries.contains_key(link) {
+            cache_guard.remove_entry(link);
+        }
+    }
+    drop(cache_guard);
+
+    let dir_entry = DirEntry {
+        path: path.clone(),
+        name: path
+            .file_name()
+            .and_then(|n| n.to_str().map(Name::new))
+            .unwrap_or_default(),
+        modified: metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok())
+            .map(system_time_to_utc)
+            .unwrap_or(DateTime::UNIX_EPOCH),
+        content_hash: 0,
+        file_count: direct_file_count,
+        total_size: direct_file_size,
+        allocated_size: direct_allocated_size,
+        children,
+        is_hidden,
+        is_dir: true,
+        id: 0,
+        file_id: metadata.as_ref().map(file_id).unwrap_or(0),
+        scan: state.scan,
+    };
+
+    if let Some(window) = state.settle {
+        if Utc::now().signed_duration_since(dir_entry.modified) < window {
+            state
+                .recently_modified
+                .lock()
+                .unwrap()
+                .push((path.clone(), dir_entry.modified));
+        }
+    }
+
+    // ========================================================
+    // Buffer directory entry (thread-local, flush periodically)
+    // Minimizes cache.write() lock acquisitions
+    // ========================================================
+    buffers.entries.push((path.clone(), dir_entry));
+
+    // The per-directory lock is released once the entry is in the cache
+    if buffers.entries.len() >= FLUSH_THRESHOLD {
+        flush_entry_buffer(state, &mut buffers.entries);
+    }
+}
+
+/// Keep the time it took to list `path` if it was slow or the directory was slow before
+/// (a fast listing takes it off the slow list).
+fn note_listing_time(state: &TraversalState, path: &Path, started: Instant) {
+    let millis = started.elapsed().as_millis() as u64;
+    if millis >= SLOW_DIR_MILLIS || state.known_slow.contains(path) {
+        state.slow_listings.lock().unwrap().push((path.to_path_buf(), millis));
+    }
+}
+
+/// `--ads`: keep the named alternate data streams of `path`, if it has any.
+#[cfg(windows)]
+fn note_alt_streams(state: &TraversalState, path: &Path) {
+    let streams = crate::ads::alternate_streams(path);
+    if !streams.is_empty() {
+        state.alt_streams.lock().unwrap().push((path.to_path_buf(), streams));
+    }
+}
+
+#[cfg(not(windows))]
+fn note_alt_streams(_state: &TraversalState, _path: &Path) {}
+
+/// `--xattrs`: keep the extended attributes of `path`, if it has any.
+#[cfg(unix)]
+fn note_xattrs(state: &TraversalState, path: &Path) {
+    let attrs = crate::xattrs::extended_attributes(path);
+    if !attrs.is_empty() {
+        state.found_xattrs.lock().unwrap().push((path.to_path_buf(), attrs));
+    }
+}
+
+#[cfg(not(unix))]
+fn note_xattrs(_state: &TraversalState, _path: &Path) {}
+
+/// Move a worker's buffered entries into the shared cache and release their directories;
+/// under `--max-memory`, spill what is fully scanned once the entries outgrow the budget.
+fn flush_entry_buffer(state: &TraversalState, entry_buffer: &mut Vec<(PathBuf, DirEntry)>) {
+    let mut cache_guard = state.cache.write();
+    let mut read_since_checkpoint = state.read_since_checkpoint.as_ref().map(|read| read.lock().unwrap());
+    for (p, e) in entry_buffer.drain(..) {
+        state.in_progress.remove(&p);
+        if let Some(read) = read_since_checkpoint.as_mut() {
+            read.push(p.clone());
+        }
+        cache_guard.add_entry(p, e);
+    }
+    drop(read_since_checkpoint);
+
+    if state.spill_cold_subtrees && cache_guard.over_memory_budget() {
+        spill_cold_subtrees(state, &mut cache_guard);
+    }
+}
+
+/// Spill every subtree no queued or in-progress directory is inside, keeping its topmost
+/// directory (whose parent still has work left) in memory.
+///
+/// `in_progress` is read shard by shard while workers keep going, but directories only leave
+/// it under the cache lock held here (or with no children claimed), and a directory is only
+/// claimed while its parent is still in the set, so every open subtree is seen as open.
+fn spill_cold_subtrees(state: &TraversalState, cache: &mut DiskCache) {
+    cache.flush_pending_writes();
+    let roots: Vec<PathBuf> = {
+        let pending: Vec<PathBuf> = state.in_progress.iter().map(|path| path.clone()).collect();
+        let mut open: std::collections::HashSet<&Path> = std::collections::HashSet::new();
+        for pending in &pending {
+            for ancestor in pending.ancestors() {
+                if !open.insert(ancestor) {
+                    break;
+                }
+            }
+        }
+
+        cache
+            .entries
+            .iter()
+            .filter(|(path, entry)| {
+                entry.scan == state.scan
+                    && !open.contains(path.as_path())
+                    && path.parent().is_some_and(|parent| open.contains(parent))
+            })
+            .map(|(path, _)| path.clone())
+            .collect()
+    };
+
+    if let Err(err) = cache.spill_subtrees(&roots, &state.previous_ids) {
+        tracing::warn!("could not spill to the cache directory ({err}); continuing without --max-memory");
+        cache.max_memory_bytes = None;
+    }
+}
+
+/// `--settle`: wait out the settle window once, rescan directories whose mtime moved
+/// meanwhile, and return those that are still changing after the rescan.
+fn settle_recently_modified(
+    state: &TraversalState,
+    pool: &rayon::ThreadPool,
+    num_threads: usize,
+    scan_roots: &[PathBuf],
+) -> std::collections::HashSet<PathBuf> {
+    let Some(window) = state.settle else {
+        return std::collections::HashSet::new();
+    };
+    let recent = std::mem::take(&mut *state.recently_modified.lock().unwrap());
+    if recent.is_empty() {
+        return std::collections::HashSet::new();
+    }
+
+    std::thread::sleep(window.to_std().unwrap_or_default());
+
+    let changed: Vec<PathBuf> = recent
+        .into_iter()
+        .filter(|(path, seen)| directory_mtime(path) != Some(*seen))
+        .map(|(path, _)| path)
+        .collect();
+    if changed.is_empty() {
+        return std::collections::HashSet::new();
+    }
+
+    // Rescan each changed directory (and anything new below it) with the same workers
+    state.cache.write().flush_pending_writes();
+    queue_shared(state, changed.iter().cloned());
+    run_workers(state, pool, num_threads, scan_roots);
+    state.recently_modified.lock().unwrap().clear();
+
+    let now = Utc::now();
+    changed
+        .into_iter()
+        .filter(|path| directory_mtime(path).is_some_and(|modified| now.signed_duration_since(modified) < window))
+        .collect()
+}
+
+fn directory_mtime(path: &Path) -> Option<chrono::DateTime<Utc>> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(system_time_to_utc)
+        .ok()
+}
+
+fn cache_matches_live_state(
+    cache: &mut DiskCache,
+    cache_path: &Path,
+    scan_root: &Path,
+    live: &LiveDirectorySummary,
+) -> Result<bool> {
+    if !cache.entries.contains_key(scan_root) {
+        cache.load_entries_lazy(&[scan_root.to_path_buf()], cache_path)?;
+    }
+
+    let Some(root_entry) = cache.get_entry(scan_root) else {
+        tracing::info!(root = %scan_root.display(), "not using the snapshot: it has no entry for the root");
+        return Ok(false);
+    };
+
+    let matches = root_entry.content_hash == live.content_hash
+        && root_entry.file_count == live.file_count
+        && root_entry.total_size == live.total_size;
+    if !matches {
+        tracing::info!(
+            root = %scan_root.display(),
+            cached_files = root_entry.file_count,
+            live_files = live.file_count,
+            cached_bytes = root_entry.total_size,
+            live_bytes = live.total_size,
+            "not using the snapshot: the disk changed since"
+        );
+    }
+    Ok(matches)
+}
+
+/// Why this run cannot be served from the snapshot without looking at the disk, if it can.
+///
+/// --no-cache and --force always trigger a rescan, incremental refresh must rescan affected
+/// directories immediately, and the first run always scans. A reduced (directory-only,
+/// disk-full fallback) or trimmed (`--cache-max-size`) snapshot is never served as-is, nor
+/// one that did or did not stop at mount points when this scan would not, or whose sizes
+/// counted hard links differently.
+/// Snapshots with followed symlinks, and scans that follow them, always read the tree: the
+/// live check does not follow links.
+fn rescan_reason(
+    args: &Args,
+    cache: &DiskCache,
+    incremental_refresh: bool,
+    is_first_run: bool,
+    one_file_system: bool,
+    follow_symlinks: bool,
+    skip_rules: u64,
+) -> Option<&'static str> {
+    if args.no_cache {
+        Some("--no-cache")
+    } else if args.force {
+        Some("--force")
+    } else if incremental_refresh {
+        Some("incremental refresh")
+    } else if is_first_run {
+        Some("no snapshot yet")
+    } else if cache.is_reduced_snapshot() {
+        Some("reduced snapshot")
+    } else if cache.snapshot_trim().is_trimmed() {
+        Some("snapshot trimmed to --cache-max-size")
+    } else if cache.is_incomplete() {
+        Some("incomplete snapshot")
+    } else if cache.one_file_system != one_file_system {
+        Some("--one-file-system differs from the snapshot")
+    } else if follow_symlinks {
+        Some("--follow-symlinks")
+    } else if !cache.symlinks.is_empty() {
+        Some("the snapshot followed symlinks")
+    } else if cache.count_links != args.counts_every_link() {
+        Some("--count-links differs from the snapshot")
+    } else if cache.skip_rules != skip_rules {
+        Some("the skip rules differ from the snapshot's")
+    } else if args.long && cache.metadata_scanned.is_none() {
+        Some("--long needs metadata the snapshot does not have")
+    } else {
+        None
+    }
+}
+
+fn summarize_live_directory(path: &Path, skip_rules: &SkipRules, links: &HardLinks) -> Result<LiveDirectorySummary> {
+    skip_rules.enter_dir(path);
+
+    let metadata = fs::metadata(path).ok();
+    let modified = metadata
+        .as_ref()
+        .and_then(|metadata| metadata.modified().ok())
+        .map(system_time_to_utc)
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let volume = metadata
+        .as_ref()
+        .and_then(|metadata| skip_rules.volumes.volume_of(path, metadata));
+
+    let mut children = Vec::new();
+    let mut child_hashes = std::collections::HashMap::new();
+    let mut file_count = 0usize;
+    let mut total_size = 0u64;
+
+    let listing = DirListing::read(path)?;
+    for (index, entry) in listing.entries().iter().enumerate() {
+        let name = entry.name.to_string_lossy().to_string();
+        let child_path = path.join(&entry.name);
+        let is_dir = entry.kind == EntryKind::Dir;
+        if skip_rules.should_skip(&name, &child_path, is_dir)
+            || (is_dir && skip_rules.marker(&child_path).is_some())
+            || (is_dir
+                && skip_rules
+                    .subvolume(volume, &child_path, &name)
+                    .is_some_and(|(_, skip)| skip))
+        {
+            continue;
+        }
+
+        children.push(Name::new(&name));
+        match entry.kind {
+            EntryKind::Dir => {
+                // Unreadable directories stay names without an entry, as the scan leaves them
+                let Ok(child) = summarize_live_directory(&child_path, skip_rules, links) else {
+                    continue;
+                };
+                file_count += child.file_count;
+                total_size += child.total_size;
+                child_hashes.insert(child_path, child.content_hash);
+            }
+            EntryKind::Symlink => {
+                file_count += 1;
+            }
+            EntryKind::File => {
+                file_count += 1;
+                if let Some(file) = listing.file_size(index) {
+                    total_size += links.account(&child_path, file).0.len;
+                }
+            }
+            EntryKind::Unknown => {}
+        }
+    }
+
+    let content_hash = compute_content_hash(path, modified, &children, (file_count, total_size), &child_hashes);
+    Ok(LiveDirectorySummary {
+        content_hash,
+        file_count,
+        total_size,
+    })
+}
+
+/// Expand leading '~' into the user's home directory. If expansion fails,
+/// returns the original path.
+pub(crate) fn expand_tilde(path: &Path) -> Result<PathBuf> {
+    use std::env;
+
+    if let Some(raw) = path.to_str() {
         if raw == "~" || raw.starts_with("~/") || raw.starts_with("~\\") {
             let home = {
                 #[cfg(windows)]
@@ -664,7 +2092,42 @@ fn expand_tilde(path: &PathBuf) -> Result<PathBuf> {
         }
     }
 
-    Ok(path.clone())
+    Ok(path.to_path_buf())
+}
+
+/// Resolve every root to scan in this run (deduplicated, in command-line order).
+///
+/// Several `PATH` arguments or several `--drive` letters produce a multi-root scan;
+/// otherwise this is the single root from `resolve_scan_root`.
+pub fn resolve_scan_roots(drive: &char, args: &Args) -> Result<Vec<PathBuf>> {
+    let mut roots = Vec::new();
+
+    if !args.paths.is_empty() {
+        for path in &args.paths {
+            roots.push(expand_tilde(path)?);
+        }
+    } else if args.drive.len() > 1 {
+        #[cfg(windows)]
+        {
+            for letter in &args.drive {
+                let root = PathBuf::from(format!("{}:\\", letter));
+                if !root.exists() {
+                    anyhow::bail!("Drive {} does not exist", letter);
+                }
+                roots.push(root);
+            }
+        }
+
+        // Drive letters have no meaning outside Windows
+        #[cfg(not(windows))]
+        roots.push(resolve_scan_root(drive, args)?);
+    } else {
+        roots.push(resolve_scan_root(drive, args)?);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    roots.retain(|root| seen.insert(root.clone()));
+    Ok(roots)
 }
 
 fn resolve_scan_root(drive: &char, args: &Args) -> Result<PathBuf> {
@@ -675,7 +2138,7 @@ fn resolve_scan_root(drive: &char, args: &Args) -> Result<PathBuf> {
     // 1) Explicit path argument (supports ~ expansion)
     // 2) --force => full filesystem root
     // 3) Default => current working directory
-    if let Some(p) = &args.path {
+    if let Some(p) = args.paths.first() {
         expand_tilde(p)
     } else if args.force {
         #[cfg(windows)]
@@ -700,7 +2163,8 @@ fn resolve_scan_root(drive: &char, args: &Args) -> Result<PathBuf> {
 mod tests {
     use std::time::{SystemTime, UNIX_EPOCH};
 
-    use ptree_core::{ColorMode, OutputFormat};
+    use ptree_cache::NEW_DIR_FLAG_HOURS;
+    use ptree_core::{BranchStyle, Charset, ColorMode, JsonSchema, OutputFormat, SortOrder, ThemeName};
     use ptree_incremental::IncrementalChange;
 
     use super::*;
@@ -712,41 +2176,98 @@ mod tests {
 
     fn test_args(path: PathBuf) -> Args {
         Args {
-            path:                Some(path),
-            drive:               'C',
+            command:             None,
+            paths:               vec![path],
+            drive:               vec!['C'],
             admin:               false,
             force:               false,
+            verify:              false,
+            mft:                 false,
+            ads:                 false,
+            xattrs:              false,
+            owners:              false,
+            extensions:          false,
+            long:                false,
             cache_ttl:           None,
             cache_dir:           None,
             no_cache:            true,
+            no_save:             false,
+            lock_timeout:        None,
+            cache_max_size:      None,
+            max_memory:          None,
+            checkpoint_interval: None,
+            no_resume:           false,
+            cache_compress:      None,
+            cache_info:          false,
+            watch:               false,
+            serve:               false,
+            emit_events:         false,
             quiet:               true,
+            summary_only:        false,
+            progress:            None,
+            verbose:             false,
+            log_level:           None,
+            log_file:            None,
+            config:              None,
+            no_migrate:          false,
+            interactive:         false,
+            query:               None,
             format:              OutputFormat::Tree,
+            json_schema:         JsonSchema::V2,
+            output:              None,
+            no_pager:            false,
+            no_report:           false,
+            report:              false,
             color:               ColorMode::Never,
+            theme:               ThemeName::Auto,
+            accessible:          false,
             size:                false,
+            no_size:             false,
+            apparent_size:       false,
             file_count:          false,
+            charset:             Charset::Unicode,
+            style:               BranchStyle::Classic,
+            sort:                SortOrder::Name,
+            reverse:             false,
+            newer_than:          None,
+            modified_since:      None,
+            modified_before:     None,
+            min_size:            None,
+            max_size:            None,
+            compact_dirs:        false,
+            full_path:           false,
+            relative_to:         None,
             max_depth:           None,
+            limit:               None,
+            scan_depth:          None,
+            one_file_system:     false,
+            count_links:         false,
+            detect_links:        false,
+            follow_symlinks:     false,
+            max_symlink_depth:   None,
             skip:                None,
+            skip_regex:          Vec::new(),
+            gitignore:           false,
+            no_gitignore:        false,
+            no_markers:          false,
+            include_snapshots:   false,
             hidden:              false,
+            no_hidden:           false,
+            settle:              None,
             threads:             Some(1),
+            engine:              ScanEngine::Dfs,
             stats:               false,
+            metrics_textfile:    None,
             skip_stats:          false,
+            errors:              false,
             scheduler:           false,
             scheduler_uninstall: false,
             scheduler_status:    false,
+            scheduled:           false,
+            include_slow:        false,
         }
     }
 
-    #[test]
-    fn test_should_skip() {
-        let mut skip = std::collections::HashSet::new();
-        skip.insert("System32".to_string());
-        skip.insert(".git".to_string());
-
-        assert!(should_skip("System32", &skip));
-        assert!(should_skip(".git", &skip));
-        assert!(!should_skip("Documents", &skip));
-    }
-
     #[test]
     fn incremental_refresh_targets_full_paths_and_prunes_stale_subtrees() -> Result<()> {
         let root = test_root("incremental_filter");
@@ -797,6 +2318,109 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn incremental_batches_match_a_fresh_scan_without_reloading_the_snapshot() -> Result<()> {
+        let root = test_root("incremental_batches");
+        for dir in ["keep/a", "gone/b/c", "gone/d"] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+        fs::write(root.join("keep/a/one.txt"), b"12345")?;
+        fs::write(root.join("gone/b/c/two.txt"), b"1234567890")?;
+
+        // The first scan saves a snapshot, which the batches must not bring back
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_dir = test_root("incremental_batches_cache");
+        let cache_path = cache_dir.join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache_path.with_extension("idx").exists());
+
+        // Like the watch: batches are applied in memory while the snapshot on disk lags behind
+        let mut apply_args = args.clone();
+        apply_args.no_save = true;
+        fs::remove_dir_all(root.join("gone"))?;
+        let deleted = [IncrementalChange::deleted(root.join("gone"), true)];
+        traverse_disk_incremental(&'C', &mut cache, &apply_args, &cache_path, &deleted)?;
+        fs::write(root.join("keep/a/three.txt"), b"123")?;
+        let created = [IncrementalChange::created(root.join("keep/a/three.txt"), false)];
+        traverse_disk_incremental(&'C', &mut cache, &apply_args, &cache_path, &created)?;
+
+        let mut fresh = DiskCache::default();
+        traverse_disk(&'C', &mut fresh, &test_args(root.clone()), &cache_dir.join("fresh.dat"))?;
+
+        let dirs = |cache: &DiskCache| {
+            let mut dirs: Vec<PathBuf> = cache.entries.keys().cloned().collect();
+            dirs.sort();
+            dirs
+        };
+        assert_eq!(dirs(&cache), dirs(&fresh));
+        let (watched, scanned) = (&cache.entries[&root], &fresh.entries[&root]);
+        assert_eq!((watched.file_count, watched.total_size), (scanned.file_count, scanned.total_size));
+        assert_eq!(watched.content_hash, scanned.content_hash);
+
+        let _ = fs::remove_dir_all(&root);
+        let _ = fs::remove_dir_all(&cache_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn gitignore_mode_skips_ignored_paths_and_records_stats() -> Result<()> {
+        let root = test_root("gitignore_mode");
+        fs::create_dir_all(root.join("target").join("debug"))?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join(".gitignore"), "target/\n*.log\n")?;
+        fs::write(root.join("src").join("main.rs"), b"fn main() {}")?;
+        fs::write(root.join("src").join("build.log"), b"noise")?;
+
+        let mut args = test_args(root.clone());
+        args.gitignore = true;
+        let cache_path = root.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        assert!(!cache.entries.contains_key(&root.join("target")));
+        assert!(!cache.entries.contains_key(&root.join("target").join("debug")));
+        let src = cache.entries.get(&root.join("src")).expect("src should be cached");
+        assert_eq!(src.children, vec!["main.rs".to_string()]);
+        assert_eq!(cache.skip_stats.get("target"), Some(&1));
+        assert_eq!(cache.skip_stats.get("build.log"), Some(&1));
+
+        let _ = fs::remove_dir_all(&root);
+        Ok(())
+    }
+
+    #[test]
+    fn marker_files_skip_directories_and_record_stats() -> Result<()> {
+        let root = test_root("marker_files");
+        fs::create_dir_all(root.join("build").join("objects"))?;
+        fs::create_dir_all(root.join("scratch"))?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join("build").join("CACHEDIR.TAG"), b"Signature: 8a477f597d28d172789f06886806bc55\n")?;
+        fs::write(root.join("scratch").join(".ptreeignore"), b"")?;
+        fs::write(root.join("src").join("main.rs"), b"fn main() {}")?;
+
+        let cache_path = root.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &test_args(root.clone()), &cache_path)?;
+
+        assert!(!cache.entries.contains_key(&root.join("build")));
+        assert!(!cache.entries.contains_key(&root.join("scratch")));
+        assert!(cache.entries.contains_key(&root.join("src")));
+        assert_eq!(cache.skip_stats.get("CACHEDIR.TAG"), Some(&1));
+        assert_eq!(cache.skip_stats.get(".ptreeignore"), Some(&1));
+
+        let mut args = test_args(root.clone());
+        args.no_markers = true;
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.entries.contains_key(&root.join("build").join("objects")));
+
+        let _ = fs::remove_dir_all(&root);
+        Ok(())
+    }
+
     #[test]
     fn warm_cache_revalidates_live_state_before_reuse() -> Result<()> {
         let root = test_root("warm_cache_validation");
@@ -831,4 +2455,679 @@ mod tests {
         let _ = fs::remove_dir_all(cache_path.parent().unwrap_or(&cache_path));
         Ok(())
     }
+
+    #[test]
+    fn refresh_keeps_directories_with_unchanged_mtime_unless_verifying() -> Result<()> {
+        let base = test_root("keep_unchanged");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("a"))?;
+        fs::create_dir_all(root.join("b"))?;
+        fs::write(root.join("a").join("x.txt"), b"x")?;
+        fs::write(root.join("b").join("y.txt"), b"y")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        // Rewriting a file in place leaves `a`'s mtime alone; adding one to `b` moves it
+        fs::write(root.join("a").join("x.txt"), b"xxxx")?;
+        fs::write(root.join("b").join("z.txt"), b"zz")?;
+
+        // As if the snapshot were saved well after the directories last changed
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.last_scan += chrono::Duration::minutes(1);
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(debug.unchanged_dirs, 2);
+        let b = cache.get_entry(&root.join("b")).unwrap();
+        assert_eq!((b.file_count, b.total_size), (2, 3));
+        assert_eq!(cache.get_entry(&root.join("a")).unwrap().total_size, 1);
+        let top = cache.get_entry(&root).unwrap();
+        assert_eq!((top.file_count, top.total_size), (3, 4));
+
+        args.verify = true;
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.last_scan += chrono::Duration::minutes(1);
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(debug.unchanged_dirs, 0);
+        assert_eq!(cache.get_entry(&root.join("a")).unwrap().total_size, 4);
+        assert_eq!(cache.get_entry(&root).unwrap().total_size, 7);
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn rescan_reasons_say_why_the_snapshot_or_a_directory_is_read() {
+        let mut args = test_args(PathBuf::from("/data"));
+        args.no_cache = false;
+        let cache = DiskCache::default();
+        let reason = |args: &Args, is_first_run| rescan_reason(args, &cache, false, is_first_run, false, false, 0);
+        assert_eq!(reason(&args, false), None);
+        assert_eq!(reason(&args, true), Some("no snapshot yet"));
+        args.force = true;
+        assert_eq!(reason(&args, false), Some("--force"));
+        args.force = false;
+        assert_eq!(
+            rescan_reason(&args, &cache, false, false, false, false, 7),
+            Some("the skip rules differ from the snapshot's")
+        );
+
+        // A refresh keeps a directory only while its mtime is the one the snapshot has
+        let saved = Utc::now();
+        let modified = saved - chrono::Duration::hours(1);
+        let entry = DirEntry {
+            modified,
+            ..ptree_cache::testing::dir("/data/src", 0, &[])
+        };
+        let reuse_before = saved - chrono::Duration::seconds(2);
+        assert_eq!(reread_reason(Some(&entry), Some(modified), reuse_before, false), None);
+        assert_eq!(
+            reread_reason(Some(&entry), Some(modified + chrono::Duration::seconds(5)), reuse_before, false),
+            Some("mtime changed")
+        );
+        assert_eq!(reread_reason(None, Some(modified), reuse_before, false), Some("not in the snapshot"));
+        assert_eq!(reread_reason(Some(&entry), None, reuse_before, false), Some("mtime unreadable"));
+        assert_eq!(
+            reread_reason(Some(&entry), Some(modified), reuse_before, true),
+            Some("still changing at the last scan")
+        );
+        assert_eq!(
+            reread_reason(Some(&entry), Some(modified), modified, false),
+            Some("cached too close to the save to trust its mtime")
+        );
+    }
+
+    #[test]
+    fn a_refresh_under_other_skip_rules_reads_the_directories_it_would_keep() -> Result<()> {
+        let base = test_root("skip_rules_changed");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("app").join("node_modules").join("left-pad"))?;
+        fs::write(root.join("app").join("node_modules").join("left-pad").join("index.js"), b"x")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let node_modules = root.join("app").join("node_modules");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.entries.contains_key(&node_modules));
+        // As if the snapshot were saved well after the directories last changed
+        cache.last_scan += chrono::Duration::minutes(1);
+        cache.save(&cache_path)?;
+
+        // No mtime moved, yet the kept `app` listed what --skip now leaves out
+        args.skip = Some("node_modules".to_string());
+        let mut cache = DiskCache::open(&cache_path)?;
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(debug.unchanged_dirs, 0);
+        assert!(!cache.entries.contains_key(&node_modules));
+        assert!(!cache.entries[&root.join("app")]
+            .children
+            .iter()
+            .any(|name| name == "node_modules"));
+
+        // And back: the snapshot without it cannot be kept either
+        args.skip = None;
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.entries.contains_key(&node_modules.join("left-pad")));
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn a_scan_without_long_that_reads_directories_forgets_the_long_records() -> Result<()> {
+        let base = test_root("long_records_stale");
+        let root = base.join("tree");
+        fs::create_dir_all(&root)?;
+        fs::write(root.join("old.txt"), b"old")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        args.long = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.entry_metadata.contains_key(&root.join("old.txt")));
+
+        // A plain scan lists the new file but records nothing about it
+        fs::write(root.join("new.txt"), b"new")?;
+        args.long = false;
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.metadata_scanned.is_none());
+
+        // So the next --long scan reads again instead of showing it without metadata
+        args.long = true;
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.entry_metadata.contains_key(&root.join("new.txt")));
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn invalidated_subtree_is_read_again_while_the_rest_is_kept() -> Result<()> {
+        let base = test_root("invalidate");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("a").join("deep"))?;
+        fs::create_dir_all(root.join("b"))?;
+        fs::write(root.join("a").join("deep").join("x.txt"), b"x")?;
+        fs::write(root.join("b").join("y.txt"), b"y")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        // Rewritten in place, the file moves no directory's mtime: a refresh would keep `a` stale
+        fs::write(root.join("a").join("deep").join("x.txt"), b"xxxx")?;
+        assert_eq!(DiskCache::invalidate(&cache_path, &root.join("a"))?, 2);
+        assert_eq!(DiskCache::invalidate(&cache_path, &root.join("c"))?, 0);
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(cache.get_entry(&root).unwrap().total_size, 1);
+        assert!(cache.get_entry(&root.join("a")).is_none() && cache.get_entry(&root.join("b")).is_some());
+        assert!(cache.is_incomplete());
+        // As if the snapshot were saved well after the directories last changed
+        cache.last_scan += chrono::Duration::minutes(1);
+        cache.save(&cache_path)?;
+
+        let mut cache = DiskCache::open(&cache_path)?;
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(!debug.cache_used && !cache.is_incomplete());
+        assert_eq!(debug.unchanged_dirs, 2);
+        assert_eq!(cache.get_entry(&root.join("a").join("deep")).unwrap().total_size, 4);
+        let top = cache.get_entry(&root).unwrap();
+        assert_eq!((top.file_count, top.total_size), (2, 5));
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn rescan_flags_large_directories_missing_from_previous_snapshot() -> Result<()> {
+        let base = test_root("new_dirs");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("old"))?;
+        fs::write(root.join("old").join("a.txt"), b"a")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        args.force = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.new_dirs.is_empty());
+
+        // A sparse file gives the new directory its size without writing a gigabyte
+        fs::create_dir_all(root.join("drop").join("inner"))?;
+        fs::File::create(root.join("drop").join("inner").join("blob.bin"))?.set_len(NEW_DIR_MIN_BYTES)?;
+        fs::create_dir_all(root.join("small"))?;
+        fs::write(root.join("small").join("b.txt"), b"b")?;
+
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        let flagged: Vec<&PathBuf> = cache.new_dirs.keys().collect();
+        assert_eq!(flagged, vec![&root.join("drop")]);
+
+        // Flags are saved with the snapshot and survive rescans for a day
+        let first_seen = cache.new_dirs[&root.join("drop")].first_seen;
+        assert_eq!(DiskCache::open(&cache_path)?.new_dirs[&root.join("drop")].size, NEW_DIR_MIN_BYTES);
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(cache.new_dirs[&root.join("drop")].first_seen, first_seen);
+
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.new_dirs.get_mut(&root.join("drop")).unwrap().first_seen =
+            first_seen - chrono::Duration::hours(NEW_DIR_FLAG_HOURS);
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.new_dirs.is_empty());
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn scheduled_refresh_keeps_slow_directories_as_last_seen() -> Result<()> {
+        let base = test_root("slow_dirs");
+        let root = base.join("tree");
+        let share = root.join("share");
+        fs::create_dir_all(share.join("project"))?;
+        fs::write(share.join("project").join("a.txt"), b"a")?;
+        fs::create_dir_all(root.join("local"))?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        args.force = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.slow_dirs.is_empty());
+
+        // As if listing `share` had taken too long on the last few scans
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.slow_dirs.insert(
+            share.clone(),
+            ptree_cache::SlowDir {
+                strikes:   ptree_cache::SLOW_DIR_STRIKES,
+                last_ms:   SLOW_DIR_MILLIS * 2,
+                last_seen: Utc::now(),
+            },
+        );
+        fs::write(share.join("project").join("b.txt"), b"b")?;
+        fs::write(root.join("local").join("c.txt"), b"c")?;
+
+        args.scheduled = true;
+        let skipped = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(skipped.skipped_slow_dirs, 1);
+        assert_eq!(cache.entries[&share.join("project")].file_count, 1);
+        assert_eq!(cache.entries[&root.join("local")].file_count, 1);
+        assert_eq!(cache.entries[&root].file_count, 2);
+        assert!(cache.build_tree_output()?.contains("share [slow]"));
+
+        // The skipped subtree is saved as it was, still on the slow list
+        let mut reopened = DiskCache::open(&cache_path)?;
+        assert!(reopened.is_slow(&share));
+        reopened.load_all_entries_lazy(&cache_path)?;
+        assert_eq!(reopened.entries[&share.join("project")].file_count, 1);
+
+        // --include-slow reads it, and the fast listing takes it off the list
+        args.include_slow = true;
+        let included = traverse_disk(&'C', &mut reopened, &args, &cache_path)?;
+        assert_eq!(included.skipped_slow_dirs, 0);
+        assert_eq!(reopened.entries[&share.join("project")].file_count, 2);
+        assert!(reopened.slow_dirs.is_empty());
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn entry_ids_survive_rescans_and_renames() -> Result<()> {
+        let base = test_root("entry_ids");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("project").join("src"))?;
+        fs::create_dir_all(root.join("docs"))?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        args.force = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        let id_of = |cache: &DiskCache, path: &Path| cache.entries[path].id;
+        let project = id_of(&cache, &root.join("project"));
+        let src = id_of(&cache, &root.join("project").join("src"));
+        let docs = id_of(&cache, &root.join("docs"));
+        assert!(project != 0 && src != 0 && docs != 0);
+        assert_ne!(project, src);
+
+        // Rename an ancestor and add a directory, then rescan from the lazily loaded snapshot
+        fs::rename(root.join("project"), root.join("renamed"))?;
+        fs::create_dir_all(root.join("fresh"))?;
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        assert_eq!(id_of(&cache, &root.join("renamed")), project);
+        assert_eq!(id_of(&cache, &root.join("renamed").join("src")), src);
+        assert_eq!(id_of(&cache, &root.join("docs")), docs);
+        let fresh = id_of(&cache, &root.join("fresh"));
+        assert!(![project, src, docs].contains(&fresh));
+
+        let found = DiskCache::find_by_id(&cache_path, src)?.expect("ID should resolve");
+        assert_eq!(found.path, root.join("renamed").join("src"));
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn no_save_scans_without_writing_the_cache() -> Result<()> {
+        let base = test_root("no_save");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("docs"))?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        args.no_save = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        assert!(cache.entries.contains_key(&root.join("docs")));
+        assert!(!cache_path.with_extension("idx").exists());
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn scan_depth_stops_queueing_below_the_limit() -> Result<()> {
+        let base = test_root("scan_depth");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("a").join("b").join("c"))?;
+        fs::write(root.join("a").join("b").join("notes.txt"), b"abc")?;
+
+        let mut args = test_args(root.clone());
+        args.scan_depth = Some(2);
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        // a/b is read (its file counts), a/b/c is only a name
+        assert_eq!(cache.entries[&root.join("a").join("b")].total_size, 3);
+        assert!(cache.entries[&root.join("a").join("b")]
+            .children
+            .iter()
+            .any(|child| child.as_str() == "c"));
+        assert!(!cache.entries.contains_key(&root.join("a").join("b").join("c")));
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_symlinks_enters_outside_targets_once() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let base = test_root("follow_symlinks");
+        let root = base.join("tree");
+        let outside = base.join("outside");
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(outside.join("lib"))?;
+        fs::write(outside.join("lib").join("data.bin"), b"12345")?;
+        symlink(&outside, root.join("ext"))?;
+        symlink(&outside, outside.join("lib").join("back"))?;
+        // A second way into the followed directory, from outside it (read after `ext` is followed)
+        symlink(outside.join("lib"), root.join("src").join("lib-too"))?;
+        symlink(root.join("src"), root.join("src-link"))?;
+        symlink(base.join("missing"), root.join("dangling"))?;
+
+        let mut args = test_args(root.clone());
+        args.follow_symlinks = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        let status = |path: PathBuf| cache.symlinks[&path].status;
+        assert_eq!(status(root.join("ext")), SymlinkStatus::Followed);
+        assert_eq!(status(root.join("ext").join("lib").join("back")), SymlinkStatus::Loop);
+        assert_eq!(status(root.join("src").join("lib-too")), SymlinkStatus::AlreadyFollowed);
+        assert_eq!(status(root.join("src-link")), SymlinkStatus::InTree);
+        assert_eq!(status(root.join("dangling")), SymlinkStatus::Broken);
+        // The outside file counts once, below the followed link
+        assert_eq!(cache.entries[&root.join("ext").join("lib")].total_size, 5);
+        assert_eq!(cache.entries[&root].total_size, 5);
+        assert!(!cache.entries.contains_key(&root.join("src-link")));
+
+        // Without the flag the links are names again
+        args.follow_symlinks = false;
+        args.force = true;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(cache.symlinks.is_empty());
+        assert!(!cache.entries.contains_key(&root.join("ext")));
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn scan_handle_reports_progress_and_cancels_without_saving() -> Result<()> {
+        let base = test_root("scan_handle");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("docs").join("notes"))?;
+        fs::write(root.join("docs").join("a.txt"), b"abc")?;
+        fs::write(root.join("b.txt"), b"de")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        args.force = true;
+        let cache_path = base.join("cache").join("ptree.dat");
+
+        let handle = crate::ScanHandle::spawn('C', DiskCache::open(&cache_path)?, args.clone(), cache_path.clone())?;
+        let outcome = handle.join()?;
+        assert!(outcome.cache.entries.contains_key(&root.join("docs").join("notes")));
+        assert_eq!(outcome.debug_info.total_dirs, 3);
+
+        let control = Arc::new(ScanControl::default());
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk_with_control(&'C', &mut cache, &args, &cache_path, &control)?;
+        assert_eq!(
+            control.progress(),
+            crate::ScanProgress {
+                dirs_scanned: 3,
+                files_seen:   2,
+                bytes_seen:   5,
+            }
+        );
+        assert!(control.current_dir().is_some_and(|dir| dir.starts_with(&root)));
+
+        // Cancelled before a worker reads anything: nothing is saved over the last snapshot
+        fs::create_dir_all(root.join("later"))?;
+        control.cancel();
+        let mut cache = DiskCache::open(&cache_path)?;
+        let err = traverse_disk_with_control(&'C', &mut cache, &args, &cache_path, &control).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PTreeError>(), Some(PTreeError::Cancelled)));
+        let mut reopened = DiskCache::open(&cache_path)?;
+        reopened.load_all_entries_lazy(&cache_path)?;
+        assert!(!reopened.entries.contains_key(&root.join("later")));
+        assert_eq!(reopened.entries.len(), 3);
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_scan_saves_what_it_read_and_the_next_run_completes_it() -> Result<()> {
+        let base = test_root("interrupted");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("a").join("deep"))?;
+        fs::write(root.join("a").join("deep").join("big.bin"), b"12345678")?;
+        fs::write(root.join("b.txt"), b"b")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+
+        // Interrupted before a worker reads anything: only the root is left to read
+        let control = Arc::new(ScanControl::default());
+        control.interrupt();
+        let mut cache = DiskCache::open(&cache_path)?;
+        let err = traverse_disk_with_control(&'C', &mut cache, &args, &cache_path, &control).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PTreeError>(), Some(PTreeError::Interrupted { saved: 0, pending: 1 })));
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.pending_dirs, vec![root.clone()]);
+
+        // An incomplete snapshot is never a cache hit; the next run reads the rest
+        let mut cache = DiskCache::open(&cache_path)?;
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(!debug.cache_used);
+        assert!(!cache.is_incomplete());
+        assert_eq!(cache.get_entry(&root).unwrap().total_size, 9);
+
+        // As if interrupted after reading `a` but not `a/deep`: the refresh keeps what it has
+        // and reads the directory left
+        cache.load_all_entries_lazy(&cache_path)?;
+        let deep = cache.entries[&root.join("a").join("deep")].clone();
+        cache.remove_entry(&deep.path);
+        for path in [root.join("a"), root.clone()] {
+            let entry = cache.entries.get_mut(&path).unwrap();
+            entry.file_count -= deep.file_count;
+            entry.total_size -= deep.total_size;
+            entry.allocated_size -= deep.allocated_size;
+        }
+        cache.pending_dirs = vec![deep.path.clone()];
+        cache.save(&cache_path)?;
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.last_scan += chrono::Duration::minutes(1);
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(debug.unchanged_dirs, 2);
+        assert!(!cache.is_incomplete());
+        assert_eq!(cache.get_entry(&root.join("a").join("deep")).unwrap().total_size, 8);
+        assert_eq!(cache.get_entry(&root).unwrap().total_size, 9);
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn force_scan_resumes_an_incomplete_snapshot_unless_told_not_to() -> Result<()> {
+        let base = test_root("force_resume");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("a").join("deep"))?;
+        fs::write(root.join("a").join("deep").join("big.bin"), b"12345678")?;
+        fs::write(root.join("b.txt"), b"b")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        // As if a checkpoint was saved after reading `a` but not `a/deep`
+        cache.load_all_entries_lazy(&cache_path)?;
+        let deep = cache.entries[&root.join("a").join("deep")].clone();
+        cache.remove_entry(&deep.path);
+        for path in [root.join("a"), root.clone()] {
+            let entry = cache.entries.get_mut(&path).unwrap();
+            entry.file_count -= deep.file_count;
+            entry.total_size -= deep.total_size;
+            entry.allocated_size -= deep.allocated_size;
+        }
+        cache.pending_dirs = vec![deep.path.clone()];
+        cache.save(&cache_path)?;
+
+        // A file added where the checkpoint already read does not show: only `a/deep` is read
+        fs::write(root.join("c.txt"), b"cc")?;
+        args.force = true;
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(!cache.is_incomplete());
+        assert_eq!(cache.get_entry(&deep.path).unwrap().total_size, 8);
+        assert_eq!(cache.get_entry(&root).unwrap().total_size, 9);
+
+        // --no-resume reads everything again
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.pending_dirs = vec![deep.path.clone()];
+        args.no_resume = true;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(cache.get_entry(&root).unwrap().total_size, 11);
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_force_scan_keeps_the_complete_snapshot_and_leaves_a_resume_point() -> Result<()> {
+        let base = test_root("resume_point");
+        let root = base.join("tree");
+        fs::create_dir_all(root.join("a"))?;
+        fs::write(root.join("a").join("x.bin"), b"1234")?;
+
+        let mut args = test_args(root.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let resume_index = DiskCache::resume_path(&cache_path).with_extension("idx");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+
+        // Interrupted before reading anything: the snapshot stays whole, what is left goes aside
+        fs::write(root.join("y.bin"), b"56")?;
+        args.force = true;
+        let control = Arc::new(ScanControl::default());
+        control.interrupt();
+        let mut cache = DiskCache::open(&cache_path)?;
+        let err = traverse_disk_with_control(&'C', &mut cache, &args, &cache_path, &control).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PTreeError>(), Some(PTreeError::Interrupted { pending: 1, .. })));
+        let mut kept = DiskCache::open(&cache_path)?;
+        kept.load_all_entries_lazy(&cache_path)?;
+        assert!(!kept.is_incomplete());
+        assert_eq!(kept.get_entry(&root).unwrap().total_size, 4);
+        assert!(resume_index.exists());
+
+        // The next --force picks up from the resume point, replaces the snapshot and drops it
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert!(!resume_index.exists());
+        let mut saved = DiskCache::open(&cache_path)?;
+        saved.load_all_entries_lazy(&cache_path)?;
+        assert!(!saved.is_incomplete());
+        assert_eq!(saved.get_entry(&root).unwrap().total_size, 6);
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_paths_scan_into_sibling_roots() -> Result<()> {
+        let base = test_root("multi_root");
+        let first = base.join("first");
+        let second = base.join("second");
+        fs::create_dir_all(first.join("docs"))?;
+        fs::create_dir_all(second.join("media"))?;
+        fs::write(first.join("docs").join("a.txt"), b"aa")?;
+        fs::write(second.join("media").join("b.bin"), b"bbbb")?;
+
+        let mut args = test_args(first.clone());
+        args.paths.push(second.clone());
+        args.no_cache = false;
+        let cache_path = base.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+
+        let debug = traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        assert_eq!(debug.total_files, 2);
+        assert_eq!(cache.top_level_roots(), vec![first.clone(), second.clone()]);
+        assert!(cache.entries.contains_key(&first.join("docs")));
+        assert!(cache.entries.contains_key(&second.join("media")));
+
+        let mut output = Vec::new();
+        cache.write_tree_output_with_options(&mut output, None, false, false)?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains(&format!("{}\n", first.display())));
+        assert!(output.contains(&format!("{}\n", second.display())));
+
+        let reopened = DiskCache::open(&cache_path)?;
+        assert_eq!(reopened.top_level_roots(), vec![first, second]);
+        assert_eq!(reopened.file_count_hint(), 2);
+
+        let _ = fs::remove_dir_all(&base);
+        Ok(())
+    }
+
+    #[test]
+    fn settle_flags_directories_still_changing_after_recheck() -> Result<()> {
+        let root = test_root("settle");
+        let busy = root.join("busy");
+        let quiet = root.join("quiet");
+        fs::create_dir_all(&busy)?;
+        fs::create_dir_all(&quiet)?;
+
+        let writer_dir = busy.clone();
+        let writer = std::thread::spawn(move || {
+            for i in 0..40 {
+                let _ = fs::write(writer_dir.join(format!("part-{i}")), b"x");
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        let mut args = test_args(root.clone());
+        args.settle = Some(150);
+        let cache_path = root.join("cache").join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path)?;
+        traverse_disk(&'C', &mut cache, &args, &cache_path)?;
+        writer.join().unwrap();
+
+        assert!(cache.unsettled_dirs.contains(&busy));
+        assert!(!cache.unsettled_dirs.contains(&quiet));
+
+        let _ = fs::remove_dir_all(&root);
+        Ok(())
+    }
 }
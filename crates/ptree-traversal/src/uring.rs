@@ -0,0 +1,340 @@
+//! `--engine uring`: an experimental io_uring traversal engine (Linux, `io-uring` feature).
+//!
+//! Each worker takes a batch of directories and opens them all with one submission, then
+//! stats the regular files of the whole batch the same way, so many directory opens and file
+//! stats are in flight per thread instead of one blocking call at a time. Mainline kernels
+//! have no getdents opcode, so each opened directory is listed with plain `getdents64` calls
+//! (the `fast-readdir` backend).
+
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::dir_listing::getdents::OPEN_FLAGS;
+use crate::dir_listing::{DirListing, EntryKind, FileSize};
+use crate::root_handles::handles::{BENEATH_FLAGS, RESOLVE};
+use crate::root_handles::RootHandles;
+
+/// Directories a worker reads together.
+pub const BATCH: usize = 64;
+/// Submission queue size; longer runs of operations are submitted in chunks of this many.
+const RING_ENTRIES: u32 = 256;
+
+/// One worker's ring.
+pub struct UringReader {
+    ring: IoUring,
+}
+
+/// What `run` got back for its operations, in order.
+struct Completions {
+    /// Each operation's result (negative errno values for failed ones, `-ECANCELED` for any
+    /// the ring never ran)
+    results:  Vec<i32>,
+    /// How long after the run started each operation completed
+    finished: Vec<Duration>,
+    /// The ring's own failure, if it stopped partway
+    failed:   Option<io::Error>,
+}
+
+impl UringReader {
+    /// Fails when the kernel has no io_uring or it is blocked (e.g. by a container's seccomp profile).
+    pub fn new() -> io::Result<Self> {
+        Ok(UringReader {
+            ring: IoUring::new(RING_ENTRIES)?,
+        })
+    }
+
+    /// List every directory of `paths`, with the sizes of their regular files fetched up front,
+    /// and how long each one took: its own open and stats from the start of their submission
+    /// to its last completion, and its own listing. Directories below a scan root are opened
+    /// relative to its handle, as `RootHandles::read` opens them.
+    pub fn read_batch(&mut self, paths: &[PathBuf], roots: &RootHandles) -> Vec<(io::Result<DirListing>, Duration)> {
+        // (directory to resolve from, path relative to it) of every directory
+        let c_paths: Vec<Option<(libc::c_int, CString)>> = paths
+            .iter()
+            .map(|path| {
+                match roots.locate(path) {
+                    Some((root, relative)) => Some((root.as_raw_fd(), relative)),
+                    None => Some((libc::AT_FDCWD, CString::new(path.as_os_str().as_bytes()).ok()?)),
+                }
+            })
+            .collect();
+        let how = types::OpenHow::new().flags(BENEATH_FLAGS as u64).resolve(RESOLVE);
+        let opens: Vec<squeue::Entry> = c_paths
+            .iter()
+            .flatten()
+            .map(|(dir, c_path)| {
+                if *dir == libc::AT_FDCWD {
+                    opcode::OpenAt::new(types::Fd(*dir), c_path.as_ptr())
+                        .flags(OPEN_FLAGS)
+                        .build()
+                } else if roots.has_openat2() {
+                    opcode::OpenAt2::new(types::Fd(*dir), c_path.as_ptr(), &how).build()
+                } else {
+                    opcode::OpenAt::new(types::Fd(*dir), c_path.as_ptr())
+                        .flags(BENEATH_FLAGS)
+                        .build()
+                }
+            })
+            .collect();
+        let completions = self.run(opens);
+        // Every directory the kernel opened is owned from here on, so none is left open
+        let opened: Vec<(Result<OwnedFd, i32>, Duration)> = completions
+            .results
+            .into_iter()
+            .zip(completions.finished)
+            .map(|(fd, finished)| {
+                // SAFETY: the kernel opened `fd` for us and nothing else owns it
+                let fd = if fd < 0 {
+                    Err(fd)
+                } else {
+                    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+                };
+                (fd, finished)
+            })
+            .collect();
+        if completions.failed.is_some() {
+            // The ring itself failed; read this batch one directory at a time
+            return paths
+                .iter()
+                .map(|path| {
+                    let started = Instant::now();
+                    (roots.read(path), started.elapsed())
+                })
+                .collect();
+        }
+
+        let mut opened = opened.into_iter();
+        let mut listings: Vec<(io::Result<DirListing>, Duration)> = c_paths
+            .iter()
+            .map(|c_path| {
+                if c_path.is_none() {
+                    return (Err(io::Error::from(io::ErrorKind::InvalidInput)), Duration::ZERO);
+                }
+                let (fd, opening) = opened.next().unwrap_or((Err(-libc::EBADF), Duration::ZERO));
+                match fd {
+                    Ok(fd) => {
+                        let started = Instant::now();
+                        let listing = DirListing::from_dir(fd);
+                        (listing, opening + started.elapsed())
+                    }
+                    Err(errno) => (Err(io::Error::from_raw_os_error(-errno)), opening),
+                }
+            })
+            .collect();
+
+        self.stat_files(&mut listings);
+        listings
+    }
+
+    /// Stat every regular file of `listings` in one run of submissions, hand each listing its
+    /// sizes and add the time until its last stat completed. Listings keep stat'ing on their
+    /// own if the ring fails.
+    fn stat_files(&mut self, listings: &mut [(io::Result<DirListing>, Duration)]) {
+        // (listing, entry index, name) of every regular file
+        let files: Vec<(usize, usize, CString)> = listings
+            .iter()
+            .enumerate()
+            .filter_map(|(listing_index, (listing, _))| Some((listing_index, listing.as_ref().ok()?)))
+            .flat_map(|(listing_index, listing)| {
+                listing
+                    .entries()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| entry.kind == EntryKind::File)
+                    .filter_map(move |(entry_index, entry)| {
+                        Some((listing_index, entry_index, CString::new(entry.name.as_bytes()).ok()?))
+                    })
+            })
+            .collect();
+        if files.is_empty() {
+            return;
+        }
+
+        // Allocated in full up front: the kernel writes through pointers into it
+        let mut buffers: Vec<MaybeUninit<libc::statx>> = vec![MaybeUninit::uninit(); files.len()];
+        let stats: Vec<squeue::Entry> = files
+            .iter()
+            .zip(buffers.iter_mut())
+            .map(|((listing_index, _, name), buffer)| {
+                let dir = listings[*listing_index]
+                    .0
+                    .as_ref()
+                    .map_or(-1, |listing| listing.dir().as_raw_fd());
+                opcode::Statx::new(types::Fd(dir), name.as_ptr(), buffer.as_mut_ptr().cast())
+                    .flags(libc::AT_SYMLINK_NOFOLLOW)
+                    .mask(libc::STATX_SIZE | libc::STATX_BLOCKS | libc::STATX_NLINK | libc::STATX_INO | libc::STATX_UID)
+                    .build()
+            })
+            .collect();
+        // Returns only once the kernel is done with `buffers` and `files`, whatever happened
+        let completions = self.run(stats);
+        if completions.failed.is_some() {
+            return;
+        }
+
+        let mut sizes: Vec<Vec<Option<FileSize>>> = listings
+            .iter()
+            .map(|(listing, _)| listing.as_ref().map_or(0, |listing| listing.entries().len()))
+            .map(|len| vec![None; len])
+            .collect();
+        let mut stating = vec![Duration::ZERO; listings.len()];
+        for (((listing_index, entry_index, _), buffer), (result, finished)) in files
+            .iter()
+            .zip(&buffers)
+            .zip(completions.results.into_iter().zip(completions.finished))
+        {
+            stating[*listing_index] = stating[*listing_index].max(finished);
+            if result < 0 {
+                continue;
+            }
+            // SAFETY: statx succeeded, so the kernel filled the buffer
+            let stat = unsafe { buffer.assume_init_ref() };
+            let device = libc::makedev(stat.stx_dev_major, stat.stx_dev_minor) as u64;
+            sizes[*listing_index][*entry_index] = Some(FileSize {
+                len:       stat.stx_size,
+                allocated: stat.stx_blocks * 512,
+                hard_link: (stat.stx_nlink > 1).then_some((device, stat.stx_ino)),
+                owner:     Some(stat.stx_uid),
+            });
+        }
+        for ((listing, took), (sizes, stating)) in listings.iter_mut().zip(sizes.into_iter().zip(stating)) {
+            if let Ok(listing) = listing {
+                listing.set_sizes(sizes);
+                *took += stating;
+            }
+        }
+    }
+
+    /// Submit `operations` in chunks the ring can hold and collect their results in order.
+    ///
+    /// Operations point into buffers the caller owns, so this returns only once the kernel is
+    /// done with every operation it was handed: when the ring fails partway, the ones already
+    /// pushed are waited for before the failure is reported.
+    fn run(&mut self, operations: Vec<squeue::Entry>) -> Completions {
+        let started = Instant::now();
+        let mut completions = Completions {
+            results:  vec![-libc::ECANCELED; operations.len()],
+            finished: vec![Duration::ZERO; operations.len()],
+            failed:   None,
+        };
+        let mut in_flight = 0;
+        'chunks: for (chunk_index, chunk) in operations.chunks(RING_ENTRIES as usize).enumerate() {
+            let base = chunk_index * RING_ENTRIES as usize;
+            {
+                let mut submission = self.ring.submission();
+                for (offset, operation) in chunk.iter().enumerate() {
+                    let operation = operation.clone().user_data((base + offset) as u64);
+                    // SAFETY: every pointer in the operation outlives this call (the caller holds them)
+                    if unsafe { submission.push(&operation) }.is_err() {
+                        completions.failed = Some(io::Error::other("io_uring submission queue is full"));
+                        break 'chunks;
+                    }
+                    in_flight += 1;
+                }
+            }
+            if let Err(err) = self.complete(&mut completions, &mut in_flight, started, false) {
+                completions.failed = Some(err);
+                break;
+            }
+        }
+        if completions.failed.is_some() {
+            // Errors of the ring no longer matter, only that nothing is left in flight
+            let _ = self.complete(&mut completions, &mut in_flight, started, true);
+        }
+        completions
+    }
+
+    /// Submit what is pushed and wait until none of the `in_flight` operations is left,
+    /// recording their results. An interrupted wait is resumed, as is any failed one with
+    /// `retry`.
+    fn complete(
+        &mut self,
+        completions: &mut Completions,
+        in_flight: &mut usize,
+        started: Instant,
+        retry: bool,
+    ) -> io::Result<()> {
+        while *in_flight > 0 {
+            match self.ring.submit_and_wait(*in_flight) {
+                Ok(_) => {}
+                Err(err) if retry || err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+            for completion in self.ring.completion() {
+                let index = completion.user_data() as usize;
+                completions.results[index] = completion.result();
+                completions.finished[index] = started.elapsed();
+                *in_flight -= 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    /// A batch lists what `DirListing::read` lists, with the same sizes; missing directories
+    /// fail on their own.
+    #[test]
+    fn batch_matches_one_at_a_time_listing() {
+        let Ok(mut reader) = UringReader::new() else {
+            eprintln!("io_uring is unavailable here; skipping");
+            return;
+        };
+        let unique = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("ptree_uring_{unique}"));
+        let mut paths = Vec::new();
+        for dir in 0..5 {
+            let path = root.join(format!("dir-{dir}"));
+            fs::create_dir_all(path.join("sub")).unwrap();
+            // More files than the ring holds, so stats go out in several chunks
+            for file in 0..100 {
+                fs::write(path.join(format!("file-{file}")), vec![0u8; dir * 100 + file]).unwrap();
+            }
+            paths.push(path);
+        }
+        paths.insert(2, root.join("missing"));
+
+        let listings = reader.read_batch(&paths, &RootHandles::open(std::slice::from_ref(&root)));
+        assert_eq!(listings.len(), paths.len());
+        assert!(listings[2].0.is_err());
+        for (path, (listing, _)) in paths.iter().zip(&listings) {
+            let Ok(listing) = listing else {
+                continue;
+            };
+            let expected = DirListing::read(path).unwrap();
+            let mut batch: Vec<_> = (0..listing.entries().len())
+                .map(|index| (listing.entries()[index].clone(), listing.file_size(index)))
+                .collect();
+            let mut single: Vec<_> = (0..expected.entries().len())
+                .map(|index| (expected.entries()[index].clone(), expected.file_size(index)))
+                .collect();
+            batch.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+            single.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+            assert_eq!(batch.len(), 101);
+            // Directories are not stat'ed ahead of time
+            let files = |listed: &[(crate::dir_listing::ListedEntry, Option<FileSize>)]| {
+                listed
+                    .iter()
+                    .filter(|(entry, _)| entry.kind == EntryKind::File)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(files(&batch), files(&single));
+        }
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,147 @@
+//! Warnings raised while scanning, grouped by kind so a tree full of unreadable directories
+//! does not flood stderr: the first few of each kind are printed as they happen, and the
+//! rest only counted and summed up once the scan is done (`--verbose` prints every one). The
+//! log (`--log-level`, `--log-file`) gets every one of them either way.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Warnings of each kind printed before the rest are only counted
+pub const SHOWN_PER_KIND: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningKind {
+    /// A directory could not be listed for lack of permission
+    PermissionDenied,
+    /// A directory disappeared between being found and being listed
+    Vanished,
+    /// A directory could not be listed for another reason
+    Unreadable,
+    /// A name that is not valid UTF-8, shown with replacement characters
+    BadName,
+}
+
+impl WarningKind {
+    fn of(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::NotFound => Self::Vanished,
+            _ => Self::Unreadable,
+        }
+    }
+
+    /// How the summary line names the warnings left unprinted
+    fn describe(self, count: usize) -> String {
+        let dirs = if count == 1 { "directory" } else { "directories" };
+        match self {
+            Self::PermissionDenied => format!("{count} more {dirs} could not be read (permission denied)"),
+            Self::Vanished => format!("{count} more {dirs} vanished during the scan"),
+            Self::Unreadable => format!("{count} more {dirs} could not be read"),
+            Self::BadName => {
+                let names = if count == 1 { "name is" } else { "names are" };
+                format!("{count} more {names} not valid UTF-8")
+            }
+        }
+    }
+}
+
+/// The warnings of one scan, shared by its workers.
+pub struct Warnings {
+    verbose: bool,
+    counts:  Mutex<BTreeMap<WarningKind, usize>>,
+}
+
+impl Warnings {
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            verbose,
+            counts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// A directory whose listing failed with `err`.
+    pub fn unreadable(&self, path: &Path, err: &io::Error) {
+        self.warn(WarningKind::of(err), || format!("{}: {err}", path.display()));
+    }
+
+    /// An entry whose name is not valid UTF-8.
+    pub fn bad_name(&self, path: &Path) {
+        self.warn(WarningKind::BadName, || {
+            format!("{}: name is not valid UTF-8; shown with replacement characters", path.display())
+        });
+    }
+
+    /// Log a warning of `kind` and count it, printing it unless enough of its kind were printed
+    /// already.
+    fn warn(&self, kind: WarningKind, message: impl FnOnce() -> String) {
+        let logged = tracing::enabled!(tracing::Level::WARN);
+        let shown = self.record(kind);
+        if !logged && !shown {
+            return;
+        }
+        let message = message();
+        if logged {
+            tracing::warn!(?kind, "{message}");
+        }
+        if shown {
+            eprintln!("warning: {message}");
+        }
+    }
+
+    /// Count a warning of `kind`; whether it should be printed.
+    fn record(&self, kind: WarningKind) -> bool {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(kind).or_insert(0);
+        *count += 1;
+        self.verbose || *count <= SHOWN_PER_KIND
+    }
+
+    /// One line per kind with warnings left unprinted.
+    pub fn summary(&self) -> Vec<String> {
+        if self.verbose {
+            return Vec::new();
+        }
+        self.counts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, &count)| count > SHOWN_PER_KIND)
+            .map(|(kind, &count)| format!("{}; --verbose lists them all", kind.describe(count - SHOWN_PER_KIND)))
+            .collect()
+    }
+
+    /// Print the summary lines, once the scan is done; the log has every warning already.
+    pub fn print_summary(&self) {
+        for line in self.summary() {
+            eprintln!("warning: {line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_beyond_the_first_few_of_a_kind_are_summed_up() {
+        let warnings = Warnings::new(false);
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let printed = (0..SHOWN_PER_KIND + 3)
+            .filter(|_| warnings.record(WarningKind::of(&denied)))
+            .count();
+        assert_eq!(printed, SHOWN_PER_KIND);
+        assert!(warnings.record(WarningKind::BadName));
+        assert!(warnings.record(WarningKind::of(&io::Error::from(io::ErrorKind::NotFound))));
+
+        assert_eq!(
+            warnings.summary(),
+            vec!["3 more directories could not be read (permission denied); --verbose lists them all"]
+        );
+
+        let verbose = Warnings::new(true);
+        assert!((0..SHOWN_PER_KIND + 3).all(|_| verbose.record(WarningKind::Unreadable)));
+        assert!(verbose.summary().is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use crossbeam_utils::Backoff;
+
+/// Work-stealing scheduler for the DFS workers.
+///
+/// Each worker pushes the subdirectories it finds onto its own deque and pops from it
+/// without locking; an idle worker takes from the shared injector (scan roots, settle
+/// rescans) and then steals from the other workers. `pending` counts directories queued or
+/// being read, so a worker only stops once it is zero: nothing is left anywhere and no
+/// running worker can queue more.
+pub struct WorkQueue {
+    injector:   Injector<PathBuf>,
+    /// Depth-first (`--max-memory`): workers pop their newest directory first
+    lifo:       bool,
+    pending:    AtomicUsize,
+    peak_depth: AtomicUsize,
+    stolen:     AtomicUsize,
+}
+
+/// One worker's end of the `WorkQueue`.
+pub struct WorkerQueue<'a> {
+    queue:    &'a WorkQueue,
+    local:    Worker<PathBuf>,
+    stealers: Arc<Vec<Stealer<PathBuf>>>,
+    index:    usize,
+}
+
+impl WorkQueue {
+    pub fn new(lifo: bool) -> Self {
+        WorkQueue {
+            injector: Injector::new(),
+            lifo,
+            pending: AtomicUsize::new(0),
+            peak_depth: AtomicUsize::new(0),
+            stolen: AtomicUsize::new(0),
+        }
+    }
+
+    /// Queue directories for whichever worker is free first.
+    pub fn push_shared(&self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            self.add_pending();
+            self.injector.push(path);
+        }
+    }
+
+    /// Fresh deques for `count` workers that can steal from each other.
+    pub fn workers(&self, count: usize) -> Vec<WorkerQueue<'_>> {
+        let locals: Vec<Worker<PathBuf>> = (0..count)
+            .map(|_| {
+                if self.lifo {
+                    Worker::new_lifo()
+                } else {
+                    Worker::new_fifo()
+                }
+            })
+            .collect();
+        let stealers = Arc::new(locals.iter().map(Worker::stealer).collect::<Vec<_>>());
+
+        locals
+            .into_iter()
+            .enumerate()
+            .map(|(index, local)| {
+                WorkerQueue {
+                    queue: self,
+                    local,
+                    stealers: Arc::clone(&stealers),
+                    index,
+                }
+            })
+            .collect()
+    }
+
+    /// Directories queued or being read right now.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Most directories that were queued or being read at once.
+    pub fn peak_depth(&self) -> usize {
+        self.peak_depth.load(Ordering::Relaxed)
+    }
+
+    /// Directories a worker took from another worker's deque.
+    pub fn stolen(&self) -> usize {
+        self.stolen.load(Ordering::Relaxed)
+    }
+
+    fn add_pending(&self) {
+        let depth = self.pending.fetch_add(1, Ordering::AcqRel) + 1;
+        self.peak_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+}
+
+impl WorkerQueue<'_> {
+    /// Queue a directory on this worker's own deque.
+    pub fn push(&self, path: PathBuf) {
+        self.queue.add_pending();
+        self.local.push(path);
+    }
+
+    /// Mark the directory last returned by `next` as finished (its subdirectories queued).
+    pub fn done(&self) {
+        self.queue.pending.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// Next directory to read: from this worker's deque, the shared injector, or another
+    /// worker. Waits while other workers may still queue more; `None` once the scan is done.
+    pub fn next(&self) -> Option<PathBuf> {
+        let backoff = Backoff::new();
+        loop {
+            if let Some(path) = self.find() {
+                return Some(path);
+            }
+            if self.queue.pending() == 0 {
+                return None;
+            }
+
+            if backoff.is_completed() {
+                thread::sleep(Duration::from_micros(50));
+            } else {
+                backoff.snooze();
+            }
+        }
+    }
+
+    /// Next directory if one is available right now, without waiting.
+    pub fn try_next(&self) -> Option<PathBuf> {
+        self.find()
+    }
+
+    fn find(&self) -> Option<PathBuf> {
+        if let Some(path) = self.local.pop() {
+            return Some(path);
+        }
+
+        loop {
+            let mut retry = false;
+            match self.queue.injector.steal_batch_and_pop(&self.local) {
+                Steal::Success(path) => return Some(path),
+                Steal::Retry => retry = true,
+                Steal::Empty => {}
+            }
+
+            for (index, stealer) in self.stealers.iter().enumerate() {
+                if index == self.index {
+                    continue;
+                }
+                match stealer.steal_batch_and_pop(&self.local) {
+                    Steal::Success(path) => {
+                        // The local deque was empty, so all it holds now came with the steal
+                        self.queue.stolen.fetch_add(1 + self.local.len(), Ordering::Relaxed);
+                        return Some(path);
+                    }
+                    Steal::Retry => retry = true,
+                    Steal::Empty => {}
+                }
+            }
+
+            if !retry {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A synthetic tree four levels deep with three subdirectories per directory; only one
+    /// worker gets the root, so the others have to steal to do anything.
+    #[test]
+    fn workers_share_the_tree_and_stop_when_it_is_done() {
+        let queue = WorkQueue::new(false);
+        queue.push_shared([PathBuf::from("/")]);
+        let seen = Mutex::new(Vec::new());
+
+        thread::scope(|s| {
+            for worker in queue.workers(4) {
+                let seen = &seen;
+                s.spawn(move || {
+                    while let Some(path) = worker.next() {
+                        if path.components().count() < 5 {
+                            for child in ["a", "b", "c"] {
+                                worker.push(path.join(child));
+                            }
+                        }
+                        seen.lock().unwrap().push(path);
+                        worker.done();
+                    }
+                });
+            }
+        });
+
+        let seen = seen.into_inner().unwrap();
+        // 1 + 3 + 9 + 27 + 81 directories, each read exactly once
+        assert_eq!(seen.len(), 121);
+        assert_eq!(seen.iter().collect::<HashSet<_>>().len(), 121);
+        assert_eq!(queue.pending(), 0);
+        assert!(queue.peak_depth() >= 3);
+    }
+}
@@ -0,0 +1,82 @@
+//! `--xattrs` (Unix, macOS): the extended attributes of files and directories, with the values
+//! of small ones. Quarantine flags (`com.apple.quarantine`), SELinux labels
+//! (`security.selinux`) and file capabilities (`security.capability`) live there.
+
+/// Extended attributes of the file or directory at `path` (symlinks are not followed); paths
+/// whose attributes cannot be listed (no support on the filesystem, permissions) have none.
+#[cfg(unix)]
+pub fn extended_attributes(path: &std::path::Path) -> Vec<ptree_cache::XAttr> {
+    let Ok(names) = xattr::list(path) else {
+        return Vec::new();
+    };
+
+    names
+        .filter_map(|name| {
+            // Attributes whose value cannot be read (removed meanwhile, not permitted) are left out
+            let value = xattr::get(path, &name).ok().flatten()?;
+            Some(ptree_cache::XAttr {
+                name:  name.to_string_lossy().into_owned(),
+                size:  value.len() as u64,
+                value: (value.len() <= ptree_cache::XATTR_VALUE_MAX).then(|| format_value(&value)),
+            })
+        })
+        .collect()
+}
+
+/// `value` as text if it is printable UTF-8 (one trailing NUL, as in SELinux labels, is
+/// dropped), otherwise as `0x`-prefixed hex.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn format_value(value: &[u8]) -> String {
+    let text = value.strip_suffix(&[0]).unwrap_or(value);
+    match std::str::from_utf8(text) {
+        Ok(text) if !text.chars().any(char::is_control) => text.to_string(),
+        _ => {
+            let hex: String = value.iter().map(|byte| format!("{byte:02x}")).collect();
+            format!("0x{hex}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printable_values_stay_text_and_binary_ones_become_hex() {
+        assert_eq!(format_value(b"system_u:object_r:bin_t:s0\0"), "system_u:object_r:bin_t:s0");
+        assert_eq!(format_value(b"0081;6530e5c1;Safari;"), "0081;6530e5c1;Safari;");
+        assert_eq!(format_value(&[0x01, 0x00, 0x00, 0x02, 0x00, 0x20]), "0x010000020020");
+        assert_eq!(format_value(b""), "");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn user_attributes_are_listed_with_small_values() {
+        use ptree_cache::XATTR_VALUE_MAX;
+
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("ptree_xattrs_{unique}"));
+        std::fs::write(&path, b"x").unwrap();
+        // Filesystems without user xattrs (some tmpfs setups) have nothing to list
+        if xattr::set(&path, "user.ptree.note", b"hello").is_ok() {
+            xattr::set(&path, "user.ptree.big", &[b'a'; XATTR_VALUE_MAX + 1]).unwrap();
+            let mut attrs = extended_attributes(&path);
+            attrs.sort_by(|a, b| a.name.cmp(&b.name));
+            let found: Vec<(&str, u64, Option<&str>)> = attrs
+                .iter()
+                .map(|attr| (attr.name.as_str(), attr.size, attr.value.as_deref()))
+                .collect();
+            assert_eq!(
+                found,
+                vec![
+                    ("user.ptree.big", XATTR_VALUE_MAX as u64 + 1, None),
+                    ("user.ptree.note", 5, Some("hello")),
+                ]
+            );
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
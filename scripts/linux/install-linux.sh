@@ -77,7 +77,7 @@ PTREE_DEBOUNCE_SECONDS="${DEFAULT_DEBOUNCE_SECONDS}"
 
 # CLI args passed to ptree on each refresh.
 # cache-ttl=30 keeps the cache fresh but avoids unnecessary full rewrites.
-PTREE_ARGS="--quiet --cache-ttl 30"
+PTREE_ARGS="--summary-only --cache-ttl 30"
 
 # Optional thread override (empty = ptree default heuristic).
 # PTREE_THREADS="1"
@@ -5,7 +5,7 @@ PTREE_BIN="${PTREE_BIN:-/usr/local/bin/ptree}"
 DEFAULT_WATCH_PATHS=(/home /etc /usr/local /opt /srv /var/lib /var/www)
 PTREE_WATCH_PATHS="${PTREE_WATCH_PATHS:-}"
 PTREE_DEBOUNCE_SECONDS="${PTREE_DEBOUNCE_SECONDS:-15}"
-PTREE_ARGS="${PTREE_ARGS:---quiet}"
+PTREE_ARGS="${PTREE_ARGS:---summary-only}"
 PTREE_THREADS="${PTREE_THREADS:-}"
 
 # Ensure cache TTL stays at 30 seconds for watcher-triggered runs.
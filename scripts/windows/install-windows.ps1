@@ -14,7 +14,7 @@
 param(
     [string]$InstallDir = "$Env:ProgramFiles\PTree",
     [switch]$RegisterScheduledTask,
-    [string]$RefreshArgs = "--quiet --cache-ttl 30"
+    [string]$RefreshArgs = "--scheduled --summary-only --cache-ttl 30"
 )
 
 if (-not $IsWindows) {
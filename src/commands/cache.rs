@@ -0,0 +1,284 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::Utc;
+use ptree_cache::{CacheDump, DiskCache, RootCacheInfo};
+use ptree_core::{Args, CacheCommand};
+
+pub fn run(action: &CacheCommand, args: &Args) -> Result<()> {
+    let cache_dir = cache_dir(args)?;
+
+    match action {
+        CacheCommand::List => list(&cache_dir),
+        CacheCommand::Compact => compact(&cache_dir),
+        CacheCommand::Verify => verify(&cache_dir),
+        CacheCommand::Lookup { id } => lookup(&cache_dir, *id),
+        CacheCommand::Stale { days } => stale(&cache_dir, *days),
+        CacheCommand::Invalidate { path } => invalidate(path, args),
+        CacheCommand::Dump { json, snapshot, paths } => dump(json.as_deref(), snapshot.as_deref(), paths, args),
+        CacheCommand::Restore { json } => restore(json, args),
+    }
+}
+
+/// Directory holding every root cache (`--cache-dir` or the platform default).
+pub(super) fn cache_dir(args: &Args) -> Result<std::path::PathBuf> {
+    let base = ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?;
+    Ok(base.parent().unwrap_or_else(|| Path::new(".")).to_path_buf())
+}
+
+fn list(cache_dir: &Path) -> Result<()> {
+    let caches = ptree_cache::list_root_caches(cache_dir)?;
+    if caches.is_empty() {
+        println!("No caches in {}", cache_dir.display());
+        return Ok(());
+    }
+
+    println!("{:<22} {:>12} {:>12} {:>10}  {:<23}  ROOT", "CACHE", "DIRS", "FILES", "SIZE", "LAST SCAN");
+    for cache in &caches {
+        print_row(cache);
+    }
+    println!("\n{} cache(s) in {}", caches.len(), cache_dir.display());
+
+    Ok(())
+}
+
+fn compact(cache_dir: &Path) -> Result<()> {
+    let caches = ptree_cache::list_root_caches(cache_dir)?;
+    if caches.is_empty() {
+        println!("No caches in {}", cache_dir.display());
+        return Ok(());
+    }
+
+    let mut reclaimed = 0;
+    for cache in &caches {
+        let name = cache.cache_path.display();
+        match DiskCache::compact(&cache.cache_path) {
+            Ok(stats) => {
+                reclaimed += stats.reclaimed();
+                println!(
+                    "{}: {} -> {} ({} records)",
+                    name,
+                    DiskCache::format_size(stats.bytes_before),
+                    DiskCache::format_size(stats.bytes_after),
+                    crate::format_number(stats.records)
+                );
+            }
+            Err(e) => eprintln!("{}: compaction failed: {}", name, e),
+        }
+    }
+    println!("\nReclaimed {} across {} cache(s)", DiskCache::format_size(reclaimed), caches.len());
+
+    Ok(())
+}
+
+fn verify(cache_dir: &Path) -> Result<()> {
+    let snapshots = ptree_cache::cache_snapshots(cache_dir)?;
+    if snapshots.is_empty() {
+        println!("No caches in {}", cache_dir.display());
+        return Ok(());
+    }
+
+    let mut corrupt_caches = 0;
+    for cache_path in &snapshots {
+        let report = match DiskCache::verify(cache_path) {
+            Ok(report) => report,
+            Err(e) => {
+                corrupt_caches += 1;
+                println!("{}: unreadable index ({})", cache_path.display(), e);
+                continue;
+            }
+        };
+
+        if report.is_ok() {
+            println!("{}: OK ({} records)", cache_path.display(), crate::format_number(report.records));
+            continue;
+        }
+
+        corrupt_caches += 1;
+        println!(
+            "{}: {} of {} records corrupt",
+            cache_path.display(),
+            crate::format_number(report.corrupt.len()),
+            crate::format_number(report.records)
+        );
+        for (path, problem) in &report.corrupt {
+            println!("    {}: {}", path.display(), problem);
+        }
+    }
+
+    if corrupt_caches > 0 {
+        anyhow::bail!(
+            "{} of {} cache(s) are corrupt; the next scan of those roots rescans them",
+            corrupt_caches,
+            snapshots.len()
+        );
+    }
+    Ok(())
+}
+
+/// IDs are unique within one cache, so every cache is searched and each match printed.
+fn lookup(cache_dir: &Path, id: u64) -> Result<()> {
+    let mut found = 0;
+    for cache_path in ptree_cache::cache_snapshots(cache_dir)? {
+        match DiskCache::find_by_id(&cache_path, id) {
+            Ok(Some(entry)) => {
+                found += 1;
+                println!(
+                    "{}: {} ({}, {} files) in {}",
+                    id,
+                    entry.path.display(),
+                    DiskCache::format_size(entry.total_size),
+                    crate::format_number(entry.file_count),
+                    cache_path.display()
+                );
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("{}: unreadable ({})", cache_path.display(), e),
+        }
+    }
+
+    if found == 0 {
+        anyhow::bail!("no entry with ID {} in {}", id, cache_dir.display());
+    }
+    Ok(())
+}
+
+/// Stale subtrees need every entry's scan, so each snapshot is loaded in full.
+fn stale(cache_dir: &Path, days: u32) -> Result<()> {
+    let snapshots = ptree_cache::cache_snapshots(cache_dir)?;
+    if snapshots.is_empty() {
+        println!("No caches in {}", cache_dir.display());
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let cutoff = now - chrono::Duration::days(days as i64);
+    for cache_path in &snapshots {
+        let mut cache = DiskCache::open(cache_path)?;
+        if let Err(e) = cache.load_all_entries_lazy(cache_path) {
+            eprintln!("{}: unreadable ({})", cache_path.display(), e);
+            continue;
+        }
+
+        let subtrees = cache.stale_subtrees(cutoff);
+        if subtrees.is_empty() {
+            println!("{}: every directory verified in the last {} days", cache_path.display(), days);
+            continue;
+        }
+
+        let directories: usize = subtrees.iter().map(|subtree| subtree.directories).sum();
+        println!(
+            "{}: {} directories in {} subtrees not verified in the last {} days",
+            cache_path.display(),
+            crate::format_number(directories),
+            crate::format_number(subtrees.len()),
+            days
+        );
+        for subtree in &subtrees {
+            let verified = match subtree.verified {
+                Some(verified) => format!("{} ({} days ago)", verified.format("%Y-%m-%d"), (now - verified).num_days()),
+                None => "before scans were numbered".to_string(),
+            };
+            println!(
+                "    {}  {} ({} directories)",
+                verified,
+                subtree.path.display(),
+                crate::format_number(subtree.directories)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Only the index and the directories above the subtree are rewritten; the next scan of its
+/// roots reads the subtree like a directory an interrupted scan left.
+fn invalidate(path: &Path, args: &Args) -> Result<()> {
+    let target = super::absolute(path)?;
+    let (cache_path, cache, records) = super::covering_cache(&target, args)?;
+    let not_cached =
+        || anyhow::anyhow!("{} is not a directory in the snapshot of {}", target.display(), cache.root.display());
+    let path =
+        super::cached_form(&cache, &target, |path| records.index.offsets.contains_key(path)).ok_or_else(not_cached)?;
+    drop(records);
+
+    // Another process may have saved since: the subtree is looked up again under the lock
+    let forgotten = DiskCache::invalidate(&cache_path, &path)?;
+    if forgotten == 0 {
+        return Err(not_cached());
+    }
+    println!(
+        "{}: forgot {} ({} cached directories); the next scan of its roots reads it again",
+        cache_path.display(),
+        path.display(),
+        crate::format_number(forgotten)
+    );
+    Ok(())
+}
+
+/// The cache is picked the way a scan of `paths` would pick it. A named snapshot is the
+/// same JSON, kept under the cache directory where `ptree show --as-of` finds it.
+fn dump(json: Option<&Path>, snapshot: Option<&str>, paths: &[PathBuf], args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let json = match (json, snapshot) {
+        (Some(json), _) => json.to_path_buf(),
+        (None, Some(name)) => {
+            let snapshots = ptree_cache::snapshots_dir(&cache_dir(args)?);
+            std::fs::create_dir_all(&snapshots)?;
+            ptree_cache::snapshot_path(&snapshots, name)?
+        }
+        (None, None) => anyhow::bail!("either --json or --snapshot is required"),
+    };
+
+    let dump = DiskCache::dump(&cache_path)?;
+    dump.write_json(&json)?;
+    println!(
+        "{}: {} entries written to {}",
+        cache_path.display(),
+        crate::format_number(dump.entries.len()),
+        json.display()
+    );
+    Ok(())
+}
+
+/// The dump's roots decide which cache it replaces, so it lands where a scan of them looks.
+fn restore(json: &Path, args: &Args) -> Result<()> {
+    let dump = CacheDump::read_json(json)?;
+    let cache_path = ptree_cache::cache_path_for_roots(
+        &ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?,
+        &dump.scan_roots(),
+    );
+
+    let entries = DiskCache::restore(&cache_path, dump)?;
+    println!(
+        "{}: restored {} entries from {}",
+        cache_path.display(),
+        crate::format_number(entries),
+        json.display()
+    );
+    Ok(())
+}
+
+fn print_row(cache: &RootCacheInfo) {
+    let name = cache
+        .cache_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let roots: Vec<String> = cache.roots.iter().map(|root| root.display().to_string()).collect();
+    let legacy = if cache.legacy {
+        " (legacy, not keyed by root)"
+    } else {
+        ""
+    };
+
+    println!(
+        "{:<22} {:>12} {:>12} {:>10}  {:<23}  {}{}",
+        name,
+        crate::format_number(cache.directories),
+        crate::format_number(cache.files),
+        DiskCache::format_size(cache.disk_size),
+        cache.last_scan.format("%Y-%m-%d %H:%M:%S UTC"),
+        roots.join(", "),
+        legacy
+    );
+}
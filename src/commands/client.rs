@@ -0,0 +1,73 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use ptree_core::{Args, ClientCall};
+use serde_json::{json, Value};
+
+use crate::server::{self, Stream};
+
+/// Send one query to the server watching `roots` (or the queried path) and print its result.
+pub fn run(roots: &[PathBuf], call: &ClientCall, args: &Args) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let (method, params, target) = match call {
+        ClientCall::Tree {
+            path,
+            depth,
+            size,
+            file_count,
+        } => {
+            let path = super::absolute(path.as_deref().unwrap_or(&current_dir))?;
+            let params = json!({
+                "path": path,
+                "depth": depth,
+                "size": size,
+                "file_count": file_count,
+                "schema": args.json_schema.to_string(),
+            });
+            ("tree", params, path)
+        }
+        ClientCall::Search { pattern, limit } => ("search", json!({ "pattern": pattern, "limit": limit }), current_dir),
+        ClientCall::Stats => ("stats", json!({}), current_dir),
+    };
+
+    let stream = find_server(roots, &target, args)?;
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let mut writer = stream.try_clone()?;
+    writeln!(writer, "{request}")?;
+    writer.flush()?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        bail!("the server closed the connection without answering");
+    }
+    let mut response: Value = serde_json::from_str(&line).context("the server sent an invalid response")?;
+    if let Some(error) = response.get("error") {
+        bail!("{} (error {})", error["message"].as_str().unwrap_or("unknown error"), error["code"]);
+    }
+    println!("{}", serde_json::to_string_pretty(&response["result"].take())?);
+    Ok(())
+}
+
+/// Connect to the server of `roots`, or without them to that of the nearest ancestor of
+/// `target` (itself included) being served.
+fn find_server(roots: &[PathBuf], target: &Path, args: &Args) -> Result<Stream> {
+    if !roots.is_empty() {
+        let cache_path = super::cache_path_for_paths(roots, args)?;
+        return server::connect(&cache_path).with_context(|| {
+            format!("no `ptree --watch --serve` is running for these roots ({})", server::endpoint(&cache_path))
+        });
+    }
+
+    for dir in target.ancestors() {
+        let cache_path = super::cache_path_for_paths(&[dir.to_path_buf()], args)?;
+        if let Ok(stream) = server::connect(&cache_path) {
+            return Ok(stream);
+        }
+    }
+    bail!(
+        "no `ptree --watch --serve` is watching {} or a directory above it; name the served roots with --root",
+        target.display()
+    )
+}
@@ -0,0 +1,154 @@
+//! `ptree completions <shell>`: the script clap_complete generates for the command line, with
+//! a hook added so path arguments also offer the roots of the caches in the cache directory
+//! (asked of `ptree cached-roots` each time, so new caches show up without reinstalling).
+
+use anyhow::{Context, Result};
+use ptree_core::{Args, Shell};
+
+/// bash: complete as the generated `_ptree` does, then add cached roots (and with them the
+/// directories that would otherwise have been offered) when the word looks like a path
+const BASH_HOOK: &str = r#"
+_ptree_with_cached_roots() {
+    _ptree "$@"
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    [[ "$cur" == /* || "$cur" == "~"* || "$cur" == [A-Za-z]:* ]] || return 0
+    local IFS=$'\n'
+    local roots=($(compgen -W "$(ptree cached-roots 2>/dev/null)" -- "$cur"))
+    if (( ${#roots[@]} )); then
+        COMPREPLY+=("${roots[@]}" $(compgen -d -- "$cur"))
+    fi
+}
+complete -F _ptree_with_cached_roots -o nosort -o bashdefault -o default ptree
+"#;
+
+/// zsh: path arguments complete with this instead of `_files`
+const ZSH_HOOK: &str = r#"
+(( $+functions[_ptree_paths] )) ||
+_ptree_paths() {
+    _files -/
+    local -a roots
+    roots=(${(f)"$(ptree cached-roots 2>/dev/null)"})
+    (( ${#roots} )) && _wanted cached-roots expl 'cached root' compadd -a roots
+}
+"#;
+
+/// fish: completions add up, so a rule offering cached roots for path-like words is enough
+const FISH_HOOK: &str = r#"
+complete -c ptree -n 'string match -qr -- "^(/|~|[A-Za-z]:)" (commandline -ct)' -a '(ptree cached-roots 2>/dev/null)' -d 'cached root'
+"#;
+
+/// PowerShell: added to the generated completer's results before they are filtered
+const POWERSHELL_HOOK: &str = r#"    if ($wordToComplete -match '^([A-Za-z]:|[\\/~])') {
+        $roots = @(ptree cached-roots 2>$null | Where-Object { $_ -like "$wordToComplete*" })
+        if ($roots.Count) {
+            $completions += $roots | ForEach-Object {
+                [CompletionResult]::new($_, $_, [CompletionResultType]::ProviderContainer, 'cached root')
+            }
+            $completions += Get-ChildItem -Directory -Path "$wordToComplete*" -ErrorAction Ignore | ForEach-Object {
+                [CompletionResult]::new($_.FullName, $_.FullName, [CompletionResultType]::ProviderContainer, $_.FullName)
+            }
+        }
+    }
+
+"#;
+
+pub fn run(shell: Shell) -> Result<()> {
+    print!("{}", script(shell)?);
+    Ok(())
+}
+
+/// Print the root of every cache, for the completion hooks; unreadable caches are left out.
+pub fn cached_roots(args: &Args) -> Result<()> {
+    let cache_dir = super::cache::cache_dir(args)?;
+    let mut roots: Vec<_> = ptree_cache::list_root_caches(&cache_dir)?
+        .into_iter()
+        .flat_map(|cache| cache.roots)
+        .collect();
+    roots.sort();
+    roots.dedup();
+    for root in roots {
+        println!("{}", root.display());
+    }
+    Ok(())
+}
+
+fn script(shell: Shell) -> Result<String> {
+    let generator = match shell {
+        Shell::Bash => clap_complete::Shell::Bash,
+        Shell::Zsh => clap_complete::Shell::Zsh,
+        Shell::Fish => clap_complete::Shell::Fish,
+        Shell::PowerShell => clap_complete::Shell::PowerShell,
+    };
+    let mut script = Vec::new();
+    clap_complete::generate(generator, &mut ptree_core::command(), "ptree", &mut script);
+    let script = String::from_utf8(script)?;
+
+    match shell {
+        Shell::Bash => Ok(script + BASH_HOOK),
+        Shell::Zsh => zsh_with_cached_roots(&script),
+        Shell::Fish => Ok(script + FISH_HOOK),
+        Shell::PowerShell => {
+            let filter = "    $completions.Where{";
+            let at = script
+                .find(filter)
+                .context("the generated PowerShell script has no completion filter to hook into")?;
+            Ok(format!("{}{}{}", &script[..at], POWERSHELL_HOOK, &script[at..]))
+        }
+    }
+}
+
+/// Point the positional path arguments (`::paths -- ...:_files`) at `_ptree_paths`, defined
+/// before the script's closing `compdef`.
+fn zsh_with_cached_roots(script: &str) -> Result<String> {
+    let mut hooked = 0;
+    let lines: Vec<String> = script
+        .lines()
+        .map(|line| {
+            let is_paths = line.starts_with("'::paths -- ") || line.starts_with("'*::paths -- ");
+            match line.rsplit_once(":_files") {
+                Some((spec, _)) if is_paths => {
+                    hooked += 1;
+                    format!("{spec}:_ptree_paths' \\")
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect();
+    if hooked == 0 {
+        anyhow::bail!("the generated zsh script has no path arguments to hook into");
+    }
+
+    let script = lines.join("\n") + "\n";
+    let closing = "\nif [ \"$funcstack[1]\" = \"_ptree\" ]; then";
+    let at = script
+        .rfind(closing)
+        .context("the generated zsh script has no compdef to hook into")?;
+    Ok(format!("{}{}{}", &script[..at], ZSH_HOOK, &script[at..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_script_asks_for_cached_roots() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let script = script(shell).unwrap();
+            assert!(script.contains("ptree cached-roots"), "{shell:?}");
+            assert!(script.contains("completions"), "{shell:?}");
+        }
+
+        // Each path argument of zsh goes through the hook, and the script still ends by registering it
+        let zsh = script(Shell::Zsh).unwrap();
+        assert!(!zsh
+            .lines()
+            .any(|line| line.contains("::paths -- ") && line.contains("_files")));
+        assert!(
+            zsh.contains("::paths -- Roots to compare (default\\: the current directory, like a scan):_ptree_paths")
+        );
+        assert!(zsh.trim_end().ends_with("fi"));
+
+        let powershell = script(Shell::PowerShell).unwrap();
+        assert!(powershell.find("ptree cached-roots") < powershell.find("$completions.Where{"));
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use ptree_core::config::{Config, KeyKind, Source, KEYS};
+use ptree_core::{Args, ConfigCommand};
+
+pub fn run(action: &ConfigCommand, args: &Args) -> Result<()> {
+    match action {
+        ConfigCommand::Show => show(args),
+        ConfigCommand::Set { key, value } => {
+            let mut config = Config::load(args.config.as_deref())?;
+            config.set(key, value)?;
+            config.save()?;
+            println!("Saved {} in {}", key, config.path.display());
+            Ok(())
+        }
+        ConfigCommand::Unset { key } => {
+            let mut config = Config::load(args.config.as_deref())?;
+            config.unset(key)?;
+            config.save()?;
+            println!("Removed {} from {}", key, config.path.display());
+            Ok(())
+        }
+    }
+}
+
+/// Every key as the next run in the current directory would see it before its command line:
+/// the configured value and where it comes from, or the option's default.
+fn show(args: &Args) -> Result<()> {
+    let mut config = Config::load(args.config.as_deref())?;
+    if let Some(root) = args.project_root() {
+        config = config.with_project(&root)?;
+    }
+    let layered = config.layered();
+    let command = ptree_core::command();
+
+    println!("# {}", config.path.display());
+    if let Some(project) = config.project_path() {
+        println!("# {} (project)", project.display());
+    }
+    for problem in config.problems() {
+        println!("# ignored: {problem}");
+    }
+    for key in KEYS {
+        match layered.iter().find(|(configured, _, _)| configured.name == key.name) {
+            Some((_, value, Source::File)) => println!("{} = {}", key.name, value),
+            Some((_, value, Source::Project)) => println!("{} = {}  # from .ptree.toml", key.name, value),
+            Some((_, value, Source::Env)) => println!("{} = {}  # from {}", key.name, value, key.env_var()),
+            None => {
+                let default = command
+                    .get_arguments()
+                    .find(|arg| arg.get_id() == key.name)
+                    .and_then(|arg| arg.get_default_values().first())
+                    .map(|value| value.to_string_lossy().into_owned());
+                match default {
+                    Some(default) => println!("# {} = {:?}  (default)", key.name, default),
+                    None if key.kind == KeyKind::Flag => println!("# {} = false  (default)", key.name),
+                    None => println!("# {} is not set", key.name),
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ptree_cache::DiskCache;
+use ptree_core::Args;
+use ptree_traversal::{resolve_scan_roots, traverse_disk};
+
+/// Rescan the roots into memory and compare against the saved snapshot, which is left untouched.
+pub fn run(paths: &[PathBuf], args: &Args) -> Result<()> {
+    let mut scan_args = args.clone();
+    scan_args.command = None;
+    if !paths.is_empty() {
+        scan_args.paths = paths.to_vec();
+    }
+    scan_args.force = true;
+    scan_args.no_save = true;
+    scan_args.quiet = true;
+
+    let drive = scan_args.primary_drive();
+    let scan_roots = resolve_scan_roots(&drive, &scan_args)?;
+    let cache_path = ptree_cache::cache_path_for_roots(
+        &ptree_cache::get_cache_path_custom(scan_args.cache_dir.as_deref())?,
+        &scan_roots,
+    );
+
+    let mut previous = DiskCache::open(&cache_path)?;
+    if !previous.has_persisted_snapshot {
+        anyhow::bail!(
+            "no cached snapshot of {} to compare against; run ptree there first",
+            scan_roots_label(&scan_roots)
+        );
+    }
+    previous.load_all_entries_lazy(&cache_path)?;
+
+    let mut current = DiskCache::open(&cache_path)?;
+    traverse_disk(&drive, &mut current, &scan_args, &cache_path)?;
+
+    let diff = ptree_cache::diff_snapshots(&previous.entries, &current.entries);
+    if diff.is_empty() {
+        println!("No changes since {}", previous.last_scan.format("%Y-%m-%d %H:%M:%S UTC"));
+        return Ok(());
+    }
+
+    for (from, to) in &diff.moved {
+        println!("moved: {} → {}", from.display(), to.display());
+    }
+    for path in &diff.added {
+        println!("added: {}", path.display());
+    }
+    for path in &diff.removed {
+        println!("removed: {}", path.display());
+    }
+    for path in &diff.changed {
+        println!("changed: {}", path.display());
+    }
+    println!(
+        "{} moved, {} added, {} removed, {} with changed files since {}",
+        diff.moved.len(),
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        previous.last_scan.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    Ok(())
+}
+
+fn scan_roots_label(scan_roots: &[PathBuf]) -> String {
+    scan_roots
+        .iter()
+        .map(|root| root.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
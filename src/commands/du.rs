@@ -0,0 +1,94 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use ptree_cache::{DiskCache, LARGEST_DIRS_KEPT, LARGEST_FILES_KEPT};
+use ptree_core::{Args, OutputFormat};
+
+/// List the `top` largest directories (or files) of the cached snapshot, with their share of
+/// the roots' total size.
+pub fn run(paths: &[PathBuf], top: usize, files: bool, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let mut cache = DiskCache::open(&cache_path)?;
+    if !cache.has_persisted_snapshot {
+        bail!("no cached snapshot in {}; run ptree there first", cache_path.display());
+    }
+    cache.apparent_size = args.apparent_size;
+
+    // The roots' records and the lists the index keeps are all this reads
+    let totals = cache.persisted_totals(&cache_path)?.unwrap_or_default();
+    let total = if args.apparent_size {
+        totals.bytes
+    } else {
+        totals.allocated_bytes
+    };
+    let rows: Vec<(&Path, u64)> = if files {
+        cache
+            .largest_files
+            .iter()
+            .take(top)
+            .map(|file| (file.path.as_path(), file.size))
+            .collect()
+    } else {
+        cache
+            .top_dirs(top)
+            .into_iter()
+            .map(|dir| {
+                let size = if args.apparent_size {
+                    dir.total_size
+                } else {
+                    dir.allocated_size
+                };
+                (dir.path.as_path(), size)
+            })
+            .collect()
+    };
+    let kind = if files { "files" } else { "directories" };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if matches!(args.format, OutputFormat::Json) {
+        let rows: Vec<serde_json::Value> = rows
+            .iter()
+            .map(|(path, size)| serde_json::json!({ "path": path, "size": size, "percent": share(*size, total) }))
+            .collect();
+        serde_json::to_writer_pretty(&mut out, &serde_json::json!({ "total": total, kind: rows }))?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    let width = rows
+        .iter()
+        .map(|(_, size)| DiskCache::format_size(*size).len())
+        .max()
+        .unwrap_or(0);
+    for (path, size) in &rows {
+        writeln!(out, "{:>width$}  {:>5.1}%  {}", DiskCache::format_size(*size), share(*size, total), path.display())?;
+    }
+    match rows.len() {
+        0 if files => eprintln!("no files recorded; a scan with the directory walk (not --mft) records the largest"),
+        0 => eprintln!("no directories below the roots"),
+        count => {
+            eprintln!(
+                "largest {count} {kind} of {} (snapshot of {})",
+                DiskCache::format_size(total),
+                cache.last_scan.format("%Y-%m-%d %H:%M:%S UTC")
+            )
+        }
+    }
+    if files && top > LARGEST_FILES_KEPT {
+        eprintln!("note: the snapshot keeps only the {LARGEST_FILES_KEPT} largest files");
+    } else if !files && top > LARGEST_DIRS_KEPT {
+        eprintln!("note: the snapshot keeps only the {LARGEST_DIRS_KEPT} largest directories");
+    }
+    Ok(())
+}
+
+/// `size` as a percentage of `total`
+fn share(size: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        size as f64 * 100.0 / total as f64
+    }
+}
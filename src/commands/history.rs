@@ -0,0 +1,104 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use ptree_cache::{read_history, DiskCache, HistoryRecord};
+use ptree_core::{Args, OutputFormat};
+
+/// List the `last` recorded scans of the cache of `paths`, oldest first, each with the size
+/// change since the scan before it.
+pub fn run(paths: &[PathBuf], last: usize, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let records = read_history(&cache_path)?;
+    if records.is_empty() {
+        bail!("no scans recorded for {}; run ptree there first", cache_path.display());
+    }
+    let first = if last == 0 {
+        0
+    } else {
+        records.len().saturating_sub(last)
+    };
+    // The oldest row shown still compares against the scan before it
+    let previous = |index: usize| index.checked_sub(1).map(|before| &records[before]);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if matches!(args.format, OutputFormat::Json) {
+        let rows: Vec<serde_json::Value> = (first..records.len())
+            .map(|index| {
+                let record = &records[index];
+                serde_json::json!({
+                    "time": record.time,
+                    "directories": record.directories,
+                    "files": record.files,
+                    "bytes": record.bytes,
+                    "allocated_bytes": record.allocated_bytes,
+                    "bytes_change": previous(index).map(|before| record.bytes as i128 - before.bytes as i128),
+                    "duration_ms": record.duration_ms,
+                    "mode": record.mode,
+                })
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut out, &serde_json::json!({ "scans": rows }))?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    let table: Vec<[String; 7]> = (first..records.len())
+        .map(|index| history_line(&records[index], previous(index)))
+        .collect();
+    let header = ["scanned", "directories", "files", "size", "change", "duration", "mode"].map(String::from);
+    let widths: Vec<usize> = (0..7)
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&table)
+                .map(|line| line[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for line in std::iter::once(&header).chain(&table) {
+        writeln!(
+            out,
+            "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}  {:>w4$}  {:>w5$}  {}",
+            line[0],
+            line[1],
+            line[2],
+            line[3],
+            line[4],
+            line[5],
+            line[6],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+            w5 = widths[5]
+        )?;
+    }
+
+    let (oldest, newest) = (&records[first], &records[records.len() - 1]);
+    if first + 1 < records.len() {
+        eprintln!(
+            "{} scans since {}: {}",
+            records.len() - first,
+            oldest.time.format("%Y-%m-%d"),
+            crate::signed_size(newest.bytes, oldest.bytes)
+        );
+    }
+    Ok(())
+}
+
+fn history_line(record: &HistoryRecord, previous: Option<&HistoryRecord>) -> [String; 7] {
+    [
+        record.time.format("%Y-%m-%d %H:%M UTC").to_string(),
+        crate::format_number(record.directories),
+        crate::format_number(record.files),
+        DiskCache::format_size(record.bytes),
+        previous
+            .map(|before| crate::signed_size(record.bytes, before.bytes))
+            .unwrap_or_else(|| "-".to_string()),
+        format!("{:.1} s", record.duration_ms as f64 / 1000.0),
+        record.mode.clone(),
+    ]
+}
@@ -0,0 +1,127 @@
+//! Subcommands that run instead of a scan (`ptree cache ...`, `ptree client ...`,
+//! `ptree completions`, `ptree config ...`, `ptree diff`, `ptree du`, `ptree history`,
+//! `ptree report ...`, `ptree scheduler ...`, `ptree search`, `ptree serve`, `ptree service ...`,
+//! `ptree show`, `ptree stats`, `ptree validate`). `ptree scan` is not one of them: it parses as
+//! a bare invocation, which runs the scan in `main`, and `Args::subcommand` never returns it.
+
+mod cache;
+#[cfg(feature = "server")]
+mod client;
+mod completions;
+mod config;
+mod diff;
+mod du;
+mod history;
+mod report;
+#[cfg(feature = "scheduler")]
+mod scheduler;
+mod search;
+#[cfg(feature = "server")]
+mod serve;
+#[cfg(all(windows, feature = "scheduler", feature = "incremental"))]
+mod service;
+mod show;
+mod stats;
+mod validate;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use ptree_cache::cache_rkyv::RkyvMmapCache;
+use ptree_cache::DiskCache;
+use ptree_core::{Args, Command};
+use ptree_traversal::resolve_scan_roots;
+
+pub fn run(command: &Command, args: &Args) -> Result<()> {
+    match command {
+        Command::Scan { .. } => bail!("`ptree scan` runs as a bare invocation, not as a subcommand"),
+        Command::Search { pattern, paths, limit } => search::run(pattern, paths, *limit, args),
+        Command::Du { paths, top, files } => du::run(paths, *top, *files, args),
+        Command::Stats {
+            paths,
+            by_extension,
+            by_owner,
+            top,
+        } => stats::run(paths, *by_extension, *by_owner, *top, args),
+        #[cfg(feature = "scheduler")]
+        Command::Scheduler { action } => scheduler::run(action, args),
+        #[cfg(not(feature = "scheduler"))]
+        Command::Scheduler { .. } => anyhow::bail!("`ptree scheduler` needs a build with the `scheduler` feature"),
+        #[cfg(all(windows, feature = "scheduler", feature = "incremental"))]
+        Command::Service { action } => service::run(action, args),
+        #[cfg(all(not(windows), feature = "scheduler", feature = "incremental"))]
+        Command::Service { .. } => bail!("Windows services are only available on Windows targets"),
+        #[cfg(not(all(feature = "scheduler", feature = "incremental")))]
+        Command::Service { .. } => {
+            anyhow::bail!("`ptree service` needs a build with the `scheduler` and `incremental` features")
+        }
+        Command::Config { action } => config::run(action, args),
+        Command::Cache { action } => cache::run(action, args),
+        Command::Diff { paths } => diff::run(paths, args),
+        Command::Show { paths, as_of } => show::run(paths, as_of.as_deref(), args),
+        Command::History { paths, last } => history::run(paths, *last, args),
+        Command::Validate { paths, sample, seed } => validate::run(paths, *sample, *seed, args),
+        Command::Report { kind } => report::run(kind, args),
+        Command::Completions { shell } => completions::run(*shell),
+        Command::CachedRoots => completions::cached_roots(args),
+        #[cfg(feature = "server")]
+        Command::Client { roots, call } => client::run(roots, call, args),
+        #[cfg(not(feature = "server"))]
+        Command::Client { .. } => anyhow::bail!("`ptree client` needs a build with the `server` feature"),
+        #[cfg(feature = "server")]
+        Command::Serve { paths, port, bind } => serve::run(paths, bind, *port, args),
+        #[cfg(not(feature = "server"))]
+        Command::Serve { .. } => anyhow::bail!("`ptree serve` needs a build with the `server` feature"),
+    }
+}
+
+/// Roots a scan of `paths` would cover (default: the scan's usual roots).
+fn scan_roots_for_paths(paths: &[PathBuf], args: &Args) -> Result<Vec<PathBuf>> {
+    let mut scan_args = args.clone();
+    if !paths.is_empty() {
+        scan_args.paths = paths.to_vec();
+    }
+    resolve_scan_roots(&scan_args.primary_drive(), &scan_args)
+}
+
+/// `path` as caches have it: canonical, or at least absolute if it no longer exists.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    Ok(std::fs::canonicalize(path).or_else(|_| std::path::absolute(path))?)
+}
+
+/// The snapshot of the nearest directory at or above `target` that has one, opened once: its
+/// cache file, the cache of its index, and its records.
+fn covering_cache(target: &Path, args: &Args) -> Result<(PathBuf, DiskCache, RkyvMmapCache)> {
+    for dir in target.ancestors() {
+        let cache_path = cache_path_for_paths(&[dir.to_path_buf()], args)?;
+        if cache_path.with_extension("idx").exists() {
+            let records = RkyvMmapCache::open(&cache_path.with_extension("idx"), &cache_path.with_extension("dat"))?;
+            return Ok((cache_path, DiskCache::from_records(&records), records));
+        }
+    }
+    bail!("no cached snapshot covers {}; run ptree on it or a directory above it first", target.display())
+}
+
+/// `target` as `cache` has it, if `is_cached` says so: roots are cached as they were given,
+/// and `target` may reach them through a symlink.
+fn cached_form(cache: &DiskCache, target: &Path, is_cached: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    if is_cached(target) {
+        return Some(target.to_path_buf());
+    }
+    cache
+        .top_level_roots()
+        .into_iter()
+        .find_map(|root| {
+            let rest = target.strip_prefix(std::fs::canonicalize(&root).ok()?).ok()?;
+            Some(root.join(rest))
+        })
+        .filter(|path| is_cached(path))
+}
+
+/// Cache file a scan of `paths` (default: the scan's usual roots) reads and writes.
+fn cache_path_for_paths(paths: &[PathBuf], args: &Args) -> Result<PathBuf> {
+    Ok(ptree_cache::cache_path_for_roots(
+        &ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?,
+        &scan_roots_for_paths(paths, args)?,
+    ))
+}
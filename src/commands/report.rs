@@ -0,0 +1,252 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use ptree_cache::{DiskCache, OwnerRow};
+use ptree_core::{Args, MatrixGroup, ReportCommand};
+
+pub fn run(kind: &ReportCommand, args: &Args) -> Result<()> {
+    match kind {
+        ReportCommand::Ads { paths, all } => ads(paths, *all, args),
+        ReportCommand::Xattrs { paths, names } => xattrs(paths, names, args),
+        ReportCommand::Matrix {
+            paths,
+            group_by: MatrixGroup::Owner,
+            level,
+            csv,
+        } => matrix(paths, *level, *csv, args),
+    }
+}
+
+/// Every path of the cached snapshot with unexpected (or, with `all`, any) named streams.
+fn ads(paths: &[PathBuf], all: bool, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let cache = DiskCache::open(&cache_path)?;
+    let Some(scanned) = cache.ads_scanned else {
+        bail!("no scan recorded alternate data streams in {}; run a scan with --ads first", cache_path.display());
+    };
+
+    let reported = cache.reported_alt_streams(all);
+    let mut streams = 0;
+    let mut bytes = 0;
+    for (path, path_streams) in &reported {
+        println!("{}", path.display());
+        for stream in path_streams {
+            println!("    :{}  {}", stream.name, DiskCache::format_size(stream.size));
+            streams += 1;
+            bytes += stream.size;
+        }
+    }
+    println!(
+        "{} path(s) with {} {}stream(s), {} (--ads scan of {})",
+        crate::format_number(reported.len()),
+        crate::format_number(streams),
+        if all { "" } else { "unexpected " },
+        DiskCache::format_size(bytes),
+        scanned.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    Ok(())
+}
+
+/// Every path of the cached snapshot with extended attributes (those named `prefixes...`, if given).
+fn xattrs(paths: &[PathBuf], prefixes: &[String], args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let cache = DiskCache::open(&cache_path)?;
+    let Some(scanned) = cache.xattrs_scanned else {
+        bail!("no scan recorded extended attributes in {}; run a scan with --xattrs first", cache_path.display());
+    };
+
+    let reported = cache.reported_xattrs(prefixes);
+    let mut attrs = 0;
+    for (path, path_attrs) in &reported {
+        println!("{}", path.display());
+        for attr in path_attrs {
+            match &attr.value {
+                Some(value) => println!("    {} = {}", attr.name, value),
+                None => println!("    {}  ({})", attr.name, DiskCache::format_size(attr.size)),
+            }
+            attrs += 1;
+        }
+    }
+    println!(
+        "{} path(s) with {} attribute(s) (--xattrs scan of {})",
+        crate::format_number(reported.len()),
+        crate::format_number(attrs),
+        scanned.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    Ok(())
+}
+
+/// Usage per owner of every directory `level` levels below the roots: one row per directory,
+/// one column per owner (most usage first) and a total of each.
+fn matrix(paths: &[PathBuf], level: usize, csv: bool, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let cache = DiskCache::open(&cache_path)?;
+    let Some(scanned) = cache.owners_scanned else {
+        bail!("no scan recorded file owners in {}; run a scan with --owners first", cache_path.display());
+    };
+
+    let rows = cache.usage_by_owner(level);
+    let table = owner_table(&rows);
+    if csv {
+        for line in &table {
+            let fields: Vec<String> = line.iter().map(|cell| csv_field(&cell.text(Layout::Csv))).collect();
+            println!("{}", fields.join(","));
+        }
+        return Ok(());
+    }
+
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|column| {
+            table
+                .iter()
+                .map(|line| line[column].text(Layout::Table).len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for line in &table {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, &width))| {
+                let text = cell.text(Layout::Table);
+                if column == 0 {
+                    format!("{text:<width$}")
+                } else {
+                    format!("{text:>width$}")
+                }
+            })
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+    println!(
+        "{} director{} at level {level}, {} owner(s) (--owners scan of {})",
+        crate::format_number(rows.len()),
+        if rows.len() == 1 { "y" } else { "ies" },
+        crate::format_number(table[0].len() - 2),
+        scanned.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    Ok(())
+}
+
+/// One cell of the owner matrix
+enum Cell {
+    Text(String),
+    Bytes(u64),
+}
+
+/// Where the owner matrix is printed
+#[derive(Clone, Copy)]
+enum Layout {
+    /// `--csv`: sizes in bytes
+    Csv,
+    /// The aligned table: sizes formatted, `-` for none
+    Table,
+}
+
+impl Cell {
+    /// The cell as printed in `layout`
+    fn text(&self, layout: Layout) -> String {
+        match (self, layout) {
+            (Cell::Text(text), _) => text.clone(),
+            (Cell::Bytes(0), Layout::Table) => "-".to_string(),
+            (Cell::Bytes(bytes), Layout::Table) => DiskCache::format_size(*bytes),
+            (Cell::Bytes(bytes), Layout::Csv) => bytes.to_string(),
+        }
+    }
+}
+
+/// Header, one line per row and a line of totals; owners are ordered by total usage.
+fn owner_table(rows: &[OwnerRow]) -> Vec<Vec<Cell>> {
+    let mut totals: std::collections::BTreeMap<u32, u64> = std::collections::BTreeMap::new();
+    for row in rows {
+        for usage in row.owners.values() {
+            *totals.entry(usage.uid).or_default() += usage.bytes;
+        }
+    }
+    let mut owners: Vec<(u32, u64)> = totals.into_iter().collect();
+    owners.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut table = Vec::with_capacity(rows.len() + 2);
+    table.push(
+        std::iter::once(Cell::Text("directory".to_string()))
+            .chain(owners.iter().map(|(uid, _)| Cell::Text(ptree_cache::owner_name(*uid))))
+            .chain(std::iter::once(Cell::Text("total".to_string())))
+            .collect(),
+    );
+    for row in rows {
+        let bytes = |uid: &u32| row.owners.get(uid).map_or(0, |usage| usage.bytes);
+        table.push(
+            std::iter::once(Cell::Text(row.path.display().to_string()))
+                .chain(owners.iter().map(|(uid, _)| Cell::Bytes(bytes(uid))))
+                .chain(std::iter::once(Cell::Bytes(row.owners.values().map(|usage| usage.bytes).sum())))
+                .collect(),
+        );
+    }
+    table.push(
+        std::iter::once(Cell::Text("total".to_string()))
+            .chain(owners.iter().map(|(_, total)| Cell::Bytes(*total)))
+            .chain(std::iter::once(Cell::Bytes(owners.iter().map(|(_, total)| total).sum())))
+            .collect(),
+    );
+    table
+}
+
+/// A CSV field, quoted when it holds a comma, quote or line break
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ptree_cache::OwnerUsage;
+
+    use super::*;
+
+    #[test]
+    fn owner_matrix_has_a_column_per_owner_and_totals() {
+        let row = |path: &str, usage: &[(u32, u64)]| {
+            OwnerRow {
+                path:   PathBuf::from(path),
+                owners: usage
+                    .iter()
+                    .map(|&(uid, bytes)| {
+                        (
+                            uid,
+                            OwnerUsage {
+                                uid,
+                                directories: 0,
+                                files: 1,
+                                bytes,
+                            },
+                        )
+                    })
+                    .collect::<BTreeMap<_, _>>(),
+            }
+        };
+        let rows = [row("/home/a", &[(1001, 10), (1002, 5)]), row("/home/b", &[(1002, 40)])];
+        let csv: Vec<String> = owner_table(&rows)
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .skip(1)
+                    .map(|cell| cell.text(Layout::Csv))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        // 1002 owns the most, so its column comes first
+        assert!(csv[0].ends_with(",total"));
+        assert_eq!(&csv[1..], ["5,10,15", "40,0,40", "45,10,55"]);
+
+        assert_eq!(csv_field("/srv/a,b"), "\"/srv/a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,15 @@
+use anyhow::Result;
+use ptree_core::{Args, SchedulerCommand};
+use ptree_scheduler as scheduler;
+
+pub fn run(action: &SchedulerCommand, args: &Args) -> Result<()> {
+    match action {
+        SchedulerCommand::Install { backend } => scheduler::install_scheduler(*backend),
+        SchedulerCommand::Uninstall => scheduler::uninstall_scheduler(),
+        SchedulerCommand::Status { last } => {
+            // Scheduled runs log next to the default cache file of the cache directory
+            let cache_path = ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?;
+            scheduler::check_scheduler_status(&cache_path, *last)
+        }
+    }
+}
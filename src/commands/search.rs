@@ -0,0 +1,41 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ptree_cache::cache_rkyv::RkyvMmapCache;
+use ptree_cache::DiskCache;
+use ptree_core::{Args, OutputFormat};
+
+/// Search the persisted snapshot in place, without loading it or scanning.
+pub fn run(pattern: &str, paths: &[PathBuf], limit: usize, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    if !DiskCache::open(&cache_path)?.has_persisted_snapshot {
+        anyhow::bail!("no cached snapshot in {}; run ptree there first", cache_path.display());
+    }
+    let records = RkyvMmapCache::open(&cache_path.with_extension("idx"), &cache_path.with_extension("dat"))?;
+
+    // One extra hit tells whether the limit cut the list short
+    let mut hits = records.search(pattern, limit.saturating_add(1))?;
+    let truncated = hits.len() > limit;
+    hits.truncate(limit);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if matches!(args.format, OutputFormat::Json) {
+        serde_json::to_writer_pretty(&mut out, &serde_json::json!({ "hits": hits, "truncated": truncated }))?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for hit in &hits {
+        match hit.size {
+            Some(size) => writeln!(out, "{} ({})", hit.path.display(), DiskCache::format_size(size))?,
+            None => writeln!(out, "{}", hit.path.display())?,
+        }
+    }
+    match hits.len() {
+        0 => eprintln!("no matches"),
+        count if truncated => eprintln!("first {count} matches shown (--limit)"),
+        count => eprintln!("{count} matches"),
+    }
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ptree_core::Args;
+
+use crate::server::http;
+
+/// Serve the snapshot of `paths` over HTTP until killed.
+pub fn run(paths: &[PathBuf], bind: &str, port: u16, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    http::run(&cache_path, bind, port)
+}
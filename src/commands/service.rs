@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ptree_core::{Args, ServiceCommand};
+use ptree_scheduler::windows_service::{self as service, WATCH_SERVICE};
+
+pub fn run(action: &ServiceCommand, args: &Args) -> Result<()> {
+    match action {
+        ServiceCommand::Install { paths } => install(paths, args),
+        ServiceCommand::Uninstall => {
+            if service::uninstall_service(&WATCH_SERVICE)? {
+                println!("✓ Service '{}' stopped and removed", service::SERVICE_NAME);
+            } else {
+                println!("Service '{}' is not installed", service::SERVICE_NAME);
+            }
+            Ok(())
+        }
+        ServiceCommand::Status => {
+            match service::service_state(&WATCH_SERVICE)? {
+                Some(state) => println!("Service '{}': {}", service::SERVICE_NAME, state),
+                None => println!("Service '{}' is not installed", service::SERVICE_NAME),
+            }
+            Ok(())
+        }
+        ServiceCommand::Run { paths } => {
+            let mut watch_args = args.clone();
+            watch_args.paths = paths.clone();
+            watch_args.watch = true;
+            watch_args.quiet = true;
+            service::run_as_service(move |stop| {
+                let result = crate::watch::run_until_stopped(&watch_args, stop);
+                // Nobody sees stderr of a service; the log file is where a failure shows up
+                if let Err(err) = &result {
+                    tracing::error!("service stopped: {:#}", err);
+                }
+                result
+            })
+        }
+    }
+}
+
+/// Register the service to watch the roots a scan of `paths` covers, keeping the cache
+/// directory of the installing user current although the service runs as LocalSystem.
+fn install(paths: &[PathBuf], args: &Args) -> Result<()> {
+    let roots = super::scan_roots_for_paths(paths, args)?;
+    let cache_path = ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?;
+    let cache_dir = cache_path.parent().map(PathBuf::from).unwrap_or_default();
+    let log_path = cache_dir.join("service.log");
+
+    let mut service_args = vec![
+        "--cache-dir".to_string(),
+        cache_dir.display().to_string(),
+        "--log-file".to_string(),
+        log_path.display().to_string(),
+        "service".to_string(),
+        "run".to_string(),
+    ];
+    service_args.extend(roots.iter().map(|root| root.display().to_string()));
+    service::install_service(&service_args)?;
+
+    println!("✓ Service '{}' installed and started", service::SERVICE_NAME);
+    for root in &roots {
+        println!("  Watching:  {}", root.display());
+    }
+    println!("  Cache dir: {}", cache_dir.display());
+    println!("  Log:       {}", log_path.display());
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use ptree_cache::{CacheDump, DiskCache, SnapshotInfo};
+use ptree_core::Args;
+
+pub fn run(paths: &[PathBuf], as_of: Option<&str>, args: &Args) -> Result<()> {
+    match as_of {
+        Some(as_of) => show_as_of(paths, as_of, args),
+        None => show_cached(paths, args),
+    }
+}
+
+/// The tree below `paths[0]` from the cache of the nearest directory at or above it that has
+/// one, with only the records the output shows loaded.
+fn show_cached(paths: &[PathBuf], args: &Args) -> Result<()> {
+    let target = match paths {
+        [] => std::env::current_dir()?,
+        [path] => path.clone(),
+        _ => bail!("`ptree show` renders one directory; give one path (or several with --as-of)"),
+    };
+
+    let (since, before) = args.modified_range(Utc::now());
+    // Changes deeper than --max-depth still keep the directories leading to them
+    let depth = match (since, before) {
+        (None, None) => args.max_depth.map(|depth| depth.saturating_sub(1)),
+        _ => None,
+    };
+    let mut cache = cached_subtree(&super::absolute(&target)?, args, depth)?;
+    apply_render_options(&mut cache, args, Utc::now())?;
+    crate::render(&cache, args, crate::use_colors(args))?;
+    Ok(())
+}
+
+/// The directory `target` rooted as its own tree, with `depth` levels below it loaded and the
+/// totals its record keeps for the report line.
+fn cached_subtree(target: &Path, args: &Args, depth: Option<usize>) -> Result<DiskCache> {
+    let (_, mut cache, records) = super::covering_cache(target, args)?;
+    let Some(path) = super::cached_form(&cache, target, |path| records.index.offsets.contains_key(path)) else {
+        bail!("{} is not a directory in the snapshot of {}", target.display(), cache.root.display());
+    };
+    // Subtrees an interrupted scan or the size budget left out would be missing from the tree
+    let left = cache
+        .pending_dirs
+        .iter()
+        .filter(|pending| pending.starts_with(&path))
+        .count();
+    if left > 0 {
+        bail!(
+            "{} is incomplete in the snapshot ({} directories below it left unread); rescan it",
+            path.display(),
+            left
+        );
+    }
+
+    cache.load_subtree_from(&records, &path, depth)?;
+    cache.report_totals = records.subtree_totals(&path)?;
+    cache.root = path.clone();
+    cache.roots = vec![path];
+    Ok(cache)
+}
+
+/// `WHEN` is tried as a dump file, then a snapshot name, then a time; a time picks the latest
+/// named snapshot (or the current cache) taken by then.
+fn show_as_of(paths: &[PathBuf], as_of: &str, args: &Args) -> Result<()> {
+    let scan_roots = super::scan_roots_for_paths(paths, args)?;
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let snapshots =
+        ptree_cache::list_snapshots(&ptree_cache::snapshots_dir(&super::cache::cache_dir(args)?), &scan_roots)?;
+
+    let (label, dump) = if Path::new(as_of).is_file() {
+        (as_of.to_string(), CacheDump::read_json(Path::new(as_of))?)
+    } else if let Some(snapshot) = snapshots.iter().find(|snapshot| snapshot.name == as_of) {
+        (format!("snapshot {}", snapshot.name), CacheDump::read_json(&snapshot.path)?)
+    } else if let Some(time) = parse_time(as_of) {
+        latest_by(time, &snapshots, &cache_path)?
+    } else {
+        bail!(
+            "{:?} is not a dump file, a snapshot or a time (YYYY-MM-DD or RFC 3339){}",
+            as_of,
+            available(&snapshots)
+        );
+    };
+
+    eprintln!("As of {} (scanned {})", label, dump.last_scan.format("%Y-%m-%d %H:%M:%S UTC"));
+    let mut cache = dump.into_cache();
+    // Spans count back from when that snapshot was taken
+    let last_scan = cache.last_scan;
+    apply_render_options(&mut cache, args, last_scan)?;
+    crate::render(&cache, args, crate::use_colors(args))?;
+    Ok(())
+}
+
+/// The options of the main command that shape the output, with `--newer-than` spans
+/// counting back from `now`.
+fn apply_render_options(cache: &mut DiskCache, args: &Args, now: DateTime<Utc>) -> Result<()> {
+    cache.show_hidden = args.hidden;
+    cache.sort = args.sort;
+    cache.reverse = args.reverse;
+    cache.compact_dirs = args.compact_dirs;
+    cache.child_limit = args.limit;
+    cache.apparent_size = args.apparent_size;
+    cache.set_size_range(args.min_size, args.max_size);
+    if args.long {
+        cache.enable_long_listing();
+    }
+    cache.glyphs = args.tree_glyphs();
+    cache.theme = ptree_cache::Theme::named(args.theme);
+    cache.path_display = args.path_display()?;
+    cache.json_schema = args.json_schema;
+    let (since, before) = args.modified_range(now);
+    cache.set_modified_range(since, before);
+    Ok(())
+}
+
+/// RFC 3339, or a bare date meaning the end of that day (UTC).
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    Some(date.and_time(NaiveTime::from_hms_opt(23, 59, 59)?).and_utc())
+}
+
+/// The newest named snapshot, or the current cache, scanned at or before `time`.
+fn latest_by(time: DateTime<Utc>, snapshots: &[SnapshotInfo], cache_path: &Path) -> Result<(String, CacheDump)> {
+    let current = if DiskCache::open(cache_path)?.has_persisted_snapshot {
+        Some(DiskCache::dump(cache_path)?).filter(|dump| dump.last_scan <= time)
+    } else {
+        None
+    };
+    let snapshot = snapshots.iter().rev().find(|snapshot| snapshot.last_scan <= time);
+
+    match (snapshot, current) {
+        (Some(snapshot), Some(current)) if current.last_scan <= snapshot.last_scan => {
+            Ok((format!("snapshot {}", snapshot.name), CacheDump::read_json(&snapshot.path)?))
+        }
+        (_, Some(current)) => Ok(("the current cache".to_string(), current)),
+        (Some(snapshot), None) => Ok((format!("snapshot {}", snapshot.name), CacheDump::read_json(&snapshot.path)?)),
+        (None, None) => {
+            bail!(
+                "no snapshot of these roots was taken by {}{}",
+                time.format("%Y-%m-%d %H:%M:%S UTC"),
+                available(snapshots)
+            )
+        }
+    }
+}
+
+fn available(snapshots: &[SnapshotInfo]) -> String {
+    if snapshots.is_empty() {
+        return "; keep one with `ptree cache dump --snapshot NAME`".to_string();
+    }
+    let names: Vec<String> = snapshots
+        .iter()
+        .map(|snapshot| format!("{} ({})", snapshot.name, snapshot.last_scan.format("%Y-%m-%d %H:%M")))
+        .collect();
+    format!("; snapshots of these roots: {}", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use ptree_cache::{testing, DirEntry};
+
+    use super::*;
+
+    #[test]
+    fn cached_paths_are_shown_with_the_totals_of_their_records() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_show_cached_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(temp_dir.join("tree/src/core"))?;
+        std::fs::create_dir_all(temp_dir.join("tree/target"))?;
+        let root = std::fs::canonicalize(temp_dir.join("tree"))?;
+
+        let mut args = Args::parse_from(["ptree"]);
+        args.cache_dir = Some(temp_dir.join("cache").to_string_lossy().into_owned());
+        let cache_path = super::super::cache_path_for_paths(std::slice::from_ref(&root), &args)?;
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.root = root.clone();
+        cache.roots = vec![root.clone()];
+        for entry in [
+            DirEntry {
+                file_count: 6,
+                ..testing::dir(&root, 600, &["src", "README.md"])
+            },
+            DirEntry {
+                file_count: 5,
+                ..testing::dir(root.join("src"), 500, &["core", "main.rs"])
+            },
+            testing::dir(root.join("src/core"), 300, &["lib.rs", "mod.rs", "util.rs"]),
+        ] {
+            cache.entries.insert(entry.path.clone(), entry);
+        }
+        cache.save(&cache_path)?;
+
+        // A directory below the cached root is rooted on its own, one level loaded
+        let below = cached_subtree(&root.join("src"), &args, Some(0))?;
+        assert_eq!(below.roots, vec![root.join("src")]);
+        assert!(below.get_entry(&root.join("src")).is_some());
+        assert!(below.get_entry(&root.join("src/core")).is_none());
+        let totals = below.report_totals.as_ref().unwrap();
+        assert_eq!((totals.directories, totals.files, totals.bytes), (1, 5, 500));
+
+        let whole = cached_subtree(&root, &args, None)?;
+        assert!(whole.get_entry(&root.join("src/core")).is_some());
+        let totals = whole.report_totals.as_ref().unwrap();
+        assert_eq!((totals.directories, totals.files, totals.bytes), (2, 6, 600));
+
+        // A directory the snapshot does not have, or left for a scan to read, is refused
+        // rather than rendered empty
+        let missing = cached_subtree(&root.join("target"), &args, None).unwrap_err();
+        assert!(missing.to_string().contains("is not a directory in the snapshot"), "{missing}");
+        let mut cache = DiskCache::open(&cache_path)?;
+        cache.load_all_entries_lazy(&cache_path)?;
+        cache.pending_dirs = vec![root.join("src/core")];
+        cache.save(&cache_path)?;
+        let incomplete = cached_subtree(&root.join("src"), &args, None).unwrap_err();
+        assert!(incomplete.to_string().contains("is incomplete"), "{incomplete}");
+        assert!(cached_subtree(&root.join("src/core"), &args, None).is_err());
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use ptree_cache::{DiskCache, ExtensionUsage, OwnerUsage};
+use ptree_core::{Args, OutputFormat};
+
+/// Summarize the cached snapshot of `paths`: its totals, the files per extension or the usage
+/// per owner.
+pub fn run(paths: &[PathBuf], by_extension: bool, by_owner: bool, top: usize, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let mut cache = DiskCache::open(&cache_path)?;
+    let Some(totals) = cache.persisted_totals(&cache_path)? else {
+        bail!("no cached snapshot in {}; run ptree there first", cache_path.display());
+    };
+    let json = matches!(args.format, OutputFormat::Json);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    if by_owner {
+        return owners(&cache, &cache_path, top, json, &mut out);
+    }
+    if !by_extension {
+        if json {
+            let stats = serde_json::json!({
+                "directories": totals.directories,
+                "files": totals.files,
+                "bytes": totals.bytes,
+                "allocated_bytes": totals.allocated_bytes,
+                "last_scan": cache.last_scan,
+            });
+            serde_json::to_writer_pretty(&mut out, &stats)?;
+            writeln!(out)?;
+            return Ok(());
+        }
+        writeln!(out, "Directories: {}", crate::format_number(totals.directories))?;
+        writeln!(out, "Files:       {}", crate::format_number(totals.files))?;
+        writeln!(
+            out,
+            "Size:        {} ({} on disk)",
+            DiskCache::format_size(totals.bytes),
+            DiskCache::format_size(totals.allocated_bytes)
+        )?;
+        writeln!(out, "Scanned:     {}", cache.last_scan.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        return Ok(());
+    }
+
+    // Sizes need an --extensions scan; without one, files are counted from the names cached
+    let sized = cache.extensions_scanned;
+    let rows = if sized.is_some() {
+        cache.usage_by_extension()
+    } else {
+        if cache.snapshot_trim().files_dropped {
+            bail!("the snapshot in {} keeps no file names; run a scan with --extensions", cache_path.display());
+        }
+        cache.load_all_entries_lazy(&cache_path)?;
+        cache.files_by_extension()
+    };
+    let files: u64 = rows.iter().map(|row| row.files).sum();
+    let bytes: u64 = rows.iter().map(|row| row.bytes).sum();
+    let shown = if top == 0 { rows.len() } else { top.min(rows.len()) };
+
+    if json {
+        let truncated = shown < rows.len();
+        let rows: Vec<serde_json::Value> = rows[..shown]
+            .iter()
+            .map(|row| {
+                serde_json::json!({
+                    "extension": row.extension,
+                    "files": row.files,
+                    "bytes": sized.map(|_| row.bytes),
+                })
+            })
+            .collect();
+        let stats = serde_json::json!({
+            "files": files,
+            "bytes": sized.map(|_| bytes),
+            "extensions": rows,
+            "truncated": truncated,
+            "sizes_scanned": sized,
+        });
+        serde_json::to_writer_pretty(&mut out, &stats)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    let table: Vec<Vec<String>> = rows[..shown]
+        .iter()
+        .map(|row| extension_line(row, sized.is_some(), files, bytes))
+        .collect();
+    write_table(&mut out, &["extension", "files", "size", "share"], &table)?;
+
+    let more = rows.len() - shown;
+    match sized {
+        Some(scanned) => {
+            eprintln!(
+                "{} files in {} extensions, {} (--extensions scan of {})",
+                crate::format_number(files as usize),
+                crate::format_number(rows.len()),
+                DiskCache::format_size(bytes),
+                scanned.format("%Y-%m-%d %H:%M:%S UTC")
+            )
+        }
+        None => {
+            eprintln!(
+                "{} files in {} extensions; run a scan with --extensions for sizes",
+                crate::format_number(files as usize),
+                crate::format_number(rows.len())
+            )
+        }
+    }
+    if more > 0 {
+        eprintln!("{} more not shown (--top 0 lists all)", crate::format_number(more));
+    }
+    Ok(())
+}
+
+/// Directories, files and bytes of each owner, from the `--owners` records; the owners with
+/// the most bytes first.
+fn owners(cache: &DiskCache, cache_path: &Path, top: usize, json: bool, out: &mut impl Write) -> Result<()> {
+    let Some(scanned) = cache.owners_scanned else {
+        if cfg!(not(unix)) {
+            bail!("file owners are only recorded on Unix and macOS; Windows scans keep no owner SIDs");
+        }
+        bail!("no scan recorded file owners in {}; run a scan with --owners first", cache_path.display());
+    };
+    let rows = cache.usage_per_owner();
+    let bytes: u64 = rows.iter().map(|row| row.bytes).sum();
+    let shown = if top == 0 { rows.len() } else { top.min(rows.len()) };
+
+    if json {
+        let owners: Vec<serde_json::Value> = rows[..shown]
+            .iter()
+            .map(|row| {
+                serde_json::json!({
+                    "uid": row.uid,
+                    "owner": ptree_cache::owner_name(row.uid),
+                    "directories": row.directories,
+                    "files": row.files,
+                    "bytes": row.bytes,
+                })
+            })
+            .collect();
+        let stats = serde_json::json!({
+            "directories": rows.iter().map(|row| row.directories).sum::<u64>(),
+            "files": rows.iter().map(|row| row.files).sum::<u64>(),
+            "bytes": bytes,
+            "owners": owners,
+            "truncated": shown < rows.len(),
+            "owners_scanned": scanned,
+        });
+        serde_json::to_writer_pretty(&mut *out, &stats)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    let table: Vec<Vec<String>> = rows[..shown].iter().map(|row| owner_line(row, bytes)).collect();
+    write_table(out, &["owner", "directories", "files", "size", "share"], &table)?;
+    eprintln!(
+        "{} owner(s), {} (--owners scan of {})",
+        crate::format_number(rows.len()),
+        DiskCache::format_size(bytes),
+        scanned.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    if rows.len() > shown {
+        eprintln!("{} more not shown (--top 0 lists all)", crate::format_number(rows.len() - shown));
+    }
+    Ok(())
+}
+
+/// The table under `header`: the first column left-aligned, the others right-aligned.
+fn write_table(out: &mut impl Write, header: &[&str], table: &[Vec<String>]) -> io::Result<()> {
+    let header: Vec<String> = header.iter().map(|title| title.to_string()).collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(table)
+                .map(|line| line[column].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for line in std::iter::once(&header).chain(table) {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, &width))| {
+                if column == 0 {
+                    format!("{cell:<width$}")
+                } else {
+                    format!("{cell:>width$}")
+                }
+            })
+            .collect();
+        writeln!(out, "{}", cells.join("  "))?;
+    }
+    Ok(())
+}
+
+/// A table line: the owner's name, directories, files, size and share of the size.
+fn owner_line(row: &OwnerUsage, bytes: u64) -> Vec<String> {
+    vec![
+        ptree_cache::owner_name(row.uid),
+        crate::format_number(row.directories as usize),
+        crate::format_number(row.files as usize),
+        DiskCache::format_size(row.bytes),
+        percent(row.bytes, bytes),
+    ]
+}
+
+/// A table line: the extension, its files, its size (`-` when not recorded) and its share of
+/// the size, or of the files without sizes.
+fn extension_line(row: &ExtensionUsage, sized: bool, files: u64, bytes: u64) -> Vec<String> {
+    let extension = if row.extension.is_empty() {
+        "(none)".to_string()
+    } else {
+        format!(".{}", row.extension)
+    };
+    let (size, share) = if sized {
+        (DiskCache::format_size(row.bytes), percent(row.bytes, bytes))
+    } else {
+        ("-".to_string(), percent(row.files, files))
+    };
+    vec![extension, crate::format_number(row.files as usize), size, share]
+}
+
+fn percent(part: u64, whole: u64) -> String {
+    if whole == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", part as f64 * 100.0 / whole as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owners_print_as_a_table_or_json_with_the_most_bytes_first() -> Result<()> {
+        // IDs no password database names, so they are shown by number
+        let usage = |uid, directories, files, bytes| {
+            OwnerUsage {
+                uid,
+                directories,
+                files,
+                bytes,
+            }
+        };
+        let mut cache = DiskCache::default();
+        cache
+            .owner_usage
+            .insert(PathBuf::from("/srv"), vec![usage(4_000_001, 1, 2, 100), usage(4_000_002, 0, 1, 300)]);
+        cache
+            .owner_usage
+            .insert(PathBuf::from("/srv/data"), vec![usage(4_000_001, 1, 5, 500)]);
+        let cache_path = Path::new("/cache/ptree.dat");
+        assert!(owners(&cache, cache_path, 0, false, &mut Vec::new()).is_err());
+        cache.owners_scanned = Some("2026-10-01T10:00:00Z".parse()?);
+
+        let mut table = Vec::new();
+        owners(&cache, cache_path, 0, false, &mut table)?;
+        assert_eq!(
+            String::from_utf8(table)?,
+            "owner    directories  files   size  share\n\
+             4000001            2      7  600 B  66.7%\n\
+             4000002            0      1  300 B  33.3%\n"
+        );
+
+        let mut json = Vec::new();
+        owners(&cache, cache_path, 1, true, &mut json)?;
+        let json: serde_json::Value = serde_json::from_slice(&json)?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "directories": 2,
+                "files": 8,
+                "bytes": 900,
+                "owners": [{"uid": 4_000_001, "owner": "4000001", "directories": 2, "files": 7, "bytes": 600}],
+                "truncated": true,
+                "owners_scanned": "2026-10-01T10:00:00Z",
+            })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use ptree_cache::DiskCache;
+use ptree_core::{Args, SampleSize};
+
+/// Paths listed per kind of change; the counts cover the whole sample.
+const LISTED_PER_KIND: usize = 10;
+
+pub fn run(paths: &[PathBuf], sample: SampleSize, seed: Option<u64>, args: &Args) -> Result<()> {
+    let cache_path = super::cache_path_for_paths(paths, args)?;
+    let entries = DiskCache::open(&cache_path)?.entry_count_hint();
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default()
+            ^ std::process::id() as u64
+    });
+
+    let report = DiskCache::validate_sample(&cache_path, sample.of(entries), seed)?;
+    let age = Utc::now() - report.last_scan;
+    println!(
+        "Sampled {} of {} cached directories in {} (snapshot {}, {} old)",
+        crate::format_number(report.sampled),
+        crate::format_number(report.entries),
+        cache_path.display(),
+        report.last_scan.format("%Y-%m-%d %H:%M:%S UTC"),
+        format_age(age)
+    );
+
+    for path in report.missing.iter().take(LISTED_PER_KIND) {
+        println!("    missing: {}", path.display());
+    }
+    for (path, drift) in report.drifted.iter().take(LISTED_PER_KIND) {
+        let sign = if *drift < Duration::zero() { "-" } else { "+" };
+        println!("    changed: {} (mtime {}{})", path.display(), sign, format_age(drift.abs()));
+    }
+
+    let checked = report.sampled - report.unreadable;
+    println!("  {:<14} {}", "Missing:", share(report.missing.len(), checked));
+    println!("  {:<14} {}", "Mtime drift:", share(report.drifted.len(), checked));
+    if report.unreadable > 0 {
+        println!("  {:<14} {} (not counted)", "Unreadable:", crate::format_number(report.unreadable));
+    }
+    println!(
+        "Staleness: {:.2}% of checked directories changed since the snapshot (seed {})",
+        report.staleness_rate() * 100.0,
+        seed
+    );
+    Ok(())
+}
+
+fn share(count: usize, of: usize) -> String {
+    let percent = if of == 0 { 0.0 } else { count as f64 * 100.0 / of as f64 };
+    format!("{} ({:.2}%)", crate::format_number(count), percent)
+}
+
+/// Largest whole unit, e.g. `3h 12m` or `45s`.
+fn format_age(age: Duration) -> String {
+    let seconds = age.num_seconds().max(0);
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        3600..86_400 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86_400, seconds % 86_400 / 3600),
+    }
+}
@@ -0,0 +1,210 @@
+//! `--interactive`: keep the scanned (or cached) tree loaded and answer filter and query
+//! commands read from stdin, one per line. A light alternative to a full-screen UI over SSH:
+//! filters set with one command apply to every later `render`.
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use ptree_cache::DiskCache;
+use ptree_core::Args;
+
+/// Most `find` matches printed
+const FIND_LIMIT: usize = 1000;
+
+const HELP: &str = "\
+Commands:
+  render [PATH]     Print the tree below PATH (default: the scanned roots) with the current filters
+  depth N | all     Levels `render` shows
+  size > SIZE       Show only directories of at least SIZE (`500M`, `1.5GB`); `size < SIZE` sets the upper bound
+  size off          Show every directory, and files, again
+  find PATTERN      List files and directories matching a `*`/`?` pattern (a path if it contains a separator)
+  help              Show this list
+  quit              Leave (so does end of input)";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Render(Option<PathBuf>),
+    Depth(Option<usize>),
+    MinSize(u64),
+    MaxSize(u64),
+    SizeOff,
+    Find(String),
+    Help,
+    Quit,
+}
+
+/// Filters the commands have set so far
+struct View {
+    depth:    Option<usize>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+}
+
+/// Read commands until `quit` or end of input.
+pub fn run(cache: &mut DiskCache, args: &Args, use_colors: bool) -> Result<()> {
+    let prompt = atty::is(atty::Stream::Stdin);
+    let mut view = View {
+        depth:    args.max_depth,
+        min_size: args.min_size,
+        max_size: args.max_size,
+    };
+    if prompt {
+        println!("{} directories loaded; type `help` for the commands", cache.entries.len());
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if prompt {
+            print!("ptree> ");
+            io::stdout().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let command = match parse(&line?) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(err) => {
+                eprintln!("error: {err}");
+                continue;
+            }
+        };
+        match command {
+            Command::Quit => break,
+            command => {
+                if let Err(err) = execute(cache, args, &mut view, command, use_colors) {
+                    eprintln!("error: {err:#}");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn execute(cache: &mut DiskCache, args: &Args, view: &mut View, command: Command, use_colors: bool) -> Result<()> {
+    match command {
+        Command::Render(path) => render(cache, args, view, path.as_deref(), use_colors)?,
+        Command::Depth(depth) => view.depth = depth,
+        Command::MinSize(size) => view.min_size = Some(size),
+        Command::MaxSize(size) => view.max_size = Some(size),
+        Command::SizeOff => (view.min_size, view.max_size) = (None, None),
+        Command::Find(pattern) => find(cache, &pattern),
+        Command::Help => println!("{HELP}"),
+        Command::Quit => {}
+    }
+    cache.set_size_range(view.min_size, view.max_size);
+    Ok(())
+}
+
+/// Render the tree, or only the cached directory at `path`, as a run would with the view's depth.
+fn render(cache: &mut DiskCache, args: &Args, view: &View, path: Option<&Path>, use_colors: bool) -> Result<()> {
+    let mut args = args.clone();
+    args.max_depth = view.depth;
+    let Some(path) = path else {
+        crate::render(cache, &args, use_colors)?;
+        return Ok(());
+    };
+
+    let path = cached_path(cache, path)?;
+    let roots = std::mem::replace(&mut cache.roots, vec![path]);
+    let rendered = crate::render(cache, &args, use_colors);
+    cache.roots = roots;
+    rendered.map(|_| ())
+}
+
+/// `path` as the cache has it: relative paths are taken from the current directory, and
+/// symlinks followed if the path as typed is not cached.
+fn cached_path(cache: &DiskCache, path: &Path) -> Result<PathBuf> {
+    let path = std::env::current_dir()?.join(path);
+    let is_dir = |path: &Path| cache.get_entry(path).is_some_and(|entry| entry.is_dir);
+    if is_dir(&path) {
+        return Ok(path);
+    }
+    match std::fs::canonicalize(&path) {
+        Ok(canonical) if is_dir(&canonical) => Ok(canonical),
+        _ => anyhow::bail!("{} is not a cached directory", path.display()),
+    }
+}
+
+fn find(cache: &DiskCache, pattern: &str) {
+    // One extra hit tells whether the limit cut the list short
+    let hits = cache.search(pattern, FIND_LIMIT + 1);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for hit in hits.iter().take(FIND_LIMIT) {
+        let _ = match hit.size {
+            Some(size) => writeln!(out, "{} ({})", hit.path.display(), DiskCache::format_size(size)),
+            None => writeln!(out, "{}", hit.path.display()),
+        };
+    }
+    let _ = match hits.len() {
+        0 => writeln!(out, "no matches"),
+        count if count > FIND_LIMIT => writeln!(out, "first {FIND_LIMIT} matches shown"),
+        count => writeln!(out, "{count} matches"),
+    };
+}
+
+/// One command line; `None` for a blank one.
+fn parse(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    let command = match word.to_lowercase().as_str() {
+        "" => return Ok(None),
+        "render" => Command::Render((!rest.is_empty()).then(|| PathBuf::from(rest))),
+        "depth" => {
+            match rest {
+                "all" | "off" => Command::Depth(None),
+                depth => {
+                    let depth = depth
+                        .parse()
+                        .map_err(|_| format!("depth needs a number of levels or `all`: {depth:?}"))?;
+                    Command::Depth(Some(depth))
+                }
+            }
+        }
+        "size" => {
+            if rest == "off" {
+                Command::SizeOff
+            } else if let Some(size) = rest.strip_prefix('>') {
+                Command::MinSize(ptree_core::parse_size(size.trim_start_matches('='))?)
+            } else if let Some(size) = rest.strip_prefix('<') {
+                Command::MaxSize(ptree_core::parse_size(size.trim_start_matches('='))?)
+            } else {
+                return Err("size takes `> SIZE`, `< SIZE` or `off`".to_string());
+            }
+        }
+        "find" if rest.is_empty() => return Err("find needs a pattern, like `find *.log`".to_string()),
+        "find" => Command::Find(rest.to_string()),
+        "help" | "?" => Command::Help,
+        "quit" | "exit" | "q" => Command::Quit,
+        other => return Err(format!("unknown command `{other}` (type `help` for the list)")),
+    };
+    Ok(Some(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_parse_with_their_arguments() {
+        assert_eq!(parse("  "), Ok(None));
+        assert_eq!(parse("depth 3"), Ok(Some(Command::Depth(Some(3)))));
+        assert_eq!(parse("depth all"), Ok(Some(Command::Depth(None))));
+        assert_eq!(parse("find *.log"), Ok(Some(Command::Find("*.log".to_string()))));
+        assert_eq!(parse("find my notes*"), Ok(Some(Command::Find("my notes*".to_string()))));
+        assert_eq!(parse("size > 1GB"), Ok(Some(Command::MinSize(1 << 30))));
+        assert_eq!(parse("size <=10M"), Ok(Some(Command::MaxSize(10 << 20))));
+        assert_eq!(parse("size off"), Ok(Some(Command::SizeOff)));
+        assert_eq!(parse("render /home"), Ok(Some(Command::Render(Some(PathBuf::from("/home"))))));
+        assert_eq!(parse("RENDER"), Ok(Some(Command::Render(None))));
+        assert_eq!(parse("exit"), Ok(Some(Command::Quit)));
+
+        for invalid in ["depth", "depth -1", "size 1GB", "size > lots", "find", "delete /home"] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,64 +1,172 @@
+mod commands;
+mod interactive;
+mod metrics;
+mod pager;
+mod progress;
+#[cfg(feature = "server")]
+mod server;
+mod terminal;
+#[cfg(feature = "incremental")]
+mod watch;
+
 use std::io::{self, BufWriter, Write};
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once, PoisonError, Weak};
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use ptree_cache::DiskCache;
-use ptree_core::{ColorMode, OutputFormat};
+use anyhow::{bail, Context, Result};
+use ptree_cache::{DiskCache, PathQuery, Prefetch, SnapshotTotals};
+use ptree_core::{Args, ColorMode, LogLevel, OutputFormat};
 #[cfg(feature = "scheduler")]
 use ptree_scheduler as scheduler;
-use ptree_traversal::traverse_disk;
+use ptree_traversal::{resolve_scan_roots, traverse_disk_with_control, ScanControl};
 
 fn main() -> Result<()> {
     let program_start = Instant::now();
 
     let args = ptree_core::parse_args();
+    init_logging(&args)?;
+
+    // ========================================================================
+    // Migrate Caches of Earlier Versions
+    // ========================================================================
+
+    if !args.no_migrate && !args.no_save {
+        migrate_caches(&args);
+    }
+
+    // ========================================================================
+    // Handle Subcommands (Early Exit)
+    // ========================================================================
+
+    if let Some(command) = args.subcommand() {
+        return commands::run(&command, &args);
+    }
+
+    // ========================================================================
+    // Watch Mode (runs until stopped)
+    // ========================================================================
+
+    if args.watch {
+        #[cfg(feature = "incremental")]
+        return watch::run(&args);
+        #[cfg(not(feature = "incremental"))]
+        anyhow::bail!("--watch needs a build with the `incremental` feature");
+    }
 
     // ========================================================================
-    // Handle Scheduler Commands (Early Exit)
+    // Coordinate Scheduled Refreshes
     // ========================================================================
 
     #[cfg(feature = "scheduler")]
-    {
-        if args.scheduler {
-            scheduler::install_scheduler()?;
-            return Ok(());
-        }
+    if args.scheduled {
+        return scheduled_refresh(&args, program_start);
+    }
 
-        if args.scheduler_uninstall {
-            scheduler::uninstall_scheduler()?;
-            return Ok(());
-        }
+    scan_and_render(&args, program_start)
+}
 
-        if args.scheduler_status {
-            scheduler::check_scheduler_status()?;
-            return Ok(());
+/// Scan (or load the cache), then print the tree, digest or statistics `args` ask for.
+///
+/// Snapshot records are checked when they are read, not when the cache is opened; one that
+/// turns out corrupt discards the snapshot, and the run starts over with a full scan.
+fn scan_and_render(args: &Args, program_start: Instant) -> Result<()> {
+    match scan_and_render_once(args, program_start) {
+        Err(err) if ptree_cache::is_corrupt_cache(&err) => {
+            tracing::warn!("{err:#}; discarding the cache and rescanning");
+            DiskCache::discard_snapshot(&cache_path(args)?)?;
+            scan_and_render_once(args, program_start)
         }
+        result => result,
     }
+}
+
+/// The cache file of the roots `args` scan: each set of scan roots gets its own, so /home and
+/// /var don't overwrite each other.
+fn cache_path(args: &Args) -> Result<PathBuf> {
+    let scan_roots = resolve_scan_roots(&args.primary_drive(), args)?;
+    Ok(ptree_cache::cache_path_for_roots(
+        &ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?,
+        &scan_roots,
+    ))
+}
 
+fn scan_and_render_once(args: &Args, program_start: Instant) -> Result<()> {
     // ========================================================================
     // Determine Color Output Settings
     // ========================================================================
 
-    let use_colors = match args.color {
-        ColorMode::Auto => atty::is(atty::Stream::Stdout),
-        ColorMode::Always => true,
-        ColorMode::Never => false,
+    let use_colors = use_colors(args);
+
+    // A mistyped --query fails before the scan rather than after it
+    let query = match &args.query {
+        Some(expr) => Some((expr, PathQuery::parse(expr).with_context(|| format!("invalid --query {:?}", expr))?)),
+        None => None,
     };
 
     // ========================================================================
     // Load or Create Cache
     // ========================================================================
 
-    let cache_path = ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?;
+    let cache_path = cache_path(args)?;
+    // Load what the last render showed in the background while the cache is opened and checked
+    let prefetch = if args.no_cache || args.force || args.quiet || args.summary_only {
+        None
+    } else {
+        Prefetch::start(&cache_path)
+    };
+
     let cache_load_start = Instant::now();
     let mut cache = DiskCache::open(&cache_path)?;
     let cache_load_elapsed = cache_load_start.elapsed();
+    cache.max_cache_bytes = args.cache_max_bytes();
+    cache.max_memory_bytes = args.max_memory_bytes();
+    if let Some(timeout) = args.lock_timeout() {
+        cache.lock_timeout = timeout;
+    }
+
+    // Switching formats rewrites the existing snapshot now, so even a cache hit uses the new one
+    if let Some(compress) = args.cache_compress {
+        if cache.has_persisted_snapshot && cache.compress != compress {
+            DiskCache::set_compression(&cache_path, compress)?;
+        }
+        cache.compress = compress;
+    }
+
+    if args.cache_info {
+        print!("{}", cache.get_cache_info_report(&cache_path));
+        return Ok(());
+    }
 
     // ========================================================================
     // Traverse Disk & Update Cache
     // ========================================================================
 
-    let mut debug_info = traverse_disk(&args.drive, &mut cache, &args, &cache_path)?;
+    // The --summary-only digest compares against the snapshot as it was before this run
+    let previous_totals = if args.summary_only {
+        cache.persisted_totals(&cache_path).ok().flatten()
+    } else {
+        None
+    };
+
+    // Ctrl-C during the scan saves what was read for the next run to complete
+    let control = Arc::new(ScanControl::default());
+    interrupt_on_ctrl_c(Arc::downgrade(&control));
+    let status_line = show_progress(args).then(|| progress::StatusLine::start(Arc::clone(&control)));
+    let scanned = traverse_disk_with_control(&args.primary_drive(), &mut cache, args, &cache_path, &control);
+    if let Some(status_line) = status_line {
+        status_line.finish();
+    }
+    let mut debug_info = scanned?;
+    // Every scan that read the disk leaves a line in the history (`ptree history`)
+    if !debug_info.cache_used && !args.no_cache && !args.no_save {
+        record_history(&cache, &debug_info, &cache_path);
+    }
+    if let Some(metrics_path) = &args.metrics_textfile {
+        write_metrics(&cache, &debug_info, &cache_path, metrics_path);
+    }
+    drop(control);
+    let print_tree = !args.quiet && !args.summary_only && !args.interactive;
 
     // ========================================================================
     // Output Results (with lazy-loading for cold-start)
@@ -66,9 +174,17 @@ fn main() -> Result<()> {
 
     cache.show_hidden = args.hidden;
 
+    // --interactive answers every command from the whole tree, so load it once up front
+    if args.interactive && (debug_info.cache_used || debug_info.spilled_dirs > 0) {
+        cache.load_all_entries_lazy(&cache_path)?;
+    }
+
     // Cache hits start with only the index in memory, so expand just the visible tree.
-    if !args.quiet && debug_info.cache_used {
+    if print_tree && debug_info.cache_used {
         let lazy_load_start = Instant::now();
+        if let Some(prefetch) = prefetch {
+            debug_info.prefetched = prefetch.apply(&mut cache);
+        }
         cache.load_visible_entries_lazy(&cache_path, args.max_depth)?;
         debug_info.lazy_load_time = lazy_load_start.elapsed();
         debug_info.total_dirs = if args.max_depth == Some(0) && !cache.root.as_os_str().is_empty() {
@@ -76,53 +192,87 @@ fn main() -> Result<()> {
         } else {
             cache.entries.len()
         };
-        debug_info.total_files = cache
-            .entries
-            .get(&cache.root)
-            .map(|entry| entry.file_count)
-            .unwrap_or_else(|| cache.file_count_hint());
+        debug_info.total_files = cache.file_count_hint();
     }
 
-    let mut formatting_elapsed = std::time::Duration::ZERO;
-    let mut output_elapsed = std::time::Duration::ZERO;
+    // Subtrees spilled under --max-memory are only in the saved snapshot now; read back
+    // what the render shows, like a cache hit does
+    if print_tree && debug_info.spilled_dirs > 0 {
+        cache.load_visible_entries_lazy(&cache_path, args.max_depth)?;
+    }
 
-    if !args.quiet {
-        // Buffer stdout to minimize write(2) syscalls; 8 MiB keeps flushes rare even for huge trees.
-        let stdout = io::stdout();
-        let mut writer = BufWriter::with_capacity(8 << 20, stdout.lock());
-
-        match args.format {
-            OutputFormat::Tree => {
-                // Treat the whole streaming render as output time (formatting is negligible compared to I/O)
-                let output_start = Instant::now();
-                if use_colors {
-                    cache.write_colored_tree_output_with_options(
-                        &mut writer,
-                        args.max_depth,
-                        args.size,
-                        args.file_count,
-                    )?
-                } else {
-                    cache.write_tree_output_with_options(&mut writer, args.max_depth, args.size, args.file_count)?
-                }
-                writer.flush()?;
-                output_elapsed = output_start.elapsed();
+    cache.sort = args.sort;
+    cache.reverse = args.reverse;
+    cache.compact_dirs = args.compact_dirs;
+    cache.child_limit = args.limit;
+    cache.apparent_size = args.apparent_size;
+    cache.set_size_range(args.min_size, args.max_size);
+    if args.long {
+        cache.enable_long_listing();
+    }
+    cache.glyphs = args.tree_glyphs();
+    cache.theme = ptree_cache::Theme::named(args.theme);
+    cache.path_display = args.path_display()?;
+    cache.json_schema = args.json_schema;
+    let (since, before) = args.modified_range(chrono::Utc::now());
+    if since.is_some() || before.is_some() {
+        // Changes deeper than --max-depth still keep the directories leading to them
+        if print_tree && (debug_info.cache_used || debug_info.spilled_dirs > 0) {
+            cache.load_all_entries_lazy(&cache_path)?;
+        }
+        cache.set_modified_range(since, before);
+    }
+
+    // --query shows the subtrees it matches in place of the roots, each to --max-depth below it
+    let query_roots = match &query {
+        Some((expr, query)) => {
+            if debug_info.cache_used || debug_info.spilled_dirs > 0 {
+                cache.load_all_entries_lazy(&cache_path)?;
             }
-            OutputFormat::Json => {
-                // JSON still builds a String first, so time formatting separately from output write.
-                let formatting_start = Instant::now();
-                let json = cache.build_json_output_with_options(args.max_depth, args.size, args.file_count)?;
-                formatting_elapsed = formatting_start.elapsed();
-
-                let output_start = Instant::now();
-                writer.write_all(json.as_bytes())?;
-                if !json.ends_with('\n') {
-                    writer.write_all(b"\n")?;
-                }
-                writer.flush()?;
-                output_elapsed = output_start.elapsed();
+            let matches = cache.query_paths(query);
+            if matches.is_empty() {
+                bail!("nothing in the tree matches --query {:?}", expr);
             }
+            cache.report_totals = Some(cache.subtree_totals(&matches));
+            Some(matches)
         }
+        None => None,
+    };
+
+    if args.interactive {
+        return interactive::run(&mut cache, args, use_colors);
+    }
+
+    let mut formatting_elapsed = Duration::ZERO;
+    let mut output_elapsed = Duration::ZERO;
+
+    if print_tree {
+        let roots = query_roots.map(|matches| std::mem::replace(&mut cache.roots, matches));
+        let rendered = render(&cache, args, use_colors);
+        if let Some(roots) = roots {
+            cache.roots = roots;
+        }
+        (formatting_elapsed, output_elapsed) = rendered?;
+
+        // The next cold start prefetches what this render showed (best effort)
+        if !args.no_cache && !args.no_save {
+            let _ = cache.save_prefetch_hint(&cache_path, args.max_depth);
+        }
+    }
+
+    if args.summary_only {
+        // A cache hit leaves the snapshot untouched and its entries unloaded
+        let current = match (&previous_totals, debug_info.cache_used) {
+            (Some(previous), true) => previous.clone(),
+            _ => {
+                SnapshotTotals {
+                    // Spilled directories left memory once they were saved
+                    directories: debug_info.total_dirs,
+                    ..cache.totals()
+                }
+            }
+        };
+        print_summary(&debug_info, previous_totals.as_ref(), &current, &cache, program_start.elapsed());
     }
 
     // ========================================================================
@@ -133,41 +283,268 @@ fn main() -> Result<()> {
         eprintln!("{}", cache.get_skip_report());
     }
 
+    // Directories the scans that last reached them could not read
+    if args.errors {
+        eprintln!("{}", cache.get_error_report());
+    }
+
+    // JSON stats are one object for scripts; the text reports stay with the text summary
+    let text_stats = args.stats && !matches!(args.format, OutputFormat::Json);
+
+    // Package stores (nix, pnpm) count shared hard-linked content once; show the reuse
+    if text_stats && !cache.store_usage.is_empty() {
+        eprintln!("{}", cache.get_store_report());
+    }
+
+    // Subvolumes and datasets, with the usage btrfs quotas or ZFS report for each
+    if text_stats && !cache.subvolumes.is_empty() {
+        eprintln!("{}", cache.get_subvolume_report());
+    }
+
     // ========================================================================
     // Statistics Output (Final Summary)
     // ========================================================================
 
-    if args.stats {
-        let total_elapsed = program_start.elapsed();
-        print_debug_summary(
-            &debug_info,
-            cache_load_elapsed,
-            formatting_elapsed,
-            output_elapsed,
-            &cache_path,
-            total_elapsed,
-        );
+    let timings = PhaseTimings {
+        cache_load: cache_load_elapsed,
+        formatting: formatting_elapsed,
+        output:     output_elapsed,
+        total:      program_start.elapsed(),
+    };
+    if text_stats {
+        print_debug_summary(&debug_info, &timings, &cache_path);
+    } else if args.stats {
+        // Scripts reading `--quiet --stats --format json` get the object alone on stdout
+        let stats = stats_json(&debug_info, &timings, &cache_path);
+        if print_tree || args.summary_only {
+            eprintln!("{stats}");
+        } else {
+            println!("{stats}");
+        }
     }
 
     Ok(())
 }
 
+/// Send log events to stderr, or as JSON lines to `--log-file`, at `--log-level`; spans log
+/// how long they took when they close. Without either flag nothing is logged.
+fn init_logging(args: &Args) -> Result<()> {
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let level = match (args.log_level, &args.log_file) {
+        (Some(level), _) => level,
+        (None, Some(_)) => LogLevel::Info,
+        (None, None) => return Ok(()),
+    };
+    let level = match level {
+        LogLevel::Error => tracing::Level::ERROR,
+        LogLevel::Warn => tracing::Level::WARN,
+        LogLevel::Info => tracing::Level::INFO,
+        LogLevel::Debug => tracing::Level::DEBUG,
+        LogLevel::Trace => tracing::Level::TRACE,
+    };
+    let logger = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(FmtSpan::CLOSE);
+    match &args.log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Cannot open log file {}", path.display()))?;
+            logger.json().with_writer(Mutex::new(file)).init();
+        }
+        None => {
+            logger
+                .with_ansi(atty::is(atty::Stream::Stderr))
+                .with_writer(io::stderr)
+                .init()
+        }
+    }
+    Ok(())
+}
+
+/// Interrupt the scan behind `control` on the first Ctrl-C (`ScanControl::interrupt`). A second
+/// one, or one once the scan is over, exits at once, unless the pager is showing the tree: then
+/// Ctrl-C is the pager's.
+fn interrupt_on_ctrl_c(control: Weak<ScanControl>) {
+    // The handler is installed once; a rescan after a corrupt cache only points it at its scan
+    static SCAN: Mutex<Weak<ScanControl>> = Mutex::new(Weak::new());
+    static INSTALLED: Once = Once::new();
+
+    *SCAN.lock().unwrap_or_else(PoisonError::into_inner) = control;
+    INSTALLED.call_once(|| {
+        let installed = ctrlc::set_handler(|| {
+            if pager::is_paging() {
+                return;
+            }
+            let control = SCAN.lock().unwrap_or_else(PoisonError::into_inner).upgrade();
+            match control {
+                Some(control) if !control.is_cancelled() => {
+                    eprintln!("\nInterrupted: saving what was read so far (Ctrl-C again to quit at once)");
+                    control.interrupt();
+                }
+                _ => std::process::exit(130),
+            }
+        });
+        if let Err(err) = installed {
+            tracing::warn!("Ctrl-C will not save a partial scan: {err}");
+        }
+    });
+}
+
+/// Whether the tree output uses colors (`--color`, or whether stdout is a terminal).
+fn use_colors(args: &Args) -> bool {
+    match args.color {
+        // https://no-color.org: a non-empty NO_COLOR turns off colors unless they are asked for
+        ColorMode::Auto => {
+            args.output.is_none()
+                && atty::is(atty::Stream::Stdout)
+                && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+    }
+}
+
+/// Whether the scan keeps a status line on stderr (`--progress`, or whether stderr is a
+/// terminal and nothing asked for quiet output).
+fn show_progress(args: &Args) -> bool {
+    args.progress
+        .unwrap_or_else(|| atty::is(atty::Stream::Stderr) && !args.quiet && !args.summary_only)
+}
+
+/// Stream the loaded tree to stdout (or `--output`) in the requested format; returns the
+/// formatting and output time. Every format is written as it is made, so the two are told apart
+/// by timing the writes that reach the terminal, pager or file: the rest of the render is formatting.
+fn render(cache: &DiskCache, args: &Args, use_colors: bool) -> Result<(Duration, Duration)> {
+    let _span = tracing::info_span!("render", format = ?args.format).entered();
+    let render_start = Instant::now();
+    let split = |output: Duration| (render_start.elapsed().saturating_sub(output), output);
+
+    let Some(path) = &args.output else {
+        // On a terminal, a tree taller than the screen goes through the pager
+        let pager = if args.no_pager {
+            None
+        } else {
+            pager::terminal_height().zip(pager::command())
+        };
+        if let Some((height, command)) = pager {
+            let mut writer = BufWriter::with_capacity(8 << 20, TimedWriter::new(pager::Pager::new(command, height)));
+            write_tree(cache, args, use_colors, &mut writer)?;
+            let TimedWriter { inner: pager, elapsed } = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+            let finish_start = Instant::now();
+            pager.finish()?;
+            return Ok(split(elapsed + finish_start.elapsed()));
+        }
+
+        // Buffer stdout to minimize write(2) syscalls; 8 MiB keeps flushes rare even for huge trees.
+        let stdout = io::stdout();
+        let mut writer = BufWriter::with_capacity(8 << 20, TimedWriter::new(stdout.lock()));
+        write_tree(cache, args, use_colors, &mut writer)?;
+        writer.flush()?;
+        return Ok(split(writer.get_ref().elapsed));
+    };
+
+    // Readers of the file (a web server, the next cron job) see the old tree or the new one, never half
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    let written = write_tree_file(cache, args, use_colors, &temp_path)
+        .and_then(|elapsed| Ok((std::fs::rename(&temp_path, path)?, elapsed)));
+    match written {
+        Ok(((), elapsed)) => Ok(split(elapsed)),
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(err.context(format!("could not write {}", path.display())))
+        }
+    }
+}
+
+/// Write the tree to a new file at `path`, on disk before it returns; returns the time spent
+/// writing and syncing it.
+fn write_tree_file(cache: &DiskCache, args: &Args, use_colors: bool, path: &Path) -> Result<Duration> {
+    let mut writer = BufWriter::with_capacity(8 << 20, TimedWriter::new(std::fs::File::create(path)?));
+    write_tree(cache, args, use_colors, &mut writer)?;
+    let TimedWriter { inner: file, elapsed } = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    let sync_start = Instant::now();
+    file.sync_all()?;
+    Ok(elapsed + sync_start.elapsed())
+}
+
+/// A writer that adds up the time its writes and flushes take, for the `Output Time` of `--stats`.
+struct TimedWriter<W> {
+    inner:   W,
+    elapsed: Duration,
+}
+
+impl<W> TimedWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl<W: Write> Write for TimedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = Instant::now();
+        let written = self.inner.write(buf);
+        self.elapsed += start.elapsed();
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let start = Instant::now();
+        let flushed = self.inner.flush();
+        self.elapsed += start.elapsed();
+        flushed
+    }
+}
+
+fn write_tree(cache: &DiskCache, args: &Args, use_colors: bool, writer: &mut impl Write) -> Result<()> {
+    match args.format {
+        OutputFormat::Tree => {
+            if args.accessible {
+                cache.write_accessible_output(writer, args.max_depth, args.size, args.file_count)?
+            } else if use_colors {
+                cache.write_colored_tree_output_with_options(writer, args.max_depth, args.size, args.file_count)?
+            } else {
+                cache.write_tree_output_with_options(writer, args.max_depth, args.size, args.file_count)?
+            }
+            // Like `tree`, the listing ends with the totals, taken from the cache rather than a walk
+            if !args.no_report {
+                writeln!(writer, "\n{}", cache.report_line())?;
+            }
+        }
+        OutputFormat::Json => {
+            // Small subtrees are still serialized in parallel, into buffers written in order
+            cache.write_json_output(writer, args.max_depth, args.size, args.file_count)?;
+            writer.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
 /// Format duration in both milliseconds and picoseconds
-fn format_duration(duration: std::time::Duration) -> String {
+fn format_duration(duration: Duration) -> String {
     let ms = duration.as_secs_f64() * 1000.0;
     let ps = duration.as_secs_f64() * 1_000_000_000_000.0;
     format!("{:.3} MS | {:.3} PS", ms, ps)
 }
 
+/// How long the phases outside the scan took, for `--stats`.
+struct PhaseTimings {
+    cache_load: Duration,
+    formatting: Duration,
+    output:     Duration,
+    total:      Duration,
+}
+
 /// Print formatted debug summary
-fn print_debug_summary(
-    debug_info: &ptree_traversal::DebugInfo,
-    cache_load_time: std::time::Duration,
-    formatting_time: std::time::Duration,
-    output_time: std::time::Duration,
-    cache_path: &std::path::Path,
-    total_time: std::time::Duration,
-) {
+fn print_debug_summary(debug_info: &ptree_traversal::DebugInfo, timings: &PhaseTimings, cache_path: &Path) {
     eprintln!("\n{}", "=".repeat(70));
     eprintln!("{:^70}", "PERFORMANCE DEBUG INFO");
     eprintln!("{}", "=".repeat(70));
@@ -190,25 +567,264 @@ fn print_debug_summary(
     eprintln!("\n{:<40} {}", "Directories Scanned:", format_number(debug_info.total_dirs));
     eprintln!("{:<40} {}", "Files Scanned:", format_number(debug_info.total_files));
     eprintln!("{:<40} {}", "Threads Used:", debug_info.threads_used);
+    if debug_info.read_from_mft {
+        eprintln!("{:<40} NTFS MFT", "Traversal Backend:");
+    } else if !debug_info.cache_used {
+        if debug_info.used_uring {
+            eprintln!("{:<40} io_uring", "Traversal Backend:");
+        }
+        eprintln!("{:<40} {}", "Peak Queue Depth:", format_number(debug_info.peak_queue_depth));
+        eprintln!("{:<40} {}", "Directories Stolen:", format_number(debug_info.stolen_dirs));
+    }
+    if debug_info.skipped_slow_dirs > 0 {
+        eprintln!("{:<40} {}", "Slow Directories Skipped:", format_number(debug_info.skipped_slow_dirs));
+    }
+    if debug_info.unchanged_dirs > 0 {
+        eprintln!("{:<40} {}", "Unchanged Directories Kept:", format_number(debug_info.unchanged_dirs));
+    }
 
-    eprintln!("\n{:<40} {}", "Cache Load Time:", format_duration(cache_load_time));
+    eprintln!("\n{:<40} {}", "Cache Load Time:", format_duration(timings.cache_load));
     if debug_info.cache_used || !debug_info.lazy_load_time.is_zero() {
         eprintln!("{:<40} {}", "Lazy Load Time:", format_duration(debug_info.lazy_load_time));
     }
+    if debug_info.prefetched > 0 {
+        eprintln!("{:<40} {}", "Prefetched Entries:", format_number(debug_info.prefetched));
+    }
+    if debug_info.spilled_dirs > 0 {
+        eprintln!("{:<40} {}", "Spilled Directories:", format_number(debug_info.spilled_dirs));
+    }
     if !debug_info.cache_used {
         eprintln!("{:<40} {}", "Traversal Time:", format_duration(debug_info.traversal_time));
         eprintln!("{:<40} {}", "Cache Index Time:", format_duration(debug_info.cache_index_time));
         eprintln!("{:<40} {}", "Cache Save Time:", format_duration(debug_info.save_time));
     }
-    eprintln!("{:<40} {}", "Formatting Time:", format_duration(formatting_time));
-    eprintln!("{:<40} {}", "Output Time:", format_duration(output_time));
-    eprintln!("{:<40} {}", "Total Time:", format_duration(total_time));
+    eprintln!("{:<40} {}", "Formatting Time:", format_duration(timings.formatting));
+    eprintln!("{:<40} {}", "Output Time:", format_duration(timings.output));
+    eprintln!("{:<40} {}", "Total Time:", format_duration(timings.total));
 
     eprintln!("\n{:<40} {}", "Cache Location:", cache_path.display());
     eprintln!("{}", "=".repeat(70));
     eprintln!();
 }
 
+/// `--stats --format json`: what `print_debug_summary` shows, as one object with the
+/// timings in milliseconds.
+fn stats_json(debug_info: &ptree_traversal::DebugInfo, timings: &PhaseTimings, cache_path: &Path) -> serde_json::Value {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let scanned = !debug_info.cache_used;
+    serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "mode": scan_mode(debug_info),
+        "scan_root": debug_info.scan_root,
+        "cache_path": cache_path,
+        "cache_used": debug_info.cache_used,
+        "first_run": debug_info.is_first_run,
+        "incremental_refresh": debug_info.incremental_refresh,
+        "directories": debug_info.total_dirs,
+        "files": debug_info.total_files,
+        "threads": debug_info.threads_used,
+        "unreadable_dirs": debug_info.unreadable_dirs,
+        "unchanged_dirs": debug_info.unchanged_dirs,
+        "skipped_slow_dirs": debug_info.skipped_slow_dirs,
+        "prefetched": debug_info.prefetched,
+        "spilled_dirs": debug_info.spilled_dirs,
+        "peak_queue_depth": debug_info.peak_queue_depth,
+        "stolen_dirs": debug_info.stolen_dirs,
+        "backend": if debug_info.read_from_mft {
+            "mft"
+        } else if debug_info.used_uring {
+            "io_uring"
+        } else {
+            "dfs"
+        },
+        "timings_ms": {
+            "cache_load": ms(timings.cache_load),
+            "lazy_load": ms(debug_info.lazy_load_time),
+            "traversal": scanned.then(|| ms(debug_info.traversal_time)),
+            "cache_index": scanned.then(|| ms(debug_info.cache_index_time)),
+            "cache_save": scanned.then(|| ms(debug_info.save_time)),
+            "formatting": ms(timings.formatting),
+            "output": ms(timings.output),
+            "total": ms(timings.total),
+        },
+    })
+}
+
+/// `--scheduled`: take the lock every scheduled refresh of this user (or of root, machine-wide)
+/// shares, so only one refresh runs at a time, then log how the run ended for `ptree scheduler status`
+/// (nobody watches its output).
+#[cfg(feature = "scheduler")]
+fn scheduled_refresh(args: &Args, program_start: Instant) -> Result<()> {
+    use scheduler::RunStatus;
+
+    let started = chrono::Utc::now();
+    let result = match scheduler::MachineRefreshLock::try_acquire() {
+        Ok(Some(lock)) => {
+            scan_and_render(args, program_start)
+                .and_then(|()| lock.finish())
+                .map(|()| RunStatus::Succeeded)
+        }
+        Ok(None) => {
+            println!("Skipped: {}", scheduler::refresh_in_progress_message());
+            Ok(RunStatus::Skipped)
+        }
+        Err(err) => Err(err),
+    };
+
+    let record = scheduler::RunRecord {
+        started,
+        status: *result.as_ref().unwrap_or(&RunStatus::Failed),
+        exit_code: if result.is_ok() { 0 } else { 1 },
+        duration_ms: program_start.elapsed().as_millis() as u64,
+        error: result.as_ref().err().map(|err| format!("{err:#}")),
+    };
+    let logged = ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())
+        .and_then(|cache_path| scheduler::append_run(&cache_path, &record));
+    if let Err(err) = logged {
+        tracing::warn!("could not log the scheduled run: {err:#}");
+    }
+    result.map(drop)
+}
+
+/// Append the totals of the scan just saved to the history of its cache; a failure only
+/// costs the history line.
+fn record_history(cache: &DiskCache, debug_info: &ptree_traversal::DebugInfo, cache_path: &Path) {
+    let totals = match cache.persisted_totals(cache_path) {
+        Ok(Some(totals)) => totals,
+        Ok(None) => return,
+        Err(err) => {
+            tracing::warn!("could not record the scan in the history: {err:#}");
+            return;
+        }
+    };
+    let duration = debug_info.traversal_time + debug_info.cache_index_time + debug_info.save_time;
+    let record = ptree_cache::HistoryRecord {
+        time:            cache.last_scan,
+        directories:     debug_info.total_dirs,
+        files:           totals.files,
+        bytes:           totals.bytes,
+        allocated_bytes: totals.allocated_bytes,
+        duration_ms:     duration.as_millis() as u64,
+        mode:            scan_mode(debug_info).to_string(),
+    };
+    if let Err(err) = ptree_cache::append_history(cache_path, &record) {
+        tracing::warn!("could not record the scan in the history: {err:#}");
+    }
+}
+
+/// `--metrics-textfile`: the saved snapshot's metrics, rewritten after every run; a failure
+/// only costs this run's update.
+fn write_metrics(cache: &DiskCache, debug_info: &ptree_traversal::DebugInfo, cache_path: &Path, metrics_path: &Path) {
+    let written = cache.persisted_totals(cache_path).and_then(|totals| {
+        let totals = totals.unwrap_or_else(|| cache.totals());
+        let text = metrics::render(cache, &totals, cache_path, Some(debug_info.cache_used));
+        metrics::write_textfile(metrics_path, &text)
+    });
+    if let Err(err) = written {
+        eprintln!("warning: could not write metrics to {}: {err:#}", metrics_path.display());
+    }
+}
+
+/// How a run got its tree: `cache hit`, `first scan`, `incremental refresh`, `refresh` or `rescan`.
+fn scan_mode(debug_info: &ptree_traversal::DebugInfo) -> &'static str {
+    if debug_info.cache_used {
+        "cache hit"
+    } else if debug_info.incremental_refresh {
+        "incremental refresh"
+    } else if debug_info.is_first_run {
+        "first scan"
+    } else if debug_info.unchanged_dirs > 0 {
+        "refresh"
+    } else {
+        "rescan"
+    }
+}
+
+/// `--summary-only` digest: the few lines a scheduled run leaves in its log
+fn print_summary(
+    debug_info: &ptree_traversal::DebugInfo,
+    previous: Option<&SnapshotTotals>,
+    current: &SnapshotTotals,
+    cache: &DiskCache,
+    elapsed: Duration,
+) {
+    let mode = scan_mode(debug_info);
+    // Compressed and sparse files (or block rounding) make the volume's usage differ from the file sizes
+    let on_disk = if current.allocated_bytes != current.bytes {
+        format!(", {} on disk", DiskCache::format_size(current.allocated_bytes))
+    } else {
+        String::new()
+    };
+    println!(
+        "ptree: {} directories, {} files, {}{} ({}, {:.2} s)",
+        format_number(current.directories),
+        format_number(current.files),
+        DiskCache::format_size(current.bytes),
+        on_disk,
+        mode,
+        elapsed.as_secs_f64()
+    );
+
+    match previous {
+        Some(previous) => {
+            println!(
+                "Since last snapshot: {} directories, {} files, {}",
+                signed_count(current.directories, previous.directories),
+                signed_count(current.files, previous.files),
+                signed_size(current.bytes, previous.bytes)
+            );
+            if let Some((path, growth)) = current.largest_growth(previous) {
+                println!("Largest growth: {} (+{})", path.display(), DiskCache::format_size(growth));
+            }
+        }
+        None => println!("Since last snapshot: no previous snapshot"),
+    }
+
+    let new_dirs = cache.new_dirs_by_size();
+    if !new_dirs.is_empty() {
+        let listed: Vec<String> = new_dirs
+            .iter()
+            .take(5)
+            .map(|(path, size)| format!("{} ({})", path.display(), DiskCache::format_size(*size)))
+            .collect();
+        let more = new_dirs.len().saturating_sub(listed.len());
+        let more = if more > 0 {
+            format!(" and {} more", more)
+        } else {
+            String::new()
+        };
+        println!("New directories: {}{}", listed.join(", "), more);
+    }
+
+    if debug_info.skipped_slow_dirs > 0 {
+        println!(
+            "Slow directories kept as last seen: {} (read them with --include-slow)",
+            format_number(debug_info.skipped_slow_dirs)
+        );
+    }
+
+    match debug_info.unreadable_dirs {
+        0 => println!("Errors: none"),
+        count => println!("Errors: {} directories could not be read", format_number(count)),
+    }
+}
+
+fn signed_count(current: usize, previous: usize) -> String {
+    if current >= previous {
+        format!("+{}", format_number(current - previous))
+    } else {
+        format!("-{}", format_number(previous - current))
+    }
+}
+
+fn signed_size(current: u64, previous: u64) -> String {
+    if current >= previous {
+        format!("+{}", DiskCache::format_size(current - previous))
+    } else {
+        format!("-{}", DiskCache::format_size(previous - current))
+    }
+}
+
 /// Format large numbers with thousands separator
 fn format_number(n: usize) -> String {
     let s = n.to_string();
@@ -221,3 +837,277 @@ fn format_number(n: usize) -> String {
     }
     result
 }
+
+/// Move caches written by earlier versions to this version's layout and report what was
+/// done; a failure only costs the old cache, never the run.
+fn migrate_caches(args: &Args) {
+    let Ok(base) = ptree_cache::get_cache_path_custom(args.cache_dir.as_deref()) else {
+        return;
+    };
+    let Some(cache_dir) = base.parent() else {
+        return;
+    };
+    match ptree_cache::migrate_caches(cache_dir) {
+        Ok(migrations) => {
+            for migration in migrations {
+                eprintln!("Cache migration: {}", migration.describe());
+            }
+        }
+        Err(err) => tracing::warn!(dir = %cache_dir.display(), "could not migrate the old cache: {err:#}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn debug_info() -> ptree_traversal::DebugInfo {
+        ptree_traversal::DebugInfo {
+            is_first_run:        false,
+            incremental_refresh: false,
+            scan_root:           PathBuf::from("/data"),
+            cache_used:          false,
+            lazy_load_time:      Duration::from_millis(1),
+            traversal_time:      Duration::from_millis(20),
+            save_time:           Duration::from_millis(3),
+            cache_index_time:    Duration::from_millis(2),
+            total_dirs:          12,
+            total_files:         340,
+            threads_used:        4,
+            unreadable_dirs:     0,
+            prefetched:          0,
+            spilled_dirs:        0,
+            peak_queue_depth:    5,
+            stolen_dirs:         1,
+            read_from_mft:       false,
+            skipped_slow_dirs:   0,
+            used_uring:          false,
+            unchanged_dirs:      0,
+        }
+    }
+
+    #[test]
+    fn scan_mode_names_how_the_tree_was_read() {
+        let cold = ptree_traversal::DebugInfo {
+            is_first_run: true,
+            ..debug_info()
+        };
+        let cached = ptree_traversal::DebugInfo {
+            cache_used: true,
+            ..debug_info()
+        };
+        let incremental = ptree_traversal::DebugInfo {
+            incremental_refresh: true,
+            ..debug_info()
+        };
+        let refresh = ptree_traversal::DebugInfo {
+            unchanged_dirs: 7,
+            ..debug_info()
+        };
+        assert_eq!(scan_mode(&cold), "first scan");
+        assert_eq!(scan_mode(&cached), "cache hit");
+        assert_eq!(scan_mode(&incremental), "incremental refresh");
+        assert_eq!(scan_mode(&refresh), "refresh");
+        assert_eq!(scan_mode(&debug_info()), "rescan");
+    }
+
+    #[test]
+    fn timed_writer_counts_only_the_time_spent_writing() {
+        struct Slow(Vec<u8>);
+        impl Write for Slow {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                std::thread::sleep(Duration::from_millis(5));
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = TimedWriter::new(Slow(Vec::new()));
+        std::thread::sleep(Duration::from_millis(50));
+        writer.write_all(b"tree\n").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.inner.0, b"tree\n");
+        assert!(writer.elapsed >= Duration::from_millis(5));
+        assert!(writer.elapsed < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn stats_json_has_every_field_and_times_only_what_ran() {
+        let timings = PhaseTimings {
+            cache_load: Duration::from_millis(4),
+            formatting: Duration::from_millis(6),
+            output:     Duration::from_millis(8),
+            total:      Duration::from_millis(40),
+        };
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+
+        let cold = ptree_traversal::DebugInfo {
+            is_first_run: true,
+            ..debug_info()
+        };
+        let stats = stats_json(&cold, &timings, Path::new("/cache/ptree.dat"));
+        assert_eq!(
+            keys(&stats),
+            [
+                "backend",
+                "cache_path",
+                "cache_used",
+                "directories",
+                "files",
+                "first_run",
+                "incremental_refresh",
+                "mode",
+                "peak_queue_depth",
+                "prefetched",
+                "scan_root",
+                "skipped_slow_dirs",
+                "spilled_dirs",
+                "stolen_dirs",
+                "threads",
+                "timestamp",
+                "timings_ms",
+                "unchanged_dirs",
+                "unreadable_dirs",
+            ]
+        );
+        assert_eq!(
+            keys(&stats["timings_ms"]),
+            [
+                "cache_index",
+                "cache_load",
+                "cache_save",
+                "formatting",
+                "lazy_load",
+                "output",
+                "total",
+                "traversal"
+            ]
+        );
+        assert_eq!(stats["mode"], "first scan");
+        assert_eq!(stats["backend"], "dfs");
+        assert_eq!((stats["directories"].as_u64(), stats["files"].as_u64()), (Some(12), Some(340)));
+        assert_eq!(stats["timings_ms"]["traversal"], 20.0);
+        assert_eq!(stats["timings_ms"]["total"], 40.0);
+
+        let incremental = ptree_traversal::DebugInfo {
+            incremental_refresh: true,
+            ..debug_info()
+        };
+        let stats = stats_json(&incremental, &timings, Path::new("/cache/ptree.dat"));
+        assert_eq!(
+            (stats["mode"].as_str(), stats["incremental_refresh"].as_bool()),
+            (Some("incremental refresh"), Some(true))
+        );
+        assert_eq!(stats["timings_ms"]["cache_save"], 3.0);
+
+        // A cache hit read nothing, so the scan phases are null rather than zero
+        let cached = ptree_traversal::DebugInfo {
+            cache_used: true,
+            ..debug_info()
+        };
+        let stats = stats_json(&cached, &timings, Path::new("/cache/ptree.dat"));
+        assert_eq!((stats["mode"].as_str(), stats["cache_used"].as_bool()), (Some("cache hit"), Some(true)));
+        for phase in ["traversal", "cache_index", "cache_save"] {
+            assert!(stats["timings_ms"][phase].is_null(), "{phase}");
+        }
+        assert_eq!(stats["timings_ms"]["cache_load"], 4.0);
+    }
+
+    #[test]
+    fn size_filters_render_only_directories_within_the_range() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_size_filters_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let root = temp_dir.join("tree");
+        for (dir, size) in [("big/huge", 3000), ("big/tiny", 10), ("small", 500)] {
+            std::fs::create_dir_all(root.join(dir))?;
+            std::fs::write(root.join(dir).join("data.bin"), vec![b'x'; size])?;
+        }
+        std::fs::write(root.join("notes.txt"), b"notes")?;
+        let output = temp_dir.join("tree.txt");
+
+        let render = |filters: &[&str]| -> Result<Vec<String>> {
+            let mut command_line = vec![
+                "ptree",
+                "--apparent-size",
+                "--color",
+                "never",
+                "--no-report",
+                "--no-pager",
+            ];
+            command_line.extend(filters);
+            let mut args = Args::parse_from(command_line);
+            args.paths = vec![root.clone()];
+            args.cache_dir = Some(temp_dir.join("cache").display().to_string());
+            args.output = Some(output.clone());
+            args.force = true;
+            scan_and_render(&args, Instant::now())?;
+            // The root line is the temp path; the listing below it is what the filters decide
+            Ok(std::fs::read_to_string(&output)?
+                .lines()
+                .skip(1)
+                .map(str::to_string)
+                .collect())
+        };
+
+        assert_eq!(render(&["--min-size", "1K"])?, ["└── big", "    └── huge"]);
+        // big (3010 bytes) is over the limit, so tiny inside it is hidden with it
+        assert_eq!(render(&["--max-size", "600"])?, ["└── small"]);
+        assert_eq!(render(&["--min-size", "100", "--max-size", "3000"])?, ["└── small"]);
+        // Without a filter the files are listed again
+        assert!(render(&[])?.iter().any(|line| line.ends_with("── notes.txt")));
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn log_file_gets_the_rescan_reasons_as_json_lines() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("ptree_test_log_file_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(&temp_dir)?;
+        let log_file = temp_dir.join("ptree.log");
+
+        let mut args = Args::parse_from(["ptree"]);
+        // Without a level or a file nothing is installed
+        init_logging(&args)?;
+        args.log_level = Some(LogLevel::Debug);
+        args.log_file = Some(log_file.clone());
+        init_logging(&args)?;
+
+        for reason in ["mtime changed", "not in the snapshot"] {
+            tracing::debug!(path = "/data/src", reason, "reading the directory again");
+        }
+        tracing::info!(reason = "--force", "not using the snapshot");
+        tracing::trace!(reason = "below the level", "reading the directory again");
+
+        // Other tests may log into the same file; keep the lines of these events
+        let reasons: Vec<String> = std::fs::read_to_string(&log_file)?
+            .lines()
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|line| line["fields"]["reason"].is_string())
+            .map(|line| format!("{} {}", line["level"].as_str().unwrap_or_default(), line["fields"]["reason"]))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                "DEBUG \"mtime changed\"",
+                "DEBUG \"not in the snapshot\"",
+                "INFO \"--force\""
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        Ok(())
+    }
+}
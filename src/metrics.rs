@@ -0,0 +1,114 @@
+//! Prometheus text-format metrics of a cache's last scan: written after a run by
+//! `--metrics-textfile` for node_exporter's textfile collector, and served on `/metrics` by
+//! `ptree serve`.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ptree_cache::{read_history, snapshot_disk_size, DiskCache, SnapshotTotals};
+
+/// The metrics of the snapshot at `cache_path`. The scan duration is that of the latest scan
+/// in the history (`ptree history`); `cache_hit` says whether this run was served from the
+/// cache, and is left out when there was no run (`/metrics`).
+pub fn render(cache: &DiskCache, totals: &SnapshotTotals, cache_path: &Path, cache_hit: Option<bool>) -> String {
+    let roots: Vec<String> = cache
+        .top_level_roots()
+        .iter()
+        .map(|root| root.display().to_string())
+        .collect();
+    let labels = format!("{{roots=\"{}\"}}", escape_label(&roots.join(",")));
+    let last_scan = read_history(cache_path)
+        .ok()
+        .and_then(|history| history.last().cloned());
+
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, value: f64| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(out, "{name}{labels} {value}");
+    };
+    gauge(
+        "ptree_last_scan_timestamp_seconds",
+        "When the cached snapshot was last saved by a scan.",
+        cache.last_scan.timestamp_millis() as f64 / 1000.0,
+    );
+    if let Some(scan) = &last_scan {
+        gauge(
+            "ptree_scan_duration_seconds",
+            "Time the latest scan spent reading, indexing and saving.",
+            scan.duration_ms as f64 / 1000.0,
+        );
+    }
+    gauge("ptree_directories", "Directories in the cached snapshot.", totals.directories as f64);
+    gauge("ptree_files", "Files in the cached snapshot.", totals.files as f64);
+    gauge("ptree_size_bytes", "Total size of the files in the cached snapshot.", totals.bytes as f64);
+    gauge(
+        "ptree_allocated_bytes",
+        "Bytes allocated on disk for the files in the cached snapshot.",
+        totals.allocated_bytes as f64,
+    );
+    gauge(
+        "ptree_unreadable_directories",
+        "Directories the scans that last reached them could not read.",
+        cache.scan_errors.len() as f64,
+    );
+    gauge(
+        "ptree_cache_size_bytes",
+        "Size of the snapshot files on disk.",
+        snapshot_disk_size(cache_path) as f64,
+    );
+    if let Some(cache_hit) = cache_hit {
+        gauge(
+            "ptree_last_run_cache_hit",
+            "1 if the last run was served from the cache, 0 if it scanned.",
+            cache_hit as u8 as f64,
+        );
+    }
+    out
+}
+
+/// Replace the metrics file at `path` in one rename, so the collector never reads half of it.
+pub fn write_textfile(path: &Path, metrics: &str) -> Result<()> {
+    // node_exporter only reads `*.prom`, so the temp file is skipped until it is renamed
+    let temp_path = path.with_extension("prom.tmp");
+    fs::write(&temp_path, metrics).with_context(|| format!("could not write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| format!("could not replace {}", path.display()))?;
+    Ok(())
+}
+
+/// A label value with `\`, `"` and newlines escaped, as the text format requires.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn metrics_are_gauges_labelled_with_the_roots() {
+        let cache_path = std::env::temp_dir().join("ptree_metrics_test_missing.dat");
+        let mut cache = DiskCache::open(&cache_path).unwrap();
+        cache.roots = vec![PathBuf::from("/data"), PathBuf::from(r#"C:\Users\"quoted""#)];
+        let totals = SnapshotTotals {
+            directories: 12,
+            files: 34,
+            bytes: 5678,
+            ..Default::default()
+        };
+
+        let text = render(&cache, &totals, &cache_path, Some(true));
+        let labels = r#"{roots="/data,C:\\Users\\\"quoted\""}"#;
+        assert!(text.contains("# TYPE ptree_directories gauge\n"));
+        assert!(text.contains(&format!("ptree_directories{labels} 12\n")));
+        assert!(text.contains(&format!("ptree_size_bytes{labels} 5678\n")));
+        assert!(text.contains(&format!("ptree_last_run_cache_hit{labels} 1\n")));
+        // No history recorded: no duration to report
+        assert!(!text.contains("ptree_scan_duration_seconds"));
+        assert!(!render(&cache, &totals, &cache_path, None).contains("cache_hit"));
+    }
+}
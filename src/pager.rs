@@ -0,0 +1,246 @@
+//! Paging of tree output on a terminal, as git does it: output that fits the screen is
+//! printed as is, anything longer goes through `$PAGER` (`less -R` by default) with its
+//! colors. `--no-pager` turns this off.
+
+use std::io::{self, Stdout, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::terminal::{self, Stream};
+
+/// Set while the pager runs, for the Ctrl-C handler to leave it alone
+static PAGING: AtomicBool = AtomicBool::new(false);
+
+/// Where the output goes, decided once it is clear whether it fits the screen
+enum State {
+    /// Output so far, shorter than the screen
+    Holding {
+        held:  Vec<u8>,
+        lines: usize,
+    },
+    Direct(Stdout),
+    Paging(Child, Box<IgnoreInterrupts>),
+    /// The pager exited (the user quit it); the rest is dropped
+    Closed,
+}
+
+/// A writer for stdout that starts the pager once the output is taller than the terminal.
+pub struct Pager {
+    state:   State,
+    command: String,
+    height:  usize,
+}
+
+impl Pager {
+    /// Page with `command` (a program and its arguments) through a terminal of `height` rows.
+    pub fn new(command: String, height: usize) -> Self {
+        Pager {
+            state: State::Holding {
+                held:  Vec::new(),
+                lines: 0,
+            },
+            command,
+            height,
+        }
+    }
+
+    /// Print what is still held and wait for the user to quit the pager.
+    pub fn finish(mut self) -> io::Result<()> {
+        match std::mem::replace(&mut self.state, State::Closed) {
+            State::Holding { held, .. } => {
+                let mut stdout = io::stdout();
+                stdout.write_all(&held)?;
+                stdout.flush()
+            }
+            State::Direct(mut stdout) => stdout.flush(),
+            State::Paging(mut child, ignored) => {
+                // Closing its input lets the pager show the end of the output
+                drop(child.stdin.take());
+                let waited = child.wait();
+                // Ctrl-C interrupts ptree again only once the pager has given the terminal back
+                drop(ignored);
+                waited.map(drop)
+            }
+            State::Closed => Ok(()),
+        }
+    }
+
+    /// Start the pager with what is held; stdout is used directly if it cannot be started.
+    fn start(&mut self, held: Vec<u8>) -> io::Result<()> {
+        self.state = match spawn(&self.command) {
+            Ok(mut child) => {
+                let ignored = Box::new(IgnoreInterrupts::start());
+                let written = child.stdin.as_mut().map_or(Ok(()), |stdin| stdin.write_all(&held));
+                match written {
+                    Ok(()) => State::Paging(child, ignored),
+                    Err(err) if err.kind() == io::ErrorKind::BrokenPipe => State::Closed,
+                    Err(err) => return Err(err),
+                }
+            }
+            Err(err) => {
+                tracing::info!("could not start the pager: {err}");
+                let mut stdout = io::stdout();
+                stdout.write_all(&held)?;
+                State::Direct(stdout)
+            }
+        };
+        Ok(())
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            State::Holding { held, lines } => {
+                held.extend_from_slice(buf);
+                *lines += buf.iter().filter(|&&byte| byte == b'\n').count();
+                // The last row stays free for the shell prompt
+                if *lines >= self.height {
+                    let held = std::mem::take(held);
+                    self.start(held)?;
+                }
+                Ok(buf.len())
+            }
+            State::Direct(stdout) => stdout.write(buf),
+            State::Paging(child, _) => {
+                let stdin = child.stdin.as_mut().expect("the pager's input is open until finish");
+                match stdin.write_all(buf) {
+                    Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                        self.state = State::Closed;
+                        Ok(buf.len())
+                    }
+                    result => result.map(|()| buf.len()),
+                }
+            }
+            State::Closed => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            State::Direct(stdout) => stdout.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `$PAGER`, or `less -R`; an empty `$PAGER` (or `cat`) means no pager.
+pub fn command() -> Option<String> {
+    match std::env::var("PAGER") {
+        Ok(pager) if pager.trim().is_empty() || pager.trim() == "cat" => None,
+        Ok(pager) => Some(pager),
+        Err(_) => Some("less -R".to_string()),
+    }
+}
+
+/// Whether a pager is showing the output, during which Ctrl-C belongs to it.
+pub fn is_paging() -> bool {
+    PAGING.load(Ordering::SeqCst)
+}
+
+/// SIGINT and SIGQUIT ignored from the start of the pager until it exits, as git does: Ctrl-C
+/// goes to the pager (`less` stops a search with it), and ptree has to outlive the pager so the
+/// shell gets the terminal back only once the pager is gone.
+struct IgnoreInterrupts {
+    #[cfg(unix)]
+    saved: [(libc::c_int, libc::sigaction); 2],
+}
+
+impl IgnoreInterrupts {
+    fn start() -> Self {
+        PAGING.store(true, Ordering::SeqCst);
+        #[cfg(unix)]
+        {
+            let saved = [libc::SIGINT, libc::SIGQUIT].map(|signal| {
+                // SAFETY: zeroed sigactions are valid to fill in; SIG_IGN needs no handler to stay alive
+                unsafe {
+                    let mut ignore: libc::sigaction = std::mem::zeroed();
+                    ignore.sa_sigaction = libc::SIG_IGN;
+                    let mut previous: libc::sigaction = std::mem::zeroed();
+                    libc::sigaction(signal, &ignore, &mut previous);
+                    (signal, previous)
+                }
+            });
+            IgnoreInterrupts { saved }
+        }
+
+        #[cfg(not(unix))]
+        IgnoreInterrupts {}
+    }
+}
+
+impl Drop for IgnoreInterrupts {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for (signal, previous) in &self.saved {
+            // SAFETY: restores the action sigaction handed back when the signal was ignored
+            unsafe {
+                libc::sigaction(*signal, previous, std::ptr::null_mut());
+            }
+        }
+        PAGING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A pager given as a program and plain arguments is run directly, so a missing one fails here
+/// and the output goes to stdout instead. One that needs a shell (quotes, `$VAR`, pipes) is run
+/// with `sh -c`, like git does.
+fn spawn(pager: &str) -> io::Result<Child> {
+    let mut command = if cfg!(unix) && pager.contains(|c| "|&;<>()$`\\\"'*?[#~=%".contains(c)) {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(pager);
+        command
+    } else {
+        let mut words = pager.split_whitespace();
+        let mut command = Command::new(words.next().unwrap_or_default());
+        command.args(words);
+        command
+    };
+    // `less` as $PAGER still shows the colors, as it does under git
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "R");
+    }
+    command.stdin(Stdio::piped()).spawn()
+}
+
+/// Rows of the terminal stdout is on, if it is one.
+pub fn terminal_height() -> Option<usize> {
+    atty::is(atty::Stream::Stdout).then(|| terminal::rows(Stream::Stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn output_taller_than_the_screen_goes_through_the_pager() -> io::Result<()> {
+        let paged = std::env::temp_dir().join(format!("ptree_pager_test_{}", std::process::id()));
+        // `cp` run directly, writing what it pages to the file and nothing to the test's stdout
+        let mut pager = Pager::new(format!("cp /dev/stdin {}", paged.display()), 3);
+        pager.write_all(b"/srv\n")?;
+        assert!(matches!(pager.state, State::Holding { lines: 1, .. }));
+        pager.write_all("├── a\n└── b\n".as_bytes())?;
+        assert!(matches!(pager.state, State::Paging(..)));
+        assert!(is_paging());
+        pager.write_all("    └── c\n".as_bytes())?;
+        pager.finish()?;
+        assert_eq!(std::fs::read_to_string(&paged)?, "/srv\n├── a\n└── b\n    └── c\n");
+        assert!(!is_paging());
+        std::fs::remove_file(&paged)?;
+
+        // A pager with quoted arguments runs through the shell
+        let quoted = std::env::temp_dir().join(format!("ptree pager test {}", std::process::id()));
+        let mut pager = Pager::new(format!("cp /dev/stdin '{}'", quoted.display()), 1);
+        pager.write_all(b"/srv\n")?;
+        pager.finish()?;
+        assert_eq!(std::fs::read_to_string(&quoted)?, "/srv\n");
+        std::fs::remove_file(&quoted)?;
+
+        // Without the pager, the output still reaches stdout
+        let mut missing = Pager::new("ptree-no-such-pager".to_string(), 1);
+        missing.write_all(b"/srv\n")?;
+        assert!(matches!(missing.state, State::Direct(_)));
+        missing.finish()
+    }
+}
@@ -0,0 +1,121 @@
+//! `--progress`: a status line on stderr, redrawn while a scan runs.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ptree_traversal::ScanControl;
+
+use crate::terminal::{self, Stream};
+
+/// How often the line is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The status line of one scan; `finish` clears it before anything else is printed.
+pub struct StatusLine {
+    done:   Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatusLine {
+    /// Redraw the progress of the scan behind `control` until `finish`.
+    pub fn start(control: Arc<ScanControl>) -> Self {
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new()
+            .name("ptree-progress".to_string())
+            .spawn({
+                let done = Arc::clone(&done);
+                move || redraw_until_done(&control, &done)
+            })
+            .ok();
+        StatusLine { done, thread }
+    }
+
+    /// Stop redrawing and clear the line.
+    pub fn finish(mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn redraw_until_done(control: &ScanControl, done: &AtomicBool) {
+    let start = Instant::now();
+    let mut stderr = io::stderr();
+    let mut drawn = false;
+    while !done.load(Ordering::Relaxed) {
+        thread::park_timeout(REDRAW_INTERVAL);
+        // Cache hits never read a directory: nothing to show
+        let progress = control.progress();
+        if progress.dirs_scanned == 0 || done.load(Ordering::Relaxed) {
+            continue;
+        }
+        let line = status_line(
+            progress.dirs_scanned,
+            control.current_dir().as_deref(),
+            start.elapsed(),
+            terminal::columns(Stream::Stderr),
+        );
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+        drawn = true;
+    }
+    if drawn {
+        let _ = write!(stderr, "\r\x1b[2K");
+        let _ = stderr.flush();
+    }
+}
+
+/// `Scanning  12,345 dirs  1,520 dirs/s  0:08  /home/user/src/...`, cut to `width` columns
+/// by shortening the path from the left.
+fn status_line(dirs_scanned: usize, current: Option<&Path>, elapsed: Duration, width: usize) -> String {
+    let rate = dirs_scanned as f64 / elapsed.as_secs_f64().max(0.001);
+    let secs = elapsed.as_secs();
+    let mut line = format!(
+        "Scanning  {} dirs  {} dirs/s  {}:{:02}",
+        crate::format_number(dirs_scanned),
+        crate::format_number(rate as usize),
+        secs / 60,
+        secs % 60
+    );
+    let used = line.chars().count();
+    if let Some(current) = current {
+        // Leave the last column free so the line never wraps
+        let room = width.saturating_sub(used + 3);
+        let path = current.display().to_string();
+        let chars = path.chars().count();
+        if room > 1 && chars <= room {
+            line.push_str("  ");
+            line.push_str(&path);
+        } else if room > 1 {
+            line.push_str("  …");
+            line.extend(path.chars().skip(chars - (room - 1)));
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_line_fits_the_terminal_by_shortening_the_path() {
+        let path = Path::new("/home/user/projects/ptree/crates/ptree-traversal/src");
+        let line = status_line(12_345, Some(path), Duration::from_secs(68), 200);
+        assert_eq!(line, format!("Scanning  12,345 dirs  181 dirs/s  1:08  {}", path.display()));
+
+        let line = status_line(12_345, Some(path), Duration::from_secs(68), 60);
+        assert_eq!(line.chars().count(), 59);
+        assert!(line.contains("  …") && line.ends_with("traversal/src"));
+
+        // Too narrow for any of the path
+        let line = status_line(5, Some(path), Duration::from_secs(1), 20);
+        assert_eq!(line, "Scanning  5 dirs  5 dirs/s  0:01");
+    }
+}
@@ -0,0 +1,376 @@
+//! `ptree serve`: the `tree`, `search` and `stats` queries over plain HTTP, answered from the
+//! saved snapshot, for dashboards and scripts that cannot reach the local socket. GET only,
+//! one request per connection, JSON bodies (but `/metrics`, in Prometheus text format). There
+//! is no authentication: anyone who can reach the port can list the tree, so it listens on
+//! localhost unless told otherwise.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ptree_core::JsonSchema;
+use serde_json::{json, Value};
+
+use super::query::{Snapshot, Source};
+
+/// Default `limit` of `/search`
+const SEARCH_LIMIT: usize = 1000;
+/// Levels `/tree` includes without `depth`, so browsing a large tree reads one directory at a time
+const TREE_DEPTH: usize = 1;
+/// Longest request head (request line and headers) read before giving up on a client
+const MAX_HEAD: u64 = 16 * 1024;
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Connections answered at once; later ones wait for a worker (past `WORKERS` more, in the
+/// listen backlog), so clients cannot make the server start threads without limit
+const WORKERS: usize = 8;
+/// Content type of `/metrics`: version 0.0.4 of the Prometheus text format
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Serve the snapshot at `cache_path` on `bind:port` until killed.
+pub fn run(cache_path: &Path, bind: &str, port: u16) -> Result<()> {
+    let listener = TcpListener::bind((bind, port)).with_context(|| format!("could not listen on {bind}:{port}"))?;
+    let source: Arc<dyn Source> = Arc::new(Snapshot::new(cache_path));
+    // Fail now rather than on every request if there is nothing to serve
+    source.stats()?;
+    println!("Serving {} on http://{}", cache_path.display(), listener.local_addr()?);
+    serve(listener, source)
+}
+
+/// Answer the clients of `listener` on a fixed pool of `WORKERS` threads.
+fn serve(listener: TcpListener, source: Arc<dyn Source>) -> Result<()> {
+    let clients = super::workers(WORKERS, source, serve_client);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if clients.send(stream).is_err() {
+                    anyhow::bail!("the server's workers stopped");
+                }
+            }
+            Err(err) => tracing::warn!("server: could not accept a client: {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Read one request and answer it.
+fn serve_client(stream: TcpStream, source: &dyn Source) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream.take(MAX_HEAD));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers carry nothing a query needs, but are read so the client sees its request taken
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let (status, content_type, body) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        [method, target, _version] => respond(source, method, target),
+        _ => json_body(400, error("malformed request line")),
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {status} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        reason(status),
+        body.len()
+    )?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+/// Status, content type and body answering `method target`: `/metrics` as text, the rest
+/// as JSON.
+fn respond(source: &dyn Source, method: &str, target: &str) -> (u16, &'static str, Vec<u8>) {
+    let route = target.split_once('?').map_or(target, |(route, _)| route);
+    if route != "/metrics" {
+        let (status, body) = answer(source, method, target);
+        return json_body(status, body);
+    }
+    if method != "GET" {
+        return json_body(405, error("/metrics only answers GET"));
+    }
+    match source.metrics() {
+        Ok(text) => (200, METRICS_CONTENT_TYPE, text.into_bytes()),
+        Err(err) => json_body(500, error(format!("{err:#}"))),
+    }
+}
+
+fn json_body(status: u16, body: Value) -> (u16, &'static str, Vec<u8>) {
+    let mut bytes = serde_json::to_vec_pretty(&body).unwrap_or_default();
+    bytes.push(b'\n');
+    (status, "application/json", bytes)
+}
+
+/// Status and JSON body answering `method target`.
+fn answer(source: &dyn Source, method: &str, target: &str) -> (u16, Value) {
+    let (route, query) = target.split_once('?').unwrap_or((target, ""));
+    if !matches!(route, "/tree" | "/search" | "/stats") {
+        return (404, error(format!("no such endpoint `{route}` (try /tree, /search, /stats or /metrics)")));
+    }
+    if method != "GET" {
+        return (405, error(format!("{route} only answers GET")));
+    }
+    let params = match parse_query(query) {
+        Ok(params) => Params(params),
+        Err(err) => return (400, error(err)),
+    };
+    let result = match route {
+        "/tree" => tree(source, &params),
+        "/search" => search(source, &params),
+        _ => source.stats().map_err(|err| (500, error(format!("{err:#}")))),
+    };
+    result.map_or_else(|failure| failure, |body| (200, body))
+}
+
+/// A 200 body, or the status and body of a failure
+type Answer = std::result::Result<Value, (u16, Value)>;
+
+/// `/tree?path=&depth=&size=&file_count=&schema=`: the `--format json` object of a cached
+/// directory (default: the first root), with `v1` or `v2` nodes (default: v2, as on the command line).
+fn tree(source: &dyn Source, params: &Params) -> Answer {
+    let path = params.get("path").map(Path::new);
+    let depth = params.number("depth")?.unwrap_or(TREE_DEPTH);
+    let size = params.flag("size")?;
+    let file_count = params.flag("file_count")?;
+    let schema = params
+        .get("schema")
+        .map(|schema| schema.parse::<JsonSchema>().map_err(|err| (400, error(err))))
+        .transpose()?
+        .unwrap_or_default();
+
+    match source.tree(path, Some(depth), size, file_count, schema) {
+        Ok(Some(tree)) => Ok(tree),
+        Ok(None) => {
+            let message = match path {
+                Some(path) => format!("{} is not a cached directory", path.display()),
+                None => "the cache has no roots".to_string(),
+            };
+            Err((404, error(message)))
+        }
+        Err(err) => Err((500, error(format!("{err:#}")))),
+    }
+}
+
+/// `/search?q=&limit=`: matching files and directories, sorted by path.
+fn search(source: &dyn Source, params: &Params) -> Answer {
+    let pattern = params.get("q").ok_or_else(|| (400, error("missing `q`")))?;
+    let limit = params.number("limit")?.unwrap_or(SEARCH_LIMIT);
+
+    // One extra hit tells whether the limit cut the list short
+    let mut hits = source
+        .search(pattern, limit.saturating_add(1))
+        .map_err(|err| (500, error(format!("{err:#}"))))?;
+    let truncated = hits.len() > limit;
+    hits.truncate(limit);
+    Ok(json!({ "hits": hits, "truncated": truncated }))
+}
+
+struct Params(Vec<(String, String)>);
+
+impl Params {
+    /// The last value given for `name`
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn number(&self, name: &str) -> std::result::Result<Option<usize>, (u16, Value)> {
+        self.get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| (400, error(format!("`{name}` must be a non-negative integer"))))
+            })
+            .transpose()
+    }
+
+    /// `size`, `size=true` and `size=1` turn a flag on
+    fn flag(&self, name: &str) -> std::result::Result<bool, (u16, Value)> {
+        match self.get(name) {
+            None | Some("false" | "0") => Ok(false),
+            Some("" | "true" | "1") => Ok(true),
+            Some(_) => Err((400, error(format!("`{name}` must be true or false")))),
+        }
+    }
+}
+
+/// The `key=value` pairs of a query string, percent-decoded (`+` standing for a space).
+fn parse_query(query: &str) -> std::result::Result<Vec<(String, String)>, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> std::result::Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let byte = text
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("bad percent escape in `{text}`"))?;
+                decoded.push(byte);
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| format!("`{text}` does not decode to UTF-8"))
+}
+
+fn error(message: impl Into<String>) -> Value {
+    json!({ "error": message.into() })
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::RwLock;
+    use std::thread;
+
+    use ptree_cache::{testing, DiskCache};
+
+    use super::*;
+    use crate::server::query::Watched;
+
+    fn served_cache() -> Watched {
+        // Nothing is written there; opening a missing cache gives an empty one
+        let mut cache = DiskCache::open(&std::env::temp_dir().join("ptree_http_test_missing.dat")).unwrap();
+        cache.roots = vec![PathBuf::from("/data")];
+        for (path, size, children) in [
+            ("/data", 300, vec!["my logs", "notes.txt"]),
+            ("/data/my logs", 200, vec!["app.log", "err.log"]),
+        ] {
+            let path = PathBuf::from(path);
+            cache.entries.insert(path.clone(), testing::dir(&path, size, &children));
+        }
+        Watched {
+            cache:      Arc::new(RwLock::new(cache)),
+            cache_path: PathBuf::from("/tmp/ptree.dat"),
+        }
+    }
+
+    #[test]
+    fn query_strings_are_percent_decoded() {
+        assert_eq!(
+            parse_query("path=%2Fdata%2Fmy+logs&size&q=%E2%9C%93").unwrap(),
+            vec![
+                ("path".to_string(), "/data/my logs".to_string()),
+                ("size".to_string(), String::new()),
+                ("q".to_string(), "✓".to_string()),
+            ]
+        );
+        assert!(parse_query("q=%2").is_err());
+        assert!(parse_query("q=%FF").is_err());
+    }
+
+    #[test]
+    fn endpoints_answer_with_json_and_status_codes() {
+        let cache = served_cache();
+
+        let (status, tree) = answer(&cache, "GET", "/tree?path=/data/my%20logs&size=true");
+        assert_eq!(status, 200);
+        assert_eq!(tree["path"], "/data/my logs");
+        assert_eq!(tree["size_bytes"], 200);
+        assert_eq!(tree["children"][1]["name"], "err.log");
+
+        // One level by default
+        let (_, root) = answer(&cache, "GET", "/tree");
+        assert_eq!(root["children"][0]["name"], "my logs");
+        assert_eq!(root["children"][0]["children"], json!([]));
+        // v2 nodes unless asked for v1, as on the command line
+        assert_eq!(root["size_bytes"], 300);
+        assert!(answer(&cache, "GET", "/tree?schema=v1").1.get("size_bytes").is_none());
+
+        let (status, found) = answer(&cache, "GET", "/search?q=*.log&limit=1");
+        assert_eq!(status, 200);
+        assert_eq!(found["hits"], json!([{ "path": "/data/my logs/app.log", "is_dir": false }]));
+        assert_eq!(found["truncated"], true);
+
+        let (status, stats) = answer(&cache, "GET", "/stats");
+        assert_eq!(status, 200);
+        assert_eq!(stats["bytes"], 300);
+
+        assert_eq!(answer(&cache, "GET", "/tree?path=/elsewhere").0, 404);
+        assert_eq!(answer(&cache, "GET", "/tree?depth=-1").0, 400);
+        assert_eq!(answer(&cache, "GET", "/tree?schema=v3").0, 400);
+        assert_eq!(answer(&cache, "GET", "/search").0, 400);
+        assert_eq!(answer(&cache, "GET", "/").0, 404);
+        assert_eq!(answer(&cache, "POST", "/stats").0, 405);
+    }
+
+    #[test]
+    fn metrics_are_served_as_prometheus_text() {
+        let cache = served_cache();
+
+        let (status, content_type, body) = respond(&cache, "GET", "/metrics");
+        assert_eq!(status, 200);
+        assert_eq!(content_type, METRICS_CONTENT_TYPE);
+        let text = String::from_utf8(body).unwrap();
+        assert!(text.contains("ptree_directories{roots=\"/data\"} 2\n"));
+        assert!(text.contains("ptree_size_bytes{roots=\"/data\"} 300\n"));
+
+        assert_eq!(respond(&cache, "POST", "/metrics").0, 405);
+        let (status, content_type, _) = respond(&cache, "GET", "/stats");
+        assert_eq!((status, content_type), (200, "application/json"));
+    }
+
+    #[test]
+    fn clients_beyond_the_worker_pool_wait_for_a_worker() -> Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let address = listener.local_addr()?;
+        let source: Arc<dyn Source> = Arc::new(served_cache());
+        thread::spawn(move || serve(listener, source));
+
+        // Every worker is held by a client that has not sent its request yet
+        let idle: Vec<TcpStream> = (0..WORKERS)
+            .map(|_| TcpStream::connect(address))
+            .collect::<io::Result<_>>()?;
+        let asking = thread::spawn(move || -> io::Result<String> {
+            let mut stream = TcpStream::connect(address)?;
+            stream.write_all(b"GET /stats HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        });
+        thread::sleep(Duration::from_millis(200));
+        assert!(!asking.is_finished());
+
+        drop(idle);
+        let response = asking.join().unwrap()?;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        Ok(())
+    }
+}
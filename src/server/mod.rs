@@ -0,0 +1,272 @@
+//! `ptree --watch --serve`: answer queries about the watched tree from the cache the watcher
+//! keeps current, so editor plugins and scripts (`ptree client`) skip loading it themselves.
+//! Each cache file gets its own endpoint: a Unix socket next to it (`ptree-<key>.sock`,
+//! readable by its owner only), or on Windows a local named pipe named after it. A fixed pool of
+//! `CLIENTS` threads answers the connected clients.
+//! `ptree serve` answers the same queries over HTTP from the saved snapshot (`http`).
+
+pub mod http;
+pub mod query;
+pub mod rpc;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+
+use anyhow::{Context, Result};
+use ptree_cache::DiskCache;
+use query::Watched;
+
+#[cfg(unix)]
+pub type Stream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+pub type Stream = std::fs::File;
+
+/// Clients answered at once. Each keeps its worker until it hangs up; later ones wait for a
+/// worker to come free (past `CLIENTS` more, in the listen backlog)
+const CLIENTS: usize = 16;
+
+/// Where the server of the cache at `cache_path` listens.
+pub fn endpoint(cache_path: &Path) -> String {
+    #[cfg(windows)]
+    {
+        // Pipe names are machine-wide, so the name carries the full cache path, user profile included
+        format!(r"\\.\pipe\ptree-{}", ptree_cache::root_cache_key(&[cache_path.to_path_buf()]))
+    }
+    #[cfg(not(windows))]
+    {
+        cache_path.with_extension("sock").display().to_string()
+    }
+}
+
+/// Connect to the server of the cache at `cache_path`.
+pub fn connect(cache_path: &Path) -> io::Result<Stream> {
+    #[cfg(unix)]
+    {
+        Stream::connect(endpoint(cache_path))
+    }
+    #[cfg(windows)]
+    {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(endpoint(cache_path))
+    }
+}
+
+/// Start answering queries in the background on a pool of `CLIENTS` threads.
+pub fn start(cache: Arc<RwLock<DiskCache>>, cache_path: &Path) -> Result<()> {
+    let listener = Listener::bind(cache_path)?;
+    let source = Arc::new(Watched {
+        cache,
+        cache_path: cache_path.to_path_buf(),
+    });
+    let clients = workers(CLIENTS, source, serve_client);
+    thread::spawn(move || {
+        loop {
+            match listener.accept() {
+                Ok(stream) => {
+                    if clients.send(stream).is_err() {
+                        tracing::warn!("server: the workers stopped; no longer accepting clients");
+                        return;
+                    }
+                }
+                Err(err) => tracing::warn!("server: could not accept a client: {err}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Start `count` threads that answer the clients sent on the returned channel with `serve`, one
+/// each at a time. A send blocks once every worker is busy and `count` clients are waiting, so
+/// clients cannot make the server start threads without limit.
+fn workers<S, Q>(count: usize, source: Arc<Q>, serve: fn(S, &Q) -> io::Result<()>) -> mpsc::SyncSender<S>
+where
+    S: Send + 'static,
+    Q: ?Sized + Send + Sync + 'static,
+{
+    let (clients, waiting) = mpsc::sync_channel::<S>(count);
+    let waiting = Arc::new(Mutex::new(waiting));
+    for _ in 0..count {
+        let (waiting, source) = (Arc::clone(&waiting), Arc::clone(&source));
+        thread::spawn(move || {
+            // The lock is held only while waiting for the next client
+            while let Ok(stream) = waiting.lock().unwrap_or_else(PoisonError::into_inner).recv() {
+                if let Err(err) = serve(stream, &source) {
+                    tracing::warn!("server: {err}");
+                }
+            }
+        });
+    }
+    clients
+}
+
+/// Answer request lines until the client hangs up.
+fn serve_client(stream: Stream, source: &Watched) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = rpc::handle(source, &line) {
+            writer.write_all(response.as_bytes())?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+struct Listener(std::os::unix::net::UnixListener);
+
+#[cfg(unix)]
+impl Listener {
+    fn bind(cache_path: &Path) -> Result<Self> {
+        use std::os::unix::net::UnixListener;
+
+        let socket = endpoint(cache_path);
+        if Path::new(&socket).exists() {
+            if Stream::connect(&socket).is_ok() {
+                anyhow::bail!("another ptree is already serving this cache on {socket}");
+            }
+            // Left behind by a server that was killed
+            std::fs::remove_file(&socket).with_context(|| format!("could not remove stale socket {socket}"))?;
+        }
+        // Create the socket owner-only rather than narrowing it after `bind`, which would leave a
+        // moment in which anyone could connect. The umask is process-wide, but this runs before
+        // the watcher starts writing files, and those would only come out stricter.
+        // SAFETY: umask cannot fail
+        let umask = unsafe { libc::umask(0o177) };
+        let bound = UnixListener::bind(&socket);
+        // SAFETY: as above
+        unsafe { libc::umask(umask) };
+        let listener = bound.with_context(|| format!("could not listen on {socket}"))?;
+        Ok(Self(listener))
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        self.0.accept().map(|(stream, _)| stream)
+    }
+}
+
+#[cfg(windows)]
+struct Listener {
+    name:  Vec<u16>,
+    /// Instance created by `bind`, waiting for the first client
+    ready: std::cell::Cell<Option<Stream>>,
+}
+
+#[cfg(windows)]
+impl Listener {
+    fn bind(cache_path: &Path) -> Result<Self> {
+        let pipe = endpoint(cache_path);
+        let name: Vec<u16> = pipe.encode_utf16().chain(std::iter::once(0)).collect();
+        // Only one server may create the first instance of a pipe name
+        let first = Self::create_instance(&name, true).map_err(|err| {
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                anyhow::anyhow!("another ptree is already serving this cache on {pipe}")
+            } else {
+                anyhow::Error::new(err).context(format!("could not create pipe {pipe}"))
+            }
+        })?;
+        Ok(Self {
+            name,
+            ready: std::cell::Cell::new(Some(first)),
+        })
+    }
+
+    fn create_instance(name: &[u16], first: bool) -> io::Result<Stream> {
+        use std::os::windows::io::FromRawHandle;
+
+        use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+        use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+        use windows_sys::Win32::System::Pipes::{
+            CreateNamedPipeW,
+            PIPE_READMODE_BYTE,
+            PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES,
+            PIPE_WAIT,
+        };
+
+        let open_mode = PIPE_ACCESS_DUPLEX | if first { FILE_FLAG_FIRST_PIPE_INSTANCE } else { 0 };
+        // SAFETY: `name` is NUL-terminated; no security attributes means the default DACL
+        // (the creating user, administrators and SYSTEM)
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                64 * 1024,
+                64 * 1024,
+                0,
+                std::ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `handle` is a freshly created pipe instance owned by nothing else
+        Ok(unsafe { Stream::from_raw_handle(handle) })
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        use std::os::windows::io::AsRawHandle;
+
+        use windows_sys::Win32::Foundation::ERROR_PIPE_CONNECTED;
+        use windows_sys::Win32::System::Pipes::ConnectNamedPipe;
+
+        let instance = match self.ready.take() {
+            Some(instance) => instance,
+            None => Self::create_instance(&self.name, false)?,
+        };
+        // SAFETY: `instance` is an open pipe instance; without OVERLAPPED the call blocks
+        let connected = unsafe { ConnectNamedPipe(instance.as_raw_handle(), std::ptr::null_mut()) } != 0;
+        if !connected {
+            let err = io::Error::last_os_error();
+            // A client that connected between creation and this call is connected all the same
+            if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(err);
+            }
+        }
+        Ok(instance)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn socket_is_owner_only_and_answers_queries() {
+        let dir = std::env::temp_dir().join(format!("ptree_server_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache_path = dir.join("ptree.dat");
+        let mut cache = DiskCache::open(&cache_path).unwrap();
+        cache.roots = vec![PathBuf::from("/data")];
+        start(Arc::new(RwLock::new(cache)), &cache_path).unwrap();
+
+        let mode = std::fs::metadata(endpoint(&cache_path)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut client = connect(&cache_path).unwrap();
+        client
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"stats\"}\n")
+            .unwrap();
+        let mut answer = String::new();
+        BufReader::new(&client).read_line(&mut answer).unwrap();
+        let answer: serde_json::Value = serde_json::from_str(&answer).unwrap();
+        assert_eq!(answer["id"], 7);
+        assert_eq!(answer["result"]["roots"][0], "/data");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+//! The queries both servers answer (`tree`, `search`, `stats`), over the cache a `--watch`
+//! keeps in memory or over the snapshot on disk (`ptree serve`).
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use ptree_cache::cache_rkyv::RkyvMmapCache;
+use ptree_cache::{DiskCache, SearchHit, SnapshotTotals};
+use ptree_core::JsonSchema;
+use serde_json::{json, Value};
+
+/// What the queries are answered from.
+pub trait Source: Send + Sync {
+    /// The `--format json` object of the cached directory `path` (default: the first root),
+    /// with nodes of `schema`, or `None` if it is not cached.
+    fn tree(
+        &self,
+        path: Option<&Path>,
+        depth: Option<usize>,
+        size: bool,
+        file_count: bool,
+        schema: JsonSchema,
+    ) -> Result<Option<Value>>;
+
+    /// Matching files and directories, sorted by path, at most `limit` of them.
+    fn search(&self, pattern: &str, limit: usize) -> Result<Vec<SearchHit>>;
+
+    /// What the served cache covers.
+    fn stats(&self) -> Result<Value>;
+
+    /// The served cache's metrics in Prometheus text format (`/metrics` of `ptree serve`).
+    fn metrics(&self) -> Result<String>;
+}
+
+/// The cache a `--watch` keeps current.
+pub struct Watched {
+    pub cache:      Arc<RwLock<DiskCache>>,
+    pub cache_path: PathBuf,
+}
+
+impl Watched {
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, DiskCache> {
+        // A refresh that panicked leaves the last complete state behind, which is still worth serving
+        self.cache.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Source for Watched {
+    fn tree(
+        &self,
+        path: Option<&Path>,
+        depth: Option<usize>,
+        size: bool,
+        file_count: bool,
+        schema: JsonSchema,
+    ) -> Result<Option<Value>> {
+        let cache = self.read();
+        let Some(path) = resolve(&cache, path, |path| cache.get_entry(path).is_some()) else {
+            return Ok(None);
+        };
+        if !cache.get_entry(&path).is_some_and(|entry| entry.is_dir) {
+            return Ok(None);
+        }
+        render(&cache, &path, depth, size, file_count, schema).map(Some)
+    }
+
+    fn search(&self, pattern: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        Ok(self.read().search(pattern, limit))
+    }
+
+    fn stats(&self) -> Result<Value> {
+        let cache = self.read();
+        Ok(stats(&cache, cache.totals(), &self.cache_path))
+    }
+
+    fn metrics(&self) -> Result<String> {
+        let cache = self.read();
+        Ok(crate::metrics::render(&cache, &cache.totals(), &self.cache_path, None))
+    }
+}
+
+/// The snapshot last saved at a cache path, read in place: each query loads only the records
+/// it renders. A newer save (by a scan or a `--watch`) is picked up by the next query.
+pub struct Snapshot {
+    cache_path: PathBuf,
+    opened:     Mutex<Option<(SystemTime, Arc<Opened>)>>,
+}
+
+struct Opened {
+    /// Everything but the entries
+    metadata: DiskCache,
+    records:  RkyvMmapCache,
+}
+
+impl Snapshot {
+    pub fn new(cache_path: &Path) -> Self {
+        Self {
+            cache_path: cache_path.to_path_buf(),
+            opened:     Mutex::new(None),
+        }
+    }
+
+    /// The snapshot as currently saved, reopened if the index changed since the last query.
+    fn current(&self) -> Result<Arc<Opened>> {
+        let index_path = self.cache_path.with_extension("idx");
+        let saved = std::fs::metadata(&index_path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| anyhow::anyhow!("no snapshot at {}: {err}", self.cache_path.display()))?;
+
+        let mut opened = self.opened.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((at, snapshot)) = opened.as_ref() {
+            if *at == saved {
+                return Ok(Arc::clone(snapshot));
+            }
+        }
+        let snapshot = Arc::new(Opened {
+            metadata: DiskCache::open(&self.cache_path)?,
+            records:  RkyvMmapCache::open(&index_path, &self.cache_path.with_extension("dat"))?,
+        });
+        *opened = Some((saved, Arc::clone(&snapshot)));
+        Ok(snapshot)
+    }
+}
+
+impl Source for Snapshot {
+    fn tree(
+        &self,
+        path: Option<&Path>,
+        depth: Option<usize>,
+        size: bool,
+        file_count: bool,
+        schema: JsonSchema,
+    ) -> Result<Option<Value>> {
+        let snapshot = self.current()?;
+        let offsets = &snapshot.records.index.offsets;
+        let Some(path) = resolve(&snapshot.metadata, path, |path| offsets.get(path).is_some()) else {
+            return Ok(None);
+        };
+        if offsets.get(&path).is_none() {
+            return Ok(None);
+        }
+        let mut cache = snapshot.metadata.clone();
+        cache.load_subtree_from(&snapshot.records, &path, depth)?;
+        if !cache.get_entry(&path).is_some_and(|entry| entry.is_dir) {
+            return Ok(None);
+        }
+        render(&cache, &path, depth, size, file_count, schema).map(Some)
+    }
+
+    fn search(&self, pattern: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        self.current()?.records.search(pattern, limit)
+    }
+
+    fn stats(&self) -> Result<Value> {
+        let snapshot = self.current()?;
+        Ok(stats(&snapshot.metadata, snapshot.metadata.totals_from(&snapshot.records), &self.cache_path))
+    }
+
+    fn metrics(&self) -> Result<String> {
+        let snapshot = self.current()?;
+        let totals = snapshot.metadata.totals_from(&snapshot.records);
+        Ok(crate::metrics::render(&snapshot.metadata, &totals, &self.cache_path, None))
+    }
+}
+
+/// `path` as the cache has it (default: the first root). Clients send canonical paths, while
+/// roots are cached as they were given (`--watch ~/src/../src`, a symlinked home).
+fn resolve(cache: &DiskCache, path: Option<&Path>, is_cached: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    let Some(path) = path else {
+        return cache.top_level_roots().into_iter().next();
+    };
+    if is_cached(path) {
+        return Some(path.to_path_buf());
+    }
+    let given = cache.top_level_roots().into_iter().find_map(|root| {
+        let canonical = std::fs::canonicalize(&root).ok()?;
+        let rest = path.strip_prefix(&canonical).ok()?;
+        Some(root.join(rest))
+    });
+    Some(given.unwrap_or_else(|| path.to_path_buf()))
+}
+
+fn render(
+    cache: &DiskCache,
+    path: &Path,
+    depth: Option<usize>,
+    size: bool,
+    file_count: bool,
+    schema: JsonSchema,
+) -> Result<Value> {
+    let mut out = Vec::new();
+    cache.write_json_subtree(&mut out, path, depth, size, file_count, schema)?;
+    Ok(serde_json::from_slice(&out)?)
+}
+
+fn stats(cache: &DiskCache, totals: SnapshotTotals, cache_path: &Path) -> Value {
+    json!({
+        "roots": cache.top_level_roots(),
+        "directories": totals.directories,
+        "files": totals.files,
+        "bytes": totals.bytes,
+        "allocated_bytes": totals.allocated_bytes,
+        "last_scan": cache.last_scan.to_rfc3339(),
+        "cache_path": cache_path,
+    })
+}
@@ -0,0 +1,219 @@
+//! JSON-RPC 2.0 dispatch for `ptree --watch --serve`: one request object per line in, one
+//! response object per line out. Requests without an `id` are notifications and get no answer.
+
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use super::query::Source;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The request was understood but could not be answered (a path that is not cached, ...)
+const SERVER_ERROR: i64 = -32000;
+
+/// Default `limit` of `search`
+const SEARCH_LIMIT: usize = 1000;
+
+struct RpcError {
+    code:    i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Answer one request line from `source`.
+pub fn handle(source: &dyn Source, line: &str) -> Option<String> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, err.to_string())))),
+    };
+    let Some(request) = request.as_object() else {
+        return Some(response(Value::Null, Err(RpcError::new(INVALID_REQUEST, "expected a request object"))));
+    };
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Some(response(id.unwrap_or(Value::Null), Err(RpcError::new(INVALID_REQUEST, "missing method"))));
+    };
+
+    let empty = Map::new();
+    let result = match request.get("params") {
+        None | Some(Value::Null) => call(source, method, &empty),
+        Some(Value::Object(params)) => call(source, method, params),
+        Some(_) => Err(RpcError::new(INVALID_PARAMS, "params must be an object")),
+    };
+    id.map(|id| response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    let body = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": err.code, "message": err.message } }),
+    };
+    body.to_string()
+}
+
+fn call(source: &dyn Source, method: &str, params: &Map<String, Value>) -> Result<Value, RpcError> {
+    match method {
+        "tree" => tree(source, params),
+        "search" => search(source, params),
+        "stats" => source.stats().map_err(server_error),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+    }
+}
+
+fn server_error(err: anyhow::Error) -> RpcError {
+    RpcError::new(SERVER_ERROR, format!("{err:#}"))
+}
+
+/// `tree {path?, depth?, size?, file_count?, schema?}`: the `--format json` object of a cached
+/// directory (default: the first root), with `schema` `v1` or `v2` nodes (default: v2, as on the
+/// command line).
+fn tree(source: &dyn Source, params: &Map<String, Value>) -> Result<Value, RpcError> {
+    let path = param_str(params, "path")?.map(Path::new);
+    let depth = param_u64(params, "depth")?.map(|depth| depth as usize);
+    let size = param_bool(params, "size")?.unwrap_or(false);
+    let file_count = param_bool(params, "file_count")?.unwrap_or(false);
+    let schema = param_str(params, "schema")?
+        .map(|schema| schema.parse().map_err(|err| RpcError::new(INVALID_PARAMS, err)))
+        .transpose()?
+        .unwrap_or_default();
+
+    source
+        .tree(path, depth, size, file_count, schema)
+        .map_err(server_error)?
+        .ok_or_else(|| {
+            match path {
+                Some(path) => RpcError::new(SERVER_ERROR, format!("{} is not a cached directory", path.display())),
+                None => RpcError::new(SERVER_ERROR, "the cache has no roots"),
+            }
+        })
+}
+
+/// `search {pattern, limit?}`: matching files and directories, sorted by path.
+fn search(source: &dyn Source, params: &Map<String, Value>) -> Result<Value, RpcError> {
+    let pattern = param_str(params, "pattern")?.ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing `pattern`"))?;
+    let limit = param_u64(params, "limit")?.map_or(SEARCH_LIMIT, |limit| limit as usize);
+
+    // One extra hit tells whether the limit cut the list short
+    let mut hits = source.search(pattern, limit.saturating_add(1)).map_err(server_error)?;
+    let truncated = hits.len() > limit;
+    hits.truncate(limit);
+    Ok(json!({ "hits": hits, "truncated": truncated }))
+}
+
+fn param_str<'a>(params: &'a Map<String, Value>, name: &str) -> Result<Option<&'a str>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(RpcError::new(INVALID_PARAMS, format!("`{name}` must be a string"))),
+    }
+}
+
+fn param_u64(params: &Map<String, Value>, name: &str) -> Result<Option<u64>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => {
+            value
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("`{name}` must be a non-negative integer")))
+        }
+    }
+}
+
+fn param_bool(params: &Map<String, Value>, name: &str) -> Result<Option<bool>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Bool(value)) => Ok(Some(*value)),
+        Some(_) => Err(RpcError::new(INVALID_PARAMS, format!("`{name}` must be true or false"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, RwLock};
+
+    use ptree_cache::{testing, DiskCache};
+
+    use super::*;
+    use crate::server::query::Watched;
+
+    fn served_cache() -> Watched {
+        // Nothing is written there; opening a missing cache gives an empty one
+        let mut cache = DiskCache::open(&std::env::temp_dir().join("ptree_rpc_test_missing.dat")).unwrap();
+        cache.roots = vec![PathBuf::from("/data")];
+        for (path, size, children) in [
+            ("/data", 300, vec!["logs", "notes.txt"]),
+            ("/data/logs", 200, vec!["app.log", "err.log"]),
+        ] {
+            let path = PathBuf::from(path);
+            cache.entries.insert(path.clone(), testing::dir(&path, size, &children));
+        }
+        Watched {
+            cache:      Arc::new(RwLock::new(cache)),
+            cache_path: PathBuf::from("/tmp/ptree.dat"),
+        }
+    }
+
+    fn ask(cache: &Watched, line: &str) -> Value {
+        serde_json::from_str(&handle(cache, line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn methods_answer_from_the_cache() {
+        let cache = served_cache();
+
+        let tree =
+            ask(&cache, r#"{"jsonrpc":"2.0","id":1,"method":"tree","params":{"path":"/data/logs","size":true}}"#);
+        assert_eq!(tree["id"], 1);
+        assert_eq!(tree["result"]["path"], "/data/logs");
+        assert_eq!(tree["result"]["size_bytes"], 200);
+        assert_eq!(tree["result"]["children"][1]["name"], "err.log");
+
+        let root = ask(&cache, r#"{"jsonrpc":"2.0","id":2,"method":"tree","params":{"depth":0}}"#);
+        assert_eq!(root["result"]["path"], "/data");
+        assert_eq!(root["result"]["children"], json!([]));
+        // v2 nodes unless asked for v1, as on the command line
+        assert_eq!(root["result"]["size_bytes"], 300);
+        let bare = ask(&cache, r#"{"jsonrpc":"2.0","id":2,"method":"tree","params":{"depth":0,"schema":"v1"}}"#);
+        assert!(bare["result"].get("size_bytes").is_none());
+
+        let found =
+            ask(&cache, r#"{"jsonrpc":"2.0","id":"s","method":"search","params":{"pattern":"*.log","limit":1}}"#);
+        assert_eq!(found["id"], "s");
+        assert_eq!(found["result"]["hits"], json!([{ "path": "/data/logs/app.log", "is_dir": false }]));
+        assert_eq!(found["result"]["truncated"], true);
+
+        let stats = ask(&cache, r#"{"jsonrpc":"2.0","id":3,"method":"stats"}"#);
+        assert_eq!(stats["result"]["roots"], json!(["/data"]));
+        assert_eq!(stats["result"]["bytes"], 300);
+    }
+
+    #[test]
+    fn bad_requests_get_json_rpc_errors() {
+        let cache = served_cache();
+        let code = |line: &str| ask(&cache, line)["error"]["code"].as_i64().unwrap();
+
+        assert_eq!(code("{not json"), PARSE_ERROR);
+        assert_eq!(code("[1, 2]"), INVALID_REQUEST);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","id":1,"method":"delete"}"#), METHOD_NOT_FOUND);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","id":1,"method":"search","params":{}}"#), INVALID_PARAMS);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","id":1,"method":"tree","params":{"depth":-1}}"#), INVALID_PARAMS);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","id":1,"method":"tree","params":{"schema":"v3"}}"#), INVALID_PARAMS);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","id":1,"method":"tree","params":{"path":"/elsewhere"}}"#), SERVER_ERROR);
+
+        // Notifications are carried out silently
+        assert!(handle(&cache, r#"{"jsonrpc":"2.0","method":"stats"}"#).is_none());
+    }
+}
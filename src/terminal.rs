@@ -0,0 +1,49 @@
+//! Size of the terminal a standard stream is on, for the pager and the `--progress` line.
+
+/// A standard stream that may be on a terminal
+#[derive(Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Rows of the terminal `stream` is on: the window size, else `$LINES`, else 24.
+pub fn rows(stream: Stream) -> usize {
+    window_size(stream)
+        .map(|(rows, _)| rows)
+        .filter(|&rows| rows > 0)
+        .unwrap_or_else(|| from_env("LINES", 24))
+}
+
+/// Columns of the terminal `stream` is on: the window size, else `$COLUMNS`, else 80.
+pub fn columns(stream: Stream) -> usize {
+    window_size(stream)
+        .map(|(_, columns)| columns)
+        .filter(|&columns| columns > 0)
+        .unwrap_or_else(|| from_env("COLUMNS", 80))
+}
+
+/// Rows and columns the terminal reports, if `stream` is on one.
+#[cfg(unix)]
+fn window_size(stream: Stream) -> Option<(usize, usize)> {
+    let fd = match stream {
+        Stream::Stdout => libc::STDOUT_FILENO,
+        Stream::Stderr => libc::STDERR_FILENO,
+    };
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes a `winsize` into the struct it is given
+    let ok = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0;
+    ok.then_some((size.ws_row as usize, size.ws_col as usize))
+}
+
+#[cfg(not(unix))]
+fn window_size(_stream: Stream) -> Option<(usize, usize)> {
+    None
+}
+
+fn from_env(var_name: &str, default: usize) -> usize {
+    std::env::var(var_name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
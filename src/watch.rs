@@ -0,0 +1,261 @@
+//! `ptree --watch`: scan (or refresh) once, then keep the cache current from filesystem
+//! events, so interactive runs find it warm instead of waiting for the next scheduled refresh.
+//! With `--serve` the kept-current cache also answers `ptree client` queries, and with
+//! `--emit-events` every change is printed as NDJSON as soon as it is seen.
+
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use ptree_cache::{DiskCache, Name};
+use ptree_core::Args;
+use ptree_incremental::{ChangeWatcher, IncrementalChange, IncrementalChangeKind};
+use ptree_traversal::{resolve_scan_roots, traverse_disk, traverse_disk_incremental, DebugInfo};
+
+/// The cache is written at most this often while changes keep coming in
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A batch of events ends after this long without a new one
+const QUIET_WINDOW: Duration = Duration::from_millis(500);
+
+/// How often a stoppable watch (`ptree service run`) checks whether it was asked to stop
+const STOP_POLL: Duration = Duration::from_secs(1);
+
+/// Watch until Ctrl-C, which stops it like the service's stop request: what is unsaved is
+/// saved first. A second Ctrl-C quits at once.
+pub fn run(args: &Args) -> Result<()> {
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let installed = ctrlc::set_handler(|| {
+        if STOP.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        eprintln!("\nStopping: saving the changes not saved yet (Ctrl-C again to quit at once)");
+    });
+    if let Err(err) = installed {
+        tracing::warn!("Ctrl-C will not save the last changes: {err}");
+    }
+    watch(args, Some(&STOP))
+}
+
+/// Watch until `stop` is set, then save what is unsaved and return (the Windows service).
+#[cfg(all(windows, feature = "scheduler"))]
+pub fn run_until_stopped(args: &Args, stop: &AtomicBool) -> Result<()> {
+    watch(args, Some(stop))
+}
+
+fn watch(args: &Args, stop: Option<&AtomicBool>) -> Result<()> {
+    #[cfg(not(feature = "server"))]
+    if args.serve {
+        anyhow::bail!("--serve needs a build with the `server` feature");
+    }
+
+    let drive = args.primary_drive();
+    let scan_roots = resolve_scan_roots(&drive, args)?;
+    let cache_path =
+        ptree_cache::cache_path_for_roots(&ptree_cache::get_cache_path_custom(args.cache_dir.as_deref())?, &scan_roots);
+    let mut cache = DiskCache::open(&cache_path)?;
+    cache.max_cache_bytes = args.cache_max_bytes();
+    if let Some(timeout) = args.lock_timeout() {
+        cache.lock_timeout = timeout;
+    }
+
+    // Subscribe before the first scan, so changes made while it runs are applied after it
+    let ignored = cache_path
+        .parent()
+        .map(|dir| vec![dir.to_path_buf()])
+        .unwrap_or_default();
+    let mut watcher = ChangeWatcher::new(&scan_roots, ignored)?;
+    // Set once the reader of --emit-events goes away (`| head`); the watch then ends like a stop
+    let events_closed = Rc::new(Cell::new(false));
+    if args.emit_events {
+        let events_closed = Rc::clone(&events_closed);
+        watcher = watcher.on_change(move |change| {
+            if !events_closed.get() && !emit_event(&mut io::stdout().lock(), change) {
+                events_closed.set(true);
+            }
+        });
+    }
+    // With --emit-events stdout carries only events; the status lines go to stderr
+    let status = |line: String| {
+        if args.quiet {
+            return;
+        }
+        if args.emit_events {
+            eprintln!("{line}");
+        } else {
+            println!("{line}");
+        }
+    };
+
+    let debug_info = traverse_disk(&drive, &mut cache, args, &cache_path)?;
+    // The first scan and each full refresh leave a line in the history, like a scan run by hand
+    if !debug_info.cache_used && !args.no_cache && !args.no_save {
+        crate::record_history(&cache, &debug_info, &cache_path);
+    }
+    let roots: Vec<String> = scan_roots.iter().map(|root| root.display().to_string()).collect();
+    status(format!("Watching {} ({} directories); stop with Ctrl-C", roots.join(", "), debug_info.total_dirs));
+
+    // A cache hit leaves most entries on disk; batches (and queries) are applied to all of them
+    // in memory, once loaded here
+    cache.load_all_entries_lazy(&cache_path)?;
+
+    // Queries are answered while the watcher applies changes, so the cache is shared behind a lock
+    let cache = Arc::new(RwLock::new(cache));
+    #[cfg(feature = "server")]
+    if args.serve {
+        crate::server::start(Arc::clone(&cache), &cache_path)?;
+        status(format!("Serving queries on {}", crate::server::endpoint(&cache_path)));
+    }
+
+    // Changes are applied in memory as they come and written out every SAVE_INTERVAL
+    let mut apply_args = args.clone();
+    apply_args.no_save = true;
+    let mut unsaved = 0usize;
+    let mut last_save = Instant::now();
+    // A refresh is applied unsaved, so it is recorded once a save writes it out
+    let mut unrecorded_refresh = None;
+
+    let wait = if stop.is_some() { STOP_POLL } else { SAVE_INTERVAL };
+    loop {
+        if stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) || events_closed.get() {
+            if unsaved > 0 && !args.no_save {
+                save(&cache, &cache_path, &mut unrecorded_refresh)?;
+            }
+            return Ok(());
+        }
+
+        let batch = watcher.next_batch(wait, QUIET_WINDOW, |path| {
+            let cache = cache.read().unwrap_or_else(PoisonError::into_inner);
+            cache.get_entry(path).is_some_and(|entry| entry.is_dir)
+        })?;
+        for err in &batch.errors {
+            tracing::warn!("watcher: {err}");
+        }
+
+        if batch.rescan {
+            // Events were dropped; a refresh rereads every directory whose mtime moved
+            tracing::warn!("the watcher lost events; refreshing the whole tree");
+            // The refresh starts from the snapshot on disk, which must not bring back what the
+            // batches since the last save removed
+            if unsaved > 0 && !args.no_save {
+                save(&cache, &cache_path, &mut unrecorded_refresh)?;
+                unsaved = 0;
+                last_save = Instant::now();
+            }
+            let mut rescan_args = apply_args.clone();
+            rescan_args.cache_ttl = Some(0);
+            let refreshed = traverse_disk(&drive, &mut write(&cache), &rescan_args, &cache_path)?;
+            unrecorded_refresh = (!args.no_cache).then_some(refreshed);
+            unsaved += 1;
+        } else if !batch.changes.is_empty() {
+            traverse_disk_incremental(&drive, &mut write(&cache), &apply_args, &cache_path, &batch.changes)?;
+            unsaved += batch.changes.len();
+            status(format!("{} applied {} change(s)", chrono::Local::now().format("%H:%M:%S"), batch.changes.len()));
+        }
+
+        if unsaved > 0 && !args.no_save && last_save.elapsed() >= SAVE_INTERVAL {
+            // Another process holding the cache lock only delays the save
+            match save(&cache, &cache_path, &mut unrecorded_refresh) {
+                Ok(()) => {
+                    unsaved = 0;
+                    last_save = Instant::now();
+                    // Names only the directories these changes removed had are freed with them
+                    Name::release_unused();
+                }
+                Err(err) => tracing::warn!("could not save the cache ({err}); retrying later"),
+            }
+        }
+    }
+}
+
+/// Save the shared cache, then record the refresh it wrote out, if any, in the history (once
+/// the cache lock the save held is released).
+fn save(cache: &RwLock<DiskCache>, cache_path: &Path, refresh: &mut Option<DebugInfo>) -> Result<()> {
+    let mut cache = write(cache);
+    cache.save(cache_path)?;
+    if let Some(debug_info) = refresh.take() {
+        crate::record_history(&cache, &debug_info, cache_path);
+    }
+    Ok(())
+}
+
+/// Exclusive access to the shared cache; queries wait until a refresh is applied.
+fn write(cache: &RwLock<DiskCache>) -> RwLockWriteGuard<'_, DiskCache> {
+    cache.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Write the `--emit-events` line of `change`; false once the reader has closed the pipe.
+fn emit_event(out: &mut impl Write, change: &IncrementalChange) -> bool {
+    match writeln!(out, "{}", event_line(change, Utc::now())) {
+        Ok(()) => true,
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => false,
+        Err(err) => {
+            tracing::warn!("could not print an event: {err}");
+            true
+        }
+    }
+}
+
+/// One `--emit-events` line: when the change was seen, what happened, and to what.
+fn event_line(change: &IncrementalChange, time: DateTime<Utc>) -> String {
+    let event = match change.kind {
+        IncrementalChangeKind::Created => "created",
+        IncrementalChangeKind::Modified => "modified",
+        IncrementalChangeKind::Deleted => "deleted",
+        IncrementalChangeKind::Renamed => "renamed",
+    };
+    serde_json::json!({
+        "time": time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "event": event,
+        "type": if change.is_directory { "directory" } else { "file" },
+        "path": change.path.to_string_lossy(),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn events_print_as_one_json_object_per_line() {
+        let time = DateTime::parse_from_rfc3339("2024-05-01T12:30:00.250Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let line = event_line(&IncrementalChange::deleted(PathBuf::from("/srv/data/old.log"), false), time);
+        assert_eq!(
+            line,
+            r#"{"event":"deleted","path":"/srv/data/old.log","time":"2024-05-01T12:30:00.250Z","type":"file"}"#
+        );
+        assert!(event_line(&IncrementalChange::created(PathBuf::from("/srv/new"), true), time)
+            .contains(r#""event":"created","path":"/srv/new""#));
+    }
+
+    #[test]
+    fn a_closed_event_reader_ends_the_watch_instead_of_panicking() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let change = IncrementalChange::created(PathBuf::from("/srv/new"), true);
+        let mut open = Vec::new();
+        assert!(emit_event(&mut open, &change));
+        assert!(String::from_utf8(open).unwrap().ends_with("}\n"));
+        assert!(!emit_event(&mut Closed, &change));
+    }
+}
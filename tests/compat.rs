@@ -0,0 +1,166 @@
+//! Compatibility checks against GNU `tree` and `du` on generated fixture trees.
+//!
+//! Ignored by default since they need the reference tools installed:
+//! `cargo test --test compat -- --ignored`. A test whose tool is missing passes with a note.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::Value;
+
+/// A fixture directory (and its ptree cache dir) removed on drop.
+struct Fixture {
+    root:      PathBuf,
+    cache_dir: PathBuf,
+}
+
+impl Fixture {
+    /// Deterministic tree with nested, empty, hidden, spaced and non-ASCII entries.
+    fn generate(name: &str) -> Self {
+        let base = std::env::temp_dir().join(format!("ptree_compat_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("tree");
+        let cache_dir = base.join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        // Small LCG so sizes vary but every run builds the same tree
+        let mut seed = 0x2545_f491_u64;
+        let mut next = move |modulo: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % modulo
+        };
+
+        for top in 0..4 {
+            for sub in 0..=next(3) {
+                let dir = root.join(format!("dir-{top}")).join(format!("sub-{sub}"));
+                fs::create_dir_all(&dir).unwrap();
+                for file in 0..next(6) {
+                    fs::write(dir.join(format!("file-{file}.bin")), vec![b'x'; next(20_000) as usize]).unwrap();
+                }
+            }
+            fs::write(root.join(format!("dir-{top}")).join("README"), vec![b'r'; next(512) as usize]).unwrap();
+        }
+
+        let deep = root.join("a/b/c/d/e");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("leaf.txt"), b"leaf").unwrap();
+        fs::create_dir_all(root.join("empty dir")).unwrap();
+        fs::write(root.join("empty file"), b"").unwrap();
+        fs::create_dir_all(root.join(".hidden-dir")).unwrap();
+        fs::write(root.join(".hidden-dir").join("secret"), vec![b's'; 300]).unwrap();
+        fs::write(root.join(".dotfile"), b"dot").unwrap();
+        fs::write(root.join("ünïcødé.txt"), vec![b'u'; 1234]).unwrap();
+
+        Fixture { root, cache_dir }
+    }
+
+    fn ptree(&self, extra: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_ptree"))
+            .arg(&self.root)
+            .args(["--hidden", "--force", "--color", "never", "--cache-dir"])
+            .arg(&self.cache_dir)
+            .args(extra)
+            .output()
+            .expect("failed to run ptree");
+        assert!(output.status.success(), "ptree failed: {}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn ptree_json(&self) -> Value {
+        serde_json::from_str(&self.ptree(&["--format", "json", "--size", "--file-count"])).unwrap()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if let Some(base) = self.root.parent() {
+            let _ = fs::remove_dir_all(base);
+        }
+    }
+}
+
+/// Run a reference tool under the C locale, or `None` if it is not installed.
+fn reference(program: &str, args: &[&str], path: &Path) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .arg(path)
+        .env("LC_ALL", "C")
+        .output()
+        .ok()?;
+    assert!(output.status.success(), "{program} failed: {}", String::from_utf8_lossy(&output.stderr));
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+/// Directories carry `file_count` in ptree's JSON output; files do not.
+fn is_dir(node: &Value) -> bool {
+    node.get("file_count").is_some()
+}
+
+fn collect_nodes<'a>(node: &'a Value, out: &mut Vec<&'a Value>) {
+    out.push(node);
+    for child in node["children"].as_array().into_iter().flatten() {
+        collect_nodes(child, out);
+    }
+}
+
+#[test]
+#[ignore = "needs GNU tree; run with --ignored"]
+fn tree_listing_and_report_match_gnu_tree() {
+    let fixture = Fixture::generate("tree");
+    let Some(reference_listing) = reference("tree", &["-a", "-N", "--noreport", "--charset=UTF-8"], &fixture.root)
+    else {
+        eprintln!("skipping: GNU tree is not installed");
+        return;
+    };
+
+    // Same entries, same order, same connectors
+    assert_eq!(fixture.ptree(&[]), reference_listing);
+
+    // `tree` reports "N directories, M files", not counting the root itself
+    let report = reference("tree", &["-a"], &fixture.root).unwrap();
+    let summary = report.lines().last().unwrap().to_string();
+    let json = fixture.ptree_json();
+    let mut nodes = Vec::new();
+    collect_nodes(&json, &mut nodes);
+    let directories = nodes.iter().filter(|node| is_dir(node)).count() - 1;
+    let files = nodes.len() - 1 - directories;
+
+    assert_eq!(summary, format!("{directories} directories, {files} files"));
+    assert_eq!(json["file_count"].as_u64(), Some(files as u64));
+}
+
+#[test]
+#[ignore = "needs GNU du; run with --ignored"]
+fn directory_sizes_and_file_counts_match_du() {
+    let fixture = Fixture::generate("du");
+    let Some(listing) = reference("du", &["-a", "-b"], &fixture.root) else {
+        eprintln!("skipping: GNU du is not installed");
+        return;
+    };
+
+    // du's directory totals include the directory inodes themselves; sum regular files only
+    let files: BTreeMap<PathBuf, u64> = listing
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(size, path)| (PathBuf::from(path), size.parse().unwrap()))
+        .filter(|(path, _)| path.is_file())
+        .collect();
+
+    let json = fixture.ptree_json();
+    let mut nodes = Vec::new();
+    collect_nodes(&json, &mut nodes);
+
+    for node in nodes.into_iter().filter(|node| is_dir(node)) {
+        let dir = Path::new(node["path"].as_str().unwrap());
+        let under: Vec<u64> = files
+            .iter()
+            .filter(|(path, _)| path.starts_with(dir))
+            .map(|(_, size)| *size)
+            .collect();
+
+        assert_eq!(node["size_bytes"].as_u64(), Some(under.iter().sum()), "size of {}", dir.display());
+        assert_eq!(node["file_count"].as_u64(), Some(under.len() as u64), "file count of {}", dir.display());
+    }
+}